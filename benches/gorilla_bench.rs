@@ -36,6 +36,21 @@ fn bench_encode(c: &mut Criterion) {
                 black_box(enc.into_compressed())
             });
         });
+
+        group.bench_with_input(
+            BenchmarkId::new("varying_hinted", size),
+            &data,
+            |b, data| {
+                b.iter(|| {
+                    let mut enc = Encoder::with_capacity_hint(data.len());
+                    for dp in data {
+                        enc.encode(black_box(*dp)).unwrap();
+                    }
+                    enc.finish().unwrap();
+                    black_box(enc.into_compressed())
+                });
+            },
+        );
     }
 
     for size in [100, 1_000, 10_000, 100_000] {
@@ -57,6 +72,30 @@ fn bench_encode(c: &mut Criterion) {
     group.finish();
 }
 
+#[cfg(feature = "simd")]
+fn bench_encode_batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode_batch");
+
+    for size in [1_000, 100_000] {
+        let data = generate_data(size);
+        group.throughput(Throughput::Elements(size as u64));
+
+        group.bench_with_input(BenchmarkId::new("varying", size), &data, |b, data| {
+            b.iter(|| {
+                let mut enc = Encoder::new();
+                enc.encode_batch(black_box(data)).unwrap();
+                enc.finish().unwrap();
+                black_box(enc.into_compressed())
+            });
+        });
+    }
+
+    group.finish();
+}
+
+#[cfg(not(feature = "simd"))]
+fn bench_encode_batch(_: &mut Criterion) {}
+
 fn bench_decode(c: &mut Criterion) {
     let mut group = c.benchmark_group("decode");
 
@@ -150,5 +189,12 @@ fn bench_roundtrip(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, bench_encode, bench_decode, bench_decode_iter, bench_roundtrip);
+criterion_group!(
+    benches,
+    bench_encode,
+    bench_encode_batch,
+    bench_decode,
+    bench_decode_iter,
+    bench_roundtrip
+);
 criterion_main!(benches);
@@ -0,0 +1,548 @@
+//! Minimal HTTP server over a `TieredStore`, exposing Prometheus-style
+//! endpoints for trying out the full ingest, store, query and export path.
+//!
+//! ```text
+//! cargo run --example query_server --features server -- /tmp/gorilla 127.0.0.1:9090
+//!
+//! curl -d 'cpu{host="a"} 1700000000 12.5' localhost:9090/api/v1/write
+//! curl -G localhost:9090/api/v1/query_range --data-urlencode 'query=cpu{host="a"}' \
+//!     -d start=1699999000 -d end=1700001000 -d step=60
+//! curl -G localhost:9090/api/v1/export --data-urlencode 'match=cpu{host="a"}'
+//! curl -X POST 'localhost:9090/api/v1/admin/spill?now=1800000000'
+//! ```
+//!
+//! | endpoint                  | method | parameters                     |
+//! |---------------------------|--------|--------------------------------|
+//! | `/api/v1/write`           | POST   | body: `series timestamp value` lines |
+//! | `/api/v1/query_range`     | GET    | `query`, `start`, `end`, `step` |
+//! | `/api/v1/export`          | GET    | `match`, optional `start`, `end`; CSV output |
+//! | `/api/v1/admin/spill`     | POST   | `now`                          |
+//!
+//! Series are written and selected as Prometheus label sets such as
+//! `cpu{host="a"}`, parsed into [`Labels`]. Requests are served one at a
+//! time on a single thread.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use gorilla::{evaluate_step, AggFn, DataPoint, Encoder, Labels, TieredConfig, TieredStore};
+
+/// Most grid points a `query_range` request may ask for.
+const MAX_POINTS: u64 = 11_000;
+/// Largest request body accepted, in bytes.
+const MAX_BODY: usize = 16 << 20;
+
+struct Request {
+    method: String,
+    path: String,
+    params: HashMap<String, String>,
+    body: String,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn ok(content_type: &'static str, body: String) -> Self {
+        Self {
+            status: 200,
+            content_type,
+            body,
+        }
+    }
+
+    fn error(status: u16, message: impl std::fmt::Display) -> Self {
+        let body = format!(
+            r#"{{"status":"error","error":"{}"}}"#,
+            escape(&message.to_string())
+        );
+        Self {
+            status,
+            content_type: "application/json",
+            body,
+        }
+    }
+}
+
+fn main() -> io::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let dir = args.next().unwrap_or_else(|| "gorilla-data".to_string());
+    let addr = args.next().unwrap_or_else(|| "127.0.0.1:9090".to_string());
+
+    let mut store = TieredStore::<Labels>::open(&dir, TieredConfig::default())
+        .map_err(|err| io::Error::other(err.to_string()))?;
+    let listener = TcpListener::bind(&addr)?;
+    eprintln!("serving {dir} on http://{}", listener.local_addr()?);
+    serve(&listener, &mut store, None)
+}
+
+/// Accepts connections until `limit` requests have been served (forever if
+/// `None`).
+fn serve(
+    listener: &TcpListener,
+    store: &mut TieredStore<Labels>,
+    limit: Option<usize>,
+) -> io::Result<()> {
+    for (served, stream) in listener.incoming().enumerate() {
+        if limit.is_some_and(|limit| served >= limit) {
+            break;
+        }
+        let mut stream = stream?;
+        let response = match read_request(&mut stream) {
+            Ok(request) => handle(store, &request),
+            Err(err) => Response::error(400, err),
+        };
+        if let Err(err) = write_response(&mut stream, &response) {
+            eprintln!("failed to send response: {err}");
+        }
+        if limit.is_some_and(|limit| served + 1 >= limit) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn handle(store: &mut TieredStore<Labels>, request: &Request) -> Response {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/api/v1/write") => ingest(store, &request.body),
+        ("GET", "/api/v1/query_range") => query_range(store, &request.params),
+        ("GET", "/api/v1/export") => export(store, &request.params),
+        ("POST", "/api/v1/admin/spill") => match param(&request.params, "now") {
+            Ok(now) => match store.spill(now) {
+                Ok(spilled) => Response::ok(
+                    "application/json",
+                    format!(r#"{{"status":"success","data":{{"spilled":{spilled}}}}}"#),
+                ),
+                Err(err) => Response::error(500, err),
+            },
+            Err(response) => response,
+        },
+        _ => Response::error(404, "not found"),
+    }
+}
+
+fn ingest(store: &mut TieredStore<Labels>, body: &str) -> Response {
+    let mut written = 0;
+    for (n, line) in body.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        // Label values may contain spaces, so split off the numbers from
+        // the end.
+        let fields: Vec<_> = line.rsplitn(3, char::is_whitespace).collect();
+        let parsed = match fields[..] {
+            [value, ts, series] => ts.parse().ok().zip(value.parse().ok()).map(|p| (series, p)),
+            _ => None,
+        };
+        let Some((series, (ts, value))) = parsed else {
+            return Response::error(
+                400,
+                format!("line {}: expected `series timestamp value`", n + 1),
+            );
+        };
+        let series: Labels = match series.parse() {
+            Ok(series) => series,
+            Err(err) => return Response::error(400, format!("line {}: {err}", n + 1)),
+        };
+        if let Err(err) = store.append(series, DataPoint::new(ts, value)) {
+            return Response::error(422, format!("line {}: {err}", n + 1));
+        }
+        written += 1;
+    }
+    Response::ok(
+        "application/json",
+        format!(r#"{{"status":"success","data":{{"written":{written}}}}}"#),
+    )
+}
+
+/// Evaluates the series at every `step` from `start` to `end`, taking the
+/// last point in the step before each evaluation time. At most
+/// [`MAX_POINTS`] evaluation times are allowed.
+fn query_range(store: &TieredStore<Labels>, params: &HashMap<String, String>) -> Response {
+    let series = match labels(params, "query") {
+        Ok(series) => series,
+        Err(response) => return response,
+    };
+    let (start, end, step) = match (
+        param(params, "start"),
+        param(params, "end"),
+        param(params, "step"),
+    ) {
+        (Ok(start), Ok(end), Ok(step)) => (start, end, step),
+        (Err(response), _, _) | (_, Err(response), _) | (_, _, Err(response)) => return response,
+    };
+    if step == 0 || start > end {
+        return Response::error(400, "need `step` > 0 and `start` <= `end`");
+    }
+
+    if (end - start) / step >= MAX_POINTS {
+        return Response::error(
+            400,
+            format!("query would return more than {MAX_POINTS} points; raise `step`"),
+        );
+    }
+
+    // Gather the points in range into one block so that the grid is
+    // evaluated by `evaluate_step`.
+    let mut encoder = Encoder::new();
+    for result in store.query(&series, start.saturating_sub(step - 1), end) {
+        let appended = match result {
+            Ok(dp) => encoder.encode(dp),
+            Err(err) => return Response::error(500, err),
+        };
+        if let Err(err) = appended {
+            return Response::error(500, err);
+        }
+    }
+    let mut blocks = Vec::new();
+    if encoder.count() > 0 {
+        if let Err(err) = encoder.finish() {
+            return Response::error(500, err);
+        }
+        blocks.push(encoder.into_compressed());
+    }
+    let grid = match evaluate_step(&blocks, start, end, step, AggFn::Last) {
+        Ok(grid) => grid,
+        Err(err) => return Response::error(500, err),
+    };
+    let mut values = String::new();
+    for (t, value) in grid {
+        let Some(value) = value else {
+            continue;
+        };
+        if !values.is_empty() {
+            values.push(',');
+        }
+        write!(values, r#"[{t},"{value}"]"#).unwrap();
+    }
+    let result = if values.is_empty() {
+        String::new()
+    } else {
+        let metric: Vec<_> = series
+            .iter()
+            .map(|(name, value)| format!(r#""{}":"{}""#, escape(name), escape(value)))
+            .collect();
+        format!(
+            r#"{{"metric":{{{}}},"values":[{values}]}}"#,
+            metric.join(",")
+        )
+    };
+    Response::ok(
+        "application/json",
+        format!(r#"{{"status":"success","data":{{"resultType":"matrix","result":[{result}]}}}}"#),
+    )
+}
+
+fn export(store: &TieredStore<Labels>, params: &HashMap<String, String>) -> Response {
+    let series = match labels(params, "match") {
+        Ok(series) => series,
+        Err(response) => return response,
+    };
+    let start = params
+        .get("start")
+        .map_or(Ok(0), |_| param(params, "start"));
+    let end = params
+        .get("end")
+        .map_or(Ok(u64::MAX), |_| param(params, "end"));
+    let (start, end) = match (start, end) {
+        (Ok(start), Ok(end)) => (start, end),
+        (Err(response), _) | (_, Err(response)) => return response,
+    };
+    let mut csv = String::from("timestamp,value\n");
+    for result in store.query(&series, start, end) {
+        match result {
+            Ok(dp) => writeln!(csv, "{},{}", dp.timestamp, dp.value).unwrap(),
+            Err(err) => return Response::error(500, err),
+        }
+    }
+    Response::ok("text/csv", csv)
+}
+
+fn param(params: &HashMap<String, String>, name: &str) -> Result<u64, Response> {
+    let value = params
+        .get(name)
+        .ok_or_else(|| Response::error(400, format!("missing parameter `{name}`")))?;
+    value
+        .parse()
+        .map_err(|_| Response::error(400, format!("parameter `{name}` is not an integer")))
+}
+
+fn labels(params: &HashMap<String, String>, name: &str) -> Result<Labels, Response> {
+    let value = params
+        .get(name)
+        .ok_or_else(|| Response::error(400, format!("missing parameter `{name}`")))?;
+    value
+        .parse()
+        .map_err(|err| Response::error(400, format!("parameter `{name}`: {err}")))
+}
+
+fn read_request(stream: &mut TcpStream) -> io::Result<Request> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts
+        .next()
+        .ok_or_else(|| invalid("empty request"))?
+        .to_string();
+    let target = parts
+        .next()
+        .ok_or_else(|| invalid("missing request target"))?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let path = path.to_string();
+    let params = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(k), percent_decode(v))
+        })
+        .collect();
+
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid("bad Content-Length"))?;
+                if content_length > MAX_BODY {
+                    return Err(invalid("body too large"));
+                }
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8(body).map_err(|_| invalid("body is not UTF-8"))?;
+    Ok(Request {
+        method,
+        path,
+        params,
+        body,
+    })
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> io::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        422 => "Unprocessable Entity",
+        _ => "Internal Server Error",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {reason}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' => {
+                let hex = bytes
+                    .get(i + 1..i + 3)
+                    .and_then(|h| std::str::from_utf8(h).ok());
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(byte) => {
+                        out.push(byte);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("gorilla-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn request(method: &str, target: &str, body: &str) -> Request {
+        let mut stream = Vec::new();
+        write!(
+            stream,
+            "{method} {target} HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(&stream).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        read_request(&mut server).unwrap()
+    }
+
+    #[test]
+    fn test_write_query_export_and_spill() {
+        let dir = temp_dir("query-server");
+        let config = TieredConfig {
+            block_duration: 600,
+            ram_retention: 600,
+            ..Default::default()
+        };
+        let mut store = TieredStore::<Labels>::open(&dir, config).unwrap();
+        let body: String = (0..30)
+            .map(|i| format!("cpu_load {} {}\n", i * 60, i))
+            .collect();
+        let response = handle(&mut store, &request("POST", "/api/v1/write", &body));
+        assert_eq!(
+            response.body,
+            r#"{"status":"success","data":{"written":30}}"#
+        );
+
+        let spill = handle(
+            &mut store,
+            &request("POST", "/api/v1/admin/spill?now=2400", ""),
+        );
+        assert_eq!(spill.body, r#"{"status":"success","data":{"spilled":2}}"#);
+
+        let range = handle(
+            &mut store,
+            &request(
+                "GET",
+                "/api/v1/query_range?query=cpu_load&start=1140&end=1290&step=75",
+                "",
+            ),
+        );
+        assert_eq!(
+            range.body,
+            concat!(
+                r#"{"status":"success","data":{"resultType":"matrix","result":["#,
+                r#"{"metric":{"__name__":"cpu_load"},"values":[[1140,"19"],[1215,"20"],[1290,"21"]]}]}}"#
+            )
+        );
+
+        let csv = handle(
+            &mut store,
+            &request(
+                "GET",
+                "/api/v1/export?match=cpu%5Fload&start=60&end=120",
+                "",
+            ),
+        );
+        assert_eq!(csv.content_type, "text/csv");
+        assert_eq!(csv.body, "timestamp,value\n60,1\n120,2\n");
+
+        let body = "cpu_load{host=\"a b\"} 60 5\ncpu_load{host=\"c\"} 60 7\n";
+        handle(&mut store, &request("POST", "/api/v1/write", body));
+        let range = handle(
+            &mut store,
+            &request(
+                "GET",
+                "/api/v1/query_range?query=cpu_load%7Bhost%3D%22a+b%22%7D&start=60&end=60&step=60",
+                "",
+            ),
+        );
+        assert!(range
+            .body
+            .contains(r#"{"metric":{"__name__":"cpu_load","host":"a b"},"values":[[60,"5"]]}"#));
+
+        let bad = handle(&mut store, &request("POST", "/api/v1/write", "cpu 1 x"));
+        assert_eq!(bad.status, 400);
+        let bad = handle(&mut store, &request("POST", "/api/v1/write", "cpu{ 1 2"));
+        assert_eq!(bad.status, 400);
+        assert_eq!(handle(&mut store, &request("GET", "/nope", "")).status, 404);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_oversized_requests() {
+        let dir = temp_dir("query-server-limits");
+        let mut store = TieredStore::<Labels>::open(&dir, TieredConfig::default()).unwrap();
+        let body = format!("cpu {} 1\n", u64::MAX - 8);
+        handle(&mut store, &request("POST", "/api/v1/write", &body));
+
+        let target = format!(
+            "/api/v1/query_range?query=cpu&start={}&end={}&step=10",
+            u64::MAX - 5,
+            u64::MAX
+        );
+        let range = handle(&mut store, &request("GET", &target, ""));
+        assert_eq!(range.status, 200);
+        assert!(range.body.contains(&format!(r#"[[{},"1"]]"#, u64::MAX - 5)));
+        let wide = handle(
+            &mut store,
+            &request(
+                "GET",
+                "/api/v1/query_range?query=cpu&start=0&end=110000&step=10",
+                "",
+            ),
+        );
+        assert_eq!(wide.status, 400);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        write!(
+            client,
+            "POST /api/v1/write HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            usize::MAX
+        )
+        .unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        let err = read_request(&mut server).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_serves_over_tcp() {
+        let dir = temp_dir("query-server-tcp");
+        let mut store = TieredStore::<Labels>::open(&dir, TieredConfig::default()).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream
+                .write_all(b"POST /api/v1/write HTTP/1.1\r\nContent-Length: 9\r\n\r\nmem 5 1.5")
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        });
+        serve(&listener, &mut store, Some(1)).unwrap();
+        let response = client.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(r#"{"written":1}}"#));
+        assert_eq!(store.series(&"mem".parse().unwrap()).unwrap().len(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,36 @@
+//! Streams without a header, under every combination of codecs. The first
+//! byte picks the codecs and whether the stream has priors, and cuts up to
+//! seven bits off the end of the stream.
+
+#![no_main]
+
+use gorilla::{CompressedBlock, Decoder, Priors, TimestampScheme, ValueScheme};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, stream)) = data.split_first() else {
+        return;
+    };
+    let total_bits = (stream.len() * 8).saturating_sub(usize::from(selector >> 5));
+    let _ = Decoder::decode_raw(stream, total_bits);
+
+    let Ok(mut block) = CompressedBlock::from_parts(stream, total_bits, 0) else {
+        return;
+    };
+    let (Some(timestamps), Some(values)) = (
+        TimestampScheme::from_id(selector & 0x03),
+        ValueScheme::from_id((selector >> 2) & 0x03),
+    ) else {
+        return;
+    };
+    block.timestamp_codec = timestamps;
+    block.value_codec = values;
+    if selector & 0x10 != 0 {
+        block.priors = Some(Priors {
+            interval: 60,
+            value: 0.0,
+        });
+    }
+    let (points, _) = Decoder::decode_lossy(&block);
+    let _ = Decoder::decode_strict(&block, points.len() + 1);
+});
@@ -0,0 +1,14 @@
+//! Serialized blocks from an untrusted peer: any input must be rejected
+//! or decoded, never panic.
+
+#![no_main]
+
+use gorilla::{CompressedBlock, Decoder};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(points) = Decoder::decode_untrusted(data) {
+        let block = CompressedBlock::from_bytes(data).unwrap();
+        assert_eq!(points.len() as u64, block.count());
+    }
+});
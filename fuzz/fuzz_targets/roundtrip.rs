@@ -0,0 +1,20 @@
+//! Arbitrary points, 16 bytes each, must decode back bit for bit. The
+//! timestamps are cut to 62 bits, as the difference of two deltas between
+//! wider ones can overflow the encoder's delta-of-delta.
+
+#![no_main]
+
+use gorilla::testutil::assert_roundtrip;
+use gorilla::DataPoint;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let points: Vec<DataPoint> = data
+        .chunks_exact(16)
+        .map(|chunk| {
+            let word = |i: usize| u64::from_le_bytes(chunk[i..i + 8].try_into().unwrap());
+            DataPoint::new(word(0) >> 2, f64::from_bits(word(8)))
+        })
+        .collect();
+    assert_roundtrip(&points);
+});
@@ -0,0 +1,68 @@
+/*
+ * C interface to the gorilla time-series codec.
+ *
+ * Build the crate with `cargo build --release --features ffi` and link
+ * against the resulting cdylib (libgorilla.so, libgorilla.dylib or
+ * gorilla.dll). Handles are opaque and owned by the caller; free each one
+ * with its `_free` function.
+ */
+#ifndef GORILLA_H
+#define GORILLA_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Status codes; 0 means success. */
+#define GORILLA_ERR_NULL      (-1) /* a required pointer was null */
+#define GORILLA_ERR_REJECTED  (-2) /* point out of order or a duplicate */
+#define GORILLA_ERR_MALFORMED (-3) /* input is not a valid serialized block */
+#define GORILLA_ERR_CAPACITY  (-4) /* output buffers smaller than the block */
+
+typedef struct GorillaEncoder GorillaEncoder;
+typedef struct GorillaBlock GorillaBlock;
+typedef struct GorillaScan GorillaScan;
+
+/* Encoding */
+
+GorillaEncoder *gorilla_encoder_new(void);
+/* Appends a point; timestamps must be strictly increasing. */
+int32_t gorilla_encode(GorillaEncoder *encoder, uint64_t timestamp, double value);
+/* Finishes and frees the encoder. Returns NULL on error. */
+GorillaBlock *gorilla_finish(GorillaEncoder *encoder);
+/* Frees an encoder that was not finished. */
+void gorilla_encoder_free(GorillaEncoder *encoder);
+
+/* Serialized block bytes, valid until the block is freed. */
+const uint8_t *gorilla_block_data(const GorillaBlock *block, size_t *len);
+uint64_t gorilla_block_count(const GorillaBlock *block);
+void gorilla_block_free(GorillaBlock *block);
+
+/* Decoding */
+
+/*
+ * Decodes a serialized block into two buffers of `capacity` elements.
+ * `*count` receives the number of points, also with GORILLA_ERR_CAPACITY.
+ */
+int32_t gorilla_decode(const uint8_t *data, size_t len, uint64_t *timestamps,
+                       double *values, size_t capacity, uint64_t *count);
+
+/* Segment scans */
+
+/* Opens a scan of `path` for timestamps in [start, end]; NULL on error. */
+GorillaScan *gorilla_scan_open(const char *path, uint64_t start, uint64_t end);
+/* Returns rows written, 0 at the end of the scan and -1 on error. */
+int64_t gorilla_scan_fill(GorillaScan *scan, uint32_t *keys, int64_t *timestamps,
+                          double *values, size_t capacity);
+/* Returns the key for a `keys` value, valid until the scan is closed. */
+const uint8_t *gorilla_scan_key(const GorillaScan *scan, uint32_t index, size_t *len);
+void gorilla_scan_close(GorillaScan *scan);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* GORILLA_H */
@@ -0,0 +1,283 @@
+//! Admission control for writes to a [`SeriesMap`](crate::SeriesMap).
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::series::SeriesKey;
+
+/// Reason an append was refused by an [`AdmissionControl`] hook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    /// The series exceeded its own rate limit.
+    SeriesRate {
+        /// Time until the series may write again.
+        retry_after: Duration,
+    },
+    /// The map as a whole exceeded its rate limit.
+    GlobalRate {
+        /// Time until the map accepts writes again.
+        retry_after: Duration,
+    },
+}
+
+impl Rejection {
+    /// Returns how long the writer should back off before retrying.
+    pub fn retry_after(&self) -> Duration {
+        match *self {
+            Rejection::SeriesRate { retry_after } | Rejection::GlobalRate { retry_after } => {
+                retry_after
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rejection::SeriesRate { retry_after } => {
+                write!(f, "series write rate exceeded, retry after {retry_after:?}")
+            }
+            Rejection::GlobalRate { retry_after } => {
+                write!(f, "global write rate exceeded, retry after {retry_after:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+/// A hook consulted by [`SeriesMap::append`](crate::SeriesMap::append)
+/// before every point is stored.
+pub trait AdmissionControl<K> {
+    /// Decides whether a point for `key` arriving at `now` is accepted. An
+    /// accepted point counts against any limits; a rejected one does not.
+    fn admit(&mut self, key: &K, now: Instant) -> Result<(), Rejection>;
+}
+
+/// A token-bucket rate: `per_second` points on average, with bursts of up
+/// to `burst` points.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    /// Sustained number of points per second.
+    pub per_second: f64,
+    /// Number of points that may be written at once after a quiet period.
+    /// Values below 1 are treated as 1.
+    pub burst: f64,
+}
+
+/// Limits enforced by a [`RateLimiter`]; limits left as `None` are not
+/// checked.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RateLimits {
+    /// Limit applied to each series separately.
+    pub per_series: Option<RateLimit>,
+    /// Limit applied to all appends together.
+    pub global: Option<RateLimit>,
+}
+
+/// Token bucket state.
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            tokens: limit.burst.max(1.0),
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, limit: RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.per_second.max(0.0)).min(limit.burst.max(1.0));
+        self.updated = self.updated.max(now);
+    }
+
+    /// Returns the time until a token is available, or `None` if one is.
+    fn wait(&self, limit: RateLimit) -> Option<Duration> {
+        if self.tokens >= 1.0 {
+            return None;
+        }
+        let secs = (1.0 - self.tokens) / limit.per_second;
+        Some(Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX))
+    }
+}
+
+/// Built-in [`AdmissionControl`] enforcing per-series and global token
+/// bucket rates.
+///
+/// # Example
+/// ```
+/// use gorilla::{AppendError, DataPoint, RateLimit, RateLimiter, RateLimits, SeriesMap};
+///
+/// let mut map = SeriesMap::<String>::default();
+/// map.set_admission(RateLimiter::new(RateLimits {
+///     per_series: Some(RateLimit { per_second: 100.0, burst: 2.0 }),
+///     ..Default::default()
+/// }));
+/// map.append("cpu".into(), DataPoint::new(1, 1.0)).unwrap();
+/// map.append("cpu".into(), DataPoint::new(2, 1.0)).unwrap();
+/// let err = map.append("cpu".into(), DataPoint::new(3, 1.0)).unwrap_err();
+/// assert!(matches!(err, AppendError::Throttled(_)));
+/// // Other series have their own budget.
+/// map.append("mem".into(), DataPoint::new(3, 1.0)).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateLimiter<K: SeriesKey> {
+    limits: RateLimits,
+    global: Option<Bucket>,
+    series: HashMap<K, Bucket>,
+    /// Number of tracked series after the last pruning pass.
+    pruned_len: usize,
+}
+
+impl<K: SeriesKey> RateLimiter<K> {
+    /// Creates a limiter enforcing `limits`.
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            global: None,
+            series: HashMap::new(),
+            pruned_len: 0,
+        }
+    }
+
+    /// Returns the enforced limits.
+    pub fn limits(&self) -> &RateLimits {
+        &self.limits
+    }
+
+    /// Returns the number of series with tracked rate state.
+    pub fn tracked_series(&self) -> usize {
+        self.series.len()
+    }
+
+    /// Drops the state of series whose buckets refilled completely, as they
+    /// behave exactly like untracked ones. Runs whenever the number of
+    /// tracked series doubles, so idle series do not accumulate.
+    fn prune(&mut self, limit: RateLimit, now: Instant) {
+        if self.series.len() < 2 * self.pruned_len.max(64) {
+            return;
+        }
+        self.series.retain(|_, bucket| {
+            bucket.refill(limit, now);
+            bucket.tokens < limit.burst.max(1.0)
+        });
+        self.pruned_len = self.series.len();
+    }
+}
+
+impl<K: SeriesKey> AdmissionControl<K> for RateLimiter<K> {
+    fn admit(&mut self, key: &K, now: Instant) -> Result<(), Rejection> {
+        let series = match self.limits.per_series {
+            Some(limit) => {
+                let bucket = self
+                    .series
+                    .entry(key.clone())
+                    .or_insert_with(|| Bucket::full(limit, now));
+                bucket.refill(limit, now);
+                if let Some(retry_after) = bucket.wait(limit) {
+                    return Err(Rejection::SeriesRate { retry_after });
+                }
+                Some(limit)
+            }
+            None => None,
+        };
+        if let Some(limit) = self.limits.global {
+            let bucket = self.global.get_or_insert_with(|| Bucket::full(limit, now));
+            bucket.refill(limit, now);
+            if let Some(retry_after) = bucket.wait(limit) {
+                return Err(Rejection::GlobalRate { retry_after });
+            }
+            bucket.tokens -= 1.0;
+        }
+        if let Some(limit) = series {
+            self.series.get_mut(key).unwrap().tokens -= 1.0;
+            self.prune(limit, now);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(per_second: f64, burst: f64) -> Option<RateLimit> {
+        Some(RateLimit { per_second, burst })
+    }
+
+    #[test]
+    fn test_per_series_bucket_refills() {
+        let mut limiter = RateLimiter::new(RateLimits {
+            per_series: limit(10.0, 3.0),
+            ..Default::default()
+        });
+        let t0 = Instant::now();
+        let cpu = "cpu".to_string();
+        for _ in 0..3 {
+            limiter.admit(&cpu, t0).unwrap();
+        }
+        assert_eq!(
+            limiter.admit(&cpu, t0),
+            Err(Rejection::SeriesRate {
+                retry_after: Duration::from_millis(100)
+            })
+        );
+        limiter.admit(&"mem".to_string(), t0).unwrap();
+        limiter
+            .admit(&cpu, t0 + Duration::from_millis(100))
+            .unwrap();
+        assert!(limiter
+            .admit(&cpu, t0 + Duration::from_millis(150))
+            .is_err());
+        // Refills stop at the burst size.
+        for _ in 0..3 {
+            limiter.admit(&cpu, t0 + Duration::from_secs(60)).unwrap();
+        }
+        assert!(limiter.admit(&cpu, t0 + Duration::from_secs(60)).is_err());
+    }
+
+    #[test]
+    fn test_global_limit_and_rejections_are_free() {
+        let mut limiter = RateLimiter::new(RateLimits {
+            per_series: limit(1.0, 1.0),
+            global: limit(1.0, 2.0),
+        });
+        let t0 = Instant::now();
+        limiter.admit(&1u64, t0).unwrap();
+        // A series rejection does not use up global budget.
+        assert!(matches!(
+            limiter.admit(&1u64, t0),
+            Err(Rejection::SeriesRate { .. })
+        ));
+        limiter.admit(&2u64, t0).unwrap();
+        let err = limiter.admit(&3u64, t0).unwrap_err();
+        assert_eq!(
+            err,
+            Rejection::GlobalRate {
+                retry_after: Duration::from_secs(1)
+            }
+        );
+        assert_eq!(err.retry_after(), Duration::from_secs(1));
+        limiter.admit(&3u64, t0 + Duration::from_secs(1)).unwrap();
+    }
+
+    #[test]
+    fn test_idle_series_are_pruned() {
+        let mut limiter = RateLimiter::new(RateLimits {
+            per_series: limit(1.0, 1.0),
+            ..Default::default()
+        });
+        let t0 = Instant::now();
+        for key in 0..127u64 {
+            limiter.admit(&key, t0).unwrap();
+        }
+        assert_eq!(limiter.tracked_series(), 127);
+        limiter.admit(&1000, t0 + Duration::from_secs(5)).unwrap();
+        assert_eq!(limiter.tracked_series(), 1);
+    }
+}
@@ -0,0 +1,498 @@
+//! Arrow interop through the Arrow C Data Interface.
+//!
+//! [`decode_to_record_batch`] exports a block as a struct array with a
+//! `ts` column of type `timestamp[ms]` and a `value` column of type
+//! `float64` — the C Data Interface form of a `RecordBatch` — and
+//! [`encode_record_batch`] encodes such an array back into a block. Any
+//! Arrow implementation imports and exports these structs (e.g.
+//! `arrow::ffi::from_ffi` in arrow-rs, `pyarrow.RecordBatch._import_from_c`
+//! or Polars and DataFusion through arrow-rs), so the crate needs no Arrow
+//! dependency of its own.
+//!
+//! The crate's timestamps are seconds, so they are multiplied by 1000 on
+//! export. Import also accepts `timestamp[s]` columns.
+
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::ptr;
+
+use crate::decoder::{DecodeError, Decoder};
+use crate::encoder::{CompressedBlock, DataPoint, EncodeError, Encoder, EncoderConfig};
+
+/// `ArrowSchema` from the Arrow C Data Interface.
+#[repr(C)]
+#[derive(Debug)]
+pub struct ArrowSchema {
+    pub format: *const c_char,
+    pub name: *const c_char,
+    pub metadata: *const c_char,
+    pub flags: i64,
+    pub n_children: i64,
+    pub children: *mut *mut ArrowSchema,
+    pub dictionary: *mut ArrowSchema,
+    pub release: Option<unsafe extern "C" fn(*mut ArrowSchema)>,
+    pub private_data: *mut c_void,
+}
+
+/// `ArrowArray` from the Arrow C Data Interface.
+#[repr(C)]
+#[derive(Debug)]
+pub struct ArrowArray {
+    pub length: i64,
+    pub null_count: i64,
+    pub offset: i64,
+    pub n_buffers: i64,
+    pub n_children: i64,
+    pub buffers: *mut *const c_void,
+    pub children: *mut *mut ArrowArray,
+    pub dictionary: *mut ArrowArray,
+    pub release: Option<unsafe extern "C" fn(*mut ArrowArray)>,
+    pub private_data: *mut c_void,
+}
+
+/// Error returned by [`encode_record_batch`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArrowError {
+    /// The array is not a struct of a `timestamp[ms]` or `timestamp[s]`
+    /// and a `float64` column.
+    UnsupportedSchema(String),
+    /// The schema or array was already released.
+    Released,
+    /// A column contains nulls, which blocks cannot represent.
+    Nulls,
+    /// A timestamp is negative.
+    NegativeTimestamp(i64),
+    /// A millisecond timestamp is not a whole number of seconds.
+    FractionalTimestamp(i64),
+    /// A timestamp in seconds does not fit in `timestamp[ms]`.
+    TimestampOverflow(u64),
+    /// The block could not be decoded.
+    Decode(DecodeError),
+    /// A row could not be encoded.
+    Encode(EncodeError),
+}
+
+impl std::fmt::Display for ArrowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArrowError::UnsupportedSchema(msg) => write!(f, "unsupported Arrow schema: {msg}"),
+            ArrowError::Released => write!(f, "Arrow structure was already released"),
+            ArrowError::Nulls => write!(f, "Arrow column contains nulls"),
+            ArrowError::NegativeTimestamp(ts) => write!(f, "negative timestamp {ts}"),
+            ArrowError::FractionalTimestamp(ts) => {
+                write!(f, "timestamp {ts}ms is not a whole number of seconds")
+            }
+            ArrowError::TimestampOverflow(ts) => {
+                write!(f, "timestamp {ts} overflows timestamp[ms]")
+            }
+            ArrowError::Decode(err) => write!(f, "{err}"),
+            ArrowError::Encode(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for ArrowError {}
+
+impl From<EncodeError> for ArrowError {
+    fn from(err: EncodeError) -> Self {
+        ArrowError::Encode(err)
+    }
+}
+
+impl From<DecodeError> for ArrowError {
+    fn from(err: DecodeError) -> Self {
+        ArrowError::Decode(err)
+    }
+}
+
+impl Drop for ArrowSchema {
+    fn drop(&mut self) {
+        if let Some(release) = self.release {
+            unsafe { release(self) }
+        }
+    }
+}
+
+impl Drop for ArrowArray {
+    fn drop(&mut self) {
+        if let Some(release) = self.release {
+            unsafe { release(self) }
+        }
+    }
+}
+
+/// Decodes `block` into an exported struct array of `ts` (`timestamp[ms]`)
+/// and `value` (`float64`) columns and its schema.
+///
+/// Dropping either struct releases it; to hand it to a consumer, move it
+/// with `std::ptr::read` / `write` and set the source's `release` to `None`
+/// as the C Data Interface prescribes.
+///
+/// Fails with [`ArrowError::TimestampOverflow`] if a timestamp in
+/// milliseconds does not fit in an `i64`.
+pub fn decode_to_record_batch(
+    block: &CompressedBlock,
+) -> Result<(ArrowArray, ArrowSchema), ArrowError> {
+    let points = Decoder::decode(block)?;
+    let timestamps = points
+        .iter()
+        .map(|dp| {
+            dp.timestamp
+                .checked_mul(1000)
+                .and_then(|ms| i64::try_from(ms).ok())
+                .ok_or(ArrowError::TimestampOverflow(dp.timestamp))
+        })
+        .collect::<Result<Vec<i64>, _>>()?;
+    let values: Vec<f64> = points.iter().map(|dp| dp.value).collect();
+    let len = points.len() as i64;
+
+    let children = vec![
+        primitive_array(Buffer::I64(timestamps), len),
+        primitive_array(Buffer::F64(values), len),
+    ];
+    let array = new_array(len, Buffer::None, 1, children);
+    let schema = new_schema(
+        "+s",
+        "",
+        vec![
+            new_schema("tsm:", "ts", Vec::new()),
+            new_schema("g", "value", Vec::new()),
+        ],
+    );
+    Ok((array, schema))
+}
+
+/// Encodes a struct array of a `timestamp[ms]` and a `float64` column, as
+/// exported by [`decode_to_record_batch`] or any Arrow implementation,
+/// into a block. A `timestamp[s]` column is accepted too. Millisecond
+/// timestamps must be whole seconds, or the call fails with
+/// [`ArrowError::FractionalTimestamp`]. Time zones on the timestamp type
+/// are ignored. The array is only borrowed; the caller still releases it.
+///
+/// # Safety
+/// `array` and `schema` must be valid, matching C Data Interface structs
+/// whose buffers stay alive for the duration of the call.
+pub unsafe fn encode_record_batch(
+    array: &ArrowArray,
+    schema: &ArrowSchema,
+    config: EncoderConfig,
+) -> Result<CompressedBlock, ArrowError> {
+    if array.release.is_none() || schema.release.is_none() {
+        return Err(ArrowError::Released);
+    }
+    let unsupported = |msg: &str| Err(ArrowError::UnsupportedSchema(msg.to_string()));
+    if format(schema) != "+s" || schema.n_children != 2 || array.n_children != 2 {
+        return unsupported("expected a struct of two columns");
+    }
+    let (ts_schema, value_schema) = (&**schema.children, &**schema.children.add(1));
+    let millis = if format(ts_schema).starts_with("tsm:") {
+        true
+    } else if format(ts_schema).starts_with("tss:") {
+        false
+    } else {
+        return unsupported("first column must be timestamp[ms] or timestamp[s]");
+    };
+    if format(value_schema) != "g" {
+        return unsupported("second column must be float64");
+    }
+    if array.offset < 0 || array.length < 0 {
+        return unsupported("negative offset or length");
+    }
+    if has_nulls(array, array.offset, array.length) {
+        return Err(ArrowError::Nulls);
+    }
+    let (ts_array, value_array) = (&**array.children, &**array.children.add(1));
+    let timestamps = column::<i64>(ts_array, array)?;
+    let values = column::<f64>(value_array, array)?;
+
+    let mut encoder = Encoder::with_config(config);
+    for (&ts, &value) in timestamps.iter().zip(values) {
+        if millis && ts % 1000 != 0 {
+            return Err(ArrowError::FractionalTimestamp(ts));
+        }
+        let seconds = if millis { ts / 1000 } else { ts };
+        let seconds = u64::try_from(seconds).map_err(|_| ArrowError::NegativeTimestamp(ts))?;
+        encoder.encode(DataPoint::new(seconds, value))?;
+    }
+    Ok(encoder.finish_into().map_err(EncodeError::from)?)
+}
+
+unsafe fn format(schema: &ArrowSchema) -> &str {
+    if schema.format.is_null() {
+        return "";
+    }
+    CStr::from_ptr(schema.format).to_str().unwrap_or("")
+}
+
+/// Returns the rows of a primitive child column, applying the struct's
+/// offset and length on top of the child's own. The struct's offset and
+/// length must already be checked to be non-negative.
+unsafe fn column<'a, T>(child: &'a ArrowArray, parent: &ArrowArray) -> Result<&'a [T], ArrowError> {
+    let unsupported = |msg: &str| Err(ArrowError::UnsupportedSchema(msg.to_string()));
+    if child.n_buffers != 2 {
+        return unsupported("expected a primitive column");
+    }
+    if child.offset < 0 || child.length < 0 {
+        return unsupported("negative offset or length");
+    }
+    if parent
+        .offset
+        .checked_add(parent.length)
+        .is_none_or(|end| end > child.length)
+    {
+        return unsupported("column is shorter than the struct");
+    }
+    let Some(start) = child.offset.checked_add(parent.offset) else {
+        return unsupported("column offset overflows");
+    };
+    if has_nulls(child, start, parent.length) {
+        return Err(ArrowError::Nulls);
+    }
+    if parent.length == 0 {
+        return Ok(&[]);
+    }
+    let data = *child.buffers.add(1) as *const T;
+    Ok(std::slice::from_raw_parts(
+        data.add(start as usize),
+        parent.length as usize,
+    ))
+}
+
+/// Returns `true` if any of the `len` rows starting at row `start` of
+/// `array`'s validity bitmap is null.
+unsafe fn has_nulls(array: &ArrowArray, start: i64, len: i64) -> bool {
+    let validity = if array.n_buffers > 0 {
+        *array.buffers as *const u8
+    } else {
+        ptr::null()
+    };
+    if array.null_count == 0 || validity.is_null() {
+        return false;
+    }
+    let start = start as usize;
+    (start..start + len as usize).any(|i| *validity.add(i / 8) & (1 << (i % 8)) == 0)
+}
+
+// ── export ─────────────────────────────────────────────────────────────
+
+enum Buffer {
+    None,
+    I64(Vec<i64>),
+    F64(Vec<f64>),
+}
+
+impl Buffer {
+    fn as_ptr(&self) -> *const c_void {
+        match self {
+            Buffer::None => ptr::null(),
+            Buffer::I64(v) => v.as_ptr().cast(),
+            Buffer::F64(v) => v.as_ptr().cast(),
+        }
+    }
+}
+
+/// Memory owned by an exported array.
+struct ArrayPrivate {
+    _data: Buffer,
+    buffers: Box<[*const c_void]>,
+    children: Box<[*mut ArrowArray]>,
+}
+
+/// Memory owned by an exported schema.
+struct SchemaPrivate {
+    _format: CString,
+    _name: CString,
+    children: Box<[*mut ArrowSchema]>,
+}
+
+fn primitive_array(data: Buffer, len: i64) -> ArrowArray {
+    new_array(len, data, 2, Vec::new())
+}
+
+fn new_array(len: i64, data: Buffer, n_buffers: i64, children: Vec<ArrowArray>) -> ArrowArray {
+    let mut buffers = vec![ptr::null(); n_buffers as usize].into_boxed_slice();
+    if n_buffers == 2 {
+        buffers[1] = data.as_ptr();
+    }
+    let children: Box<[*mut ArrowArray]> = children
+        .into_iter()
+        .map(|c| Box::into_raw(Box::new(c)))
+        .collect();
+    let mut private = Box::new(ArrayPrivate {
+        _data: data,
+        buffers,
+        children,
+    });
+    ArrowArray {
+        length: len,
+        null_count: 0,
+        offset: 0,
+        n_buffers,
+        n_children: private.children.len() as i64,
+        buffers: private.buffers.as_mut_ptr(),
+        children: private.children.as_mut_ptr(),
+        dictionary: ptr::null_mut(),
+        release: Some(release_array),
+        private_data: Box::into_raw(private).cast(),
+    }
+}
+
+fn new_schema(format: &str, name: &str, children: Vec<ArrowSchema>) -> ArrowSchema {
+    let format = CString::new(format).unwrap();
+    let name = CString::new(name).unwrap();
+    let children: Box<[*mut ArrowSchema]> = children
+        .into_iter()
+        .map(|c| Box::into_raw(Box::new(c)))
+        .collect();
+    let mut private = Box::new(SchemaPrivate {
+        _format: format,
+        _name: name,
+        children,
+    });
+    ArrowSchema {
+        format: private._format.as_ptr(),
+        name: private._name.as_ptr(),
+        metadata: ptr::null(),
+        flags: 0,
+        n_children: private.children.len() as i64,
+        children: private.children.as_mut_ptr(),
+        dictionary: ptr::null_mut(),
+        release: Some(release_schema),
+        private_data: Box::into_raw(private).cast(),
+    }
+}
+
+unsafe extern "C" fn release_array(array: *mut ArrowArray) {
+    let Some(array) = array.as_mut() else {
+        return;
+    };
+    let private = Box::from_raw(array.private_data.cast::<ArrayPrivate>());
+    for &child in private.children.iter() {
+        drop(Box::from_raw(child));
+    }
+    array.release = None;
+}
+
+unsafe extern "C" fn release_schema(schema: *mut ArrowSchema) {
+    let Some(schema) = schema.as_mut() else {
+        return;
+    };
+    let private = Box::from_raw(schema.private_data.cast::<SchemaPrivate>());
+    for &child in private.children.iter() {
+        drop(Box::from_raw(child));
+    }
+    schema.release = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment::tests::regular_block;
+
+    #[test]
+    fn test_roundtrip_through_c_data_interface() {
+        let original = regular_block(1_700_000_000, 100);
+        let (array, schema) = decode_to_record_batch(&original).unwrap();
+        assert_eq!(array.length, 100);
+        unsafe {
+            assert_eq!(format(&**schema.children), "tsm:");
+            let ts = &**array.children;
+            assert_eq!(*(*ts.buffers.add(1) as *const i64), 1_700_000_000_000);
+            assert_eq!(
+                CStr::from_ptr((**schema.children.add(1)).name).to_str(),
+                Ok("value")
+            );
+            let encoded = encode_record_batch(&array, &schema, EncoderConfig::default()).unwrap();
+            assert_eq!(encoded.bytes, original.bytes);
+        }
+    }
+
+    #[test]
+    fn test_encode_respects_offsets_and_rejects_nulls_and_bad_bounds() {
+        let (mut array, schema) =
+            decode_to_record_batch(&regular_block(1_700_000_000, 10)).unwrap();
+        array.offset = 3;
+        array.length = 4;
+        let sliced = unsafe { encode_record_batch(&array, &schema, Default::default()) }.unwrap();
+        let points = Decoder::decode(&sliced).unwrap();
+        assert_eq!(points.len(), 4);
+        assert_eq!(points[0].value, 3.0);
+
+        let validity = [0b1111_0111u8, 0xFF];
+        unsafe {
+            let values = &mut **array.children.add(1);
+            *values.buffers = validity.as_ptr().cast();
+            values.null_count = 1;
+            assert_eq!(
+                encode_record_batch(&array, &schema, Default::default()).unwrap_err(),
+                ArrowError::Nulls
+            );
+            *values.buffers = ptr::null();
+        }
+
+        array.length = 8;
+        assert!(matches!(
+            unsafe { encode_record_batch(&array, &schema, Default::default()) },
+            Err(ArrowError::UnsupportedSchema(_))
+        ));
+        array.offset = -1;
+        array.length = 4;
+        assert!(matches!(
+            unsafe { encode_record_batch(&array, &schema, Default::default()) },
+            Err(ArrowError::UnsupportedSchema(_))
+        ));
+        array.offset = 3;
+        unsafe { (**array.children).offset = -3 };
+        assert!(matches!(
+            unsafe { encode_record_batch(&array, &schema, Default::default()) },
+            Err(ArrowError::UnsupportedSchema(_))
+        ));
+        unsafe { (**array.children).offset = 0 };
+
+        let mut released = schema;
+        unsafe { release_schema(&mut released) };
+        assert_eq!(
+            unsafe { encode_record_batch(&array, &released, Default::default()) }.unwrap_err(),
+            ArrowError::Released
+        );
+    }
+
+    #[test]
+    fn test_timestamp_units() {
+        let (array, schema) = decode_to_record_batch(&regular_block(1_700_000_000, 4)).unwrap();
+        let set_format = |format: &'static CStr| unsafe {
+            (**schema.children).format = format.as_ptr();
+        };
+        let set_ts = |i: usize, ts: i64| unsafe {
+            let data = *(**array.children).buffers.add(1) as *mut i64;
+            *data.add(i) = ts;
+        };
+        let encode = || unsafe { encode_record_batch(&array, &schema, Default::default()) };
+
+        set_ts(1, 1_700_000_001_500);
+        assert_eq!(
+            encode().unwrap_err(),
+            ArrowError::FractionalTimestamp(1_700_000_001_500)
+        );
+        set_ts(0, -1000);
+        assert_eq!(encode().unwrap_err(), ArrowError::NegativeTimestamp(-1000));
+
+        set_format(c"tss:");
+        for i in 0..4 {
+            set_ts(i, 1_700_000_000 + i as i64);
+        }
+        let points = Decoder::decode(&encode().unwrap()).unwrap();
+        assert_eq!(points[3].timestamp, 1_700_000_003);
+        set_format(c"tsn:");
+        assert!(matches!(
+            encode().unwrap_err(),
+            ArrowError::UnsupportedSchema(_)
+        ));
+        set_format(c"tsm:");
+
+        let huge = i64::MAX as u64 / 1000 + 1;
+        assert_eq!(
+            decode_to_record_batch(&regular_block(huge, 1)).unwrap_err(),
+            ArrowError::TimestampOverflow(huge)
+        );
+    }
+}
@@ -147,6 +147,85 @@ impl BitBuffer {
         Ok(())
     }
 
+    /// Shortens the buffer to `len_bits` bits, discarding everything after.
+    /// Has no effect if `len_bits` is not less than the current length.
+    pub fn truncate(&mut self, len_bits: usize) {
+        if len_bits >= self.len_bits() {
+            return;
+        }
+        let full_bytes = len_bits / 8;
+        let remaining = (len_bits % 8) as u8;
+        if remaining == 0 {
+            self.bytes.truncate(full_bytes);
+            self.bit_count = if full_bytes == 0 { 0 } else { 8 };
+        } else {
+            self.bytes.truncate(full_bytes + 1);
+            let last = self.bytes.last_mut().unwrap();
+            *last &= 0xFF << (8 - remaining);
+            self.bit_count = remaining;
+        }
+    }
+
+    /// Overwrites the `n` bits starting at bit `pos`, all of which must
+    /// already have been written, with the lowest `n` bits of `value`.
+    ///
+    /// # Panics
+    /// Panics if the bits extend past the end of the buffer.
+    pub fn set_bits(&mut self, pos: usize, value: u64, n: u8) {
+        assert!(pos + n as usize <= self.len_bits(), "bits not yet written");
+        set_bits_in(&mut self.bytes, pos, value, n);
+    }
+
+    /// Reserves storage for at least `n` more bits, as for [`Vec::reserve`],
+    /// so that writing them does not reallocate. Never reserves beyond the
+    /// byte limit.
+    pub fn reserve_bits(&mut self, n: usize) {
+        let mut bytes = self.len_bits().saturating_add(n).div_ceil(8);
+        if let Some(max) = self.max_bytes {
+            bytes = bytes.min(max);
+        }
+        self.bytes.reserve(bytes.saturating_sub(self.bytes.len()));
+    }
+
+    /// Returns the offset in bits the next bit is written at, i.e.
+    /// [`len_bits`](BitBuffer::len_bits).
+    #[inline]
+    pub fn position(&self) -> usize {
+        self.len_bits()
+    }
+
+    /// Writes zero bits up to the next byte boundary, so that the next
+    /// section starts at a whole byte.
+    pub fn align_to_byte(&mut self) -> Result<(), BufferFull> {
+        self.pad_to(self.len_bits().next_multiple_of(8))
+    }
+
+    /// Writes zero bits until the buffer holds `n_bits`. Has no effect if it
+    /// already holds at least that many.
+    pub fn pad_to(&mut self, n_bits: usize) -> Result<(), BufferFull> {
+        pad_to(self, n_bits)
+    }
+
+    /// Writes `n` zero bits as a placeholder for a field whose value is
+    /// known only later, such as a count ahead of the data it counts, and
+    /// returns the slot to [`patch`](BitBuffer::patch) it through. `n` must
+    /// be <= 64.
+    pub fn reserve_slot(&mut self, n: u8) -> Result<Slot, BufferFull> {
+        let pos = self.len_bits();
+        self.write_bits(0, n)?;
+        Ok(Slot { pos, width: n })
+    }
+
+    /// Fills in a field reserved by [`reserve_slot`](BitBuffer::reserve_slot).
+    ///
+    /// # Panics
+    /// Panics if `value` does not fit the slot's width, or if the buffer was
+    /// truncated into the slot.
+    pub fn patch(&mut self, slot: Slot, value: u64) {
+        slot.check(value);
+        self.set_bits(slot.pos, value, slot.width);
+    }
+
     /// Returns the number of bytes that can still be added before hitting the
     /// limit, or `None` if no limit is set.
     pub fn remaining_capacity(&self) -> Option<usize> {
@@ -160,6 +239,345 @@ impl Default for BitBuffer {
     }
 }
 
+/// A fixed-width field reserved in a bit buffer, to be filled in once its
+/// value is known, see [`BitWrite::reserve_slot`].
+///
+/// ```
+/// use gorilla::bitbuffer::{BitBuffer, BitReader};
+///
+/// let mut buf = BitBuffer::new();
+/// let count = buf.reserve_slot(8).unwrap();
+/// for value in [3, 1, 4] {
+///     buf.write_bits(value, 4).unwrap();
+/// }
+/// buf.patch(count, 3);
+///
+/// let mut reader = BitReader::new(&buf);
+/// assert_eq!(reader.read_bits(8), Some(3));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pos: usize,
+    width: u8,
+}
+
+impl Slot {
+    /// Returns the bit offset of the field from the start of the buffer.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Returns the width of the field in bits.
+    pub fn width(&self) -> u8 {
+        self.width
+    }
+
+    fn check(&self, value: u64) {
+        assert!(
+            self.width == 64 || value >> self.width == 0,
+            "value {value} does not fit a {}-bit slot",
+            self.width
+        );
+    }
+}
+
+/// Bit-level write access to an encoder's output storage.
+///
+/// Implemented by the growable [`BitBuffer`] and by [`FixedBitBuffer`],
+/// which writes into caller-provided memory without allocating.
+pub trait BitWrite {
+    /// Writes a single bit.
+    ///
+    /// Returns `Err(BufferFull)` if the storage has no room for another byte.
+    fn write_bit(&mut self, bit: bool) -> Result<(), BufferFull>;
+
+    /// Writes the lowest `n` bits of `value` (big-endian order). `n` must be <= 64.
+    ///
+    /// On error some bits of this call may have been written.
+    fn write_bits(&mut self, value: u64, n: u8) -> Result<(), BufferFull> {
+        debug_assert!(n <= 64);
+        for i in (0..n).rev() {
+            self.write_bit((value >> i) & 1 == 1)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the total number of bits written.
+    fn len_bits(&self) -> usize;
+
+    /// Shortens the storage to `len_bits` bits, discarding everything after.
+    fn truncate(&mut self, len_bits: usize);
+
+    /// Overwrites the `n` bits starting at bit `pos`, all of which must
+    /// already have been written, with the lowest `n` bits of `value`.
+    fn set_bits(&mut self, pos: usize, value: u64, n: u8);
+
+    /// Returns the written bytes; the last byte may be partially filled.
+    fn as_bytes(&self) -> &[u8];
+
+    /// Returns the number of bits the storage holds at most, including
+    /// those already written, or `None` if it grows without bound.
+    fn capacity_bits(&self) -> Option<usize> {
+        None
+    }
+
+    /// Writes zero bits up to the next byte boundary, so that the next
+    /// section starts at a whole byte.
+    fn align_to_byte(&mut self) -> Result<(), BufferFull> {
+        self.pad_to(self.len_bits().next_multiple_of(8))
+    }
+
+    /// Writes zero bits until the storage holds `n_bits`. Has no effect if
+    /// it already holds at least that many.
+    fn pad_to(&mut self, n_bits: usize) -> Result<(), BufferFull> {
+        pad_to(self, n_bits)
+    }
+
+    /// Writes `n` zero bits as a placeholder for a field whose value is
+    /// known only later and returns the slot to [`patch`](BitWrite::patch)
+    /// it through. `n` must be <= 64.
+    fn reserve_slot(&mut self, n: u8) -> Result<Slot, BufferFull> {
+        let pos = self.len_bits();
+        self.write_bits(0, n)?;
+        Ok(Slot { pos, width: n })
+    }
+
+    /// Fills in a field reserved by [`reserve_slot`](BitWrite::reserve_slot).
+    ///
+    /// # Panics
+    /// Panics if `value` does not fit the slot's width, or if the storage was
+    /// truncated into the slot.
+    fn patch(&mut self, slot: Slot, value: u64) {
+        slot.check(value);
+        self.set_bits(slot.pos, value, slot.width);
+    }
+}
+
+impl BitWrite for BitBuffer {
+    #[inline]
+    fn write_bit(&mut self, bit: bool) -> Result<(), BufferFull> {
+        BitBuffer::write_bit(self, bit)
+    }
+
+    fn write_bits(&mut self, value: u64, n: u8) -> Result<(), BufferFull> {
+        BitBuffer::write_bits(self, value, n)
+    }
+
+    fn len_bits(&self) -> usize {
+        BitBuffer::len_bits(self)
+    }
+
+    fn truncate(&mut self, len_bits: usize) {
+        BitBuffer::truncate(self, len_bits)
+    }
+
+    fn set_bits(&mut self, pos: usize, value: u64, n: u8) {
+        BitBuffer::set_bits(self, pos, value, n)
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        BitBuffer::as_bytes(self)
+    }
+
+    fn capacity_bits(&self) -> Option<usize> {
+        self.max_bytes.map(|max| max.saturating_mul(8))
+    }
+}
+
+/// Writes zero bits to `buf` until it holds `n_bits`.
+fn pad_to<W: BitWrite + ?Sized>(buf: &mut W, n_bits: usize) -> Result<(), BufferFull> {
+    while buf.len_bits() < n_bits {
+        let n = (n_bits - buf.len_bits()).min(64);
+        buf.write_bits(0, n as u8)?;
+    }
+    Ok(())
+}
+
+/// Overwrites `n` bits of `bytes` starting at bit `pos`, big-endian.
+fn set_bits_in(bytes: &mut [u8], pos: usize, value: u64, n: u8) {
+    for i in 0..n as usize {
+        let bit = (value >> (n as usize - 1 - i)) & 1;
+        let (byte, shift) = ((pos + i) / 8, 7 - (pos + i) % 8);
+        bytes[byte] = (bytes[byte] & !(1 << shift)) | ((bit as u8) << shift);
+    }
+}
+
+/// A bit buffer over fixed, caller-provided storage such as `&mut [u8]` or
+/// `[u8; N]`. It never allocates; writes past the end of the storage return
+/// `Err(BufferFull)`.
+///
+/// ```
+/// use gorilla::bitbuffer::{BitWrite, FixedBitBuffer};
+///
+/// let mut buf = FixedBitBuffer::new([0u8; 2]);
+/// buf.write_bits(0xABC, 12).unwrap();
+/// assert_eq!(buf.as_bytes(), &[0xAB, 0xC0]);
+/// assert!(buf.write_bits(0xFF, 8).is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct FixedBitBuffer<S> {
+    storage: S,
+    len_bits: usize,
+}
+
+impl<S: AsRef<[u8]> + AsMut<[u8]>> FixedBitBuffer<S> {
+    /// Creates an empty buffer writing into `storage`. The storage's
+    /// existing contents are overwritten as bits are written.
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            len_bits: 0,
+        }
+    }
+
+    /// Returns the capacity of the storage in bytes.
+    pub fn capacity(&self) -> usize {
+        self.storage.as_ref().len()
+    }
+
+    /// Returns `true` if no bits have been written.
+    pub fn is_empty(&self) -> bool {
+        self.len_bits == 0
+    }
+
+    /// Consumes the buffer and returns the underlying storage.
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+}
+
+impl<S: AsRef<[u8]> + AsMut<[u8]>> BitWrite for FixedBitBuffer<S> {
+    #[inline]
+    fn write_bit(&mut self, bit: bool) -> Result<(), BufferFull> {
+        let byte_idx = self.len_bits / 8;
+        let bit_idx = self.len_bits % 8;
+        let bytes = self.storage.as_mut();
+        if byte_idx >= bytes.len() {
+            return Err(BufferFull);
+        }
+        if bit_idx == 0 {
+            bytes[byte_idx] = 0;
+        }
+        if bit {
+            bytes[byte_idx] |= 1 << (7 - bit_idx);
+        }
+        self.len_bits += 1;
+        Ok(())
+    }
+
+    fn len_bits(&self) -> usize {
+        self.len_bits
+    }
+
+    fn truncate(&mut self, len_bits: usize) {
+        if len_bits >= self.len_bits {
+            return;
+        }
+        self.len_bits = len_bits;
+        let remaining = len_bits % 8;
+        if remaining != 0 {
+            self.storage.as_mut()[len_bits / 8] &= 0xFF << (8 - remaining);
+        }
+    }
+
+    fn set_bits(&mut self, pos: usize, value: u64, n: u8) {
+        assert!(pos + n as usize <= self.len_bits, "bits not yet written");
+        set_bits_in(self.storage.as_mut(), pos, value, n);
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.storage.as_ref()[..self.len_bits.div_ceil(8)]
+    }
+
+    fn capacity_bits(&self) -> Option<usize> {
+        Some(self.capacity() * 8)
+    }
+}
+
+/// Test double that writes into a [`BitBuffer`] until it holds a set number
+/// of bits and fails every write after that with `Err(BufferFull)`, leaving
+/// a partial write behind exactly as a byte limit would. Lets code that
+/// recovers from a full buffer be tested at any bit position, without
+/// working out which byte limit the format would hit there.
+///
+/// Available with the `test-util` feature.
+///
+/// ```
+/// use gorilla::bitbuffer::{BitWrite, FailingBitBuffer};
+/// use gorilla::{DataPoint, EncodeError, Encoder};
+///
+/// // Room for the first point, but not the second point's value.
+/// let mut encoder = Encoder::with_buffer(FailingBitBuffer::new(140), Default::default());
+/// encoder.encode(DataPoint::new(1609459200, 1.0)).unwrap();
+/// let err = encoder.encode(DataPoint::new(1609459260, 2.0)).unwrap_err();
+/// assert_eq!(err, EncodeError::BufferFull);
+/// // The encoder drops the part of the second point that was written.
+/// assert_eq!(encoder.buffer().len_bits(), 128);
+/// ```
+#[cfg(feature = "test-util")]
+#[derive(Debug, Clone)]
+pub struct FailingBitBuffer {
+    inner: BitBuffer,
+    fail_after: usize,
+}
+
+#[cfg(feature = "test-util")]
+impl FailingBitBuffer {
+    /// Creates an empty buffer that accepts `fail_after` bits.
+    pub fn new(fail_after: usize) -> Self {
+        Self {
+            inner: BitBuffer::new(),
+            fail_after,
+        }
+    }
+
+    /// Returns the number of bits accepted before writes fail.
+    pub fn fail_after(&self) -> usize {
+        self.fail_after
+    }
+
+    /// Moves the failure point, e.g. to `usize::MAX` to let a recovery
+    /// path write freely.
+    pub fn set_fail_after(&mut self, fail_after: usize) {
+        self.fail_after = fail_after;
+    }
+
+    /// Consumes the wrapper and returns the bits written so far.
+    pub fn into_inner(self) -> BitBuffer {
+        self.inner
+    }
+}
+
+#[cfg(feature = "test-util")]
+impl BitWrite for FailingBitBuffer {
+    fn write_bit(&mut self, bit: bool) -> Result<(), BufferFull> {
+        if self.inner.len_bits() >= self.fail_after {
+            return Err(BufferFull);
+        }
+        self.inner.write_bit(bit)
+    }
+
+    fn len_bits(&self) -> usize {
+        self.inner.len_bits()
+    }
+
+    fn truncate(&mut self, len_bits: usize) {
+        self.inner.truncate(len_bits)
+    }
+
+    fn set_bits(&mut self, pos: usize, value: u64, n: u8) {
+        self.inner.set_bits(pos, value, n)
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        self.inner.as_bytes()
+    }
+
+    fn capacity_bits(&self) -> Option<usize> {
+        Some(self.fail_after)
+    }
+}
+
 /// A cursor for reading bits sequentially from a `BitBuffer`.
 #[derive(Debug)]
 pub struct BitReader<'a> {
@@ -195,6 +613,29 @@ impl<'a> BitReader<'a> {
         self.total_bits.saturating_sub(self.pos)
     }
 
+    /// Returns the read position in bits from the start of the buffer, to
+    /// [`seek`](BitReader::seek) back to later.
+    #[doc(alias = "tell")]
+    #[inline]
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Moves the read position to bit `pos` from the start of the buffer,
+    /// e.g. to re-parse a section or resume at a checkpoint. A position past
+    /// the end leaves nothing to read.
+    #[inline]
+    pub fn seek(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+
+    /// Skips to the next byte boundary, past the padding written by
+    /// [`BitBuffer::align_to_byte`]. Has no effect at a byte boundary.
+    #[inline]
+    pub fn align_to_byte(&mut self) {
+        self.pos = self.pos.next_multiple_of(8);
+    }
+
     /// Returns `true` if there are no more bits to read.
     #[inline]
     pub fn is_exhausted(&self) -> bool {
@@ -228,6 +669,33 @@ impl<'a> BitReader<'a> {
         Some(value)
     }
 
+    /// Fills `dst` with consecutive `bits_each`-bit values (big-endian),
+    /// e.g. a section of fixed-width fields. `bits_each` must be <= 64.
+    /// Returns `None`, without reading anything, if fewer than
+    /// `dst.len() * bits_each` bits remain.
+    pub fn read_exact_bits(&mut self, dst: &mut [u64], bits_each: u8) -> Option<()> {
+        debug_assert!(bits_each <= 64);
+        if dst.len().checked_mul(bits_each as usize)? > self.remaining() {
+            return None;
+        }
+        for slot in dst {
+            *slot = self.read_bits(bits_each)?;
+        }
+        Some(())
+    }
+
+    /// Returns the next `n` bytes as a slice of the underlying storage,
+    /// without copying, and advances past them. Returns `None` if the reader
+    /// is not at a byte boundary or fewer than `n` bytes remain.
+    pub fn read_aligned_bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        if !self.pos.is_multiple_of(8) || n.checked_mul(8)? > self.remaining() {
+            return None;
+        }
+        let start = self.pos / 8;
+        self.pos += n * 8;
+        Some(&self.bytes[start..start + n])
+    }
+
     /// Peeks at the next bit without advancing the position.
     #[inline]
     pub fn peek_bit(&self) -> Option<bool> {
@@ -341,6 +809,201 @@ mod tests {
         buf.write_bits(0xDEADBEEF, 32).unwrap();
     }
 
+    #[test]
+    fn test_truncate() {
+        let mut buf = BitBuffer::new();
+        buf.write_bits(0xFFFF, 16).unwrap();
+        buf.write_bits(0b101, 3).unwrap();
+
+        buf.truncate(12);
+        assert_eq!(buf.len_bits(), 12);
+        assert_eq!(buf.as_bytes(), &[0xFF, 0xF0]);
+
+        // Writing after a truncate continues from the new end.
+        buf.write_bits(0b0101, 4).unwrap();
+        let mut reader = BitReader::new(&buf);
+        assert_eq!(reader.read_bits(16), Some(0xFFF5));
+
+        buf.truncate(8);
+        assert_eq!(buf.as_bytes(), &[0xFF]);
+        buf.truncate(0);
+        assert!(buf.is_empty());
+        buf.write_bit(true).unwrap();
+        assert_eq!(buf.as_bytes(), &[0x80]);
+    }
+
+    #[test]
+    fn test_set_bits_overwrites_in_place() {
+        let mut buf = BitBuffer::new();
+        buf.write_bits(0, 20).unwrap();
+        buf.set_bits(5, 0x3FF, 10);
+        assert_eq!(buf.len_bits(), 20);
+        assert_eq!(BitReader::new(&buf).read_bits(20), Some(0x3FF << 5));
+
+        let mut fixed = FixedBitBuffer::new([0xFFu8; 2]);
+        fixed.write_bits(0xFFF, 12).unwrap();
+        fixed.set_bits(3, 0, 6);
+        assert_eq!(fixed.as_bytes(), &[0xE0, 0x70]);
+    }
+
+    #[test]
+    fn test_reserved_slots_are_patched_in_place() {
+        let mut buf = BitBuffer::new();
+        buf.write_bit(true).unwrap();
+        let len = buf.reserve_slot(12).unwrap();
+        assert_eq!((len.position(), len.width()), (1, 12));
+        buf.write_bits(0xAB, 8).unwrap();
+        let wide = buf.reserve_slot(64).unwrap();
+        buf.patch(len, 0xFFF);
+        buf.patch(wide, u64::MAX);
+        let mut reader = BitReader::new(&buf);
+        assert_eq!(reader.read_bits(21), Some(1 << 20 | 0xFFF << 8 | 0xAB));
+        assert_eq!(reader.read_bits(64), Some(u64::MAX));
+
+        // Over fixed storage, which runs out.
+        let mut fixed = FixedBitBuffer::new([0xFFu8; 2]);
+        let slot = fixed.reserve_slot(4).unwrap();
+        fixed.write_bits(0x5, 4).unwrap();
+        fixed.patch(slot, 0xA);
+        assert_eq!(fixed.as_bytes(), &[0xA5]);
+        assert_eq!(fixed.reserve_slot(9), Err(BufferFull));
+    }
+
+    #[test]
+    fn test_bulk_reads() {
+        let mut buf = BitBuffer::new();
+        for value in [5, 0, 7, 2] {
+            buf.write_bits(value, 3).unwrap();
+        }
+        buf.write_bits(0, 4).unwrap();
+        buf.write_bits(0xBEEF, 16).unwrap();
+        buf.write_bit(true).unwrap();
+
+        let mut reader = BitReader::new(&buf);
+        let mut fields = [0; 4];
+        assert_eq!(reader.read_exact_bits(&mut fields, 3), Some(()));
+        assert_eq!(fields, [5, 0, 7, 2]);
+        // Not at a byte boundary yet.
+        assert_eq!(reader.read_aligned_bytes(1), None);
+        reader.read_bits(4).unwrap();
+        assert_eq!(reader.read_aligned_bytes(2), Some(&[0xBE, 0xEF][..]));
+
+        // Neither read consumes anything when the bits run out.
+        assert_eq!(reader.read_aligned_bytes(1), None);
+        let mut two = [0; 2];
+        assert_eq!(reader.read_exact_bits(&mut two, 1), None);
+        assert_eq!(reader.remaining(), 1);
+        assert_eq!(reader.read_exact_bits(&mut two[..1], 1), Some(()));
+        assert_eq!(two, [1, 0]);
+    }
+
+    #[test]
+    fn test_sections_aligned_to_bytes() {
+        let mut buf = BitBuffer::new();
+        buf.write_bits(0b101, 3).unwrap();
+        buf.align_to_byte().unwrap();
+        assert_eq!(buf.position(), 8);
+        // Already aligned: nothing is written.
+        buf.align_to_byte().unwrap();
+        buf.write_bits(0xCD, 8).unwrap();
+        buf.write_bit(true).unwrap();
+        buf.pad_to(100).unwrap();
+        buf.pad_to(50).unwrap();
+        assert_eq!(buf.len_bits(), 100);
+
+        let mut reader = BitReader::new(&buf);
+        assert_eq!(reader.read_bits(3), Some(0b101));
+        reader.align_to_byte();
+        assert_eq!(reader.position(), 8);
+        reader.align_to_byte();
+        assert_eq!(reader.read_aligned_bytes(1), Some(&[0xCD][..]));
+        assert_eq!(reader.read_bit(), Some(true));
+        assert_eq!(reader.read_bits(64), Some(0));
+        assert_eq!(reader.remaining(), 100 - 81);
+
+        // Padding fails like any write once the storage is full.
+        let mut fixed = FixedBitBuffer::new([0u8; 1]);
+        fixed.write_bit(true).unwrap();
+        fixed.align_to_byte().unwrap();
+        assert_eq!(fixed.pad_to(9), Err(BufferFull));
+    }
+
+    #[test]
+    fn test_seek_rereads_from_any_position() {
+        let mut buf = BitBuffer::new();
+        buf.write_bits(0xA5, 8).unwrap();
+        buf.write_bits(0b011, 3).unwrap();
+
+        let mut reader = BitReader::new(&buf);
+        reader.read_bits(4).unwrap();
+        let section = reader.position();
+        assert_eq!(reader.read_bits(7), Some(0b010_1011));
+        assert_eq!(reader.read_bit(), None);
+        reader.seek(section);
+        assert_eq!(reader.read_bits(4), Some(0b0101));
+        reader.seek(0);
+        assert_eq!(reader.read_bits(8), Some(0xA5));
+
+        reader.seek(100);
+        assert_eq!(reader.remaining(), 0);
+        assert_eq!(reader.read_bit(), None);
+        assert_eq!(reader.peek_bit(), None);
+    }
+
+    #[test]
+    fn test_reserve_bits_avoids_reallocation() {
+        let mut buf = BitBuffer::new();
+        buf.write_bits(0b1, 3).unwrap();
+        buf.reserve_bits(10_000);
+        let start = buf.as_bytes().as_ptr();
+        for _ in 0..10_000 / 64 {
+            buf.write_bits(u64::MAX, 64).unwrap();
+        }
+        assert_eq!(buf.as_bytes().as_ptr(), start);
+
+        // The limit caps the reservation, not the other way round.
+        let mut limited = BitBuffer::with_limit(4);
+        limited.reserve_bits(usize::MAX);
+        limited.write_bits(0, 32).unwrap();
+        assert_eq!(limited.write_bit(true), Err(BufferFull));
+    }
+
+    #[test]
+    fn test_fixed_buffer_over_slice() {
+        let mut storage = [0xAAu8; 3];
+        let mut buf = FixedBitBuffer::new(&mut storage[..]);
+        buf.write_bits(0xDEAD, 16).unwrap();
+        buf.write_bits(0b1, 1).unwrap();
+        assert_eq!(buf.len_bits(), 17);
+        assert_eq!(buf.as_bytes(), &[0xDE, 0xAD, 0x80]);
+
+        buf.write_bits(0x7F, 7).unwrap();
+        assert_eq!(buf.write_bit(true), Err(BufferFull));
+        assert_eq!(buf.len_bits(), 24);
+
+        buf.truncate(12);
+        assert_eq!(buf.as_bytes(), &[0xDE, 0xA0]);
+        let mut reader = BitReader::from_raw(buf.as_bytes(), buf.len_bits());
+        assert_eq!(reader.read_bits(12), Some(0xDEA));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn test_failing_buffer_fails_at_exact_bit() {
+        let mut buf = FailingBitBuffer::new(10);
+        buf.write_bits(0xFF, 8).unwrap();
+        // A write straddling the limit stops after its first two bits.
+        assert_eq!(buf.write_bits(0b1010, 4), Err(BufferFull));
+        assert_eq!(buf.len_bits(), 10);
+        assert_eq!(buf.as_bytes(), &[0xFF, 0x80]);
+        assert_eq!(buf.write_bit(false), Err(BufferFull));
+
+        buf.truncate(4);
+        buf.set_fail_after(usize::MAX);
+        buf.write_bits(0, 60).unwrap();
+        assert_eq!(buf.into_inner().len_bits(), 64);
+    }
+
     #[test]
     fn test_set_limit() {
         let mut buf = BitBuffer::new();
@@ -0,0 +1,546 @@
+//! Append-only files holding many blocks of many series, with a directory
+//! for looking blocks up by series and time range.
+//!
+//! Unlike a [`Segment`](crate::segment::Segment), which is assembled in
+//! memory and written once, a block file grows as blocks are sealed, e.g.
+//! over hours of ingestion, and can be reopened to append more. Its layout
+//! is a header, one record per block and, once the file is finished, a
+//! directory of all records:
+//!
+//! | field            | size                        |
+//! |------------------|-----------------------------|
+//! | magic            | 4 bytes (`GBLK`)            |
+//! | version          | 1 byte                      |
+//! | *records*        |                             |
+//! | kind             | 1 byte: 0 = block           |
+//! | key length       | 4 bytes (LE)                |
+//! | key              | key length bytes            |
+//! | start            | 8 bytes (LE)                |
+//! | end              | 8 bytes (LE)                |
+//! | block length     | 4 bytes (LE)                |
+//! | checksum         | 4 bytes (LE): [`crc32c`] of the block |
+//! | block            | [`CompressedBlock::to_bytes`] output |
+//! | *directory*      |                             |
+//! | kind             | 1 byte: 1 = directory       |
+//! | entry count      | 4 bytes (LE)                |
+//! | entries          | per record: key length, key, start, end, block offset (8 bytes LE), block length and checksum |
+//! | directory offset | 8 bytes (LE)                |
+//! | magic            | 4 bytes (`GBLK`)            |
+//!
+//! [`BlockFileReader::open`] reads the directory if the file ends with one.
+//! Otherwise, e.g. after a crash, it rebuilds the directory by walking the
+//! records, ignoring a record torn off at the end. [`BlockFileWriter::open`]
+//! drops the directory and any torn record before appending.
+//!
+//! ```
+//! use gorilla::block_file::{BlockFileReader, BlockFileWriter};
+//! use gorilla::{DataPoint, Encoder};
+//!
+//! let path = std::env::temp_dir().join(format!("gorilla-doc-{}.gblk", std::process::id()));
+//! let mut writer = BlockFileWriter::create(&path).unwrap();
+//! for hour in 0..3u64 {
+//!     let mut encoder = Encoder::new();
+//!     for t in 0..60 {
+//!         encoder.encode(DataPoint::new(hour * 3600 + t * 60, 1.0)).unwrap();
+//!     }
+//!     encoder.finish().unwrap();
+//!     let end = hour * 3600 + 59 * 60;
+//!     writer.append(b"cpu", hour * 3600, end, &encoder.into_compressed()).unwrap();
+//! }
+//! writer.finish().unwrap();
+//!
+//! let reader = BlockFileReader::open(&path).unwrap();
+//! let hits: Vec<_> = reader.lookup(b"cpu", 4000, 7300).collect();
+//! assert_eq!(hits.len(), 2);
+//! assert_eq!(reader.read_block(hits[0]).unwrap().count(), 60);
+//! # std::fs::remove_file(&path).unwrap();
+//! ```
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::checksum::crc32c;
+use crate::encoder::CompressedBlock;
+
+const MAGIC: &[u8; 4] = b"GBLK";
+const VERSION: u8 = 1;
+const HEADER_LEN: u64 = 5;
+/// Directory offset and magic at the very end of a finished file.
+const TRAILER_LEN: u64 = 12;
+/// Record kind: a block follows.
+const KIND_BLOCK: u8 = 0;
+/// Record kind: the directory follows.
+const KIND_DIRECTORY: u8 = 1;
+
+/// Location and metadata of one block inside a block file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockFileEntry {
+    /// Encoded key of the series the block belongs to, see
+    /// [`SeriesKey`](crate::SeriesKey).
+    pub key: Vec<u8>,
+    /// Smallest timestamp in the block.
+    pub start: u64,
+    /// Largest timestamp in the block.
+    pub end: u64,
+    /// CRC32C of the serialized block.
+    pub checksum: u32,
+    /// Byte offset of the serialized block within the file.
+    offset: u64,
+    /// Length of the serialized block in bytes.
+    len: u32,
+}
+
+impl BlockFileEntry {
+    /// Returns the byte offset of the serialized block within the file.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the length of the serialized block in bytes.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns `true` if the serialized block is empty, which a valid
+    /// block never is.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.key.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.key);
+        out.extend_from_slice(&self.start.to_le_bytes());
+        out.extend_from_slice(&self.end.to_le_bytes());
+        out.extend_from_slice(&self.offset.to_le_bytes());
+        out.extend_from_slice(&self.len.to_le_bytes());
+        out.extend_from_slice(&self.checksum.to_le_bytes());
+    }
+}
+
+/// Appends blocks to a block file.
+///
+/// Records are written through a buffer; [`sync`](BlockFileWriter::sync)
+/// makes them durable. A file whose writer is dropped without
+/// [`finish`](BlockFileWriter::finish) has no directory but stays readable.
+#[derive(Debug)]
+pub struct BlockFileWriter {
+    file: BufWriter<File>,
+    entries: Vec<BlockFileEntry>,
+    /// Length of the file including buffered writes.
+    len: u64,
+}
+
+impl BlockFileWriter {
+    /// Creates an empty block file at `path`, replacing any existing file.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&[VERSION])?;
+        Ok(Self {
+            file,
+            entries: Vec::new(),
+            len: HEADER_LEN,
+        })
+    }
+
+    /// Opens an existing block file for appending. Its directory, if any,
+    /// and a record torn off at the end are removed; the directory is
+    /// written again by [`finish`](BlockFileWriter::finish).
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let (entries, data_end) = load(&mut file)?;
+        file.set_len(data_end)?;
+        file.seek(SeekFrom::Start(data_end))?;
+        Ok(Self {
+            file: BufWriter::new(file),
+            entries,
+            len: data_end,
+        })
+    }
+
+    /// Appends a block belonging to the series with encoded key `key`,
+    /// covering timestamps `start..=end`.
+    pub fn append(
+        &mut self,
+        key: &[u8],
+        start: u64,
+        end: u64,
+        block: &CompressedBlock,
+    ) -> io::Result<()> {
+        let block = block.to_bytes();
+        let len = u32::try_from(block.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "block too large"))?;
+        let checksum = crc32c(&block);
+        let mut record = Vec::with_capacity(29 + key.len() + block.len());
+        record.push(KIND_BLOCK);
+        record.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        record.extend_from_slice(key);
+        record.extend_from_slice(&start.to_le_bytes());
+        record.extend_from_slice(&end.to_le_bytes());
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(&checksum.to_le_bytes());
+        let offset = self.len + record.len() as u64;
+        record.extend_from_slice(&block);
+        self.file.write_all(&record)?;
+        self.len += record.len() as u64;
+        self.entries.push(BlockFileEntry {
+            key: key.to_vec(),
+            start,
+            end,
+            checksum,
+            offset,
+            len,
+        });
+        Ok(())
+    }
+
+    /// Returns the entries of all blocks in the file, in file order.
+    pub fn entries(&self) -> &[BlockFileEntry] {
+        &self.entries
+    }
+
+    /// Writes buffered records and syncs them to disk.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file.get_ref().sync_data()
+    }
+
+    /// Writes the directory and syncs the file.
+    pub fn finish(mut self) -> io::Result<()> {
+        let mut directory = vec![KIND_DIRECTORY];
+        directory.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for entry in &self.entries {
+            entry.write_to(&mut directory);
+        }
+        directory.extend_from_slice(&self.len.to_le_bytes());
+        directory.extend_from_slice(MAGIC);
+        self.file.write_all(&directory)?;
+        self.file.flush()?;
+        self.file.get_ref().sync_all()
+    }
+}
+
+/// A block file opened for lookups.
+#[derive(Debug)]
+pub struct BlockFileReader {
+    path: PathBuf,
+    entries: Vec<BlockFileEntry>,
+    /// Entry indices by series key, in file order.
+    series: HashMap<Vec<u8>, Vec<usize>>,
+}
+
+impl BlockFileReader {
+    /// Opens a block file and loads its directory, or rebuilds it from the
+    /// records if the file was not finished.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let (entries, _) = load(&mut File::open(&path)?)?;
+        let mut series: HashMap<_, Vec<_>> = HashMap::new();
+        for (i, entry) in entries.iter().enumerate() {
+            series.entry(entry.key.clone()).or_default().push(i);
+        }
+        Ok(Self {
+            path,
+            entries,
+            series,
+        })
+    }
+
+    /// Returns the path of the block file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the entries of all blocks in the file, in file order.
+    pub fn entries(&self) -> &[BlockFileEntry] {
+        &self.entries
+    }
+
+    /// Returns the entries of the blocks of series `key` that overlap
+    /// `start..=end`, in file order.
+    pub fn lookup<'a>(
+        &'a self,
+        key: &[u8],
+        start: u64,
+        end: u64,
+    ) -> impl Iterator<Item = &'a BlockFileEntry> + 'a {
+        self.series
+            .get(key)
+            .into_iter()
+            .flatten()
+            .map(|&i| &self.entries[i])
+            .filter(move |entry| entry.start <= end && entry.end >= start)
+    }
+
+    /// Reads, verifies and parses the block described by `entry`.
+    pub fn read_block(&self, entry: &BlockFileEntry) -> io::Result<CompressedBlock> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(entry.offset))?;
+        let mut bytes = vec![0; entry.len()];
+        file.read_exact(&mut bytes)?;
+        if crc32c(&bytes) != entry.checksum {
+            return Err(invalid("block checksum mismatch"));
+        }
+        CompressedBlock::from_bytes(&bytes)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Reads the entries of a block file and the offset where its last
+/// complete record ends, from the directory if there is a valid one and
+/// from the records otherwise.
+fn load(file: &mut File) -> io::Result<(Vec<BlockFileEntry>, u64)> {
+    let len = file.metadata()?.len();
+    let mut header = [0; HEADER_LEN as usize];
+    file.seek(SeekFrom::Start(0))?;
+    if file.read_exact(&mut header).is_err() || &header[..4] != MAGIC {
+        return Err(invalid("not a block file"));
+    }
+    if header[4] != VERSION {
+        return Err(invalid("unsupported block file version"));
+    }
+    if let Some(loaded) = read_directory(file, len)? {
+        return Ok(loaded);
+    }
+    scan(file, len)
+}
+
+/// Reads the directory of a finished file, or returns `None` if the file
+/// does not end with a valid one.
+fn read_directory(file: &mut File, len: u64) -> io::Result<Option<(Vec<BlockFileEntry>, u64)>> {
+    if len < HEADER_LEN + 5 + TRAILER_LEN {
+        return Ok(None);
+    }
+    let mut trailer = [0; TRAILER_LEN as usize];
+    file.seek(SeekFrom::Start(len - TRAILER_LEN))?;
+    file.read_exact(&mut trailer)?;
+    let offset = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+    if &trailer[8..] != MAGIC || offset < HEADER_LEN || offset > len - TRAILER_LEN {
+        return Ok(None);
+    }
+    let mut bytes = vec![0; (len - TRAILER_LEN - offset) as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut bytes)?;
+    Ok(parse_directory(&bytes, offset).map(|entries| (entries, offset)))
+}
+
+/// Parses a directory ending at the end of `bytes` whose block offsets all
+/// lie before `data_end`.
+fn parse_directory(bytes: &[u8], data_end: u64) -> Option<Vec<BlockFileEntry>> {
+    let mut pos = 0usize;
+    let mut take = |n: usize| -> Option<&[u8]> {
+        let slice = bytes.get(pos..pos.checked_add(n)?)?;
+        pos += n;
+        Some(slice)
+    };
+    if take(1)? != [KIND_DIRECTORY] {
+        return None;
+    }
+    let count = u32::from_le_bytes(take(4)?.try_into().unwrap());
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let key_len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+        let key = take(key_len)?.to_vec();
+        let mut word = || Some(u64::from_le_bytes(take(8)?.try_into().unwrap()));
+        let (start, end, offset) = (word()?, word()?, word()?);
+        let len = u32::from_le_bytes(take(4)?.try_into().unwrap());
+        let checksum = u32::from_le_bytes(take(4)?.try_into().unwrap());
+        let in_data = offset
+            .checked_add(len as u64)
+            .is_some_and(|end| end <= data_end);
+        if offset < HEADER_LEN || !in_data {
+            return None;
+        }
+        entries.push(BlockFileEntry {
+            key,
+            start,
+            end,
+            checksum,
+            offset,
+            len,
+        });
+    }
+    (pos == bytes.len()).then_some(entries)
+}
+
+/// Walks the records of a file without a valid directory, stopping at a
+/// directory or at a record that is cut short.
+fn scan(file: &mut File, len: u64) -> io::Result<(Vec<BlockFileEntry>, u64)> {
+    file.seek(SeekFrom::Start(HEADER_LEN))?;
+    let mut reader = BufReader::new(file);
+    let mut entries = Vec::new();
+    let mut pos = HEADER_LEN;
+    loop {
+        let mut kind = [0];
+        if reader.read(&mut kind)? == 0 || kind[0] == KIND_DIRECTORY {
+            break;
+        }
+        if kind[0] != KIND_BLOCK {
+            return Err(invalid("unknown block file record kind"));
+        }
+        let Some(key_len) = read_word(&mut reader, 4)? else {
+            break;
+        };
+        if pos + 5 + key_len > len {
+            break;
+        }
+        let mut key = vec![0; key_len as usize];
+        if reader.read_exact(&mut key).is_err() {
+            break;
+        }
+        let (Some(start), Some(end), Some(block_len), Some(checksum)) = (
+            read_word(&mut reader, 8)?,
+            read_word(&mut reader, 8)?,
+            read_word(&mut reader, 4)?,
+            read_word(&mut reader, 4)?,
+        ) else {
+            break;
+        };
+        let offset = pos + 29 + key_len;
+        if offset + block_len > len {
+            break;
+        }
+        reader.seek_relative(block_len as i64)?;
+        entries.push(BlockFileEntry {
+            key,
+            start,
+            end,
+            checksum: checksum as u32,
+            offset,
+            len: block_len as u32,
+        });
+        pos = offset + block_len;
+    }
+    Ok((entries, pos))
+}
+
+/// Reads an `n`-byte little-endian integer, or returns `None` if the file
+/// ends first.
+fn read_word(reader: &mut impl Read, n: usize) -> io::Result<Option<u64>> {
+    let mut bytes = [0; 8];
+    match reader.read_exact(&mut bytes[..n]) {
+        Ok(()) => Ok(Some(u64::from_le_bytes(bytes))),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment::tests::{regular_block, temp_dir};
+    use std::fs;
+
+    #[test]
+    fn test_block_file_lookup_and_reopen() {
+        let dir = temp_dir("block-file-lookup");
+        let path = dir.join("a.gblk");
+        let mut writer = BlockFileWriter::create(&path).unwrap();
+        for hour in 0..4 {
+            let start = hour * 3600;
+            writer
+                .append(b"cpu", start, start + 59 * 60, &regular_block(start, 60))
+                .unwrap();
+            writer
+                .append(b"mem", start, start + 60, &regular_block(start, 2))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        // Appending after reopening replaces the directory.
+        let mut writer = BlockFileWriter::open(&path).unwrap();
+        assert_eq!(writer.entries().len(), 8);
+        writer
+            .append(b"cpu", 14_400, 14_400, &regular_block(14_400, 1))
+            .unwrap();
+        writer.finish().unwrap();
+
+        let reader = BlockFileReader::open(&path).unwrap();
+        assert_eq!(reader.entries().len(), 9);
+        let starts: Vec<_> = reader
+            .lookup(b"cpu", 3600, 14_400)
+            .map(|entry| entry.start)
+            .collect();
+        assert_eq!(starts, [3600, 7200, 10_800, 14_400]);
+        assert_eq!(reader.lookup(b"mem", 61, 3599).count(), 0);
+        assert_eq!(reader.lookup(b"disk", 0, u64::MAX).count(), 0);
+        let entry = reader.lookup(b"mem", 7200, 7200).next().unwrap();
+        assert_eq!(
+            reader.read_block(entry).unwrap().to_bytes(),
+            regular_block(7200, 2).to_bytes()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_block_file_recovers_from_torn_tail() {
+        let dir = temp_dir("block-file-torn");
+        let path = dir.join("a.gblk");
+        let mut writer = BlockFileWriter::create(&path).unwrap();
+        writer.append(b"cpu", 0, 240, &regular_block(0, 5)).unwrap();
+        writer
+            .append(b"cpu", 300, 540, &regular_block(300, 5))
+            .unwrap();
+        writer.sync().unwrap();
+        drop(writer);
+        // A crash while appending the third record.
+        let mut bytes = fs::read(&path).unwrap();
+        let complete = bytes.len();
+        bytes.extend_from_slice(&[KIND_BLOCK, 3, 0, 0, 0, b'c']);
+        fs::write(&path, bytes).unwrap();
+
+        let reader = BlockFileReader::open(&path).unwrap();
+        assert_eq!(reader.entries().len(), 2);
+        assert_eq!(reader.read_block(&reader.entries()[1]).unwrap().count(), 5);
+
+        let mut writer = BlockFileWriter::open(&path).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), complete as u64);
+        writer
+            .append(b"cpu", 600, 840, &regular_block(600, 5))
+            .unwrap();
+        writer.finish().unwrap();
+        let reader = BlockFileReader::open(&path).unwrap();
+        assert_eq!(reader.lookup(b"cpu", 600, 600).count(), 1);
+
+        // Damaged block bytes fail their checksum.
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[reader.entries()[0].offset() as usize + 20] ^= 0xFF;
+        fs::write(&path, bytes).unwrap();
+        let err = reader.read_block(&reader.entries()[0]).unwrap_err();
+        assert_eq!(err.to_string(), "block checksum mismatch");
+
+        fs::write(&path, b"GSEG\x02").unwrap();
+        assert!(BlockFileReader::open(&path).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_directory_entry_past_data_is_rejected() {
+        let directory = |offset: u64, len: u32| {
+            let mut bytes = vec![KIND_DIRECTORY];
+            bytes.extend_from_slice(&1u32.to_le_bytes());
+            bytes.extend_from_slice(&0u32.to_le_bytes());
+            for word in [0, 60, offset] {
+                bytes.extend_from_slice(&word.to_le_bytes());
+            }
+            bytes.extend_from_slice(&len.to_le_bytes());
+            bytes.extend_from_slice(&0u32.to_le_bytes());
+            bytes
+        };
+        assert_eq!(
+            parse_directory(&directory(HEADER_LEN, 10), 100)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(parse_directory(&directory(HEADER_LEN, 100), 100).is_none());
+        assert!(parse_directory(&directory(u64::MAX - 4, 10), 100).is_none());
+    }
+}
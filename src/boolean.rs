@@ -0,0 +1,421 @@
+//! Boolean series (up/down checks, feature flags) stored as run lengths.
+//!
+//! Timestamps use the same delta-of-delta stream as [`Encoder`], terminated
+//! by the usual end marker; values are kept apart as the first value plus
+//! the lengths of the runs of equal values. A check that stays up for a day
+//! of 10-second samples costs one run instead of 8640 value bits.
+//!
+//! [`Encoder`]: crate::encoder::Encoder
+
+use crate::bitbuffer::{BitBuffer, BitReader, BufferFull};
+use crate::decoder::{next_timestamp, DecodeError, Decoder, DodResult};
+use crate::encoder::{checked_delta, write_delta_of_delta, DataPoint, EncodeError};
+
+const BOOL_FORMAT_VERSION: u8 = 1;
+/// Header flag: the first run holds `true` values.
+const FLAG_FIRST_TRUE: u8 = 0b0000_0001;
+
+/// A finished boolean block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoolBlock {
+    /// Delta-of-delta encoded timestamp stream.
+    pub bytes: Vec<u8>,
+    /// Number of valid bits in `bytes`.
+    pub total_bits: usize,
+    /// Number of points in the block.
+    pub count: u64,
+    /// Value of the first run.
+    pub first: bool,
+    /// Lengths of the runs of equal values, alternating from `first`. They
+    /// add up to `count`.
+    pub runs: Vec<u64>,
+}
+
+impl BoolBlock {
+    /// Returns an iterator over the `(timestamp, value)` pairs.
+    pub fn iter(&self) -> BoolIter<'_> {
+        BoolIter {
+            reader: BitReader::from_raw(&self.bytes, self.total_bits),
+            runs: &self.runs,
+            run: 0,
+            left_in_run: self.runs.first().copied().unwrap_or(0),
+            value: self.first,
+            yielded: 0,
+            prev_timestamp: 0,
+            prev_delta: 0,
+            done: false,
+        }
+    }
+
+    /// Returns the points as `DataPoint`s with values `1.0` and `0.0`, so
+    /// boolean blocks can feed the same aggregation code as float blocks;
+    /// the mean over a range is then the fraction of time up.
+    pub fn points(&self) -> impl Iterator<Item = Result<DataPoint, DecodeError>> + '_ {
+        self.iter().map(|result| {
+            result.map(|(timestamp, value)| DataPoint::new(timestamp, value as u8 as f64))
+        })
+    }
+
+    /// Returns the number of `true` values, computed from the runs without
+    /// decoding timestamps.
+    pub fn count_true(&self) -> u64 {
+        let skip = if self.first { 0 } else { 1 };
+        self.runs.iter().skip(skip).step_by(2).sum()
+    }
+
+    /// Serializes the block into a self-contained byte representation:
+    ///
+    /// | field        | size          |
+    /// |--------------|---------------|
+    /// | version      | 1 byte        |
+    /// | flags        | 1 byte        |
+    /// | count        | 8 bytes (LE)  |
+    /// | total bits   | 8 bytes (LE)  |
+    /// | run count    | 4 bytes (LE)  |
+    /// | runs         | LEB128 varint per run |
+    /// | stream bytes | `ceil(total_bits / 8)` |
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(22 + self.runs.len() * 2 + self.bytes.len());
+        out.push(BOOL_FORMAT_VERSION);
+        out.push(if self.first { FLAG_FIRST_TRUE } else { 0 });
+        out.extend_from_slice(&self.count.to_le_bytes());
+        out.extend_from_slice(&(self.total_bits as u64).to_le_bytes());
+        out.extend_from_slice(&(self.runs.len() as u32).to_le_bytes());
+        for &run in &self.runs {
+            let mut run = run;
+            while run >= 0x80 {
+                out.push(run as u8 | 0x80);
+                run >>= 7;
+            }
+            out.push(run as u8);
+        }
+        out.extend_from_slice(&self.bytes);
+        out
+    }
+
+    /// Parses a block produced by [`to_bytes`](BoolBlock::to_bytes).
+    /// `bytes` must contain exactly one serialized block.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut pos = 0;
+        let mut take = |n: usize| -> Result<&[u8], DecodeError> {
+            let slice = bytes.get(pos..pos + n).ok_or(DecodeError::UnexpectedEnd)?;
+            pos += n;
+            Ok(slice)
+        };
+        let version = take(1)?[0];
+        if version != BOOL_FORMAT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let flags = take(1)?[0];
+        if flags & !FLAG_FIRST_TRUE != 0 {
+            return Err(DecodeError::MalformedHeader("unknown block flags"));
+        }
+        let count = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let total_bits = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let total_bits = usize::try_from(total_bits)
+            .map_err(|_| DecodeError::MalformedHeader("total bits out of range"))?;
+        let run_count = u32::from_le_bytes(take(4)?.try_into().unwrap());
+        let mut runs = Vec::new();
+        for _ in 0..run_count {
+            let mut run = 0u64;
+            let mut shift = 0;
+            loop {
+                let byte = take(1)?[0];
+                if shift > 63 {
+                    return Err(DecodeError::MalformedHeader("run length out of range"));
+                }
+                run |= ((byte & 0x7F) as u64) << shift;
+                shift += 7;
+                if byte & 0x80 == 0 {
+                    break;
+                }
+            }
+            runs.push(run);
+        }
+        if runs.iter().try_fold(0u64, |sum, &run| sum.checked_add(run)) != Some(count) {
+            return Err(DecodeError::MalformedHeader(
+                "run lengths do not add up to count",
+            ));
+        }
+        let stream = take(total_bits.div_ceil(8))?.to_vec();
+        if pos != bytes.len() {
+            return Err(DecodeError::MalformedHeader("trailing bytes after block"));
+        }
+        Ok(BoolBlock {
+            bytes: stream,
+            total_bits,
+            count,
+            first: flags & FLAG_FIRST_TRUE != 0,
+            runs,
+        })
+    }
+}
+
+/// Compresses a stream of boolean points.
+///
+/// # Example
+/// ```
+/// use gorilla::BoolEncoder;
+///
+/// let mut encoder = BoolEncoder::new();
+/// for t in 0..100 {
+///     encoder.encode(t * 10, !(40..45).contains(&t)).unwrap();
+/// }
+/// let block = encoder.finish().unwrap();
+/// assert_eq!(block.runs, vec![40, 5, 55]);
+/// assert_eq!(block.count_true(), 95);
+/// ```
+#[derive(Debug)]
+pub struct BoolEncoder {
+    buf: BitBuffer,
+    count: u64,
+    prev_timestamp: u64,
+    prev_delta: i64,
+    first: bool,
+    runs: Vec<u64>,
+}
+
+impl BoolEncoder {
+    /// Creates an empty encoder.
+    pub fn new() -> Self {
+        Self::with_buffer(BitBuffer::new())
+    }
+
+    /// Creates an encoder whose timestamp stream will not grow beyond
+    /// `max_bytes`.
+    pub fn with_limit(max_bytes: usize) -> Self {
+        Self::with_buffer(BitBuffer::with_limit(max_bytes))
+    }
+
+    fn with_buffer(buf: BitBuffer) -> Self {
+        Self {
+            buf,
+            count: 0,
+            prev_timestamp: 0,
+            prev_delta: 0,
+            first: false,
+            runs: Vec::new(),
+        }
+    }
+
+    /// Returns the number of points encoded so far.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Appends a point.
+    ///
+    /// Returns `Err(EncodeError::TimestampOutOfRange)` without encoding the
+    /// point if its distance from the previous timestamp, or that
+    /// distance's difference from the previous one, does not fit in an
+    /// `i64`.
+    pub fn encode(&mut self, timestamp: u64, value: bool) -> Result<(), EncodeError> {
+        if self.count == 0 {
+            self.buf.write_bits(timestamp, 64)?;
+            self.first = value;
+            self.runs.push(1);
+        } else {
+            let (delta, dod) = checked_delta(self.prev_timestamp, timestamp)
+                .and_then(|delta| Some((delta, delta.checked_sub(self.prev_delta)?)))
+                .ok_or(EncodeError::TimestampOutOfRange {
+                    previous: self.prev_timestamp,
+                    timestamp,
+                })?;
+            write_delta_of_delta(&mut self.buf, dod)?;
+            self.prev_delta = delta;
+            // Runs alternate starting from `first`, so the current run holds
+            // `first` exactly when it has an even index.
+            let current = self.first ^ self.runs.len().is_multiple_of(2);
+            if value == current {
+                *self.runs.last_mut().unwrap() += 1;
+            } else {
+                self.runs.push(1);
+            }
+        }
+        self.prev_timestamp = timestamp;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Writes the end-of-stream marker and returns the finished block.
+    pub fn finish(mut self) -> Result<BoolBlock, BufferFull> {
+        self.buf.write_bits(0b1111, 4)?;
+        self.buf.write_bits(0xFFFF_FFFF_FFFF_FFFF, 64)?;
+        let total_bits = self.buf.len_bits();
+        Ok(BoolBlock {
+            bytes: self.buf.into_bytes(),
+            total_bits,
+            count: self.count,
+            first: self.first,
+            runs: self.runs,
+        })
+    }
+}
+
+impl Default for BoolEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Iterator returned by [`BoolBlock::iter`].
+pub struct BoolIter<'a> {
+    reader: BitReader<'a>,
+    runs: &'a [u64],
+    run: usize,
+    left_in_run: u64,
+    value: bool,
+    yielded: u64,
+    prev_timestamp: u64,
+    prev_delta: i64,
+    done: bool,
+}
+
+impl BoolIter<'_> {
+    fn next_timestamp(&mut self) -> Result<Option<u64>, DecodeError> {
+        if self.yielded == 0 {
+            return Ok(self.reader.read_bits(64));
+        }
+        match Decoder::decode_delta_of_delta(&mut self.reader)? {
+            DodResult::EndOfStream => Ok(None),
+            DodResult::Value(dod) => {
+                self.prev_delta = if self.yielded == 1 {
+                    dod
+                } else {
+                    self.prev_delta
+                        .checked_add(dod)
+                        .ok_or(DecodeError::TimestampOverflow)?
+                };
+                next_timestamp(self.prev_timestamp, self.prev_delta).map(Some)
+            }
+        }
+    }
+
+    fn next_value(&mut self) -> Option<bool> {
+        while self.left_in_run == 0 {
+            self.run += 1;
+            self.left_in_run = *self.runs.get(self.run)?;
+            self.value = !self.value;
+        }
+        self.left_in_run -= 1;
+        Some(self.value)
+    }
+}
+
+impl Iterator for BoolIter<'_> {
+    type Item = Result<(u64, bool), DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let timestamp = match self.next_timestamp() {
+            Ok(Some(timestamp)) => timestamp,
+            Ok(None) => {
+                self.done = true;
+                return None;
+            }
+            Err(err) => {
+                self.done = true;
+                return Some(Err(err));
+            }
+        };
+        let Some(value) = self.next_value() else {
+            self.done = true;
+            return Some(Err(DecodeError::CountMismatch {
+                expected: self.runs.iter().sum(),
+                actual: self.yielded + 1,
+            }));
+        };
+        self.prev_timestamp = timestamp;
+        self.yielded += 1;
+        Some(Ok((timestamp, value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::{Accumulator, AggFn};
+
+    fn encode(points: &[(u64, bool)]) -> BoolBlock {
+        let mut enc = BoolEncoder::new();
+        for &(t, v) in points {
+            enc.encode(t, v).unwrap();
+        }
+        enc.finish().unwrap()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let points: Vec<_> = (0..500u64)
+            .map(|i| (1_000 + i * 10 + i % 3, i % 50 < 7 || i % 97 == 0))
+            .collect();
+        let block = encode(&points);
+        let decoded: Vec<_> = block.iter().map(Result::unwrap).collect();
+        assert_eq!(decoded, points);
+        assert_eq!(
+            block.count_true(),
+            points.iter().filter(|&&(_, v)| v).count() as u64
+        );
+    }
+
+    #[test]
+    fn test_steady_series_is_one_run() {
+        let points: Vec<_> = (0..8640).map(|i| (i * 10, true)).collect();
+        let block = encode(&points);
+        assert_eq!(block.runs, vec![8640]);
+        assert!(block.first);
+        // 64-bit first timestamp, a 9-bit second delta, one bit per later
+        // timestamp and the 68-bit end marker.
+        assert_eq!(block.total_bits, 64 + 9 + 8638 + 68);
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let block = encode(&[(1, false), (2, false), (3, true), (4, false)]);
+        let bytes = block.to_bytes();
+        assert_eq!(BoolBlock::from_bytes(&bytes).unwrap(), block);
+        assert_eq!(
+            BoolBlock::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        let mut bad = bytes.clone();
+        bad[22] = 9; // first run length no longer matches the count
+        assert!(matches!(
+            BoolBlock::from_bytes(&bad),
+            Err(DecodeError::MalformedHeader(_))
+        ));
+    }
+
+    #[test]
+    fn test_out_of_range_timestamp_is_rejected() {
+        let mut enc = BoolEncoder::new();
+        enc.encode(i64::MAX as u64, true).unwrap();
+        enc.encode(i64::MAX as u64 + 1, false).unwrap();
+        assert_eq!(
+            enc.encode(0, true),
+            Err(EncodeError::TimestampOutOfRange {
+                previous: i64::MAX as u64 + 1,
+                timestamp: 0,
+            })
+        );
+        let block = enc.finish().unwrap();
+        let decoded: Vec<_> = block.iter().map(Result::unwrap).collect();
+        assert_eq!(
+            decoded,
+            [(i64::MAX as u64, true), (i64::MAX as u64 + 1, false)]
+        );
+    }
+
+    #[test]
+    fn test_points_feed_float_aggregation() {
+        let block = encode(&[(0, true), (10, true), (20, false), (30, true)]);
+        let mut acc = Accumulator::new();
+        for dp in block.points() {
+            acc.push(dp.unwrap().value);
+        }
+        assert_eq!(acc.result(AggFn::Mean), Some(0.75));
+        assert_eq!(acc.result(AggFn::Min), Some(0.0));
+    }
+}
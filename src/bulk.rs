@@ -0,0 +1,100 @@
+//! Compression of many series at once on the rayon thread pool, e.g. for
+//! backfills (feature `rayon`).
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use rayon::prelude::*;
+
+use crate::encoder::{CompressedBlock, DataPoint, EncodeError, Encoder};
+
+/// Compresses every series into one block with the default
+/// [`EncoderConfig`](crate::EncoderConfig), spreading the series over the
+/// rayon thread pool. Each block is the same as encoding its points in
+/// order with [`Encoder::new`] and finishing it, or the error that stopped
+/// it, such as [`EncodeError::TimestampOutOfRange`] for points too far
+/// apart. Keys are expected to be distinct; of repeated ones, an arbitrary
+/// result is kept.
+///
+/// ```
+/// use gorilla::{compress_all, DataPoint, Decoder};
+///
+/// let series = (0..100u64).map(|id| {
+///     let points = (0..500).map(|i| DataPoint::new(i * 60, (id * i) as f64)).collect();
+///     (id, points)
+/// });
+/// let blocks = compress_all(series.collect::<Vec<_>>());
+/// assert_eq!(blocks.len(), 100);
+/// let block = blocks[&7].as_ref().unwrap();
+/// assert_eq!(Decoder::decode(block).unwrap()[3], DataPoint::new(180, 21.0));
+/// ```
+pub fn compress_all<K, I>(series: I) -> HashMap<K, Result<CompressedBlock, EncodeError>>
+where
+    K: Eq + Hash + Send,
+    I: IntoParallelIterator<Item = (K, Vec<DataPoint>)>,
+{
+    series
+        .into_par_iter()
+        .map(|(key, points)| (key, compress(points)))
+        .collect()
+}
+
+fn compress(points: Vec<DataPoint>) -> Result<CompressedBlock, EncodeError> {
+    let mut encoder = Encoder::new();
+    encoder.try_extend(points)?;
+    encoder.finish()?;
+    Ok(encoder.into_compressed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+
+    #[test]
+    fn test_matches_serial_encoding() {
+        let series: Vec<(String, Vec<DataPoint>)> = (0..64u64)
+            .map(|id| {
+                let points = (0..200 + id)
+                    .map(|i| DataPoint::new(i * 15 + i % 4, (i as f64 + id as f64).sqrt()))
+                    .collect();
+                (format!("series-{id}"), points)
+            })
+            .collect();
+        let blocks = compress_all(series.clone());
+        assert_eq!(blocks.len(), series.len());
+        for (key, points) in series {
+            let mut encoder = Encoder::new();
+            for &dp in &points {
+                encoder.encode(dp).unwrap();
+            }
+            encoder.finish().unwrap();
+            let block = blocks[&key].as_ref().unwrap();
+            assert_eq!(block.bytes(), encoder.into_compressed().bytes());
+            assert_eq!(Decoder::decode(block).unwrap(), points);
+        }
+    }
+
+    #[test]
+    fn test_unsorted_points_are_kept() {
+        let points = vec![DataPoint::new(120, 2.0), DataPoint::new(60, 1.0)];
+        let blocks = compress_all(vec![("cpu", points.clone())]);
+        let block = blocks["cpu"].as_ref().unwrap();
+        assert_eq!(Decoder::decode(block).unwrap(), points);
+    }
+
+    #[test]
+    fn test_unencodable_series_is_an_error() {
+        let far = vec![DataPoint::new(0, 1.0), DataPoint::new(u64::MAX, 2.0)];
+        let near = vec![DataPoint::new(0, 1.0)];
+        let blocks = compress_all(vec![("far", far), ("near", near)]);
+        assert_eq!(
+            blocks["far"].as_ref().unwrap_err(),
+            &EncodeError::TimestampOutOfRange {
+                previous: 0,
+                timestamp: u64::MAX
+            }
+        );
+        assert!(blocks["near"].is_ok());
+    }
+}
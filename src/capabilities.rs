@@ -0,0 +1,368 @@
+//! Block format capabilities exchanged between peers.
+//!
+//! Services that ship [`CompressedBlock`]s to each other send their
+//! [`FormatCapabilities`] in a handshake and [`negotiate`] the set both
+//! sides read before writing any block. During a rolling upgrade the new
+//! build thus keeps writing blocks the old one understands, e.g. with the
+//! default codecs and without header sections the old build does not know,
+//! instead of the old build failing on them with
+//! [`DecodeError::UnsupportedVersion`] or an unknown-flags error.
+//!
+//! [`negotiate`]: FormatCapabilities::negotiate
+
+use crate::codec::{TimestampScheme, ValueScheme};
+use crate::decoder::DecodeError;
+use crate::encoder::{
+    CompressedBlock, EncoderConfig, BLOCK_FORMAT_VERSION, FLAG_CHECKSUM, FLAG_CODEC, FLAG_GAPS,
+    FLAG_INDEX, FLAG_METADATA, FLAG_PRIORS, FLAG_STATS, FLAG_WATERMARK, KNOWN_FLAGS,
+};
+use crate::metadata::BlockMetadata;
+
+/// What a peer reads: block format versions, optional header sections and
+/// codecs. Codecs are listed in order of preference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatCapabilities {
+    /// Oldest block format version read.
+    pub min_version: u8,
+    /// Newest block format version read.
+    pub max_version: u8,
+    /// Optional block header sections read, as a set of the associated
+    /// constants such as [`FormatCapabilities::CHECKSUM`].
+    pub features: u8,
+    /// Timestamp schemes read, preferred first.
+    pub timestamp_codecs: Vec<TimestampScheme>,
+    /// Value schemes read, preferred first.
+    pub value_codecs: Vec<ValueScheme>,
+}
+
+impl FormatCapabilities {
+    /// Feature: blocks carry a checksum.
+    pub const CHECKSUM: u8 = FLAG_CHECKSUM;
+    /// Feature: blocks carry statistics.
+    pub const STATS: u8 = FLAG_STATS;
+    /// Feature: blocks carry a checkpoint index.
+    pub const INDEX: u8 = FLAG_INDEX;
+    /// Feature: blocks carry metadata.
+    pub const METADATA: u8 = FLAG_METADATA;
+    /// Feature: blocks carry a completeness watermark.
+    pub const WATERMARK: u8 = FLAG_WATERMARK;
+    /// Feature: blocks carry encoder priors.
+    pub const PRIORS: u8 = FLAG_PRIORS;
+    /// Feature: blocks use codecs other than the defaults. Without it only
+    /// [`TimestampScheme::DeltaOfDelta`] and [`ValueScheme::Gorilla`] are
+    /// agreed on, whatever codecs are listed, streams end with the
+    /// end-of-stream marker, see [`EncoderConfig::count_terminated`], and
+    /// first timestamps are stored in full, see [`EncoderConfig::epoch`],
+    /// and values are not quantized, see [`EncoderConfig::quantizer`].
+    pub const CODEC: u8 = FLAG_CODEC;
+    /// Feature: blocks mark missing samples.
+    pub const GAPS: u8 = FLAG_GAPS;
+
+    /// Returns the capabilities of this build.
+    pub fn current() -> Self {
+        Self {
+            min_version: BLOCK_FORMAT_VERSION,
+            max_version: BLOCK_FORMAT_VERSION,
+            features: KNOWN_FLAGS,
+            timestamp_codecs: vec![
+                TimestampScheme::DeltaOfDelta,
+                TimestampScheme::Delta,
+                TimestampScheme::Raw,
+                TimestampScheme::RunLength,
+            ],
+            value_codecs: vec![ValueScheme::Gorilla, ValueScheme::Chimp, ValueScheme::Raw],
+        }
+    }
+
+    /// Returns what both `self` and `peer` read: the newest common version
+    /// and the common features and codecs, in `self`'s order of preference.
+    ///
+    /// ```
+    /// use gorilla::{FormatCapabilities, ValueScheme};
+    ///
+    /// let ours = FormatCapabilities::current();
+    /// // An older build that knows neither codec choice nor Chimp.
+    /// let theirs = FormatCapabilities {
+    ///     features: FormatCapabilities::CHECKSUM | FormatCapabilities::STATS,
+    ///     value_codecs: vec![ValueScheme::Gorilla],
+    ///     ..FormatCapabilities::current()
+    /// };
+    /// let agreed = ours.negotiate(&theirs).unwrap();
+    /// assert_eq!(agreed.value_codecs, [ValueScheme::Gorilla]);
+    /// ```
+    pub fn negotiate(&self, peer: &FormatCapabilities) -> Result<Self, NegotiationError> {
+        let min_version = self.min_version.max(peer.min_version);
+        let max_version = self.max_version.min(peer.max_version);
+        if min_version > max_version {
+            return Err(NegotiationError::NoCommonVersion {
+                ours: (self.min_version, self.max_version),
+                theirs: (peer.min_version, peer.max_version),
+            });
+        }
+        let features = self.features & peer.features;
+        let codecs = features & Self::CODEC != 0;
+        let timestamp_codecs: Vec<_> = self
+            .timestamp_codecs
+            .iter()
+            .copied()
+            .filter(|c| peer.timestamp_codecs.contains(c))
+            .filter(|&c| codecs || c == TimestampScheme::default())
+            .collect();
+        if timestamp_codecs.is_empty() {
+            return Err(NegotiationError::NoCommonTimestampCodec);
+        }
+        let value_codecs: Vec<_> = self
+            .value_codecs
+            .iter()
+            .copied()
+            .filter(|c| peer.value_codecs.contains(c))
+            .filter(|&c| codecs || c == ValueScheme::default())
+            .collect();
+        if value_codecs.is_empty() {
+            return Err(NegotiationError::NoCommonValueCodec);
+        }
+        Ok(Self {
+            min_version: max_version,
+            max_version,
+            features,
+            timestamp_codecs,
+            value_codecs,
+        })
+    }
+
+    /// Returns whether a peer with these capabilities reads `block`.
+    pub fn can_read(&self, block: &CompressedBlock) -> bool {
+        (self.min_version..=self.max_version).contains(&BLOCK_FORMAT_VERSION)
+            && block.flags() & !self.features == 0
+            && self.timestamp_codecs.contains(&block.timestamp_codec)
+            && self.value_codecs.contains(&block.value_codec)
+    }
+
+    /// Returns `current` restricted so that the blocks it produces are
+    /// readable with these capabilities: codecs not listed are replaced by
+    /// the preferred ones, the raw fallback is turned off unless both raw
+    /// schemes are listed, streams keep their end-of-stream marker and
+    /// checksums, checkpoint indexes, metadata and priors are dropped unless
+    /// their feature is present. Statistics and
+    /// watermarks are not set through the encoder configuration; check
+    /// blocks carrying them with [`can_read`](FormatCapabilities::can_read).
+    pub fn encoder_config(&self, current: &EncoderConfig) -> EncoderConfig {
+        let mut config = current.clone();
+        if !self.timestamp_codecs.contains(&config.timestamp_codec) {
+            config.timestamp_codec = self.timestamp_codecs.first().copied().unwrap_or_default();
+        }
+        if !self.value_codecs.contains(&config.value_codec) {
+            config.value_codec = self.value_codecs.first().copied().unwrap_or_default();
+        }
+        if !self.timestamp_codecs.contains(&TimestampScheme::Raw)
+            || !self.value_codecs.contains(&ValueScheme::Raw)
+        {
+            config.raw_fallback = false;
+        }
+        if self.features & Self::CODEC == 0 {
+            config.count_terminated = false;
+            config.epoch = None;
+            config.quantizer = None;
+        }
+        if self.features & Self::CHECKSUM == 0 {
+            config.checksum = false;
+        }
+        if self.features & Self::INDEX == 0 {
+            config.checkpoint_interval = None;
+        }
+        if self.features & Self::METADATA == 0 {
+            config.metadata = BlockMetadata::default();
+        }
+        if self.features & Self::PRIORS == 0 {
+            config.priors = None;
+        }
+        config
+    }
+
+    /// Serializes the capabilities:
+    ///
+    /// | field                 | size |
+    /// |-----------------------|------|
+    /// | min version           | 1 byte |
+    /// | max version           | 1 byte |
+    /// | features              | 1 byte |
+    /// | timestamp codecs      | count (1 byte), then one [`TimestampScheme::id`] byte each |
+    /// | value codecs          | count (1 byte), then one [`ValueScheme::id`] byte each |
+    ///
+    /// Later versions may append fields, so
+    /// [`from_bytes`](FormatCapabilities::from_bytes) ignores trailing bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![self.min_version, self.max_version, self.features];
+        out.push(self.timestamp_codecs.len() as u8);
+        out.extend(self.timestamp_codecs.iter().map(|c| c.id()));
+        out.push(self.value_codecs.len() as u8);
+        out.extend(self.value_codecs.iter().map(|c| c.id()));
+        out
+    }
+
+    /// Parses capabilities produced by
+    /// [`to_bytes`](FormatCapabilities::to_bytes), possibly by a newer
+    /// build: unknown codec identifiers and trailing bytes are skipped.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut pos = 0;
+        let mut take = |n: usize| -> Result<&[u8], DecodeError> {
+            let slice = bytes.get(pos..pos + n).ok_or(DecodeError::UnexpectedEnd)?;
+            pos += n;
+            Ok(slice)
+        };
+        let header = take(3)?;
+        let (min_version, max_version, features) = (header[0], header[1], header[2]);
+        if min_version > max_version {
+            return Err(DecodeError::MalformedHeader("empty version range"));
+        }
+        let len = take(1)?[0] as usize;
+        let timestamp_codecs = take(len)?
+            .iter()
+            .filter_map(|&id| TimestampScheme::from_id(id))
+            .collect();
+        let len = take(1)?[0] as usize;
+        let value_codecs = take(len)?
+            .iter()
+            .filter_map(|&id| ValueScheme::from_id(id))
+            .collect();
+        Ok(Self {
+            min_version,
+            max_version,
+            features,
+            timestamp_codecs,
+            value_codecs,
+        })
+    }
+}
+
+impl Default for FormatCapabilities {
+    fn default() -> Self {
+        Self::current()
+    }
+}
+
+/// Error returned by [`FormatCapabilities::negotiate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NegotiationError {
+    /// The version ranges, as `(min, max)`, do not overlap.
+    NoCommonVersion { ours: (u8, u8), theirs: (u8, u8) },
+    /// No timestamp scheme is read by both peers.
+    NoCommonTimestampCodec,
+    /// No value scheme is read by both peers.
+    NoCommonValueCodec,
+}
+
+impl std::fmt::Display for NegotiationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NegotiationError::NoCommonVersion { ours, theirs } => write!(
+                f,
+                "no common block version: ours {}..={}, theirs {}..={}",
+                ours.0, ours.1, theirs.0, theirs.1
+            ),
+            NegotiationError::NoCommonTimestampCodec => write!(f, "no common timestamp codec"),
+            NegotiationError::NoCommonValueCodec => write!(f, "no common value codec"),
+        }
+    }
+}
+
+impl std::error::Error for NegotiationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::{DataPoint, Encoder};
+    use crate::lossy::QuantizeSpec;
+
+    fn encode(config: EncoderConfig) -> CompressedBlock {
+        let mut enc = Encoder::with_config(config);
+        for i in 0..50u64 {
+            enc.encode(DataPoint::new(1_000 + i * 10, i as f64 * 0.25))
+                .unwrap();
+        }
+        enc.finish().unwrap();
+        enc.into_compressed()
+    }
+
+    #[test]
+    fn test_negotiate_downgrades_for_older_peer() {
+        let ours = FormatCapabilities::current();
+        let old = FormatCapabilities {
+            features: FormatCapabilities::CHECKSUM | FormatCapabilities::STATS,
+            timestamp_codecs: vec![TimestampScheme::DeltaOfDelta],
+            value_codecs: vec![ValueScheme::Gorilla, ValueScheme::Chimp],
+            ..FormatCapabilities::current()
+        };
+        let agreed = ours.negotiate(&old).unwrap();
+        assert_eq!(agreed, old.negotiate(&ours).unwrap());
+        // Chimp is listed but unusable without the codec byte.
+        assert_eq!(agreed.value_codecs, [ValueScheme::Gorilla]);
+        assert_eq!(ours.negotiate(&ours).unwrap(), ours);
+
+        let wanted = EncoderConfig {
+            checksum: true,
+            checkpoint_interval: Some(8),
+            timestamp_codec: TimestampScheme::Delta,
+            value_codec: ValueScheme::Chimp,
+            count_terminated: true,
+            epoch: Some(0),
+            quantizer: Some(QuantizeSpec::AbsError(0.5)),
+            raw_fallback: true,
+            ..Default::default()
+        };
+        assert!(!old.can_read(&encode(wanted.clone())));
+        let config = agreed.encoder_config(&wanted);
+        assert!(config.checksum);
+        assert!(!config.raw_fallback);
+        assert!(!config.count_terminated);
+        assert_eq!(config.epoch, None);
+        assert_eq!(config.quantizer, None);
+        assert_eq!(config.checkpoint_interval, None);
+        let block = encode(config);
+        assert!(old.can_read(&block));
+        assert!(ours.can_read(&block));
+
+        let newer = FormatCapabilities {
+            min_version: BLOCK_FORMAT_VERSION + 1,
+            max_version: BLOCK_FORMAT_VERSION + 2,
+            ..FormatCapabilities::current()
+        };
+        assert!(matches!(
+            ours.negotiate(&newer),
+            Err(NegotiationError::NoCommonVersion { .. })
+        ));
+        assert!(!newer.can_read(&block));
+        let chimp_only = FormatCapabilities {
+            value_codecs: vec![ValueScheme::Chimp],
+            ..FormatCapabilities::current()
+        };
+        assert_eq!(
+            old.negotiate(&chimp_only),
+            Err(NegotiationError::NoCommonValueCodec)
+        );
+    }
+
+    #[test]
+    fn test_capabilities_bytes_roundtrip() {
+        let caps = FormatCapabilities {
+            value_codecs: vec![ValueScheme::Chimp, ValueScheme::Gorilla],
+            ..FormatCapabilities::current()
+        };
+        let bytes = caps.to_bytes();
+        assert_eq!(FormatCapabilities::from_bytes(&bytes).unwrap(), caps);
+
+        // A newer peer listing a codec this build lacks and an extra field.
+        let mut newer = bytes.clone();
+        let value_count = 3 + 1 + caps.timestamp_codecs.len();
+        newer[value_count] += 1;
+        newer.insert(value_count + 1, 0x0F);
+        newer.extend_from_slice(&[0xAA, 0xBB]);
+        assert_eq!(FormatCapabilities::from_bytes(&newer).unwrap(), caps);
+
+        assert_eq!(
+            FormatCapabilities::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert!(FormatCapabilities::from_bytes(&[2, 1, 0, 0, 0]).is_err());
+    }
+}
@@ -0,0 +1,421 @@
+//! Chimp128 value compression, an alternative to Gorilla's XOR scheme.
+//!
+//! Follows "Chimp: Efficient Lossless Floating Point Compression for Time
+//! Series Databases" (Liakos et al., VLDB 2022). Instead of always XORing a
+//! value with its predecessor, the encoder looks up the latest of the
+//! previous 128 values that shares its low bits and uses it if the XOR ends
+//! in a long run of zeros. Volatile series that revisit earlier values
+//! compress noticeably better this way. Select it with
+//! [`ValueScheme::Chimp`](crate::ValueScheme::Chimp).
+//!
+//! Every value after the first, which is stored raw, starts with a two-bit
+//! flag:
+//!
+//! | flag | followed by |
+//! |------|-------------|
+//! | `00` | 7-bit slot of an identical earlier value |
+//! | `01` | 7-bit slot of the reference value, 3-bit leading zero class, 6-bit length and the meaningful bits of the XOR |
+//! | `10` | the XOR with the predecessor after its leading zeros, whose class is the previous one's |
+//! | `11` | 3-bit leading zero class and the XOR with the predecessor after its leading zeros |
+//!
+//! Leading zero counts are rounded down to one of eight classes so that
+//! they fit in three bits.
+
+use crate::bitbuffer::{BitReader, BitWrite, BufferFull};
+use crate::codec::ValueCodec;
+use crate::decoder::{DecodeError, ValueEncoding};
+use crate::snapshot::StateReader;
+
+/// Number of earlier values a value can be XORed with.
+const PREVIOUS_VALUES: usize = 128;
+/// Bits of a slot in the ring of previous values.
+const SLOT_BITS: u8 = 7;
+/// Trailing zeros an XOR with an earlier value must exceed to be used.
+const THRESHOLD: u32 = 6 + SLOT_BITS as u32;
+/// Low bits of a value its earlier occurrences are looked up by.
+const KEY_BITS: u32 = THRESHOLD + 1;
+/// Leading zero count of each class.
+const LEADING_CLASSES: [u8; 8] = [0, 8, 12, 16, 18, 20, 22, 24];
+/// Stored leading zeros when the previous value did not set a class.
+const NO_CLASS: u8 = 65;
+
+/// Returns the class of `leading` zeros and the count it stands for.
+fn leading_class(leading: u32) -> (u64, u8) {
+    let class = LEADING_CLASSES
+        .iter()
+        .rposition(|&zeros| zeros as u32 <= leading)
+        .expect("class 0 covers every count");
+    (class as u64, LEADING_CLASSES[class])
+}
+
+/// The 128 previous values and the leading zero class in effect, shared by
+/// both sides of the codec.
+#[derive(Debug, Clone)]
+struct Ring {
+    values: [u64; PREVIOUS_VALUES],
+    /// Number of values stored so far; the newest is at `len - 1`.
+    len: u64,
+    stored_leading: u8,
+}
+
+impl Ring {
+    fn new() -> Self {
+        Self {
+            values: [0; PREVIOUS_VALUES],
+            len: 0,
+            stored_leading: NO_CLASS,
+        }
+    }
+
+    fn newest(&self) -> u64 {
+        self.values[(self.len - 1) as usize % PREVIOUS_VALUES]
+    }
+
+    fn push(&mut self, bits: u64) {
+        self.values[self.len as usize % PREVIOUS_VALUES] = bits;
+        self.len += 1;
+    }
+}
+
+/// State [`ChimpCodec::truncate`] restores.
+#[derive(Debug, Clone, Copy)]
+struct Undo {
+    key: usize,
+    position: u32,
+    overwritten: u64,
+    stored_leading: u8,
+}
+
+/// State of a Chimp128 value stream, for either encoding or decoding it.
+#[derive(Debug, Clone)]
+pub struct ChimpCodec {
+    ring: Ring,
+    /// Position of the latest value for every pattern of low bits,
+    /// truncated to 32 bits to halve the table. Only encoding needs it, so
+    /// it is built on the first [`encode`](ValueCodec::encode); empty until
+    /// then.
+    positions: Vec<u32>,
+    /// State before the most recent value.
+    undo: Option<Undo>,
+}
+
+impl ChimpCodec {
+    /// Creates the state of an empty stream.
+    pub fn new() -> Self {
+        Self {
+            ring: Ring::new(),
+            positions: Vec::new(),
+            undo: None,
+        }
+    }
+
+    /// Reverts the most recent [`encode`](ValueCodec::encode) or
+    /// [`seed`](ValueCodec::seed) if `len` values were stored before it;
+    /// resets the state if `len` is zero.
+    pub(crate) fn truncate(&mut self, len: u64) {
+        if len == 0 {
+            *self = Self::new();
+            return;
+        }
+        if self.ring.len != len + 1 {
+            return;
+        }
+        let undo = self
+            .undo
+            .take()
+            .expect("every stored value records its undo");
+        self.ring.len = len;
+        self.ring.values[len as usize % PREVIOUS_VALUES] = undo.overwritten;
+        self.ring.stored_leading = undo.stored_leading;
+        // A restored codec rebuilds its positions on the next encode.
+        if !self.positions.is_empty() {
+            self.positions[undo.key] = undo.position;
+        }
+    }
+
+    /// Appends the state for `EncoderState::to_bytes`: the number of values
+    /// stored, the leading zero class in effect, the occupied slots of the
+    /// ring and the undo record. The table of positions is rebuilt from the
+    /// ring instead.
+    pub(crate) fn write_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.ring.len.to_le_bytes());
+        out.push(self.ring.stored_leading);
+        let occupied = self.ring.len.min(PREVIOUS_VALUES as u64) as usize;
+        for bits in &self.ring.values[..occupied] {
+            out.extend_from_slice(&bits.to_le_bytes());
+        }
+        match self.undo {
+            Some(undo) => {
+                out.push(1);
+                out.extend_from_slice(&(undo.key as u32).to_le_bytes());
+                out.extend_from_slice(&undo.position.to_le_bytes());
+                out.extend_from_slice(&undo.overwritten.to_le_bytes());
+                out.push(undo.stored_leading);
+            }
+            None => out.push(0),
+        }
+    }
+
+    /// Reads a state written by [`write_state`](ChimpCodec::write_state).
+    pub(crate) fn read_state(reader: &mut StateReader<'_>) -> Result<Self, DecodeError> {
+        let mut ring = Ring::new();
+        ring.len = reader.u64()?;
+        ring.stored_leading = reader.u8()?;
+        if ring.stored_leading > NO_CLASS {
+            return Err(DecodeError::MalformedHeader(
+                "Chimp leading zeros out of range",
+            ));
+        }
+        let occupied = ring.len.min(PREVIOUS_VALUES as u64) as usize;
+        for slot in &mut ring.values[..occupied] {
+            *slot = reader.u64()?;
+        }
+        let undo = match reader.u8()? {
+            0 => None,
+            _ => {
+                let key = reader.u32()? as usize;
+                let undo = Undo {
+                    key,
+                    position: reader.u32()?,
+                    overwritten: reader.u64()?,
+                    stored_leading: reader.u8()?,
+                };
+                if key >= 1 << KEY_BITS || undo.stored_leading > NO_CLASS {
+                    return Err(DecodeError::MalformedHeader("Chimp undo out of range"));
+                }
+                Some(undo)
+            }
+        };
+        Ok(Self {
+            ring,
+            positions: Vec::new(),
+            undo,
+        })
+    }
+
+    /// Returns the number of values stored so far, for
+    /// [`truncate`](ChimpCodec::truncate).
+    pub(crate) fn len(&self) -> u64 {
+        self.ring.len
+    }
+
+    /// Builds the table of positions from the values stored so far, e.g.
+    /// after decoding a stream that is then continued.
+    fn index(&mut self) {
+        self.positions = vec![0; 1 << KEY_BITS];
+        let len = self.ring.len;
+        for position in len.saturating_sub(PREVIOUS_VALUES as u64)..len {
+            let bits = self.ring.values[position as usize % PREVIOUS_VALUES];
+            self.positions[key(bits)] = position as u32;
+        }
+    }
+
+    fn store(&mut self, bits: u64) {
+        if !self.positions.is_empty() {
+            let key = key(bits);
+            self.undo = Some(Undo {
+                key,
+                position: self.positions[key],
+                overwritten: self.ring.values[self.ring.len as usize % PREVIOUS_VALUES],
+                stored_leading: self.ring.stored_leading,
+            });
+            self.positions[key] = self.ring.len as u32;
+        }
+        self.ring.push(bits);
+    }
+
+    /// Returns the most bits the next value can take: a full value first,
+    /// then a flag, a leading zero class and a XOR without leading zeros.
+    pub(crate) fn max_bits(&self) -> usize {
+        if self.ring.len == 0 {
+            64
+        } else {
+            2 + 3 + 64
+        }
+    }
+
+    /// Checks that `slot` holds a value.
+    fn slot(&self, slot: u64) -> Result<usize, DecodeError> {
+        if slot >= self.ring.len {
+            return Err(DecodeError::InvalidValueReference { slot: slot as u8 });
+        }
+        Ok(slot as usize)
+    }
+}
+
+impl Default for ChimpCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ValueCodec for ChimpCodec {
+    fn seed(&mut self, bits: u64) {
+        self.store(bits);
+    }
+
+    fn encode<W: BitWrite>(&mut self, buf: &mut W, bits: u64) -> Result<(), BufferFull> {
+        if self.positions.is_empty() {
+            self.index();
+        }
+        let ring = &self.ring;
+        if ring.len == 0 {
+            buf.write_bits(bits, 64)?;
+            self.store(bits);
+            return Ok(());
+        }
+        let newest = (ring.len - 1) as u32;
+        let candidate = self.positions[key(bits)];
+        let mut reference = newest;
+        if newest.wrapping_sub(candidate) < PREVIOUS_VALUES as u32 {
+            let xor = ring.values[candidate as usize % PREVIOUS_VALUES] ^ bits;
+            if xor.trailing_zeros() > THRESHOLD {
+                reference = candidate;
+            }
+        }
+        let slot = reference as u64 % PREVIOUS_VALUES as u64;
+        let xor = ring.values[slot as usize] ^ bits;
+        let stored_leading = if xor == 0 {
+            buf.write_bits(0b00, 2)?;
+            buf.write_bits(slot, SLOT_BITS)?;
+            NO_CLASS
+        } else {
+            let (class, leading) = leading_class(xor.leading_zeros());
+            let trailing = xor.trailing_zeros();
+            if trailing > THRESHOLD {
+                let meaningful = 64 - leading - trailing as u8;
+                buf.write_bits(0b01, 2)?;
+                buf.write_bits(slot, SLOT_BITS)?;
+                buf.write_bits(class, 3)?;
+                buf.write_bits(meaningful as u64, 6)?;
+                buf.write_bits(xor >> trailing, meaningful)?;
+                NO_CLASS
+            } else if leading == ring.stored_leading {
+                buf.write_bits(0b10, 2)?;
+                buf.write_bits(xor, 64 - leading)?;
+                leading
+            } else {
+                buf.write_bits(0b11, 2)?;
+                buf.write_bits(class, 3)?;
+                buf.write_bits(xor, 64 - leading)?;
+                leading
+            }
+        };
+        self.store(bits);
+        self.ring.stored_leading = stored_leading;
+        Ok(())
+    }
+
+    fn decode(&mut self, reader: &mut BitReader<'_>) -> Result<(u64, ValueEncoding), DecodeError> {
+        let mut read = |n: u8| reader.read_bits(n).ok_or(DecodeError::UnexpectedEnd);
+        if self.ring.len == 0 {
+            let bits = read(64)?;
+            self.store(bits);
+            return Ok((bits, ValueEncoding::Raw));
+        }
+        let (bits, encoding, stored_leading) = match read(2)? {
+            0b00 => {
+                let slot = self.slot(read(SLOT_BITS)?)?;
+                (self.ring.values[slot], ValueEncoding::Repeat, NO_CLASS)
+            }
+            0b01 => {
+                let slot = self.slot(read(SLOT_BITS)?)?;
+                let leading = LEADING_CLASSES[read(3)? as usize];
+                let meaningful = read(6)? as u8;
+                if meaningful == 0 || leading + meaningful > 64 {
+                    return Err(DecodeError::InvalidXorWindow {
+                        leading,
+                        meaningful,
+                    });
+                }
+                let xor = read(meaningful)? << (64 - leading - meaningful);
+                let bits = self.ring.values[slot] ^ xor;
+                (bits, ValueEncoding::NewWindow, NO_CLASS)
+            }
+            0b10 => {
+                let leading = self.ring.stored_leading;
+                if leading == NO_CLASS {
+                    return Err(DecodeError::InvalidXorWindow {
+                        leading,
+                        meaningful: 0,
+                    });
+                }
+                let xor = read(64 - leading)?;
+                let bits = self.ring.newest() ^ xor;
+                (bits, ValueEncoding::ReusedWindow, leading)
+            }
+            _ => {
+                let leading = LEADING_CLASSES[read(3)? as usize];
+                let xor = read(64 - leading)?;
+                (self.ring.newest() ^ xor, ValueEncoding::NewWindow, leading)
+            }
+        };
+        self.store(bits);
+        self.ring.stored_leading = stored_leading;
+        Ok((bits, encoding))
+    }
+}
+
+/// Returns the low bits `bits` is looked up by.
+fn key(bits: u64) -> usize {
+    (bits & ((1 << KEY_BITS) - 1)) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitbuffer::BitBuffer;
+
+    fn roundtrip(values: &[f64]) -> usize {
+        let mut encoder = ChimpCodec::new();
+        let mut buf = BitBuffer::new();
+        for value in values {
+            encoder.encode(&mut buf, value.to_bits()).unwrap();
+        }
+        let mut decoder = ChimpCodec::new();
+        let mut reader = BitReader::new(&buf);
+        for value in values {
+            let (bits, _) = decoder.decode(&mut reader).unwrap();
+            assert_eq!(bits, value.to_bits());
+        }
+        assert!(reader.is_exhausted());
+        buf.len_bits()
+    }
+
+    #[test]
+    fn test_roundtrip_every_case() {
+        let mut values = vec![1.0, 1.0, 2.0, 3.0, 2.5, -7.25, 1.0, f64::NAN, 0.0, -0.0];
+        values.extend((0..300).map(|i| (i % 17) as f64 * 0.1 + (i / 50) as f64));
+        values.extend((0..300).map(|i| ((i * 7919) % 1000) as f64 / 3.0));
+        roundtrip(&values);
+    }
+
+    #[test]
+    fn test_recurring_values_reference_earlier_slots() {
+        // Cycles through 20 unrelated values: Gorilla's predecessor XOR
+        // finds nothing to share, Chimp finds each value 20 slots back.
+        let cycle: Vec<f64> = (0..20).map(|i| (i as f64 * 1.37).sin() * 1e3).collect();
+        let values: Vec<f64> = cycle.iter().cycle().take(1000).copied().collect();
+        let bits = roundtrip(&values);
+        assert!(bits < 64 * 20 + 9 * 1000, "{bits}");
+    }
+
+    #[test]
+    fn test_truncate_reverts_last_value() {
+        let mut encoder = ChimpCodec::new();
+        let mut buf = BitBuffer::new();
+        for value in [1.0f64, 2.0, 3.0] {
+            encoder.encode(&mut buf, value.to_bits()).unwrap();
+        }
+        let (len, bits) = (encoder.len(), buf.len_bits());
+        let snapshot = encoder.clone();
+        encoder.encode(&mut buf, 4.0f64.to_bits()).unwrap();
+        encoder.truncate(len);
+        buf.truncate(bits);
+        assert_eq!(encoder.ring.values, snapshot.ring.values);
+        assert_eq!(encoder.positions, snapshot.positions);
+
+        encoder.truncate(0);
+        assert_eq!(encoder.len(), 0);
+    }
+}
@@ -0,0 +1,240 @@
+//! Encoding of unbounded streams into a sequence of blocks.
+
+use crate::bitbuffer::BufferFull;
+use crate::encoder::{CompressedBlock, DataPoint, EncodeError, Encoder, EncoderConfig};
+
+/// Rollover rules for a [`ChunkedEncoder`]. A block is completed as soon as
+/// any configured limit is reached; limits left as `None` are not checked.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkConfig {
+    /// Configuration used for every block's encoder.
+    pub encoder: EncoderConfig,
+    /// Complete a block once its compressed stream reaches this many bytes.
+    /// This is a target rather than a hard cap: the finished block may exceed
+    /// it by one point plus the end-of-stream marker.
+    pub max_bytes: Option<usize>,
+    /// Complete a block once it holds this many points.
+    pub max_points: Option<u64>,
+    /// Time span covered by one block, in timestamp units. A point at or past
+    /// `first_timestamp + window` starts a new block, e.g. `7200` for the
+    /// paper's two-hour blocks.
+    pub window: Option<u64>,
+    /// Align time windows to multiples of `window` since the epoch (e.g. to
+    /// the top of each hour for `3600`) instead of starting them at each
+    /// block's first point, so every block falls within one window.
+    pub align_window: bool,
+}
+
+/// An encoder for unbounded streams that finishes the current block and
+/// starts a new one whenever a [`ChunkConfig`] limit is reached, handing each
+/// completed block to a callback.
+///
+/// The block still open when the encoder is dropped is discarded; call
+/// [`finish`](ChunkedEncoder::finish) to emit it.
+///
+/// # Example
+/// ```
+/// use gorilla::{ChunkConfig, ChunkedEncoder, DataPoint};
+///
+/// let mut blocks = Vec::new();
+/// let mut encoder = ChunkedEncoder::new(
+///     ChunkConfig { max_points: Some(100), ..Default::default() },
+///     |block| blocks.push(block),
+/// );
+/// for i in 0..250 {
+///     encoder.encode(DataPoint::new(1609459200 + i * 10, 1.0)).unwrap();
+/// }
+/// encoder.finish().unwrap();
+/// assert_eq!(blocks.iter().map(|b| b.count()).collect::<Vec<_>>(), [100, 100, 50]);
+/// ```
+pub struct ChunkedEncoder<F: FnMut(CompressedBlock)> {
+    config: ChunkConfig,
+    current: Encoder,
+    on_block: F,
+    blocks_emitted: u64,
+}
+
+impl<F: FnMut(CompressedBlock)> ChunkedEncoder<F> {
+    /// Creates a chunked encoder that passes every completed block to
+    /// `on_block`.
+    pub fn new(config: ChunkConfig, on_block: F) -> Self {
+        Self {
+            current: Encoder::with_config(config.encoder.clone()),
+            config,
+            on_block,
+            blocks_emitted: 0,
+        }
+    }
+
+    /// Encodes a data point, first completing the current block if the point
+    /// falls outside its time window, and afterwards if the block reached its
+    /// size or point limit.
+    pub fn encode(&mut self, dp: DataPoint) -> Result<(), EncodeError> {
+        if let (Some(window), Some(first)) = (self.config.window, self.current.first_timestamp()) {
+            let start = if self.config.align_window {
+                first - first % window
+            } else {
+                first
+            };
+            if dp.timestamp >= start.saturating_add(window) {
+                self.flush()?;
+            }
+        }
+
+        self.current.encode(dp)?;
+
+        let full_bytes = self
+            .config
+            .max_bytes
+            .is_some_and(|max| self.current.len_bits() >= max * 8);
+        let full_points = self
+            .config
+            .max_points
+            .is_some_and(|max| self.current.count() >= max);
+        if full_bytes || full_points {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Completes the current block and passes it to the callback. Does
+    /// nothing if the block is empty.
+    pub fn flush(&mut self) -> Result<(), BufferFull> {
+        if self.current.count() == 0 {
+            return Ok(());
+        }
+        self.current.finish()?;
+        let block = std::mem::replace(
+            &mut self.current,
+            Encoder::with_config(self.config.encoder.clone()),
+        );
+        (self.on_block)(block.into_compressed());
+        self.blocks_emitted += 1;
+        Ok(())
+    }
+
+    /// Completes the last block, consuming the encoder.
+    pub fn finish(mut self) -> Result<(), BufferFull> {
+        self.flush()
+    }
+
+    /// Returns the encoder of the block currently being filled.
+    pub fn current(&self) -> &Encoder {
+        &self.current
+    }
+
+    /// Returns the number of blocks passed to the callback so far.
+    pub fn blocks_emitted(&self) -> u64 {
+        self.blocks_emitted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+
+    fn run(
+        config: ChunkConfig,
+        points: impl IntoIterator<Item = DataPoint>,
+    ) -> Vec<CompressedBlock> {
+        let mut blocks = Vec::new();
+        let mut enc = ChunkedEncoder::new(config, |b| blocks.push(b));
+        for dp in points {
+            enc.encode(dp).unwrap();
+        }
+        enc.finish().unwrap();
+        blocks
+    }
+
+    #[test]
+    fn test_rolls_over_on_time_window() {
+        let points = (0..5 * 60).map(|i| DataPoint::new(i * 60, i as f64));
+        let blocks = run(
+            ChunkConfig {
+                window: Some(2 * 3600),
+                ..Default::default()
+            },
+            points,
+        );
+        assert_eq!(
+            blocks.iter().map(|b| b.count()).collect::<Vec<_>>(),
+            [120, 120, 60]
+        );
+        let second = Decoder::decode(&blocks[1]).unwrap();
+        assert_eq!(second[0].timestamp, 7200);
+    }
+
+    #[test]
+    fn test_aligned_windows_start_on_boundaries() {
+        // Starts at 00:45; blocks end at the top of each hour.
+        let points = (0..12).map(|i| DataPoint::new(2700 + i * 900, 1.0));
+        let blocks = run(
+            ChunkConfig {
+                window: Some(3600),
+                align_window: true,
+                ..Default::default()
+            },
+            points,
+        );
+        let ranges: Vec<_> = blocks
+            .iter()
+            .map(|b| (b.start_timestamp().unwrap(), b.end_timestamp().unwrap()))
+            .collect();
+        assert_eq!(
+            ranges,
+            [(2700, 2700), (3600, 6300), (7200, 9900), (10800, 12600)]
+        );
+    }
+
+    #[test]
+    fn test_rolls_over_on_byte_size() {
+        let points = (0..1000).map(|i| DataPoint::new(i * 60, (i * 7919 % 1000) as f64));
+        let blocks = run(
+            ChunkConfig {
+                max_bytes: Some(256),
+                ..Default::default()
+            },
+            points,
+        );
+        assert!(blocks.len() > 1);
+        // Each block overshoots the target by at most one point plus marker.
+        assert!(blocks.iter().all(|b| b.bytes.len() <= 256 + 16 + 9));
+        let total: u64 = blocks.iter().map(|b| b.count).sum();
+        assert_eq!(total, 1000);
+    }
+
+    #[test]
+    fn test_blocks_concatenate_to_input() {
+        let points: Vec<_> = (0..500)
+            .map(|i| DataPoint::new(1_000_000 + i * 15, (i as f64).sin()))
+            .collect();
+        let blocks = run(
+            ChunkConfig {
+                max_points: Some(64),
+                window: Some(3000),
+                ..Default::default()
+            },
+            points.clone(),
+        );
+        let decoded: Vec<_> = blocks
+            .iter()
+            .flat_map(|b| Decoder::decode(b).unwrap())
+            .collect();
+        assert_eq!(decoded, points);
+    }
+
+    #[test]
+    fn test_flush_on_empty_block_emits_nothing() {
+        let mut count = 0;
+        let mut enc = ChunkedEncoder::new(ChunkConfig::default(), |_| count += 1);
+        enc.flush().unwrap();
+        enc.encode(DataPoint::new(0, 1.0)).unwrap();
+        assert_eq!(enc.blocks_emitted(), 0);
+        enc.flush().unwrap();
+        enc.flush().unwrap();
+        assert_eq!(enc.blocks_emitted(), 1);
+        enc.finish().unwrap();
+        assert_eq!(count, 1);
+    }
+}
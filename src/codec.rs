@@ -0,0 +1,730 @@
+//! Timestamp and value codecs that a block's stream is built from.
+//!
+//! The first point of a stream is its timestamp, raw in 64 bits or as a
+//! varint offset from the block's
+//! [`epoch`](crate::EncoderConfig::epoch), followed by a value from the
+//! block's [`ValueCodec`]. Every further point is a delta written
+//! by its [`TimestampCodec`] and then a value. Both schemes are chosen per
+//! block through [`EncoderConfig::timestamp_codec`] and
+//! [`EncoderConfig::value_codec`], and are recorded in the block, so blocks
+//! mixing any of them decode without outside knowledge.
+//!
+//! | timestamps                        | values                         |
+//! |-----------------------------------|--------------------------------|
+//! | [`TimestampScheme::DeltaOfDelta`] | [`ValueScheme::Gorilla`] ([`XorCodec`]) |
+//! | [`TimestampScheme::Delta`]        | [`ValueScheme::Chimp`] ([`ChimpCodec`]) |
+//! | [`TimestampScheme::Raw`]          | [`ValueScheme::Raw`]           |
+//! | [`TimestampScheme::RunLength`]    |                                |
+//!
+//! The codecs can also be driven directly over a
+//! [`BitBuffer`](crate::bitbuffer::BitBuffer), e.g. to compare schemes on a
+//! sample of a series.
+//!
+//! [`EncoderConfig::timestamp_codec`]: crate::EncoderConfig::timestamp_codec
+//! [`EncoderConfig::value_codec`]: crate::EncoderConfig::value_codec
+
+use crate::bitbuffer::{BitReader, BitWrite, BufferFull};
+pub use crate::chimp::ChimpCodec;
+use crate::decoder::{DecodeError, Decoder, DodResult, ValueEncoding};
+use crate::encoder::{delta_of_delta_bits, write_delta_of_delta, write_xor};
+use crate::snapshot::StateReader;
+
+/// Writes and reads the distances between consecutive timestamps.
+///
+/// Each distance is stored relative to a reference delta that the codec
+/// moves along as it goes, kept in a [`DeltaState`]; the reference before
+/// the second point is the interval of the block's
+/// [`Priors`](crate::Priors), or zero. The compressing schemes share the
+/// variable-length buckets of the Gorilla paper and with them the
+/// end-of-stream marker, which no stored value can be mistaken for;
+/// [`TimestampScheme::Raw`] streams simply end.
+pub trait TimestampCodec {
+    /// Writes `delta`, the distance from the previous timestamp, and
+    /// updates `state` for the next one.
+    fn write_delta<W: BitWrite>(
+        &self,
+        buf: &mut W,
+        delta: i64,
+        state: &mut DeltaState,
+    ) -> Result<(), BufferFull>;
+
+    /// Reads the next distance written by
+    /// [`write_delta`](TimestampCodec::write_delta) and updates `state`,
+    /// or returns `None` at the end of the stream.
+    fn read_delta(
+        &self,
+        reader: &mut BitReader<'_>,
+        state: &mut DeltaState,
+    ) -> Result<Option<i64>, DecodeError>;
+}
+
+/// Zero delta-of-deltas written in a row, one bit each, after which
+/// [`TimestampScheme::RunLength`] opens a run.
+const RUN_AFTER: u16 = 16;
+
+/// Width of the length field of a run.
+const RUN_BITS: u8 = 16;
+
+/// Position of a [`TimestampCodec`] in its stream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeltaState {
+    /// Delta the next distance is stored against.
+    pub reference: i64,
+    /// Zero delta-of-deltas stored in a row since the last run.
+    zeros: u16,
+    /// Points of the open run so far.
+    run: u16,
+    /// Bit offset of the open run's length field.
+    run_at: Option<usize>,
+    /// Points of the open run not yet read.
+    left: u16,
+}
+
+impl DeltaState {
+    /// Creates the state before the second point of a stream, whose delta
+    /// is stored against `reference`.
+    pub fn new(reference: i64) -> Self {
+        Self {
+            reference,
+            ..Self::default()
+        }
+    }
+
+    /// Writes the length of the open run back into `buf` after the stream
+    /// was truncated to this state, undoing points added to the run since.
+    pub(crate) fn rewind<W: BitWrite>(&self, buf: &mut W) {
+        if let Some(at) = self.run_at {
+            buf.set_bits(at, self.run as u64, RUN_BITS);
+        }
+    }
+
+    /// Returns the bit offset of the open run's length field, which later
+    /// points of the run overwrite.
+    pub(crate) fn run_at(&self) -> Option<usize> {
+        self.run_at
+    }
+
+    /// Appends the state for `EncoderState::to_bytes`: the reference
+    /// delta and the run bookkeeping, 22 bytes in all.
+    pub(crate) fn write_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.reference.to_le_bytes());
+        out.extend_from_slice(&self.zeros.to_le_bytes());
+        out.extend_from_slice(&self.run.to_le_bytes());
+        let run_at = self.run_at.map_or(u64::MAX, |at| at as u64);
+        out.extend_from_slice(&run_at.to_le_bytes());
+        out.extend_from_slice(&self.left.to_le_bytes());
+    }
+
+    /// Reads a state written by [`write_state`](DeltaState::write_state)
+    /// for a stream of `len_bits` bits.
+    pub(crate) fn read_state(
+        reader: &mut StateReader<'_>,
+        len_bits: usize,
+    ) -> Result<Self, DecodeError> {
+        let reference = reader.u64()? as i64;
+        let zeros = reader.u16()?;
+        let run = reader.u16()?;
+        let run_at = match reader.u64()? {
+            u64::MAX => None,
+            at if at.saturating_add(RUN_BITS as u64) <= len_bits as u64 => Some(at as usize),
+            _ => return Err(DecodeError::MalformedHeader("run beyond stream")),
+        };
+        Ok(Self {
+            reference,
+            zeros,
+            run,
+            run_at,
+            left: reader.u16()?,
+        })
+    }
+}
+
+/// Writes and reads the values of a stream, keeping whatever state of the
+/// previous values the scheme needs. One instance handles one stream.
+pub trait ValueCodec {
+    /// Makes `bits` the value the next one is encoded against, as with
+    /// [`Priors`](crate::Priors). Without a seed, the first value is stored
+    /// in full.
+    fn seed(&mut self, bits: u64);
+
+    /// Writes the value with raw bits `bits`.
+    fn encode<W: BitWrite>(&mut self, buf: &mut W, bits: u64) -> Result<(), BufferFull>;
+
+    /// Reads the next value, returning its raw bits and how it was stored.
+    fn decode(&mut self, reader: &mut BitReader<'_>) -> Result<(u64, ValueEncoding), DecodeError>;
+}
+
+/// Timestamp compression scheme of a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimestampScheme {
+    /// Difference between consecutive deltas, as in the Gorilla paper: one
+    /// bit per point at a fixed interval. The reference is the previous
+    /// delta.
+    #[default]
+    DeltaOfDelta,
+    /// The delta minus a fixed reference, the interval of the block's
+    /// [`Priors`](crate::Priors) or zero: plain deltas without priors. With
+    /// priors on a regular grid, a late point or a gap is paid for once,
+    /// where delta-of-delta pays again when the grid resumes.
+    Delta,
+    /// Every delta stored in full, 64 bits, with no end-of-stream marker:
+    /// the stream ends with its last bit. Chosen by
+    /// [`Encoder::finish`](crate::Encoder::finish) for blocks that do not
+    /// compress.
+    Raw,
+    /// [`DeltaOfDelta`](TimestampScheme::DeltaOfDelta), except that after
+    /// 16 zero delta-of-deltas in a row the next zero is followed by a
+    /// 16-bit count of the points after it that repeat the delta, which
+    /// take no timestamp bits at all. The count is updated in place as the
+    /// run grows, so perfectly regular timestamps cost a few bytes per
+    /// 65536 points instead of a bit per point. Not indexed:
+    /// [`EncoderConfig::checkpoint_interval`](crate::EncoderConfig::checkpoint_interval)
+    /// has no effect.
+    RunLength,
+}
+
+impl TimestampScheme {
+    /// Returns the identifier stored in serialized blocks.
+    pub fn id(self) -> u8 {
+        match self {
+            TimestampScheme::DeltaOfDelta => 0,
+            TimestampScheme::Delta => 1,
+            TimestampScheme::Raw => 2,
+            TimestampScheme::RunLength => 3,
+        }
+    }
+
+    /// Returns the scheme with identifier `id`, if there is one.
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(TimestampScheme::DeltaOfDelta),
+            1 => Some(TimestampScheme::Delta),
+            2 => Some(TimestampScheme::Raw),
+            3 => Some(TimestampScheme::RunLength),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of bits [`write_delta`](TimestampCodec::write_delta)
+    /// appends for `delta` in `state`: none for a point that joins an open
+    /// run, whose length is updated in place.
+    pub(crate) fn delta_bits(self, delta: i64, state: &DeltaState) -> usize {
+        if self == TimestampScheme::Raw {
+            return 64;
+        }
+        let dod = delta.wrapping_sub(state.reference);
+        if self == TimestampScheme::RunLength && dod == 0 {
+            if state.run_at.is_some() && state.run < u16::MAX {
+                return 0;
+            }
+            if state.zeros == RUN_AFTER {
+                return 1 + RUN_BITS as usize;
+            }
+        }
+        delta_of_delta_bits(dod)
+    }
+}
+
+impl TimestampCodec for TimestampScheme {
+    fn write_delta<W: BitWrite>(
+        &self,
+        buf: &mut W,
+        delta: i64,
+        state: &mut DeltaState,
+    ) -> Result<(), BufferFull> {
+        if *self == TimestampScheme::Raw {
+            return buf.write_bits(delta as u64, 64);
+        }
+        let dod = delta - state.reference;
+        if *self == TimestampScheme::RunLength {
+            if let Some(at) = state.run_at.filter(|_| dod == 0 && state.run < u16::MAX) {
+                state.run += 1;
+                buf.set_bits(at, state.run as u64, RUN_BITS);
+                return Ok(());
+            }
+            (state.run, state.run_at) = (0, None);
+        }
+        write_delta_of_delta(buf, dod)?;
+        match self {
+            TimestampScheme::RunLength if dod != 0 => state.zeros = 0,
+            TimestampScheme::RunLength if state.zeros == RUN_AFTER => {
+                let at = buf.reserve_slot(RUN_BITS)?.position();
+                (state.zeros, state.run_at) = (0, Some(at));
+            }
+            TimestampScheme::RunLength => state.zeros += 1,
+            _ => {}
+        }
+        if *self != TimestampScheme::Delta {
+            state.reference = delta;
+        }
+        Ok(())
+    }
+
+    fn read_delta(
+        &self,
+        reader: &mut BitReader<'_>,
+        state: &mut DeltaState,
+    ) -> Result<Option<i64>, DecodeError> {
+        if *self == TimestampScheme::Raw {
+            if reader.is_exhausted() {
+                return Ok(None);
+            }
+            let delta = reader.read_bits(64).ok_or(DecodeError::UnexpectedEnd)?;
+            return Ok(Some(delta as i64));
+        }
+        if state.left > 0 {
+            state.left -= 1;
+            return Ok(Some(state.reference));
+        }
+        let DodResult::Value(stored) = Decoder::decode_delta_of_delta(reader)? else {
+            return Ok(None);
+        };
+        (state.run, state.run_at) = (0, None);
+        let delta = state
+            .reference
+            .checked_add(stored)
+            .ok_or(DecodeError::TimestampOverflow)?;
+        match self {
+            TimestampScheme::RunLength if stored != 0 => state.zeros = 0,
+            TimestampScheme::RunLength if state.zeros == RUN_AFTER => {
+                let at = reader.position();
+                let run = reader
+                    .read_bits(RUN_BITS)
+                    .ok_or(DecodeError::UnexpectedEnd)? as u16;
+                (state.zeros, state.run, state.run_at, state.left) = (0, run, Some(at), run);
+            }
+            TimestampScheme::RunLength => state.zeros += 1,
+            _ => {}
+        }
+        if *self != TimestampScheme::Delta {
+            state.reference = delta;
+        }
+        Ok(Some(delta))
+    }
+}
+
+/// Value compression scheme of a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ValueScheme {
+    /// XOR with the previous value, as in the Gorilla paper, see
+    /// [`XorCodec`].
+    #[default]
+    Gorilla,
+    /// XOR with the best match among the previous 128 values, see
+    /// [`chimp`](crate::chimp). Smaller for volatile values, slower to
+    /// encode, and not indexed:
+    /// [`EncoderConfig::checkpoint_interval`](crate::EncoderConfig::checkpoint_interval)
+    /// has no effect.
+    Chimp,
+    /// Every value stored in full, 64 bits. Chosen by
+    /// [`Encoder::finish`](crate::Encoder::finish) for blocks that do not
+    /// compress; not indexed.
+    Raw,
+}
+
+impl ValueScheme {
+    /// Returns the identifier stored in serialized blocks.
+    pub fn id(self) -> u8 {
+        match self {
+            ValueScheme::Gorilla => 0,
+            ValueScheme::Chimp => 1,
+            ValueScheme::Raw => 2,
+        }
+    }
+
+    /// Returns the scheme with identifier `id`, if there is one.
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(ValueScheme::Gorilla),
+            1 => Some(ValueScheme::Chimp),
+            2 => Some(ValueScheme::Raw),
+            _ => None,
+        }
+    }
+}
+
+/// Gorilla's value compression: each value is XORed with its predecessor
+/// and only the bits between the XOR's leading and trailing zeros are
+/// stored, reusing the previous window of zeros where it fits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XorCodec {
+    prev: u64,
+    leading_zeros: u8,
+    trailing_zeros: u8,
+    started: bool,
+}
+
+impl XorCodec {
+    /// Creates the state of an empty stream.
+    pub fn new() -> Self {
+        Self {
+            prev: 0,
+            // No window yet.
+            leading_zeros: 64,
+            trailing_zeros: 64,
+            started: false,
+        }
+    }
+
+    /// Continues a stream whose last value was `prev`, stored with the given
+    /// window, e.g. from a [`Checkpoint`](crate::Checkpoint).
+    pub(crate) fn resume(prev: u64, leading_zeros: u8, trailing_zeros: u8) -> Self {
+        Self {
+            prev,
+            leading_zeros,
+            trailing_zeros,
+            started: true,
+        }
+    }
+
+    /// Returns the leading and trailing zeros of the window in effect.
+    pub(crate) fn window(&self) -> (u8, u8) {
+        (self.leading_zeros, self.trailing_zeros)
+    }
+
+    /// Returns the number of bits [`encode`](ValueCodec::encode) takes for
+    /// `bits`.
+    pub(crate) fn value_bits(&self, bits: u64) -> usize {
+        let xor = bits ^ self.prev;
+        let (leading, trailing) = (xor.leading_zeros() as u8, xor.trailing_zeros() as u8);
+        if !self.started {
+            64
+        } else if xor == 0 {
+            1
+        } else if leading >= self.leading_zeros && trailing >= self.trailing_zeros {
+            2 + (64 - self.leading_zeros - self.trailing_zeros) as usize
+        } else {
+            2 + 12 + (64 - leading - trailing) as usize
+        }
+    }
+
+    /// Writes `bits` given its XOR with the previous value and the XOR's
+    /// leading and trailing zero counts, computed by the caller.
+    pub(crate) fn encode_xor<W: BitWrite>(
+        &mut self,
+        buf: &mut W,
+        bits: u64,
+        xor: u64,
+        leading: u8,
+        trailing: u8,
+    ) -> Result<(), BufferFull> {
+        let mut window = self.window();
+        write_xor(buf, xor, leading, trailing, &mut window)?;
+        (self.leading_zeros, self.trailing_zeros) = window;
+        self.prev = bits;
+        Ok(())
+    }
+}
+
+impl XorCodec {
+    /// Appends the state for `EncoderState::to_bytes`: the previous value,
+    /// the window and whether the stream has started, 11 bytes in all.
+    pub(crate) fn write_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.prev.to_le_bytes());
+        out.extend_from_slice(&[self.leading_zeros, self.trailing_zeros, self.started as u8]);
+    }
+
+    /// Reads a state written by [`write_state`](XorCodec::write_state).
+    pub(crate) fn read_state(reader: &mut StateReader<'_>) -> Result<Self, DecodeError> {
+        let prev = reader.u64()?;
+        let (leading_zeros, trailing_zeros) = (reader.u8()?, reader.u8()?);
+        if leading_zeros > 64 || trailing_zeros > 64 {
+            return Err(DecodeError::MalformedHeader("XOR window out of range"));
+        }
+        Ok(Self {
+            prev,
+            leading_zeros,
+            trailing_zeros,
+            started: reader.u8()? != 0,
+        })
+    }
+}
+
+impl Default for XorCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ValueCodec for XorCodec {
+    fn seed(&mut self, bits: u64) {
+        self.prev = bits;
+        self.started = true;
+    }
+
+    fn encode<W: BitWrite>(&mut self, buf: &mut W, bits: u64) -> Result<(), BufferFull> {
+        if !self.started {
+            buf.write_bits(bits, 64)?;
+            self.seed(bits);
+            return Ok(());
+        }
+        let xor = bits ^ self.prev;
+        self.encode_xor(
+            buf,
+            bits,
+            xor,
+            xor.leading_zeros() as u8,
+            xor.trailing_zeros() as u8,
+        )
+    }
+
+    fn decode(&mut self, reader: &mut BitReader<'_>) -> Result<(u64, ValueEncoding), DecodeError> {
+        if !self.started {
+            let bits = reader.read_bits(64).ok_or(DecodeError::UnexpectedEnd)?;
+            self.seed(bits);
+            return Ok((bits, ValueEncoding::Raw));
+        }
+        let encoding;
+        (self.prev, self.leading_zeros, self.trailing_zeros, encoding) =
+            Decoder::read_xor(reader, self.prev, self.leading_zeros, self.trailing_zeros)?;
+        Ok((self.prev, encoding))
+    }
+}
+
+/// Value state of a stream under any built-in scheme.
+#[derive(Debug, Clone)]
+pub(crate) enum Values {
+    Gorilla(XorCodec),
+    Chimp(Box<ChimpCodec>),
+    Raw,
+}
+
+/// Point in a value stream that [`Values::rewind`] returns to.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ValuesMark {
+    Gorilla(XorCodec),
+    Chimp(u64),
+    Raw,
+}
+
+impl Values {
+    pub(crate) fn new(scheme: ValueScheme) -> Self {
+        match scheme {
+            ValueScheme::Gorilla => Values::Gorilla(XorCodec::new()),
+            ValueScheme::Chimp => Values::Chimp(Box::default()),
+            ValueScheme::Raw => Values::Raw,
+        }
+    }
+
+    /// Returns the XOR window in effect; 64 and 64 under Chimp and Raw,
+    /// which have no such window.
+    pub(crate) fn window(&self) -> (u8, u8) {
+        match self {
+            Values::Gorilla(xor) => xor.window(),
+            Values::Chimp(_) | Values::Raw => (64, 64),
+        }
+    }
+
+    /// Returns an upper bound on the bits [`encode`](ValueCodec::encode)
+    /// takes for `bits`: exact under Gorilla and Raw, the longest case
+    /// under Chimp.
+    pub(crate) fn max_bits(&self, bits: u64) -> usize {
+        match self {
+            Values::Gorilla(xor) => xor.value_bits(bits),
+            Values::Chimp(chimp) => chimp.max_bits(),
+            Values::Raw => 64,
+        }
+    }
+
+    pub(crate) fn mark(&self) -> ValuesMark {
+        match self {
+            Values::Gorilla(xor) => ValuesMark::Gorilla(*xor),
+            Values::Chimp(chimp) => ValuesMark::Chimp(chimp.len()),
+            Values::Raw => ValuesMark::Raw,
+        }
+    }
+
+    /// Appends the state for `EncoderState::to_bytes`; the scheme itself
+    /// is stored separately.
+    pub(crate) fn write_state(&self, out: &mut Vec<u8>) {
+        match self {
+            Values::Gorilla(xor) => xor.write_state(out),
+            Values::Chimp(chimp) => chimp.write_state(out),
+            Values::Raw => {}
+        }
+    }
+
+    /// Reads a state written by [`write_state`](Values::write_state) for a
+    /// stream under `scheme`.
+    pub(crate) fn read_state(
+        reader: &mut StateReader<'_>,
+        scheme: ValueScheme,
+    ) -> Result<Self, DecodeError> {
+        Ok(match scheme {
+            ValueScheme::Gorilla => Values::Gorilla(XorCodec::read_state(reader)?),
+            ValueScheme::Chimp => Values::Chimp(Box::new(ChimpCodec::read_state(reader)?)),
+            ValueScheme::Raw => Values::Raw,
+        })
+    }
+
+    /// Reverts the most recent value if `mark` was taken just before it,
+    /// or resets the stream if `mark` was taken before the first.
+    pub(crate) fn rewind(&mut self, mark: ValuesMark) {
+        match (self, mark) {
+            (Values::Gorilla(xor), ValuesMark::Gorilla(saved)) => *xor = saved,
+            (Values::Chimp(chimp), ValuesMark::Chimp(len)) => chimp.truncate(len),
+            (Values::Raw, ValuesMark::Raw) => {}
+            _ => unreachable!("a mark is only rewound to on its own stream"),
+        }
+    }
+}
+
+impl ValuesMark {
+    /// Appends the mark for `EncoderState::to_bytes`.
+    pub(crate) fn write_state(&self, out: &mut Vec<u8>) {
+        match self {
+            ValuesMark::Gorilla(xor) => xor.write_state(out),
+            ValuesMark::Chimp(len) => out.extend_from_slice(&len.to_le_bytes()),
+            ValuesMark::Raw => {}
+        }
+    }
+
+    /// Reads a mark written by [`write_state`](ValuesMark::write_state) on
+    /// a stream under `scheme`.
+    pub(crate) fn read_state(
+        reader: &mut StateReader<'_>,
+        scheme: ValueScheme,
+    ) -> Result<Self, DecodeError> {
+        Ok(match scheme {
+            ValueScheme::Gorilla => ValuesMark::Gorilla(XorCodec::read_state(reader)?),
+            ValueScheme::Chimp => ValuesMark::Chimp(reader.u64()?),
+            ValueScheme::Raw => ValuesMark::Raw,
+        })
+    }
+}
+
+impl ValueCodec for Values {
+    fn seed(&mut self, bits: u64) {
+        match self {
+            Values::Gorilla(xor) => xor.seed(bits),
+            Values::Chimp(chimp) => chimp.seed(bits),
+            Values::Raw => {}
+        }
+    }
+
+    fn encode<W: BitWrite>(&mut self, buf: &mut W, bits: u64) -> Result<(), BufferFull> {
+        match self {
+            Values::Gorilla(xor) => xor.encode(buf, bits),
+            Values::Chimp(chimp) => chimp.encode(buf, bits),
+            Values::Raw => buf.write_bits(bits, 64),
+        }
+    }
+
+    fn decode(&mut self, reader: &mut BitReader<'_>) -> Result<(u64, ValueEncoding), DecodeError> {
+        match self {
+            Values::Gorilla(xor) => xor.decode(reader),
+            Values::Chimp(chimp) => chimp.decode(reader),
+            Values::Raw => {
+                let bits = reader.read_bits(64).ok_or(DecodeError::UnexpectedEnd)?;
+                Ok((bits, ValueEncoding::Raw))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitbuffer::BitBuffer;
+
+    #[test]
+    fn test_timestamp_schemes_roundtrip() {
+        // A grid of 60 with a late point and a gap.
+        let deltas = [60, 60, 65, 55, 60, 120, 60, -5, i64::from(u32::MAX), 60];
+        let mut sizes = Vec::new();
+        let schemes = [
+            TimestampScheme::DeltaOfDelta,
+            TimestampScheme::Delta,
+            TimestampScheme::Raw,
+            TimestampScheme::RunLength,
+        ];
+        for scheme in schemes {
+            let mut buf = BitBuffer::new();
+            let mut state = DeltaState::new(60);
+            for &delta in &deltas {
+                scheme.write_delta(&mut buf, delta, &mut state).unwrap();
+            }
+            sizes.push(buf.len_bits());
+            if scheme != TimestampScheme::Raw {
+                buf.write_bits(0b1111, 4).unwrap();
+                buf.write_bits(u64::MAX, 64).unwrap();
+            }
+            let mut reader = BitReader::new(&buf);
+            let mut state = DeltaState::new(60);
+            for &delta in &deltas {
+                let read = scheme.read_delta(&mut reader, &mut state).unwrap();
+                assert_eq!(read, Some(delta), "{scheme:?}");
+            }
+            assert_eq!(scheme.read_delta(&mut reader, &mut state), Ok(None));
+            assert_eq!(TimestampScheme::from_id(scheme.id()), Some(scheme));
+        }
+        assert!(sizes[1] < sizes[0], "{sizes:?}");
+        assert_eq!(sizes[2], 64 * deltas.len());
+        assert_eq!(sizes[3], sizes[0]);
+        assert_eq!(TimestampScheme::from_id(4), None);
+    }
+
+    #[test]
+    fn test_run_length_stores_runs_in_place() {
+        // Two runs, the first cut short by a late point and the second by
+        // the end of the field's range, then a rewind into the second.
+        let deltas: Vec<i64> = (0..100)
+            .map(|_| 15)
+            .chain([16, 14])
+            .chain((0..70_000).map(|_| 15))
+            .collect();
+        let scheme = TimestampScheme::RunLength;
+        let mut buf = BitBuffer::new();
+        let mut state = DeltaState::new(0);
+        let mut saved = None;
+        for (i, &delta) in deltas.iter().enumerate() {
+            if i == 50_000 {
+                saved = Some((state, buf.len_bits()));
+            }
+            scheme.write_delta(&mut buf, delta, &mut state).unwrap();
+        }
+        // Each run: a change of delta, 16 zeros, a zero with its count.
+        let run = 9 + 16 + 1 + RUN_BITS as usize;
+        let saturated = 16 + 1 + RUN_BITS as usize;
+        assert_eq!(buf.len_bits(), run + 9 + 9 + run + saturated);
+
+        let (rewound, len) = saved.unwrap();
+        rewound.rewind(&mut buf);
+        buf.truncate(len);
+        buf.write_bits(0b1111, 4).unwrap();
+        buf.write_bits(u64::MAX, 64).unwrap();
+        let mut reader = BitReader::new(&buf);
+        let mut state = DeltaState::new(0);
+        for &delta in &deltas[..50_000] {
+            assert_eq!(scheme.read_delta(&mut reader, &mut state), Ok(Some(delta)));
+        }
+        // Reading ends in the state writing did, so the stream can resume.
+        assert_eq!(state, rewound);
+        assert_eq!(scheme.read_delta(&mut reader, &mut state), Ok(None));
+    }
+
+    #[test]
+    fn test_value_codecs_roundtrip_and_rewind() {
+        let values = [1.5f64, 1.5, 2.25, -7.0, f64::NAN, 0.0, 1e300, 2.25];
+        for scheme in [ValueScheme::Gorilla, ValueScheme::Chimp, ValueScheme::Raw] {
+            let mut encoder = Values::new(scheme);
+            let mut buf = BitBuffer::new();
+            encoder.seed(1.0f64.to_bits());
+            for value in values {
+                let (mark, len) = (encoder.mark(), buf.len_bits());
+                encoder.encode(&mut buf, 99.0f64.to_bits()).unwrap();
+                encoder.rewind(mark);
+                buf.truncate(len);
+                encoder.encode(&mut buf, value.to_bits()).unwrap();
+            }
+            let mut decoder = Values::new(scheme);
+            decoder.seed(1.0f64.to_bits());
+            let mut reader = BitReader::new(&buf);
+            for value in values {
+                let (bits, _) = decoder.decode(&mut reader).unwrap();
+                assert_eq!(bits, value.to_bits(), "{scheme:?}");
+            }
+            assert!(reader.is_exhausted());
+        }
+    }
+}
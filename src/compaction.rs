@@ -0,0 +1,247 @@
+//! Pluggable rules for merging sealed blocks, applied by
+//! [`TimeSeries::compact`](crate::TimeSeries::compact).
+
+use std::ops::Range;
+
+use crate::codec::{TimestampScheme, ValueScheme};
+use crate::encoder::{CompressedBlock, EncoderConfig};
+
+/// Summary of a sealed block passed to a [`CompactionPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockInfo {
+    /// Smallest timestamp in the block.
+    pub start: u64,
+    /// Largest timestamp in the block.
+    pub end: u64,
+    /// Number of points in the block.
+    pub count: u64,
+    /// Size of the compressed stream in bytes.
+    pub bytes: usize,
+    /// Whether the block carries a checksum.
+    pub checksum: bool,
+    /// Whether the block carries a checkpoint index.
+    pub indexed: bool,
+    /// Compression scheme of the block's timestamps.
+    pub timestamp_codec: TimestampScheme,
+    /// Compression scheme of the block's values.
+    pub value_codec: ValueScheme,
+}
+
+impl BlockInfo {
+    /// Summarizes `block`, which covers `start..=end`.
+    pub fn new(block: &CompressedBlock, start: u64, end: u64) -> Self {
+        Self {
+            start,
+            end,
+            count: block.count,
+            bytes: block.bytes.len(),
+            checksum: block.checksum.is_some(),
+            indexed: !block.index.is_empty(),
+            timestamp_codec: block.timestamp_codec,
+            value_codec: block.value_codec,
+        }
+    }
+}
+
+/// Decides which sealed blocks a compaction rewrites and how.
+///
+/// # Example
+/// ```
+/// use std::ops::Range;
+/// use gorilla::{BlockInfo, CompactionPolicy, DataPoint, TimeSeries};
+///
+/// /// Merges everything into blocks of about 4 KiB.
+/// struct MergeAll;
+///
+/// impl CompactionPolicy for MergeAll {
+///     fn select(&self, blocks: &[BlockInfo]) -> Vec<Range<usize>> {
+///         if blocks.len() > 1 { vec![0..blocks.len()] } else { Vec::new() }
+///     }
+///
+///     fn target_bytes(&self) -> Option<usize> {
+///         Some(4096)
+///     }
+/// }
+///
+/// let mut series = TimeSeries::default();
+/// for t in 0..30 {
+///     series.append(DataPoint::new(t * 60, 1.0)).unwrap();
+///     if t % 10 == 9 {
+///         series.seal().unwrap();
+///     }
+/// }
+/// assert_eq!(series.compact(&MergeAll).unwrap(), 1);
+/// assert_eq!(series.blocks().len(), 1);
+/// ```
+pub trait CompactionPolicy {
+    /// Returns the runs of adjacent blocks to rewrite, as ascending,
+    /// non-overlapping index ranges into `blocks` (oldest first). Each run
+    /// is re-encoded into blocks of about
+    /// [`target_bytes`](CompactionPolicy::target_bytes); a run of a single
+    /// block re-encodes it alone, e.g. to upgrade its codec.
+    fn select(&self, blocks: &[BlockInfo]) -> Vec<Range<usize>>;
+
+    /// Size the rewritten blocks roll over at, as a target rather than a
+    /// hard cap. `None` writes each run into a single block.
+    fn target_bytes(&self) -> Option<usize> {
+        None
+    }
+
+    /// Returns the encoder configuration for rewritten blocks, given the
+    /// series' own. Override to upgrade blocks, e.g. by enabling checksums
+    /// or checkpoint indexes.
+    fn encoder_config(&self, current: &EncoderConfig) -> EncoderConfig {
+        current.clone()
+    }
+}
+
+/// Merges runs of adjacent blocks of similar size, as in size-tiered
+/// compaction: small blocks are merged with each other first, and the
+/// merged blocks only later with blocks of their own size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SizeTiered {
+    /// Minimum number of similar blocks worth merging.
+    pub min_blocks: usize,
+    /// Blocks are similar if their size is within this factor of the run's
+    /// average size.
+    pub bucket_ratio: f64,
+    /// Blocks at or above this size are left alone, and merged blocks roll
+    /// over at it.
+    pub target_bytes: usize,
+}
+
+impl Default for SizeTiered {
+    fn default() -> Self {
+        Self {
+            min_blocks: 4,
+            bucket_ratio: 2.0,
+            target_bytes: 64 * 1024,
+        }
+    }
+}
+
+impl CompactionPolicy for SizeTiered {
+    fn select(&self, blocks: &[BlockInfo]) -> Vec<Range<usize>> {
+        let mut runs = Vec::new();
+        let mut start = 0;
+        let mut total = 0;
+        for (i, block) in blocks.iter().enumerate() {
+            let average = total as f64 / (i - start).max(1) as f64;
+            let similar = i == start
+                || (block.bytes as f64 <= average * self.bucket_ratio
+                    && block.bytes as f64 * self.bucket_ratio >= average);
+            if !similar || block.bytes >= self.target_bytes {
+                if i - start >= self.min_blocks.max(2) {
+                    runs.push(start..i);
+                }
+                start = i;
+                total = 0;
+            }
+            if block.bytes >= self.target_bytes {
+                start = i + 1;
+                continue;
+            }
+            total += block.bytes;
+        }
+        if blocks.len().saturating_sub(start) >= self.min_blocks.max(2) {
+            runs.push(start..blocks.len());
+        }
+        runs
+    }
+
+    fn target_bytes(&self) -> Option<usize> {
+        Some(self.target_bytes)
+    }
+}
+
+/// Merges adjacent blocks that fall within the same time window, e.g. to
+/// turn many small flushes into one block per day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeWindowed {
+    /// Window length in timestamp units. Windows are aligned to multiples
+    /// of it since the epoch.
+    pub window: u64,
+}
+
+impl Default for TimeWindowed {
+    fn default() -> Self {
+        Self { window: 86_400 }
+    }
+}
+
+impl CompactionPolicy for TimeWindowed {
+    fn select(&self, blocks: &[BlockInfo]) -> Vec<Range<usize>> {
+        let window = self.window.max(1);
+        let mut runs = Vec::new();
+        let mut start = 0;
+        for i in 0..=blocks.len() {
+            let same = i < blocks.len()
+                && blocks[i].start / window == blocks[i].end / window
+                && blocks[start].start / window == blocks[i].start / window;
+            if !same {
+                if i - start >= 2 {
+                    runs.push(start..i);
+                }
+                start = if i < blocks.len() && blocks[i].start / window == blocks[i].end / window {
+                    i
+                } else {
+                    i + 1
+                };
+            }
+        }
+        runs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(start: u64, end: u64, bytes: usize) -> BlockInfo {
+        BlockInfo {
+            start,
+            end,
+            count: 1,
+            bytes,
+            checksum: false,
+            indexed: false,
+            timestamp_codec: TimestampScheme::DeltaOfDelta,
+            value_codec: ValueScheme::Gorilla,
+        }
+    }
+
+    #[test]
+    fn test_size_tiered_groups_similar_sizes() {
+        let policy = SizeTiered {
+            min_blocks: 3,
+            bucket_ratio: 2.0,
+            target_bytes: 1000,
+        };
+        let blocks: Vec<_> = [100, 120, 90, 110, 600, 2000, 50, 60, 70, 400]
+            .iter()
+            .enumerate()
+            .map(|(i, &bytes)| info(i as u64, i as u64, bytes))
+            .collect();
+        assert_eq!(policy.select(&blocks), [0..4, 6..9]);
+        assert!(policy.select(&blocks[..2]).is_empty());
+        assert_eq!(policy.target_bytes(), Some(1000));
+    }
+
+    #[test]
+    fn test_time_windowed_groups_by_window() {
+        let policy = TimeWindowed { window: 100 };
+        let blocks = [
+            info(0, 10, 1),
+            info(20, 50, 1),
+            info(60, 99, 1),
+            info(100, 150, 1),
+            // Straddles two windows, so it is never merged.
+            info(180, 220, 1),
+            info(230, 240, 1),
+            info(250, 260, 1),
+            info(300, 310, 1),
+        ];
+        assert_eq!(policy.select(&blocks), [0..3, 5..7]);
+        assert_eq!(policy.target_bytes(), None);
+    }
+}
@@ -0,0 +1,220 @@
+//! A block that one thread appends to while others read it.
+//!
+//! The Gorilla paper keeps the open block of every series in memory and
+//! serves queries from it while points keep arriving: a reader copies the
+//! block under a short lock and decodes the copy on its own time. A
+//! [`SyncEncoder`] is that writer, and every [`SyncReader`] it hands out
+//! takes such copies. Once the writer finishes the block, readers get the
+//! sealed stream without taking the lock at all.
+//!
+//! ```
+//! use gorilla::{DataPoint, Decoder, SyncEncoder};
+//!
+//! let mut writer = SyncEncoder::new(Default::default());
+//! let point = |i: u64| DataPoint::new(1609459200 + i * 60, i as f64);
+//! writer.encode(point(0)).unwrap();
+//! let reader = writer.reader();
+//! let query = std::thread::spawn(move || Decoder::decode(&reader.snapshot()).unwrap());
+//! for i in 1..1000 {
+//!     writer.encode(point(i)).unwrap();
+//! }
+//! let seen = query.join().unwrap();
+//! assert_eq!(seen, (0..seen.len() as u64).map(point).collect::<Vec<_>>());
+//! assert_eq!(writer.finish().unwrap().count(), 1000);
+//! ```
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+use crate::bitbuffer::BufferFull;
+use crate::encoder::{CompressedBlock, DataPoint, EncodeError, Encoder, EncoderConfig};
+
+/// A block whose stream is shared between clones.
+pub type SharedBlock = CompressedBlock<Arc<[u8]>>;
+
+/// State shared by the writer and its readers.
+#[derive(Debug)]
+struct Shared {
+    open: Mutex<Encoder>,
+    /// Points encoded so far, readable without the lock.
+    count: AtomicU64,
+    /// The finished block, set by the writer while it holds the lock.
+    sealed: OnceLock<SharedBlock>,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Encoder> {
+        self.open
+            .lock()
+            .expect("writer panicked while holding the encoder")
+    }
+}
+
+/// The single writer of a block read concurrently through [`SyncReader`]s.
+///
+/// Every call holds the lock only while its points are encoded, so readers
+/// wait for at most one [`encode_batch`](SyncEncoder::encode_batch).
+#[derive(Debug)]
+pub struct SyncEncoder {
+    shared: Arc<Shared>,
+}
+
+impl SyncEncoder {
+    /// Creates a writer for an empty block.
+    pub fn new(config: EncoderConfig) -> Self {
+        Self::from(Encoder::with_config(config))
+    }
+
+    /// Returns a handle for reading the block from other threads.
+    pub fn reader(&self) -> SyncReader {
+        SyncReader {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    /// Encodes a data point, see [`Encoder::encode`].
+    pub fn encode(&mut self, dp: DataPoint) -> Result<(), EncodeError> {
+        self.encode_batch(&[dp])
+    }
+
+    /// Encodes `points` in order under a single acquisition of the lock,
+    /// stopping at the first error as [`Encoder::try_extend`] does. Readers
+    /// see none of the points or all of those encoded.
+    pub fn encode_batch(&mut self, points: &[DataPoint]) -> Result<(), EncodeError> {
+        let mut open = self.shared.lock();
+        let result = open.try_extend(points.iter().copied());
+        self.shared.count.store(open.count(), Ordering::Release);
+        result
+    }
+
+    /// Returns the number of points encoded so far.
+    pub fn count(&self) -> u64 {
+        self.shared.count.load(Ordering::Acquire)
+    }
+
+    /// Finishes the block and publishes it to the readers, which from then
+    /// on read it without locking.
+    ///
+    /// Returns `Err(BufferFull)` if the buffer cannot fit the end-of-stream
+    /// marker, in which case the block stays open for the readers.
+    pub fn finish(self) -> Result<SharedBlock, BufferFull> {
+        let mut open = self.shared.lock();
+        open.finish()?;
+        let block = std::mem::take(&mut *open).into_compressed().into_storage();
+        self.shared
+            .sealed
+            .set(block.clone())
+            .expect("only the writer seals the block");
+        Ok(block)
+    }
+}
+
+impl From<Encoder> for SyncEncoder {
+    /// Shares an open encoder, e.g. one resumed from a stored block.
+    fn from(encoder: Encoder) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                count: AtomicU64::new(encoder.count()),
+                open: Mutex::new(encoder),
+                sealed: OnceLock::new(),
+            }),
+        }
+    }
+}
+
+/// A reader of the block of a [`SyncEncoder`], cheap to clone and send to
+/// other threads.
+#[derive(Debug, Clone)]
+pub struct SyncReader {
+    shared: Arc<Shared>,
+}
+
+impl SyncReader {
+    /// Returns a finished copy of every point encoded so far.
+    ///
+    /// While the block is open this clones the encoder under the lock and
+    /// finishes the clone after releasing it. Once the writer has finished
+    /// the block, the sealed block is returned without locking and without
+    /// copying its stream.
+    pub fn snapshot(&self) -> SharedBlock {
+        if let Some(block) = self.shared.sealed.get() {
+            return block.clone();
+        }
+        let open = {
+            let guard = self.shared.lock();
+            // The writer may have sealed the block while this waited.
+            if let Some(block) = self.shared.sealed.get() {
+                return block.clone();
+            }
+            Encoder::clone(&guard)
+        };
+        open.to_compressed().into_storage()
+    }
+
+    /// Returns the number of points encoded so far, without locking.
+    pub fn count(&self) -> u64 {
+        self.shared.count.load(Ordering::Acquire)
+    }
+
+    /// Returns `true` once the writer has finished the block.
+    pub fn is_sealed(&self) -> bool {
+        self.shared.sealed.get().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+
+    #[test]
+    fn test_snapshots_are_prefixes_while_writing() {
+        let points: Vec<_> = (0..5_000u64)
+            .map(|i| DataPoint::new(i * 10 + i % 3, (i % 17) as f64 * 0.5))
+            .collect();
+        let mut writer = SyncEncoder::new(EncoderConfig::default());
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let reader = writer.reader();
+                let points = points.clone();
+                std::thread::spawn(move || {
+                    while !reader.is_sealed() {
+                        let seen = reader.count();
+                        let snapshot = reader.snapshot();
+                        if snapshot.count() == 0 {
+                            continue;
+                        }
+                        let decoded = Decoder::decode(&snapshot).unwrap();
+                        assert!(decoded.len() as u64 >= seen);
+                        assert_eq!(decoded, points[..decoded.len()]);
+                    }
+                })
+            })
+            .collect();
+        for chunk in points.chunks(50) {
+            writer.encode_batch(chunk).unwrap();
+        }
+        let block = writer.finish().unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(Decoder::decode(&block).unwrap(), points);
+    }
+
+    #[test]
+    fn test_sealed_block_is_shared_without_copying() {
+        let mut writer = SyncEncoder::new(EncoderConfig::default());
+        let reader = writer.reader();
+        writer.encode(DataPoint::new(60, 1.0)).unwrap();
+        writer.encode(DataPoint::new(120, 2.0)).unwrap();
+        assert_eq!(reader.snapshot().count(), 2);
+        assert!(!reader.is_sealed());
+
+        let block = writer.finish().unwrap();
+        let (first, second) = (reader.snapshot(), reader.clone().snapshot());
+        assert!(reader.is_sealed());
+        assert_eq!(first.bytes().as_ptr(), block.bytes().as_ptr());
+        assert_eq!(second.bytes().as_ptr(), block.bytes().as_ptr());
+        assert_eq!(reader.count(), 2);
+    }
+}
@@ -0,0 +1,237 @@
+//! Conversions between data points and [`chrono`] date-times (feature
+//! `chrono`).
+//!
+//! Timestamps are whole seconds since the Unix epoch; constructing a point
+//! from a date-time drops its sub-second part. Decoded timestamps outside
+//! the range `DateTime<Utc>` can represent are reported as
+//! [`DecodeError::TimestampOverflow`] rather than clamped.
+
+use chrono::{DateTime, Utc};
+
+use crate::decoder::{DecodeError, Decoder, DecoderIter};
+use crate::encoder::{CompressedBlock, DataPoint};
+use crate::signed::{DataPoint64, SignedBlock, SignedDecoder, SignedIter};
+
+impl DataPoint {
+    /// Creates a point at `time`, truncated to whole seconds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `time` is before the Unix epoch; use [`DataPoint::try_at`]
+    /// or [`DataPoint64::at`] for such times.
+    ///
+    /// ```
+    /// use chrono::{TimeZone, Utc};
+    /// use gorilla::DataPoint;
+    ///
+    /// let time = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+    /// assert_eq!(DataPoint::at(time, 12.0), DataPoint::new(1609459200, 12.0));
+    /// ```
+    pub fn at(time: DateTime<Utc>, value: f64) -> Self {
+        Self::try_at(time, value).expect("time is before the Unix epoch")
+    }
+
+    /// Creates a point at `time`, or returns `None` if `time` is before the
+    /// Unix epoch.
+    pub fn try_at(time: DateTime<Utc>, value: f64) -> Option<Self> {
+        let timestamp = u64::try_from(time.timestamp()).ok()?;
+        Some(Self::new(timestamp, value))
+    }
+
+    /// Returns the point's timestamp as a date-time, or `None` if it lies
+    /// beyond the range `DateTime<Utc>` can represent.
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp(i64::try_from(self.timestamp).ok()?, 0)
+    }
+}
+
+impl DataPoint64 {
+    /// Creates a point at `time`, truncated to whole seconds. Unlike
+    /// [`DataPoint::at`], times before the Unix epoch are allowed.
+    pub fn at(time: DateTime<Utc>, value: f64) -> Self {
+        Self::new(time.timestamp(), value)
+    }
+
+    /// Returns the point's timestamp as a date-time, or `None` if it lies
+    /// beyond the range `DateTime<Utc>` can represent.
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp(self.timestamp, 0)
+    }
+}
+
+/// A data point whose timestamp can be read as a date-time.
+pub trait TimedPoint {
+    /// Returns the timestamp as a date-time, if representable.
+    fn time(&self) -> Option<DateTime<Utc>>;
+    /// Returns the value.
+    fn value(&self) -> f64;
+}
+
+impl TimedPoint for DataPoint {
+    fn time(&self) -> Option<DateTime<Utc>> {
+        DataPoint::time(self)
+    }
+
+    fn value(&self) -> f64 {
+        self.value
+    }
+}
+
+impl TimedPoint for DataPoint64 {
+    fn time(&self) -> Option<DateTime<Utc>> {
+        DataPoint64::time(self)
+    }
+
+    fn value(&self) -> f64 {
+        self.value
+    }
+}
+
+/// Adapts an iterator of decoded points into `(DateTime<Utc>, f64)` pairs.
+pub struct DateTimes<I> {
+    inner: I,
+}
+
+impl<I> DateTimes<I> {
+    /// Wraps an iterator of decoded points, e.g. a
+    /// [`DecoderIter`] returned by [`Decoder::iter_from`].
+    pub fn new(inner: I) -> Self {
+        Self { inner }
+    }
+}
+
+impl<I, P> Iterator for DateTimes<I>
+where
+    I: Iterator<Item = Result<P, DecodeError>>,
+    P: TimedPoint,
+{
+    type Item = Result<(DateTime<Utc>, f64), DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let point = match self.inner.next()? {
+            Ok(point) => point,
+            Err(e) => return Some(Err(e)),
+        };
+        Some(
+            point
+                .time()
+                .map(|time| (time, point.value()))
+                .ok_or(DecodeError::TimestampOverflow),
+        )
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl Decoder {
+    /// Decodes all points of `block` as `(DateTime<Utc>, f64)` pairs.
+    ///
+    /// ```
+    /// use chrono::{TimeZone, Utc};
+    /// use gorilla::{DataPoint, Decoder, Encoder};
+    ///
+    /// let time = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+    /// let mut encoder = Encoder::new();
+    /// encoder.encode(DataPoint::at(time, 12.0)).unwrap();
+    /// encoder.finish().unwrap();
+    ///
+    /// let points = Decoder::decode_datetimes(&encoder.into_compressed()).unwrap();
+    /// assert_eq!(points, vec![(time, 12.0)]);
+    /// ```
+    pub fn decode_datetimes(
+        block: &CompressedBlock,
+    ) -> Result<Vec<(DateTime<Utc>, f64)>, DecodeError> {
+        Self::iter_datetimes(block).collect()
+    }
+
+    /// Returns an iterator that lazily decodes the points of `block` as
+    /// `(DateTime<Utc>, f64)` pairs.
+    pub fn iter_datetimes(block: &CompressedBlock) -> DateTimes<DecoderIter<'_>> {
+        DateTimes::new(Self::iter(block))
+    }
+}
+
+impl SignedDecoder {
+    /// Decodes all points of `block` as `(DateTime<Utc>, f64)` pairs.
+    pub fn decode_datetimes(block: &SignedBlock) -> Result<Vec<(DateTime<Utc>, f64)>, DecodeError> {
+        Self::iter_datetimes(block).collect()
+    }
+
+    /// Returns an iterator that lazily decodes the points of `block` as
+    /// `(DateTime<Utc>, f64)` pairs.
+    pub fn iter_datetimes(block: &SignedBlock) -> DateTimes<SignedIter<'_>> {
+        DateTimes::new(Self::iter(block))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+    use crate::encoder::Encoder;
+    use crate::signed::SignedEncoder;
+
+    #[test]
+    fn test_datetime_roundtrip() {
+        let start = Utc.with_ymd_and_hms(2024, 2, 29, 23, 59, 0).unwrap();
+        let times: Vec<_> = (0..100)
+            .map(|i| start + Duration::seconds(i * 15))
+            .collect();
+        let mut encoder = Encoder::new();
+        for (i, &time) in times.iter().enumerate() {
+            encoder.encode(DataPoint::at(time, i as f64)).unwrap();
+        }
+        encoder.finish().unwrap();
+        let block = encoder.into_compressed();
+
+        let decoded = Decoder::decode_datetimes(&block).unwrap();
+        let expected: Vec<_> = times
+            .iter()
+            .enumerate()
+            .map(|(i, &t)| (t, i as f64))
+            .collect();
+        assert_eq!(decoded, expected);
+
+        let from: Vec<_> = DateTimes::new(Decoder::iter_from(&block, times[90].timestamp() as u64))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(from, expected[90..]);
+
+        // Sub-second parts are dropped.
+        let late = start + Duration::milliseconds(999);
+        assert_eq!(DataPoint::at(late, 0.0).timestamp, start.timestamp() as u64);
+    }
+
+    #[test]
+    fn test_pre_epoch_datetimes() {
+        let moon = Utc.with_ymd_and_hms(1969, 7, 20, 20, 17, 40).unwrap();
+        assert_eq!(DataPoint::try_at(moon, 1.0), None);
+
+        let mut encoder = SignedEncoder::new();
+        encoder.encode(DataPoint64::at(moon, 1.0)).unwrap();
+        encoder
+            .encode(DataPoint64::at(moon + Duration::days(365), 2.0))
+            .unwrap();
+        encoder.finish().unwrap();
+        let decoded = SignedDecoder::decode_datetimes(&encoder.into_block()).unwrap();
+        assert_eq!(
+            decoded,
+            vec![(moon, 1.0), (moon + Duration::days(365), 2.0)]
+        );
+    }
+
+    #[test]
+    fn test_unrepresentable_timestamp_is_an_error() {
+        let mut encoder = Encoder::new();
+        encoder.encode(DataPoint::new(0, 1.0)).unwrap();
+        encoder.encode(DataPoint::new(u64::MAX / 2, 2.0)).unwrap();
+        encoder.finish().unwrap();
+        let block = encoder.into_compressed();
+        let mut iter = Decoder::iter_datetimes(&block);
+        assert_eq!(iter.next(), Some(Ok((DateTime::UNIX_EPOCH, 1.0))));
+        assert_eq!(iter.next(), Some(Err(DecodeError::TimestampOverflow)));
+    }
+}
@@ -0,0 +1,417 @@
+//! Exact fixed-scale decimal series.
+//!
+//! Values are `i64` mantissas sharing one decimal scale per block, so
+//! `12.34` at scale 2 is stored as `1234`. Timestamps use the same
+//! delta-of-delta scheme as [`Encoder`]; each mantissa is stored as the
+//! zig-zag encoded difference from the previous one:
+//!
+//! | zig-zag delta == 0 | `0`                   | 1 bit   |
+//! | < 2^8              | `10` + 8-bit value    | 10 bits |
+//! | < 2^16             | `110` + 16-bit value  | 19 bits |
+//! | < 2^32             | `1110` + 32-bit value | 36 bits |
+//! | otherwise          | `1111` + 64-bit value | 68 bits |
+//!
+//! The first point is stored as a 64-bit timestamp and the raw mantissa.
+//! Streams end with the same marker as float blocks.
+//!
+//! [`Encoder`]: crate::encoder::Encoder
+
+use crate::bitbuffer::{BitBuffer, BitReader, BufferFull};
+use crate::decoder::{next_timestamp, DecodeError, Decoder, DodResult};
+use crate::encoder::{checked_delta, write_delta_of_delta};
+
+/// A decimal number `mantissa * 10^-scale`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decimal {
+    /// Unscaled value.
+    pub mantissa: i64,
+    /// Number of digits after the decimal point.
+    pub scale: u8,
+}
+
+impl Decimal {
+    /// Creates a decimal from its mantissa and scale.
+    pub fn new(mantissa: i64, scale: u8) -> Self {
+        Self { mantissa, scale }
+    }
+
+    /// Returns the same number at `scale`, or `None` if it cannot be
+    /// represented exactly (digits would be lost or the mantissa overflows).
+    pub fn rescale(self, scale: u8) -> Option<Decimal> {
+        let mantissa = if scale >= self.scale {
+            let factor = 10i64.checked_pow((scale - self.scale) as u32)?;
+            self.mantissa.checked_mul(factor)?
+        } else {
+            let factor = 10i64.checked_pow((self.scale - scale) as u32)?;
+            if self.mantissa % factor != 0 {
+                return None;
+            }
+            self.mantissa / factor
+        };
+        Some(Decimal { mantissa, scale })
+    }
+
+    /// Returns the nearest `f64`.
+    pub fn to_f64(self) -> f64 {
+        self.mantissa as f64 / 10f64.powi(self.scale as i32)
+    }
+}
+
+impl std::fmt::Display for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.scale == 0 {
+            return write!(f, "{}", self.mantissa);
+        }
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let scale = self.scale as usize;
+        let digits = format!("{digits:0>width$}", width = scale + 1);
+        let (int, frac) = digits.split_at(digits.len() - scale);
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        write!(f, "{sign}{int}.{frac}")
+    }
+}
+
+/// A single timestamped decimal value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecimalPoint {
+    /// Unix timestamp in seconds.
+    pub timestamp: u64,
+    /// The value.
+    pub value: Decimal,
+}
+
+impl DecimalPoint {
+    /// Creates a new point.
+    pub fn new(timestamp: u64, value: Decimal) -> Self {
+        Self { timestamp, value }
+    }
+}
+
+/// Error returned by [`DecimalEncoder::encode`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecimalError {
+    /// The buffer's byte limit was reached.
+    BufferFull,
+    /// The value cannot be represented exactly at the block's scale.
+    Inexact {
+        /// The rejected value.
+        value: Decimal,
+        /// The block's scale.
+        scale: u8,
+    },
+    /// The point's distance from the previous timestamp, or that distance's
+    /// difference from the previous one, does not fit in an `i64`.
+    TimestampOutOfRange {
+        /// Timestamp of the last encoded point.
+        previous: u64,
+        /// Timestamp of the rejected point.
+        timestamp: u64,
+    },
+}
+
+impl std::fmt::Display for DecimalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecimalError::BufferFull => write!(f, "buffer limit reached"),
+            DecimalError::Inexact { value, scale } => {
+                write!(f, "{value} cannot be represented exactly at scale {scale}")
+            }
+            DecimalError::TimestampOutOfRange {
+                previous,
+                timestamp,
+            } => write!(
+                f,
+                "timestamp {timestamp} is too far from previous timestamp {previous}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DecimalError {}
+
+impl From<BufferFull> for DecimalError {
+    fn from(_: BufferFull) -> Self {
+        DecimalError::BufferFull
+    }
+}
+
+/// A finished stream of decimal points at one scale.
+#[derive(Debug, Clone)]
+pub struct DecimalBlock {
+    /// Raw compressed bytes.
+    pub bytes: Vec<u8>,
+    /// Number of valid bits in `bytes`.
+    pub total_bits: usize,
+    /// Number of points in the block.
+    pub count: u64,
+    /// Scale shared by every value in the block.
+    pub scale: u8,
+}
+
+/// Compresses a stream of decimal points at a fixed scale.
+///
+/// # Example
+/// ```
+/// use gorilla::{Decimal, DecimalDecoder, DecimalEncoder, DecimalPoint};
+///
+/// let mut encoder = DecimalEncoder::new(2);
+/// encoder.encode(DecimalPoint::new(0, Decimal::new(1999, 2))).unwrap();
+/// encoder.encode(DecimalPoint::new(60, Decimal::new(20, 0))).unwrap();
+/// let block = encoder.finish().unwrap();
+///
+/// let points = DecimalDecoder::decode(&block).unwrap();
+/// assert_eq!(points[0].value.to_string(), "19.99");
+/// assert_eq!(points[1].value.to_string(), "20.00");
+/// ```
+#[derive(Debug)]
+pub struct DecimalEncoder {
+    buf: BitBuffer,
+    scale: u8,
+    count: u64,
+    prev_timestamp: u64,
+    prev_delta: i64,
+    prev_mantissa: i64,
+}
+
+impl DecimalEncoder {
+    /// Creates an empty encoder storing values at `scale`.
+    pub fn new(scale: u8) -> Self {
+        Self::with_buffer(BitBuffer::new(), scale)
+    }
+
+    /// Creates an encoder whose buffer will not grow beyond `max_bytes`.
+    pub fn with_limit(max_bytes: usize, scale: u8) -> Self {
+        Self::with_buffer(BitBuffer::with_limit(max_bytes), scale)
+    }
+
+    fn with_buffer(buf: BitBuffer, scale: u8) -> Self {
+        Self {
+            buf,
+            scale,
+            count: 0,
+            prev_timestamp: 0,
+            prev_delta: 0,
+            prev_mantissa: 0,
+        }
+    }
+
+    /// Returns the block's scale.
+    pub fn scale(&self) -> u8 {
+        self.scale
+    }
+
+    /// Returns the number of points encoded so far.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Appends a point, rescaling its value to the block's scale. Values
+    /// that would lose digits are rejected with [`DecimalError::Inexact`],
+    /// and timestamps too far from the previous one with
+    /// [`DecimalError::TimestampOutOfRange`].
+    pub fn encode(&mut self, point: DecimalPoint) -> Result<(), DecimalError> {
+        let mantissa = point
+            .value
+            .rescale(self.scale)
+            .ok_or(DecimalError::Inexact {
+                value: point.value,
+                scale: self.scale,
+            })?
+            .mantissa;
+        if self.count == 0 {
+            self.buf.write_bits(point.timestamp, 64)?;
+            self.buf.write_bits(mantissa as u64, 64)?;
+        } else {
+            let (delta, dod) = checked_delta(self.prev_timestamp, point.timestamp)
+                .and_then(|delta| Some((delta, delta.checked_sub(self.prev_delta)?)))
+                .ok_or(DecimalError::TimestampOutOfRange {
+                    previous: self.prev_timestamp,
+                    timestamp: point.timestamp,
+                })?;
+            write_delta_of_delta(&mut self.buf, dod)?;
+            self.encode_mantissa(mantissa)?;
+            self.prev_delta = delta;
+        }
+        self.prev_timestamp = point.timestamp;
+        self.prev_mantissa = mantissa;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Writes the end-of-stream marker and returns the finished block.
+    pub fn finish(mut self) -> Result<DecimalBlock, BufferFull> {
+        self.buf.write_bits(0b1111, 4)?;
+        self.buf.write_bits(0xFFFF_FFFF_FFFF_FFFF, 64)?;
+        let total_bits = self.buf.len_bits();
+        Ok(DecimalBlock {
+            bytes: self.buf.into_bytes(),
+            total_bits,
+            count: self.count,
+            scale: self.scale,
+        })
+    }
+
+    fn encode_mantissa(&mut self, mantissa: i64) -> Result<(), BufferFull> {
+        let zz = zigzag(mantissa.wrapping_sub(self.prev_mantissa));
+        if zz == 0 {
+            self.buf.write_bit(false)
+        } else if zz < 1 << 8 {
+            self.buf.write_bits(0b10, 2)?;
+            self.buf.write_bits(zz, 8)
+        } else if zz < 1 << 16 {
+            self.buf.write_bits(0b110, 3)?;
+            self.buf.write_bits(zz, 16)
+        } else if zz < 1 << 32 {
+            self.buf.write_bits(0b1110, 4)?;
+            self.buf.write_bits(zz, 32)
+        } else {
+            self.buf.write_bits(0b1111, 4)?;
+            self.buf.write_bits(zz, 64)
+        }
+    }
+}
+
+/// Decompresses a [`DecimalBlock`].
+pub struct DecimalDecoder;
+
+impl DecimalDecoder {
+    /// Decodes all points of `block`, checking the count against
+    /// `block.count`.
+    pub fn decode(block: &DecimalBlock) -> Result<Vec<DecimalPoint>, DecodeError> {
+        let mut reader = BitReader::from_raw(&block.bytes, block.total_bits);
+        let mut points = Vec::with_capacity(block.count as usize);
+        let point =
+            |timestamp, mantissa| DecimalPoint::new(timestamp, Decimal::new(mantissa, block.scale));
+
+        let mut timestamp = reader.read_bits(64).ok_or(DecodeError::Empty)?;
+        let mut mantissa = reader.read_bits(64).ok_or(DecodeError::UnexpectedEnd)? as i64;
+        let mut delta: i64 = 0;
+        points.push(point(timestamp, mantissa));
+
+        while let DodResult::Value(dod) = Decoder::decode_delta_of_delta(&mut reader)? {
+            delta = match points.len() {
+                1 => dod,
+                _ => delta
+                    .checked_add(dod)
+                    .ok_or(DecodeError::TimestampOverflow)?,
+            };
+            timestamp = next_timestamp(timestamp, delta)?;
+
+            let mut width = 0;
+            for bits in [8, 16, 32, 64] {
+                if !reader.read_bit().ok_or(DecodeError::UnexpectedEnd)? {
+                    break;
+                }
+                width = bits;
+            }
+            // A run of four ones has no terminating zero: the 64-bit case.
+            if width != 0 {
+                let zz = reader.read_bits(width).ok_or(DecodeError::UnexpectedEnd)?;
+                mantissa = mantissa.wrapping_add(unzigzag(zz));
+            }
+            points.push(point(timestamp, mantissa));
+        }
+
+        if points.len() as u64 != block.count {
+            return Err(DecodeError::CountMismatch {
+                expected: block.count,
+                actual: points.len() as u64,
+            });
+        }
+        Ok(points)
+    }
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    (n >> 1) as i64 ^ -((n & 1) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(scale: u8, points: &[DecimalPoint]) -> DecimalBlock {
+        let mut enc = DecimalEncoder::new(scale);
+        for &p in points {
+            enc.encode(p).unwrap();
+        }
+        let block = enc.finish().unwrap();
+        assert_eq!(DecimalDecoder::decode(&block).unwrap(), points);
+        block
+    }
+
+    #[test]
+    fn test_roundtrip_is_exact() {
+        let mantissas = [0, 1, -1, 255, -70_000, i64::MAX, i64::MIN, 12_345_678_901];
+        let points: Vec<_> = mantissas
+            .iter()
+            .enumerate()
+            .map(|(i, &m)| DecimalPoint::new(i as u64 * 60, Decimal::new(m, 4)))
+            .collect();
+        roundtrip(4, &points);
+    }
+
+    #[test]
+    fn test_small_price_moves_are_compact() {
+        let points: Vec<_> = (0..100)
+            .map(|i| DecimalPoint::new(i * 60, Decimal::new(10_000 + (i as i64 % 7) - 3, 2)))
+            .collect();
+        let block = roundtrip(2, &points);
+        // 128-bit header and 68-bit marker; every later point fits in one
+        // timestamp bit (after the second) and at most 10 value bits.
+        assert!(block.total_bits <= 128 + 68 + 9 + 99 * 11);
+    }
+
+    #[test]
+    fn test_values_are_rescaled_to_block_scale() {
+        let mut enc = DecimalEncoder::new(3);
+        enc.encode(DecimalPoint::new(0, Decimal::new(15, 1)))
+            .unwrap();
+        enc.encode(DecimalPoint::new(1, Decimal::new(1_250_000, 6)))
+            .unwrap();
+        let err = enc
+            .encode(DecimalPoint::new(2, Decimal::new(1_234_567, 6)))
+            .unwrap_err();
+        assert!(matches!(err, DecimalError::Inexact { scale: 3, .. }));
+        let points = DecimalDecoder::decode(&enc.finish().unwrap()).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].value, Decimal::new(1500, 3));
+        assert_eq!(points[1].value, Decimal::new(1250, 3));
+    }
+
+    #[test]
+    fn test_out_of_range_timestamp_is_rejected() {
+        let value = Decimal::new(1, 0);
+        let mut enc = DecimalEncoder::new(0);
+        enc.encode(DecimalPoint::new(0, value)).unwrap();
+        assert_eq!(
+            enc.encode(DecimalPoint::new(u64::MAX, value)),
+            Err(DecimalError::TimestampOutOfRange {
+                previous: 0,
+                timestamp: u64::MAX,
+            })
+        );
+        let decoded = DecimalDecoder::decode(&enc.finish().unwrap()).unwrap();
+        assert_eq!(decoded, [DecimalPoint::new(0, value)]);
+        let points = [
+            DecimalPoint::new(i64::MAX as u64, value),
+            DecimalPoint::new(i64::MAX as u64 + 1, value),
+        ];
+        roundtrip(0, &points);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Decimal::new(1999, 2).to_string(), "19.99");
+        assert_eq!(Decimal::new(-5, 3).to_string(), "-0.005");
+        assert_eq!(Decimal::new(42, 0).to_string(), "42");
+        assert_eq!(
+            Decimal::new(i64::MIN, 1).to_string(),
+            "-922337203685477580.8"
+        );
+    }
+}
@@ -1,5 +1,16 @@
+use std::ops::{Bound, RangeBounds};
+
 use crate::bitbuffer::BitReader;
-use crate::encoder::{CompressedBlock, DataPoint};
+use crate::checksum::crc32c;
+use crate::codec::{
+    DeltaState, TimestampCodec, TimestampScheme, ValueCodec, ValueScheme, Values, XorCodec,
+};
+use crate::encoder::{
+    check_gaps, check_index, extend_gap_stats, extend_stats, BlockStats, Checkpoint,
+    CompressedBlock, DataPoint, Priors,
+};
+use crate::query::{Accumulator, AggFn};
+use crate::typed::ValueType;
 
 /// Error type for decoding failures.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -8,6 +19,106 @@ pub enum DecodeError {
     UnexpectedEnd,
     /// The stream contains no data points.
     Empty,
+    /// The block's bytes do not match its stored checksum.
+    ChecksumMismatch {
+        /// Checksum recorded when the block was finished.
+        expected: u32,
+        /// Checksum of the bytes as they are now.
+        actual: u32,
+    },
+    /// The stream decoded cleanly but holds a different number of points
+    /// than the block's `count` says.
+    CountMismatch {
+        /// Point count recorded in the block.
+        expected: u64,
+        /// Number of points actually decoded.
+        actual: u64,
+    },
+    /// A value header describes an XOR window wider than 64 bits.
+    InvalidXorWindow {
+        /// Leading zero count from the header.
+        leading: u8,
+        /// Meaningful bit count from the header.
+        meaningful: u8,
+    },
+    /// A Chimp-coded value refers to a slot of the previous values that
+    /// holds none yet.
+    InvalidValueReference {
+        /// Slot from the stream.
+        slot: u8,
+    },
+    /// Reconstructing a timestamp overflowed.
+    TimestampOverflow,
+    /// The stream holds more points than the caller allowed.
+    TooManyPoints {
+        /// The maximum number of points the caller accepted.
+        limit: usize,
+    },
+    /// A serialized block uses a format version this crate cannot read.
+    UnsupportedVersion(u8),
+    /// A serialized block header is invalid.
+    MalformedHeader(&'static str),
+    /// A typed block holds a different value type than requested.
+    TypeMismatch {
+        /// The type the caller asked for.
+        expected: ValueType,
+        /// The type stored in the block.
+        actual: ValueType,
+    },
+    /// Decoding a point of the stream failed with `error`, such as
+    /// [`UnexpectedEnd`](DecodeError::UnexpectedEnd) or
+    /// [`InvalidXorWindow`](DecodeError::InvalidXorWindow). Returned by
+    /// [`Decoder`] so that reports of corrupt blocks say where the
+    /// corruption is; see [`kind`](DecodeError::kind) to match on the error
+    /// itself.
+    AtPoint {
+        /// Zero-based index of the point being decoded.
+        point_index: u64,
+        /// Offset in bits from the start of the stream at which decoding
+        /// failed.
+        bit_offset: u64,
+        /// What went wrong.
+        error: Box<DecodeError>,
+    },
+}
+
+impl DecodeError {
+    /// Returns the error without the position context of
+    /// [`AtPoint`](DecodeError::AtPoint).
+    ///
+    /// ```
+    /// use gorilla::{CompressedBlock, DataPoint, DecodeError, Decoder, Encoder};
+    ///
+    /// let mut encoder = Encoder::new();
+    /// for i in 0..10 {
+    ///     encoder.encode(DataPoint::new(1609459200 + i * 60, 1.0)).unwrap();
+    /// }
+    /// let block = encoder.finish_into().unwrap();
+    /// // Cut off in the middle of the eighth point.
+    /// let block = CompressedBlock::from_parts(block.bytes()[..19].to_vec(), 148, 10).unwrap();
+    ///
+    /// let err = Decoder::decode(&block).unwrap_err();
+    /// assert_eq!(err.kind(), &DecodeError::UnexpectedEnd);
+    /// assert_eq!(
+    ///     err.to_string(),
+    ///     "unexpected end of compressed stream at bit 148 of point 7"
+    /// );
+    /// ```
+    pub fn kind(&self) -> &DecodeError {
+        match self {
+            DecodeError::AtPoint { error, .. } => error.kind(),
+            _ => self,
+        }
+    }
+
+    /// Adds the position of the point being decoded to the error.
+    pub(crate) fn at(self, reader: &BitReader<'_>, point_index: u64) -> Self {
+        DecodeError::AtPoint {
+            point_index,
+            bit_offset: reader.position() as u64,
+            error: Box::new(self),
+        }
+    }
 }
 
 impl std::fmt::Display for DecodeError {
@@ -15,11 +126,100 @@ impl std::fmt::Display for DecodeError {
         match self {
             DecodeError::UnexpectedEnd => write!(f, "unexpected end of compressed stream"),
             DecodeError::Empty => write!(f, "compressed stream is empty"),
+            DecodeError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "block checksum mismatch: expected {expected:#010x}, got {actual:#010x}"
+            ),
+            DecodeError::CountMismatch { expected, actual } => write!(
+                f,
+                "block should hold {expected} points but {actual} were decoded"
+            ),
+            DecodeError::InvalidXorWindow {
+                leading,
+                meaningful,
+            } => write!(
+                f,
+                "XOR window of {leading} leading zeros and {meaningful} meaningful bits exceeds 64 bits"
+            ),
+            DecodeError::InvalidValueReference { slot } => {
+                write!(f, "value refers to empty slot {slot} of the previous values")
+            }
+            DecodeError::TimestampOverflow => write!(f, "timestamp reconstruction overflowed"),
+            DecodeError::TooManyPoints { limit } => {
+                write!(f, "stream holds more than {limit} points")
+            }
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported block format version {version}")
+            }
+            DecodeError::MalformedHeader(reason) => write!(f, "malformed block header: {reason}"),
+            DecodeError::TypeMismatch { expected, actual } => {
+                write!(f, "expected a {expected} block, found {actual}")
+            }
+            DecodeError::AtPoint {
+                point_index,
+                bit_offset,
+                error,
+            } => write!(f, "{error} at bit {bit_offset} of point {point_index}"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecodeError::AtPoint { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// A decoded sample: a point with a value, or the timestamp of a sample
+/// recorded as missing with [`Encoder::encode_gap`](crate::Encoder::encode_gap).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sample {
+    /// A point with a value, which may itself be NaN.
+    Value(DataPoint),
+    /// A missing sample at the given timestamp.
+    Missing(u64),
+}
+
+impl Sample {
+    /// Returns the sample's timestamp.
+    pub fn timestamp(&self) -> u64 {
+        match self {
+            Sample::Value(dp) => dp.timestamp,
+            Sample::Missing(timestamp) => *timestamp,
+        }
+    }
+
+    /// Returns the value, or `None` for a missing sample.
+    pub fn value(&self) -> Option<f64> {
+        match self {
+            Sample::Value(dp) => Some(dp.value),
+            Sample::Missing(_) => None,
+        }
+    }
+}
+
+impl From<Sample> for DataPoint {
+    /// Returns the point, with a NaN value for a missing sample.
+    fn from(sample: Sample) -> Self {
+        match sample {
+            Sample::Value(dp) => dp,
+            Sample::Missing(timestamp) => DataPoint::new(timestamp, f64::NAN),
         }
     }
 }
 
-impl std::error::Error for DecodeError {}
+/// How [`Decoder::resample`] fills grid points that fall between samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// On the straight line between the samples before and after.
+    Linear,
+    /// With the value of the sample before, carried forward, as for
+    /// step-wise series stored with a [`DeadBand`](crate::DeadBand).
+    Previous,
+}
 
 /// The Gorilla decompressor (decoder).
 ///
@@ -43,79 +243,973 @@ pub struct Decoder;
 
 impl Decoder {
     /// Decodes all data points from a `CompressedBlock`.
-    pub fn decode(block: &CompressedBlock) -> Result<Vec<DataPoint>, DecodeError> {
-        let mut reader = BitReader::from_raw(&block.bytes, block.total_bits);
-        Self::decode_from_reader(&mut reader)
+    ///
+    /// If the block carries a checksum it is verified first. The number of
+    /// decoded points is checked against [`CompressedBlock::count`].
+    pub fn decode<B: AsRef<[u8]>>(
+        block: &CompressedBlock<B>,
+    ) -> Result<Vec<DataPoint>, DecodeError> {
+        Self::check_block(block)?;
+        let mut reader = BitReader::from_raw(block.bytes(), block.total_bits);
+        let points = Self::decode_from_reader(
+            &mut reader,
+            block.epoch,
+            block.priors,
+            block.timestamp_codec,
+            block.value_codec,
+            block.count_terminated.then_some(block.count),
+        )?;
+        Self::check_count(block, points.len())?;
+        Ok(Self::mask_gaps(block, points))
+    }
+
+    /// Decodes all samples of `block`, telling missing samples recorded
+    /// with [`Encoder::encode_gap`](crate::Encoder::encode_gap) apart from
+    /// values. Checks as [`decode`](Decoder::decode) does.
+    pub fn decode_samples<B: AsRef<[u8]>>(
+        block: &CompressedBlock<B>,
+    ) -> Result<Vec<Sample>, DecodeError> {
+        let samples = Self::samples(block).collect::<Result<Vec<_>, _>>()?;
+        Self::check_count(block, samples.len())?;
+        Ok(samples)
+    }
+
+    /// Returns an iterator that lazily decodes the samples of `block`, see
+    /// [`decode_samples`](Decoder::decode_samples).
+    pub fn samples<B: AsRef<[u8]>>(block: &CompressedBlock<B>) -> Samples<'_> {
+        Samples {
+            inner: Self::iter(block),
+        }
+    }
+
+    /// Replaces the values of the missing samples of `block` among its
+    /// decoded `points` by NaN.
+    fn mask_gaps<B: AsRef<[u8]>>(
+        block: &CompressedBlock<B>,
+        mut points: Vec<DataPoint>,
+    ) -> Vec<DataPoint> {
+        for &gap in &block.gaps {
+            if let Some(dp) = points.get_mut(gap as usize) {
+                dp.value = f64::NAN;
+            }
+        }
+        points
+    }
+
+    /// Decodes as many points as possible, returning them together with the
+    /// error that stopped decoding (if any) instead of discarding them.
+    ///
+    /// The stream is decoded even if the checksum does not match; in that
+    /// case the mismatch is reported after all points have been read, and
+    /// some of the returned values may be corrupted.
+    ///
+    /// # Example
+    /// ```
+    /// use gorilla::{CompressedBlock, DataPoint, DecodeError, Decoder, Encoder};
+    ///
+    /// let mut encoder = Encoder::new();
+    /// for i in 0..10 {
+    ///     encoder.encode(DataPoint::new(1609459200 + i * 60, 1.0)).unwrap();
+    /// }
+    /// encoder.finish().unwrap();
+    /// let block = encoder.into_compressed();
+    /// // Cut off mid-stream.
+    /// let block = CompressedBlock::from_parts(block.bytes()[..19].to_vec(), 148, 10).unwrap();
+    ///
+    /// let (points, err) = Decoder::decode_lossy(&block);
+    /// assert_eq!(points.len(), 7);
+    /// assert_eq!(err.unwrap().kind(), &DecodeError::UnexpectedEnd);
+    /// ```
+    pub fn decode_lossy<B: AsRef<[u8]>>(
+        block: &CompressedBlock<B>,
+    ) -> (Vec<DataPoint>, Option<DecodeError>) {
+        let mut points = Vec::new();
+        for result in Self::unchecked_iter(block) {
+            match result {
+                Ok(dp) => points.push(dp),
+                Err(err) => return (points, Some(err)),
+            }
+        }
+        let err = Self::verify_checksum(block)
+            .and_then(|()| Self::check_count(block, points.len()))
+            .err();
+        (points, err)
     }
 
-    /// Decodes all data points from raw bytes + total bit count.
+    /// Decodes all data points from raw bytes + total bit count. The stream
+    /// must have been encoded without [`Priors`] and with
+    /// [`ValueScheme::Gorilla`].
     pub fn decode_raw(bytes: &[u8], total_bits: usize) -> Result<Vec<DataPoint>, DecodeError> {
         let mut reader = BitReader::from_raw(bytes, total_bits);
-        Self::decode_from_reader(&mut reader)
+        Self::decode_from_reader(
+            &mut reader,
+            None,
+            None,
+            TimestampScheme::DeltaOfDelta,
+            ValueScheme::Gorilla,
+            None,
+        )
     }
 
-    /// Returns an iterator that lazily decodes data points from a `CompressedBlock`.
-    pub fn iter(block: &CompressedBlock) -> DecoderIter<'_> {
-        let reader = BitReader::from_raw(&block.bytes, block.total_bits);
+    /// Returns an iterator that lazily decodes data points from raw bytes +
+    /// total bit count, borrowing `bytes` instead of copying them into a
+    /// [`CompressedBlock`], e.g. a memory-mapped file or a received frame.
+    /// The stream must be encoded as for [`decode_raw`](Decoder::decode_raw).
+    /// A `total_bits` beyond the end of `bytes` is reported as
+    /// [`DecodeError::UnexpectedEnd`] where the bytes run out.
+    ///
+    /// ```
+    /// use gorilla::{DataPoint, Decoder, Encoder};
+    ///
+    /// let mut encoder = Encoder::new();
+    /// for i in 0..10u64 {
+    ///     encoder.encode(DataPoint::new(1609459200 + i * 60, i as f64)).unwrap();
+    /// }
+    /// encoder.finish().unwrap();
+    /// let frame = encoder.buffer().as_bytes();
+    ///
+    /// let points: Vec<_> = Decoder::iter_raw(frame, encoder.len_bits())
+    ///     .collect::<Result<_, _>>()
+    ///     .unwrap();
+    /// assert_eq!(points[9], DataPoint::new(1609459740, 9.0));
+    /// ```
+    pub fn iter_raw(bytes: &[u8], total_bits: usize) -> DecoderIter<'_> {
         DecoderIter {
-            reader,
+            pending_error: None,
+            reader: BitReader::from_raw(bytes, total_bits.min(bytes.len() * 8)),
+            epoch: None,
+            priors: None,
+            timestamp_codec: TimestampScheme::DeltaOfDelta,
+            value_codec: ValueScheme::Gorilla,
+            values: Values::new(ValueScheme::Gorilla),
             state: IterState::Initial,
             prev_timestamp: 0,
-            prev_delta: 0,
+            deltas: DeltaState::default(),
             prev_value_bits: 0,
-            prev_leading_zeros: 0,
-            prev_trailing_zeros: 0,
+            skip_before: 0,
+            position: 0,
+            count: None,
+            count_terminated: false,
+            gaps: &[],
             done: false,
         }
     }
 
-    fn decode_from_reader(reader: &mut BitReader<'_>) -> Result<Vec<DataPoint>, DecodeError> {
-        let mut points = Vec::new();
-        let mut prev_timestamp: u64;
-        let mut prev_delta: i64;
-        let mut prev_value_bits: u64;
-        let mut prev_leading_zeros: u8 = 0;
-        let mut prev_trailing_zeros: u8 = 0;
+    /// Returns an iterator that lazily decodes data points from a `CompressedBlock`.
+    ///
+    /// If the block carries a checksum that does not match, the iterator
+    /// yields a single `Err(DecodeError::ChecksumMismatch)`.
+    pub fn iter<B: AsRef<[u8]>>(block: &CompressedBlock<B>) -> DecoderIter<'_> {
+        let mut iter = Self::unchecked_iter(block);
+        iter.pending_error = Self::check_block(block).err();
+        iter
+    }
 
-        // ── First data point ────────────────────────────────────────
-        let ts = reader.read_bits(64).ok_or(DecodeError::Empty)?;
-        let val_bits = reader.read_bits(64).ok_or(DecodeError::UnexpectedEnd)?;
-        prev_timestamp = ts;
-        prev_value_bits = val_bits;
-        prev_delta = 0;
-        points.push(DataPoint::new(ts, f64::from_bits(val_bits)));
+    /// Returns an iterator over the points of `block` with timestamps at or
+    /// after `timestamp`.
+    ///
+    /// Decoding starts at the last [`Checkpoint`] before `timestamp` in the
+    /// block's index, so only the points between that checkpoint and
+    /// `timestamp` are decoded and skipped. Without an index (see
+    /// [`EncoderConfig::checkpoint_interval`](crate::EncoderConfig::checkpoint_interval))
+    /// this scans from the start of the block like [`iter`](Decoder::iter).
+    ///
+    /// ```
+    /// use gorilla::{DataPoint, Decoder, Encoder, EncoderConfig};
+    ///
+    /// let config = EncoderConfig { checkpoint_interval: Some(64), ..Default::default() };
+    /// let mut encoder = Encoder::with_config(config);
+    /// for t in 0..1000 {
+    ///     encoder.encode(DataPoint::new(t * 10, t as f64)).unwrap();
+    /// }
+    /// encoder.finish().unwrap();
+    /// let block = encoder.into_compressed();
+    ///
+    /// let first = Decoder::iter_from(&block, 7005).next().unwrap().unwrap();
+    /// assert_eq!(first, DataPoint::new(7010, 701.0));
+    /// ```
+    pub fn iter_from<B: AsRef<[u8]>>(
+        block: &CompressedBlock<B>,
+        timestamp: u64,
+    ) -> DecoderIter<'_> {
+        let (_, mut iter) = Self::seek(block, timestamp);
+        iter.skip_before = timestamp;
+        iter
+    }
 
-        // ── Subsequent data points ──────────────────────────────────
+    /// Returns an iterator starting at the last checkpoint before
+    /// `timestamp`, together with the index of the first point it yields.
+    pub(crate) fn seek<B: AsRef<[u8]>>(
+        block: &CompressedBlock<B>,
+        timestamp: u64,
+    ) -> (u64, DecoderIter<'_>) {
+        // The encoder only checkpoints a point at least as late as every
+        // point before it, so no point before the last checkpoint ahead of
+        // `timestamp` reaches it. Stopping at the first later checkpoint
+        // keeps that true even if the timestamps are not sorted.
+        let checkpoint = Self::checkpoints(block)
+            .iter()
+            .take_while(|cp| cp.timestamp < timestamp)
+            .last();
+        let mut iter = Self::iter_at(block, checkpoint);
+        if let Err(err) = Self::check_block(block) {
+            iter.pending_error = Some(err);
+        }
+        (iter.position, iter)
+    }
+
+    /// Decodes a block on the rayon thread pool, one segment per
+    /// [`Checkpoint`] interval, and concatenates the
+    /// results. Blocks without an index are decoded serially, as by
+    /// [`decode`](Decoder::decode).
+    ///
+    /// ```
+    /// use gorilla::{DataPoint, Decoder, Encoder, EncoderConfig};
+    ///
+    /// let config = EncoderConfig { checkpoint_interval: Some(4096), ..Default::default() };
+    /// let mut encoder = Encoder::with_config(config);
+    /// for t in 0..100_000u64 {
+    ///     encoder.encode(DataPoint::new(t, (t % 100) as f64)).unwrap();
+    /// }
+    /// encoder.finish().unwrap();
+    /// let block = encoder.into_compressed();
+    ///
+    /// assert_eq!(Decoder::decode_parallel(&block).unwrap(), Decoder::decode(&block).unwrap());
+    /// ```
+    #[cfg(feature = "rayon")]
+    pub fn decode_parallel<B: AsRef<[u8]> + Sync>(
+        block: &CompressedBlock<B>,
+    ) -> Result<Vec<DataPoint>, DecodeError> {
+        use rayon::prelude::*;
+
+        let index = Self::checkpoints(block);
+        if index.is_empty() {
+            return Self::decode(block);
+        }
+        Self::check_block(block)?;
+        // Segment i starts after checkpoint i - 1 (or at the start of the
+        // stream) and ends with the point of checkpoint i; the last one runs
+        // to the end-of-stream marker.
+        let mut segments = Vec::with_capacity(index.len() + 1);
+        let mut start = None;
+        let mut decoded = 0u64;
+        for cp in index {
+            let len = cp
+                .point_index
+                .checked_add(1)
+                .and_then(|end| end.checked_sub(decoded))
+                .filter(|&len| len > 0)
+                .ok_or(DecodeError::MalformedHeader("checkpoints out of order"))?;
+            segments.push((start, Some(len as usize)));
+            start = Some(cp);
+            decoded += len;
+        }
+        segments.push((start, None));
+
+        let decoded: Vec<Vec<DataPoint>> = segments
+            .into_par_iter()
+            .map(|(checkpoint, len)| {
+                let iter = Self::iter_at(block, checkpoint);
+                let points = match len {
+                    Some(len) => {
+                        let points = iter.take(len).collect::<Result<Vec<_>, _>>()?;
+                        if points.len() < len {
+                            return Err(DecodeError::UnexpectedEnd);
+                        }
+                        points
+                    }
+                    None => iter.collect::<Result<Vec<_>, _>>()?,
+                };
+                Ok(points)
+            })
+            .collect::<Result<_, _>>()?;
+        let points = decoded.concat();
+        Self::check_count(block, points.len())?;
+        Ok(points)
+    }
+
+    /// Returns an iterator that resumes after `checkpoint`, or starts at the
+    /// beginning of the stream. The checksum is not verified.
+    fn iter_at<'a, B: AsRef<[u8]>>(
+        block: &'a CompressedBlock<B>,
+        checkpoint: Option<&Checkpoint>,
+    ) -> DecoderIter<'a> {
+        let mut iter = Self::unchecked_iter(block);
+        let Some(cp) = checkpoint else {
+            return iter;
+        };
+        if cp.bit_offset > block.total_bits as u64 {
+            let err = DecodeError::MalformedHeader("checkpoint beyond end of stream");
+            iter.pending_error = Some(err);
+            return iter;
+        }
+        let position = cp.point_index.checked_add(1);
+        let Some(position) = position.filter(|_| cp.has_valid_window()) else {
+            iter.pending_error = Some(DecodeError::MalformedHeader("invalid checkpoint"));
+            return iter;
+        };
+        iter.reader.seek(cp.bit_offset as usize);
+        iter.state = IterState::Subsequent;
+        iter.position = position;
+        iter.prev_timestamp = cp.timestamp;
+        iter.deltas = DeltaState::new(cp.delta);
+        iter.prev_value_bits = cp.value_bits;
+        let xor = XorCodec::resume(cp.value_bits, cp.leading_zeros, cp.trailing_zeros);
+        iter.values = Values::Gorilla(xor);
+        iter
+    }
+
+    /// Returns the checkpoints of `block` that decoding can resume from:
+    /// none under [`ValueScheme::Chimp`] or [`TimestampScheme::RunLength`],
+    /// whose state they cannot hold, or [`ValueScheme::Raw`], which is
+    /// never indexed.
+    fn checkpoints<B: AsRef<[u8]>>(block: &CompressedBlock<B>) -> &[Checkpoint] {
+        match (block.timestamp_codec, block.value_codec) {
+            (TimestampScheme::RunLength, _) => &[],
+            (_, ValueScheme::Gorilla) => &block.index,
+            (_, ValueScheme::Chimp | ValueScheme::Raw) => &[],
+        }
+    }
+
+    fn unchecked_iter<B: AsRef<[u8]>>(block: &CompressedBlock<B>) -> DecoderIter<'_> {
+        let mut iter = Self::iter_raw(block.bytes(), block.total_bits);
+        iter.epoch = block.epoch;
+        iter.priors = block.priors;
+        iter.timestamp_codec = block.timestamp_codec;
+        iter.value_codec = block.value_codec;
+        iter.values = Values::new(block.value_codec);
+        iter.count = Some(block.count);
+        iter.count_terminated = block.count_terminated;
+        iter.gaps = &block.gaps;
+        iter
+    }
+
+    /// Decodes a block from an untrusted source, validating stream invariants
+    /// as it goes.
+    ///
+    /// In addition to the checks done by [`decode`](Decoder::decode), this
+    /// stops with `DecodeError::TooManyPoints` once more than `max_points`
+    /// points have been decoded, bounding memory use regardless of the
+    /// block's claimed `count`.
+    pub fn decode_strict<B: AsRef<[u8]>>(
+        block: &CompressedBlock<B>,
+        max_points: usize,
+    ) -> Result<Vec<DataPoint>, DecodeError> {
+        Self::check_block(block)?;
+        let mut reader = BitReader::from_raw(block.bytes(), block.total_bits);
+        let too_many = DecodeError::TooManyPoints { limit: max_points };
+        let mut points = Vec::with_capacity((block.count as usize).min(max_points));
+        let timestamps = block.timestamp_codec;
+        let first = Self::read_first(&mut reader, block.epoch, block.priors, block.value_codec)
+            .map_err(|e| e.at(&reader, 0))?
+            .ok_or(DecodeError::Empty)?;
+        let mut prev_timestamp = first.timestamp;
+        let mut deltas = first.deltas;
+        let mut values = first.values;
+        if max_points == 0 {
+            return Err(too_many);
+        }
+        points.push(DataPoint::new(
+            prev_timestamp,
+            f64::from_bits(first.value_bits),
+        ));
+
+        while !(block.count_terminated && points.len() as u64 >= block.count) {
+            let index = points.len() as u64;
+            let read = timestamps.read_delta(&mut reader, &mut deltas);
+            let Some(delta) = read.map_err(|e| e.at(&reader, index))? else {
+                break;
+            };
+            if points.len() >= max_points {
+                return Err(too_many);
+            }
+            prev_timestamp =
+                next_timestamp(prev_timestamp, delta).map_err(|e| e.at(&reader, index))?;
+
+            let read = values.decode(&mut reader);
+            let (val_bits, _) = read.map_err(|e| e.at(&reader, index))?;
+            points.push(DataPoint::new(prev_timestamp, f64::from_bits(val_bits)));
+        }
+
+        Self::check_count(block, points.len())?;
+        Ok(Self::mask_gaps(block, points))
+    }
+
+    /// Parses and decodes a block serialized with
+    /// [`CompressedBlock::to_bytes`] that comes from an untrusted source,
+    /// such as a network peer. This is the entry point for such data: it
+    /// returns an error, and never panics, loops forever or overflows, for
+    /// any input.
+    ///
+    /// The header is validated by [`CompressedBlock::from_bytes`] and the
+    /// stream decoded by [`decode_strict`](Decoder::decode_strict) with the
+    /// block's own `count` as the limit. As the header cannot claim more
+    /// points than its stream has room for, the memory used is bounded by a
+    /// small multiple of `bytes.len()`.
+    ///
+    /// ```
+    /// use gorilla::{DataPoint, DecodeError, Decoder, Encoder};
+    ///
+    /// let mut encoder = Encoder::new();
+    /// for i in 0..10 {
+    ///     encoder.encode(DataPoint::new(1609459200 + i * 60, 1.0)).unwrap();
+    /// }
+    /// encoder.finish().unwrap();
+    /// let mut bytes = encoder.into_compressed().to_bytes();
+    /// assert_eq!(Decoder::decode_untrusted(&bytes).unwrap().len(), 10);
+    ///
+    /// // Claim a hundred points instead of ten.
+    /// bytes[2] = 100;
+    /// assert!(Decoder::decode_untrusted(&bytes).is_err());
+    /// assert_eq!(Decoder::decode_untrusted(&[]), Err(DecodeError::UnexpectedEnd));
+    /// ```
+    pub fn decode_untrusted(bytes: &[u8]) -> Result<Vec<DataPoint>, DecodeError> {
+        let block = CompressedBlock::from_bytes(bytes)?;
+        let limit = usize::try_from(block.count).unwrap_or(usize::MAX);
+        Self::decode_strict(&block, limit)
+    }
+
+    /// Returns the last point of `block` without collecting the points
+    /// before it, or `None` if the block is empty.
+    ///
+    /// # Example
+    /// ```
+    /// use gorilla::{DataPoint, Decoder, Encoder};
+    ///
+    /// let mut encoder = Encoder::new();
+    /// for i in 0..100 {
+    ///     encoder.encode(DataPoint::new(i * 60, i as f64)).unwrap();
+    /// }
+    /// encoder.finish().unwrap();
+    /// let block = encoder.into_compressed();
+    ///
+    /// assert_eq!(Decoder::last(&block).unwrap(), Some(DataPoint::new(5940, 99.0)));
+    /// assert_eq!(Decoder::nth(&block, 3).unwrap(), Some(DataPoint::new(180, 3.0)));
+    /// ```
+    pub fn last<B: AsRef<[u8]>>(
+        block: &CompressedBlock<B>,
+    ) -> Result<Option<DataPoint>, DecodeError> {
+        match block.count {
+            0 => Ok(None),
+            count => Self::nth(block, count as usize - 1),
+        }
+    }
+
+    /// Returns the `n`th point (zero-based) of `block`, decoding only the
+    /// points up to it, or `None` if the block holds `n` points or fewer.
+    pub fn nth<B: AsRef<[u8]>>(
+        block: &CompressedBlock<B>,
+        n: usize,
+    ) -> Result<Option<DataPoint>, DecodeError> {
+        if n as u64 >= block.count {
+            return Ok(None);
+        }
+        let mut decoded = 0;
+        for result in Self::iter(block) {
+            let dp = result?;
+            if decoded == n {
+                return Ok(Some(dp));
+            }
+            decoded += 1;
+        }
+        Err(DecodeError::CountMismatch {
+            expected: block.count,
+            actual: decoded as u64,
+        })
+    }
+
+    /// Computes `agg` over the points of `block` whose timestamps fall in
+    /// `range`, without collecting them into a vector. Returns `None` if no
+    /// point is in range.
+    ///
+    /// Points are streamed through the decoder. Blocks written under
+    /// [`OutOfOrderPolicy::AllowNegativeDelta`](crate::encoder::OutOfOrderPolicy)
+    /// need not be sorted, so the whole block is scanned rather than stopping
+    /// at the first timestamp past the end of the range. If the block carries
+    /// [`BlockStats`](crate::encoder::BlockStats) and lies entirely inside
+    /// `range`, `Sum`, `Mean`, `Min`, `Max` and `Count` are answered from the
+    /// stats without decoding; if it lies entirely outside, `None` is
+    /// returned without decoding.
+    ///
+    /// # Example
+    /// ```
+    /// use gorilla::{AggFn, DataPoint, Decoder, Encoder};
+    ///
+    /// let mut encoder = Encoder::new();
+    /// for i in 0..10 {
+    ///     encoder.encode(DataPoint::new(i * 60, i as f64)).unwrap();
+    /// }
+    /// encoder.finish().unwrap();
+    /// let block = encoder.into_compressed();
+    ///
+    /// assert_eq!(Decoder::aggregate(&block, AggFn::Sum, 120..300).unwrap(), Some(9.0));
+    /// assert_eq!(Decoder::aggregate(&block, AggFn::Max, ..).unwrap(), Some(9.0));
+    /// ```
+    pub fn aggregate<B: AsRef<[u8]>>(
+        block: &CompressedBlock<B>,
+        agg: AggFn,
+        range: impl RangeBounds<u64>,
+    ) -> Result<Option<f64>, DecodeError> {
+        if let (Some(stats), true) = (block.stats, block.gaps.is_empty()) {
+            let covered =
+                range.contains(&stats.start_timestamp) && range.contains(&stats.end_timestamp);
+            let before = match range.end_bound() {
+                Bound::Included(&end) => stats.start_timestamp > end,
+                Bound::Excluded(&end) => stats.start_timestamp >= end,
+                Bound::Unbounded => false,
+            };
+            let after = match range.start_bound() {
+                Bound::Included(&start) => stats.end_timestamp < start,
+                Bound::Excluded(&start) => stats.end_timestamp <= start,
+                Bound::Unbounded => false,
+            };
+            if before || after {
+                return Ok(None);
+            }
+            if covered && block.count > 0 {
+                match agg {
+                    AggFn::Sum => return Ok(Some(stats.sum)),
+                    AggFn::Mean => return Ok(Some(stats.sum / block.count as f64)),
+                    AggFn::Min => return Ok(Some(stats.min_value)),
+                    AggFn::Max => return Ok(Some(stats.max_value)),
+                    AggFn::Count => return Ok(Some(block.count as f64)),
+                    AggFn::First | AggFn::Last => {}
+                }
+            }
+        }
+
+        let mut acc = Accumulator::new();
+        for result in Self::samples(block) {
+            let sample = result?;
+            if let (Sample::Value(dp), true) = (sample, range.contains(&sample.timestamp())) {
+                acc.push(dp.value);
+            }
+        }
+        Ok(acc.result(agg))
+    }
+
+    /// Returns an iterator yielding one point per `bucket_width`-wide time
+    /// bucket that contains data, aggregated with `agg`.
+    ///
+    /// Buckets are aligned to multiples of `bucket_width` and each output
+    /// point is stamped with its bucket's start. The block is decoded once,
+    /// lazily, as the iterator advances. Missing samples are skipped, as in
+    /// [`aggregate`](Decoder::aggregate).
+    ///
+    /// # Panics
+    /// Panics if `bucket_width` is zero.
+    ///
+    /// # Example
+    /// ```
+    /// use gorilla::{AggFn, DataPoint, Decoder, Encoder};
+    ///
+    /// let mut encoder = Encoder::new();
+    /// for i in 0..6 {
+    ///     encoder.encode(DataPoint::new(i * 30, i as f64)).unwrap();
+    /// }
+    /// encoder.finish().unwrap();
+    /// let block = encoder.into_compressed();
+    ///
+    /// let points: Vec<_> = Decoder::downsample(&block, 60, AggFn::Max)
+    ///     .collect::<Result<_, _>>()
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     points,
+    ///     vec![DataPoint::new(0, 1.0), DataPoint::new(60, 3.0), DataPoint::new(120, 5.0)]
+    /// );
+    /// ```
+    pub fn downsample<B: AsRef<[u8]>>(
+        block: &CompressedBlock<B>,
+        bucket_width: u64,
+        agg: AggFn,
+    ) -> Downsample<'_> {
+        assert!(bucket_width > 0, "bucket width must be non-zero");
+        Downsample {
+            inner: Self::iter(block),
+            bucket_width,
+            agg,
+            bucket: 0,
+            acc: Accumulator::new(),
+        }
+    }
+
+    /// Returns an iterator over a regular grid of points spanning `block`,
+    /// for charting code that wants uniform arrays.
+    ///
+    /// Grid points are aligned to multiples of `step`, from the first at or
+    /// after the block's first point to the last at or before its last
+    /// point. Each takes the value of a point at its timestamp, or one
+    /// filled in from the points around it by `interpolation`. Missing
+    /// samples are filled like any other time without a point, and points
+    /// whose timestamp does not advance past the previous point's are
+    /// ignored. The block is decoded once, lazily, as the iterator
+    /// advances.
+    ///
+    /// # Panics
+    /// Panics if `step` is zero.
+    ///
+    /// # Example
+    /// ```
+    /// use gorilla::{DataPoint, Decoder, Encoder, Interpolation};
+    ///
+    /// let mut encoder = Encoder::new();
+    /// encoder.encode(DataPoint::new(10, 1.0)).unwrap();
+    /// encoder.encode_gap(40).unwrap();
+    /// encoder.encode(DataPoint::new(70, 4.0)).unwrap();
+    /// encoder.finish().unwrap();
+    /// let block = encoder.into_compressed();
+    ///
+    /// let values = |interpolation| -> Vec<f64> {
+    ///     Decoder::resample(&block, 20, interpolation)
+    ///         .map(|dp| dp.unwrap().value)
+    ///         .collect()
+    /// };
+    /// // Grid points at 20, 40 and 60.
+    /// assert_eq!(values(Interpolation::Linear), [1.5, 2.5, 3.5]);
+    /// assert_eq!(values(Interpolation::Previous), [1.0, 1.0, 1.0]);
+    /// ```
+    pub fn resample<B: AsRef<[u8]>>(
+        block: &CompressedBlock<B>,
+        step: u64,
+        interpolation: Interpolation,
+    ) -> Resample<'_> {
+        assert!(step > 0, "resampling step must be non-zero");
+        Resample {
+            inner: Self::iter(block),
+            step,
+            interpolation,
+            before: None,
+            after: None,
+            time: 0,
+            done: false,
+        }
+    }
+
+    /// Returns an iterator over every `n`th point of `block` (the first,
+    /// the `n + 1`th, and so on), for approximate rendering of dense series.
+    ///
+    /// Skipped points are not materialized, but their bits are still read,
+    /// since each value is XORed against its predecessor. If the block has a
+    /// [`Checkpoint`] index, decoding jumps to the last checkpoint before
+    /// each sampled point, so with `n` larger than the checkpoint interval
+    /// most of the stream is never touched. A sampled position holding a
+    /// [`Sample::Missing`] gap yields nothing.
+    ///
+    /// # Panics
+    /// Panics if `n` is zero.
+    ///
+    /// # Example
+    /// ```
+    /// use gorilla::{DataPoint, Decoder, Encoder, EncoderConfig};
+    ///
+    /// let config = EncoderConfig { checkpoint_interval: Some(16), ..Default::default() };
+    /// let mut encoder = Encoder::with_config(config);
+    /// for i in 0..1000 {
+    ///     encoder.encode(DataPoint::new(i, i as f64)).unwrap();
+    /// }
+    /// encoder.finish().unwrap();
+    /// let block = encoder.into_compressed();
+    ///
+    /// let sampled: Vec<_> = Decoder::every_nth(&block, 250)
+    ///     .collect::<Result<_, _>>()
+    ///     .unwrap();
+    /// assert_eq!(sampled.iter().map(|dp| dp.timestamp).collect::<Vec<_>>(), [0, 250, 500, 750]);
+    /// ```
+    pub fn every_nth<B: AsRef<[u8]>>(block: &CompressedBlock<B>, n: usize) -> EveryNth<'_, B> {
+        assert!(n > 0, "sampling interval must be non-zero");
+        EveryNth {
+            block,
+            inner: Self::iter(block),
+            n: n as u64,
+            position: 0,
+            target: 0,
+            done: false,
+        }
+    }
+
+    /// Returns how every point of `block` was encoded: the delta-of-delta
+    /// bucket and XOR case used and the bits each half of the point took.
+    /// The records explain where a poorly compressing series spends its
+    /// bits; the end-of-stream marker is not included.
+    ///
+    /// # Example
+    /// ```
+    /// use gorilla::{DataPoint, Decoder, DodBucket, Encoder, ValueEncoding};
+    ///
+    /// let mut encoder = Encoder::new();
+    /// for (t, v) in [(0, 1.0), (60, 1.0), (120, 2.0), (185, 2.0)] {
+    ///     encoder.encode(DataPoint::new(t, v)).unwrap();
+    /// }
+    /// encoder.finish().unwrap();
+    /// let records = Decoder::inspect(&encoder.into_compressed()).unwrap();
+    ///
+    /// assert_eq!(records[1].value_encoding, ValueEncoding::Repeat);
+    /// assert_eq!(records[2].timestamp_encoding, DodBucket::Zero);
+    /// assert_eq!(records[2].value_encoding, ValueEncoding::NewWindow);
+    /// assert_eq!((records[3].dod, records[3].timestamp_bits), (5, 9));
+    /// ```
+    pub fn inspect<B: AsRef<[u8]>>(
+        block: &CompressedBlock<B>,
+    ) -> Result<Vec<PointEncoding>, DecodeError> {
+        let mut reader = BitReader::from_raw(block.bytes(), block.total_bits);
+        let mut records = Vec::with_capacity(block.count.min(1 << 20) as usize);
+        let before = reader.remaining();
+        let timestamps = block.timestamp_codec;
+        let Some(first) =
+            Self::read_first(&mut reader, block.epoch, block.priors, block.value_codec)
+                .map_err(|e| e.at(&reader, 0))?
+        else {
+            return Ok(records);
+        };
+        let bits = (before - reader.remaining()) as u32 - first.timestamp_bits;
+        records.push(PointEncoding {
+            point: DataPoint::new(first.timestamp, f64::from_bits(first.value_bits)),
+            dod: 0,
+            timestamp_encoding: DodBucket::Raw,
+            timestamp_bits: first.timestamp_bits,
+            value_encoding: first.value_encoding,
+            value_bits: bits,
+            leading_zeros: first.values.window().0,
+            trailing_zeros: first.values.window().1,
+        });
+        let (mut timestamp, mut deltas) = (first.timestamp, first.deltas);
+        let mut values = first.values;
         loop {
-            // Decode delta-of-delta.
-            let dod = match Self::decode_delta_of_delta(reader)? {
-                DodResult::Value(v) => v,
-                DodResult::EndOfStream => break,
+            if block.count_terminated && records.len() as u64 >= block.count {
+                return Ok(records);
+            }
+            let before = reader.remaining();
+            let previous = deltas.reference;
+            let index = records.len() as u64;
+            let read = timestamps.read_delta(&mut reader, &mut deltas);
+            let Some(delta) = read.map_err(|e| e.at(&reader, index))? else {
+                return Ok(records);
+            };
+            let timestamp_bits = (before - reader.remaining()) as u32;
+            let dod = delta.wrapping_sub(previous);
+            let timestamp_encoding = match timestamps {
+                TimestampScheme::Raw => DodBucket::Raw,
+                TimestampScheme::RunLength if timestamp_bits == 0 => DodBucket::Run,
+                TimestampScheme::RunLength if dod == 0 => DodBucket::Zero,
+                _ => DodBucket::for_bits(timestamp_bits),
             };
+            timestamp = next_timestamp(timestamp, delta).map_err(|e| e.at(&reader, index))?;
 
-            if points.len() == 1 {
-                // Second point: dod IS the delta.
-                prev_delta = dod;
-            } else {
-                prev_delta += dod;
+            let before = reader.remaining();
+            let read = values.decode(&mut reader);
+            let (value_bits, value_encoding) = read.map_err(|e| e.at(&reader, index))?;
+            let bits = (before - reader.remaining()) as u32;
+            let (leading, trailing) = values.window();
+            records.push(PointEncoding {
+                point: DataPoint::new(timestamp, f64::from_bits(value_bits)),
+                dod,
+                timestamp_encoding,
+                timestamp_bits,
+                value_encoding,
+                value_bits: bits,
+                leading_zeros: leading,
+                trailing_zeros: trailing,
+            });
+        }
+    }
+
+    /// Walks the stream of `block` without materializing its points and
+    /// returns the statistics of what it holds, or `None` if it is empty.
+    ///
+    /// This checks everything [`decode`](Decoder::decode) does: the
+    /// checksum, that every point parses and that the stream holds `count`
+    /// points. Stream errors carry the index and bit offset of the point
+    /// they were found at, see [`DecodeError::AtPoint`]. Memory use is
+    /// constant, so storage layers can verify blocks after WAL replay
+    /// before serving them.
+    ///
+    /// ```
+    /// use gorilla::{DataPoint, Decoder, Encoder};
+    ///
+    /// let mut encoder = Encoder::new();
+    /// for i in 0..100 {
+    ///     encoder.encode(DataPoint::new(i * 60, i as f64)).unwrap();
+    /// }
+    /// encoder.finish().unwrap();
+    /// let block = encoder.into_compressed();
+    ///
+    /// let stats = Decoder::validate(&block).unwrap().unwrap();
+    /// assert_eq!((stats.end_timestamp, stats.max_value), (5940, 99.0));
+    /// ```
+    pub fn validate<B: AsRef<[u8]>>(
+        block: &CompressedBlock<B>,
+    ) -> Result<Option<BlockStats>, DecodeError> {
+        let mut iter = Self::iter(block);
+        let mut stats = None;
+        let mut decoded = 0;
+        while let Some(sample) = iter.next_sample() {
+            stats = Some(match sample? {
+                Sample::Value(dp) => extend_stats(stats, dp),
+                Sample::Missing(timestamp) => extend_gap_stats(stats, timestamp),
+            });
+            decoded += 1;
+        }
+        Self::check_count(block, decoded)?;
+        Ok(stats)
+    }
+
+    /// Checks the block's bytes against its stored checksum. Blocks without
+    /// a checksum always pass.
+    pub fn verify_checksum<B: AsRef<[u8]>>(block: &CompressedBlock<B>) -> Result<(), DecodeError> {
+        if let Some(expected) = block.checksum {
+            let actual = crc32c(block.bytes());
+            if actual != expected {
+                return Err(DecodeError::ChecksumMismatch { expected, actual });
             }
-            prev_timestamp = (prev_timestamp as i64 + prev_delta) as u64;
+        }
+        Ok(())
+    }
+
+    /// Checks the block's checksum, and its checkpoint index and gap
+    /// positions against the stream, since those are public fields that
+    /// need not come from the encoder.
+    fn check_block<B: AsRef<[u8]>>(block: &CompressedBlock<B>) -> Result<(), DecodeError> {
+        Self::verify_checksum(block)?;
+        check_index(Self::checkpoints(block), block.count, block.total_bits)?;
+        check_gaps(&block.gaps, block.count)
+    }
+
+    fn check_count<B: AsRef<[u8]>>(
+        block: &CompressedBlock<B>,
+        decoded: usize,
+    ) -> Result<(), DecodeError> {
+        if decoded as u64 != block.count {
+            return Err(DecodeError::CountMismatch {
+                expected: block.count,
+                actual: decoded as u64,
+            });
+        }
+        Ok(())
+    }
+
+    /// Reads the first point of a stream, or returns `None` if the stream is
+    /// empty. With an `epoch`, the timestamp is a varint offset from it.
+    fn read_first(
+        reader: &mut BitReader<'_>,
+        epoch: Option<u64>,
+        priors: Option<Priors>,
+        values: ValueScheme,
+    ) -> Result<Option<FirstPoint>, DecodeError> {
+        let before = reader.remaining();
+        let timestamp = match epoch {
+            Some(epoch) => match read_varint(reader)? {
+                Some(offset) => epoch
+                    .checked_add(offset)
+                    .ok_or(DecodeError::TimestampOverflow)?,
+                None => return Ok(None),
+            },
+            None => match reader.read_bits(64) {
+                Some(timestamp) => timestamp,
+                None => return Ok(None),
+            },
+        };
+        let timestamp_bits = (before - reader.remaining()) as u32;
+        let mut values = Values::new(values);
+        let mut deltas = DeltaState::default();
+        if let Some(priors) = priors {
+            values.seed(priors.value.to_bits());
+            deltas = DeltaState::new(priors.interval as i64);
+        }
+        let (value_bits, value_encoding) = values.decode(reader)?;
+        Ok(Some(FirstPoint {
+            timestamp,
+            timestamp_bits,
+            value_bits,
+            deltas,
+            value_encoding,
+            values,
+        }))
+    }
+
+    /// Decodes a stream up to its end-of-stream marker, or, for a stream
+    /// without one, up to `count` points.
+    fn decode_from_reader(
+        reader: &mut BitReader<'_>,
+        epoch: Option<u64>,
+        priors: Option<Priors>,
+        timestamps: TimestampScheme,
+        values: ValueScheme,
+        count: Option<u64>,
+    ) -> Result<Vec<DataPoint>, DecodeError> {
+        let mut points = Vec::new();
+
+        // ── First data point ────────────────────────────────────────
+        let first = Self::read_first(reader, epoch, priors, values)
+            .map_err(|e| e.at(reader, 0))?
+            .ok_or(DecodeError::Empty)?;
+        let mut prev_timestamp = first.timestamp;
+        let mut deltas = first.deltas;
+        let mut values = first.values;
+        points.push(DataPoint::new(
+            prev_timestamp,
+            f64::from_bits(first.value_bits),
+        ));
 
-            // Decode value.
-            let (val_bits, leading, trailing) =
-                Self::decode_value(reader, prev_value_bits, prev_leading_zeros, prev_trailing_zeros)?;
-            prev_value_bits = val_bits;
-            prev_leading_zeros = leading;
-            prev_trailing_zeros = trailing;
+        // ── Subsequent data points ──────────────────────────────────
+        // The second point's dod is relative to the first point's delta:
+        // zero, or the expected interval of the priors.
+        while count.is_none_or(|count| (points.len() as u64) < count) {
+            let index = points.len() as u64;
+            let read = timestamps.read_delta(reader, &mut deltas);
+            let Some(delta) = read.map_err(|e| e.at(reader, index))? else {
+                break;
+            };
+            prev_timestamp =
+                next_timestamp(prev_timestamp, delta).map_err(|e| e.at(reader, index))?;
 
+            let read = values.decode(reader);
+            let (val_bits, _) = read.map_err(|e| e.at(reader, index))?;
             points.push(DataPoint::new(prev_timestamp, f64::from_bits(val_bits)));
         }
 
         Ok(points)
     }
 
+    /// Decodes the first `count` points of a stream that need not be
+    /// terminated yet, such as an open encoder's.
+    pub(crate) fn decode_points(
+        reader: &mut BitReader<'_>,
+        epoch: Option<u64>,
+        priors: Option<Priors>,
+        timestamps: TimestampScheme,
+        values: ValueScheme,
+        count: u64,
+    ) -> Result<Vec<DataPoint>, DecodeError> {
+        let mut points = Vec::with_capacity(count as usize);
+        if count == 0 {
+            return Ok(points);
+        }
+        let first = Self::read_first(reader, epoch, priors, values)
+            .map_err(|e| e.at(reader, 0))?
+            .ok_or(DecodeError::Empty)?;
+        let mut prev_timestamp = first.timestamp;
+        let mut deltas = first.deltas;
+        let mut values = first.values;
+        points.push(DataPoint::new(
+            prev_timestamp,
+            f64::from_bits(first.value_bits),
+        ));
+        while (points.len() as u64) < count {
+            let index = points.len() as u64;
+            let timestamp = timestamps
+                .read_delta(reader, &mut deltas)
+                .and_then(|delta| delta.ok_or(DecodeError::UnexpectedEnd))
+                .and_then(|delta| next_timestamp(prev_timestamp, delta));
+            prev_timestamp = timestamp.map_err(|e| e.at(reader, index))?;
+            let read = values.decode(reader);
+            let (val_bits, _) = read.map_err(|e| e.at(reader, index))?;
+            points.push(DataPoint::new(prev_timestamp, f64::from_bits(val_bits)));
+        }
+        Ok(points)
+    }
+
     /// Decodes a variable-length delta-of-delta value.
-    fn decode_delta_of_delta(reader: &mut BitReader<'_>) -> Result<DodResult, DecodeError> {
+    pub(crate) fn decode_delta_of_delta(reader: &mut BitReader<'_>) -> Result<DodResult, DecodeError> {
         let bit = reader.read_bit().ok_or(DecodeError::UnexpectedEnd)?;
         if !bit {
             // '0' => dod == 0
@@ -156,39 +1250,79 @@ impl Decoder {
     }
 
     /// Decodes an XOR-compressed value.
-    fn decode_value(
+    pub(crate) fn decode_value(
         reader: &mut BitReader<'_>,
         prev_value_bits: u64,
         prev_leading_zeros: u8,
         prev_trailing_zeros: u8,
     ) -> Result<(u64, u8, u8), DecodeError> {
+        let (bits, leading, trailing, _) = Self::read_xor(
+            reader,
+            prev_value_bits,
+            prev_leading_zeros,
+            prev_trailing_zeros,
+        )?;
+        Ok((bits, leading, trailing))
+    }
+
+    /// Decodes an XOR-compressed value, also returning which case it was
+    /// stored with.
+    pub(crate) fn read_xor(
+        reader: &mut BitReader<'_>,
+        prev_value_bits: u64,
+        prev_leading_zeros: u8,
+        prev_trailing_zeros: u8,
+    ) -> Result<(u64, u8, u8, ValueEncoding), DecodeError> {
         let bit = reader.read_bit().ok_or(DecodeError::UnexpectedEnd)?;
         if !bit {
             // XOR is zero — same value.
-            return Ok((prev_value_bits, prev_leading_zeros, prev_trailing_zeros));
+            return Ok((
+                prev_value_bits,
+                prev_leading_zeros,
+                prev_trailing_zeros,
+                ValueEncoding::Repeat,
+            ));
         }
 
         let control = reader.read_bit().ok_or(DecodeError::UnexpectedEnd)?;
         if !control {
             // '10' — reuse previous leading/trailing zero window.
-            let meaningful_bits = 64 - prev_leading_zeros - prev_trailing_zeros;
+            let meaningful_bits = prev_leading_zeros
+                .checked_add(prev_trailing_zeros)
+                .and_then(|zeros| 64u8.checked_sub(zeros))
+                .filter(|&bits| bits > 0)
+                .ok_or(DecodeError::InvalidXorWindow {
+                    leading: prev_leading_zeros,
+                    meaningful: 0,
+                })?;
             let meaningful = reader
                 .read_bits(meaningful_bits)
                 .ok_or(DecodeError::UnexpectedEnd)?;
             let xor = meaningful << prev_trailing_zeros;
             let value_bits = prev_value_bits ^ xor;
-            Ok((value_bits, prev_leading_zeros, prev_trailing_zeros))
+            Ok((
+                value_bits,
+                prev_leading_zeros,
+                prev_trailing_zeros,
+                ValueEncoding::ReusedWindow,
+            ))
         } else {
             // '11' — new window.
             let leading = reader.read_bits(6).ok_or(DecodeError::UnexpectedEnd)? as u8;
             let meaningful_bits = reader.read_bits(6).ok_or(DecodeError::UnexpectedEnd)? as u8 + 1;
+            if leading + meaningful_bits > 64 {
+                return Err(DecodeError::InvalidXorWindow {
+                    leading,
+                    meaningful: meaningful_bits,
+                });
+            }
             let trailing = 64 - leading - meaningful_bits;
             let meaningful = reader
                 .read_bits(meaningful_bits)
                 .ok_or(DecodeError::UnexpectedEnd)?;
             let xor = meaningful << trailing;
             let value_bits = prev_value_bits ^ xor;
-            Ok((value_bits, leading, trailing))
+            Ok((value_bits, leading, trailing, ValueEncoding::NewWindow))
         }
     }
 }
@@ -200,7 +1334,60 @@ fn sign_extend(value: u64, bits: u8) -> i64 {
     ((value << shift) as i64) >> shift
 }
 
-enum DodResult {
+/// Decoder state after the first point of a stream.
+struct FirstPoint {
+    timestamp: u64,
+    /// Bits the timestamp took: 64, or a varint's with an epoch.
+    timestamp_bits: u32,
+    value_bits: u64,
+    /// Timestamp codec state before the second point, whose dod is
+    /// relative to its reference.
+    deltas: DeltaState,
+    /// Case the value was stored with.
+    value_encoding: ValueEncoding,
+    /// State of the value codec after the value.
+    values: Values,
+}
+
+/// Returns the timestamp `delta` after `prev`, or
+/// `DecodeError::TimestampOverflow` if it is not a `u64`, which the encoder
+/// never writes.
+pub(crate) fn next_timestamp(prev: u64, delta: i64) -> Result<u64, DecodeError> {
+    prev.checked_add_signed(delta)
+        .ok_or(DecodeError::TimestampOverflow)
+}
+
+/// Reads a varint written by
+/// [`write_varint`](crate::encoder::write_varint), or returns `None` if the
+/// stream is empty. A varint of more than 64 bits is reported as
+/// `DecodeError::TimestampOverflow`, as the encoder only writes timestamps.
+fn read_varint(reader: &mut BitReader<'_>) -> Result<Option<u64>, DecodeError> {
+    let Some(mut group) = reader.read_bits(8) else {
+        return Ok(None);
+    };
+    let mut n = 0u64;
+    let mut shift = 0;
+    loop {
+        let bits = group & 0x7F;
+        if shift == 63 && bits > 1 {
+            return Err(DecodeError::TimestampOverflow);
+        }
+        n |= bits << shift;
+        if group & 0x80 == 0 {
+            return Ok(Some(n));
+        }
+        shift += 7;
+        if shift > 63 {
+            return Err(DecodeError::TimestampOverflow);
+        }
+        group = reader.read_bits(8).ok_or(DecodeError::UnexpectedEnd)?;
+    }
+}
+
+/// A delta-of-delta read by [`Decoder::decode_delta_of_delta`]: a value,
+/// or the end-of-stream marker, whose 64-bit payload no value is stored as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DodResult {
     Value(i64),
     EndOfStream,
 }
@@ -210,56 +1397,177 @@ enum DodResult {
 #[derive(Debug)]
 enum IterState {
     Initial,
-    SecondPoint,
     Subsequent,
 }
 
 /// A lazy iterator that yields `DataPoint`s from a compressed block.
 pub struct DecoderIter<'a> {
     reader: BitReader<'a>,
+    /// Error to report before decoding anything (e.g. a checksum mismatch).
+    pending_error: Option<DecodeError>,
+    epoch: Option<u64>,
+    priors: Option<Priors>,
+    timestamp_codec: TimestampScheme,
+    value_codec: ValueScheme,
+    /// State of the value codec.
+    values: Values,
     state: IterState,
     prev_timestamp: u64,
-    prev_delta: i64,
+    /// State of the timestamp codec, including the delta the next
+    /// timestamp is read against.
+    deltas: DeltaState,
     prev_value_bits: u64,
-    prev_leading_zeros: u8,
-    prev_trailing_zeros: u8,
+    /// Points with earlier timestamps are decoded but not yielded.
+    skip_before: u64,
+    /// Index in the block of the next point read from the stream.
+    position: u64,
+    /// Number of points the block holds, if known.
+    count: Option<u64>,
+    /// Whether the stream ends after `count` points rather than at an
+    /// end-of-stream marker.
+    count_terminated: bool,
+    /// Indices of the block's missing samples.
+    gaps: &'a [u64],
     done: bool,
 }
 
+/// Continuation state of a stream, as needed to append to it.
+#[derive(Debug, Clone)]
+pub(crate) struct StreamState {
+    pub(crate) timestamp: u64,
+    pub(crate) deltas: DeltaState,
+    pub(crate) value_bits: u64,
+    pub(crate) values: Values,
+    /// Number of stream bits not yet read.
+    pub(crate) remaining_bits: usize,
+}
+
+impl DecoderIter<'_> {
+    /// Returns the state after the most recently yielded point.
+    pub(crate) fn state(&self) -> StreamState {
+        StreamState {
+            timestamp: self.prev_timestamp,
+            deltas: self.deltas,
+            value_bits: self.prev_value_bits,
+            values: self.values.clone(),
+            remaining_bits: self.reader.remaining(),
+        }
+    }
+}
+
 impl<'a> Iterator for DecoderIter<'a> {
     type Item = Result<DataPoint, DecodeError>;
 
+    /// Yields missing samples with a NaN value.
     fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_sample()?.map(DataPoint::from))
+    }
+
+    /// Bounded by the points left according to the block's count; exact
+    /// unless points are being skipped or the stream is corrupt.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            return (0, Some(0));
+        }
+        if self.pending_error.is_some() {
+            return (1, Some(1));
+        }
+        let Some(count) = self.count else {
+            return (0, None);
+        };
+        let remaining = usize::try_from(count.saturating_sub(self.position)).ok();
+        if self.skip_before > 0 {
+            (0, remaining)
+        } else {
+            (remaining.unwrap_or(usize::MAX), remaining)
+        }
+    }
+}
+
+impl<'a, B: AsRef<[u8]>> IntoIterator for &'a CompressedBlock<B> {
+    type Item = Result<DataPoint, DecodeError>;
+    type IntoIter = DecoderIter<'a>;
+
+    /// Same as [`Decoder::iter`].
+    fn into_iter(self) -> DecoderIter<'a> {
+        Decoder::iter(self)
+    }
+}
+
+impl DecoderIter<'_> {
+    /// Returns the next sample at or after `skip_before`.
+    pub(crate) fn next_sample(&mut self) -> Option<Result<Sample, DecodeError>> {
+        loop {
+            let result = self.next_point()?;
+            let position = self.position;
+            self.position += 1;
+            match result {
+                Ok(dp) if dp.timestamp < self.skip_before => continue,
+                Ok(dp) if self.gaps.binary_search(&position).is_ok() => {
+                    return Some(Ok(Sample::Missing(dp.timestamp)))
+                }
+                Ok(dp) => return Some(Ok(Sample::Value(dp))),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+
+    fn next_point(&mut self) -> Option<Result<DataPoint, DecodeError>> {
         if self.done {
             return None;
         }
+        if let Some(err) = self.pending_error.take() {
+            self.done = true;
+            return Some(Err(err));
+        }
+        if self.count_terminated && self.count.is_some_and(|count| self.position >= count) {
+            self.done = true;
+            return None;
+        }
+        let result = self.read_point()?;
+        Some(result.map_err(|e| e.at(&self.reader, self.position)))
+    }
 
+    /// Reads the next point from the stream; `next_point` adds the position
+    /// to errors.
+    fn read_point(&mut self) -> Option<Result<DataPoint, DecodeError>> {
         match self.state {
             IterState::Initial => {
                 // Read first data point.
-                let ts = match self.reader.read_bits(64) {
-                    Some(v) => v,
-                    None => {
+                let read = Decoder::read_first(
+                    &mut self.reader,
+                    self.epoch,
+                    self.priors,
+                    self.value_codec,
+                );
+                let first = match read {
+                    Ok(Some(first)) => first,
+                    Ok(None) => {
                         self.done = true;
                         return None; // empty stream
                     }
-                };
-                let val_bits = match self.reader.read_bits(64) {
-                    Some(v) => v,
-                    None => {
+                    Err(e) => {
                         self.done = true;
-                        return Some(Err(DecodeError::UnexpectedEnd));
+                        return Some(Err(e));
                     }
                 };
-                self.prev_timestamp = ts;
-                self.prev_value_bits = val_bits;
-                self.state = IterState::SecondPoint;
-                Some(Ok(DataPoint::new(ts, f64::from_bits(val_bits))))
-            }
-            IterState::SecondPoint | IterState::Subsequent => {
-                let dod = match Decoder::decode_delta_of_delta(&mut self.reader) {
-                    Ok(DodResult::Value(v)) => v,
-                    Ok(DodResult::EndOfStream) => {
+                self.prev_timestamp = first.timestamp;
+                self.deltas = first.deltas;
+                self.prev_value_bits = first.value_bits;
+                self.values = first.values;
+                self.state = IterState::Subsequent;
+                Some(Ok(DataPoint::new(
+                    first.timestamp,
+                    f64::from_bits(first.value_bits),
+                )))
+            }
+            IterState::Subsequent => {
+                let read = self
+                    .timestamp_codec
+                    .read_delta(&mut self.reader, &mut self.deltas);
+                let delta = match read {
+                    Ok(Some(delta)) => delta,
+                    Ok(None) => {
                         self.done = true;
                         return None;
                     }
@@ -269,27 +1577,17 @@ impl<'a> Iterator for DecoderIter<'a> {
                     }
                 };
 
-                match self.state {
-                    IterState::SecondPoint => {
-                        self.prev_delta = dod;
-                        self.state = IterState::Subsequent;
-                    }
-                    _ => {
-                        self.prev_delta += dod;
+                self.prev_timestamp = match next_timestamp(self.prev_timestamp, delta) {
+                    Ok(timestamp) => timestamp,
+                    Err(e) => {
+                        self.done = true;
+                        return Some(Err(e));
                     }
-                }
-                self.prev_timestamp = (self.prev_timestamp as i64 + self.prev_delta) as u64;
+                };
 
-                match Decoder::decode_value(
-                    &mut self.reader,
-                    self.prev_value_bits,
-                    self.prev_leading_zeros,
-                    self.prev_trailing_zeros,
-                ) {
-                    Ok((val_bits, leading, trailing)) => {
+                match self.values.decode(&mut self.reader) {
+                    Ok((val_bits, _)) => {
                         self.prev_value_bits = val_bits;
-                        self.prev_leading_zeros = leading;
-                        self.prev_trailing_zeros = trailing;
                         Some(Ok(DataPoint::new(
                             self.prev_timestamp,
                             f64::from_bits(val_bits),
@@ -305,50 +1603,359 @@ impl<'a> Iterator for DecoderIter<'a> {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::encoder::Encoder;
-
-    #[test]
-    fn test_roundtrip_basic() {
-        let input = vec![
-            DataPoint::new(1609459200, 12.0),
-            DataPoint::new(1609459260, 12.5),
-            DataPoint::new(1609459320, 13.0),
-            DataPoint::new(1609459380, 11.5),
-            DataPoint::new(1609459440, 12.0),
-        ];
+/// Iterator returned by [`Decoder::samples`].
+pub struct Samples<'a> {
+    inner: DecoderIter<'a>,
+}
 
-        let mut enc = Encoder::new();
-        for dp in &input {
-            enc.encode(*dp).unwrap();
-        }
-        enc.finish().unwrap();
-        let block = enc.into_compressed();
+impl Iterator for Samples<'_> {
+    type Item = Result<Sample, DecodeError>;
 
-        let output = Decoder::decode(&block).unwrap();
-        assert_eq!(input, output);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next_sample()
     }
 
-    #[test]
-    fn test_roundtrip_identical_values() {
-        let input: Vec<DataPoint> = (0..100)
-            .map(|i| DataPoint::new(1000 + i * 60, 42.0))
-            .collect();
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
 
-        let mut enc = Encoder::new();
-        for dp in &input {
-            enc.encode(*dp).unwrap();
-        }
-        enc.finish().unwrap();
-        let block = enc.into_compressed();
+/// Iterator returned by [`Decoder::downsample`].
+pub struct Downsample<'a> {
+    inner: DecoderIter<'a>,
+    bucket_width: u64,
+    agg: AggFn,
+    /// Start of the bucket `acc` is collecting.
+    bucket: u64,
+    acc: Accumulator,
+}
 
-        let output = Decoder::decode(&block).unwrap();
-        assert_eq!(input, output);
+impl Downsample<'_> {
+    /// Emits the current bucket, if it holds any points, and starts a fresh one.
+    fn take_bucket(&mut self) -> Option<DataPoint> {
+        let acc = std::mem::replace(&mut self.acc, Accumulator::new());
+        acc.result(self.agg)
+            .map(|value| DataPoint::new(self.bucket, value))
     }
+}
 
-    #[test]
+impl Iterator for Downsample<'_> {
+    type Item = Result<DataPoint, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let dp = match self.inner.next_sample() {
+                Some(Ok(Sample::Value(dp))) => dp,
+                Some(Ok(Sample::Missing(_))) => continue,
+                Some(Err(err)) => return Some(Err(err)),
+                None => return self.take_bucket().map(Ok),
+            };
+            let bucket = dp.timestamp - dp.timestamp % self.bucket_width;
+            let done = if bucket != self.bucket {
+                let done = self.take_bucket();
+                self.bucket = bucket;
+                done
+            } else {
+                None
+            };
+            self.acc.push(dp.value);
+            if let Some(dp) = done {
+                return Some(Ok(dp));
+            }
+        }
+    }
+}
+
+/// Iterator returned by [`Decoder::resample`].
+pub struct Resample<'a> {
+    inner: DecoderIter<'a>,
+    step: u64,
+    interpolation: Interpolation,
+    /// Latest point at or before `time`.
+    before: Option<DataPoint>,
+    /// Point following `before`, once read.
+    after: Option<DataPoint>,
+    /// Timestamp of the next grid point.
+    time: u64,
+    done: bool,
+}
+
+impl Resample<'_> {
+    /// Reads the next point with a value that comes after the last one read.
+    fn next_value(&mut self) -> Option<Result<DataPoint, DecodeError>> {
+        let last = self.after.or(self.before).map(|dp| dp.timestamp);
+        loop {
+            match self.inner.next_sample()? {
+                Ok(Sample::Value(dp)) if last.is_none_or(|last| dp.timestamp > last) => {
+                    return Some(Ok(dp))
+                }
+                Ok(_) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+
+    /// Returns the grid point at `time` with `value` and moves to the next.
+    fn emit(&mut self, value: f64) -> DataPoint {
+        let dp = DataPoint::new(self.time, value);
+        match self.time.checked_add(self.step) {
+            Some(time) => self.time = time,
+            None => self.done = true,
+        }
+        dp
+    }
+}
+
+impl Iterator for Resample<'_> {
+    type Item = Result<DataPoint, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let Some(before) = self.before else {
+                match self.next_value() {
+                    Some(Ok(dp)) => {
+                        match dp.timestamp.div_ceil(self.step).checked_mul(self.step) {
+                            Some(time) => self.time = time,
+                            None => self.done = true,
+                        }
+                        self.before = Some(dp);
+                        continue;
+                    }
+                    Some(Err(err)) => {
+                        self.done = true;
+                        return Some(Err(err));
+                    }
+                    None => break,
+                }
+            };
+            if self.time == before.timestamp {
+                return Some(Ok(self.emit(before.value)));
+            }
+            let after = match self.after {
+                Some(after) => after,
+                None => match self.next_value() {
+                    Some(Ok(dp)) => *self.after.insert(dp),
+                    Some(Err(err)) => {
+                        self.done = true;
+                        return Some(Err(err));
+                    }
+                    None => break,
+                },
+            };
+            if after.timestamp <= self.time {
+                self.before = self.after.take();
+                continue;
+            }
+            let value = match self.interpolation {
+                Interpolation::Previous => before.value,
+                Interpolation::Linear => {
+                    let elapsed = (self.time - before.timestamp) as f64;
+                    let span = (after.timestamp - before.timestamp) as f64;
+                    before.value + (after.value - before.value) * (elapsed / span)
+                }
+            };
+            return Some(Ok(self.emit(value)));
+        }
+        self.done = true;
+        None
+    }
+}
+
+/// Encoding of a timestamp, by delta-of-delta range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DodBucket {
+    /// The first point's timestamp, or any under
+    /// [`TimestampScheme::Raw`], stored in full.
+    Raw,
+    /// Delta-of-delta of zero: 1 bit, or 17 where it opens a run under
+    /// [`TimestampScheme::RunLength`].
+    Zero,
+    /// Delta-of-delta of zero inside a run under
+    /// [`TimestampScheme::RunLength`]: no bits.
+    Run,
+    /// 7-bit delta-of-delta: 9 bits.
+    Bits7,
+    /// 9-bit delta-of-delta: 12 bits.
+    Bits9,
+    /// 12-bit delta-of-delta: 16 bits.
+    Bits12,
+    /// 64-bit delta-of-delta: 68 bits.
+    Bits64,
+}
+
+impl DodBucket {
+    fn for_bits(bits: u32) -> Self {
+        match bits {
+            1 => DodBucket::Zero,
+            9 => DodBucket::Bits7,
+            12 => DodBucket::Bits9,
+            16 => DodBucket::Bits12,
+            _ => DodBucket::Bits64,
+        }
+    }
+}
+
+/// Encoding of a value relative to its predecessor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueEncoding {
+    /// The first point's value, or any under [`ValueScheme::Raw`], stored
+    /// in full.
+    Raw,
+    /// Same value as the previous point: 1 bit. Under
+    /// [`ValueScheme::Chimp`], same as one of the previous 128: 9 bits.
+    Repeat,
+    /// XOR fits the previous leading/trailing zero window.
+    ReusedWindow,
+    /// XOR stored with a new window header.
+    NewWindow,
+}
+
+/// How one point of a block was encoded, as returned by
+/// [`Decoder::inspect`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointEncoding {
+    /// The decoded point.
+    pub point: DataPoint,
+    /// Delta-of-delta of the timestamp; for the second point this is the
+    /// delta minus the interval of the block's [`Priors`] (or the delta
+    /// itself without priors), for the first 0. Under
+    /// [`TimestampScheme::Delta`], the delta minus that interval throughout.
+    pub dod: i64,
+    /// Bucket the delta-of-delta was stored in.
+    pub timestamp_encoding: DodBucket,
+    /// Bits taken by the timestamp.
+    pub timestamp_bits: u32,
+    /// Case the value was stored with; the first value is XORed against
+    /// the typical value of the block's [`Priors`], if any.
+    pub value_encoding: ValueEncoding,
+    /// Bits taken by the value.
+    pub value_bits: u32,
+    /// Leading zeros of the XOR window in effect after the point; 64 under
+    /// [`ValueScheme::Chimp`] and [`ValueScheme::Raw`], which have no such
+    /// window.
+    pub leading_zeros: u8,
+    /// Trailing zeros of the XOR window in effect after the point; 64 under
+    /// [`ValueScheme::Chimp`] and [`ValueScheme::Raw`].
+    pub trailing_zeros: u8,
+}
+
+impl std::fmt::Display for PointEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}: dod {} as {:?} ({} bits), value as {:?} ({} bits, window {}/{})",
+            self.point.timestamp,
+            self.point.value,
+            self.dod,
+            self.timestamp_encoding,
+            self.timestamp_bits,
+            self.value_encoding,
+            self.value_bits,
+            self.leading_zeros,
+            self.trailing_zeros
+        )
+    }
+}
+
+/// Iterator returned by [`Decoder::every_nth`].
+pub struct EveryNth<'a, B = Vec<u8>> {
+    block: &'a CompressedBlock<B>,
+    inner: DecoderIter<'a>,
+    n: u64,
+    /// Index of the point `inner` yields next.
+    position: u64,
+    /// Index of the next point to return.
+    target: u64,
+    done: bool,
+}
+
+impl<B: AsRef<[u8]>> Iterator for EveryNth<'_, B> {
+    type Item = Result<DataPoint, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let index = Decoder::checkpoints(self.block);
+            let before = index.partition_point(|cp| cp.point_index < self.target);
+            if let Some(cp) = before.checked_sub(1).map(|i| &index[i]) {
+                if cp.point_index >= self.position {
+                    self.inner = Decoder::iter_at(self.block, Some(cp));
+                    self.position = cp.point_index.saturating_add(1);
+                }
+            }
+            loop {
+                match self.inner.next_sample() {
+                    Some(Ok(sample)) => {
+                        self.position += 1;
+                        if self.position <= self.target {
+                            continue;
+                        }
+                        self.target = self.target.saturating_add(self.n);
+                        match sample {
+                            Sample::Value(dp) => return Some(Ok(dp)),
+                            // A gap at a sampled position yields nothing.
+                            Sample::Missing(_) => break,
+                        }
+                    }
+                    Some(Err(err)) => {
+                        self.done = true;
+                        return Some(Err(err));
+                    }
+                    None => {
+                        self.done = true;
+                        break;
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitbuffer::BitBuffer;
+    use crate::encoder::{Encoder, EncoderConfig};
+
+    #[test]
+    fn test_roundtrip_basic() {
+        let input = vec![
+            DataPoint::new(1609459200, 12.0),
+            DataPoint::new(1609459260, 12.5),
+            DataPoint::new(1609459320, 13.0),
+            DataPoint::new(1609459380, 11.5),
+            DataPoint::new(1609459440, 12.0),
+        ];
+
+        let mut enc = Encoder::new();
+        for dp in &input {
+            enc.encode(*dp).unwrap();
+        }
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+
+        let output = Decoder::decode(&block).unwrap();
+        assert_eq!(input, output);
+    }
+
+    #[test]
+    fn test_roundtrip_identical_values() {
+        let input: Vec<DataPoint> = (0..100)
+            .map(|i| DataPoint::new(1000 + i * 60, 42.0))
+            .collect();
+
+        let mut enc = Encoder::new();
+        for dp in &input {
+            enc.encode(*dp).unwrap();
+        }
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+
+        let output = Decoder::decode(&block).unwrap();
+        assert_eq!(input, output);
+    }
+
+    #[test]
     fn test_roundtrip_single() {
         let input = vec![DataPoint::new(12345, 99.99)];
         let mut enc = Encoder::new();
@@ -359,6 +1966,696 @@ mod tests {
         assert_eq!(input, output);
     }
 
+    #[test]
+    fn test_checksum_detects_corruption() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            checksum: true,
+            ..Default::default()
+        });
+        for i in 0..20 {
+            enc.encode(DataPoint::new(1000 + i * 60, i as f64 * 0.5)).unwrap();
+        }
+        enc.finish().unwrap();
+        let mut block = enc.into_compressed();
+        assert!(block.checksum.is_some());
+        assert_eq!(Decoder::decode(&block).unwrap().len(), 20);
+
+        block.bytes[10] ^= 0x04;
+        let err = Decoder::decode(&block).unwrap_err();
+        assert!(matches!(err, DecodeError::ChecksumMismatch { .. }));
+        let results: Vec<_> = Decoder::iter(&block).collect();
+        assert_eq!(results, vec![Err(err)]);
+    }
+
+    #[test]
+    fn test_count_mismatch() {
+        let mut enc = Encoder::new();
+        for i in 0..5 {
+            enc.encode(DataPoint::new(1000 + i * 60, 1.0)).unwrap();
+        }
+        enc.finish().unwrap();
+        let mut block = enc.into_compressed();
+        block.count = 6;
+        assert_eq!(
+            Decoder::decode(&block),
+            Err(DecodeError::CountMismatch {
+                expected: 6,
+                actual: 5
+            })
+        );
+    }
+
+    #[test]
+    fn test_validate_matches_encoder_stats() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            checksum: true,
+            ..Default::default()
+        });
+        for i in 0..100u64 {
+            if i % 10 == 3 {
+                enc.encode_gap(i * 60).unwrap();
+            } else {
+                enc.encode(DataPoint::new(i * 60, (i as f64).sin()))
+                    .unwrap();
+            }
+        }
+        enc.finish().unwrap();
+        let mut block = enc.into_compressed();
+        assert_eq!(Decoder::validate(&block).unwrap(), block.stats);
+        assert_eq!(
+            Decoder::validate(&Encoder::new().into_compressed()),
+            Ok(None)
+        );
+
+        block.count = 101;
+        assert_eq!(
+            Decoder::validate(&block),
+            Err(DecodeError::CountMismatch {
+                expected: 101,
+                actual: 100
+            })
+        );
+        block.bytes[10] ^= 0x04;
+        assert!(matches!(
+            Decoder::validate(&block),
+            Err(DecodeError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_lossy_keeps_prefix() {
+        let input: Vec<DataPoint> = (0..50)
+            .map(|i| DataPoint::new(1000 + i * 60, (i as f64).sqrt()))
+            .collect();
+        let mut enc = Encoder::new();
+        for dp in &input {
+            enc.encode(*dp).unwrap();
+        }
+        enc.finish().unwrap();
+        let mut block = enc.into_compressed();
+
+        let (points, err) = Decoder::decode_lossy(&block);
+        assert_eq!(points, input);
+        assert_eq!(err, None);
+
+        block.total_bits /= 2;
+        let (points, err) = Decoder::decode_lossy(&block);
+        assert_eq!(
+            err.as_ref().map(DecodeError::kind),
+            Some(&DecodeError::UnexpectedEnd)
+        );
+        assert!(!points.is_empty() && points.len() < input.len());
+        assert_eq!(points[..], input[..points.len()]);
+    }
+
+    fn raw_block(buf: BitBuffer, count: u64) -> CompressedBlock {
+        CompressedBlock {
+            total_bits: buf.len_bits(),
+            bytes: buf.into_bytes(),
+            count,
+            checksum: None,
+            stats: None,
+            index: Vec::new(),
+            metadata: Default::default(),
+            complete_until: None,
+            priors: None,
+            timestamp_codec: TimestampScheme::DeltaOfDelta,
+            value_codec: ValueScheme::Gorilla,
+            count_terminated: false,
+            epoch: None,
+            quantizer: None,
+            gaps: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_strict_rejects_oversized_xor_window() {
+        let mut buf = BitBuffer::new();
+        buf.write_bits(1000, 64).unwrap();
+        buf.write_bits(1.0f64.to_bits(), 64).unwrap();
+        buf.write_bit(false).unwrap(); // dod == 0
+        buf.write_bits(0b11, 2).unwrap(); // new window
+        buf.write_bits(40, 6).unwrap(); // leading zeros
+        buf.write_bits(39, 6).unwrap(); // 40 meaningful bits
+        buf.write_bits(0, 40).unwrap();
+        let block = raw_block(buf, 2);
+        let expected = DecodeError::InvalidXorWindow {
+            leading: 40,
+            meaningful: 40,
+        };
+        let err = Decoder::decode_strict(&block, 10).unwrap_err();
+        assert_eq!(err.kind(), &expected);
+        assert_eq!(Decoder::decode(&block).unwrap_err(), err);
+    }
+
+    #[test]
+    fn test_timestamp_overflow_is_reported_on_every_path() {
+        let mut buf = BitBuffer::new();
+        buf.write_bits(u64::MAX - 10, 64).unwrap();
+        buf.write_bits(1.0f64.to_bits(), 64).unwrap();
+        buf.write_bits(0b10, 2).unwrap();
+        buf.write_bits(60, 7).unwrap(); // delta = +60
+        buf.write_bit(false).unwrap(); // same value
+        let block = raw_block(buf, 2);
+        // Reported at the second point, after its 128 + 9 bits were read.
+        let overflow = DecodeError::AtPoint {
+            point_index: 1,
+            bit_offset: 137,
+            error: Box::new(DecodeError::TimestampOverflow),
+        };
+        assert_eq!(Decoder::decode_strict(&block, 10).unwrap_err(), overflow);
+        assert_eq!(Decoder::decode(&block).unwrap_err(), overflow);
+        assert_eq!(Decoder::inspect(&block).unwrap_err(), overflow);
+        assert_eq!(Decoder::last(&block).unwrap_err(), overflow);
+        assert_eq!(Decoder::validate(&block).unwrap_err(), overflow);
+        let (points, err) = Decoder::decode_lossy(&block);
+        assert_eq!((points.len(), err), (1, Some(overflow)));
+    }
+
+    #[test]
+    fn test_strict_limits_point_count() {
+        let mut enc = Encoder::new();
+        for i in 0..20 {
+            enc.encode(DataPoint::new(1000 + i * 60, 1.0)).unwrap();
+        }
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+        assert_eq!(Decoder::decode_strict(&block, 20).unwrap().len(), 20);
+        assert_eq!(
+            Decoder::decode_strict(&block, 19),
+            Err(DecodeError::TooManyPoints { limit: 19 })
+        );
+        assert_eq!(
+            Decoder::decode_strict(&block, 0),
+            Err(DecodeError::TooManyPoints { limit: 0 })
+        );
+    }
+
+    #[test]
+    fn test_checksum_disabled_by_default() {
+        let mut enc = Encoder::new();
+        enc.encode(DataPoint::new(1000, 1.0)).unwrap();
+        enc.finish().unwrap();
+        assert_eq!(enc.into_compressed().checksum, None);
+    }
+
+    #[test]
+    fn test_aggregate_over_range() {
+        let mut enc = Encoder::new();
+        for (t, v) in [(10, 4.0), (20, -1.0), (30, 6.0), (40, 3.0), (50, 8.0)] {
+            enc.encode(DataPoint::new(t, v)).unwrap();
+        }
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+
+        let agg = |f, r: std::ops::RangeInclusive<u64>| Decoder::aggregate(&block, f, r).unwrap();
+        assert_eq!(agg(AggFn::Sum, 20..=40), Some(8.0));
+        assert_eq!(agg(AggFn::Mean, 20..=40), Some(8.0 / 3.0));
+        assert_eq!(agg(AggFn::Min, 20..=40), Some(-1.0));
+        assert_eq!(agg(AggFn::Max, 20..=40), Some(6.0));
+        assert_eq!(agg(AggFn::Count, 20..=40), Some(3.0));
+        assert_eq!(agg(AggFn::First, 20..=40), Some(-1.0));
+        assert_eq!(agg(AggFn::Last, 20..=40), Some(3.0));
+        assert_eq!(agg(AggFn::Sum, 60..=90), None);
+        assert_eq!(Decoder::aggregate(&block, AggFn::Count, 20..40).unwrap(), Some(2.0));
+    }
+
+    #[test]
+    fn test_aggregate_scans_past_out_of_order_points() {
+        let mut enc = Encoder::new();
+        for (t, v) in [(100, 1.0), (200, 2.0), (50, 4.0), (300, 8.0)] {
+            enc.encode(DataPoint::new(t, v)).unwrap();
+        }
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+
+        assert_eq!(
+            Decoder::aggregate(&block, AggFn::Sum, 0..=150).unwrap(),
+            Some(5.0)
+        );
+        assert_eq!(
+            Decoder::aggregate(&block, AggFn::Last, 0..=150).unwrap(),
+            Some(4.0)
+        );
+        assert_eq!(Decoder::aggregate(&block, AggFn::Sum, 400..).unwrap(), None);
+    }
+
+    #[test]
+    fn test_aggregate_uses_stats_for_covered_block() {
+        let mut enc = Encoder::new();
+        for (t, v) in [(10, 1.0), (20, 2.0), (30, 3.0)] {
+            enc.encode(DataPoint::new(t, v)).unwrap();
+        }
+        enc.finish().unwrap();
+        let mut block = enc.into_compressed();
+        block.bytes.iter_mut().for_each(|b| *b = 0);
+
+        assert_eq!(Decoder::aggregate(&block, AggFn::Sum, ..).unwrap(), Some(6.0));
+        assert_eq!(Decoder::aggregate(&block, AggFn::Max, 0..=30).unwrap(), Some(3.0));
+        // A partial range has to decode, which exposes the damage.
+        assert_ne!(Decoder::aggregate(&block, AggFn::Sum, 15..), Ok(Some(5.0)));
+    }
+
+    #[test]
+    fn test_downsample_buckets() {
+        let mut enc = Encoder::new();
+        for (t, v) in [(5, 1.0), (9, 3.0), (12, 2.0), (41, 7.0), (44, 5.0)] {
+            enc.encode(DataPoint::new(t, v)).unwrap();
+        }
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+
+        let run = |agg| -> Vec<DataPoint> {
+            Decoder::downsample(&block, 10, agg)
+                .map(Result::unwrap)
+                .collect()
+        };
+        let mean = run(AggFn::Mean);
+        assert_eq!(
+            mean,
+            vec![
+                DataPoint::new(0, 2.0),
+                DataPoint::new(10, 2.0),
+                DataPoint::new(40, 6.0),
+            ]
+        );
+        let last: Vec<f64> = run(AggFn::Last).iter().map(|p| p.value).collect();
+        assert_eq!(last, vec![3.0, 2.0, 5.0]);
+        let min: Vec<f64> = run(AggFn::Min).iter().map(|p| p.value).collect();
+        assert_eq!(min, vec![1.0, 2.0, 5.0]);
+    }
+
+    #[test]
+    fn test_downsample_skips_gaps() {
+        let mut enc = Encoder::new();
+        enc.encode(DataPoint::new(5, 1.0)).unwrap();
+        enc.encode_gap(8).unwrap();
+        enc.encode(DataPoint::new(9, 3.0)).unwrap();
+        enc.encode_gap(25).unwrap();
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+
+        let points: Vec<_> = Decoder::downsample(&block, 10, AggFn::Mean)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(points, [DataPoint::new(0, 2.0)]);
+    }
+
+    #[test]
+    fn test_downsample_reports_corruption() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            checksum: true,
+            ..Default::default()
+        });
+        enc.encode(DataPoint::new(1, 1.0)).unwrap();
+        enc.finish().unwrap();
+        let mut block = enc.into_compressed();
+        block.bytes[0] ^= 1;
+        let mut iter = Decoder::downsample(&block, 60, AggFn::Mean);
+        assert!(matches!(
+            iter.next(),
+            Some(Err(DecodeError::ChecksumMismatch { .. }))
+        ));
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_resample_fills_a_regular_grid() {
+        let mut enc = Encoder::new();
+        for (t, v) in [(5, 0.0), (20, 3.0), (15, 9.0), (100, 20.0), (120, 20.0)] {
+            enc.encode(DataPoint::new(t, v)).unwrap();
+        }
+        enc.encode_gap(130).unwrap();
+        enc.encode(DataPoint::new(150, 50.0)).unwrap();
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+        let resample = |interpolation| -> Vec<(u64, f64)> {
+            Decoder::resample(&block, 10, interpolation)
+                .map(|dp| dp.map(|dp| (dp.timestamp, dp.value)).unwrap())
+                .collect()
+        };
+
+        // The point at 15 goes backwards and is ignored.
+        let linear = resample(Interpolation::Linear);
+        let timestamps: Vec<u64> = linear.iter().map(|&(t, _)| t).collect();
+        assert_eq!(timestamps, (1..=15).map(|i| i * 10).collect::<Vec<_>>());
+        assert_eq!(linear[0], (10, 1.0));
+        assert_eq!(linear[1], (20, 3.0));
+        assert_eq!(linear[5], (60, 11.5));
+        assert_eq!(linear[12], (130, 30.0));
+        assert_eq!(linear[14], (150, 50.0));
+        let previous = resample(Interpolation::Previous);
+        assert_eq!(previous[5], (60, 3.0));
+        assert_eq!(previous[13], (140, 20.0));
+
+        let mut corrupt = block.clone();
+        corrupt.total_bits = 140;
+        let results: Vec<_> = Decoder::resample(&corrupt, 10, Interpolation::Linear).collect();
+        assert!(matches!(results.last(), Some(Err(_))));
+        assert_eq!(
+            Decoder::resample(&block, u64::MAX, Interpolation::Linear).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_iter_from_seeks_with_checkpoints() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            checkpoint_interval: Some(16),
+            ..Default::default()
+        });
+        for i in 0..200u64 {
+            let ts = i * 60 + (i % 7);
+            enc.encode(DataPoint::new(ts, (i as f64).sin())).unwrap();
+        }
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+        let mut plain = block.clone();
+        plain.index.clear();
+
+        let all = Decoder::decode(&block).unwrap();
+        for ts in [0, 1, 960, 961, 5000, 11_940, 11_946, 20_000] {
+            let expected: Vec<_> = all.iter().copied().filter(|dp| dp.timestamp >= ts).collect();
+            let seeked: Vec<_> = Decoder::iter_from(&block, ts).map(Result::unwrap).collect();
+            assert_eq!(seeked, expected, "from {ts}");
+            let scanned: Vec<_> = Decoder::iter_from(&plain, ts).map(Result::unwrap).collect();
+            assert_eq!(scanned, expected, "from {ts} without index");
+        }
+
+        let mut damaged = block.clone();
+        damaged.index.iter_mut().for_each(|cp| cp.bit_offset = u64::MAX);
+        assert!(Decoder::iter_from(&damaged, 5000).next().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_iter_from_with_out_of_order_points() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            checkpoint_interval: Some(2),
+            ..Default::default()
+        });
+        for ts in [100, 200, 600, 300, 350, 360, 700, 800] {
+            enc.encode(DataPoint::new(ts, ts as f64)).unwrap();
+        }
+        let block = enc.finish_into().unwrap();
+        // Points 3 and 5 come after 600, so only 1 and 7 are checkpointed.
+        let indexed: Vec<_> = block.index.iter().map(|cp| cp.point_index).collect();
+        assert_eq!(indexed, [1, 7]);
+        let mut plain = block.clone();
+        plain.index.clear();
+        for ts in [0, 250, 500, 650, 750] {
+            let seeked: Vec<_> = Decoder::iter_from(&block, ts).map(Result::unwrap).collect();
+            let scanned: Vec<_> = Decoder::iter_from(&plain, ts).map(Result::unwrap).collect();
+            assert_eq!(seeked, scanned, "from {ts}");
+        }
+        assert_eq!(
+            Decoder::iter_from(&block, 500).next(),
+            Some(Ok(DataPoint::new(600, 600.0)))
+        );
+    }
+
+    #[test]
+    fn test_gaps_survive_seeking_and_aggregation() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            checkpoint_interval: Some(16),
+            ..Default::default()
+        });
+        for i in 0..200u64 {
+            if i % 10 == 3 {
+                enc.encode_gap(i * 60).unwrap();
+            } else {
+                enc.encode(DataPoint::new(i * 60, 1.0)).unwrap();
+            }
+        }
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+        assert!(!block.index.is_empty());
+
+        let gaps_from = |ts: u64| -> Vec<u64> {
+            let mut iter = Decoder::iter_from(&block, ts);
+            let mut gaps = Vec::new();
+            while let Some(sample) = iter.next_sample() {
+                if let Sample::Missing(timestamp) = sample.unwrap() {
+                    gaps.push(timestamp / 60);
+                }
+            }
+            gaps
+        };
+        assert_eq!(gaps_from(0).len(), 20);
+        assert_eq!(gaps_from(150 * 60), [153, 163, 173, 183, 193]);
+        #[cfg(feature = "rayon")]
+        {
+            let parallel = Decoder::decode_parallel(&block).unwrap();
+            assert!(parallel[183].value.is_nan() && parallel[184].value == 1.0);
+        }
+
+        let agg = |f, r| Decoder::aggregate(&block, f, r).unwrap();
+        assert_eq!(agg(AggFn::Count, 0..12_000), Some(180.0));
+        assert_eq!(agg(AggFn::Sum, 0..600), Some(9.0));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_decode_parallel_matches_serial() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            checkpoint_interval: Some(1000),
+            checksum: true,
+            ..Default::default()
+        });
+        for i in 0..25_500u64 {
+            let ts = 1_600_000_000 + i * 15 + (i % 3);
+            enc.encode(DataPoint::new(ts, (i as f64 / 10.0).cos())).unwrap();
+        }
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+        assert_eq!(block.index.len(), 25);
+        assert_eq!(
+            Decoder::decode_parallel(&block).unwrap(),
+            Decoder::decode(&block).unwrap()
+        );
+
+        let mut wrong_count = block.clone();
+        wrong_count.count += 1;
+        assert!(matches!(
+            Decoder::decode_parallel(&wrong_count),
+            Err(DecodeError::CountMismatch { .. })
+        ));
+        let mut unordered = block.clone();
+        unordered.index.swap(3, 4);
+        assert!(Decoder::decode_parallel(&unordered).is_err());
+        let mut overflowing = block.clone();
+        overflowing.index.last_mut().unwrap().point_index = u64::MAX;
+        assert_eq!(
+            Decoder::decode_parallel(&overflowing),
+            Err(DecodeError::MalformedHeader("checkpoint beyond stream"))
+        );
+    }
+
+    #[test]
+    fn test_tampered_index_and_gaps_are_rejected() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            checkpoint_interval: Some(8),
+            ..Default::default()
+        });
+        for i in 0..64u64 {
+            if i % 10 == 3 {
+                enc.encode_gap(i * 60).unwrap();
+            } else {
+                enc.encode(DataPoint::new(i * 60, i as f64)).unwrap();
+            }
+        }
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+
+        let mut unordered = block.clone();
+        unordered.index.swap(2, 3);
+        let err = DecodeError::MalformedHeader("checkpoints out of order");
+        assert_eq!(Decoder::decode(&unordered), Err(err.clone()));
+        assert_eq!(Decoder::iter_from(&unordered, 1800).next(), Some(Err(err)));
+
+        let mut gaps = block.clone();
+        gaps.gaps.reverse();
+        let err = DecodeError::MalformedHeader("gaps out of order");
+        assert_eq!(Decoder::decode(&gaps), Err(err.clone()));
+        assert_eq!(Decoder::decode_samples(&gaps), Err(err.clone()));
+        gaps.gaps = vec![64];
+        assert_eq!(Decoder::validate(&gaps), Err(err));
+    }
+
+    #[test]
+    fn test_last_and_nth() {
+        let mut enc = Encoder::new();
+        for i in 0..10 {
+            enc.encode(DataPoint::new(100 + i * 7, i as f64 * 1.5)).unwrap();
+        }
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+        assert_eq!(Decoder::last(&block).unwrap(), Some(DataPoint::new(163, 13.5)));
+        assert_eq!(Decoder::nth(&block, 0).unwrap(), Some(DataPoint::new(100, 0.0)));
+        assert_eq!(Decoder::nth(&block, 10).unwrap(), None);
+
+        let empty = Encoder::new().to_compressed();
+        assert_eq!(Decoder::last(&empty).unwrap(), None);
+
+        let mut damaged = block.clone();
+        damaged.count = 11;
+        assert_eq!(
+            Decoder::last(&damaged),
+            Err(DecodeError::CountMismatch {
+                expected: 11,
+                actual: 10
+            })
+        );
+        damaged.total_bits /= 2;
+        let err = Decoder::last(&damaged).unwrap_err();
+        assert_eq!(err.kind(), &DecodeError::UnexpectedEnd);
+    }
+
+    #[test]
+    fn test_inspect_accounts_for_every_bit() {
+        let input = [
+            DataPoint::new(1000, 1.0),
+            DataPoint::new(1060, 1.5),
+            DataPoint::new(1120, 1.25),
+            DataPoint::new(1300, 1.75),
+            DataPoint::new(2300, 1.75),
+            DataPoint::new(1_000_000, 1.5),
+        ];
+        let mut enc = Encoder::new();
+        for dp in input {
+            enc.encode(dp).unwrap();
+        }
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+        let records = Decoder::inspect(&block).unwrap();
+
+        let points: Vec<_> = records.iter().map(|r| r.point).collect();
+        assert_eq!(points, input);
+        let buckets: Vec<_> = records.iter().map(|r| r.timestamp_encoding).collect();
+        assert_eq!(
+            buckets,
+            [
+                DodBucket::Raw,
+                DodBucket::Bits7,
+                DodBucket::Zero,
+                DodBucket::Bits9,
+                DodBucket::Bits12,
+                DodBucket::Bits64
+            ]
+        );
+        assert_eq!(records[4].value_encoding, ValueEncoding::Repeat);
+        let bits: u32 = records
+            .iter()
+            .map(|r| r.timestamp_bits + r.value_bits)
+            .sum();
+        assert_eq!(bits as usize + 68, block.total_bits);
+        assert!(records[3]
+            .to_string()
+            .starts_with("1300 1.75: dod 120 as Bits9"));
+    }
+
+    #[test]
+    fn test_every_nth_matches_step_by() {
+        for interval in [None, Some(1), Some(7), Some(64)] {
+            let mut enc = Encoder::with_config(EncoderConfig {
+                checkpoint_interval: interval,
+                ..Default::default()
+            });
+            for i in 0..500u64 {
+                enc.encode(DataPoint::new(i * 10 + i % 3, (i as f64).sin()))
+                    .unwrap();
+            }
+            enc.finish().unwrap();
+            let block = enc.into_compressed();
+            let all = Decoder::decode(&block).unwrap();
+            for n in [1, 2, 7, 33, 499, 500, 1000] {
+                let sampled: Vec<_> = Decoder::every_nth(&block, n)
+                    .collect::<Result<_, _>>()
+                    .unwrap();
+                let expected: Vec<_> = all.iter().copied().step_by(n).collect();
+                assert_eq!(sampled, expected, "interval {interval:?}, n {n}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_every_nth_ignores_index_of_unindexed_codec() {
+        let mut indexed = Encoder::with_config(EncoderConfig {
+            checkpoint_interval: Some(4),
+            ..Default::default()
+        });
+        let mut enc = Encoder::with_config(EncoderConfig {
+            value_codec: ValueScheme::Chimp,
+            ..Default::default()
+        });
+        for i in 0..40u64 {
+            let dp = DataPoint::new(i * 10, (i as f64).sqrt());
+            indexed.encode(dp).unwrap();
+            enc.encode(dp).unwrap();
+        }
+        indexed.finish().unwrap();
+        enc.finish().unwrap();
+        let mut block = enc.into_compressed();
+        // Checkpoints only describe Gorilla XOR streams.
+        block.index = indexed.into_compressed().index;
+        assert!(!block.index.is_empty());
+
+        let sampled: Vec<_> = Decoder::every_nth(&block, 6)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let expected: Vec<_> = Decoder::decode(&block)
+            .unwrap()
+            .into_iter()
+            .step_by(6)
+            .collect();
+        assert_eq!(sampled, expected);
+    }
+
+    #[test]
+    fn test_every_nth_skips_gaps() {
+        for interval in [None, Some(4)] {
+            let mut enc = Encoder::with_config(EncoderConfig {
+                checkpoint_interval: interval,
+                ..Default::default()
+            });
+            for i in 0..40u64 {
+                if i % 5 == 0 {
+                    enc.encode_gap(i * 10).unwrap();
+                } else {
+                    enc.encode(DataPoint::new(i * 10, i as f64)).unwrap();
+                }
+            }
+            enc.finish().unwrap();
+            let block = enc.into_compressed();
+            let sampled: Vec<_> = Decoder::every_nth(&block, 3)
+                .collect::<Result<_, _>>()
+                .unwrap();
+            let expected: Vec<_> = (0..40u64)
+                .step_by(3)
+                .filter(|i| i % 5 != 0)
+                .map(|i| DataPoint::new(i * 10, i as f64))
+                .collect();
+            assert_eq!(sampled, expected, "interval {interval:?}");
+        }
+    }
+
+    #[test]
+    fn test_every_nth_reports_errors_once() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            checksum: true,
+            checkpoint_interval: Some(4),
+            ..Default::default()
+        });
+        for i in 0..40u64 {
+            enc.encode(DataPoint::new(i, i as f64)).unwrap();
+        }
+        enc.finish().unwrap();
+        let mut block = enc.into_compressed();
+        block.bytes[10] ^= 0xff;
+        let results: Vec<_> = Decoder::every_nth(&block, 10).collect();
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0], Err(DecodeError::ChecksumMismatch { .. })));
+    }
+
     #[test]
     fn test_iterator() {
         let input = vec![
@@ -379,4 +2676,91 @@ mod tests {
             .collect();
         assert_eq!(input, output);
     }
+
+    #[test]
+    fn test_block_into_iter_with_size_hint() {
+        let mut enc = Encoder::new();
+        for i in 0..10 {
+            enc.encode(DataPoint::new(i * 60, i as f64)).unwrap();
+        }
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+
+        let mut iter = (&block).into_iter();
+        assert_eq!(iter.size_hint(), (10, Some(10)));
+        iter.next();
+        assert_eq!(iter.size_hint(), (9, Some(9)));
+        assert_eq!(iter.by_ref().count(), 9);
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+
+        let mut timestamps = Vec::new();
+        for dp in &block {
+            timestamps.push(dp.unwrap().timestamp);
+        }
+        assert_eq!(timestamps, (0..10).map(|i| i * 60).collect::<Vec<_>>());
+        assert_eq!(Decoder::iter_from(&block, 300).size_hint(), (0, Some(10)));
+    }
+
+    #[test]
+    fn test_iter_raw_borrows_and_stops_at_truncation() {
+        let mut enc = Encoder::new();
+        for i in 0..50 {
+            enc.encode(DataPoint::new(i * 60, i as f64)).unwrap();
+        }
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+
+        let points: Vec<_> = Decoder::iter_raw(block.bytes(), block.total_bits())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(points, Decoder::decode(&block).unwrap());
+        assert_eq!(Decoder::iter_raw(block.bytes(), 0).size_hint(), (0, None));
+
+        let frame = &block.bytes()[..block.bytes().len() / 2];
+        let results: Vec<_> = Decoder::iter_raw(frame, block.total_bits()).collect();
+        assert!(results.len() < 50);
+        let err = results.last().unwrap().as_ref().unwrap_err();
+        assert_eq!(err.kind(), &DecodeError::UnexpectedEnd);
+        assert!(
+            matches!(err, DecodeError::AtPoint { point_index, .. } if *point_index == results.len() as u64 - 1)
+        );
+    }
+
+    #[test]
+    fn test_decode_untrusted_survives_corruption() {
+        let codecs = [
+            (TimestampScheme::DeltaOfDelta, ValueScheme::Gorilla),
+            (TimestampScheme::RunLength, ValueScheme::Chimp),
+            (TimestampScheme::Delta, ValueScheme::Raw),
+        ];
+        for (timestamp_codec, value_codec) in codecs {
+            let mut enc = Encoder::with_config(EncoderConfig {
+                timestamp_codec,
+                value_codec,
+                checkpoint_interval: Some(8),
+                priors: Some(Priors {
+                    interval: 60,
+                    value: 1.0,
+                }),
+                ..Default::default()
+            });
+            for i in 0..40 {
+                enc.encode(DataPoint::new(i * 60 + i % 3, (i % 5) as f64))
+                    .unwrap();
+            }
+            enc.encode_gap(2_460).unwrap();
+            enc.finish().unwrap();
+            let bytes = enc.into_compressed().to_bytes();
+            assert_eq!(Decoder::decode_untrusted(&bytes).unwrap().len(), 41);
+
+            for len in 0..bytes.len() {
+                assert!(Decoder::decode_untrusted(&bytes[..len]).is_err());
+            }
+            for bit in 0..bytes.len() * 8 {
+                let mut corrupt = bytes.clone();
+                corrupt[bit / 8] ^= 1 << (bit % 8);
+                let _ = Decoder::decode_untrusted(&corrupt);
+            }
+        }
+    }
 }
@@ -0,0 +1,483 @@
+//! A [`SeriesMap`] made durable by a write-ahead log and checkpoints.
+//!
+//! A store directory holds one generation of state: `checkpoint-{gen}.seg`,
+//! a segment with every sealed block at checkpoint time (absent for
+//! generation 0), and `wal-{gen}.log`, the points appended since. A
+//! checkpoint writes the next generation's segment, then its log seeded
+//! with the points of every open block, and only then removes the previous
+//! generation, so a crash at any step leaves one complete generation.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::bitbuffer::BufferFull;
+use crate::decoder::Decoder;
+use crate::encoder::DataPoint;
+use crate::map::SeriesMap;
+use crate::segment::{Segment, SegmentWriter};
+use crate::series::{AppendError, SeriesConfig, SeriesKey};
+use crate::wal::{encode_record, Wal, WalReader};
+
+/// Number of replayed log records between two progress reports.
+const PROGRESS_INTERVAL: u64 = 4096;
+
+/// Error returned by [`DurableMap`] operations.
+#[derive(Debug)]
+pub enum DurableError {
+    /// Reading or writing the checkpoint or log failed, or they are corrupt.
+    Io(io::Error),
+    /// A point could not be appended, either live or while replaying the log.
+    Append(AppendError),
+}
+
+impl std::fmt::Display for DurableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DurableError::Io(err) => write!(f, "durable store I/O error: {err}"),
+            DurableError::Append(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for DurableError {}
+
+impl From<io::Error> for DurableError {
+    fn from(err: io::Error) -> Self {
+        DurableError::Io(err)
+    }
+}
+
+impl From<AppendError> for DurableError {
+    fn from(err: AppendError) -> Self {
+        DurableError::Append(err)
+    }
+}
+
+/// Progress of restoring a store, reported while its checkpoint and log are
+/// read back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayProgress {
+    /// Index of the store being restored, as passed to [`open_shards`]; `0`
+    /// for [`DurableMap::open`].
+    pub shard: usize,
+    /// Bytes of checkpoint and log processed so far.
+    pub bytes_read: u64,
+    /// Total bytes of checkpoint and log to process.
+    pub bytes_total: u64,
+    /// Sealed blocks restored from the checkpoint so far.
+    pub blocks: u64,
+    /// Points replayed from the log so far.
+    pub points: u64,
+}
+
+/// A [`SeriesMap`] whose appends are logged to a write-ahead log, restored
+/// on open from the latest checkpoint plus the log.
+#[derive(Debug)]
+pub struct DurableMap<K: SeriesKey> {
+    dir: PathBuf,
+    generation: u64,
+    map: SeriesMap<K>,
+    wal: Wal,
+}
+
+impl<K: SeriesKey> DurableMap<K> {
+    /// Opens the store in `dir`, creating it if needed, and restores its
+    /// series, calling `progress` as the checkpoint and log are replayed.
+    ///
+    /// A record cut short at the end of the log (a write interrupted by a
+    /// crash) is discarded.
+    pub fn open(
+        dir: impl AsRef<Path>,
+        config: SeriesConfig,
+        mut progress: impl FnMut(ReplayProgress),
+    ) -> Result<Self, DurableError> {
+        Self::open_shard(dir.as_ref(), config, 0, &mut progress)
+    }
+
+    fn open_shard(
+        dir: &Path,
+        config: SeriesConfig,
+        shard: usize,
+        progress: &mut dyn FnMut(ReplayProgress),
+    ) -> Result<Self, DurableError> {
+        fs::create_dir_all(dir)?;
+        let generation = list_generations(dir, "wal-", ".log")?
+            .into_iter()
+            .max()
+            .unwrap_or(0);
+        let checkpoint_path = dir.join(checkpoint_name(generation));
+        let wal_path = dir.join(wal_name(generation));
+
+        let checkpoint = match Segment::open(&checkpoint_path) {
+            Ok(segment) => Some(segment),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+        let log = match fs::read(&wal_path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let checkpoint_len = match &checkpoint {
+            Some(_) => fs::metadata(&checkpoint_path)?.len(),
+            None => 0,
+        };
+        let mut state = ReplayProgress {
+            shard,
+            bytes_read: 0,
+            bytes_total: checkpoint_len + log.len() as u64,
+            blocks: 0,
+            points: 0,
+        };
+
+        let mut map = SeriesMap::new(config);
+        if let Some(segment) = &checkpoint {
+            for entry in segment.entries() {
+                let key = K::from_key_bytes(&entry.key).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "invalid series key in checkpoint",
+                    )
+                })?;
+                let block = segment.read_block(entry)?;
+                map.get_or_insert(key)
+                    .push_block(block, entry.start, entry.end);
+                state.blocks += 1;
+                progress(state);
+            }
+        }
+        state.bytes_read = checkpoint_len;
+
+        let mut reader = WalReader::new(&log);
+        while let Some(record) = reader.next() {
+            let record = record?;
+            let key = K::from_key_bytes(&record.key).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "invalid series key in WAL")
+            })?;
+            map.append(key, record.point)?;
+            state.points += 1;
+            if state.points.is_multiple_of(PROGRESS_INTERVAL) {
+                state.bytes_read = checkpoint_len + reader.position() as u64;
+                progress(state);
+            }
+        }
+        let valid = reader.position() as u64;
+        if valid < log.len() as u64 {
+            fs::OpenOptions::new()
+                .write(true)
+                .open(&wal_path)?
+                .set_len(valid)?;
+        }
+        state.bytes_read = state.bytes_total;
+        progress(state);
+
+        remove_other_generations(dir, generation)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            generation,
+            map,
+            wal: Wal::open(wal_path)?,
+        })
+    }
+
+    /// Appends a data point to its series and logs it. Points rejected by
+    /// the series are not logged.
+    ///
+    /// The record is buffered; call [`sync`](DurableMap::sync) to make it
+    /// durable.
+    pub fn append(&mut self, key: K, dp: DataPoint) -> Result<(), DurableError> {
+        let key_bytes = key.to_key_bytes();
+        self.map.append(key, dp)?;
+        self.wal.append(&key_bytes, dp)?;
+        Ok(())
+    }
+
+    /// Seals the open block of the series for `key`, if it exists.
+    pub fn seal(&mut self, key: &K) -> Result<(), BufferFull> {
+        match self.map.get_mut(key) {
+            Some(series) => series.seal(),
+            None => Ok(()),
+        }
+    }
+
+    /// Flushes and syncs the log.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.wal.sync()
+    }
+
+    /// Writes a checkpoint of all sealed blocks and starts a new log holding
+    /// only the points of the open blocks, bounding the time of the next
+    /// restore.
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        let next = self.generation + 1;
+
+        let mut segment = SegmentWriter::new(self.dir.join(checkpoint_name(next)));
+        let mut log = Vec::new();
+        for (key, series) in self.map.iter() {
+            let key_bytes = key.to_key_bytes();
+            for (i, block) in series.blocks().iter().enumerate() {
+                let (start, end) = series.block_time_range(i).unwrap();
+                segment.add(&key_bytes, start, end, block);
+            }
+            if series.open_encoder().count() > 0 {
+                let open = Decoder::decode(&series.open_encoder().to_compressed())
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                for dp in open {
+                    log.extend_from_slice(&encode_record(&key_bytes, dp));
+                }
+            }
+        }
+        segment.finish()?;
+
+        let wal_path = self.dir.join(wal_name(next));
+        let tmp = wal_path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(&log)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &wal_path)?;
+
+        self.wal = Wal::open(wal_path)?;
+        self.generation = next;
+        remove_other_generations(&self.dir, next)
+    }
+
+    /// Returns the restored and live series.
+    pub fn map(&self) -> &SeriesMap<K> {
+        &self.map
+    }
+
+    /// Returns the current checkpoint generation.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+/// Opens one store per directory in parallel, one thread per store, for
+/// sharded deployments where restart time is dominated by log replay.
+///
+/// `progress` is called from the replay threads; [`ReplayProgress::shard`]
+/// is the directory's index in `dirs`. The first error aborts the open.
+pub fn open_shards<K: SeriesKey + Send>(
+    dirs: &[PathBuf],
+    config: &SeriesConfig,
+    progress: impl Fn(ReplayProgress) + Sync,
+) -> Result<Vec<DurableMap<K>>, DurableError> {
+    let progress = &progress;
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = dirs
+            .iter()
+            .enumerate()
+            .map(|(shard, dir)| {
+                let config = config.clone();
+                scope
+                    .spawn(move || DurableMap::open_shard(dir, config, shard, &mut |p| progress(p)))
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("replay thread panicked"))
+            .collect()
+    })
+}
+
+fn checkpoint_name(generation: u64) -> String {
+    format!("checkpoint-{generation:08}.seg")
+}
+
+fn wal_name(generation: u64) -> String {
+    format!("wal-{generation:08}.log")
+}
+
+fn list_generations(dir: &Path, prefix: &str, suffix: &str) -> io::Result<Vec<u64>> {
+    let mut generations = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let generation = name
+            .to_str()
+            .and_then(|n| n.strip_prefix(prefix))
+            .and_then(|n| n.strip_suffix(suffix))
+            .and_then(|n| n.parse::<u64>().ok());
+        generations.extend(generation);
+    }
+    Ok(generations)
+}
+
+/// Removes checkpoints and logs of every generation other than `keep`,
+/// including leftovers of an interrupted checkpoint.
+fn remove_other_generations(dir: &Path, keep: u64) -> io::Result<()> {
+    for (prefix, suffix) in [
+        ("checkpoint-", ".seg"),
+        ("checkpoint-", ".tmp"),
+        ("wal-", ".log"),
+        ("wal-", ".tmp"),
+    ] {
+        for generation in list_generations(dir, prefix, suffix)? {
+            if generation != keep || suffix == ".tmp" {
+                fs::remove_file(dir.join(format!("{prefix}{generation:08}{suffix}")))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment::tests::temp_dir;
+    use std::sync::Mutex;
+
+    fn open(dir: &Path) -> DurableMap<String> {
+        DurableMap::open(dir, SeriesConfig::default(), |_| {}).unwrap()
+    }
+
+    fn points(map: &DurableMap<String>, key: &str) -> Vec<DataPoint> {
+        let series = map.map().get(&key.to_string()).unwrap();
+        let mut out: Vec<_> = series
+            .blocks()
+            .iter()
+            .flat_map(|b| Decoder::decode(b).unwrap())
+            .collect();
+        if series.open_encoder().count() > 0 {
+            out.extend(Decoder::decode(&series.open_encoder().to_compressed()).unwrap());
+        }
+        out
+    }
+
+    #[test]
+    fn test_restore_from_log() {
+        let dir = temp_dir("durable-log");
+        let mut map = open(&dir);
+        for i in 0..100 {
+            map.append("cpu".into(), DataPoint::new(i * 10, i as f64))
+                .unwrap();
+        }
+        map.sync().unwrap();
+        drop(map);
+
+        let map = open(&dir);
+        assert_eq!(points(&map, "cpu").len(), 100);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_restore_from_checkpoint_and_log() {
+        let dir = temp_dir("durable-checkpoint");
+        let mut map = open(&dir);
+        for i in 0..50 {
+            map.append("cpu".into(), DataPoint::new(i * 10, i as f64))
+                .unwrap();
+        }
+        map.seal(&"cpu".to_string()).unwrap();
+        for i in 50..70 {
+            map.append("cpu".into(), DataPoint::new(i * 10, i as f64))
+                .unwrap();
+        }
+        map.checkpoint().unwrap();
+        for i in 70..80 {
+            map.append("cpu".into(), DataPoint::new(i * 10, i as f64))
+                .unwrap();
+        }
+        map.sync().unwrap();
+        let expected = points(&map, "cpu");
+        drop(map);
+
+        let mut reports = Vec::new();
+        let map =
+            DurableMap::<String>::open(&dir, SeriesConfig::default(), |p| reports.push(p)).unwrap();
+        assert_eq!(map.generation(), 1);
+        assert_eq!(map.map().get(&"cpu".to_string()).unwrap().blocks().len(), 1);
+        assert_eq!(points(&map, "cpu"), expected);
+
+        let last = reports.last().unwrap();
+        assert_eq!((last.blocks, last.points), (1, 30));
+        assert_eq!(last.bytes_read, last.bytes_total);
+        let names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(names.len(), 2, "old generation removed: {names:?}");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_torn_log_tail_is_discarded() {
+        let dir = temp_dir("durable-torn");
+        let mut map = open(&dir);
+        map.append("cpu".into(), DataPoint::new(1, 1.0)).unwrap();
+        map.append("cpu".into(), DataPoint::new(2, 2.0)).unwrap();
+        map.sync().unwrap();
+        drop(map);
+
+        let wal = dir.join(wal_name(0));
+        let len = fs::metadata(&wal).unwrap().len();
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&wal)
+            .unwrap()
+            .set_len(len - 5)
+            .unwrap();
+
+        let mut map = open(&dir);
+        assert_eq!(points(&map, "cpu"), [DataPoint::new(1, 1.0)]);
+        map.append("cpu".into(), DataPoint::new(3, 3.0)).unwrap();
+        map.sync().unwrap();
+        drop(map);
+        assert_eq!(points(&open(&dir), "cpu").len(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_log_length_is_not_truncated() {
+        let dir = temp_dir("durable-corrupt");
+        let mut map = open(&dir);
+        for i in 0..5 {
+            map.append("cpu".into(), DataPoint::new(i, i as f64))
+                .unwrap();
+        }
+        map.sync().unwrap();
+        drop(map);
+
+        let wal = dir.join(wal_name(0));
+        let mut data = fs::read(&wal).unwrap();
+        let record = data.len() / 5;
+        data[record..record + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&wal, &data).unwrap();
+
+        let err = DurableMap::<String>::open(&dir, SeriesConfig::default(), |_| {}).unwrap_err();
+        assert!(matches!(err, DurableError::Io(e) if e.kind() == io::ErrorKind::InvalidData));
+        assert_eq!(fs::read(&wal).unwrap(), data);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_open_shards_in_parallel() {
+        let dirs: Vec<_> = (0..3)
+            .map(|i| temp_dir(&format!("durable-shard{i}")))
+            .collect();
+        for (i, dir) in dirs.iter().enumerate() {
+            let mut map = open(dir);
+            for t in 0..=i as u64 {
+                map.append(format!("s{i}"), DataPoint::new(t, 0.0)).unwrap();
+            }
+            map.sync().unwrap();
+        }
+
+        let finished = Mutex::new(Vec::new());
+        let maps = open_shards::<String>(&dirs, &SeriesConfig::default(), |p| {
+            if p.bytes_read == p.bytes_total {
+                finished.lock().unwrap().push(p.shard);
+            }
+        })
+        .unwrap();
+        for (i, map) in maps.iter().enumerate() {
+            assert_eq!(map.map().get(&format!("s{i}")).unwrap().len(), i as u64 + 1);
+        }
+        let mut finished = finished.into_inner().unwrap();
+        finished.sort();
+        assert_eq!(finished, [0, 1, 2]);
+        for dir in dirs {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
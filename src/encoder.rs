@@ -1,7 +1,350 @@
-use crate::bitbuffer::{BitBuffer, BufferFull};
+use crate::bitbuffer::{BitBuffer, BitReader, BitWrite, BufferFull};
+use crate::checksum::crc32c;
+use crate::codec::{
+    DeltaState, TimestampCodec, TimestampScheme, ValueCodec, ValueScheme, Values, ValuesMark,
+};
+use crate::decoder::{DecodeError, Decoder, DodBucket, Sample, ValueEncoding};
+use crate::lossy::{Precision, QuantizeSpec};
+use crate::metadata::BlockMetadata;
+use crate::snapshot::{read_stats, write_stats, EncoderState, RestoreError, StateReader};
 
-/// A single time-series data point: a Unix timestamp (seconds) and an f64 value.
+/// Error returned by [`Encoder::encode`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncodeError {
+    /// The buffer's byte limit would be exceeded.
+    BufferFull,
+    /// The point's timestamp is earlier than the previously encoded one and
+    /// the encoder is configured with [`OutOfOrderPolicy::Reject`].
+    OutOfOrder {
+        /// Timestamp of the last encoded point.
+        previous: u64,
+        /// Timestamp of the rejected point.
+        timestamp: u64,
+    },
+    /// The point's timestamp equals the previously encoded one and the
+    /// encoder is configured with [`DuplicatePolicy::Reject`], or with
+    /// [`DuplicatePolicy::KeepLast`] but the previous point came from a
+    /// resumed block.
+    DuplicateTimestamp {
+        /// The repeated timestamp.
+        timestamp: u64,
+    },
+    /// The point's timestamp is too far from the previous one: their
+    /// distance, or under the compressing [`TimestampScheme`]s its
+    /// difference from the previous distance, does not fit in an `i64`.
+    TimestampOutOfRange {
+        /// Timestamp of the last encoded point.
+        previous: u64,
+        /// Timestamp of the rejected point.
+        timestamp: u64,
+    },
+    /// The block's first timestamp is earlier than
+    /// [`EncoderConfig::epoch`].
+    BeforeEpoch {
+        /// The configured epoch.
+        epoch: u64,
+        /// Timestamp of the rejected point.
+        timestamp: u64,
+    },
+    /// The encoder was already finished. Use [`Encoder::resume`] to append
+    /// to its block.
+    Finished,
+    /// The timestamp and value columns passed to
+    /// [`Encoder::encode_columns`] have different lengths.
+    LengthMismatch {
+        /// Length of the timestamp column.
+        timestamps: usize,
+        /// Length of the value column.
+        values: usize,
+    },
+}
+
+impl std::fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncodeError::BufferFull => write!(f, "{}", BufferFull),
+            EncodeError::OutOfOrder {
+                previous,
+                timestamp,
+            } => write!(
+                f,
+                "timestamp {timestamp} is earlier than previous timestamp {previous}"
+            ),
+            EncodeError::DuplicateTimestamp { timestamp } => {
+                write!(f, "timestamp {timestamp} was already encoded")
+            }
+            EncodeError::TimestampOutOfRange {
+                previous,
+                timestamp,
+            } => write!(
+                f,
+                "timestamp {timestamp} is too far from previous timestamp {previous} to encode"
+            ),
+            EncodeError::BeforeEpoch { epoch, timestamp } => {
+                write!(f, "first timestamp {timestamp} is before epoch {epoch}")
+            }
+            EncodeError::Finished => write!(f, "cannot encode after finish()"),
+            EncodeError::LengthMismatch { timestamps, values } => write!(
+                f,
+                "column lengths differ: {timestamps} timestamps, {values} values"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+impl From<BufferFull> for EncodeError {
+    fn from(_: BufferFull) -> Self {
+        EncodeError::BufferFull
+    }
+}
+
+/// Error returned by [`CompressedBlock::merge`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeError {
+    /// One of the blocks could not be decoded.
+    Decode(DecodeError),
+    /// A point of the second block could not be appended to the first, e.g.
+    /// with [`EncodeError::TimestampOutOfRange`].
+    Encode(EncodeError),
+    /// The second block does not start after the first one ends.
+    Overlap {
+        /// Last timestamp of the first block.
+        a_end: u64,
+        /// First timestamp of the second block.
+        b_start: u64,
+    },
+    /// The blocks were quantized differently, so `b`'s values would not be
+    /// stored as they were encoded.
+    QuantizerMismatch {
+        /// Quantizer of the first block.
+        a: Option<QuantizeSpec>,
+        /// Quantizer of the second block.
+        b: Option<QuantizeSpec>,
+    },
+}
+
+impl std::fmt::Display for MergeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeError::Decode(err) => write!(f, "{err}"),
+            MergeError::Encode(err) => write!(f, "{err}"),
+            MergeError::Overlap { a_end, b_start } => write!(
+                f,
+                "second block starts at {b_start}, not after first block end {a_end}"
+            ),
+            MergeError::QuantizerMismatch { a, b } => {
+                write!(f, "blocks are quantized differently: {a:?} and {b:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+impl From<DecodeError> for MergeError {
+    fn from(err: DecodeError) -> Self {
+        MergeError::Decode(err)
+    }
+}
+
+impl From<EncodeError> for MergeError {
+    fn from(err: EncodeError) -> Self {
+        MergeError::Encode(err)
+    }
+}
+
+/// Error returned by [`CompressedBlock::from_parts`] for parts that do not
+/// form a decodable block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidBlock {
+    /// The byte length is not `ceil(total_bits / 8)`.
+    LengthMismatch {
+        /// Length of the bytes.
+        len: usize,
+        /// Claimed number of valid bits.
+        total_bits: usize,
+    },
+    /// The stream is too short to hold the claimed number of points.
+    CountExceedsBits {
+        /// Claimed number of points.
+        count: u64,
+        /// Number of valid bits.
+        total_bits: usize,
+    },
+}
+
+impl std::fmt::Display for InvalidBlock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvalidBlock::LengthMismatch { len, total_bits } => {
+                write!(f, "{len} bytes cannot hold exactly {total_bits} bits")
+            }
+            InvalidBlock::CountExceedsBits { count, total_bits } => {
+                write!(f, "{total_bits} bits cannot hold {count} points")
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvalidBlock {}
+
+/// What the encoder does with a point whose timestamp is earlier than the
+/// previous one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutOfOrderPolicy {
+    /// Return `Err(EncodeError::OutOfOrder)` and leave the stream untouched.
+    Reject,
+    /// Silently discard the point.
+    Drop,
+    /// Encode the point with a negative delta. Decoding reproduces the input
+    /// order exactly, but the stream is no longer monotonic.
+    #[default]
+    AllowNegativeDelta,
+}
+
+/// What the encoder does with a point whose timestamp equals the previous one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// Encode every point, including repeats.
+    #[default]
+    Allow,
+    /// Keep the first point for a timestamp and skip later ones.
+    KeepFirst,
+    /// Replace the previously encoded point with the new one. The last
+    /// point of a block reopened with [`Encoder::resume`] cannot be
+    /// replaced; a repeat of it returns
+    /// `Err(EncodeError::DuplicateTimestamp)`.
+    KeepLast,
+    /// Return `Err(EncodeError::DuplicateTimestamp)` and leave the stream
+    /// untouched.
+    Reject,
+}
+
+/// Configuration for an [`Encoder`].
+///
+/// # Example
+/// ```
+/// use gorilla::{DataPoint, EncodeError, Encoder, EncoderConfig, OutOfOrderPolicy};
+///
+/// let mut encoder = Encoder::with_config(EncoderConfig {
+///     on_out_of_order: OutOfOrderPolicy::Reject,
+///     ..Default::default()
+/// });
+/// encoder.encode(DataPoint::new(1609459260, 1.0)).unwrap();
+/// let err = encoder.encode(DataPoint::new(1609459200, 2.0)).unwrap_err();
+/// assert!(matches!(err, EncodeError::OutOfOrder { .. }));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EncoderConfig {
+    /// Maximum size of the compressed buffer in bytes (`None` = unlimited).
+    pub max_bytes: Option<usize>,
+    /// Handling of points whose timestamp goes backwards.
+    pub on_out_of_order: OutOfOrderPolicy,
+    /// Handling of points whose timestamp repeats the previous one.
+    pub on_duplicate: DuplicatePolicy,
+    /// Compute a CRC32C checksum of the stream in `finish()`. The decoder
+    /// verifies it and reports `DecodeError::ChecksumMismatch` on corruption.
+    pub checksum: bool,
+    /// Record a [`Checkpoint`] every this many points, so that
+    /// [`Decoder::iter_from`] can start decoding near a timestamp instead of
+    /// at the beginning of the block (`None` = no index). A point earlier
+    /// than one before it gets no checkpoint, so every checkpoint is at
+    /// least as late as all points before it.
+    pub checkpoint_interval: Option<u32>,
+    /// Metadata attached to every block this encoder produces.
+    pub metadata: BlockMetadata,
+    /// Expected interval and value the first points are encoded against,
+    /// so that short blocks do not pay full width for them (`None` = start
+    /// from scratch). Recorded in every block, as decoding needs them.
+    pub priors: Option<Priors>,
+    /// Compression scheme for timestamps, recorded in every block.
+    pub timestamp_codec: TimestampScheme,
+    /// Compression scheme for values, recorded in every block.
+    pub value_codec: ValueScheme,
+    /// Lossy mode: trailing mantissa bits of every value are zeroed as far
+    /// as this bound allows before the value is encoded, see
+    /// [`lossy`](crate::lossy) (`None` = lossless).
+    pub precision: Option<Precision>,
+    /// Lossy mode: values are rounded to the grid of this spec before
+    /// `precision` is applied, see [`Encoder::with_quantizer`] (`None` =
+    /// lossless). Recorded in every block.
+    pub quantizer: Option<QuantizeSpec>,
+    /// Re-encode the stream raw in `finish()` if storing every point in 16
+    /// bytes would be smaller than compressing it. The rewritten stream has
+    /// no end-of-stream marker, so it cannot be read back from
+    /// [`Encoder::buffer`] with [`Decoder::decode_raw`]; read it through its
+    /// [`CompressedBlock`].
+    pub raw_fallback: bool,
+    /// End the stream in `finish()` without the 68-bit end-of-stream
+    /// marker, saving 9 bytes per block. Decoders then stop after
+    /// [`CompressedBlock::count`] points, which must be known: such streams
+    /// cannot be decoded with [`Decoder::decode_raw`].
+    pub count_terminated: bool,
+    /// Epoch the first timestamp is stored relative to, e.g. the start of
+    /// the hour a block covers under per-series chunking: the offset takes a
+    /// varint of 8 bits per 7 instead of 64 bits (`None` = store it in
+    /// full). Recorded in every block, see [`CompressedBlock::epoch`].
+    pub epoch: Option<u64>,
+    /// Report-by-exception filter: points close to the last stored one are
+    /// dropped, see [`DeadBand`] (`None` = store every point).
+    pub dead_band: Option<DeadBand>,
+}
+
+/// Dead-band filter of [`EncoderConfig::dead_band`], as used by SCADA
+/// historians to store slowly changing signals by exception.
+///
+/// A point is dropped if its value differs from that of the last stored
+/// point by less than `threshold` and it comes less than `max_interval`
+/// after it. The most recent dropped point is stored by
+/// [`finish`](Encoder::finish), so the block covers every point received:
+/// carrying each stored value forward to the next stored point
+/// reconstructs the series within `threshold`.
+///
+/// ```
+/// use gorilla::{DataPoint, DeadBand, Decoder, Encoder, EncoderConfig};
+///
+/// let dead_band = DeadBand { threshold: 0.5, max_interval: 600 };
+/// let mut encoder = Encoder::with_config(EncoderConfig {
+///     dead_band: Some(dead_band),
+///     ..Default::default()
+/// });
+/// for (t, value) in [(0, 20.0), (60, 20.2), (120, 20.4), (180, 21.0), (240, 21.1)] {
+///     encoder.encode(DataPoint::new(t, value)).unwrap();
+/// }
+/// encoder.finish().unwrap();
+///
+/// let stored = Decoder::decode(&encoder.into_compressed()).unwrap();
+/// assert_eq!(stored, [(0, 20.0).into(), (180, 21.0).into(), (240, 21.1).into()]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeadBand {
+    /// Smallest change of value that is always stored.
+    pub threshold: f64,
+    /// Longest time after a stored point before the next point is stored
+    /// whatever its value.
+    pub max_interval: u64,
+}
+
+/// Known statistics of a series that seed the encoder's state, see
+/// [`Encoder::with_priors`].
 #[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Priors {
+    /// Expected distance between consecutive timestamps. The second point's
+    /// delta is encoded as its difference from this.
+    pub interval: u64,
+    /// Typical value. The first point's value is XOR-encoded against it
+    /// instead of being stored in full.
+    pub value: f64,
+}
+
+/// A single time-series data point: a Unix timestamp (seconds) and an f64 value.
+///
+/// Points compare as floats do, so a NaN point equals no point, and order by
+/// timestamp first, then value. Wrap them in [`ExactPoint`] for the total
+/// order, `Eq` and `Hash` that `BTreeMap`s, `HashSet`s and `sort` need.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub struct DataPoint {
     pub timestamp: u64,
     pub value: f64,
@@ -14,6 +357,73 @@ impl DataPoint {
     }
 }
 
+impl From<(u64, f64)> for DataPoint {
+    fn from((timestamp, value): (u64, f64)) -> Self {
+        DataPoint::new(timestamp, value)
+    }
+}
+
+impl From<DataPoint> for (u64, f64) {
+    fn from(dp: DataPoint) -> Self {
+        (dp.timestamp, dp.value)
+    }
+}
+
+/// A [`DataPoint`] compared bit for bit: equal to another only if their
+/// timestamps and value bits match, so NaNs equal themselves and `0.0`
+/// differs from `-0.0`. Ordered by timestamp, then by
+/// [`f64::total_cmp`] of the values.
+///
+/// ```
+/// use std::collections::BTreeSet;
+/// use gorilla::{DataPoint, ExactPoint};
+///
+/// let points: BTreeSet<_> = [(120, 1.0), (60, f64::NAN), (60, f64::NAN)]
+///     .into_iter()
+///     .map(|p| ExactPoint(DataPoint::from(p)))
+///     .collect();
+/// assert_eq!(points.len(), 2);
+/// assert_eq!(points.first().unwrap().0.timestamp, 60);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct ExactPoint(pub DataPoint);
+
+impl PartialEq for ExactPoint {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for ExactPoint {}
+
+impl PartialOrd for ExactPoint {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ExactPoint {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0
+            .timestamp
+            .cmp(&other.0.timestamp)
+            .then_with(|| self.0.value.total_cmp(&other.0.value))
+    }
+}
+
+impl std::hash::Hash for ExactPoint {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.timestamp.hash(state);
+        self.0.value.to_bits().hash(state);
+    }
+}
+
+impl From<DataPoint> for ExactPoint {
+    fn from(dp: DataPoint) -> Self {
+        ExactPoint(dp)
+    }
+}
+
 /// The Gorilla compressor (encoder).
 ///
 /// Implements the compression scheme from Facebook's Gorilla paper:
@@ -32,99 +442,238 @@ impl DataPoint {
 ///
 /// let compressed = encoder.into_compressed();
 /// ```
-pub struct Encoder {
-    buf: BitBuffer,
+///
+/// The encoder writes into a growable [`BitBuffer`] by default. Any other
+/// [`BitWrite`] storage can be supplied through [`Encoder::with_buffer`],
+/// e.g. a [`FixedBitBuffer`](crate::bitbuffer::FixedBitBuffer) over a stack
+/// array for allocation-free encoding on embedded targets.
+#[derive(Debug, Clone)]
+pub struct Encoder<W = BitBuffer> {
+    buf: W,
     /// Number of data points encoded so far.
     count: u64,
+    /// Timestamp of the first data point.
+    first_timestamp: u64,
+    /// Statistics of the points encoded so far.
+    stats: Option<BlockStats>,
     /// Previous timestamp.
     prev_timestamp: u64,
-    /// Previous delta between timestamps.
-    prev_delta: i64,
+    /// State of the timestamp codec, including the delta the next
+    /// timestamp is encoded against: the previous delta under
+    /// [`TimestampScheme::DeltaOfDelta`].
+    deltas: DeltaState,
     /// Previous value as raw bits.
     prev_value_bits: u64,
-    /// Number of leading zeros in the previous XOR result.
-    prev_leading_zeros: u8,
-    /// Number of trailing zeros in the previous XOR result.
-    prev_trailing_zeros: u8,
+    /// State of the value codec.
+    values: Values,
     /// Whether `finish()` has been called.
     finished: bool,
+    config: EncoderConfig,
+    /// State before the most recent point, kept for `DuplicatePolicy::KeepLast`.
+    rollback: Option<Rollback>,
+    /// CRC32C of the finished stream, if enabled in the config.
+    checksum: Option<u32>,
+    /// Checkpoints recorded so far.
+    index: Vec<Checkpoint>,
+    /// Indices of the points written by `encode_gap`.
+    gaps: Vec<u64>,
+    /// Bytes already returned by `drain_completed_bytes`.
+    drained: usize,
+    /// Most recent point dropped by the dead band, stored by `finish()`.
+    held: Option<DataPoint>,
+}
+
+/// Encoder state captured before a point is written, used to undo it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Rollback {
+    len_bits: usize,
+    pub(crate) count: u64,
+    prev_timestamp: u64,
+    deltas: DeltaState,
+    prev_value_bits: u64,
+    values: ValuesMark,
+    stats: Option<BlockStats>,
+    pub(crate) index_len: usize,
+    pub(crate) gaps_len: usize,
+}
+
+impl Rollback {
+    /// Appends the state for [`EncoderState::to_bytes`].
+    pub(crate) fn write_state(&self, out: &mut Vec<u8>) {
+        for word in [
+            self.len_bits as u64,
+            self.count,
+            self.prev_timestamp,
+            self.prev_value_bits,
+            self.index_len as u64,
+            self.gaps_len as u64,
+        ] {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        self.deltas.write_state(out);
+        self.values.write_state(out);
+        match self.stats {
+            Some(stats) => {
+                out.push(1);
+                write_stats(out, stats);
+            }
+            None => out.push(0),
+        }
+    }
+
+    /// Reads a state written by [`write_state`](Rollback::write_state) for
+    /// a stream under `scheme` of `len_bits` bits.
+    pub(crate) fn read_state(
+        reader: &mut StateReader<'_>,
+        scheme: ValueScheme,
+        len_bits: usize,
+    ) -> Result<Self, DecodeError> {
+        let mut word = || -> Result<usize, DecodeError> {
+            usize::try_from(reader.u64()?)
+                .map_err(|_| DecodeError::MalformedHeader("rollback out of range"))
+        };
+        let (rollback_bits, count) = (word()?, word()? as u64);
+        let (prev_timestamp, prev_value_bits) = (word()? as u64, word()? as u64);
+        let (index_len, gaps_len) = (word()?, word()?);
+        if rollback_bits > len_bits {
+            return Err(DecodeError::MalformedHeader("rollback beyond stream"));
+        }
+        Ok(Rollback {
+            len_bits: rollback_bits,
+            count,
+            prev_timestamp,
+            deltas: DeltaState::read_state(reader, len_bits)?,
+            prev_value_bits,
+            values: ValuesMark::read_state(reader, scheme)?,
+            stats: match reader.u8()? {
+                0 => None,
+                _ => Some(read_stats(reader)?),
+            },
+            index_len,
+            gaps_len,
+        })
+    }
 }
 
 impl Encoder {
     /// Creates a new `Encoder` with a default buffer.
     pub fn new() -> Self {
-        Self {
-            buf: BitBuffer::with_capacity(128),
-            count: 0,
-            prev_timestamp: 0,
-            prev_delta: 0,
-            prev_value_bits: 0,
-            prev_leading_zeros: 64,
-            prev_trailing_zeros: 64,
-            finished: false,
-        }
+        Self::with_config(EncoderConfig::default())
     }
 
     /// Creates a new `Encoder` whose internal buffer will not grow beyond
     /// `max_bytes` bytes. Once the limit is reached, `encode()` will return
-    /// `Err(BufferFull)`.
+    /// `Err(EncodeError::BufferFull)`.
     pub fn with_limit(max_bytes: usize) -> Self {
-        Self {
-            buf: BitBuffer::with_limit(max_bytes),
-            count: 0,
-            prev_timestamp: 0,
-            prev_delta: 0,
-            prev_value_bits: 0,
-            prev_leading_zeros: 64,
-            prev_trailing_zeros: 64,
-            finished: false,
-        }
+        Self::with_config(EncoderConfig {
+            max_bytes: Some(max_bytes),
+            ..Default::default()
+        })
     }
 
-    /// Encodes a data point into the compressed stream.
-    ///
-    /// Data points should be appended in strictly increasing timestamp order.
-    ///
-    /// Returns `Err(BufferFull)` if the buffer's byte limit would be exceeded.
-    /// On error the encoder may be in a partially-written state; use
-    /// `into_compressed()` to recover the data encoded so far.
-    pub fn encode(&mut self, dp: DataPoint) -> Result<(), BufferFull> {
-        assert!(!self.finished, "cannot encode after finish()");
-
-        if self.count == 0 {
-            self.encode_first(dp)?;
-        } else if self.count == 1 {
-            self.encode_second(dp)?;
-        } else {
-            self.encode_subsequent(dp)?;
-        }
+    /// Creates a new `Encoder` whose buffer is pre-sized for about `points`
+    /// points of a typical series, so that encoding them does not keep
+    /// reallocating it. More points are still accepted.
+    pub fn with_capacity_hint(points: usize) -> Self {
+        let mut encoder = Self::new();
+        let bits = points
+            .saturating_mul(HINT_BITS_PER_POINT)
+            .saturating_add(128 + END_MARKER_BITS);
+        encoder.buf.reserve_bits(bits);
+        encoder
+    }
 
-        self.count += 1;
-        Ok(())
+    /// Creates a new `Encoder` with the given configuration.
+    pub fn with_config(config: EncoderConfig) -> Self {
+        let buf = match config.max_bytes {
+            Some(max_bytes) => BitBuffer::with_limit(max_bytes),
+            None => BitBuffer::with_capacity(128),
+        };
+        Self::with_buffer(buf, config)
     }
 
-    /// Writes the end-of-stream marker. Must be called after all data points
-    /// have been encoded.
+    /// Creates an encoder seeded with known statistics of the series: the
+    /// second point's timestamp is delta-of-delta encoded against
+    /// `expected_interval` and the first value is XORed against
+    /// `typical_value`. This saves most of the first points' cost in
+    /// blocks of only tens of points. The priors are stored in the block.
     ///
-    /// Returns `Err(BufferFull)` if the buffer cannot fit the marker.
-    pub fn finish(&mut self) -> Result<(), BufferFull> {
-        if self.finished {
-            return Ok(());
-        }
-        self.buf.write_bits(0b1111, 4)?;
-        self.buf.write_bits(0xFFFF_FFFF_FFFF_FFFF, 64)?;
-        self.finished = true;
-        Ok(())
+    /// ```
+    /// use gorilla::{DataPoint, Decoder, Encoder};
+    ///
+    /// let encode = |mut encoder: Encoder| {
+    ///     for i in 0..20u64 {
+    ///         encoder.encode(DataPoint::new(1609459200 + i * 60, 21.5)).unwrap();
+    ///     }
+    ///     encoder.finish().unwrap();
+    ///     encoder.into_compressed()
+    /// };
+    /// let cold = encode(Encoder::new());
+    /// let warm = encode(Encoder::with_priors(60, 21.5));
+    /// assert_eq!(cold.total_bits() - warm.total_bits(), 64 - 1 + 9 - 1);
+    /// assert_eq!(Decoder::decode(&warm).unwrap(), Decoder::decode(&cold).unwrap());
+    /// ```
+    pub fn with_priors(expected_interval: u64, typical_value: f64) -> Self {
+        Self::with_config(EncoderConfig {
+            priors: Some(Priors {
+                interval: expected_interval,
+                value: typical_value,
+            }),
+            ..Default::default()
+        })
     }
 
-    /// Returns a reference to the underlying `BitBuffer`.
-    pub fn buffer(&self) -> &BitBuffer {
-        &self.buf
+    /// Creates an encoder that rounds every value to the grid of `spec`
+    /// before encoding it, trading accuracy for space on noisy analog
+    /// sensors: readings that stay within the grid's step repeat exactly
+    /// and cost one bit each. The spec is stored in the block, so readers
+    /// know how far decoded values may be from the input.
+    ///
+    /// ```
+    /// use gorilla::lossy::QuantizeSpec;
+    /// use gorilla::{CompressedBlock, DataPoint, Decoder, Encoder};
+    ///
+    /// let mut encoder = Encoder::with_quantizer(QuantizeSpec::AbsError(0.05));
+    /// for (i, value) in [21.52, 21.49, 21.55, 21.61].into_iter().enumerate() {
+    ///     encoder.encode(DataPoint::new(i as u64 * 60, value)).unwrap();
+    /// }
+    /// encoder.finish().unwrap();
+    /// let block = CompressedBlock::from_bytes(&encoder.into_compressed().to_bytes()).unwrap();
+    ///
+    /// assert_eq!(block.quantizer, Some(QuantizeSpec::AbsError(0.05)));
+    /// let values: Vec<f64> = Decoder::decode(&block).unwrap().iter().map(|p| p.value).collect();
+    /// assert_eq!(values, [21.5, 21.5, 21.5625, 21.625]);
+    /// ```
+    pub fn with_quantizer(spec: QuantizeSpec) -> Self {
+        Self::with_config(EncoderConfig {
+            quantizer: Some(spec),
+            ..Default::default()
+        })
     }
 
-    /// Consumes the encoder and returns the compressed `BitBuffer`.
-    pub fn into_buffer(self) -> BitBuffer {
-        self.buf
+    /// Finishes the stream, as [`finish`](Encoder::finish), and returns the
+    /// block, consuming the encoder so that encoding into it afterwards is a
+    /// compile-time error rather than [`EncodeError::Finished`].
+    ///
+    /// ```
+    /// use gorilla::{DataPoint, Decoder, Encoder};
+    ///
+    /// let mut encoder = Encoder::new();
+    /// encoder.encode(DataPoint::new(1609459200, 1.5)).unwrap();
+    /// let block = encoder.finish_into().unwrap();
+    /// assert_eq!(Decoder::decode(&block).unwrap(), [DataPoint::new(1609459200, 1.5)]);
+    /// ```
+    ///
+    /// ```compile_fail
+    /// use gorilla::{DataPoint, Encoder};
+    ///
+    /// let mut encoder = Encoder::new();
+    /// let block = encoder.finish_into().unwrap();
+    /// encoder.encode(DataPoint::new(1609459200, 1.5)).unwrap();
+    /// ```
+    pub fn finish_into(mut self) -> Result<CompressedBlock, BufferFull> {
+        self.finish()?;
+        Ok(self.into_compressed())
     }
 
     /// Returns the compressed data as `(bytes, total_bits)`.
@@ -133,200 +682,3950 @@ impl Encoder {
             total_bits: self.buf.len_bits(),
             bytes: self.buf.into_bytes(),
             count: self.count,
+            checksum: self.checksum,
+            stats: self.stats,
+            index: self.index,
+            metadata: self.config.metadata,
+            complete_until: None,
+            priors: self.config.priors,
+            timestamp_codec: self.config.timestamp_codec,
+            value_codec: self.config.value_codec,
+            count_terminated: self.config.count_terminated,
+            epoch: self.config.epoch,
+            quantizer: self.config.quantizer,
+            gaps: self.gaps,
         }
     }
 
-    /// Returns the number of data points encoded so far.
-    pub fn count(&self) -> u64 {
-        self.count
-    }
-
-    // ── internal helpers ───────────────────────────────────────────────
-
-    fn encode_first(&mut self, dp: DataPoint) -> Result<(), BufferFull> {
-        self.buf.write_bits(dp.timestamp, 64)?;
-        let bits = dp.value.to_bits();
-        self.buf.write_bits(bits, 64)?;
+    /// Reopens a finished block for appending, so that further points
+    /// continue its stream.
+    ///
+    /// The block is walked once to recover the encoder state at its end
+    /// (the last timestamp, delta and XOR window) and its statistics; its
+    /// bits are then copied up to the end-of-stream marker without being
+    /// re-encoded. `config` applies to the points appended afterwards,
+    /// except that the block's [`Priors`], codecs, epoch,
+    /// [`count_terminated`](CompressedBlock::count_terminated) and
+    /// quantizer replace those of `config`.
+    pub fn resume<B: AsRef<[u8]>>(
+        block: &CompressedBlock<B>,
+        config: EncoderConfig,
+    ) -> Result<Self, DecodeError> {
+        let config = EncoderConfig {
+            priors: block.priors,
+            timestamp_codec: block.timestamp_codec,
+            value_codec: block.value_codec,
+            count_terminated: block.count_terminated,
+            epoch: block.epoch,
+            quantizer: block.quantizer,
+            ..config
+        };
+        if block.count == 0 {
+            return Ok(Encoder::with_config(config));
+        }
+        let mut iter = Decoder::iter(block);
+        let mut count = 0;
+        let mut first_timestamp = 0;
+        let mut stats = None;
+        while let Some(result) = iter.next_sample() {
+            let sample = result?;
+            if count == 0 {
+                first_timestamp = sample.timestamp();
+            }
+            stats = Some(match sample {
+                Sample::Value(dp) => extend_stats(stats, dp),
+                Sample::Missing(timestamp) => extend_gap_stats(stats, timestamp),
+            });
+            count += 1;
+        }
+        if count != block.count {
+            return Err(DecodeError::CountMismatch {
+                expected: block.count,
+                actual: count,
+            });
+        }
 
-        self.prev_timestamp = dp.timestamp;
-        self.prev_value_bits = bits;
-        Ok(())
+        let mut encoder = Encoder::with_config(config);
+        let state = iter.state();
+        let marker_bits = match block.timestamp_codec {
+            TimestampScheme::Raw => 0,
+            _ if block.count_terminated => 0,
+            _ => END_MARKER_BITS,
+        };
+        let stream_bits = block.total_bits - state.remaining_bits - marker_bits;
+        let mut buf = BitBuffer::from_raw(block.bytes().to_vec(), block.total_bits);
+        buf.truncate(stream_bits);
+        buf.set_limit(encoder.config.max_bytes);
+        encoder.buf = buf;
+        encoder.count = count;
+        encoder.first_timestamp = first_timestamp;
+        encoder.stats = stats;
+        encoder.index = block.index.clone();
+        encoder.gaps = block.gaps.clone();
+        encoder.prev_timestamp = state.timestamp;
+        encoder.deltas = state.deltas;
+        encoder.prev_value_bits = state.value_bits;
+        encoder.values = state.values;
+        encoder.drained = encoder.stable_bits() / 8;
+        Ok(encoder)
     }
 
-    fn encode_second(&mut self, dp: DataPoint) -> Result<(), BufferFull> {
-        let delta = dp.timestamp as i64 - self.prev_timestamp as i64;
-        self.encode_delta_of_delta(delta)?;
-
-        self.encode_value(dp.value)?;
+    /// Returns where the bits of the points encoded so far went, for tuning
+    /// sampling intervals or comparing against the paper's 1.37 bytes per
+    /// point. The stream is walked on every call.
+    ///
+    /// ```
+    /// use gorilla::{DataPoint, Encoder};
+    ///
+    /// let mut encoder = Encoder::new();
+    /// for i in 0..100u64 {
+    ///     encoder.encode(DataPoint::new(i * 60, (i / 10) as f64)).unwrap();
+    /// }
+    /// let stats = encoder.stats();
+    /// assert_eq!(stats.dod_buckets, [98, 1, 0, 0, 0]);
+    /// assert_eq!(stats.repeated_values, 90);
+    /// assert!(stats.bytes_per_point() < 1.37);
+    /// ```
+    pub fn stats(&self) -> CompressionStats {
+        if self.count == 0 {
+            return CompressionStats::default();
+        }
+        CompressionStats::of_block(&self.to_compressed()).expect("an encoder's own stream decodes")
+    }
 
-        self.prev_delta = delta;
-        self.prev_timestamp = dp.timestamp;
-        Ok(())
+    /// Returns a finished copy of everything encoded so far, leaving this
+    /// encoder open for further points. The copy ignores the byte limit, so
+    /// it always has room for the end-of-stream marker.
+    pub fn to_compressed(&self) -> CompressedBlock {
+        let mut copy = self.clone();
+        copy.buf.set_limit(None);
+        copy.finish()
+            .expect("an unlimited buffer always fits the end-of-stream marker");
+        copy.into_compressed()
     }
+}
 
-    fn encode_subsequent(&mut self, dp: DataPoint) -> Result<(), BufferFull> {
-        let delta = dp.timestamp as i64 - self.prev_timestamp as i64;
-        let dod = delta - self.prev_delta;
-        self.encode_delta_of_delta(dod)?;
+impl<W: BitWrite> Encoder<W> {
+    /// Creates a new `Encoder` writing into `buf`. [`EncoderConfig::max_bytes`]
+    /// is not applied to the supplied storage; its own capacity is the limit.
+    ///
+    /// ```
+    /// use gorilla::bitbuffer::{BitWrite, FixedBitBuffer};
+    /// use gorilla::{DataPoint, Decoder, Encoder};
+    ///
+    /// let mut encoder = Encoder::with_buffer(FixedBitBuffer::new([0u8; 64]), Default::default());
+    /// encoder.encode(DataPoint::new(1609459200, 21.5)).unwrap();
+    /// encoder.encode(DataPoint::new(1609459260, 21.5)).unwrap();
+    /// encoder.finish().unwrap();
+    ///
+    /// let buf = encoder.buffer();
+    /// let points = Decoder::decode_raw(buf.as_bytes(), buf.len_bits()).unwrap();
+    /// assert_eq!(points.len(), 2);
+    /// ```
+    pub fn with_buffer(buf: W, config: EncoderConfig) -> Self {
+        Self {
+            buf,
+            count: 0,
+            first_timestamp: 0,
+            stats: None,
+            prev_timestamp: 0,
+            deltas: DeltaState::default(),
+            prev_value_bits: 0,
+            values: Values::new(config.value_codec),
+            finished: false,
+            rollback: None,
+            checksum: None,
+            index: Vec::new(),
+            gaps: Vec::new(),
+            drained: 0,
+            held: None,
+            config,
+        }
+    }
 
-        self.encode_value(dp.value)?;
+    /// Returns the configuration this encoder was created with.
+    pub fn config(&self) -> &EncoderConfig {
+        &self.config
+    }
 
-        self.prev_delta = delta;
-        self.prev_timestamp = dp.timestamp;
-        Ok(())
+    /// Encodes a data point into the compressed stream.
+    ///
+    /// Data points should be appended in strictly increasing timestamp order;
+    /// points that go backwards or repeat a timestamp are handled according
+    /// to [`EncoderConfig::on_out_of_order`] and [`EncoderConfig::on_duplicate`].
+    ///
+    /// Returns `Err(EncodeError::BufferFull)` if the buffer's byte limit would
+    /// be exceeded. The stream then still ends with the previous point, so
+    /// [`to_compressed`](Encoder::to_compressed) and
+    /// [`stats`](Encoder::stats) cover the data encoded so far.
+    ///
+    /// Returns `Err(EncodeError::Finished)`, encoding nothing, once
+    /// [`finish`](Encoder::finish) has been called.
+    pub fn encode(&mut self, dp: DataPoint) -> Result<(), EncodeError> {
+        self.encode_sample(dp, false)
     }
 
-    /// Encodes a delta-of-delta value using the Gorilla variable-length scheme:
-    ///
-    /// | dod == 0       | `0`                            | 1 bit   |
-    /// | [-63, 64]      | `10` + 7-bit value             | 9 bits  |
-    /// | [-255, 256]    | `110` + 9-bit value            | 12 bits |
-    /// | [-2047, 2048]  | `1110` + 12-bit value          | 16 bits |
-    /// | otherwise      | `1111` + 64-bit value          | 68 bits |
-    fn encode_delta_of_delta(&mut self, dod: i64) -> Result<(), BufferFull> {
-        if dod == 0 {
-            self.buf.write_bit(false)?;
-        } else if dod >= -63 && dod <= 64 {
-            self.buf.write_bits(0b10, 2)?;
-            self.buf.write_bits((dod as u64) & 0x7F, 7)?;
-        } else if dod >= -255 && dod <= 256 {
-            self.buf.write_bits(0b110, 3)?;
-            self.buf.write_bits((dod as u64) & 0x1FF, 9)?;
-        } else if dod >= -2047 && dod <= 2048 {
-            self.buf.write_bits(0b1110, 4)?;
-            self.buf.write_bits((dod as u64) & 0xFFF, 12)?;
-        } else {
-            self.buf.write_bits(0b1111, 4)?;
-            self.buf.write_bits(dod as u64, 64)?;
+    /// Returns an upper bound on the bits encoding `dp` next would add to
+    /// the stream. It is exact except under [`ValueScheme::Chimp`], whose
+    /// values are counted at their longest. Ordering and duplicate policies
+    /// are not applied: the bound is for `dp` appended after the last point.
+    pub fn estimate_size(&self, dp: DataPoint) -> usize {
+        let value = self.lossy_value(dp.value);
+        if self.count == 0 {
+            let value_bits = match self.config.priors {
+                Some(priors) => {
+                    let mut values = Values::new(self.config.value_codec);
+                    values.seed(priors.value.to_bits());
+                    values.max_bits(value.to_bits())
+                }
+                None => 64,
+            };
+            return self.first_timestamp_bits(dp.timestamp) + value_bits;
         }
-        Ok(())
+        let delta = dp.timestamp.wrapping_sub(self.prev_timestamp) as i64;
+        let timestamp_bits = self.config.timestamp_codec.delta_bits(delta, &self.deltas);
+        timestamp_bits + self.values.max_bits(value.to_bits())
     }
 
-    /// XOR-based value compression:
+    /// Returns `true` if `dp` is certain to fit in the buffer with room
+    /// left for the end-of-stream marker, so that the block can still be
+    /// finished after encoding it. Encoders over an unbounded buffer always
+    /// have room; a finished one never does.
     ///
-    /// 1. XOR with previous value.
-    /// 2. If XOR == 0: write single `0` bit.
-    /// 3. Else:
-    ///    a. Write `1`.  
-    ///    b. If leading/trailing zeros fit within previous window:
-    ///       write `0` + meaningful bits.  
-    ///    c. Else: write `1` + 6-bit leading zeros + 6-bit meaningful length + meaningful bits.
-    fn encode_value(&mut self, value: f64) -> Result<(), BufferFull> {
-        let bits = value.to_bits();
-        let xor = bits ^ self.prev_value_bits;
+    /// Checking before each point lets a writer rotate blocks before
+    /// [`encode`](Encoder::encode) fails with `BufferFull` halfway through
+    /// a point:
+    ///
+    /// ```
+    /// use gorilla::{DataPoint, Decoder, Encoder};
+    ///
+    /// let mut blocks = Vec::new();
+    /// let mut encoder = Encoder::with_limit(32);
+    /// for i in 0..100u64 {
+    ///     let dp = DataPoint::new(1609459200 + i * 60, (i * i) as f64);
+    ///     if !encoder.would_fit(dp) {
+    ///         encoder.finish().unwrap();
+    ///         blocks.push(std::mem::replace(&mut encoder, Encoder::with_limit(32)));
+    ///     }
+    ///     encoder.encode(dp).unwrap();
+    /// }
+    /// encoder.finish().unwrap();
+    /// blocks.push(encoder);
+    ///
+    /// let decoded: usize = blocks
+    ///     .into_iter()
+    ///     .map(|encoder| Decoder::decode(&encoder.into_compressed()).unwrap().len())
+    ///     .sum();
+    /// assert_eq!(decoded, 100);
+    /// ```
+    pub fn would_fit(&self, dp: DataPoint) -> bool {
+        if self.finished {
+            return false;
+        }
+        let marker = match self.config.timestamp_codec {
+            TimestampScheme::Raw => 0,
+            _ if self.config.count_terminated => 0,
+            _ => END_MARKER_BITS,
+        };
+        let needed = self.buf.len_bits() + self.estimate_size(dp) + marker;
+        self.buf
+            .capacity_bits()
+            .is_none_or(|capacity| needed <= capacity)
+    }
 
-        if xor == 0 {
-            self.buf.write_bit(false)?;
-        } else {
-            self.buf.write_bit(true)?; // '1' — value changed
+    /// Records that the sample at `timestamp` is missing, e.g. because a
+    /// scrape failed, so that decoding tells it apart from a NaN value.
+    ///
+    /// The point is stored with the previous value, which costs a single
+    /// bit under [`ValueScheme::Gorilla`] and [`ValueScheme::Chimp`], and
+    /// its position is listed in [`CompressedBlock::gaps`]. It counts as a
+    /// point for ordering policies and [`count`](Encoder::count) but not
+    /// for the value statistics. [`Decoder::samples`] yields it as
+    /// [`Sample::Missing`]; [`Decoder::decode`] and [`Decoder::iter`] yield
+    /// it with a NaN value.
+    ///
+    /// ```
+    /// use gorilla::{DataPoint, Decoder, Encoder, Sample};
+    ///
+    /// let mut encoder = Encoder::new();
+    /// encoder.encode(DataPoint::new(60, 1.0)).unwrap();
+    /// encoder.encode_gap(120).unwrap();
+    /// encoder.encode(DataPoint::new(180, f64::NAN)).unwrap();
+    /// encoder.finish().unwrap();
+    /// let block = encoder.into_compressed();
+    ///
+    /// let samples = Decoder::decode_samples(&block).unwrap();
+    /// assert_eq!(samples[1], Sample::Missing(120));
+    /// assert!(samples[2].value().unwrap().is_nan());
+    /// assert_eq!(block.max_value(), Some(1.0));
+    /// ```
+    pub fn encode_gap(&mut self, timestamp: u64) -> Result<(), EncodeError> {
+        let placeholder = match (self.count, self.config.priors) {
+            (0, Some(priors)) => priors.value,
+            (0, None) => 0.0,
+            _ => f64::from_bits(self.prev_value_bits),
+        };
+        self.encode_sample(DataPoint::new(timestamp, placeholder), true)
+    }
 
-            let leading = xor.leading_zeros() as u8;
-            let trailing = xor.trailing_zeros() as u8;
+    /// Encodes `dp`, as a missing sample if `gap` is set, applying the
+    /// ordering and duplicate policies.
+    fn encode_sample(&mut self, dp: DataPoint, gap: bool) -> Result<(), EncodeError> {
+        if self.finished {
+            return Err(EncodeError::Finished);
+        }
 
-            if leading >= self.prev_leading_zeros && trailing >= self.prev_trailing_zeros {
-                // The meaningful bits fit within the previous window.
-                self.buf.write_bit(false)?; // '0' — reuse window
-                let meaningful_bits = 64 - self.prev_leading_zeros - self.prev_trailing_zeros;
-                let meaningful_value = (xor >> self.prev_trailing_zeros) & bitmask(meaningful_bits);
-                self.buf.write_bits(meaningful_value, meaningful_bits)?;
-            } else {
-                // New window.
-                self.buf.write_bit(true)?; // '1' — new window
-                let meaningful_bits = 64 - leading - trailing;
-                self.buf.write_bits(leading as u64, 6)?;
-                self.buf.write_bits((meaningful_bits - 1) as u64, 6)?;
-                let meaningful_value = (xor >> trailing) & bitmask(meaningful_bits);
-                self.buf.write_bits(meaningful_value, meaningful_bits)?;
+        // A point held back by the dead band is the latest one seen, even
+        // though it is not stored yet.
+        let latest = self.held.map_or(self.prev_timestamp, |held| held.timestamp);
+        if self.count > 0 && dp.timestamp < latest {
+            match self.config.on_out_of_order {
+                OutOfOrderPolicy::Reject => {
+                    return Err(EncodeError::OutOfOrder {
+                        previous: latest,
+                        timestamp: dp.timestamp,
+                    });
+                }
+                OutOfOrderPolicy::Drop => return Ok(()),
+                OutOfOrderPolicy::AllowNegativeDelta => {}
+            }
+        }
 
-                self.prev_leading_zeros = leading;
-                self.prev_trailing_zeros = trailing;
+        if self.count > 0 && dp.timestamp == latest {
+            match self.config.on_duplicate {
+                DuplicatePolicy::Allow => {}
+                DuplicatePolicy::KeepFirst => return Ok(()),
+                DuplicatePolicy::KeepLast if self.held.is_some() => self.held = None,
+                DuplicatePolicy::KeepLast => return self.replace_last(dp, gap),
+                DuplicatePolicy::Reject => {
+                    return Err(EncodeError::DuplicateTimestamp {
+                        timestamp: dp.timestamp,
+                    });
+                }
             }
         }
 
-        self.prev_value_bits = bits;
+        match self.config.epoch {
+            Some(epoch) if self.count == 0 && dp.timestamp < epoch => {
+                return Err(EncodeError::BeforeEpoch {
+                    epoch,
+                    timestamp: dp.timestamp,
+                });
+            }
+            _ if self.count > 0 => {
+                self.check_delta(self.prev_timestamp, &self.deltas, dp.timestamp)?;
+            }
+            _ => {}
+        }
+        if !gap && self.in_dead_band(dp) {
+            self.held = Some(dp);
+            return Ok(());
+        }
+        let rollback = self.rollback;
+        if self.config.on_duplicate == DuplicatePolicy::KeepLast {
+            self.rollback = Some(self.checkpoint());
+        }
+        if let Err(err) = self.encode_point(dp, gap) {
+            self.rollback = rollback;
+            return Err(err.into());
+        }
+        self.held = None;
         Ok(())
     }
-}
 
-impl Default for Encoder {
-    fn default() -> Self {
-        Self::new()
+    /// Returns `true` if the dead band drops `dp`, a point that would
+    /// otherwise follow the last stored one.
+    fn in_dead_band(&self, dp: DataPoint) -> bool {
+        let Some(dead_band) = self.config.dead_band else {
+            return false;
+        };
+        let change = self.lossy_value(dp.value) - f64::from_bits(self.prev_value_bits);
+        self.count > 0
+            && change.abs() < dead_band.threshold
+            && dp.timestamp.saturating_sub(self.prev_timestamp) < dead_band.max_interval
     }
-}
 
-/// Returns a bitmask with the lowest `n` bits set. Handles `n == 64` without overflow.
-#[inline]
-fn bitmask(n: u8) -> u64 {
-    if n >= 64 {
-        u64::MAX
-    } else {
-        (1u64 << n) - 1
+    /// Returns `Err(EncodeError::TimestampOutOfRange)` unless `timestamp`
+    /// can follow `previous`, encoded against `deltas`, so that decoding
+    /// reconstructs it without overflow.
+    fn check_delta(
+        &self,
+        previous: u64,
+        deltas: &DeltaState,
+        timestamp: u64,
+    ) -> Result<(), EncodeError> {
+        let fits = checked_delta(previous, timestamp).is_some_and(|delta| {
+            self.config.timestamp_codec == TimestampScheme::Raw
+                || delta.checked_sub(deltas.reference).is_some()
+        });
+        if !fits {
+            return Err(EncodeError::TimestampOutOfRange {
+                previous,
+                timestamp,
+            });
+        }
+        Ok(())
     }
-}
-
-/// A compressed block of Gorilla-encoded time-series data.
-#[derive(Debug, Clone)]
-pub struct CompressedBlock {
-    /// The compressed byte data.
-    pub bytes: Vec<u8>,
-    /// Total number of valid bits in `bytes`.
-    pub total_bits: usize,
-    /// Number of data points in this block.
-    pub count: u64,
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Encodes points given as separate timestamp and value columns, as
+    /// produced by Arrow or ndarray pipelines, without building
+    /// [`DataPoint`]s first.
+    ///
+    /// Returns `Err(EncodeError::LengthMismatch)` without encoding anything
+    /// if the columns differ in length. Otherwise behaves like calling
+    /// [`encode`](Encoder::encode) for each row, stopping at the first
+    /// error with the rows before it encoded.
+    ///
+    /// ```
+    /// use gorilla::{Decoder, EncodeError, Encoder};
+    ///
+    /// let mut encoder = Encoder::new();
+    /// encoder.encode_columns(&[60, 120, 180], &[1.0, 1.5, 2.0]).unwrap();
+    /// assert_eq!(
+    ///     encoder.encode_columns(&[240], &[]),
+    ///     Err(EncodeError::LengthMismatch { timestamps: 1, values: 0 })
+    /// );
+    /// encoder.finish().unwrap();
+    /// assert_eq!(Decoder::decode(&encoder.into_compressed()).unwrap().len(), 3);
+    /// ```
+    pub fn encode_columns(
+        &mut self,
+        timestamps: &[u64],
+        values: &[f64],
+    ) -> Result<(), EncodeError> {
+        if timestamps.len() != values.len() {
+            return Err(EncodeError::LengthMismatch {
+                timestamps: timestamps.len(),
+                values: values.len(),
+            });
+        }
+        for (&timestamp, &value) in timestamps.iter().zip(values) {
+            self.encode(DataPoint::new(timestamp, value))?;
+        }
+        Ok(())
+    }
 
-    #[test]
+    /// Encodes the points of an iterator in order, stopping at the first
+    /// error with the points before it encoded. This is the fallible form of
+    /// [`Extend::extend`], which panics instead.
+    ///
+    /// ```
+    /// use gorilla::{DataPoint, EncodeError, Encoder};
+    ///
+    /// let mut encoder = Encoder::with_limit(16);
+    /// let points = (0..100).map(|t| DataPoint::new(t * 60, t as f64));
+    /// assert_eq!(encoder.try_extend(points), Err(EncodeError::BufferFull));
+    /// assert!(encoder.count() > 0);
+    /// ```
+    pub fn try_extend<I>(&mut self, points: I) -> Result<(), EncodeError>
+    where
+        I: IntoIterator<Item = DataPoint>,
+    {
+        for dp in points {
+            self.encode(dp)?;
+        }
+        Ok(())
+    }
+
+    /// Encodes `points` in order, as if by calling [`encode`](Encoder::encode)
+    /// for each, and produces the same stream.
+    ///
+    /// Runs of [`LANES`] points with strictly increasing timestamps have the
+    /// XORs of their values and the XORs' leading and trailing zero counts
+    /// computed together, in a form the compiler vectorizes, before the
+    /// results are bit-packed. Other points, and all points under
+    /// [`ValueScheme::Chimp`], go through `encode`.
+    ///
+    /// Stops at the first error; the points before it stay encoded.
+    #[cfg(feature = "simd")]
+    pub fn encode_batch(&mut self, points: &[DataPoint]) -> Result<(), EncodeError> {
+        let mut rest = points;
+        while !rest.is_empty() {
+            if self.count < 2
+                || self.config.dead_band.is_some()
+                || !matches!(self.values, Values::Gorilla(_))
+                || rest.len() < LANES
+                || !self.increasing(&rest[..LANES])
+            {
+                self.encode(rest[0])?;
+                rest = &rest[1..];
+                continue;
+            }
+            if self.finished {
+                return Err(EncodeError::Finished);
+            }
+            let (chunk, tail) = rest.split_at(LANES);
+            let mut chunk: [DataPoint; LANES] = chunk.try_into().unwrap();
+            for dp in &mut chunk {
+                dp.value = self.lossy_value(dp.value);
+            }
+            let lanes = XorLanes::compute(self.prev_value_bits, &chunk);
+            for (i, &dp) in chunk.iter().enumerate() {
+                self.check_delta(self.prev_timestamp, &self.deltas, dp.timestamp)?;
+                if self.config.on_duplicate == DuplicatePolicy::KeepLast {
+                    self.rollback = Some(self.checkpoint());
+                }
+                let delta = dp.timestamp.wrapping_sub(self.prev_timestamp) as i64;
+                let mut deltas = self.deltas;
+                let timestamps = self.config.timestamp_codec;
+                let len_bits = self.buf.len_bits();
+                let Values::Gorilla(xor) = &mut self.values else {
+                    unreachable!("batches are only encoded under Gorilla");
+                };
+                let (bits, value) = (lanes.bits[i], lanes.xor[i]);
+                let written = timestamps
+                    .write_delta(&mut self.buf, delta, &mut deltas)
+                    .and_then(|()| {
+                        xor.encode_xor(
+                            &mut self.buf,
+                            bits,
+                            value,
+                            lanes.leading[i],
+                            lanes.trailing[i],
+                        )
+                    });
+                if let Err(err) = written {
+                    self.deltas.rewind(&mut self.buf);
+                    self.buf.truncate(len_bits);
+                    return Err(err.into());
+                }
+                self.prev_value_bits = bits;
+                self.deltas = deltas;
+                self.prev_timestamp = dp.timestamp;
+                self.record_point(dp, false);
+            }
+            rest = tail;
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if `points` continue the stream with strictly
+    /// increasing timestamps, so no ordering policy applies to them.
+    #[cfg(feature = "simd")]
+    fn increasing(&self, points: &[DataPoint]) -> bool {
+        let mut prev = self.prev_timestamp;
+        points.iter().all(|dp| {
+            let ok = dp.timestamp > prev;
+            prev = dp.timestamp;
+            ok
+        })
+    }
+
+    /// Writes the end-of-stream marker. Must be called after all data points
+    /// have been encoded. If [`EncoderConfig::checksum`] is set, this also
+    /// computes the block's checksum.
+    ///
+    /// If the points and the marker then take more than 16 bytes per point,
+    /// as for random values at random intervals, the stream is re-encoded with
+    /// [`TimestampScheme::Raw`] and [`ValueScheme::Raw`] if
+    /// [`EncoderConfig::raw_fallback`] is set, so no block pays more for its
+    /// points than storing them uncompressed. The raw block drops its
+    /// checkpoint index, priors and epoch, which it does not need. The
+    /// stream is kept as it is if the raw points would exceed the byte limit.
+    ///
+    /// ```
+    /// use gorilla::{DataPoint, Encoder, EncoderConfig, ValueScheme};
+    ///
+    /// let mut encoder = Encoder::with_config(EncoderConfig {
+    ///     raw_fallback: true,
+    ///     ..Default::default()
+    /// });
+    /// let (mut state, mut timestamp) = (0x2545_F491_4F6C_DD1Du64, 0);
+    /// for _ in 0..100 {
+    ///     state ^= state << 13;
+    ///     state ^= state >> 7;
+    ///     state ^= state << 17;
+    ///     timestamp += 1 + state % 1_000_000;
+    ///     encoder.encode(DataPoint::new(timestamp, f64::from_bits(state >> 2))).unwrap();
+    /// }
+    /// encoder.finish().unwrap();
+    /// let block = encoder.into_compressed();
+    /// assert_eq!(block.value_codec, ValueScheme::Raw);
+    /// assert_eq!(block.total_bits(), 100 * 128);
+    /// ```
+    ///
+    /// Under [`EncoderConfig::count_terminated`] no marker is written.
+    /// Under [`EncoderConfig::dead_band`] the most recent dropped point is
+    /// stored first.
+    ///
+    /// Returns `Err(BufferFull)` if the buffer cannot fit the marker.
+    pub fn finish(&mut self) -> Result<(), BufferFull> {
+        if self.finished {
+            return Ok(());
+        }
+        if let Some(dp) = self.held {
+            self.encode_point(dp, false)?;
+            self.held = None;
+        }
+        let marker_bits = match self.config.timestamp_codec {
+            TimestampScheme::Raw => 0,
+            _ if self.config.count_terminated => 0,
+            _ => END_MARKER_BITS,
+        };
+        let raw_bits = self.count * 128;
+        let raw_smaller = (self.buf.len_bits() + marker_bits) as u64 > raw_bits;
+        // The rewrite discards the stream first, so it must not run out of
+        // room halfway.
+        let raw_fits = self
+            .buf
+            .capacity_bits()
+            .is_none_or(|capacity| raw_bits <= capacity as u64);
+        if self.config.raw_fallback && self.drained == 0 && raw_smaller && raw_fits {
+            self.rewrite_raw()?;
+        }
+        if self.config.timestamp_codec != TimestampScheme::Raw && !self.config.count_terminated {
+            self.buf.write_bits(0b1111, 4)?;
+            self.buf.write_bits(0xFFFF_FFFF_FFFF_FFFF, 64)?;
+        }
+        if self.config.checksum {
+            self.checksum = Some(crc32c(self.buf.as_bytes()));
+        }
+        self.finished = true;
+        Ok(())
+    }
+
+    /// Replaces the stream, not yet terminated, with the same points stored
+    /// raw.
+    fn rewrite_raw(&mut self) -> Result<(), BufferFull> {
+        let mut reader = BitReader::from_raw(self.buf.as_bytes(), self.buf.len_bits());
+        let points = Decoder::decode_points(
+            &mut reader,
+            self.config.epoch,
+            self.config.priors,
+            self.config.timestamp_codec,
+            self.config.value_codec,
+            self.count,
+        )
+        .expect("an encoder's own stream decodes");
+        self.config.timestamp_codec = TimestampScheme::Raw;
+        self.config.value_codec = ValueScheme::Raw;
+        self.config.priors = None;
+        self.config.epoch = None;
+        self.values = Values::new(ValueScheme::Raw);
+        self.index.clear();
+        self.rollback = None;
+        self.buf.truncate(0);
+        let mut deltas = DeltaState::default();
+        for (i, dp) in points.iter().enumerate() {
+            if i == 0 {
+                self.buf.write_bits(dp.timestamp, 64)?;
+            } else {
+                let delta = dp.timestamp.wrapping_sub(points[i - 1].timestamp) as i64;
+                TimestampScheme::Raw.write_delta(&mut self.buf, delta, &mut deltas)?;
+            }
+            self.values.encode(&mut self.buf, dp.value.to_bits())?;
+        }
+        Ok(())
+    }
+
+    /// Returns a reference to the underlying buffer.
+    pub fn buffer(&self) -> &W {
+        &self.buf
+    }
+
+    /// Consumes the encoder and returns the compressed buffer.
+    pub fn into_buffer(self) -> W {
+        self.buf
+    }
+
+    /// Returns the number of data points encoded so far.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the size of the stream written so far in bits, including
+    /// the end-of-stream marker once finished.
+    pub fn len_bits(&self) -> usize {
+        self.buf.len_bits()
+    }
+
+    /// Returns the size of the stream written so far in bytes, the last
+    /// one possibly partially filled.
+    pub fn len_bytes(&self) -> usize {
+        self.buf.len_bits().div_ceil(8)
+    }
+
+    /// Returns the average number of stream bits per point so far, or 0
+    /// for no points, so that blocks can be rotated when compression
+    /// degrades.
+    ///
+    /// ```
+    /// use gorilla::{DataPoint, Encoder};
+    ///
+    /// let mut encoder = Encoder::new();
+    /// for i in 0..100u64 {
+    ///     encoder.encode(DataPoint::new(i * 60, 1.0)).unwrap();
+    /// }
+    /// assert_eq!(encoder.len_bits(), 64 + 64 + 9 + 1 + 98 * 2);
+    /// assert_eq!(encoder.len_bytes(), 42);
+    /// assert!(encoder.avg_bits_per_point() < 3.4);
+    /// ```
+    pub fn avg_bits_per_point(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.buf.len_bits() as f64 / self.count as f64
+    }
+
+    /// Returns the timestamp of the first encoded data point, if any.
+    pub fn first_timestamp(&self) -> Option<u64> {
+        (self.count > 0).then_some(self.first_timestamp)
+    }
+
+    /// Returns the smallest and largest timestamp encoded so far, if any.
+    /// These differ from the first and last point's timestamps once points
+    /// arrive out of order.
+    pub fn time_range(&self) -> Option<(u64, u64)> {
+        self.stats
+            .map(|stats| (stats.start_timestamp, stats.end_timestamp))
+    }
+
+    /// Returns the most recently encoded data point, if any.
+    pub fn last_point(&self) -> Option<DataPoint> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(DataPoint::new(
+                self.prev_timestamp,
+                f64::from_bits(self.prev_value_bits),
+            ))
+        }
+    }
+
+    /// Returns the bytes of the stream written since the previous call that
+    /// no later point can change, so that a write-ahead log can append the
+    /// new bytes after each [`encode`](Encoder::encode) instead of the whole
+    /// block. Concatenating the results of every call, up to and including
+    /// one after [`finish`](Encoder::finish), gives the block's bytes.
+    ///
+    /// While the block is open, the trailing partial byte is held back, as
+    /// are bytes that may still be rewritten: those of the most recent point
+    /// under [`DuplicatePolicy::KeepLast`] and those from the length field
+    /// of an open [`TimestampScheme::RunLength`] run on. After `finish` the
+    /// rest of the stream is returned. Once any bytes have been drained,
+    /// `finish` keeps the compressed stream even where
+    /// [`EncoderConfig::raw_fallback`] is set.
+    ///
+    /// ```
+    /// use gorilla::{DataPoint, Encoder};
+    ///
+    /// let mut encoder = Encoder::new();
+    /// let mut wal = Vec::new();
+    /// for t in 0..100 {
+    ///     encoder.encode(DataPoint::new(t * 60, (t % 7) as f64)).unwrap();
+    ///     wal.extend_from_slice(encoder.drain_completed_bytes());
+    /// }
+    /// encoder.finish().unwrap();
+    /// wal.extend_from_slice(encoder.drain_completed_bytes());
+    /// assert_eq!(wal, encoder.into_compressed().bytes());
+    /// ```
+    pub fn drain_completed_bytes(&mut self) -> &[u8] {
+        let end = if self.finished {
+            self.buf.as_bytes().len()
+        } else {
+            self.stable_bits() / 8
+        };
+        let end = end.max(self.drained);
+        let start = std::mem::replace(&mut self.drained, end);
+        &self.buf.as_bytes()[start..end]
+    }
+
+    /// Returns the length of the prefix of the stream that no later point
+    /// rewrites.
+    fn stable_bits(&self) -> usize {
+        let mut bits = self.buf.len_bits();
+        if let Some(rollback) = &self.rollback {
+            bits = bits.min(rollback.len_bits);
+            bits = bits.min(rollback.deltas.run_at().unwrap_or(usize::MAX));
+        }
+        bits.min(self.deltas.run_at().unwrap_or(usize::MAX))
+    }
+
+    /// Captures the encoder's state apart from the bits written so far,
+    /// which stay in [`buffer`](Encoder::buffer), so that an open block can
+    /// be picked up again by [`restore`](Encoder::restore), e.g. after a
+    /// crash. See [`snapshot`](crate::snapshot) for an example.
+    pub fn snapshot(&self) -> EncoderState {
+        EncoderState {
+            config: self.config.clone(),
+            len_bits: self.buf.len_bits(),
+            count: self.count,
+            first_timestamp: self.first_timestamp,
+            stats: self.stats,
+            prev_timestamp: self.prev_timestamp,
+            deltas: self.deltas,
+            prev_value_bits: self.prev_value_bits,
+            values: self.values.clone(),
+            finished: self.finished,
+            rollback: self.rollback,
+            checksum: self.checksum,
+            index: self.index.clone(),
+            gaps: self.gaps.clone(),
+            held: self.held,
+        }
+    }
+
+    /// Recreates an encoder from a [`snapshot`](Encoder::snapshot) and a
+    /// buffer holding the bits written up to it. Further points produce the
+    /// same stream as they would have in the original encoder. As with
+    /// [`with_buffer`](Encoder::with_buffer),
+    /// [`EncoderConfig::max_bytes`] is not applied to `buf`.
+    ///
+    /// The bytes in `buf` count as already returned by
+    /// [`drain_completed_bytes`](Encoder::drain_completed_bytes). Fails
+    /// without touching `buf` if it holds a different number of bits than
+    /// the snapshot recorded.
+    pub fn restore(state: EncoderState, buf: W) -> Result<Self, RestoreError> {
+        if buf.len_bits() != state.len_bits {
+            return Err(RestoreError::LengthMismatch {
+                expected: state.len_bits,
+                actual: buf.len_bits(),
+            });
+        }
+        let mut encoder = Self {
+            buf,
+            count: state.count,
+            first_timestamp: state.first_timestamp,
+            stats: state.stats,
+            prev_timestamp: state.prev_timestamp,
+            deltas: state.deltas,
+            prev_value_bits: state.prev_value_bits,
+            values: state.values,
+            finished: state.finished,
+            config: state.config,
+            rollback: state.rollback,
+            checksum: state.checksum,
+            index: state.index,
+            gaps: state.gaps,
+            drained: 0,
+            held: state.held,
+        };
+        encoder.drained = encoder.stable_bits() / 8;
+        Ok(encoder)
+    }
+
+    // ── internal helpers ───────────────────────────────────────────────
+
+    /// Returns `value` as the lossy modes of the configuration store it.
+    fn lossy_value(&self, mut value: f64) -> f64 {
+        if let Some(quantizer) = self.config.quantizer {
+            value = quantizer.apply(value);
+        }
+        if let Some(precision) = self.config.precision {
+            value = precision.apply(value);
+        }
+        value
+    }
+
+    /// On error the stream is left as it was, ending with the last point.
+    fn encode_point(&mut self, mut dp: DataPoint, gap: bool) -> Result<(), BufferFull> {
+        dp.value = self.lossy_value(dp.value);
+        let len_bits = self.buf.len_bits();
+        let written = if self.count == 0 {
+            self.encode_first(dp)
+        } else {
+            // The second point is encoded against a delta of zero, or the
+            // expected interval of the priors.
+            self.encode_subsequent(dp)
+        };
+        if let Err(err) = written {
+            self.buf.truncate(len_bits);
+            return Err(err);
+        }
+        self.record_point(dp, gap);
+        Ok(())
+    }
+
+    /// Updates the statistics, count, gaps and index after `dp` was
+    /// written, as a missing sample if `gap` is set.
+    fn record_point(&mut self, dp: DataPoint, gap: bool) {
+        if gap {
+            self.stats = Some(extend_gap_stats(self.stats, dp.timestamp));
+            self.gaps.push(self.count);
+        } else {
+            self.stats = Some(extend_stats(self.stats, dp));
+        }
+        self.count += 1;
+        if let (Some(interval), Values::Gorilla(xor)) =
+            (self.config.checkpoint_interval, &self.values)
+        {
+            let indexed = self.config.timestamp_codec != TimestampScheme::RunLength;
+            // Seeking skips everything before a checkpoint, so it must not
+            // follow a later point.
+            let latest = self
+                .stats
+                .is_some_and(|stats| stats.end_timestamp == dp.timestamp);
+            if indexed && latest && self.count.is_multiple_of(interval.max(1) as u64) {
+                let (leading_zeros, trailing_zeros) = xor.window();
+                self.index.push(Checkpoint {
+                    point_index: self.count - 1,
+                    bit_offset: self.buf.len_bits() as u64,
+                    timestamp: self.prev_timestamp,
+                    delta: self.deltas.reference,
+                    value_bits: self.prev_value_bits,
+                    leading_zeros,
+                    trailing_zeros,
+                });
+            }
+        }
+    }
+
+    fn checkpoint(&self) -> Rollback {
+        Rollback {
+            len_bits: self.buf.len_bits(),
+            count: self.count,
+            prev_timestamp: self.prev_timestamp,
+            deltas: self.deltas,
+            prev_value_bits: self.prev_value_bits,
+            values: self.values.mark(),
+            stats: self.stats,
+            index_len: self.index.len(),
+            gaps_len: self.gaps.len(),
+        }
+    }
+
+    fn roll_back(&mut self, rollback: Rollback) {
+        self.buf.truncate(rollback.len_bits);
+        self.count = rollback.count;
+        self.prev_timestamp = rollback.prev_timestamp;
+        self.deltas = rollback.deltas;
+        self.deltas.rewind(&mut self.buf);
+        self.prev_value_bits = rollback.prev_value_bits;
+        self.values.rewind(rollback.values);
+        self.stats = rollback.stats;
+        self.index.truncate(rollback.index_len);
+        self.gaps.truncate(rollback.gaps_len);
+    }
+
+    /// Undoes the most recent point and encodes `dp` in its place. If `dp`
+    /// does not fit, the replaced point is restored.
+    fn replace_last(&mut self, dp: DataPoint, gap: bool) -> Result<(), EncodeError> {
+        // No rollback is recorded for the last point of a resumed block.
+        let Some(rollback) = self.rollback else {
+            return Err(EncodeError::DuplicateTimestamp {
+                timestamp: dp.timestamp,
+            });
+        };
+        let previous = DataPoint::new(self.prev_timestamp, f64::from_bits(self.prev_value_bits));
+        let previous_gap = self.gaps.last() == Some(&(self.count - 1));
+        self.roll_back(rollback);
+        if let Err(err) = self.encode_point(dp, gap) {
+            self.roll_back(rollback);
+            self.encode_point(previous, previous_gap)
+                .expect("replaced point fits in the space it used before");
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    fn encode_first(&mut self, dp: DataPoint) -> Result<(), BufferFull> {
+        match self.config.epoch {
+            Some(epoch) => write_varint(&mut self.buf, dp.timestamp - epoch)?,
+            None => self.buf.write_bits(dp.timestamp, 64)?,
+        }
+        self.deltas = DeltaState::default();
+        if let Some(priors) = self.config.priors {
+            self.values.seed(priors.value.to_bits());
+            self.deltas = DeltaState::new(priors.interval as i64);
+        }
+        self.encode_value(dp.value)?;
+
+        self.first_timestamp = dp.timestamp;
+        self.prev_timestamp = dp.timestamp;
+        Ok(())
+    }
+
+    /// Returns the number of bits the first point's `timestamp` takes.
+    fn first_timestamp_bits(&self, timestamp: u64) -> usize {
+        match self.config.epoch {
+            Some(epoch) => varint_bits(timestamp.saturating_sub(epoch)),
+            None => 64,
+        }
+    }
+
+    fn encode_subsequent(&mut self, dp: DataPoint) -> Result<(), BufferFull> {
+        let delta = dp.timestamp.wrapping_sub(self.prev_timestamp) as i64;
+        let mut deltas = self.deltas;
+        let timestamps = self.config.timestamp_codec;
+        timestamps.write_delta(&mut self.buf, delta, &mut deltas)?;
+
+        if let Err(err) = self.encode_value(dp.value) {
+            // Take the point back out of the run it may have joined.
+            self.deltas.rewind(&mut self.buf);
+            return Err(err);
+        }
+
+        self.deltas = deltas;
+        self.prev_timestamp = dp.timestamp;
+        Ok(())
+    }
+
+    /// Writes a value with the configured [`ValueScheme`]. Under
+    /// [`ValueScheme::Gorilla`]:
+    ///
+    /// 1. XOR with previous value.
+    /// 2. If XOR == 0: write single `0` bit.
+    /// 3. Else:
+    ///    a. Write `1`.
+    ///    b. If leading/trailing zeros fit within previous window:
+    ///    write `0` + meaningful bits.
+    ///    c. Else: write `1` + 6-bit leading zeros + 6-bit meaningful length + meaningful bits.
+    fn encode_value(&mut self, value: f64) -> Result<(), BufferFull> {
+        let bits = value.to_bits();
+        self.values.encode(&mut self.buf, bits)?;
+        self.prev_value_bits = bits;
+        Ok(())
+    }
+}
+
+/// Writes an XOR-compressed value given its XOR with the previous value,
+/// the XOR's leading and trailing zero counts, and the current window of
+/// leading and trailing zeros, which is updated when a new one is opened.
+pub(crate) fn write_xor<W: BitWrite>(
+    buf: &mut W,
+    xor: u64,
+    leading: u8,
+    trailing: u8,
+    window: &mut (u8, u8),
+) -> Result<(), BufferFull> {
+    if xor == 0 {
+        return buf.write_bit(false);
+    }
+    buf.write_bit(true)?; // '1' — value changed
+
+    let (prev_leading, prev_trailing) = *window;
+    if leading >= prev_leading && trailing >= prev_trailing {
+        // The meaningful bits fit within the previous window.
+        buf.write_bit(false)?; // '0' — reuse window
+        let meaningful_bits = 64 - prev_leading - prev_trailing;
+        let meaningful_value = (xor >> prev_trailing) & bitmask(meaningful_bits);
+        buf.write_bits(meaningful_value, meaningful_bits)?;
+    } else {
+        // New window.
+        buf.write_bit(true)?; // '1' — new window
+        let meaningful_bits = 64 - leading - trailing;
+        buf.write_bits(leading as u64, 6)?;
+        buf.write_bits((meaningful_bits - 1) as u64, 6)?;
+        let meaningful_value = (xor >> trailing) & bitmask(meaningful_bits);
+        buf.write_bits(meaningful_value, meaningful_bits)?;
+        *window = (leading, trailing);
+    }
+    Ok(())
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Encodes every point, as [`Encoder::try_extend`].
+///
+/// # Panics
+///
+/// Panics if a point cannot be encoded, e.g. because the buffer is full or
+/// the configuration rejects it; use [`Encoder::try_extend`] to handle
+/// such errors.
+impl<W: BitWrite> Extend<DataPoint> for Encoder<W> {
+    fn extend<I: IntoIterator<Item = DataPoint>>(&mut self, points: I) {
+        if let Err(err) = self.try_extend(points) {
+            panic!("failed to extend encoder: {err}");
+        }
+    }
+}
+
+impl<'a, W: BitWrite> Extend<&'a DataPoint> for Encoder<W> {
+    fn extend<I: IntoIterator<Item = &'a DataPoint>>(&mut self, points: I) {
+        self.extend(points.into_iter().copied());
+    }
+}
+
+/// Collects points into an open encoder with the default configuration,
+/// which accepts every point. Call [`Encoder::finish`] before taking the
+/// block.
+///
+/// ```
+/// use gorilla::{DataPoint, Decoder, Encoder};
+///
+/// let points: Vec<_> = (0..10).map(|t| DataPoint::new(t * 60, 1.0)).collect();
+/// let mut encoder: Encoder = points.iter().copied().collect();
+/// encoder.extend(&[DataPoint::new(600, 2.0), DataPoint::new(660, 2.0)]);
+/// encoder.finish().unwrap();
+/// assert_eq!(Decoder::decode(&encoder.into_compressed()).unwrap().len(), 12);
+/// ```
+impl FromIterator<DataPoint> for Encoder {
+    fn from_iter<I: IntoIterator<Item = DataPoint>>(points: I) -> Self {
+        let mut encoder = Encoder::new();
+        encoder.extend(points);
+        encoder
+    }
+}
+
+/// Number of values [`Encoder::encode_batch`] processes together.
+#[cfg(feature = "simd")]
+pub const LANES: usize = 8;
+
+/// Value bits, XORs and zero counts of one batch of [`LANES`] values.
+#[cfg(feature = "simd")]
+struct XorLanes {
+    bits: [u64; LANES],
+    xor: [u64; LANES],
+    leading: [u8; LANES],
+    trailing: [u8; LANES],
+}
+
+#[cfg(feature = "simd")]
+impl XorLanes {
+    /// Computes the lanes of `chunk`, whose first value follows `prev_bits`.
+    /// Each step is a fixed-width loop over arrays with no cross-lane
+    /// dependency, which LLVM lowers to vector instructions.
+    #[inline]
+    fn compute(prev_bits: u64, chunk: &[DataPoint]) -> Self {
+        let mut bits = [0u64; LANES];
+        for (b, dp) in bits.iter_mut().zip(chunk) {
+            *b = dp.value.to_bits();
+        }
+        let mut prev = [prev_bits; LANES];
+        prev[1..].copy_from_slice(&bits[..LANES - 1]);
+        let mut xor = [0u64; LANES];
+        for i in 0..LANES {
+            xor[i] = bits[i] ^ prev[i];
+        }
+        let mut leading = [0u8; LANES];
+        let mut trailing = [0u8; LANES];
+        for i in 0..LANES {
+            leading[i] = xor[i].leading_zeros() as u8;
+            trailing[i] = xor[i].trailing_zeros() as u8;
+        }
+        Self {
+            bits,
+            xor,
+            leading,
+            trailing,
+        }
+    }
+}
+
+/// Extends `stats` by a missing sample at `timestamp`, which widens the
+/// time range but leaves the value statistics alone.
+pub(crate) fn extend_gap_stats(stats: Option<BlockStats>, timestamp: u64) -> BlockStats {
+    match stats {
+        None => BlockStats {
+            start_timestamp: timestamp,
+            end_timestamp: timestamp,
+            min_value: f64::NAN,
+            max_value: f64::NAN,
+            sum: 0.0,
+        },
+        Some(stats) => BlockStats {
+            start_timestamp: stats.start_timestamp.min(timestamp),
+            end_timestamp: stats.end_timestamp.max(timestamp),
+            ..stats
+        },
+    }
+}
+
+/// Returns `stats` extended with `dp`.
+pub(crate) fn extend_stats(stats: Option<BlockStats>, dp: DataPoint) -> BlockStats {
+    match stats {
+        None => BlockStats {
+            start_timestamp: dp.timestamp,
+            end_timestamp: dp.timestamp,
+            min_value: dp.value,
+            max_value: dp.value,
+            sum: dp.value,
+        },
+        Some(stats) => BlockStats {
+            start_timestamp: stats.start_timestamp.min(dp.timestamp),
+            end_timestamp: stats.end_timestamp.max(dp.timestamp),
+            min_value: stats.min_value.min(dp.value),
+            max_value: stats.max_value.max(dp.value),
+            sum: stats.sum + dp.value,
+        },
+    }
+}
+
+/// Encodes a delta-of-delta value using the Gorilla variable-length scheme:
+///
+/// | dod == 0       | `0`                            | 1 bit   |
+/// | [-64, 63]      | `10` + 7-bit value             | 9 bits  |
+/// | [-256, 255]    | `110` + 9-bit value            | 12 bits |
+/// | [-2048, 2047]  | `1110` + 12-bit value          | 16 bits |
+/// | otherwise      | `1111` + 64-bit value          | 68 bits |
+///
+/// Every dod goes in the smallest bucket that holds it, so the 64-bit
+/// bucket never holds one in [-2048, 2047]. Its all-ones payload, -1, is
+/// thus free for the end-of-stream marker, and every other `i64` round-trips.
+pub(crate) fn write_delta_of_delta<W: BitWrite>(buf: &mut W, dod: i64) -> Result<(), BufferFull> {
+    if dod == 0 {
+        buf.write_bit(false)?;
+    } else if (-64..=63).contains(&dod) {
+        buf.write_bits(0b10, 2)?;
+        buf.write_bits((dod as u64) & 0x7F, 7)?;
+    } else if (-256..=255).contains(&dod) {
+        buf.write_bits(0b110, 3)?;
+        buf.write_bits((dod as u64) & 0x1FF, 9)?;
+    } else if (-2048..=2047).contains(&dod) {
+        buf.write_bits(0b1110, 4)?;
+        buf.write_bits((dod as u64) & 0xFFF, 12)?;
+    } else {
+        buf.write_bits(0b1111, 4)?;
+        buf.write_bits(dod as u64, 64)?;
+    }
+    Ok(())
+}
+
+/// Returns the distance from `previous` to `timestamp`, or `None` if it does
+/// not fit in an `i64`.
+pub(crate) fn checked_delta(previous: u64, timestamp: u64) -> Option<i64> {
+    i64::try_from(i128::from(timestamp) - i128::from(previous)).ok()
+}
+
+/// Writes `n` as a LEB128 varint: 7 bits at a time, least significant
+/// first, each group preceded by a bit that is set if another follows.
+pub(crate) fn write_varint<W: BitWrite>(buf: &mut W, mut n: u64) -> Result<(), BufferFull> {
+    while n >= 0x80 {
+        buf.write_bits(0x80 | (n & 0x7F), 8)?;
+        n >>= 7;
+    }
+    buf.write_bits(n, 8)
+}
+
+/// Returns the number of bits [`write_varint`] takes for `n`.
+pub(crate) fn varint_bits(n: u64) -> usize {
+    8 * (64 - n.leading_zeros() as usize).div_ceil(7).max(1)
+}
+
+/// Returns the number of bits [`write_delta_of_delta`] takes for `dod`.
+pub(crate) fn delta_of_delta_bits(dod: i64) -> usize {
+    match dod {
+        0 => 1,
+        -64..=63 => 9,
+        -256..=255 => 12,
+        -2048..=2047 => 16,
+        _ => 68,
+    }
+}
+
+/// Returns the greatest common divisor of `a` and `b`.
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Returns a bitmask with the lowest `n` bits set. Handles `n == 64` without overflow.
+#[inline]
+fn bitmask(n: u8) -> u64 {
+    if n >= 64 {
+        u64::MAX
+    } else {
+        (1u64 << n) - 1
+    }
+}
+
+/// Returns the completeness watermark of data merged from sources with the
+/// given watermarks: the earliest one, or `None` if any source's is
+/// unknown, since the merge is only complete where every source is.
+///
+/// ```
+/// use gorilla::merged_watermark;
+///
+/// assert_eq!(merged_watermark([Some(120), Some(90)]), Some(90));
+/// assert_eq!(merged_watermark([Some(120), None]), None);
+/// ```
+pub fn merged_watermark(watermarks: impl IntoIterator<Item = Option<u64>>) -> Option<u64> {
+    let mut merged = None;
+    for watermark in watermarks {
+        let watermark = watermark?;
+        merged = Some(merged.map_or(watermark, |m: u64| m.min(watermark)));
+    }
+    merged
+}
+
+/// A compressed block of Gorilla-encoded time-series data.
+///
+/// The stream is held in a `Vec<u8>` by default. Any other storage that
+/// derefs to bytes works as well, e.g. `Arc<[u8]>` or `bytes::Bytes`, so
+/// that clones of a block share one copy of the stream across threads, see
+/// [`into_storage`](CompressedBlock::into_storage).
+///
+/// `Display` gives a one-line summary for logs. `Debug` lists the fields, in
+/// the alternate form `{:#?}` with the stream as a hex dump of its first
+/// [`DEBUG_HEX_BYTES`] bytes.
+///
+/// ```
+/// use gorilla::{DataPoint, Encoder};
+///
+/// let mut encoder = Encoder::new();
+/// for i in 0..10 {
+///     encoder.encode(DataPoint::new(1609459200 + i * 60, 1.0)).unwrap();
+/// }
+/// let block = encoder.finish_into().unwrap();
+/// assert_eq!(
+///     block.to_string(),
+///     "10 points, 28 bytes, 22.2 bits/point, 1609459200..=1609459740"
+/// );
+/// // The stream starts with the first timestamp, 0x5fee6600.
+/// assert!(format!("{block:#?}").contains("bytes: 00 00 00 00 5f ee 66 00 "));
+/// ```
+#[derive(Clone)]
+pub struct CompressedBlock<B = Vec<u8>> {
+    pub(crate) bytes: B,
+    pub(crate) total_bits: usize,
+    pub(crate) count: u64,
+    /// CRC32C of `bytes`, present when the encoder was configured with
+    /// [`EncoderConfig::checksum`].
+    pub checksum: Option<u32>,
+    /// Metadata recorded by the encoder; `None` for empty blocks and blocks
+    /// assembled by hand.
+    pub stats: Option<BlockStats>,
+    /// Random-access checkpoints recorded every
+    /// [`EncoderConfig::checkpoint_interval`] points, oldest first; empty if
+    /// no index was built. Decoding checks it against the stream and fails
+    /// with [`DecodeError::MalformedHeader`] if it does not fit.
+    pub index: Vec<Checkpoint>,
+    /// Provenance metadata, stored after the stream by
+    /// [`to_bytes`](CompressedBlock::to_bytes).
+    pub metadata: BlockMetadata,
+    /// Completeness watermark: every point with a timestamp up to and
+    /// including this one has arrived, so a gap before it means no data
+    /// rather than data not yet arrived. `None` if unknown.
+    pub complete_until: Option<u64>,
+    /// Priors the stream was encoded against, see [`Encoder::with_priors`].
+    pub priors: Option<Priors>,
+    /// Compression scheme of the timestamps in the stream.
+    pub timestamp_codec: TimestampScheme,
+    /// Compression scheme of the values in the stream.
+    pub value_codec: ValueScheme,
+    /// Whether the stream ends without an end-of-stream marker, after
+    /// [`count`](CompressedBlock::count) points, see
+    /// [`EncoderConfig::count_terminated`].
+    pub count_terminated: bool,
+    /// Epoch the first timestamp is stored relative to, see
+    /// [`EncoderConfig::epoch`].
+    pub epoch: Option<u64>,
+    /// Grid the values were rounded to, see [`Encoder::with_quantizer`].
+    pub quantizer: Option<QuantizeSpec>,
+    /// Zero-based indices of the points that are missing samples, see
+    /// [`Encoder::encode_gap`], in ascending order. Checked like
+    /// [`index`](CompressedBlock::index) when decoding.
+    pub gaps: Vec<u64>,
+}
+
+/// Number of stream bytes the alternate `Debug` form of a
+/// [`CompressedBlock`] shows.
+pub const DEBUG_HEX_BYTES: usize = 32;
+
+impl<B: AsRef<[u8]>> std::fmt::Display for CompressedBlock<B> {
+    /// Shows the point count, stream size, bits per point and, if recorded,
+    /// time range.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bits_per_point = match self.count {
+            0 => 0.0,
+            count => self.total_bits as f64 / count as f64,
+        };
+        write!(
+            f,
+            "{} points, {} bytes, {bits_per_point:.1} bits/point",
+            self.count,
+            self.bytes.as_ref().len()
+        )?;
+        if let Some(stats) = self.stats {
+            write!(f, ", {}..={}", stats.start_timestamp, stats.end_timestamp)?;
+        }
+        Ok(())
+    }
+}
+
+impl<B: AsRef<[u8]>> std::fmt::Debug for CompressedBlock<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bytes = HexPrefix(self.bytes.as_ref());
+        f.debug_struct("CompressedBlock")
+            .field("bytes", &bytes)
+            .field("total_bits", &self.total_bits)
+            .field("count", &self.count)
+            .field("checksum", &self.checksum)
+            .field("stats", &self.stats)
+            .field("index", &self.index)
+            .field("metadata", &self.metadata)
+            .field("complete_until", &self.complete_until)
+            .field("priors", &self.priors)
+            .field("timestamp_codec", &self.timestamp_codec)
+            .field("value_codec", &self.value_codec)
+            .field("count_terminated", &self.count_terminated)
+            .field("epoch", &self.epoch)
+            .field("quantizer", &self.quantizer)
+            .field("gaps", &self.gaps)
+            .finish()
+    }
+}
+
+/// Block stream bytes in `Debug` output: listed in full as usual, or in the
+/// alternate form the first [`DEBUG_HEX_BYTES`] as hex.
+struct HexPrefix<'a>(&'a [u8]);
+
+impl std::fmt::Debug for HexPrefix<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !f.alternate() {
+            return std::fmt::Debug::fmt(self.0, f);
+        }
+        let shown = &self.0[..self.0.len().min(DEBUG_HEX_BYTES)];
+        for (i, byte) in shown.iter().enumerate() {
+            let sep = if i == 0 { "" } else { " " };
+            write!(f, "{sep}{byte:02x}")?;
+        }
+        match self.0.len() - shown.len() {
+            0 => Ok(()),
+            rest => write!(f, " … ({rest} more)"),
+        }
+    }
+}
+
+/// Checks the invariants of [`CompressedBlock::from_parts`]. The first point
+/// takes at least a one-byte varint timestamp (see [`EncoderConfig::epoch`])
+/// and one value bit, every further one at least the fewest bits its codecs
+/// store a point in: none for a timestamp inside a
+/// [`TimestampScheme::RunLength`] run.
+fn check_parts(
+    len: usize,
+    total_bits: usize,
+    count: u64,
+    timestamp_codec: TimestampScheme,
+    value_codec: ValueScheme,
+) -> Result<(), InvalidBlock> {
+    if len != total_bits.div_ceil(8) {
+        return Err(InvalidBlock::LengthMismatch { len, total_bits });
+    }
+    let timestamp_bits = match timestamp_codec {
+        TimestampScheme::RunLength => 0,
+        TimestampScheme::DeltaOfDelta | TimestampScheme::Delta => 1,
+        TimestampScheme::Raw => 64,
+    };
+    let value_bits = match value_codec {
+        ValueScheme::Gorilla => 1,
+        ValueScheme::Chimp => 2,
+        ValueScheme::Raw => 64,
+    };
+    let min_bits = count.checked_sub(1).map_or(0, |rest| {
+        rest.saturating_mul(timestamp_bits + value_bits)
+            .saturating_add(9)
+    });
+    if min_bits > total_bits as u64 {
+        return Err(InvalidBlock::CountExceedsBits { count, total_bits });
+    }
+    Ok(())
+}
+
+/// Checks that checkpoints lie inside a stream of `count` points and
+/// `total_bits` bits, in order, with valid XOR windows.
+pub(crate) fn check_index(
+    index: &[Checkpoint],
+    count: u64,
+    total_bits: usize,
+) -> Result<(), DecodeError> {
+    let mut prev: Option<&Checkpoint> = None;
+    for cp in index {
+        if cp.point_index >= count || cp.bit_offset > total_bits as u64 {
+            return Err(DecodeError::MalformedHeader("checkpoint beyond stream"));
+        }
+        if prev.is_some_and(|p| cp.point_index <= p.point_index || cp.bit_offset <= p.bit_offset) {
+            return Err(DecodeError::MalformedHeader("checkpoints out of order"));
+        }
+        if !cp.has_valid_window() {
+            return Err(DecodeError::MalformedHeader(
+                "checkpoint XOR window out of range",
+            ));
+        }
+        prev = Some(cp);
+    }
+    Ok(())
+}
+
+/// Checks that the positions of the missing samples of a block holding
+/// `count` points are strictly increasing and inside the block.
+pub(crate) fn check_gaps(gaps: &[u64], count: u64) -> Result<(), DecodeError> {
+    let mut prev = None;
+    for &gap in gaps {
+        if gap >= count || prev.is_some_and(|prev| gap <= prev) {
+            return Err(DecodeError::MalformedHeader("gaps out of order"));
+        }
+        prev = Some(gap);
+    }
+    Ok(())
+}
+
+/// Decoder state after one point of a block, recorded by the encoder so that
+/// decoding can resume from the middle of the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// Zero-based position of the point in the block.
+    pub point_index: u64,
+    /// Bit offset just past the point in the stream.
+    pub bit_offset: u64,
+    /// Timestamp of the point.
+    pub timestamp: u64,
+    /// Delta the next timestamp is stored against: under
+    /// [`TimestampScheme::DeltaOfDelta`], the delta between the point's
+    /// timestamp and the previous one.
+    pub delta: i64,
+    /// Raw bits of the point's value.
+    pub value_bits: u64,
+    /// Leading zeros of the XOR window in effect after the point.
+    pub leading_zeros: u8,
+    /// Trailing zeros of the XOR window in effect after the point.
+    pub trailing_zeros: u8,
+}
+
+impl Checkpoint {
+    /// Returns `true` if the XOR window fits in 64 bits or is the empty
+    /// window of a stream that has not stored a non-zero XOR yet.
+    pub(crate) fn has_valid_window(&self) -> bool {
+        u16::from(self.leading_zeros) + u16::from(self.trailing_zeros) <= 64
+            || (self.leading_zeros, self.trailing_zeros) == (64, 64)
+    }
+}
+
+/// Breakdown of the bits of a stream by purpose, see [`Encoder::stats`].
+/// The end-of-stream marker is not counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    /// Number of points.
+    pub points: u64,
+    /// Bits spent on timestamps.
+    pub timestamp_bits: u64,
+    /// Bits spent on values.
+    pub value_bits: u64,
+    /// Timestamps after the first per delta-of-delta bucket: zero, 7, 9, 12
+    /// and 64 bits.
+    pub dod_buckets: [u64; 5],
+    /// Values equal to their predecessor.
+    pub repeated_values: u64,
+    /// Values that fit the previous XOR window.
+    pub reused_windows: u64,
+    /// Values that opened a new XOR window, including a first value
+    /// encoded against priors.
+    pub new_windows: u64,
+}
+
+impl CompressionStats {
+    /// Computes the statistics of a finished block.
+    pub fn of_block<B: AsRef<[u8]>>(block: &CompressedBlock<B>) -> Result<Self, DecodeError> {
+        let mut stats = Self::default();
+        for record in Decoder::inspect(block)? {
+            stats.points += 1;
+            stats.timestamp_bits += record.timestamp_bits as u64;
+            stats.value_bits += record.value_bits as u64;
+            let bucket = match record.timestamp_encoding {
+                DodBucket::Raw => None,
+                DodBucket::Zero | DodBucket::Run => Some(0),
+                DodBucket::Bits7 => Some(1),
+                DodBucket::Bits9 => Some(2),
+                DodBucket::Bits12 => Some(3),
+                DodBucket::Bits64 => Some(4),
+            };
+            if let Some(bucket) = bucket {
+                stats.dod_buckets[bucket] += 1;
+            }
+            match record.value_encoding {
+                ValueEncoding::Raw => {}
+                ValueEncoding::Repeat => stats.repeated_values += 1,
+                ValueEncoding::ReusedWindow => stats.reused_windows += 1,
+                ValueEncoding::NewWindow => stats.new_windows += 1,
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Adds the statistics of another stream, e.g. to summarize a series.
+    pub fn add(&mut self, other: &CompressionStats) {
+        self.points += other.points;
+        self.timestamp_bits += other.timestamp_bits;
+        self.value_bits += other.value_bits;
+        for (bucket, count) in self.dod_buckets.iter_mut().zip(other.dod_buckets) {
+            *bucket += count;
+        }
+        self.repeated_values += other.repeated_values;
+        self.reused_windows += other.reused_windows;
+        self.new_windows += other.new_windows;
+    }
+
+    /// Returns the share of changed values that reused the previous XOR
+    /// window, or 0 if no value changed.
+    pub fn window_reuse_rate(&self) -> f64 {
+        let changed = self.reused_windows + self.new_windows;
+        if changed == 0 {
+            return 0.0;
+        }
+        self.reused_windows as f64 / changed as f64
+    }
+
+    /// Returns the average number of bits per point, or 0 for no points.
+    pub fn bits_per_point(&self) -> f64 {
+        if self.points == 0 {
+            return 0.0;
+        }
+        (self.timestamp_bits + self.value_bits) as f64 / self.points as f64
+    }
+
+    /// Returns the average number of bytes per point, the measure used by
+    /// the Gorilla paper.
+    pub fn bytes_per_point(&self) -> f64 {
+        self.bits_per_point() / 8.0
+    }
+}
+
+/// Metadata about the points of a [`CompressedBlock`], computed during
+/// encoding so that query layers can prune blocks and answer simple
+/// aggregates without decoding them. The point count is
+/// [`CompressedBlock::count`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockStats {
+    /// Smallest timestamp in the block.
+    pub start_timestamp: u64,
+    /// Largest timestamp in the block.
+    pub end_timestamp: u64,
+    /// Smallest value in the block, ignoring NaNs unless all values are NaN.
+    pub min_value: f64,
+    /// Largest value in the block, ignoring NaNs unless all values are NaN.
+    pub max_value: f64,
+    /// Sum of all values in the block.
+    pub sum: f64,
+}
+
+/// Length of the end-of-stream marker: the `1111` prefix and 64 one bits.
+const END_MARKER_BITS: usize = 68;
+
+/// Bits per point [`Encoder::with_capacity_hint`] sizes the buffer for: a
+/// little over the 1.37 bytes per point the Gorilla paper reports for
+/// production series.
+const HINT_BITS_PER_POINT: usize = 12;
+
+/// Version byte written by [`CompressedBlock::to_bytes`].
+pub(crate) const BLOCK_FORMAT_VERSION: u8 = 1;
+/// Flag bit: a CRC32C checksum follows the fixed header.
+pub(crate) const FLAG_CHECKSUM: u8 = 0b0000_0001;
+/// Flag bit: block statistics follow the checksum.
+pub(crate) const FLAG_STATS: u8 = 0b0000_0010;
+/// Flag bit: a checkpoint index follows the statistics.
+pub(crate) const FLAG_INDEX: u8 = 0b0000_0100;
+/// Flag bit: block metadata follows the stream.
+pub(crate) const FLAG_METADATA: u8 = 0b0000_1000;
+/// Flag bit: a completeness watermark follows the index.
+pub(crate) const FLAG_WATERMARK: u8 = 0b0001_0000;
+/// Flag bit: encoder priors follow the watermark.
+pub(crate) const FLAG_PRIORS: u8 = 0b0010_0000;
+/// Flag bit: a codec byte follows the priors, as some codec of the block is
+/// not the default.
+pub(crate) const FLAG_CODEC: u8 = 0b0100_0000;
+/// Flag bit: the indices of missing samples follow the codec byte.
+pub(crate) const FLAG_GAPS: u8 = 0b1000_0000;
+/// Every flag bit this build reads. All eight are assigned, so a further
+/// header section needs a new format version.
+pub(crate) const KNOWN_FLAGS: u8 = FLAG_CHECKSUM
+    | FLAG_STATS
+    | FLAG_INDEX
+    | FLAG_METADATA
+    | FLAG_WATERMARK
+    | FLAG_PRIORS
+    | FLAG_CODEC
+    | FLAG_GAPS;
+/// Serialized size of one [`Checkpoint`].
+const CHECKPOINT_LEN: usize = 42;
+
+impl<B: AsRef<[u8]>> CompressedBlock<B> {
+    /// Assembles a block from a raw stream of `total_bits` bits holding
+    /// `count` points, e.g. one stored outside the format of
+    /// [`to_bytes`](CompressedBlock::to_bytes). Checks that `bytes` is
+    /// exactly as long as `total_bits` needs and that `count` points fit in
+    /// it; the stream itself is only checked when decoded.
+    ///
+    /// ```
+    /// use gorilla::{CompressedBlock, DataPoint, Decoder, Encoder, InvalidBlock};
+    ///
+    /// let mut encoder = Encoder::new();
+    /// encoder.encode(DataPoint::new(1609459200, 1.5)).unwrap();
+    /// encoder.finish().unwrap();
+    /// let block = encoder.into_compressed();
+    ///
+    /// let copy =
+    ///     CompressedBlock::from_parts(block.bytes().to_vec(), block.total_bits(), 1).unwrap();
+    /// assert_eq!(Decoder::decode(&copy).unwrap(), [DataPoint::new(1609459200, 1.5)]);
+    /// assert_eq!(
+    ///     CompressedBlock::from_parts(vec![0; 4], 64, 0).unwrap_err(),
+    ///     InvalidBlock::LengthMismatch { len: 4, total_bits: 64 }
+    /// );
+    /// ```
+    pub fn from_parts(bytes: B, total_bits: usize, count: u64) -> Result<Self, InvalidBlock> {
+        check_parts(
+            bytes.as_ref().len(),
+            total_bits,
+            count,
+            TimestampScheme::default(),
+            ValueScheme::default(),
+        )?;
+        Ok(CompressedBlock {
+            bytes,
+            total_bits,
+            count,
+            checksum: None,
+            stats: None,
+            index: Vec::new(),
+            metadata: BlockMetadata::default(),
+            complete_until: None,
+            priors: None,
+            timestamp_codec: TimestampScheme::DeltaOfDelta,
+            value_codec: ValueScheme::Gorilla,
+            count_terminated: false,
+            epoch: None,
+            quantizer: None,
+            gaps: Vec::new(),
+        })
+    }
+
+    /// Returns the compressed stream.
+    pub fn bytes(&self) -> &[u8] {
+        self.bytes.as_ref()
+    }
+
+    /// Consumes the block and returns the compressed stream.
+    pub fn into_bytes(self) -> B {
+        self.bytes
+    }
+
+    /// Moves the stream into another kind of storage, e.g. `Arc<[u8]>` to
+    /// share it between clones of the block.
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    ///
+    /// use gorilla::{CompressedBlock, DataPoint, Decoder, Encoder};
+    ///
+    /// let mut encoder = Encoder::new();
+    /// encoder.encode(DataPoint::new(1609459200, 1.5)).unwrap();
+    /// encoder.finish().unwrap();
+    /// let block: CompressedBlock<Arc<[u8]>> = encoder.into_compressed().into_storage();
+    ///
+    /// let shared = block.clone();
+    /// assert_eq!(shared.bytes().as_ptr(), block.bytes().as_ptr());
+    /// let reader = std::thread::spawn(move || Decoder::decode(&shared).unwrap());
+    /// assert_eq!(reader.join().unwrap(), [DataPoint::new(1609459200, 1.5)]);
+    /// ```
+    pub fn into_storage<C: From<B> + AsRef<[u8]>>(self) -> CompressedBlock<C> {
+        CompressedBlock {
+            bytes: C::from(self.bytes),
+            total_bits: self.total_bits,
+            count: self.count,
+            checksum: self.checksum,
+            stats: self.stats,
+            index: self.index,
+            metadata: self.metadata,
+            complete_until: self.complete_until,
+            priors: self.priors,
+            timestamp_codec: self.timestamp_codec,
+            value_codec: self.value_codec,
+            count_terminated: self.count_terminated,
+            epoch: self.epoch,
+            quantizer: self.quantizer,
+            gaps: self.gaps,
+        }
+    }
+
+    /// Returns the number of valid bits in [`bytes`](CompressedBlock::bytes).
+    pub fn total_bits(&self) -> usize {
+        self.total_bits
+    }
+
+    /// Returns the number of points in the block.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the smallest timestamp in the block, if recorded.
+    pub fn start_timestamp(&self) -> Option<u64> {
+        self.stats.map(|s| s.start_timestamp)
+    }
+
+    /// Returns the largest timestamp in the block, if recorded.
+    pub fn end_timestamp(&self) -> Option<u64> {
+        self.stats.map(|s| s.end_timestamp)
+    }
+
+    /// Returns the smallest value in the block, if recorded.
+    pub fn min_value(&self) -> Option<f64> {
+        self.stats.map(|s| s.min_value)
+    }
+
+    /// Returns the largest value in the block, if recorded.
+    pub fn max_value(&self) -> Option<f64> {
+        self.stats.map(|s| s.max_value)
+    }
+
+    /// Returns the sum of the values in the block, if recorded.
+    pub fn sum(&self) -> Option<f64> {
+        self.stats.map(|s| s.sum)
+    }
+
+    /// Concatenates two blocks covering adjacent time ranges into one.
+    ///
+    /// `a`'s stream is reused as is: the encoder is re-seeded with its state
+    /// at the boundary (see [`Encoder::resume`]) and only `b`'s points are
+    /// re-encoded onto the end. The result carries a checksum if `a` does,
+    /// and is complete only as far as both inputs are (see
+    /// [`merged_watermark`]). Its checkpoint index extends over `b`'s points
+    /// at the interval of the inputs' indexes. `b` must start after `a` ends
+    /// and have the same [`quantizer`](CompressedBlock::quantizer) as `a`.
+    ///
+    /// # Example
+    /// ```
+    /// use gorilla::{CompressedBlock, DataPoint, Decoder, Encoder};
+    ///
+    /// let block = |range: std::ops::Range<u64>| {
+    ///     let mut encoder = Encoder::new();
+    ///     for t in range {
+    ///         encoder.encode(DataPoint::new(t * 60, t as f64)).unwrap();
+    ///     }
+    ///     encoder.finish().unwrap();
+    ///     encoder.into_compressed()
+    /// };
+    /// let merged = CompressedBlock::merge(&block(0..10), &block(10..20)).unwrap();
+    /// assert_eq!(Decoder::decode(&merged).unwrap(), Decoder::decode(&block(0..20)).unwrap());
+    /// ```
+    pub fn merge(a: &Self, b: &Self) -> Result<CompressedBlock, MergeError> {
+        if a.quantizer != b.quantizer {
+            return Err(MergeError::QuantizerMismatch {
+                a: a.quantizer,
+                b: b.quantizer,
+            });
+        }
+        // Blocks do not record their checkpoint interval, but every
+        // checkpoint is taken after a multiple of that many points.
+        let checkpoint_interval = a
+            .index
+            .iter()
+            .chain(&b.index)
+            .filter_map(|cp| cp.point_index.checked_add(1))
+            .reduce(gcd)
+            .and_then(|interval| u32::try_from(interval).ok());
+        let config = EncoderConfig {
+            checksum: a.checksum.is_some(),
+            checkpoint_interval,
+            metadata: a.metadata.clone(),
+            ..Default::default()
+        };
+        let mut encoder = Encoder::resume(a, config)?;
+        for result in Decoder::samples(b) {
+            let sample = result?;
+            if let Some(last) = encoder.last_point() {
+                if sample.timestamp() <= last.timestamp {
+                    return Err(MergeError::Overlap {
+                        a_end: last.timestamp,
+                        b_start: sample.timestamp(),
+                    });
+                }
+            }
+            match sample {
+                Sample::Value(dp) => encoder.encode(dp)?,
+                Sample::Missing(timestamp) => encoder.encode_gap(timestamp)?,
+            }
+        }
+        encoder.finish().map_err(EncodeError::from)?;
+        let mut merged = encoder.into_compressed();
+        merged.complete_until = merged_watermark([a.complete_until, b.complete_until]);
+        Ok(merged)
+    }
+
+    /// Serializes the block into a self-contained byte representation:
+    ///
+    /// | field        | size          |
+    /// |--------------|---------------|
+    /// | version      | 1 byte        |
+    /// | flags        | 1 byte        |
+    /// | count        | 8 bytes (LE)  |
+    /// | total bits   | 8 bytes (LE)  |
+    /// | checksum     | 4 bytes (LE), only if flagged |
+    /// | statistics   | 5 × 8 bytes (LE), only if flagged: start and end timestamp, min, max and sum bits |
+    /// | index        | only if flagged: checkpoint count (4 bytes LE), then per checkpoint point index, bit offset, timestamp, delta and value bits (5 × 8 bytes LE) and leading and trailing zeros (2 × 1 byte) |
+    /// | watermark    | 8 bytes (LE), only if flagged |
+    /// | priors       | 2 × 8 bytes (LE), only if flagged: interval and value bits |
+    /// | codecs       | 1 byte, only if flagged: [`TimestampScheme::id`] in bits 4–6, [`ValueScheme::id`] in bits 0–1, bit 2 set if the block has a [`quantizer`](CompressedBlock::quantizer), bit 3 set if it has an [`epoch`](CompressedBlock::epoch) and bit 7 set for [`count_terminated`](CompressedBlock::count_terminated) streams |
+    /// | epoch        | varint, 7 bits per byte from the lowest, with the top bit set on all but the last byte, only if flagged in the codecs |
+    /// | quantizer    | 1 + 8 bytes (LE), only if flagged in the codecs: kind (0 for [`AbsError`](QuantizeSpec::AbsError), 1 for [`RelError`](QuantizeSpec::RelError)) and error bits |
+    /// | gaps         | only if flagged: gap count (4 bytes LE), then the index of each missing sample (8 bytes LE) |
+    /// | stream bytes | `ceil(total_bits / 8)` |
+    /// | metadata     | only if flagged: see [`BlockMetadata`], at most [`MAX_METADATA_LEN`](crate::metadata::MAX_METADATA_LEN) bytes |
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(62 + self.bytes().len());
+        let flags = self.flags();
+        let codecs = (self.count_terminated as u8) << 7
+            | self.timestamp_codec.id() << 4
+            | (self.epoch.is_some() as u8) << 3
+            | (self.quantizer.is_some() as u8) << 2
+            | self.value_codec.id();
+        out.push(BLOCK_FORMAT_VERSION);
+        out.push(flags);
+        out.extend_from_slice(&self.count.to_le_bytes());
+        out.extend_from_slice(&(self.total_bits as u64).to_le_bytes());
+        if let Some(checksum) = self.checksum {
+            out.extend_from_slice(&checksum.to_le_bytes());
+        }
+        if let Some(stats) = self.stats {
+            out.extend_from_slice(&stats.start_timestamp.to_le_bytes());
+            out.extend_from_slice(&stats.end_timestamp.to_le_bytes());
+            out.extend_from_slice(&stats.min_value.to_bits().to_le_bytes());
+            out.extend_from_slice(&stats.max_value.to_bits().to_le_bytes());
+            out.extend_from_slice(&stats.sum.to_bits().to_le_bytes());
+        }
+        if !self.index.is_empty() {
+            out.extend_from_slice(&(self.index.len() as u32).to_le_bytes());
+            for cp in &self.index {
+                out.extend_from_slice(&cp.point_index.to_le_bytes());
+                out.extend_from_slice(&cp.bit_offset.to_le_bytes());
+                out.extend_from_slice(&cp.timestamp.to_le_bytes());
+                out.extend_from_slice(&cp.delta.to_le_bytes());
+                out.extend_from_slice(&cp.value_bits.to_le_bytes());
+                out.push(cp.leading_zeros);
+                out.push(cp.trailing_zeros);
+            }
+        }
+        if let Some(complete_until) = self.complete_until {
+            out.extend_from_slice(&complete_until.to_le_bytes());
+        }
+        if let Some(priors) = self.priors {
+            out.extend_from_slice(&priors.interval.to_le_bytes());
+            out.extend_from_slice(&priors.value.to_bits().to_le_bytes());
+        }
+        if flags & FLAG_CODEC != 0 {
+            out.push(codecs);
+        }
+        if let Some(mut epoch) = self.epoch {
+            while epoch >= 0x80 {
+                out.push(0x80 | (epoch & 0x7F) as u8);
+                epoch >>= 7;
+            }
+            out.push(epoch as u8);
+        }
+        if let Some(quantizer) = self.quantizer {
+            out.push(quantizer.id());
+            out.extend_from_slice(&quantizer.param().to_bits().to_le_bytes());
+        }
+        if !self.gaps.is_empty() {
+            out.extend_from_slice(&(self.gaps.len() as u32).to_le_bytes());
+            for gap in &self.gaps {
+                out.extend_from_slice(&gap.to_le_bytes());
+            }
+        }
+        out.extend_from_slice(self.bytes());
+        if !self.metadata.is_empty() {
+            self.metadata.write_to(&mut out);
+        }
+        out
+    }
+
+    /// Returns the header flags [`to_bytes`](CompressedBlock::to_bytes)
+    /// writes for this block.
+    pub(crate) fn flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.checksum.is_some() {
+            flags |= FLAG_CHECKSUM;
+        }
+        if self.stats.is_some() {
+            flags |= FLAG_STATS;
+        }
+        if !self.index.is_empty() {
+            flags |= FLAG_INDEX;
+        }
+        if !self.metadata.is_empty() {
+            flags |= FLAG_METADATA;
+        }
+        if self.complete_until.is_some() {
+            flags |= FLAG_WATERMARK;
+        }
+        if self.priors.is_some() {
+            flags |= FLAG_PRIORS;
+        }
+        if self.timestamp_codec != TimestampScheme::default()
+            || self.value_codec != ValueScheme::default()
+            || self.count_terminated
+            || self.epoch.is_some()
+            || self.quantizer.is_some()
+        {
+            flags |= FLAG_CODEC;
+        }
+        if !self.gaps.is_empty() {
+            flags |= FLAG_GAPS;
+        }
+        flags
+    }
+}
+
+impl CompressedBlock {
+    /// Parses a block produced by [`to_bytes`](CompressedBlock::to_bytes).
+    /// `bytes` must contain exactly one serialized block.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let (block, used) = Self::read_from(bytes)?;
+        if used != bytes.len() {
+            return Err(DecodeError::MalformedHeader("trailing bytes after block"));
+        }
+        Ok(block)
+    }
+
+    /// Parses a serialized block from the front of `bytes`, returning it with
+    /// the number of bytes consumed.
+    pub(crate) fn read_from(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let mut pos = 0;
+        let mut take = |n: usize| -> Result<&[u8], DecodeError> {
+            let slice = bytes.get(pos..pos + n).ok_or(DecodeError::UnexpectedEnd)?;
+            pos += n;
+            Ok(slice)
+        };
+        let version = take(1)?[0];
+        if version != BLOCK_FORMAT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        // Every flag bit is assigned, so there are no unknown flags to reject.
+        let flags = take(1)?[0];
+        let count = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let total_bits = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let total_bits = usize::try_from(total_bits)
+            .map_err(|_| DecodeError::MalformedHeader("total bits out of range"))?;
+        let checksum = if flags & FLAG_CHECKSUM != 0 {
+            Some(u32::from_le_bytes(take(4)?.try_into().unwrap()))
+        } else {
+            None
+        };
+        let stats = if flags & FLAG_STATS != 0 {
+            let mut word = || -> Result<u64, DecodeError> {
+                Ok(u64::from_le_bytes(take(8)?.try_into().unwrap()))
+            };
+            Some(BlockStats {
+                start_timestamp: word()?,
+                end_timestamp: word()?,
+                min_value: f64::from_bits(word()?),
+                max_value: f64::from_bits(word()?),
+                sum: f64::from_bits(word()?),
+            })
+        } else {
+            None
+        };
+        let mut index = Vec::new();
+        if flags & FLAG_INDEX != 0 {
+            let len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+            let len = len
+                .checked_mul(CHECKPOINT_LEN)
+                .ok_or(DecodeError::UnexpectedEnd)?;
+            for chunk in take(len)?.chunks_exact(CHECKPOINT_LEN) {
+                let word = |i: usize| {
+                    u64::from_le_bytes(chunk[i * 8..i * 8 + 8].try_into().unwrap())
+                };
+                index.push(Checkpoint {
+                    point_index: word(0),
+                    bit_offset: word(1),
+                    timestamp: word(2),
+                    delta: word(3) as i64,
+                    value_bits: word(4),
+                    leading_zeros: chunk[40],
+                    trailing_zeros: chunk[41],
+                });
+            }
+        }
+        let complete_until = if flags & FLAG_WATERMARK != 0 {
+            Some(u64::from_le_bytes(take(8)?.try_into().unwrap()))
+        } else {
+            None
+        };
+        let priors = if flags & FLAG_PRIORS != 0 {
+            let mut word = || -> Result<u64, DecodeError> {
+                Ok(u64::from_le_bytes(take(8)?.try_into().unwrap()))
+            };
+            Some(Priors {
+                interval: word()?,
+                value: f64::from_bits(word()?),
+            })
+        } else {
+            None
+        };
+        let codecs = if flags & FLAG_CODEC != 0 {
+            take(1)?[0]
+        } else {
+            0
+        };
+        let timestamp_codec = TimestampScheme::from_id(codecs >> 4 & 0x07)
+            .ok_or(DecodeError::MalformedHeader("unknown timestamp codec"))?;
+        let value_codec = ValueScheme::from_id(codecs & 0x03)
+            .ok_or(DecodeError::MalformedHeader("unknown value codec"))?;
+        let count_terminated = codecs & 0x80 != 0;
+        let epoch = if codecs & 0x08 != 0 {
+            let mut epoch = 0u64;
+            let mut shift = 0;
+            loop {
+                let byte = take(1)?[0];
+                let bits = u64::from(byte & 0x7F);
+                if shift >= 64 || (shift > 0 && bits >> (64 - shift) != 0) {
+                    return Err(DecodeError::MalformedHeader("epoch out of range"));
+                }
+                epoch |= bits << shift;
+                if byte & 0x80 == 0 {
+                    break;
+                }
+                shift += 7;
+            }
+            Some(epoch)
+        } else {
+            None
+        };
+        let quantizer = if codecs & 0x04 != 0 {
+            let id = take(1)?[0];
+            let param = f64::from_bits(u64::from_le_bytes(take(8)?.try_into().unwrap()));
+            Some(
+                QuantizeSpec::from_parts(id, param)
+                    .ok_or(DecodeError::MalformedHeader("unknown quantizer"))?,
+            )
+        } else {
+            None
+        };
+        let mut gaps = Vec::new();
+        if flags & FLAG_GAPS != 0 {
+            let len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+            let len = len.checked_mul(8).ok_or(DecodeError::UnexpectedEnd)?;
+            for chunk in take(len)?.chunks_exact(8) {
+                gaps.push(u64::from_le_bytes(chunk.try_into().unwrap()));
+            }
+            check_gaps(&gaps, count)?;
+        }
+        let stream = take(total_bits.div_ceil(8))?.to_vec();
+        let parts = check_parts(
+            stream.len(),
+            total_bits,
+            count,
+            timestamp_codec,
+            value_codec,
+        );
+        if parts.is_err() {
+            return Err(DecodeError::MalformedHeader("point count exceeds stream"));
+        }
+        check_index(&index, count, total_bits)?;
+        let metadata = if flags & FLAG_METADATA != 0 {
+            let (metadata, used) = BlockMetadata::read_from(&bytes[pos..])?;
+            pos += used;
+            metadata
+        } else {
+            BlockMetadata::default()
+        };
+        Ok((
+            CompressedBlock {
+                bytes: stream,
+                total_bits,
+                count,
+                checksum,
+                stats,
+                index,
+                metadata,
+                complete_until,
+                priors,
+                timestamp_codec,
+                value_codec,
+                count_terminated,
+                epoch,
+                quantizer,
+                gaps,
+            },
+            pos,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::DodResult;
+
+    #[test]
     fn test_encode_single_point() {
         let mut enc = Encoder::new();
-        enc.encode(DataPoint::new(1609459200, 42.0)).unwrap();
+        enc.encode(DataPoint::new(1609459200, 42.0)).unwrap();
+        enc.finish().unwrap();
+        assert_eq!(enc.count(), 1);
+        assert!(enc.buffer().len_bits() > 0);
+    }
+
+    #[test]
+    fn test_encode_identical_values() {
+        let mut enc = Encoder::new();
+        for i in 0..10 {
+            enc.encode(DataPoint::new(1609459200 + i * 60, 42.0)).unwrap();
+        }
+        enc.finish().unwrap();
+        assert_eq!(enc.count(), 10);
+        // Identical values should compress very efficiently.
+    }
+
+    #[test]
+    fn test_encode_varying_deltas() {
+        let mut enc = Encoder::new();
+        enc.encode(DataPoint::new(100, 1.0)).unwrap();
+        enc.encode(DataPoint::new(160, 2.0)).unwrap(); // delta=60
+        enc.encode(DataPoint::new(220, 3.0)).unwrap(); // delta=60, dod=0
+        enc.encode(DataPoint::new(290, 4.0)).unwrap(); // delta=70, dod=10
+        enc.encode(DataPoint::new(500, 5.0)).unwrap(); // delta=210, dod=140
+        enc.finish().unwrap();
+        assert_eq!(enc.count(), 5);
+    }
+
+    #[test]
+    fn test_every_dod_is_told_apart_from_end_marker() {
+        let dods = [
+            0,
+            -1,
+            1,
+            -64,
+            63,
+            -65,
+            64,
+            -2048,
+            2047,
+            -2049,
+            2048,
+            i64::from(i32::MIN),
+            -2,
+            i64::MIN,
+            i64::MIN + 1,
+            i64::MAX,
+        ];
+        let mut buf = BitBuffer::new();
+        for &dod in &dods {
+            let before = buf.len_bits();
+            write_delta_of_delta(&mut buf, dod).unwrap();
+            assert_eq!(buf.len_bits() - before, delta_of_delta_bits(dod));
+        }
+        buf.write_bits(0b1111, 4).unwrap();
+        buf.write_bits(u64::MAX, 64).unwrap();
+        let mut reader = BitReader::new(&buf);
+        for &dod in &dods {
+            assert_eq!(
+                Decoder::decode_delta_of_delta(&mut reader),
+                Ok(DodResult::Value(dod))
+            );
+        }
+        assert_eq!(
+            Decoder::decode_delta_of_delta(&mut reader),
+            Ok(DodResult::EndOfStream)
+        );
+    }
+
+    #[test]
+    fn test_encode_with_limit_ok() {
+        // 256 bytes is plenty for a few points with constant values.
+        let mut enc = Encoder::with_limit(256);
+        enc.encode(DataPoint::new(1609459200, 42.0)).unwrap();
+        enc.encode(DataPoint::new(1609459260, 42.0)).unwrap();
+        enc.encode(DataPoint::new(1609459320, 42.0)).unwrap();
+        enc.finish().unwrap();
+        assert_eq!(enc.count(), 3);
+    }
+
+    #[test]
+    fn test_out_of_order_reject() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            on_out_of_order: OutOfOrderPolicy::Reject,
+            ..Default::default()
+        });
+        enc.encode(DataPoint::new(200, 1.0)).unwrap();
+        let bits = enc.buffer().len_bits();
+        let err = enc.encode(DataPoint::new(100, 2.0)).unwrap_err();
+        assert_eq!(
+            err,
+            EncodeError::OutOfOrder {
+                previous: 200,
+                timestamp: 100
+            }
+        );
+        assert_eq!(enc.count(), 1);
+        assert_eq!(enc.buffer().len_bits(), bits);
+        // Equal timestamps are not out of order.
+        enc.encode(DataPoint::new(200, 3.0)).unwrap();
+        assert_eq!(enc.count(), 2);
+    }
+
+    #[test]
+    fn test_out_of_order_drop() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            on_out_of_order: OutOfOrderPolicy::Drop,
+            ..Default::default()
+        });
+        enc.encode(DataPoint::new(200, 1.0)).unwrap();
+        enc.encode(DataPoint::new(100, 2.0)).unwrap();
+        enc.encode(DataPoint::new(260, 3.0)).unwrap();
+        assert_eq!(enc.count(), 2);
+    }
+
+    #[test]
+    fn test_out_of_order_allowed_by_default() {
+        let mut enc = Encoder::new();
+        assert_eq!(
+            enc.config().on_out_of_order,
+            OutOfOrderPolicy::AllowNegativeDelta
+        );
+        enc.encode(DataPoint::new(200, 1.0)).unwrap();
+        enc.encode(DataPoint::new(100, 2.0)).unwrap();
+        assert_eq!(enc.count(), 2);
+    }
+
+    #[test]
+    fn test_duplicate_keep_first() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            on_duplicate: DuplicatePolicy::KeepFirst,
+            ..Default::default()
+        });
+        enc.encode(DataPoint::new(100, 1.0)).unwrap();
+        enc.encode(DataPoint::new(100, 2.0)).unwrap();
+        enc.encode(DataPoint::new(160, 3.0)).unwrap();
+        assert_eq!(enc.count(), 2);
+    }
+
+    #[test]
+    fn test_duplicate_keep_last_matches_clean_stream() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            on_duplicate: DuplicatePolicy::KeepLast,
+            ..Default::default()
+        });
+        enc.encode(DataPoint::new(100, 1.0)).unwrap();
+        enc.encode(DataPoint::new(100, 1.5)).unwrap();
+        enc.encode(DataPoint::new(160, 2.0)).unwrap();
+        enc.encode(DataPoint::new(160, 2.5)).unwrap();
+        enc.encode(DataPoint::new(160, 3.0)).unwrap();
+        enc.encode(DataPoint::new(220, 4.0)).unwrap();
+        assert_eq!(enc.count(), 3);
+
+        let mut clean = Encoder::new();
+        clean.encode(DataPoint::new(100, 1.5)).unwrap();
+        clean.encode(DataPoint::new(160, 3.0)).unwrap();
+        clean.encode(DataPoint::new(220, 4.0)).unwrap();
+        assert_eq!(enc.buffer().as_bytes(), clean.buffer().as_bytes());
+        assert_eq!(enc.buffer().len_bits(), clean.buffer().len_bits());
+    }
+
+    #[test]
+    fn test_duplicate_keep_last_restores_on_buffer_full() {
+        // 16 bytes for the first point plus 2 bytes: enough for a repeated
+        // value but not for a new XOR window.
+        let mut enc = Encoder::with_config(EncoderConfig {
+            max_bytes: Some(18),
+            on_duplicate: DuplicatePolicy::KeepLast,
+            ..Default::default()
+        });
+        enc.encode(DataPoint::new(100, 1.0)).unwrap();
+        enc.encode(DataPoint::new(160, 1.0)).unwrap();
+        let bytes = enc.buffer().as_bytes().to_vec();
+        let err = enc.encode(DataPoint::new(160, 1234.5678)).unwrap_err();
+        assert_eq!(err, EncodeError::BufferFull);
+        assert_eq!(enc.count(), 2);
+        assert_eq!(enc.buffer().as_bytes(), &bytes[..]);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn test_duplicate_keep_last_restores_at_every_failure_point() {
+        use crate::bitbuffer::FailingBitBuffer;
+
+        for value_codec in [ValueScheme::Gorilla, ValueScheme::Chimp] {
+            for fail_after in 150..230 {
+                let config = EncoderConfig {
+                    on_duplicate: DuplicatePolicy::KeepLast,
+                    value_codec,
+                    ..Default::default()
+                };
+                let mut enc = Encoder::with_buffer(FailingBitBuffer::new(fail_after), config);
+                enc.encode(DataPoint::new(100, 1.0)).unwrap();
+                enc.encode(DataPoint::new(160, 1.0)).unwrap();
+                let bytes = enc.buffer().as_bytes().to_vec();
+                match enc.encode(DataPoint::new(160, 1234.5678)) {
+                    Ok(()) => assert_eq!(enc.last_point().unwrap().value, 1234.5678),
+                    Err(err) => {
+                        assert_eq!(err, EncodeError::BufferFull);
+                        assert_eq!(enc.buffer().as_bytes(), &bytes[..], "{fail_after}");
+                        assert_eq!(enc.last_point(), Some(DataPoint::new(160, 1.0)));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_duplicate_reject() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            on_duplicate: DuplicatePolicy::Reject,
+            ..Default::default()
+        });
+        enc.encode(DataPoint::new(100, 1.0)).unwrap();
+        assert_eq!(
+            enc.encode(DataPoint::new(100, 1.0)),
+            Err(EncodeError::DuplicateTimestamp { timestamp: 100 })
+        );
+        assert_eq!(enc.count(), 1);
+    }
+
+    #[test]
+    fn test_point_ordering_and_exact_comparison() {
+        let a = DataPoint::from((60, 1.0));
+        assert_eq!(<(u64, f64)>::from(a), (60, 1.0));
+        assert!(a < DataPoint::new(60, 2.0) && a < DataPoint::new(120, 0.0));
+        let nan = DataPoint::new(60, f64::NAN);
+        assert_ne!(nan, nan);
+        assert_eq!(a.partial_cmp(&nan), None);
+
+        assert_eq!(ExactPoint(nan), ExactPoint(nan));
+        assert_ne!(
+            ExactPoint(DataPoint::new(0, 0.0)),
+            ExactPoint(DataPoint::new(0, -0.0))
+        );
+        let mut points: Vec<_> = [(120, 1.0), (60, f64::NAN), (60, -1.0), (60, 1.0)]
+            .into_iter()
+            .map(|p| ExactPoint(p.into()))
+            .collect();
+        points.sort();
+        let timestamps: Vec<_> = points.iter().map(|p| p.0.timestamp).collect();
+        assert_eq!(timestamps, [60, 60, 60, 120]);
+        assert_eq!(points[0].0.value, -1.0);
+        assert!(points[2].0.value.is_nan());
+
+        let set: std::collections::HashSet<_> = points.iter().chain(&points).collect();
+        assert_eq!(set.len(), 4);
+    }
+
+    #[test]
+    fn test_block_display_and_debug() {
+        let mut enc = Encoder::new();
+        for i in 0..100u64 {
+            enc.encode(DataPoint::new(1_000 + i * 10, (i % 7) as f64))
+                .unwrap();
+        }
+        let block = enc.finish_into().unwrap();
+        let summary = format!(
+            "100 points, {} bytes, {:.1} bits/point, 1000..=1990",
+            block.bytes.len(),
+            block.total_bits as f64 / 100.0
+        );
+        assert_eq!(block.to_string(), summary);
+        let empty = CompressedBlock::from_parts(Vec::new(), 0, 0).unwrap();
+        assert_eq!(empty.to_string(), "0 points, 0 bytes, 0.0 bits/point");
+
+        // Plain Debug lists every byte, the alternate form only the first.
+        let plain = format!("{block:?}");
+        assert!(plain.contains(&format!("bytes: {:?}", block.bytes)));
+        let pretty = format!("{block:#?}");
+        let hex: Vec<_> = block.bytes[..DEBUG_HEX_BYTES]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        let rest = block.bytes.len() - DEBUG_HEX_BYTES;
+        assert!(pretty.contains(&format!("bytes: {} … ({rest} more),", hex.join(" "))));
+        assert!(pretty.contains("count: 100,"));
+    }
+
+    #[test]
+    fn test_encode_after_finish_is_an_error() {
+        let points: Vec<_> = (0..20u64).map(|i| DataPoint::new(i * 60, 1.0)).collect();
+        let mut enc = Encoder::new();
+        for &dp in &points[..10] {
+            enc.encode(dp).unwrap();
+        }
+        enc.finish().unwrap();
+        let block = enc.to_compressed();
+        assert_eq!(enc.encode(points[10]), Err(EncodeError::Finished));
+        assert_eq!(enc.encode_gap(600), Err(EncodeError::Finished));
+        #[cfg(feature = "simd")]
+        assert_eq!(enc.encode_batch(&points[10..]), Err(EncodeError::Finished));
+        assert_eq!(enc.into_compressed().to_bytes(), block.to_bytes());
+    }
+
+    #[test]
+    fn test_timestamp_out_of_range() {
+        let far = i64::MAX as u64;
+        for codec in [TimestampScheme::DeltaOfDelta, TimestampScheme::Raw] {
+            let mut enc = Encoder::with_config(EncoderConfig {
+                timestamp_codec: codec,
+                ..Default::default()
+            });
+            enc.encode(DataPoint::new(0, 1.0)).unwrap();
+            assert_eq!(
+                enc.encode(DataPoint::new(u64::MAX, 1.0)),
+                Err(EncodeError::TimestampOutOfRange {
+                    previous: 0,
+                    timestamp: u64::MAX
+                })
+            );
+            enc.encode(DataPoint::new(far, 2.0)).unwrap();
+            // Back down by as much: the distance fits, its change does not.
+            let back = enc.encode(DataPoint::new(0, 3.0));
+            assert_eq!(back.is_ok(), codec == TimestampScheme::Raw, "{codec:?}");
+            enc.finish().unwrap();
+            let block = enc.into_compressed();
+            let points = Decoder::decode_strict(&block, 10).unwrap();
+            assert_eq!(points[1], DataPoint::new(far, 2.0));
+            assert_eq!(points.len() as u64, block.count());
+        }
+    }
+
+    #[test]
+    fn test_to_compressed_leaves_encoder_open() {
+        let mut enc = Encoder::with_limit(16);
+        enc.encode(DataPoint::new(100, 1.0)).unwrap();
+        let snapshot = enc.to_compressed();
+        assert_eq!(snapshot.count, 1);
+        assert_eq!(snapshot.total_bits, 128 + 68);
+        assert_eq!(enc.buffer().len_bits(), 128);
+        assert_eq!(enc.first_timestamp(), Some(100));
+    }
+
+    #[test]
+    fn test_block_bytes_roundtrip() {
+        for checksum in [false, true] {
+            let mut enc = Encoder::with_config(EncoderConfig {
+                checksum,
+                ..Default::default()
+            });
+            for i in 0..10 {
+                enc.encode(DataPoint::new(100 + i * 60, i as f64)).unwrap();
+            }
+            enc.finish().unwrap();
+            let block = enc.into_compressed();
+            let bytes = block.to_bytes();
+            let parsed = CompressedBlock::from_bytes(&bytes).unwrap();
+            assert_eq!(parsed.bytes, block.bytes);
+            assert_eq!(parsed.total_bits, block.total_bits);
+            assert_eq!(parsed.count, block.count);
+            assert_eq!(parsed.checksum, block.checksum);
+            assert_eq!(parsed.stats, block.stats);
+
+            assert_eq!(
+                CompressedBlock::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(),
+                DecodeError::UnexpectedEnd
+            );
+        }
+    }
+
+    #[test]
+    fn test_block_bytes_rejects_unknown_version() {
+        let mut bytes = Encoder::new().to_compressed().to_bytes();
+        bytes[0] = 99;
+        assert_eq!(
+            CompressedBlock::from_bytes(&bytes).unwrap_err(),
+            DecodeError::UnsupportedVersion(99)
+        );
+    }
+
+    #[test]
+    fn test_encode_with_limit_exceeded() {
+        // 1 byte can't even fit the first 64-bit timestamp.
+        let mut enc = Encoder::with_limit(1);
+        let result = enc.encode(DataPoint::new(1609459200, 42.0));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_stream_ends_at_last_point_after_buffer_full() {
+        let points: Vec<_> = (0..20)
+            .map(|i| DataPoint::new(1609459200 + i * 60 + i % 7, (i as f64).sqrt()))
+            .collect();
+        for limit in 17..40 {
+            let mut enc = Encoder::with_limit(limit);
+            let stored = points
+                .iter()
+                .take_while(|&&dp| enc.encode(dp).is_ok())
+                .count();
+            assert!(stored < points.len());
+            assert_eq!(enc.stats().points, stored as u64);
+            assert_eq!(
+                Decoder::decode(&enc.to_compressed()).unwrap(),
+                points[..stored]
+            );
+        }
+    }
+
+    #[test]
+    fn test_estimate_size_bounds_growth() {
+        let schemes = [
+            (TimestampScheme::DeltaOfDelta, ValueScheme::Gorilla),
+            (TimestampScheme::RunLength, ValueScheme::Gorilla),
+            (TimestampScheme::Delta, ValueScheme::Chimp),
+            (TimestampScheme::Raw, ValueScheme::Raw),
+        ];
+        for (timestamp_codec, value_codec) in schemes {
+            let config = EncoderConfig {
+                timestamp_codec,
+                value_codec,
+                priors: Some(Priors {
+                    interval: 60,
+                    value: 20.0,
+                }),
+                ..Default::default()
+            };
+            let mut enc = Encoder::with_config(config);
+            for i in 0..200u64 {
+                let jitter = if i % 50 < 30 { 0 } else { i % 7 };
+                let dp = DataPoint::new(i * 60 + jitter, 20.0 + (i % 9) as f64 * 0.25);
+                let (estimate, before) = (enc.estimate_size(dp), enc.buffer().len_bits());
+                enc.encode(dp).unwrap();
+                let grown = enc.buffer().len_bits() - before;
+                if value_codec == ValueScheme::Chimp {
+                    assert!(grown <= estimate, "{grown} > {estimate} at {i}");
+                } else {
+                    assert_eq!(grown, estimate, "{timestamp_codec:?} at {i}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_would_fit_leaves_room_to_finish() {
+        use crate::bitbuffer::FixedBitBuffer;
+
+        let mut enc = Encoder::with_buffer(FixedBitBuffer::new([0u8; 40]), Default::default());
+        let mut i = 0;
+        loop {
+            let dp = DataPoint::new(i * 60, (i * i) as f64);
+            if !enc.would_fit(dp) {
+                break;
+            }
+            enc.encode(dp).unwrap();
+            i += 1;
+        }
+        assert!(i > 1);
+        enc.finish().unwrap();
+        assert!(!enc.would_fit(DataPoint::new(i * 60, 0.0)));
+        assert!(Encoder::new().would_fit(DataPoint::new(0, 0.0)));
+    }
+
+    #[test]
+    fn test_fixed_buffer_matches_growable_buffer() {
+        use crate::bitbuffer::FixedBitBuffer;
+
+        let points: Vec<_> = (0..20)
+            .map(|i| DataPoint::new(1609459200 + i * 60, 20.0 + (i % 3) as f64))
+            .collect();
+        let mut growable = Encoder::new();
+        let mut fixed = Encoder::with_buffer(FixedBitBuffer::new([0u8; 256]), Default::default());
+        for &dp in &points {
+            growable.encode(dp).unwrap();
+            fixed.encode(dp).unwrap();
+        }
+        growable.finish().unwrap();
+        fixed.finish().unwrap();
+        assert_eq!(fixed.buffer().as_bytes(), growable.buffer().as_bytes());
+        assert_eq!(fixed.buffer().len_bits(), growable.buffer().len_bits());
+
+        let mut tiny = Encoder::with_buffer(FixedBitBuffer::new([0u8; 8]), Default::default());
+        assert_eq!(
+            tiny.encode(DataPoint::new(1609459200, 1.0)),
+            Err(EncodeError::BufferFull)
+        );
+    }
+
+    #[test]
+    fn test_block_over_borrowed_and_shared_storage() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            checksum: true,
+            checkpoint_interval: Some(8),
+            ..Default::default()
+        });
+        for i in 0..40 {
+            enc.encode(DataPoint::new(i * 15, i as f64 * 0.25)).unwrap();
+        }
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+        let points = Decoder::decode(&block).unwrap();
+
+        let shared: CompressedBlock<std::sync::Arc<[u8]>> = block.clone().into_storage();
+        assert_eq!(Decoder::decode(&shared).unwrap(), points);
+        assert_eq!(shared.to_bytes(), block.to_bytes());
+        assert_eq!(Decoder::iter_from(&shared, 300).count(), 20);
+        let merged = CompressedBlock::merge(&shared, &shared.clone());
+        assert!(matches!(merged, Err(MergeError::Overlap { .. })));
+
+        let borrowed = CompressedBlock::from_parts(block.bytes(), block.total_bits, 40).unwrap();
+        assert_eq!(Decoder::decode(&borrowed).unwrap(), points);
+        let resumed = Encoder::resume(&borrowed, EncoderConfig::default()).unwrap();
+        assert_eq!(resumed.last_point(), points.last().copied());
+    }
+
+    #[test]
+    fn test_merge_matches_single_encode() {
+        let points: Vec<_> = (0..40)
+            .map(|i| DataPoint::new(1000 + i * 15 + i % 4, (i as f64 * 0.3).sin()))
+            .collect();
+        let encode = |points: &[DataPoint]| {
+            let mut enc = Encoder::with_config(EncoderConfig {
+                checksum: true,
+                ..Default::default()
+            });
+            for &dp in points {
+                enc.encode(dp).unwrap();
+            }
+            enc.finish().unwrap();
+            enc.into_compressed()
+        };
+        let whole = encode(&points);
+        for split in [1, 2, 17, 39] {
+            let merged =
+                CompressedBlock::merge(&encode(&points[..split]), &encode(&points[split..]))
+                    .unwrap();
+            assert_eq!(merged.bytes, whole.bytes);
+            assert_eq!(merged.total_bits, whole.total_bits);
+            assert_eq!(merged.count, whole.count);
+            assert_eq!(merged.checksum, whole.checksum);
+            assert_eq!(merged.stats, whole.stats);
+        }
+    }
+
+    #[test]
+    fn test_merge_indexes_both_halves() {
+        let points: Vec<_> = (0..40)
+            .map(|i| DataPoint::new(1000 + i * 15 + i % 4, (i as f64 * 0.3).sin()))
+            .collect();
+        let encode = |points: &[DataPoint]| {
+            let mut enc = Encoder::with_config(EncoderConfig {
+                checkpoint_interval: Some(8),
+                ..Default::default()
+            });
+            for &dp in points {
+                enc.encode(dp).unwrap();
+            }
+            enc.finish_into().unwrap()
+        };
+        let whole = encode(&points);
+        assert_eq!(whole.index.len(), 5);
+        for split in [3, 8, 17, 39] {
+            let merged =
+                CompressedBlock::merge(&encode(&points[..split]), &encode(&points[split..]))
+                    .unwrap();
+            assert_eq!(merged.index, whole.index);
+            assert_eq!(
+                Decoder::iter_from(&merged, points[35].timestamp)
+                    .map(Result::unwrap)
+                    .collect::<Vec<_>>(),
+                points[35..]
+            );
+        }
+    }
+
+    #[test]
+    fn test_watermark_roundtrip_and_merge() {
+        let block = |range: std::ops::Range<u64>, complete_until| {
+            let mut enc = Encoder::new();
+            for t in range {
+                enc.encode(DataPoint::new(t * 10, 1.0)).unwrap();
+            }
+            enc.finish().unwrap();
+            CompressedBlock {
+                complete_until,
+                ..enc.into_compressed()
+            }
+        };
+        let a = block(0..5, Some(60));
+        let parsed = CompressedBlock::from_bytes(&a.to_bytes()).unwrap();
+        assert_eq!(parsed.complete_until, Some(60));
+        assert_eq!(parsed.bytes, a.bytes);
+
+        let merged = CompressedBlock::merge(&a, &block(10..15, Some(200))).unwrap();
+        assert_eq!(merged.complete_until, Some(60));
+        let merged = CompressedBlock::merge(&a, &block(10..15, None)).unwrap();
+        assert_eq!(merged.complete_until, None);
+    }
+
+    #[test]
+    fn test_gaps_roundtrip_on_every_path() {
+        let block = |range: std::ops::Range<u64>| {
+            let mut enc = Encoder::new();
+            for t in range {
+                if t % 4 == 1 {
+                    enc.encode_gap(t * 10).unwrap();
+                } else {
+                    enc.encode(DataPoint::new(t * 10, t as f64)).unwrap();
+                }
+            }
+            enc.finish().unwrap();
+            enc.into_compressed()
+        };
+        let samples = |range: std::ops::Range<u64>| -> Vec<Sample> {
+            range
+                .map(|t| match t % 4 {
+                    1 => Sample::Missing(t * 10),
+                    _ => Sample::Value(DataPoint::new(t * 10, t as f64)),
+                })
+                .collect()
+        };
+        let a = block(0..10);
+        assert_eq!(a.gaps, [1, 5, 9]);
+        assert_eq!(Decoder::decode_samples(&a).unwrap(), samples(0..10));
+        let points = Decoder::decode(&a).unwrap();
+        assert!(points[5].value.is_nan());
+        assert_eq!(points[6], DataPoint::new(60, 6.0));
+        // Gaps widen the time range but are not values.
+        assert_eq!(a.end_timestamp(), Some(90));
+        assert_eq!(a.sum(), Some(0.0 + 2.0 + 3.0 + 4.0 + 6.0 + 7.0 + 8.0));
+
+        let parsed = CompressedBlock::from_bytes(&a.to_bytes()).unwrap();
+        assert_eq!(parsed.gaps, a.gaps);
+        let mut bytes = a.to_bytes();
+        let at = bytes.len() - a.bytes.len() - 8;
+        bytes[at..at + 8].copy_from_slice(&10u64.to_le_bytes());
+        assert_eq!(
+            CompressedBlock::from_bytes(&bytes).unwrap_err(),
+            DecodeError::MalformedHeader("gaps out of order")
+        );
+
+        let merged = CompressedBlock::merge(&a, &block(10..20)).unwrap();
+        assert_eq!(Decoder::decode_samples(&merged).unwrap(), samples(0..20));
+        assert_eq!(merged.stats, block(0..20).stats);
+    }
+
+    #[test]
+    fn test_gap_replaced_under_keep_last() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            on_duplicate: DuplicatePolicy::KeepLast,
+            ..Default::default()
+        });
+        enc.encode(DataPoint::new(0, 1.0)).unwrap();
+        enc.encode_gap(10).unwrap();
+        enc.encode(DataPoint::new(10, 2.0)).unwrap();
+        enc.encode(DataPoint::new(20, 3.0)).unwrap();
+        enc.encode_gap(20).unwrap();
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+        assert_eq!(block.gaps, [2]);
+        assert_eq!(
+            Decoder::decode_samples(&block).unwrap(),
+            [
+                Sample::Value(DataPoint::new(0, 1.0)),
+                Sample::Value(DataPoint::new(10, 2.0)),
+                Sample::Missing(20),
+            ]
+        );
+        assert_eq!(block.max_value(), Some(2.0));
+    }
+
+    #[test]
+    fn test_priors_roundtrip_on_every_path() {
+        let points: Vec<_> = (0..30u64)
+            .map(|i| DataPoint::new(1000 + i * 10 + i % 3, 50.0 + (i % 4) as f64))
+            .collect();
+        let encode = |priors, points: &[DataPoint]| {
+            let mut enc = Encoder::with_config(EncoderConfig {
+                priors,
+                checkpoint_interval: Some(8),
+                ..Default::default()
+            });
+            for &dp in points {
+                enc.encode(dp).unwrap();
+            }
+            enc.finish().unwrap();
+            enc.into_compressed()
+        };
+        let priors = Some(Priors {
+            interval: 10,
+            value: 50.0,
+        });
+        let block = encode(priors, &points);
+        assert!(block.total_bits < encode(None, &points).total_bits);
+        let parsed = CompressedBlock::from_bytes(&block.to_bytes()).unwrap();
+        assert_eq!(parsed.priors, priors);
+        assert_eq!(Decoder::decode(&parsed).unwrap(), points);
+        assert_eq!(Decoder::decode_strict(&block, 100).unwrap(), points);
+        let iterated: Vec<_> = Decoder::iter(&block).map(Result::unwrap).collect();
+        assert_eq!(iterated, points);
+        assert_eq!(
+            Decoder::iter_from(&block, 1200).next().unwrap().unwrap(),
+            points[20]
+        );
+        let inspected: Vec<_> = Decoder::inspect(&block)
+            .unwrap()
+            .iter()
+            .map(|r| r.point)
+            .collect();
+        assert_eq!(inspected, points);
+
+        // Resumed and merged streams keep the priors they were encoded with.
+        let merged =
+            CompressedBlock::merge(&encode(priors, &points[..12]), &encode(None, &points[12..]))
+                .unwrap();
+        assert_eq!(merged.priors, priors);
+        assert_eq!(Decoder::decode(&merged).unwrap(), points);
+
+        // Priors far off the data still roundtrip.
+        let off = Some(Priors {
+            interval: u64::MAX,
+            value: f64::NAN,
+        });
+        assert_eq!(Decoder::decode(&encode(off, &points)).unwrap(), points);
+    }
+
+    #[test]
+    fn test_count_terminated_roundtrip_on_every_path() {
+        let points: Vec<_> = (0..40u64)
+            .map(|i| DataPoint::new(1000 + i * 10 + i % 3, (i % 5) as f64))
+            .collect();
+        let encode = |count_terminated, timestamp_codec, points: &[DataPoint]| {
+            let mut enc = Encoder::with_config(EncoderConfig {
+                count_terminated,
+                timestamp_codec,
+                checkpoint_interval: Some(8),
+                ..Default::default()
+            });
+            for &dp in points {
+                enc.encode(dp).unwrap();
+            }
+            enc.finish().unwrap();
+            enc.into_compressed()
+        };
+        for codec in [TimestampScheme::DeltaOfDelta, TimestampScheme::RunLength] {
+            let block = encode(true, codec, &points);
+            assert!(block.count_terminated);
+            assert_eq!(
+                block.total_bits + END_MARKER_BITS,
+                encode(false, codec, &points).total_bits
+            );
+            let parsed = CompressedBlock::from_bytes(&block.to_bytes()).unwrap();
+            assert!(parsed.count_terminated);
+            assert_eq!(parsed.timestamp_codec, codec);
+            assert_eq!(Decoder::decode(&parsed).unwrap(), points);
+            assert_eq!(Decoder::decode_strict(&block, 100).unwrap(), points);
+            let iterated: Vec<_> = Decoder::iter(&block).map(Result::unwrap).collect();
+            assert_eq!(iterated, points);
+            assert_eq!(
+                Decoder::iter_from(&block, 1200).next().unwrap().unwrap(),
+                points[20]
+            );
+            assert_eq!(Decoder::last(&block).unwrap(), points.last().copied());
+            assert_eq!(Decoder::inspect(&block).unwrap().len(), points.len());
+
+            // Resumed and merged streams stay unterminated.
+            let merged = CompressedBlock::merge(
+                &encode(true, codec, &points[..15]),
+                &encode(false, codec, &points[15..]),
+            )
+            .unwrap();
+            assert!(merged.count_terminated);
+            assert_eq!(merged.bytes, block.bytes);
+        }
+
+        // Without the count the stream runs off its end.
+        let block = encode(true, TimestampScheme::DeltaOfDelta, &points);
+        let err = Decoder::decode_raw(block.bytes(), block.total_bits).unwrap_err();
+        assert_eq!(err.kind(), &DecodeError::UnexpectedEnd);
+    }
+
+    #[test]
+    fn test_would_fit_without_end_marker() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            max_bytes: Some(18),
+            count_terminated: true,
+            ..Default::default()
+        });
+        // 128 bits for the first point leave room for a second one only
+        // when no marker has to follow.
+        let second = DataPoint::new(60, 1.0);
+        enc.encode(DataPoint::new(0, 1.0)).unwrap();
+        assert!(enc.would_fit(second));
+        enc.encode(second).unwrap();
+        enc.finish().unwrap();
+        assert_eq!(enc.len_bits(), 128 + 9 + 1);
+
+        let mut marked = Encoder::with_limit(18);
+        marked.encode(DataPoint::new(0, 1.0)).unwrap();
+        assert!(!marked.would_fit(second));
+    }
+
+    #[test]
+    fn test_capacity_hint_presizes_buffer() {
+        // A steady series, which takes fewer bits per point than the hint.
+        let mut enc = Encoder::with_capacity_hint(1_000);
+        let start = enc.buffer().as_bytes().as_ptr();
+        for i in 0..1_000u64 {
+            enc.encode(DataPoint::new(i * 60, (i / 100) as f64)).unwrap();
+        }
+        enc.finish().unwrap();
+        assert!(enc.len_bits() < 1_000 * HINT_BITS_PER_POINT);
+        assert_eq!(enc.buffer().as_bytes().as_ptr(), start);
+        // The hint is not a limit.
+        enc = Encoder::with_capacity_hint(0);
+        for i in 0..100u64 {
+            enc.encode(DataPoint::new(i, i as f64)).unwrap();
+        }
+        assert_eq!(enc.count(), 100);
+    }
+
+    #[test]
+    fn test_epoch_roundtrip_on_every_path() {
+        let epoch = 1_609_459_200;
+        let points: Vec<_> = (0..30u64)
+            .map(|i| DataPoint::new(epoch + 1_000 + i * 10, (i % 4) as f64))
+            .collect();
+        let encode = |epoch, points: &[DataPoint]| {
+            let mut enc = Encoder::with_config(EncoderConfig {
+                epoch,
+                checkpoint_interval: Some(8),
+                ..Default::default()
+            });
+            for &dp in points {
+                enc.encode(dp).unwrap();
+            }
+            enc.finish().unwrap();
+            enc.into_compressed()
+        };
+        let block = encode(Some(epoch), &points);
+        assert_eq!(block.epoch, Some(epoch));
+        // An offset of 1000 takes a two-byte varint instead of 64 bits.
+        assert_eq!(block.total_bits + 48, encode(None, &points).total_bits);
+        assert_eq!(Decoder::decode(&block).unwrap(), points);
+        assert_eq!(Decoder::decode_strict(&block, 100).unwrap(), points);
+        let iterated: Vec<_> = Decoder::iter(&block).map(Result::unwrap).collect();
+        assert_eq!(iterated, points);
+        assert_eq!(Decoder::last(&block).unwrap(), points.last().copied());
+        assert_eq!(Decoder::inspect(&block).unwrap()[0].timestamp_bits, 16);
+
+        // The epoch is serialized as a varint after the codecs byte.
+        let bytes = block.to_bytes();
+        let parsed = CompressedBlock::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.epoch, Some(epoch));
+        assert_eq!(Decoder::decode(&parsed).unwrap(), points);
+        let plain = encode(None, &points).to_bytes();
+        assert_eq!(bytes.len(), plain.len() - 6 + 1 + 5);
+        let max = Encoder::with_config(EncoderConfig {
+            epoch: Some(u64::MAX),
+            ..Default::default()
+        });
+        let max = CompressedBlock::from_bytes(&max.into_compressed().to_bytes()).unwrap();
+        assert_eq!(max.epoch, Some(u64::MAX));
+
+        // Resumed and merged streams keep the first block's epoch.
+        let merged = CompressedBlock::merge(
+            &encode(Some(epoch), &points[..10]),
+            &encode(None, &points[10..]),
+        )
+        .unwrap();
+        assert_eq!(merged.epoch, Some(epoch));
+        assert_eq!(merged.bytes, block.bytes);
+    }
+
+    #[test]
+    fn test_quantizer_is_recorded_and_kept() {
+        let values: Vec<f64> = (0..200)
+            .map(|i| 20.0 + (i as f64 * 0.05).sin() + (i * 7919 % 1000) as f64 * 1e-5)
+            .collect();
+        let encode = |quantizer, values: &[f64]| {
+            let mut enc = Encoder::with_config(EncoderConfig {
+                quantizer,
+                ..Default::default()
+            });
+            for (i, &value) in values.iter().enumerate() {
+                enc.encode(DataPoint::new(i as u64 * 60, value)).unwrap();
+            }
+            enc.finish().unwrap();
+            enc.into_compressed()
+        };
+        let spec = QuantizeSpec::RelError(1e-3);
+        let block = encode(Some(spec), &values);
+        assert!(block.total_bits * 2 < encode(None, &values).total_bits);
+        let decoded = Decoder::decode(&block).unwrap();
+        for (dp, &value) in decoded.iter().zip(&values) {
+            assert!((dp.value - value).abs() <= spec.max_error(value));
+        }
+
+        let mut bytes = block.to_bytes();
+        let parsed = CompressedBlock::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.quantizer, Some(spec));
+        assert_eq!(Decoder::decode(&parsed).unwrap(), decoded);
+        // The kind byte follows the codecs byte at the end of the header.
+        let kind = bytes.len() - block.bytes.len() - 9;
+        assert_eq!(bytes[kind - 1], 0b0000_0100);
+        bytes[kind] = 7;
+        assert_eq!(
+            CompressedBlock::from_bytes(&bytes).unwrap_err(),
+            DecodeError::MalformedHeader("unknown quantizer")
+        );
+
+        // Resumed streams keep quantizing to the block's grid.
+        let head = encode(Some(spec), &values[..50]);
+        let mut enc = Encoder::resume(&head, EncoderConfig::default()).unwrap();
+        enc.encode(DataPoint::new(50 * 60, values[50])).unwrap();
+        let resumed = enc.finish_into().unwrap();
+        assert_eq!(resumed.quantizer, Some(spec));
+        assert_eq!(Decoder::last(&resumed).unwrap(), Some(decoded[50]));
+    }
+
+    #[test]
+    fn test_resumed_block_keep_last() {
+        let mut enc = Encoder::new();
+        enc.encode(DataPoint::new(60, 1.0)).unwrap();
+        enc.encode(DataPoint::new(120, 2.0)).unwrap();
+        let head = enc.finish_into().unwrap();
+        let config = EncoderConfig {
+            on_duplicate: DuplicatePolicy::KeepLast,
+            ..Default::default()
+        };
+        let mut enc = Encoder::resume(&head, config).unwrap();
+        assert_eq!(
+            enc.encode(DataPoint::new(120, 3.0)),
+            Err(EncodeError::DuplicateTimestamp { timestamp: 120 })
+        );
+        // Points appended after resuming are replaced as usual.
+        enc.encode(DataPoint::new(180, 4.0)).unwrap();
+        enc.encode(DataPoint::new(180, 5.0)).unwrap();
+        assert_eq!(
+            Decoder::decode(&enc.finish_into().unwrap()).unwrap(),
+            [
+                DataPoint::new(60, 1.0),
+                DataPoint::new(120, 2.0),
+                DataPoint::new(180, 5.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dead_band_keeps_step_wise_series() {
+        let dead_band = DeadBand {
+            threshold: 0.5,
+            max_interval: 300,
+        };
+        let config = EncoderConfig {
+            dead_band: Some(dead_band),
+            ..Default::default()
+        };
+        let values: Vec<f64> = (0..100).map(|i| (i as f64 * 0.05).sin() * 3.0).collect();
+        let mut enc = Encoder::with_config(config.clone());
+        for (i, &value) in values.iter().enumerate() {
+            enc.encode(DataPoint::new(i as u64 * 60, value)).unwrap();
+        }
         enc.finish().unwrap();
-        assert_eq!(enc.count(), 1);
-        assert!(enc.buffer().len_bits() > 0);
+        let stored = Decoder::decode(&enc.into_compressed()).unwrap();
+        assert!(stored.len() < values.len() / 2);
+        // The last point is stored even though it is in the dead band.
+        assert_eq!(stored.last().unwrap().timestamp, 99 * 60);
+        for pair in stored.windows(2) {
+            assert!(pair[1].timestamp - pair[0].timestamp <= dead_band.max_interval);
+        }
+        let mut held = 0;
+        for (i, &value) in values.iter().enumerate() {
+            while stored
+                .get(held + 1)
+                .is_some_and(|dp| dp.timestamp <= i as u64 * 60)
+            {
+                held += 1;
+            }
+            assert!((stored[held].value - value).abs() < dead_band.threshold);
+        }
+
+        // Missing samples are always stored.
+        let mut enc = Encoder::with_config(config);
+        enc.encode(DataPoint::new(0, 1.0)).unwrap();
+        enc.encode_gap(60).unwrap();
+        enc.encode(DataPoint::new(120, 1.0)).unwrap();
+        enc.finish().unwrap();
+        let samples = Decoder::decode_samples(&enc.into_compressed()).unwrap();
+        assert_eq!(samples[1], Sample::Missing(60));
+        assert_eq!(samples.len(), 3);
     }
 
     #[test]
-    fn test_encode_identical_values() {
+    fn test_dead_band_held_point_orders_later_points() {
+        let encoder = |on_out_of_order, on_duplicate| {
+            let mut enc = Encoder::with_config(EncoderConfig {
+                dead_band: Some(DeadBand {
+                    threshold: 1.0,
+                    max_interval: 1_000,
+                }),
+                on_out_of_order,
+                on_duplicate,
+                ..Default::default()
+            });
+            enc.encode(DataPoint::new(10, 5.0)).unwrap();
+            // Held back by the dead band, but still the latest point.
+            enc.encode(DataPoint::new(20, 5.1)).unwrap();
+            enc
+        };
+
+        let mut enc = encoder(OutOfOrderPolicy::Reject, DuplicatePolicy::Allow);
+        assert_eq!(
+            enc.encode(DataPoint::new(15, 9.0)),
+            Err(EncodeError::OutOfOrder {
+                previous: 20,
+                timestamp: 15
+            })
+        );
+        let mut enc = encoder(OutOfOrderPolicy::Reject, DuplicatePolicy::Reject);
+        assert_eq!(
+            enc.encode(DataPoint::new(20, 9.0)),
+            Err(EncodeError::DuplicateTimestamp { timestamp: 20 })
+        );
+        enc.finish().unwrap();
+        let stored = Decoder::decode(&enc.into_compressed()).unwrap();
+        assert_eq!(stored, [DataPoint::new(10, 5.0), DataPoint::new(20, 5.1)]);
+
+        let mut enc = encoder(OutOfOrderPolicy::Reject, DuplicatePolicy::KeepLast);
+        enc.encode(DataPoint::new(20, 9.0)).unwrap();
+        enc.finish().unwrap();
+        let stored = Decoder::decode(&enc.into_compressed()).unwrap();
+        assert_eq!(stored, [DataPoint::new(10, 5.0), DataPoint::new(20, 9.0)]);
+    }
+
+    #[test]
+    fn test_first_timestamp_before_epoch() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            epoch: Some(3_600),
+            ..Default::default()
+        });
+        assert_eq!(
+            enc.encode(DataPoint::new(3_599, 1.0)),
+            Err(EncodeError::BeforeEpoch {
+                epoch: 3_600,
+                timestamp: 3_599
+            })
+        );
+        enc.encode(DataPoint::new(3_600, 1.0)).unwrap();
+        // Only the first point is stored relative to the epoch.
+        enc.encode(DataPoint::new(3_590, 2.0)).unwrap();
+        enc.finish().unwrap();
+        let points = Decoder::decode(&enc.into_compressed()).unwrap();
+        assert_eq!(points[0], DataPoint::new(3_600, 1.0));
+        assert_eq!(points[1], DataPoint::new(3_590, 2.0));
+    }
+
+    #[test]
+    fn test_chimp_roundtrip_on_every_path() {
+        // Readings that alternate between a few levels, which Gorilla XORs
+        // against the wrong neighbour.
+        let levels = [21.37, 19.02, 23.91, 20.48, 22.15];
+        let points: Vec<_> = (0..200u64)
+            .map(|i| DataPoint::new(1000 + i * 10, levels[(i * i % 7 % 5) as usize]))
+            .collect();
+        let encode = |value_codec, priors, points: &[DataPoint]| {
+            let mut enc = Encoder::with_config(EncoderConfig {
+                value_codec,
+                priors,
+                checkpoint_interval: Some(8),
+                on_duplicate: DuplicatePolicy::KeepLast,
+                ..Default::default()
+            });
+            for &dp in points {
+                enc.encode(dp).unwrap();
+                // Replaced at once, exercising the rollback.
+                enc.encode(DataPoint::new(dp.timestamp, -1.0)).unwrap();
+                enc.encode(dp).unwrap();
+            }
+            enc.finish().unwrap();
+            enc.into_compressed()
+        };
+        let block = encode(ValueScheme::Chimp, None, &points);
+        assert!(block.total_bits * 2 < encode(ValueScheme::Gorilla, None, &points).total_bits);
+        assert!(block.index.is_empty());
+        let parsed = CompressedBlock::from_bytes(&block.to_bytes()).unwrap();
+        assert_eq!(parsed.value_codec, ValueScheme::Chimp);
+        assert_eq!(Decoder::decode(&parsed).unwrap(), points);
+        assert_eq!(Decoder::decode_strict(&block, 1000).unwrap(), points);
+        assert_eq!(
+            Decoder::iter_from(&block, 1500).next().unwrap().unwrap(),
+            points[50]
+        );
+        let stats = CompressionStats::of_block(&block).unwrap();
+        assert_eq!(stats.points, 200);
+        assert_eq!(
+            (stats.timestamp_bits + stats.value_bits) as usize + END_MARKER_BITS,
+            block.total_bits
+        );
+
+        // Resumed streams and priors keep the codec.
+        let priors = Some(Priors {
+            interval: 10,
+            value: 21.37,
+        });
+        let head = encode(ValueScheme::Chimp, priors, &points[..150]);
+        let tail = encode(ValueScheme::Gorilla, None, &points[150..]);
+        let merged = CompressedBlock::merge(&head, &tail).unwrap();
+        assert_eq!(merged.value_codec, ValueScheme::Chimp);
+        assert_eq!(Decoder::decode(&merged).unwrap(), points);
+        assert_eq!(
+            merged.total_bits,
+            encode(ValueScheme::Chimp, priors, &points).total_bits
+        );
+    }
+
+    #[test]
+    fn test_codecs_mix_per_block() {
+        // Jittery timestamps a few seconds apart, where delta-of-delta pays
+        // for every jitter twice.
+        let points: Vec<_> = (0..300u64)
+            .map(|i| DataPoint::new(i * 5 + i * i % 3, (i % 11) as f64 * 0.5))
+            .collect();
+        let encode = |timestamp_codec, value_codec, points: &[DataPoint]| {
+            let mut enc = Encoder::with_config(EncoderConfig {
+                timestamp_codec,
+                value_codec,
+                checkpoint_interval: Some(16),
+                priors: Some(Priors {
+                    interval: 5,
+                    value: 0.0,
+                }),
+                ..Default::default()
+            });
+            for &dp in points {
+                enc.encode(dp).unwrap();
+            }
+            enc.finish().unwrap();
+            enc.into_compressed()
+        };
+        let bits = |timestamp_codec| {
+            let block = encode(timestamp_codec, ValueScheme::Gorilla, &points);
+            CompressionStats::of_block(&block).unwrap().timestamp_bits
+        };
+        assert!(bits(TimestampScheme::Delta) < bits(TimestampScheme::DeltaOfDelta));
+
+        let timestamp_codecs = [
+            TimestampScheme::DeltaOfDelta,
+            TimestampScheme::Delta,
+            TimestampScheme::RunLength,
+        ];
+        for timestamp_codec in timestamp_codecs {
+            for value_codec in [ValueScheme::Gorilla, ValueScheme::Chimp] {
+                let block = encode(timestamp_codec, value_codec, &points);
+                let parsed = CompressedBlock::from_bytes(&block.to_bytes()).unwrap();
+                assert_eq!(parsed.timestamp_codec, timestamp_codec);
+                assert_eq!(parsed.value_codec, value_codec);
+                assert_eq!(Decoder::decode(&parsed).unwrap(), points);
+                assert_eq!(Decoder::decode_strict(&block, 300).unwrap(), points);
+                let from: Vec<_> = Decoder::iter_from(&block, 1000)
+                    .collect::<Result<_, _>>()
+                    .unwrap();
+                assert_eq!(from, points[200..]);
+
+                // A block resumed under other codecs keeps its own.
+                let head = encode(timestamp_codec, value_codec, &points[..100]);
+                let tail = encode(TimestampScheme::Delta, ValueScheme::Chimp, &points[100..]);
+                let merged = CompressedBlock::merge(&head, &tail).unwrap();
+                assert_eq!(merged.timestamp_codec, timestamp_codec);
+                assert_eq!(merged.total_bits, block.total_bits);
+                assert_eq!(Decoder::decode(&merged).unwrap(), points);
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_length_timestamps() {
+        // A 15s scrape interval with one missed scrape.
+        let points: Vec<_> = (0..100_000u64)
+            .filter(|&i| i != 60_000)
+            .map(|i| DataPoint::new(1_600_000_000 + i * 15, (i % 7) as f64))
+            .collect();
+        let config = EncoderConfig {
+            timestamp_codec: TimestampScheme::RunLength,
+            checkpoint_interval: Some(1024),
+            ..Default::default()
+        };
+        let encode = |config: EncoderConfig, points: &[DataPoint]| {
+            let mut enc = Encoder::with_config(config);
+            for &dp in points {
+                enc.encode(dp).unwrap();
+            }
+            enc.finish().unwrap();
+            enc.into_compressed()
+        };
+        let block = encode(config.clone(), &points);
+        assert!(block.index.is_empty());
+        let stats = CompressionStats::of_block(&block).unwrap();
+        assert!(stats.timestamp_bits < 64 + 8 * 16, "{stats:?}");
+        assert_eq!(stats.dod_buckets[0], points.len() as u64 - 1 - 3);
+        let plain = encode(EncoderConfig::default(), &points);
+        let plain_stats = CompressionStats::of_block(&plain).unwrap();
+        assert!(plain_stats.timestamp_bits > 12_000 * 8);
+
+        let parsed = CompressedBlock::from_bytes(&block.to_bytes()).unwrap();
+        assert_eq!(Decoder::decode(&parsed).unwrap(), points);
+        assert_eq!(
+            Decoder::decode_strict(&block, points.len()).unwrap(),
+            points
+        );
+
+        // Constant values take a bit a point and the timestamps next to
+        // none, under the two bits a point other codecs need at least.
+        let constant: Vec<_> = (0..10_000u64)
+            .map(|i| DataPoint::new(i * 15, 1.0))
+            .collect();
+        let regular = encode(config.clone(), &constant);
+        assert!(regular.total_bits < 2 * constant.len());
+        let parsed = CompressedBlock::from_bytes(&regular.to_bytes()).unwrap();
+        assert_eq!(Decoder::decode(&parsed).unwrap(), constant);
+
+        // Resuming mid-run continues the run rather than starting over.
+        let head = encode(config.clone(), &points[..30_000]);
+        let tail = encode(config.clone(), &points[30_000..]);
+        let merged = CompressedBlock::merge(&head, &tail).unwrap();
+        assert_eq!(merged.total_bits, block.total_bits);
+        assert_eq!(Decoder::decode(&merged).unwrap(), points);
+
+        // Replacing a point inside a run takes it back out first.
+        let mut enc = Encoder::with_config(EncoderConfig {
+            on_duplicate: DuplicatePolicy::KeepLast,
+            ..config.clone()
+        });
+        for &dp in &points[..1_000] {
+            enc.encode(dp).unwrap();
+            enc.encode(DataPoint::new(dp.timestamp, -1.0)).unwrap();
+            enc.encode(dp).unwrap();
+        }
+        enc.finish().unwrap();
+        let replaced = enc.into_compressed();
+        assert_eq!(Decoder::decode(&replaced).unwrap(), points[..1_000]);
+        assert_eq!(
+            replaced.total_bits,
+            encode(config, &points[..1_000]).total_bits
+        );
+    }
+
+    #[test]
+    fn test_finish_falls_back_to_raw() {
+        // Random values at random intervals cost more than 128 bits a point.
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let mut timestamp = 1_000;
+        let points: Vec<_> = (0..200)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                timestamp += 1 + state % 100_000;
+                DataPoint::new(timestamp, f64::from_bits(state >> 1))
+            })
+            .collect();
+        let encode = |raw_fallback, points: &[DataPoint]| {
+            let mut enc = Encoder::with_config(EncoderConfig {
+                checksum: true,
+                checkpoint_interval: Some(16),
+                priors: Some(Priors {
+                    interval: 50_000,
+                    value: 0.0,
+                }),
+                raw_fallback,
+                ..Default::default()
+            });
+            for &dp in points {
+                enc.encode(dp).unwrap();
+            }
+            enc.finish().unwrap();
+            enc.into_compressed()
+        };
+
+        let compressed = encode(false, &points);
+        assert_eq!(compressed.value_codec, ValueScheme::Gorilla);
+        assert!(compressed.total_bits > 200 * 128);
+        let block = encode(true, &points);
+        assert_eq!(block.timestamp_codec, TimestampScheme::Raw);
+        assert_eq!(block.value_codec, ValueScheme::Raw);
+        assert_eq!(block.total_bits, 200 * 128);
+        assert!(block.index.is_empty() && block.priors.is_none());
+        assert_eq!(block.stats, compressed.stats);
+
+        let parsed = CompressedBlock::from_bytes(&block.to_bytes()).unwrap();
+        assert_eq!(parsed.value_codec, ValueScheme::Raw);
+        assert_eq!(Decoder::decode(&parsed).unwrap(), points);
+        assert_eq!(Decoder::decode_strict(&block, 200).unwrap(), points);
+        let from = points[150].timestamp;
+        let tail: Vec<_> = Decoder::iter_from(&block, from)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(tail, points[150..]);
+        let records = Decoder::inspect(&block).unwrap();
+        assert!(records
+            .iter()
+            .all(|r| r.timestamp_encoding == DodBucket::Raw && r.value_bits == 64));
+
+        let merged =
+            CompressedBlock::merge(&encode(true, &points[..100]), &encode(true, &points[100..]))
+                .unwrap();
+        assert_eq!(merged.bytes, block.bytes);
+        assert_eq!(merged.checksum, block.checksum);
+    }
+
+    #[test]
+    fn test_merge_rejects_overlap() {
+        let block = |ts: &[u64]| {
+            let mut enc = Encoder::new();
+            for &t in ts {
+                enc.encode(DataPoint::new(t, 1.0)).unwrap();
+            }
+            enc.finish().unwrap();
+            enc.into_compressed()
+        };
+        assert_eq!(
+            CompressedBlock::merge(&block(&[10, 20]), &block(&[20, 30])).unwrap_err(),
+            MergeError::Overlap {
+                a_end: 20,
+                b_start: 20
+            }
+        );
+        let empty = Encoder::new().to_compressed();
+        let merged = CompressedBlock::merge(&empty, &block(&[5])).unwrap();
+        assert_eq!(merged.count, 1);
+    }
+
+    #[test]
+    fn test_merge_reports_unencodable_points() {
+        let block = |t| {
+            let mut enc = Encoder::new();
+            enc.encode(DataPoint::new(t, 1.0)).unwrap();
+            enc.finish().unwrap();
+            enc.into_compressed()
+        };
+        assert_eq!(
+            CompressedBlock::merge(&block(0), &block(u64::MAX)).unwrap_err(),
+            MergeError::Encode(EncodeError::TimestampOutOfRange {
+                previous: 0,
+                timestamp: u64::MAX
+            })
+        );
+    }
+
+    #[test]
+    fn test_merge_rejects_different_quantizers() {
+        let block = |quantizer, ts: u64| {
+            let mut enc = Encoder::with_config(EncoderConfig {
+                quantizer,
+                ..Default::default()
+            });
+            enc.encode(DataPoint::new(ts, 1.3)).unwrap();
+            enc.finish().unwrap();
+            enc.into_compressed()
+        };
+        let coarse = Some(QuantizeSpec::AbsError(0.5));
+        assert_eq!(
+            CompressedBlock::merge(&block(coarse, 10), &block(None, 20)).unwrap_err(),
+            MergeError::QuantizerMismatch { a: coarse, b: None }
+        );
+        let merged = CompressedBlock::merge(&block(coarse, 10), &block(coarse, 20)).unwrap();
+        assert_eq!(merged.quantizer, coarse);
+    }
+
+    #[cfg(feature = "simd")]
+    #[test]
+    fn test_encode_batch_matches_encode() {
+        let mut points: Vec<_> = (0..203u64)
+            .map(|i| DataPoint::new(1_000 + i * 15 + i % 4, ((i / 3) as f64 * 0.37).sin()))
+            .collect();
+        // A duplicate and a step back break runs into the scalar path.
+        points.insert(50, DataPoint::new(points[49].timestamp, 9.0));
+        points.insert(120, DataPoint::new(points[119].timestamp - 1, 7.0));
+        for policy in [DuplicatePolicy::KeepLast, DuplicatePolicy::Allow] {
+            let config = EncoderConfig {
+                on_duplicate: policy,
+                on_out_of_order: OutOfOrderPolicy::Drop,
+                checkpoint_interval: Some(16),
+                ..Default::default()
+            };
+            let mut scalar = Encoder::with_config(config.clone());
+            for &dp in &points {
+                scalar.encode(dp).unwrap();
+            }
+            let mut batched = Encoder::with_config(config);
+            batched.encode_batch(&points[..77]).unwrap();
+            batched.encode_batch(&points[77..]).unwrap();
+            let (a, b) = (scalar.to_compressed(), batched.to_compressed());
+            assert_eq!(a.bytes, b.bytes);
+            assert_eq!((a.count, a.stats, &a.index), (b.count, b.stats, &b.index));
+        }
+
+        let mut scalar = Encoder::with_limit(40);
+        let err = points.iter().find_map(|&dp| scalar.encode(dp).err());
+        let mut batched = Encoder::with_limit(40);
+        assert_eq!(batched.encode_batch(&points).err(), err);
+        assert_eq!(batched.count(), scalar.count());
+        assert_eq!(batched.buffer().as_bytes(), scalar.buffer().as_bytes());
+    }
+
+    #[test]
+    fn test_encode_columns() {
+        let timestamps: Vec<u64> = (0..50).map(|i| 1000 + i * 30).collect();
+        let values: Vec<f64> = (0..50).map(|i| i as f64 / 4.0).collect();
+        let mut columns = Encoder::new();
+        columns.encode_columns(&timestamps, &values).unwrap();
+        let mut rows = Encoder::new();
+        for (&t, &v) in timestamps.iter().zip(&values) {
+            rows.encode(DataPoint::new(t, v)).unwrap();
+        }
+        assert_eq!(columns.to_compressed().bytes, rows.to_compressed().bytes);
+
+        let err = columns.encode_columns(&[5000, 5030], &[1.0]).unwrap_err();
+        assert_eq!(
+            err,
+            EncodeError::LengthMismatch {
+                timestamps: 2,
+                values: 1
+            }
+        );
+        assert_eq!(columns.count(), 50);
+    }
+
+    #[test]
+    fn test_checkpoint_index() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            on_duplicate: DuplicatePolicy::KeepLast,
+            checkpoint_interval: Some(4),
+            ..Default::default()
+        });
+        for t in 0..10u64 {
+            enc.encode(DataPoint::new(t * 10, t as f64)).unwrap();
+        }
+        // Replacing the 12th point drops its checkpoint and records a new one.
+        enc.encode(DataPoint::new(100, 10.0)).unwrap();
+        enc.encode(DataPoint::new(110, 11.0)).unwrap();
+        enc.encode(DataPoint::new(110, 11.5)).unwrap();
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+
+        let points: Vec<_> = block.index.iter().map(|cp| cp.point_index).collect();
+        assert_eq!(points, vec![3, 7, 11]);
+        let last = block.index[2];
+        assert_eq!(last.timestamp, 110);
+        assert_eq!(f64::from_bits(last.value_bits), 11.5);
+        assert_eq!(last.delta, 10);
+
+        let parsed = CompressedBlock::from_bytes(&block.to_bytes()).unwrap();
+        assert_eq!(parsed.index, block.index);
+        assert_eq!(parsed.bytes, block.bytes);
+        assert!(Encoder::new().to_compressed().index.is_empty());
+    }
+
+    #[test]
+    fn test_corrupt_checkpoint_is_rejected() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            checksum: true,
+            checkpoint_interval: Some(8),
+            ..Default::default()
+        });
+        for t in 0..40u64 {
+            enc.encode(DataPoint::new(t * 10, (t as f64).sqrt()))
+                .unwrap();
+        }
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+        let bytes = block.to_bytes();
+        let cp = block.index[1];
+        let mut words = Vec::new();
+        for word in [cp.point_index, cp.bit_offset, cp.timestamp] {
+            words.extend_from_slice(&word.to_le_bytes());
+        }
+        let at = bytes.windows(24).position(|w| w == words).unwrap();
+
+        let corrupt = |offset: usize, patch: &[u8]| {
+            let mut bytes = bytes.clone();
+            bytes[at + offset..at + offset + patch.len()].copy_from_slice(patch);
+            CompressedBlock::from_bytes(&bytes).map(|_| ())
+        };
+        assert!(corrupt(0, &[]).is_ok());
+        for (offset, patch) in [
+            (0, u64::MAX.to_le_bytes().to_vec()),
+            (0, 0u64.to_le_bytes().to_vec()),
+            (8, (block.total_bits as u64 + 1).to_le_bytes().to_vec()),
+            (40, vec![60, 60]),
+        ] {
+            assert!(
+                matches!(
+                    corrupt(offset, &patch),
+                    Err(DecodeError::MalformedHeader(_))
+                ),
+                "{offset} {patch:?}"
+            );
+        }
+
+        // Checkpoints set through the field fail to decode without panicking.
+        for damage in [
+            |cp: &mut Checkpoint| cp.point_index = u64::MAX,
+            |cp: &mut Checkpoint| (cp.leading_zeros, cp.trailing_zeros) = (60, 60),
+        ] {
+            let mut damaged = block.clone();
+            damaged.index.iter_mut().for_each(damage);
+            let results: Vec<_> = Decoder::iter_from(&damaged, 300).collect();
+            assert!(results.iter().any(Result::is_err));
+        }
+    }
+
+    #[test]
+    fn test_block_value_stats() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            on_duplicate: DuplicatePolicy::KeepLast,
+            ..Default::default()
+        });
+        for (ts, v) in [(10, 3.0), (20, f64::NAN), (30, -1.5), (40, 100.0), (40, 8.0)] {
+            enc.encode(DataPoint::new(ts, v)).unwrap();
+        }
+        enc.finish().unwrap();
+        let block = enc.into_compressed();
+        // The replaced value 100.0 leaves no trace in the statistics.
+        assert_eq!(block.min_value(), Some(-1.5));
+        assert_eq!(block.max_value(), Some(8.0));
+        assert!(block.sum().unwrap().is_nan());
+
         let mut enc = Encoder::new();
-        for i in 0..10 {
-            enc.encode(DataPoint::new(1609459200 + i * 60, 42.0)).unwrap();
+        for v in [1.0, 2.5, 4.0] {
+            enc.encode(DataPoint::new(v as u64, v)).unwrap();
+        }
+        let stats = enc.to_compressed().stats.unwrap();
+        assert_eq!((stats.min_value, stats.max_value, stats.sum), (1.0, 4.0, 7.5));
+    }
+
+    #[test]
+    fn test_block_time_range_stats() {
+        let mut enc = Encoder::new();
+        assert_eq!(enc.to_compressed().start_timestamp(), None);
+        for ts in [500, 560, 440, 620] {
+            enc.encode(DataPoint::new(ts, 1.0)).unwrap();
         }
         enc.finish().unwrap();
-        assert_eq!(enc.count(), 10);
-        // Identical values should compress very efficiently.
+        let block = enc.into_compressed();
+        assert_eq!(block.start_timestamp(), Some(440));
+        assert_eq!(block.end_timestamp(), Some(620));
     }
 
     #[test]
-    fn test_encode_varying_deltas() {
+    fn test_compression_stats() {
         let mut enc = Encoder::new();
-        enc.encode(DataPoint::new(100, 1.0)).unwrap();
-        enc.encode(DataPoint::new(160, 2.0)).unwrap(); // delta=60
-        enc.encode(DataPoint::new(220, 3.0)).unwrap(); // delta=60, dod=0
-        enc.encode(DataPoint::new(290, 4.0)).unwrap(); // delta=70, dod=10
-        enc.encode(DataPoint::new(500, 5.0)).unwrap(); // delta=210, dod=140
+        assert_eq!(enc.stats(), CompressionStats::default());
+        let values = [1.0, 1.0, 2.0, 4.0, 4.0];
+        for (ts, value) in [1000, 1060, 1120, 1185, 1245].into_iter().zip(values) {
+            enc.encode(DataPoint::new(ts, value)).unwrap();
+        }
+        let stats = enc.stats();
+        assert_eq!(stats.points, 5);
+        assert_eq!(stats.dod_buckets, [1, 3, 0, 0, 0]);
+        assert_eq!((stats.repeated_values, stats.new_windows), (2, 1));
+        assert_eq!(stats.window_reuse_rate(), 0.5);
         enc.finish().unwrap();
-        assert_eq!(enc.count(), 5);
+        let block = enc.into_compressed();
+        let bits = stats.timestamp_bits + stats.value_bits;
+        assert_eq!(bits as usize + 68, block.total_bits);
+        assert_eq!(CompressionStats::of_block(&block).unwrap(), stats);
+
+        let mut total = stats;
+        total.add(&stats);
+        assert_eq!(total.points, 10);
+        assert_eq!(total.bits_per_point(), stats.bits_per_point());
     }
 
     #[test]
-    fn test_encode_with_limit_ok() {
-        // 256 bytes is plenty for a few points with constant values.
-        let mut enc = Encoder::with_limit(256);
-        enc.encode(DataPoint::new(1609459200, 42.0)).unwrap();
-        enc.encode(DataPoint::new(1609459260, 42.0)).unwrap();
-        enc.encode(DataPoint::new(1609459320, 42.0)).unwrap();
+    fn test_from_parts_checks_invariants() {
+        let mut enc = Encoder::new();
+        for t in 0..20u64 {
+            enc.encode(DataPoint::new(t * 60, 1.0)).unwrap();
+        }
         enc.finish().unwrap();
-        assert_eq!(enc.count(), 3);
+        let block = enc.into_compressed();
+        let (total_bits, count) = (block.total_bits(), block.count());
+        let rebuilt = CompressedBlock::from_parts(block.bytes().to_vec(), total_bits, count);
+        assert_eq!(Decoder::decode(&rebuilt.unwrap()).unwrap().len(), 20);
+
+        let short = block.bytes()[1..].to_vec();
+        assert_eq!(
+            CompressedBlock::from_parts(short, total_bits, count).unwrap_err(),
+            InvalidBlock::LengthMismatch {
+                len: block.bytes().len() - 1,
+                total_bits
+            }
+        );
+        assert_eq!(
+            CompressedBlock::from_parts(vec![0; 1], 8, 1).unwrap_err(),
+            InvalidBlock::CountExceedsBits {
+                count: 1,
+                total_bits: 8
+            }
+        );
+        assert!(CompressedBlock::from_parts(Vec::new(), 0, 0).is_ok());
+
+        // A serialized header claiming more points than fit is rejected too.
+        let mut bytes = block.to_bytes();
+        bytes[2..10].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(
+            CompressedBlock::from_bytes(&bytes),
+            Err(DecodeError::MalformedHeader(_))
+        ));
+    }
+
+    /// Encodes `points` with `config`, draining the completed bytes after
+    /// every point, and checks them against the finished block.
+    fn drain_while_encoding(config: EncoderConfig, points: &[DataPoint]) -> CompressedBlock {
+        let mut encoder = Encoder::with_config(config);
+        let mut drained = Vec::new();
+        for &dp in points {
+            encoder.encode(dp).unwrap();
+            let bytes = encoder.drain_completed_bytes();
+            drained.extend_from_slice(bytes);
+            assert!(drained.len() * 8 <= encoder.buffer().len_bits());
+            // Drained bytes are never rewritten.
+            assert_eq!(drained, encoder.buffer().as_bytes()[..drained.len()]);
+        }
+        encoder.finish().unwrap();
+        drained.extend_from_slice(encoder.drain_completed_bytes());
+        assert!(encoder.drain_completed_bytes().is_empty());
+        let block = encoder.into_compressed();
+        assert_eq!(drained, block.bytes());
+        block
     }
 
     #[test]
-    fn test_encode_with_limit_exceeded() {
-        // 1 byte can't even fit the first 64-bit timestamp.
-        let mut enc = Encoder::with_limit(1);
-        let result = enc.encode(DataPoint::new(1609459200, 42.0));
-        assert!(result.is_err());
+    fn test_drain_completed_bytes_survive_rewrites() {
+        let regular: Vec<_> = (0..2_000).map(|i| DataPoint::new(i * 10, 1.5)).collect();
+        drain_while_encoding(
+            EncoderConfig {
+                timestamp_codec: TimestampScheme::RunLength,
+                ..Default::default()
+            },
+            &regular,
+        );
+
+        let with_repeats: Vec<_> = (0..1_000u64)
+            .map(|i| DataPoint::new(i / 4 * 60, i as f64 * 0.5))
+            .collect();
+        for value_codec in [ValueScheme::Gorilla, ValueScheme::Chimp] {
+            let config = EncoderConfig {
+                on_duplicate: DuplicatePolicy::KeepLast,
+                timestamp_codec: TimestampScheme::RunLength,
+                value_codec,
+                ..Default::default()
+            };
+            let block = drain_while_encoding(config, &with_repeats);
+            assert_eq!(block.count(), 250);
+        }
+    }
+
+    #[test]
+    fn test_incompressible_buffer_decodes_raw_by_default() {
+        let (mut state, mut timestamp) = (0x9E37_79B9_7F4A_7C15u64, 0);
+        let noise: Vec<_> = (0..100)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                timestamp += 1 + state % 1_000_000;
+                DataPoint::new(timestamp, f64::from_bits(state >> 2))
+            })
+            .collect();
+        let mut encoder = Encoder::new();
+        for &dp in &noise {
+            encoder.encode(dp).unwrap();
+        }
+        encoder.finish().unwrap();
+        let buf = encoder.into_buffer();
+        assert!(buf.len_bits() > 100 * 128);
+        assert_eq!(
+            Decoder::decode_raw(buf.as_bytes(), buf.len_bits()).unwrap(),
+            noise
+        );
+    }
+
+    #[test]
+    fn test_raw_fallback_counts_end_marker() {
+        let encode = |count_terminated| {
+            let mut enc = Encoder::with_config(EncoderConfig {
+                raw_fallback: true,
+                count_terminated,
+                ..Default::default()
+            });
+            enc.encode(DataPoint::new(1_700_000_000, 0.1)).unwrap();
+            enc.finish().unwrap();
+            enc.into_compressed()
+        };
+        // The first point takes exactly its raw 128 bits; the marker tips
+        // the block over unless the count terminates it.
+        let terminated = encode(true);
+        assert_eq!(terminated.value_codec, ValueScheme::Gorilla);
+        assert_eq!(terminated.total_bits, 128);
+        let marked = encode(false);
+        assert_eq!(marked.value_codec, ValueScheme::Raw);
+        assert_eq!(marked.total_bits, 128);
+        assert_eq!(
+            Decoder::decode(&marked).unwrap(),
+            [DataPoint::new(1_700_000_000, 0.1)]
+        );
+    }
+
+    #[test]
+    fn test_raw_fallback_skipped_when_raw_exceeds_limit() {
+        let mut enc = Encoder::with_config(EncoderConfig {
+            max_bytes: Some(29),
+            raw_fallback: true,
+            ..Default::default()
+        });
+        let points = [
+            DataPoint::new(1_700_000_000, 0.1),
+            DataPoint::new(1_700_000_061, 7.3),
+        ];
+        for &dp in &points {
+            enc.encode(dp).unwrap();
+        }
+        // Two raw points take 32 bytes, more than the limit, so the stream
+        // is left intact even though its marker does not fit.
+        assert_eq!(enc.finish(), Err(BufferFull));
+        assert_eq!(enc.stats().points, 2);
+        assert_eq!(Decoder::decode(&enc.to_compressed()).unwrap(), points);
+    }
+
+    #[test]
+    fn test_drained_stream_stays_compressed() {
+        // Random values compress worse than raw, so finish() would rewrite
+        // the stream if nothing had been drained.
+        let (mut state, mut timestamp) = (0x2545_F491_4F6C_DD1Du64, 0);
+        let noise: Vec<_> = (0..200)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                timestamp += 1 + state % 1_000_000;
+                DataPoint::new(timestamp, f64::from_bits(state >> 2))
+            })
+            .collect();
+        let config = EncoderConfig {
+            raw_fallback: true,
+            ..Default::default()
+        };
+        let mut encoder = Encoder::with_config(config.clone());
+        for &dp in &noise {
+            encoder.encode(dp).unwrap();
+        }
+        encoder.finish().unwrap();
+        assert_eq!(encoder.into_compressed().value_codec, ValueScheme::Raw);
+
+        let block = drain_while_encoding(config, &noise);
+        assert_eq!(block.value_codec, ValueScheme::Gorilla);
+        assert_eq!(Decoder::decode(&block).unwrap(), noise);
+    }
+
+    #[test]
+    fn test_extend_matches_encode_and_stops_at_error() {
+        let points: Vec<_> = (0..50)
+            .map(|i| DataPoint::new(1000 + i * 60, (i % 5) as f64))
+            .collect();
+        let mut looped = Encoder::new();
+        for &dp in &points {
+            looped.encode(dp).unwrap();
+        }
+        let mut collected: Encoder = points[..20].iter().copied().collect();
+        collected.extend(&points[20..]);
+        looped.finish().unwrap();
+        collected.finish().unwrap();
+        assert_eq!(
+            collected.into_compressed().to_bytes(),
+            looped.into_compressed().to_bytes()
+        );
+
+        let mut strict = Encoder::with_config(EncoderConfig {
+            on_out_of_order: OutOfOrderPolicy::Reject,
+            ..Default::default()
+        });
+        let unordered = [points[0], points[2], points[1], points[3]];
+        assert_eq!(
+            strict.try_extend(unordered),
+            Err(EncodeError::OutOfOrder {
+                previous: points[2].timestamp,
+                timestamp: points[1].timestamp,
+            })
+        );
+        assert_eq!(strict.count(), 2);
     }
 }
@@ -0,0 +1,228 @@
+//! C ABI for embedding the codec in C, C++, Go and other non-Rust services.
+//!
+//! Encoders and finished blocks are opaque handles owned by the caller and
+//! released with their `_free` function. Blocks cross the boundary in the
+//! serialized form of [`CompressedBlock::to_bytes`], so they can be stored
+//! or sent as is and decoded later with [`gorilla_decode`]. The declarations
+//! are in `include/gorilla.h`; build with `--features ffi` to get a
+//! `cdylib` exporting them.
+//!
+//! Functions returning `i32` use 0 for success and one of the negative
+//! `GORILLA_ERR_*` codes on failure.
+
+use std::ptr;
+use std::slice;
+
+use crate::decoder::Decoder;
+use crate::encoder::{
+    CompressedBlock, DataPoint, DuplicatePolicy, Encoder, EncoderConfig, OutOfOrderPolicy,
+};
+
+/// A required pointer argument was null.
+pub const GORILLA_ERR_NULL: i32 = -1;
+/// The point was rejected: out of order or a duplicate.
+pub const GORILLA_ERR_REJECTED: i32 = -2;
+/// The input bytes are not a valid serialized block.
+pub const GORILLA_ERR_MALFORMED: i32 = -3;
+/// The output buffers are smaller than the block's point count.
+pub const GORILLA_ERR_CAPACITY: i32 = -4;
+
+/// A finished block in serialized form.
+#[derive(Debug)]
+pub struct GorillaBlock {
+    count: u64,
+    bytes: Vec<u8>,
+}
+
+/// Creates an encoder that rejects out-of-order and duplicate timestamps.
+#[no_mangle]
+pub extern "C" fn gorilla_encoder_new() -> *mut Encoder {
+    Box::into_raw(Box::new(Encoder::with_config(EncoderConfig {
+        on_out_of_order: OutOfOrderPolicy::Reject,
+        on_duplicate: DuplicatePolicy::Reject,
+        ..Default::default()
+    })))
+}
+
+/// Appends a point. Timestamps must be strictly increasing.
+///
+/// # Safety
+/// `encoder` must come from `gorilla_encoder_new` and not be finished or
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn gorilla_encode(encoder: *mut Encoder, timestamp: u64, value: f64) -> i32 {
+    let Some(encoder) = encoder.as_mut() else {
+        return GORILLA_ERR_NULL;
+    };
+    match encoder.encode(DataPoint::new(timestamp, value)) {
+        Ok(()) => 0,
+        Err(_) => GORILLA_ERR_REJECTED,
+    }
+}
+
+/// Finishes and frees the encoder, returning the block, or null if
+/// `encoder` is null or the end-of-stream marker does not fit.
+///
+/// # Safety
+/// `encoder` must come from `gorilla_encoder_new` and not be finished or
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn gorilla_finish(encoder: *mut Encoder) -> *mut GorillaBlock {
+    if encoder.is_null() {
+        return ptr::null_mut();
+    }
+    let mut encoder = *Box::from_raw(encoder);
+    if encoder.finish().is_err() {
+        return ptr::null_mut();
+    }
+    let block = encoder.into_compressed();
+    Box::into_raw(Box::new(GorillaBlock {
+        count: block.count,
+        bytes: block.to_bytes(),
+    }))
+}
+
+/// Frees an encoder without finishing it. Passing null is a no-op.
+///
+/// # Safety
+/// `encoder` must come from `gorilla_encoder_new` and not be finished or
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn gorilla_encoder_free(encoder: *mut Encoder) {
+    if !encoder.is_null() {
+        drop(Box::from_raw(encoder));
+    }
+}
+
+/// Returns the serialized block and stores its length in `len`. The bytes
+/// stay valid until the block is freed.
+///
+/// # Safety
+/// `block` must come from `gorilla_finish` and not be freed; `len` must be
+/// valid for a write.
+#[no_mangle]
+pub unsafe extern "C" fn gorilla_block_data(
+    block: *const GorillaBlock,
+    len: *mut usize,
+) -> *const u8 {
+    match (block.as_ref(), len.is_null()) {
+        (Some(block), false) => {
+            *len = block.bytes.len();
+            block.bytes.as_ptr()
+        }
+        _ => ptr::null(),
+    }
+}
+
+/// Returns the number of points in the block, or 0 if `block` is null.
+///
+/// # Safety
+/// `block` must come from `gorilla_finish` and not be freed.
+#[no_mangle]
+pub unsafe extern "C" fn gorilla_block_count(block: *const GorillaBlock) -> u64 {
+    block.as_ref().map_or(0, |block| block.count)
+}
+
+/// Frees a block. Passing null is a no-op.
+///
+/// # Safety
+/// `block` must come from `gorilla_finish` and not already be freed.
+#[no_mangle]
+pub unsafe extern "C" fn gorilla_block_free(block: *mut GorillaBlock) {
+    if !block.is_null() {
+        drop(Box::from_raw(block));
+    }
+}
+
+/// Decodes the serialized block in `data[..len]` into the `timestamps` and
+/// `values` buffers of `capacity` elements each. Stores the number of
+/// points in `count`, also when the buffers are too small, so the caller can
+/// retry with larger ones.
+///
+/// # Safety
+/// `data` must be valid for reads of `len` bytes, each buffer valid for
+/// writes of `capacity` elements and `count` valid for a write.
+#[no_mangle]
+pub unsafe extern "C" fn gorilla_decode(
+    data: *const u8,
+    len: usize,
+    timestamps: *mut u64,
+    values: *mut f64,
+    capacity: usize,
+    count: *mut u64,
+) -> i32 {
+    if data.is_null() || timestamps.is_null() || values.is_null() || count.is_null() {
+        return GORILLA_ERR_NULL;
+    }
+    let Ok(block) = CompressedBlock::from_bytes(slice::from_raw_parts(data, len)) else {
+        return GORILLA_ERR_MALFORMED;
+    };
+    *count = block.count;
+    if block.count > capacity as u64 {
+        return GORILLA_ERR_CAPACITY;
+    }
+    let timestamps = slice::from_raw_parts_mut(timestamps, capacity);
+    let values = slice::from_raw_parts_mut(values, capacity);
+    let mut decoded = 0;
+    for result in Decoder::iter(&block) {
+        // A corrupt stream may not end where its header says.
+        let (Ok(dp), true) = (result, decoded < block.count as usize) else {
+            return GORILLA_ERR_MALFORMED;
+        };
+        timestamps[decoded] = dp.timestamp;
+        values[decoded] = dp.value;
+        decoded += 1;
+    }
+    *count = decoded as u64;
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip_through_c_abi() {
+        unsafe {
+            let encoder = gorilla_encoder_new();
+            for t in 0..100u64 {
+                assert_eq!(gorilla_encode(encoder, 1000 + t * 60, t as f64 * 0.5), 0);
+            }
+            assert_eq!(gorilla_encode(encoder, 1000, 1.0), GORILLA_ERR_REJECTED);
+            let block = gorilla_finish(encoder);
+            assert_eq!(gorilla_block_count(block), 100);
+            let mut len = 0;
+            let data = gorilla_block_data(block, &mut len);
+
+            let (mut ts, mut vs, mut count) = ([0u64; 100], [0f64; 100], 0);
+            let status =
+                gorilla_decode(data, len, ts.as_mut_ptr(), vs.as_mut_ptr(), 10, &mut count);
+            assert_eq!((status, count), (GORILLA_ERR_CAPACITY, 100));
+            let status =
+                gorilla_decode(data, len, ts.as_mut_ptr(), vs.as_mut_ptr(), 100, &mut count);
+            assert_eq!((status, count), (0, 100));
+            assert_eq!((ts[99], vs[99]), (1000 + 99 * 60, 49.5));
+            gorilla_block_free(block);
+        }
+    }
+
+    #[test]
+    fn test_rejects_null_and_malformed_input() {
+        unsafe {
+            assert_eq!(gorilla_encode(ptr::null_mut(), 1, 1.0), GORILLA_ERR_NULL);
+            assert!(gorilla_finish(ptr::null_mut()).is_null());
+            let (mut ts, mut vs, mut count) = ([0u64; 4], [0f64; 4], 0);
+            let garbage = [7u8; 16];
+            let status = gorilla_decode(
+                garbage.as_ptr(),
+                garbage.len(),
+                ts.as_mut_ptr(),
+                vs.as_mut_ptr(),
+                4,
+                &mut count,
+            );
+            assert_eq!(status, GORILLA_ERR_MALFORMED);
+            gorilla_encoder_free(gorilla_encoder_new());
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! Gorilla compression for half-precision values (`f16` and `bf16`).
+//!
+//! Timestamps use the same delta-of-delta scheme as [`Encoder`]; values are
+//! XOR-compressed against the previous value with a 16-bit window, so an
+//! unchanged value costs one bit and a changed one at most 2 + 8 + 16 bits:
+//!
+//! | XOR == 0            | `0`                                    |
+//! | fits previous window | `10` + meaningful bits                |
+//! | otherwise           | `11` + 4-bit leading zeros + 4-bit (length - 1) + meaningful bits |
+//!
+//! The first point is stored as a 64-bit timestamp and the raw 16 value
+//! bits. Streams end with the same marker as full-precision blocks.
+//!
+//! [`Encoder`]: crate::encoder::Encoder
+
+use std::marker::PhantomData;
+
+use half::{bf16, f16};
+
+use crate::bitbuffer::{BitBuffer, BitReader, BufferFull};
+use crate::decoder::{next_timestamp, DecodeError, Decoder, DodResult};
+use crate::encoder::{checked_delta, write_delta_of_delta, EncodeError};
+
+/// A 16-bit float type that can be stored in a [`HalfBlock`].
+pub trait HalfFloat: Copy {
+    /// Returns the raw bit pattern.
+    fn to_bits16(self) -> u16;
+    /// Rebuilds a value from its raw bit pattern.
+    fn from_bits16(bits: u16) -> Self;
+}
+
+impl HalfFloat for f16 {
+    fn to_bits16(self) -> u16 {
+        self.to_bits()
+    }
+
+    fn from_bits16(bits: u16) -> Self {
+        f16::from_bits(bits)
+    }
+}
+
+impl HalfFloat for bf16 {
+    fn to_bits16(self) -> u16 {
+        self.to_bits()
+    }
+
+    fn from_bits16(bits: u16) -> Self {
+        bf16::from_bits(bits)
+    }
+}
+
+/// A single timestamped half-precision value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HalfPoint<T> {
+    /// Unix timestamp in seconds.
+    pub timestamp: u64,
+    /// The value.
+    pub value: T,
+}
+
+impl<T> HalfPoint<T> {
+    /// Creates a new point.
+    pub fn new(timestamp: u64, value: T) -> Self {
+        Self { timestamp, value }
+    }
+}
+
+/// A finished stream of half-precision points.
+#[derive(Debug, Clone)]
+pub struct HalfBlock<T> {
+    /// Raw compressed bytes.
+    pub bytes: Vec<u8>,
+    /// Number of valid bits in `bytes`.
+    pub total_bits: usize,
+    /// Number of points in the block.
+    pub count: u64,
+    marker: PhantomData<T>,
+}
+
+impl<T> HalfBlock<T> {
+    pub(crate) fn from_parts(bytes: Vec<u8>, total_bits: usize, count: u64) -> Self {
+        Self {
+            bytes,
+            total_bits,
+            count,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Compresses a stream of half-precision points.
+///
+/// # Example
+/// ```
+/// use gorilla::{HalfDecoder, HalfEncoder, HalfPoint};
+/// use half::bf16;
+///
+/// let mut encoder = HalfEncoder::new();
+/// for (i, loss) in [2.5f32, 2.25, 2.0, 2.0].into_iter().enumerate() {
+///     encoder.encode(HalfPoint::new(i as u64 * 10, bf16::from_f32(loss))).unwrap();
+/// }
+/// let block = encoder.finish().unwrap();
+///
+/// let points = HalfDecoder::decode(&block).unwrap();
+/// assert_eq!(points[1].value, bf16::from_f32(2.25));
+/// ```
+#[derive(Debug)]
+pub struct HalfEncoder<T> {
+    buf: BitBuffer,
+    count: u64,
+    prev_timestamp: u64,
+    prev_delta: i64,
+    prev_value_bits: u16,
+    prev_leading_zeros: u8,
+    prev_trailing_zeros: u8,
+    marker: PhantomData<T>,
+}
+
+impl<T: HalfFloat> HalfEncoder<T> {
+    /// Creates an empty encoder.
+    pub fn new() -> Self {
+        Self::with_buffer(BitBuffer::new())
+    }
+
+    /// Creates an encoder whose buffer will not grow beyond `max_bytes`.
+    pub fn with_limit(max_bytes: usize) -> Self {
+        Self::with_buffer(BitBuffer::with_limit(max_bytes))
+    }
+
+    fn with_buffer(buf: BitBuffer) -> Self {
+        Self {
+            buf,
+            count: 0,
+            prev_timestamp: 0,
+            prev_delta: 0,
+            prev_value_bits: 0,
+            prev_leading_zeros: u8::MAX,
+            prev_trailing_zeros: 0,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the number of points encoded so far.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Appends a point.
+    ///
+    /// Returns `Err(EncodeError::TimestampOutOfRange)` without encoding the
+    /// point if its distance from the previous timestamp, or that
+    /// distance's difference from the previous one, does not fit in an
+    /// `i64`.
+    pub fn encode(&mut self, point: HalfPoint<T>) -> Result<(), EncodeError> {
+        let bits = point.value.to_bits16();
+        if self.count == 0 {
+            self.buf.write_bits(point.timestamp, 64)?;
+            self.buf.write_bits(bits as u64, 16)?;
+        } else {
+            let (delta, dod) = checked_delta(self.prev_timestamp, point.timestamp)
+                .and_then(|delta| Some((delta, delta.checked_sub(self.prev_delta)?)))
+                .ok_or(EncodeError::TimestampOutOfRange {
+                    previous: self.prev_timestamp,
+                    timestamp: point.timestamp,
+                })?;
+            write_delta_of_delta(&mut self.buf, dod)?;
+            self.encode_value(bits)?;
+            self.prev_delta = delta;
+        }
+        self.prev_timestamp = point.timestamp;
+        self.prev_value_bits = bits;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Writes the end-of-stream marker and returns the finished block.
+    pub fn finish(mut self) -> Result<HalfBlock<T>, BufferFull> {
+        self.buf.write_bits(0b1111, 4)?;
+        self.buf.write_bits(0xFFFF_FFFF_FFFF_FFFF, 64)?;
+        let total_bits = self.buf.len_bits();
+        Ok(HalfBlock::from_parts(
+            self.buf.into_bytes(),
+            total_bits,
+            self.count,
+        ))
+    }
+
+    fn encode_value(&mut self, bits: u16) -> Result<(), BufferFull> {
+        let xor = bits ^ self.prev_value_bits;
+        if xor == 0 {
+            return self.buf.write_bit(false);
+        }
+        self.buf.write_bit(true)?;
+
+        let leading = xor.leading_zeros() as u8;
+        let trailing = xor.trailing_zeros() as u8;
+        if self.prev_leading_zeros != u8::MAX
+            && leading >= self.prev_leading_zeros
+            && trailing >= self.prev_trailing_zeros
+        {
+            let meaningful = 16 - self.prev_leading_zeros - self.prev_trailing_zeros;
+            self.buf.write_bit(false)?;
+            self.buf
+                .write_bits((xor >> self.prev_trailing_zeros) as u64, meaningful)?;
+        } else {
+            let meaningful = 16 - leading - trailing;
+            self.buf.write_bit(true)?;
+            self.buf.write_bits(leading as u64, 4)?;
+            self.buf.write_bits((meaningful - 1) as u64, 4)?;
+            self.buf.write_bits((xor >> trailing) as u64, meaningful)?;
+            self.prev_leading_zeros = leading;
+            self.prev_trailing_zeros = trailing;
+        }
+        Ok(())
+    }
+}
+
+impl<T: HalfFloat> Default for HalfEncoder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decompresses a [`HalfBlock`].
+pub struct HalfDecoder;
+
+impl HalfDecoder {
+    /// Decodes all points of `block`, checking the count against
+    /// `block.count`.
+    pub fn decode<T: HalfFloat>(block: &HalfBlock<T>) -> Result<Vec<HalfPoint<T>>, DecodeError> {
+        let mut reader = BitReader::from_raw(&block.bytes, block.total_bits);
+        let mut points = Vec::with_capacity(block.count as usize);
+
+        let mut timestamp = reader.read_bits(64).ok_or(DecodeError::Empty)?;
+        let mut value_bits = reader.read_bits(16).ok_or(DecodeError::UnexpectedEnd)? as u16;
+        let mut delta: i64 = 0;
+        let mut leading: u8 = 0;
+        let mut trailing: u8 = 0;
+        points.push(HalfPoint::new(timestamp, T::from_bits16(value_bits)));
+
+        while let DodResult::Value(dod) = Decoder::decode_delta_of_delta(&mut reader)? {
+            delta = match points.len() {
+                1 => dod,
+                _ => delta
+                    .checked_add(dod)
+                    .ok_or(DecodeError::TimestampOverflow)?,
+            };
+            timestamp = next_timestamp(timestamp, delta)?;
+
+            if reader.read_bit().ok_or(DecodeError::UnexpectedEnd)? {
+                if reader.read_bit().ok_or(DecodeError::UnexpectedEnd)? {
+                    leading = reader.read_bits(4).ok_or(DecodeError::UnexpectedEnd)? as u8;
+                    let meaningful =
+                        reader.read_bits(4).ok_or(DecodeError::UnexpectedEnd)? as u8 + 1;
+                    if leading + meaningful > 16 {
+                        return Err(DecodeError::InvalidXorWindow {
+                            leading,
+                            meaningful,
+                        });
+                    }
+                    trailing = 16 - leading - meaningful;
+                }
+                let meaningful = 16 - leading - trailing;
+                let xor = reader
+                    .read_bits(meaningful)
+                    .ok_or(DecodeError::UnexpectedEnd)? as u16;
+                value_bits ^= xor << trailing;
+            }
+            points.push(HalfPoint::new(timestamp, T::from_bits16(value_bits)));
+        }
+
+        if points.len() as u64 != block.count {
+            return Err(DecodeError::CountMismatch {
+                expected: block.count,
+                actual: points.len() as u64,
+            });
+        }
+        Ok(points)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip<T: HalfFloat + PartialEq + std::fmt::Debug>(points: &[HalfPoint<T>]) -> usize {
+        let mut enc = HalfEncoder::new();
+        for &p in points {
+            enc.encode(p).unwrap();
+        }
+        let block = enc.finish().unwrap();
+        assert_eq!(HalfDecoder::decode(&block).unwrap(), points);
+        block.total_bits
+    }
+
+    #[test]
+    fn test_f16_roundtrip() {
+        let points: Vec<_> = (0..100)
+            .map(|i| HalfPoint::new(1_000 + i * 15, f16::from_f32((i as f32 * 0.37).sin())))
+            .collect();
+        roundtrip(&points);
+    }
+
+    #[test]
+    fn test_bf16_roundtrip_with_special_values() {
+        let values = [1.0, f32::NAN, -0.0, f32::INFINITY, 3.5e30, 1.0];
+        let points: Vec<_> = values
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| HalfPoint::new(i as u64 * 1000, bf16::from_f32(v)))
+            .collect();
+        let mut enc = HalfEncoder::new();
+        for &p in &points {
+            enc.encode(p).unwrap();
+        }
+        let decoded = HalfDecoder::decode(&enc.finish().unwrap()).unwrap();
+        for (got, want) in decoded.iter().zip(&points) {
+            assert_eq!(got.timestamp, want.timestamp);
+            assert_eq!(got.value.to_bits(), want.value.to_bits());
+        }
+    }
+
+    #[test]
+    fn test_constant_values_cost_one_bit() {
+        let points: Vec<_> = (0..64)
+            .map(|i| HalfPoint::new(i * 10, bf16::from_f32(0.5)))
+            .collect();
+        // 80-bit header, then two bits per repeated point (timestamp and
+        // value), except the second point's 9-bit delta, then the marker.
+        assert_eq!(roundtrip(&points), 80 + 10 + 62 * 2 + 68);
+    }
+
+    #[test]
+    fn test_limit_reports_buffer_full() {
+        let mut enc = HalfEncoder::with_limit(10);
+        assert!(enc.encode(HalfPoint::new(0, f16::ONE)).is_ok());
+        assert_eq!(
+            enc.encode(HalfPoint::new(1, f16::from_f32(-7.0))),
+            Err(EncodeError::BufferFull)
+        );
+    }
+
+    #[test]
+    fn test_out_of_range_timestamp_is_rejected() {
+        let mut enc = HalfEncoder::new();
+        enc.encode(HalfPoint::new(0, f16::ONE)).unwrap();
+        assert_eq!(
+            enc.encode(HalfPoint::new(u64::MAX, f16::ONE)),
+            Err(EncodeError::TimestampOutOfRange {
+                previous: 0,
+                timestamp: u64::MAX,
+            })
+        );
+        let points = [
+            HalfPoint::new(i64::MAX as u64, f16::ONE),
+            HalfPoint::new(i64::MAX as u64 + 1, f16::ZERO),
+        ];
+        roundtrip(&points);
+    }
+}
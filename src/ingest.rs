@@ -0,0 +1,404 @@
+//! Parallel write path over a [`ShardedMap`].
+//!
+//! [`Pipeline`] moves every shard of a map onto its own worker thread and
+//! routes pushed points to the shard owning their series, so shards encode
+//! in parallel while the points of any one series are appended in the order
+//! they were pushed.
+//!
+//! Points are batched per shard. A batch is handed to its worker as soon as
+//! the worker's queue has room, so a lightly loaded pipeline adds little
+//! latency; while the worker is busy the batch keeps growing up to
+//! [`IngestConfig::max_batch`] points. Once a shard has a full batch and a
+//! full queue, [`Pipeline::push`] blocks until the worker catches up and
+//! [`Pipeline::try_push`] hands the point back instead.
+
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+
+use crate::encoder::DataPoint;
+use crate::map::SeriesMap;
+use crate::series::{AppendError, SeriesKey};
+use crate::shard::{ShardedMap, Sharding};
+
+/// Batching and queueing limits of a [`Pipeline`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IngestConfig {
+    /// Largest number of points sent to a worker at once.
+    pub max_batch: usize,
+    /// Number of batches that may wait for each worker before pushes to its
+    /// shard apply backpressure.
+    pub queue_depth: usize,
+}
+
+impl Default for IngestConfig {
+    fn default() -> Self {
+        Self {
+            max_batch: 1024,
+            queue_depth: 4,
+        }
+    }
+}
+
+/// Error returned when a shard's worker is gone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IngestError {
+    /// The worker of `shard` panicked. Its points are lost.
+    WorkerFailed {
+        /// Id of the failed shard.
+        shard: u32,
+    },
+}
+
+impl std::fmt::Display for IngestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IngestError::WorkerFailed { shard } => {
+                write!(f, "ingest worker for shard {shard} failed")
+            }
+        }
+    }
+}
+
+impl std::error::Error for IngestError {}
+
+/// Error returned by [`Pipeline::try_push`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum TryPushError<K> {
+    /// The shard's batch and queue are full; the point was not taken.
+    Full(K, DataPoint),
+    /// The shard's worker is gone.
+    Failed(IngestError),
+}
+
+impl<K> std::fmt::Display for TryPushError<K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TryPushError::Full(..) => write!(f, "ingest queue is full"),
+            TryPushError::Failed(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl<K: std::fmt::Debug> std::error::Error for TryPushError<K> {}
+
+impl<K> From<IngestError> for TryPushError<K> {
+    fn from(err: IngestError) -> Self {
+        TryPushError::Failed(err)
+    }
+}
+
+/// A pushed point its series refused, e.g. because it was out of order or
+/// throttled by the shard's admission control.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rejected<K> {
+    /// Series the point was pushed to.
+    pub key: K,
+    /// The refused point.
+    pub point: DataPoint,
+    /// Why it was refused.
+    pub error: AppendError,
+}
+
+/// Outcome of [`Pipeline::finish`].
+#[derive(Debug)]
+pub struct IngestReport<K: SeriesKey> {
+    /// The map with every accepted point appended.
+    pub map: ShardedMap<K>,
+    /// Number of points appended.
+    pub appended: u64,
+    /// Points that were refused, per shard in push order.
+    pub rejected: Vec<Rejected<K>>,
+}
+
+enum Command<K> {
+    Batch(Vec<(K, DataPoint)>),
+    Flush(mpsc::Sender<()>),
+}
+
+struct Output<K: SeriesKey> {
+    map: SeriesMap<K>,
+    appended: u64,
+    rejected: Vec<Rejected<K>>,
+}
+
+struct Worker<K: SeriesKey> {
+    sender: SyncSender<Command<K>>,
+    pending: Vec<(K, DataPoint)>,
+    handle: JoinHandle<Output<K>>,
+}
+
+/// Points routed to per-shard worker threads.
+///
+/// Dropping a pipeline without calling [`finish`](Pipeline::finish) stops
+/// the workers and discards the map.
+pub struct Pipeline<K: SeriesKey + Send + 'static> {
+    map: ShardedMap<K>,
+    config: IngestConfig,
+    workers: Vec<Worker<K>>,
+}
+
+impl<K: SeriesKey + Send + 'static> Pipeline<K> {
+    /// Starts one worker per shard of `map`. Each worker owns its shard,
+    /// including any admission control set on it, until
+    /// [`finish`](Pipeline::finish) hands the map back.
+    ///
+    /// # Panics
+    /// Panics if `config.max_batch` or `config.queue_depth` is 0.
+    pub fn new(mut map: ShardedMap<K>, config: IngestConfig) -> Self {
+        assert!(config.max_batch > 0, "max_batch must be at least 1");
+        assert!(config.queue_depth > 0, "queue_depth must be at least 1");
+        let workers = (0..map.sharding().shards())
+            .map(|id| {
+                let shard = map.take_shard(id);
+                let (sender, receiver) = mpsc::sync_channel(config.queue_depth);
+                let handle = thread::spawn(move || run(shard, receiver));
+                Worker {
+                    sender,
+                    pending: Vec::new(),
+                    handle,
+                }
+            })
+            .collect();
+        Self {
+            map,
+            config,
+            workers,
+        }
+    }
+
+    /// Returns the assignment used for routing.
+    pub fn sharding(&self) -> &Sharding {
+        self.map.sharding()
+    }
+
+    /// Queues a point for the series `key`, blocking while its shard has a
+    /// full batch and a full queue.
+    ///
+    /// Refused points do not fail the push; they are collected in
+    /// [`IngestReport::rejected`].
+    pub fn push(&mut self, key: K, dp: DataPoint) -> Result<(), IngestError> {
+        let shard = self.map.sharding().shard_for(&key);
+        let max_batch = self.config.max_batch;
+        let worker = &mut self.workers[shard as usize];
+        worker.pending.push((key, dp));
+        if worker.pending.len() >= max_batch {
+            worker.send(shard)
+        } else {
+            worker.try_send(shard).map(|_| ())
+        }
+    }
+
+    /// Queues a point for the series `key` without blocking, returning it in
+    /// [`TryPushError::Full`] if its shard has a full batch and a full queue.
+    pub fn try_push(&mut self, key: K, dp: DataPoint) -> Result<(), TryPushError<K>> {
+        let shard = self.map.sharding().shard_for(&key);
+        let worker = &mut self.workers[shard as usize];
+        if worker.pending.len() >= self.config.max_batch && !worker.try_send(shard)? {
+            return Err(TryPushError::Full(key, dp));
+        }
+        worker.pending.push((key, dp));
+        worker.try_send(shard)?;
+        Ok(())
+    }
+
+    /// Sends every pending batch and waits until the workers have appended
+    /// all points pushed so far.
+    pub fn flush(&mut self) -> Result<(), IngestError> {
+        let mut acks = Vec::with_capacity(self.workers.len());
+        for (shard, worker) in self.workers.iter_mut().enumerate() {
+            let shard = shard as u32;
+            worker.send(shard)?;
+            let (ack, done) = mpsc::channel();
+            worker
+                .sender
+                .send(Command::Flush(ack))
+                .map_err(|_| IngestError::WorkerFailed { shard })?;
+            acks.push((shard, done));
+        }
+        for (shard, done) in acks {
+            done.recv()
+                .map_err(|_| IngestError::WorkerFailed { shard })?;
+        }
+        Ok(())
+    }
+
+    /// Appends all pending points, stops the workers and returns the map.
+    pub fn finish(mut self) -> Result<IngestReport<K>, IngestError> {
+        let mut appended = 0;
+        let mut rejected = Vec::new();
+        for (shard, mut worker) in std::mem::take(&mut self.workers).into_iter().enumerate() {
+            let shard = shard as u32;
+            worker.send(shard)?;
+            drop(worker.sender);
+            let output = worker
+                .handle
+                .join()
+                .map_err(|_| IngestError::WorkerFailed { shard })?;
+            self.map.replace_shard(shard, output.map);
+            appended += output.appended;
+            rejected.extend(output.rejected);
+        }
+        Ok(IngestReport {
+            map: self.map,
+            appended,
+            rejected,
+        })
+    }
+}
+
+impl<K: SeriesKey> Worker<K> {
+    /// Sends the pending batch, if any, waiting for room in the queue.
+    fn send(&mut self, shard: u32) -> Result<(), IngestError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let batch = std::mem::take(&mut self.pending);
+        self.sender
+            .send(Command::Batch(batch))
+            .map_err(|_| IngestError::WorkerFailed { shard })
+    }
+
+    /// Sends the pending batch if the queue has room, returning whether the
+    /// batch is now empty.
+    fn try_send(&mut self, shard: u32) -> Result<bool, IngestError> {
+        if self.pending.is_empty() {
+            return Ok(true);
+        }
+        let batch = std::mem::take(&mut self.pending);
+        match self.sender.try_send(Command::Batch(batch)) {
+            Ok(()) => Ok(true),
+            Err(TrySendError::Full(Command::Batch(batch))) => {
+                self.pending = batch;
+                Ok(false)
+            }
+            Err(TrySendError::Full(Command::Flush(_))) => unreachable!("sent a batch"),
+            Err(TrySendError::Disconnected(_)) => Err(IngestError::WorkerFailed { shard }),
+        }
+    }
+}
+
+fn run<K: SeriesKey>(mut map: SeriesMap<K>, receiver: Receiver<Command<K>>) -> Output<K> {
+    let mut appended = 0;
+    let mut rejected = Vec::new();
+    for command in receiver {
+        match command {
+            Command::Batch(points) => {
+                for (key, point) in points {
+                    match map.append_ref(&key, point) {
+                        Ok(()) => appended += 1,
+                        Err(error) => rejected.push(Rejected { key, point, error }),
+                    }
+                }
+            }
+            Command::Flush(ack) => {
+                let _ = ack.send(());
+            }
+        }
+    }
+    Output {
+        map,
+        appended,
+        rejected,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admission::{AdmissionControl, Rejection};
+    use crate::encoder::OutOfOrderPolicy;
+    use crate::series::SeriesConfig;
+    use std::time::Instant;
+
+    fn key(series: u64) -> String {
+        format!("host-{series}")
+    }
+
+    #[test]
+    fn test_pipeline_matches_serial_appends() {
+        let sharding = Sharding::new(4);
+        let mut config = SeriesConfig::default();
+        config.encoder.on_out_of_order = OutOfOrderPolicy::Reject;
+        let mut serial = ShardedMap::new(sharding.clone(), config.clone());
+        let mut pipeline = Pipeline::new(
+            ShardedMap::new(sharding, config),
+            IngestConfig {
+                max_batch: 16,
+                queue_depth: 2,
+            },
+        );
+        for i in 0..500u64 {
+            for series in 0..20 {
+                let dp = DataPoint::new(1_000 + i * 10, (i * series) as f64);
+                serial.append(key(series), dp).unwrap();
+                pipeline.push(key(series), dp).unwrap();
+            }
+        }
+        pipeline.flush().unwrap();
+        pipeline.push(key(3), DataPoint::new(1_000, 0.0)).unwrap();
+
+        let report = pipeline.finish().unwrap();
+        assert_eq!(report.appended, 500 * 20);
+        assert_eq!(report.rejected.len(), 1);
+        assert_eq!(report.rejected[0].key, key(3));
+        assert!(matches!(report.rejected[0].error, AppendError::Encode(_)));
+        for series in 0..20 {
+            let shard = report.map.sharding().shard_for(&key(series));
+            let got = report.map.shard(shard).get(&key(series)).unwrap();
+            let want = serial.shard(shard).get(&key(series)).unwrap();
+            assert_eq!(got.len(), want.len());
+            assert_eq!(
+                got.open_encoder().to_compressed().bytes(),
+                want.open_encoder().to_compressed().bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn test_try_push_applies_backpressure() {
+        struct Gate(Option<Receiver<()>>);
+
+        impl AdmissionControl<String> for Gate {
+            fn admit(&mut self, _key: &String, _now: Instant) -> Result<(), Rejection> {
+                if let Some(release) = self.0.take() {
+                    release.recv().unwrap();
+                }
+                Ok(())
+            }
+        }
+
+        let (release, gate) = mpsc::channel();
+        let mut map = ShardedMap::new(Sharding::new(1), SeriesConfig::default());
+        let mut shard = map.take_shard(0);
+        shard.set_admission(Gate(Some(gate)));
+        map.replace_shard(0, shard);
+        let mut pipeline = Pipeline::new(
+            map,
+            IngestConfig {
+                max_batch: 2,
+                queue_depth: 1,
+            },
+        );
+
+        let mut accepted = 0;
+        let full =
+            (0..20u64).find_map(
+                |i| match pipeline.try_push(key(0), DataPoint::new(i, 1.0)) {
+                    Ok(()) => {
+                        accepted += 1;
+                        None
+                    }
+                    Err(err) => Some(err),
+                },
+            );
+        assert!(matches!(full, Some(TryPushError::Full(_, _))));
+        assert!(accepted < 10);
+
+        release.send(()).unwrap();
+        pipeline.flush().unwrap();
+        pipeline.try_push(key(0), DataPoint::new(100, 1.0)).unwrap();
+        let report = pipeline.finish().unwrap();
+        assert_eq!(report.appended, accepted + 1);
+        assert!(report.rejected.is_empty());
+    }
+}
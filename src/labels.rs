@@ -0,0 +1,486 @@
+//! Series identity as a set of labels.
+//!
+//! [`Labels`] are `name=value` pairs sorted by name, the way Prometheus and
+//! InfluxDB identify series: the metric name is the [`METRIC_NAME`] label,
+//! and two sets with the same pairs are the same series whatever order the
+//! pairs were given in. Pairs with an empty value are dropped, since
+//! Prometheus treats them as absent. `Labels` implement [`SeriesKey`], so
+//! they key a [`SeriesMap`](crate::SeriesMap), sharded map or tiered store
+//! directly, and [`Labels::id`] gives a stable 64-bit fingerprint.
+//!
+//! A [`LabelInterner`] shares the strings of the label sets it has seen, so
+//! many series keyed by labels do not each hold a copy of `host`, `env` or
+//! `__name__`.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::checksum::hash64;
+use crate::series::SeriesKey;
+
+/// Name of the label holding the metric name.
+pub const METRIC_NAME: &str = "__name__";
+
+/// Error returned when parsing [`Labels`] from text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LabelsError {
+    /// The text does not follow `name{label="value",...}` at the given byte
+    /// offset.
+    Syntax { position: usize },
+    /// A metric or label name is not an identifier.
+    InvalidName(String),
+}
+
+impl fmt::Display for LabelsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LabelsError::Syntax { position } => {
+                write!(f, "invalid label syntax at byte {position}")
+            }
+            LabelsError::InvalidName(name) => write!(f, "invalid label name {name:?}"),
+        }
+    }
+}
+
+impl std::error::Error for LabelsError {}
+
+/// A set of `name=value` labels identifying a series, sorted by name.
+///
+/// Cloning is cheap: the pairs are shared.
+///
+/// ```
+/// use gorilla::Labels;
+///
+/// let a = Labels::metric("cpu", [("host", "a"), ("env", "prod")]);
+/// let b: Labels = r#"cpu{env="prod",host="a"}"#.parse().unwrap();
+/// assert_eq!(a, b);
+/// assert_eq!(a.id(), b.id());
+/// assert_eq!(a.get("host"), Some("a"));
+/// assert_eq!(a.to_string(), r#"cpu{env="prod",host="a"}"#);
+/// ```
+#[derive(Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Labels {
+    pairs: Arc<[(Arc<str>, Arc<str>)]>,
+}
+
+impl Labels {
+    /// Creates a label set from `(name, value)` pairs in any order. Of
+    /// pairs with the same name the last one wins; pairs with an empty
+    /// value are dropped.
+    pub fn new<I, N, V>(pairs: I) -> Self
+    where
+        I: IntoIterator<Item = (N, V)>,
+        N: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut pairs: Vec<(Arc<str>, Arc<str>)> = pairs
+            .into_iter()
+            .map(|(name, value)| (Arc::from(name.as_ref()), Arc::from(value.as_ref())))
+            .collect();
+        // A stable sort keeps duplicates in input order; keep the last.
+        pairs.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut deduped: Vec<(Arc<str>, Arc<str>)> = Vec::with_capacity(pairs.len());
+        for pair in pairs {
+            match deduped.last_mut() {
+                Some(last) if last.0 == pair.0 => *last = pair,
+                _ => deduped.push(pair),
+            }
+        }
+        deduped.retain(|(_, value)| !value.is_empty());
+        Self {
+            pairs: deduped.into(),
+        }
+    }
+
+    /// Creates a label set for the metric `name` with the given labels.
+    pub fn metric<I, N, V>(name: &str, labels: I) -> Self
+    where
+        I: IntoIterator<Item = (N, V)>,
+        N: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let labels = labels
+            .into_iter()
+            .map(|(n, v)| (n.as_ref().to_string(), v.as_ref().to_string()));
+        Self::new(std::iter::once((METRIC_NAME.to_string(), name.to_string())).chain(labels))
+    }
+
+    /// Returns the metric name, the value of [`METRIC_NAME`].
+    pub fn name(&self) -> Option<&str> {
+        self.get(METRIC_NAME)
+    }
+
+    /// Returns the value of label `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        let i = self
+            .pairs
+            .binary_search_by(|(n, _)| n.as_ref().cmp(name))
+            .ok()?;
+        Some(&self.pairs[i].1)
+    }
+
+    /// Returns the pairs in order of their names.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(n, v)| (n.as_ref(), v.as_ref()))
+    }
+
+    /// Returns the number of labels, including the metric name.
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// Returns `true` if there are no labels.
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Returns a copy with label `name` set to `value`, or removed if
+    /// `value` is empty.
+    pub fn with(&self, name: &str, value: &str) -> Self {
+        Self::new(self.iter().chain([(name, value)]))
+    }
+
+    /// Returns a copy without label `name`.
+    pub fn without(&self, name: &str) -> Self {
+        Self::new(self.iter().filter(|&(n, _)| n != name))
+    }
+
+    /// Returns the fingerprint of the set: a hash of its
+    /// [key bytes](SeriesKey::to_key_bytes) that is the same on every
+    /// platform and release.
+    pub fn id(&self) -> SeriesId {
+        SeriesId(hash64(&self.to_key_bytes()))
+    }
+}
+
+impl fmt::Debug for Labels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Formats the set like Prometheus: `name{label="value",...}`, with the
+/// braces left out if there are no labels besides the metric name.
+impl fmt::Display for Labels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(name) = self.name() {
+            f.write_str(name)?;
+        }
+        let mut labels = self.iter().filter(|&(n, _)| n != METRIC_NAME).peekable();
+        if labels.peek().is_none() && self.name().is_some() {
+            return Ok(());
+        }
+        f.write_str("{")?;
+        for (i, (name, value)) in labels.enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{name}=\"")?;
+            for c in value.chars() {
+                match c {
+                    '\\' => f.write_str("\\\\")?,
+                    '"' => f.write_str("\\\"")?,
+                    '\n' => f.write_str("\\n")?,
+                    c => write!(f, "{c}")?,
+                }
+            }
+            f.write_str("\"")?;
+        }
+        f.write_str("}")
+    }
+}
+
+/// Parses the format written by [`Display`](fmt::Display): an optional
+/// metric name followed by optional braces holding `label="value"` pairs.
+impl FromStr for Labels {
+    type Err = LabelsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let syntax = |position| LabelsError::Syntax { position };
+        let (name, rest) = s.split_at(s.find('{').unwrap_or(s.len()));
+        let name = name.trim();
+        let mut pairs = Vec::new();
+        if !name.is_empty() {
+            check_name(name, true)?;
+            pairs.push((METRIC_NAME.to_string(), name.to_string()));
+        }
+        if rest.is_empty() {
+            return Ok(Self::new(pairs));
+        }
+        let offset = s.len() - rest.len();
+        let mut chars = rest.char_indices().skip(1).peekable();
+        loop {
+            while chars.next_if(|&(_, c)| c.is_whitespace()).is_some() {}
+            match chars.peek() {
+                Some(&(_, '}')) => {
+                    chars.next();
+                    break;
+                }
+                Some(_) => {}
+                None => return Err(syntax(s.len())),
+            }
+            let mut label = String::new();
+            while let Some((_, c)) = chars.next_if(|&(_, c)| c != '=' && c != '}') {
+                label.push(c);
+            }
+            let label = label.trim().to_string();
+            check_name(&label, false)?;
+            for expected in ['=', '"'] {
+                while chars.next_if(|&(_, c)| c.is_whitespace()).is_some() {}
+                match chars.next() {
+                    Some((_, c)) if c == expected => {}
+                    Some((i, _)) => return Err(syntax(offset + i)),
+                    None => return Err(syntax(s.len())),
+                }
+            }
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, 'n')) => value.push('\n'),
+                        Some((_, c @ ('\\' | '"'))) => value.push(c),
+                        Some((i, _)) => return Err(syntax(offset + i)),
+                        None => return Err(syntax(s.len())),
+                    },
+                    Some((_, c)) => value.push(c),
+                    None => return Err(syntax(s.len())),
+                }
+            }
+            pairs.push((label, value));
+            while chars.next_if(|&(_, c)| c.is_whitespace()).is_some() {}
+            match chars.next() {
+                Some((_, ',')) => {}
+                Some((_, '}')) => break,
+                Some((i, _)) => return Err(syntax(offset + i)),
+                None => return Err(syntax(s.len())),
+            }
+        }
+        while chars.next_if(|&(_, c)| c.is_whitespace()).is_some() {}
+        if let Some((i, _)) = chars.next() {
+            return Err(syntax(offset + i));
+        }
+        Ok(Self::new(pairs))
+    }
+}
+
+/// Checks that `name` is a Prometheus label name, or metric name if
+/// `metric` is set, which may also contain colons.
+fn check_name(name: &str, metric: bool) -> Result<(), LabelsError> {
+    let allowed = |c: char| c.is_ascii_alphanumeric() || c == '_' || (metric && c == ':');
+    let valid =
+        name.chars().all(allowed) && name.chars().next().is_some_and(|c| !c.is_ascii_digit());
+    if valid {
+        Ok(())
+    } else {
+        Err(LabelsError::InvalidName(name.to_string()))
+    }
+}
+
+/// Stored as each name and value in turn, prefixed by its length as 4
+/// bytes (LE).
+impl SeriesKey for Labels {
+    fn to_key_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (name, value) in self.iter() {
+            for s in [name, value] {
+                out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                out.extend_from_slice(s.as_bytes());
+            }
+        }
+        out
+    }
+
+    fn from_key_bytes(mut bytes: &[u8]) -> Option<Self> {
+        let mut next = || -> Option<String> {
+            let len = u32::from_le_bytes(bytes.get(..4)?.try_into().ok()?) as usize;
+            let s = std::str::from_utf8(bytes.get(4..4 + len)?)
+                .ok()?
+                .to_string();
+            bytes = &bytes[4 + len..];
+            Some(s)
+        };
+        let mut pairs = Vec::new();
+        while let Some(name) = next() {
+            pairs.push((name, next()?));
+        }
+        if !bytes.is_empty() {
+            return None;
+        }
+        Some(Labels::new(pairs))
+    }
+}
+
+/// Fingerprint of a label set, see [`Labels::id`].
+///
+/// Distinct sets can share a fingerprint, if rarely; key by the
+/// [`Labels`] themselves where that matters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SeriesId(pub u64);
+
+impl fmt::Display for SeriesId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl SeriesKey for SeriesId {
+    fn to_key_bytes(&self) -> Vec<u8> {
+        self.0.to_key_bytes()
+    }
+
+    fn from_key_bytes(bytes: &[u8]) -> Option<Self> {
+        u64::from_key_bytes(bytes).map(SeriesId)
+    }
+}
+
+/// Shares the strings of the label sets it interns.
+///
+/// ```
+/// use gorilla::{LabelInterner, Labels};
+///
+/// let mut interner = LabelInterner::new();
+/// let a = interner.labels([("__name__", "cpu"), ("host", "a")]);
+/// let b = interner.labels([("__name__", "mem"), ("host", "a")]);
+/// assert_eq!(interner.len(), 2);
+/// assert_eq!(interner.strings(), 5);
+/// assert_eq!(a, Labels::metric("cpu", [("host", "a")]));
+/// # let _ = b;
+/// ```
+#[derive(Debug, Default)]
+pub struct LabelInterner {
+    strings: HashSet<Arc<str>>,
+    sets: HashSet<Labels>,
+}
+
+impl LabelInterner {
+    /// Creates an empty interner.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a set equal to `labels` that shares its pairs with every
+    /// other set equal to it, and its strings with every other set interned
+    /// here.
+    pub fn intern(&mut self, labels: &Labels) -> Labels {
+        if let Some(interned) = self.sets.get(labels) {
+            return interned.clone();
+        }
+        let pairs: Vec<_> = labels
+            .pairs
+            .iter()
+            .map(|(name, value)| (self.string(name), self.string(value)))
+            .collect();
+        let interned = Labels {
+            pairs: pairs.into(),
+        };
+        self.sets.insert(interned.clone());
+        interned
+    }
+
+    /// Builds a set from `(name, value)` pairs, as [`Labels::new`], and
+    /// interns it.
+    pub fn labels<I, N, V>(&mut self, pairs: I) -> Labels
+    where
+        I: IntoIterator<Item = (N, V)>,
+        N: AsRef<str>,
+        V: AsRef<str>,
+    {
+        self.intern(&Labels::new(pairs))
+    }
+
+    /// Returns the number of distinct sets interned.
+    pub fn len(&self) -> usize {
+        self.sets.len()
+    }
+
+    /// Returns `true` if nothing has been interned.
+    pub fn is_empty(&self) -> bool {
+        self.sets.is_empty()
+    }
+
+    /// Returns the number of distinct names and values held.
+    pub fn strings(&self) -> usize {
+        self.strings.len()
+    }
+
+    fn string(&mut self, s: &Arc<str>) -> Arc<str> {
+        if let Some(shared) = self.strings.get(s) {
+            return shared.clone();
+        }
+        self.strings.insert(s.clone());
+        s.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::DataPoint;
+    use crate::map::SeriesMap;
+
+    #[test]
+    fn test_labels_normalize_and_roundtrip() {
+        let labels = Labels::new([("b", "2"), ("a", "1"), ("b", "3"), ("c", "")]);
+        assert_eq!(labels.iter().collect::<Vec<_>>(), [("a", "1"), ("b", "3")]);
+        assert_eq!(labels.name(), None);
+        assert_eq!(labels.to_string(), r#"{a="1",b="3"}"#);
+        assert_eq!(
+            labels.with("c", "x").without("a").to_string(),
+            r#"{b="3",c="x"}"#
+        );
+
+        let tricky = Labels::metric("http:requests", [("path", "/a \"b\"\\\n"), ("code", "200")]);
+        let text = tricky.to_string();
+        assert_eq!(text.parse::<Labels>().unwrap(), tricky);
+        assert_eq!(
+            Labels::from_key_bytes(&tricky.to_key_bytes()),
+            Some(tricky.clone())
+        );
+        assert_eq!(
+            "up".parse::<Labels>().unwrap(),
+            Labels::new([(METRIC_NAME, "up")])
+        );
+        assert_eq!(
+            r#" up { job = "x" , } "#.parse::<Labels>().unwrap().to_string(),
+            r#"up{job="x"}"#
+        );
+
+        assert_eq!(
+            "cpu{host=a}".parse::<Labels>(),
+            Err(LabelsError::Syntax { position: 9 })
+        );
+        assert_eq!(
+            r#"cpu{host="a"#.parse::<Labels>(),
+            Err(LabelsError::Syntax { position: 11 })
+        );
+        assert_eq!(
+            r#"cpu{1x="a"}"#.parse::<Labels>(),
+            Err(LabelsError::InvalidName("1x".into()))
+        );
+        assert_eq!(Labels::from_key_bytes(&[1, 0, 0]), None);
+    }
+
+    #[test]
+    fn test_labels_key_series_maps() {
+        let mut interner = LabelInterner::new();
+        let mut map = SeriesMap::default();
+        for host in ["a", "b"] {
+            for t in 0..3 {
+                let labels = interner.labels([("host", host), (METRIC_NAME, "cpu")]);
+                map.append(labels, DataPoint::new(t * 60, 1.0)).unwrap();
+            }
+        }
+        assert_eq!(map.len(), 2);
+        assert_eq!(interner.len(), 2);
+        assert_eq!(interner.strings(), 5);
+        let key: Labels = r#"cpu{host="b"}"#.parse().unwrap();
+        assert_eq!(map.get(&key).unwrap().len(), 3);
+
+        // Fingerprints are pinned, so that nodes on different releases agree.
+        assert_eq!(key.id(), SeriesId(0x7D48_D112_D25E_CA99));
+        assert_ne!(key.id(), key.with("host", "a").id());
+    }
+}
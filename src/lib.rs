@@ -30,7 +30,7 @@
 //! encoder.finish().unwrap();
 //!
 //! let compressed = encoder.into_compressed();
-//! println!("Compressed {} points into {} bytes", compressed.count, compressed.bytes.len());
+//! println!("Compressed {} points into {} bytes", compressed.count(), compressed.bytes().len());
 //!
 //! // Decompress
 //! let points = Decoder::decode(&compressed).unwrap();
@@ -55,11 +55,109 @@
 //! }
 //! ```
 
+pub mod admission;
+#[cfg(feature = "arrow")]
+pub mod arrow;
 pub mod bitbuffer;
+pub mod block_file;
+pub mod boolean;
+#[cfg(feature = "rayon")]
+pub mod bulk;
+pub mod capabilities;
+pub mod checksum;
+pub mod chimp;
+pub mod chunked;
+pub mod codec;
+pub mod compaction;
+pub mod concurrent;
+#[cfg(feature = "chrono")]
+pub mod datetime;
+pub mod decimal;
 pub mod decoder;
+pub mod durable;
 pub mod encoder;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "half")]
+pub mod half_float;
+pub mod ingest;
+pub mod labels;
+pub mod line_protocol;
+pub mod lossy;
+pub mod map;
+pub mod merge;
+pub mod metadata;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod query;
+pub mod regular;
+pub mod replay;
+pub mod scan;
+pub mod schema;
+pub mod segment;
+pub mod series;
+pub mod shard;
+pub mod signed;
+pub mod snapshot;
+pub mod soak;
+pub mod statsd;
+pub mod store;
+#[cfg(feature = "test-util")]
+pub mod testutil;
+pub mod tiered;
+pub mod transcode;
+pub mod typed;
+pub mod vtab;
+pub mod wal;
 
 // Re-export primary types at the crate root.
+pub use admission::{AdmissionControl, RateLimit, RateLimiter, RateLimits, Rejection};
 pub use bitbuffer::BufferFull;
-pub use decoder::{DecodeError, Decoder, DecoderIter};
-pub use encoder::{CompressedBlock, DataPoint, Encoder};
+pub use block_file::{BlockFileEntry, BlockFileReader, BlockFileWriter};
+pub use boolean::{BoolBlock, BoolEncoder, BoolIter};
+#[cfg(feature = "rayon")]
+pub use bulk::compress_all;
+pub use capabilities::{FormatCapabilities, NegotiationError};
+pub use chunked::{ChunkConfig, ChunkedEncoder};
+pub use codec::{DeltaState, TimestampCodec, TimestampScheme, ValueCodec, ValueScheme};
+pub use compaction::{BlockInfo, CompactionPolicy, SizeTiered, TimeWindowed};
+pub use concurrent::{SharedBlock, SyncEncoder, SyncReader};
+#[cfg(feature = "chrono")]
+pub use datetime::{DateTimes, TimedPoint};
+pub use decimal::{
+    Decimal, DecimalBlock, DecimalDecoder, DecimalEncoder, DecimalError, DecimalPoint,
+};
+pub use decoder::{
+    DecodeError, Decoder, DecoderIter, DodBucket, Downsample, EveryNth, Interpolation,
+    PointEncoding, Resample, Sample, Samples, ValueEncoding,
+};
+pub use durable::{open_shards, DurableError, DurableMap, ReplayProgress};
+pub use encoder::{
+    merged_watermark, BlockStats, Checkpoint, CompressedBlock, CompressionStats, DataPoint,
+    DeadBand, DuplicatePolicy, EncodeError, Encoder, EncoderConfig, ExactPoint, InvalidBlock,
+    MergeError, OutOfOrderPolicy, Priors,
+};
+#[cfg(feature = "half")]
+pub use half_float::{HalfBlock, HalfDecoder, HalfEncoder, HalfFloat, HalfPoint};
+pub use ingest::{IngestConfig, IngestError, IngestReport, Pipeline, Rejected, TryPushError};
+pub use labels::{LabelInterner, Labels, LabelsError, SeriesId};
+pub use lossy::{Precision, QuantizeSpec};
+pub use map::{FlushReport, SeriesMap};
+pub use merge::{KWayMerge, SkewTolerance, Winner};
+pub use metadata::{BlockMetadata, MetadataTooLarge};
+pub use query::{evaluate_step, AggFn, QueryError};
+pub use regular::{RegularBlock, RegularDecoder, RegularEncoder};
+pub use replay::ReplayConfig;
+pub use schema::{FieldDef, Projection, Schema};
+pub use series::{
+    AppendError, BlockUsage, ReplaceError, SeriesConfig, SeriesKey, SeriesQuery, TimeSeries,
+};
+pub use shard::{ShardedMap, Sharding};
+pub use signed::{DataPoint64, SignedBlock, SignedDecoder, SignedEncoder, SignedIter};
+pub use snapshot::{EncoderState, RestoreError};
+pub use statsd::{StatsdAggregator, StatsdConfig};
+pub use store::{BlockStore, TimeSeriesMap};
+pub use tiered::{TieredConfig, TieredError, TieredQuery, TieredStore};
+pub use transcode::{transcode, TranscodeConfig, TranscodeError};
+pub use typed::{DecodedSeries, TypedBlock, ValueType};
+pub use wal::{Wal, WalReader, WalRecord};
@@ -0,0 +1,337 @@
+//! InfluxDB line protocol ingestion.
+//!
+//! Each line `measurement[,tag=value...] field=value[,field=value...]
+//! [timestamp]` yields one sample per numeric field. Fields are stored as
+//! separate series named like StatsD metrics with folded tags:
+//! `cpu,host=a,env=prod idle=97.5,user=1i 1609459200000000000` appends to
+//! `cpu.idle;env=prod;host=a` and `cpu.user;env=prod;host=a`.
+//!
+//! Floats, integers (`1i`) and unsigned integers (`1u`) are stored as
+//! `f64`; boolean and string fields are skipped. Backslash escapes follow
+//! the protocol: `\,`, `\=` and `\ ` in names, tag values and field keys,
+//! `\"` and `\\` in string fields. Blank lines and `#` comments are ignored.
+
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::encoder::DataPoint;
+use crate::map::SeriesMap;
+use crate::series::AppendError;
+
+/// Error describing why a line was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineError {
+    /// The line has no measurement name.
+    MissingMeasurement,
+    /// The line has no field set.
+    MissingFields,
+    /// A tag is not a non-empty `key=value` pair.
+    InvalidTag(String),
+    /// A field is not a `key=value` pair or its value cannot be parsed.
+    InvalidField(String),
+    /// The timestamp is not an unsigned integer, or is followed by more
+    /// text.
+    InvalidTimestamp(String),
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineError::MissingMeasurement => write!(f, "missing measurement"),
+            LineError::MissingFields => write!(f, "missing field set"),
+            LineError::InvalidTag(t) => write!(f, "invalid tag {t:?}"),
+            LineError::InvalidField(v) => write!(f, "invalid field {v:?}"),
+            LineError::InvalidTimestamp(t) => write!(f, "invalid timestamp {t:?}"),
+        }
+    }
+}
+
+impl std::error::Error for LineError {}
+
+/// Error returned by [`ingest`] and [`ingest_into`], with the 1-based
+/// number of the offending line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IngestError {
+    /// The line could not be parsed.
+    Parse { line: usize, error: LineError },
+    /// A sample of the line could not be appended.
+    Append { line: usize, error: AppendError },
+}
+
+impl fmt::Display for IngestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IngestError::Parse { line, error } => write!(f, "line {line}: {error}"),
+            IngestError::Append { line, error } => write!(f, "line {line}: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for IngestError {}
+
+/// One numeric field value of a parsed line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    /// Series name: measurement and field key, followed by the sorted tags.
+    pub series: String,
+    pub timestamp: u64,
+    pub value: f64,
+}
+
+/// Parses one line into its numeric samples, using `default_timestamp` if
+/// the line has none. Blank lines and comments yield no samples.
+pub fn parse_line(line: &str, default_timestamp: u64) -> Result<Vec<Sample>, LineError> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    if line.trim().is_empty() || line.starts_with('#') {
+        return Ok(Vec::new());
+    }
+    let sections = split_unescaped(line, ' ', true);
+    let (series, fields, timestamp) = match sections.as_slice() {
+        [series] => (*series, "", None),
+        [series, fields] => (*series, *fields, None),
+        [series, fields, timestamp] => (*series, *fields, Some(*timestamp)),
+        [_, _, rest @ ..] => return Err(LineError::InvalidTimestamp(rest.join(" "))),
+        [] => unreachable!("split always yields at least one piece"),
+    };
+
+    let mut parts = split_unescaped(series, ',', false).into_iter();
+    let measurement = unescape(parts.next().unwrap_or_default());
+    if measurement.is_empty() {
+        return Err(LineError::MissingMeasurement);
+    }
+    let mut tags = Vec::new();
+    for tag in parts {
+        match split_unescaped(tag, '=', false).as_slice() {
+            [key, value] if !key.is_empty() && !value.is_empty() => {
+                tags.push(format!("{}={}", unescape(key), unescape(value)));
+            }
+            _ => return Err(LineError::InvalidTag(tag.to_string())),
+        }
+    }
+    tags.sort_unstable();
+
+    let timestamp = match timestamp {
+        Some(text) => text
+            .parse()
+            .map_err(|_| LineError::InvalidTimestamp(text.to_string()))?,
+        None => default_timestamp,
+    };
+    if fields.is_empty() {
+        return Err(LineError::MissingFields);
+    }
+    let mut samples = Vec::new();
+    for field in split_unescaped(fields, ',', true) {
+        let invalid = || LineError::InvalidField(field.to_string());
+        let (key, value) = split_first_unescaped(field, '=').ok_or_else(invalid)?;
+        if key.is_empty() {
+            return Err(invalid());
+        }
+        let Some(value) = parse_value(value).ok_or_else(invalid)? else {
+            continue;
+        };
+        let mut series = format!("{measurement}.{}", unescape(key));
+        for tag in &tags {
+            series.push(';');
+            series.push_str(tag);
+        }
+        samples.push(Sample {
+            series,
+            timestamp,
+            value,
+        });
+    }
+    Ok(samples)
+}
+
+/// Parses `input` and compresses every numeric field into its own series
+/// of a new map. Lines without a timestamp get the current time in
+/// nanoseconds, the protocol's default precision.
+///
+/// # Example
+/// ```
+/// use gorilla::line_protocol;
+///
+/// let input = "weather,station=ams temp=11.5,humidity=81i 1609459200\n\
+///              weather,station=ams temp=11.25,humidity=80i 1609459260\n";
+/// let map = line_protocol::ingest(input).unwrap();
+/// assert_eq!(map.len(), 2);
+/// let temp = map.get(&"weather.temp;station=ams".to_string()).unwrap();
+/// assert_eq!(temp.len(), 2);
+/// ```
+pub fn ingest(input: &str) -> Result<SeriesMap<String>, IngestError> {
+    let mut map = SeriesMap::default();
+    ingest_into(input, &mut map)?;
+    Ok(map)
+}
+
+/// Like [`ingest`], but appends to an existing map and returns the number
+/// of samples appended. Lines before a failing one stay appended.
+pub fn ingest_into(input: &str, map: &mut SeriesMap<String>) -> Result<usize, IngestError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    let mut appended = 0;
+    for (i, text) in input.lines().enumerate() {
+        let line = i + 1;
+        let samples = parse_line(text, now).map_err(|error| IngestError::Parse { line, error })?;
+        for sample in samples {
+            map.append(
+                sample.series,
+                DataPoint::new(sample.timestamp, sample.value),
+            )
+            .map_err(|error| IngestError::Append { line, error })?;
+            appended += 1;
+        }
+    }
+    Ok(appended)
+}
+
+/// Parses a field value, returning `Some(None)` for non-numeric values.
+fn parse_value(value: &str) -> Option<Option<f64>> {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        return Some(None);
+    }
+    match value {
+        "t" | "T" | "true" | "True" | "TRUE" | "f" | "F" | "false" | "False" | "FALSE" => {
+            return Some(None)
+        }
+        _ => {}
+    }
+    let number = if let Some(int) = value.strip_suffix('i') {
+        int.parse::<i64>().ok()? as f64
+    } else if let Some(uint) = value.strip_suffix('u') {
+        uint.parse::<u64>().ok()? as f64
+    } else {
+        value.parse::<f64>().ok().filter(|v| v.is_finite())?
+    };
+    Some(Some(number))
+}
+
+/// Splits `s` at every `sep` not preceded by a backslash and, if `quotes`
+/// is set, not inside a double-quoted string.
+fn split_unescaped(s: &str, sep: char, quotes: bool) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    let mut quoted = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' if quotes => quoted = !quoted,
+            c if c == sep && !quoted => {
+                pieces.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    pieces.push(&s[start..]);
+    pieces
+}
+
+/// Splits `s` at its first unescaped `sep`.
+fn split_first_unescaped(s: &str, sep: char) -> Option<(&str, &str)> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == sep => return Some((&s[..i], &s[i + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Removes the backslashes of `\,`, `\=`, `\ `, `\"` and `\\`.
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(&next) = chars.peek() {
+                if matches!(next, ',' | '=' | ' ' | '"' | '\\') {
+                    out.push(next);
+                    chars.next();
+                    continue;
+                }
+            }
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(series: &str, timestamp: u64, value: f64) -> Sample {
+        Sample {
+            series: series.to_string(),
+            timestamp,
+            value,
+        }
+    }
+
+    #[test]
+    fn test_parse_line_fields_and_escapes() {
+        let line =
+            r#"disk\ io,path=/var\,log,host=a reads=3i,busy=0.25,up=t,note="a, b=c",free=7u 100"#;
+        assert_eq!(
+            parse_line(line, 0).unwrap(),
+            [
+                sample("disk io.reads;host=a;path=/var,log", 100, 3.0),
+                sample("disk io.busy;host=a;path=/var,log", 100, 0.25),
+                sample("disk io.free;host=a;path=/var,log", 100, 7.0),
+            ]
+        );
+        assert_eq!(
+            parse_line("cpu value=1", 42).unwrap(),
+            [sample("cpu.value", 42, 1.0)]
+        );
+        assert!(parse_line("# comment", 0).unwrap().is_empty());
+        assert!(parse_line("   ", 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_line_errors() {
+        assert_eq!(parse_line("cpu", 0), Err(LineError::MissingFields));
+        assert_eq!(
+            parse_line(",host=a value=1", 0),
+            Err(LineError::MissingMeasurement)
+        );
+        assert_eq!(
+            parse_line("cpu,host value=1", 0),
+            Err(LineError::InvalidTag("host".into()))
+        );
+        assert_eq!(
+            parse_line("cpu value=abc", 0),
+            Err(LineError::InvalidField("value=abc".into()))
+        );
+        assert_eq!(
+            parse_line("cpu value=1 -5", 0),
+            Err(LineError::InvalidTimestamp("-5".into()))
+        );
+        assert_eq!(
+            parse_line("cpu value=1 5 6", 0),
+            Err(LineError::InvalidTimestamp("5 6".into()))
+        );
+    }
+
+    #[test]
+    fn test_ingest_reports_line_numbers() {
+        let mut map = SeriesMap::default();
+        let input = "cpu,host=a idle=90 10\ncpu,host=a idle=91 20\n\ncpu idle= 30\n";
+        assert_eq!(
+            ingest_into(input, &mut map),
+            Err(IngestError::Parse {
+                line: 4,
+                error: LineError::InvalidField("idle=".into())
+            })
+        );
+        let series = map.get(&"cpu.idle;host=a".to_string()).unwrap();
+        assert_eq!(series.last_point(), Some(DataPoint::new(20, 91.0)));
+    }
+}
@@ -0,0 +1,270 @@
+//! Lossy value mode that erases trailing mantissa bits before encoding.
+//!
+//! Noisy sensor readings carry random low-order mantissa bits that leave
+//! every XOR wide. With [`EncoderConfig::precision`](crate::EncoderConfig::precision)
+//! set, the encoder zeroes as many of those bits as the chosen
+//! [`Precision`] allows, in the spirit of the Elf scheme, so XORs end in
+//! long runs of zeros that cost nothing to store. Decoding is unchanged and
+//! returns the truncated values.
+//!
+//! [`EncoderConfig::quantizer`](crate::EncoderConfig::quantizer) instead
+//! rounds values to the nearest point of a grid described by a
+//! [`QuantizeSpec`], which is recorded in the block so that readers know
+//! the error bound. Slowly changing readings then repeat exactly, each
+//! repeat costing a single bit.
+
+/// Error bound of the lossy value mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Precision {
+    /// Keeps values within half a unit of their `n`th significant decimal
+    /// digit, e.g. within 0.005 of 21.4837 for 4 digits.
+    SignificantDigits(u8),
+    /// Keeps values within this distance of their input.
+    AbsoluteError(f64),
+}
+
+impl Precision {
+    /// Returns the largest distance from `value` the stored value may have.
+    fn bound(self, value: f64) -> f64 {
+        match self {
+            Precision::SignificantDigits(digits) => {
+                let magnitude = value.abs();
+                let mut exponent = magnitude.log10().floor() as i32;
+                // log10 may round up just below a power of ten.
+                if 10f64.powi(exponent) > magnitude {
+                    exponent -= 1;
+                }
+                0.5 * 10f64.powi(exponent + 1 - digits as i32)
+            }
+            Precision::AbsoluteError(error) => error,
+        }
+    }
+
+    /// Returns `value` with as many trailing mantissa bits zeroed as keeps it
+    /// within the bound. Zeros, subnormals, infinities and NaN are returned
+    /// unchanged, as is every value under a bound that is not positive.
+    ///
+    /// ```
+    /// use gorilla::lossy::Precision;
+    ///
+    /// let stored = Precision::AbsoluteError(0.01).apply(21.4837);
+    /// assert!((stored - 21.4837).abs() <= 0.01);
+    /// assert!(stored.to_bits().trailing_zeros() >= 41);
+    /// ```
+    pub fn apply(self, value: f64) -> f64 {
+        if !value.is_normal() {
+            return value;
+        }
+        let bound = self.bound(value);
+        if bound.is_nan() || bound <= 0.0 {
+            return value;
+        }
+        // Zeroing the lowest k mantissa bits of 1.m × 2^e moves the value
+        // toward zero by less than 2^(e - 52 + k).
+        let exponent = ((value.to_bits() >> 52) & 0x7FF) as i32 - 1023;
+        let mut erase = (bound.log2().floor() as i32 + 52 - exponent).clamp(0, 52);
+        loop {
+            let erased = f64::from_bits(value.to_bits() & (u64::MAX << erase));
+            if (erased - value).abs() <= bound || erase == 0 {
+                return erased;
+            }
+            erase -= 1;
+        }
+    }
+}
+
+/// Grid the encoder rounds values to, see [`Encoder::with_quantizer`].
+///
+/// [`Encoder::with_quantizer`]: crate::Encoder::with_quantizer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuantizeSpec {
+    /// Rounds values to multiples of the largest power of two not above
+    /// twice this error, keeping them within it of their input.
+    AbsError(f64),
+    /// Rounds the mantissa of values to as few bits as keep them within this
+    /// fraction of their magnitude, e.g. `0.001` for 0.1%.
+    RelError(f64),
+}
+
+impl QuantizeSpec {
+    /// Returns the largest distance from `value` its quantized value may
+    /// have.
+    pub fn max_error(self, value: f64) -> f64 {
+        match self {
+            QuantizeSpec::AbsError(error) => error,
+            QuantizeSpec::RelError(fraction) => fraction * value.abs(),
+        }
+    }
+
+    /// Returns the point of the grid nearest to `value`. Infinities, NaN,
+    /// subnormals and values that would round out of range are returned
+    /// unchanged, as is every value under an error that is not positive.
+    ///
+    /// ```
+    /// use gorilla::lossy::QuantizeSpec;
+    ///
+    /// assert_eq!(QuantizeSpec::AbsError(0.01).apply(21.4837), 21.484375);
+    /// assert_eq!(QuantizeSpec::RelError(0.01).apply(21.4837), 21.5);
+    /// ```
+    pub fn apply(self, value: f64) -> f64 {
+        if !value.is_normal() {
+            return value;
+        }
+        let quantized = match self {
+            QuantizeSpec::AbsError(error) => {
+                if error.is_nan() || error <= 0.0 || !(2.0 * error).is_finite() {
+                    return value;
+                }
+                let step = 2f64.powi((2.0 * error).log2().floor() as i32);
+                if !step.is_normal() {
+                    return value;
+                }
+                // Dividing and multiplying by a power of two is exact.
+                (value / step).round() * step
+            }
+            QuantizeSpec::RelError(fraction) => {
+                if fraction.is_nan() || fraction <= 0.0 {
+                    return value;
+                }
+                // Rounding to k mantissa bits moves 1.m × 2^e by at most
+                // 2^(e - k - 1), which is within `fraction` of it when
+                // 2^-(k + 1) <= fraction.
+                let keep = ((-fraction.log2()).ceil() as i32 - 1).clamp(0, 52);
+                if keep == 52 {
+                    return value;
+                }
+                let erase = 52 - keep;
+                let half = 1u64 << (erase - 1);
+                // A carry out of the mantissa bumps the exponent, which
+                // is the correctly rounded result.
+                f64::from_bits((value.to_bits() + half) & (u64::MAX << erase))
+            }
+        };
+        if quantized.is_finite() {
+            quantized
+        } else {
+            value
+        }
+    }
+
+    /// Returns the identifier of the spec's kind in a block header.
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            QuantizeSpec::AbsError(_) => 0,
+            QuantizeSpec::RelError(_) => 1,
+        }
+    }
+
+    /// Returns the spec's error parameter.
+    pub(crate) fn param(self) -> f64 {
+        match self {
+            QuantizeSpec::AbsError(error) | QuantizeSpec::RelError(error) => error,
+        }
+    }
+
+    /// Returns the spec with kind `id` and error parameter `param`, if
+    /// there is one.
+    pub(crate) fn from_parts(id: u8, param: f64) -> Option<Self> {
+        match id {
+            0 => Some(QuantizeSpec::AbsError(param)),
+            1 => Some(QuantizeSpec::RelError(param)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::{DataPoint, Encoder, EncoderConfig};
+
+    fn noisy(n: u64) -> impl Iterator<Item = f64> {
+        (0..n).map(|i| 20.0 + (i as f64 * 0.37).sin() * 3.0 + (i * 7919 % 1000) as f64 * 1e-6)
+    }
+
+    #[test]
+    fn test_error_stays_within_bound() {
+        let precisions = [
+            Precision::AbsoluteError(0.01),
+            Precision::AbsoluteError(1e-9),
+            Precision::AbsoluteError(1e6),
+            Precision::SignificantDigits(1),
+            Precision::SignificantDigits(4),
+            Precision::SignificantDigits(17),
+        ];
+        let values = noisy(500).chain([-1234.5678, 9.999999, 1e300, -3e-300, 1000.0]);
+        for value in values {
+            for precision in precisions {
+                let stored = precision.apply(value);
+                assert!(
+                    (stored - value).abs() <= precision.bound(value),
+                    "{precision:?} {value} {stored}"
+                );
+                assert_eq!(precision.apply(stored), stored);
+            }
+        }
+        let digits = Precision::SignificantDigits(4);
+        assert!((digits.apply(21.4837) - 21.4837).abs() <= 0.005);
+        for special in [0.0, -0.0, f64::INFINITY, f64::MIN_POSITIVE / 2.0] {
+            assert_eq!(digits.apply(special).to_bits(), special.to_bits());
+        }
+        assert!(digits.apply(f64::NAN).is_nan());
+        assert_eq!(Precision::AbsoluteError(-1.0).apply(1.1), 1.1);
+    }
+
+    #[test]
+    fn test_quantized_values_stay_within_error() {
+        let specs = [
+            QuantizeSpec::AbsError(0.01),
+            QuantizeSpec::AbsError(1e-12),
+            QuantizeSpec::AbsError(1e300),
+            QuantizeSpec::RelError(0.001),
+            QuantizeSpec::RelError(1e-18),
+            QuantizeSpec::RelError(2.0),
+        ];
+        let values = noisy(500).chain([-1234.5678, 9.999999, 1e300, -3e-300, f64::MAX]);
+        for value in values {
+            for spec in specs {
+                let stored = spec.apply(value);
+                assert!(
+                    (stored - value).abs() <= spec.max_error(value),
+                    "{spec:?} {value} {stored}"
+                );
+                assert_eq!(spec.apply(stored), stored);
+            }
+        }
+        // Nearby readings land on the same grid point.
+        let grid = QuantizeSpec::AbsError(0.05);
+        assert_eq!(grid.apply(21.49), grid.apply(21.52));
+        for special in [0.0, -0.0, f64::NEG_INFINITY, f64::MIN_POSITIVE / 2.0] {
+            assert_eq!(grid.apply(special).to_bits(), special.to_bits());
+        }
+        assert!(grid.apply(f64::NAN).is_nan());
+        assert_eq!(QuantizeSpec::AbsError(0.0).apply(1.1), 1.1);
+        assert_eq!(QuantizeSpec::RelError(f64::NAN).apply(1.1), 1.1);
+    }
+
+    #[test]
+    fn test_erasure_shrinks_noisy_blocks() {
+        let encode = |precision| {
+            let mut encoder = Encoder::with_config(EncoderConfig {
+                precision,
+                ..Default::default()
+            });
+            for (t, value) in noisy(1000).enumerate() {
+                encoder
+                    .encode(DataPoint::new(t as u64 * 60, value))
+                    .unwrap();
+            }
+            encoder.finish().unwrap();
+            encoder.into_compressed()
+        };
+        let lossless = encode(None);
+        let lossy = encode(Some(Precision::SignificantDigits(3)));
+        assert!(lossy.total_bits() * 2 < lossless.total_bits());
+        let decoded = crate::Decoder::decode(&lossy).unwrap();
+        for (dp, value) in decoded.iter().zip(noisy(1000)) {
+            assert!((dp.value - value).abs() <= 0.05);
+        }
+    }
+}
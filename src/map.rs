@@ -0,0 +1,411 @@
+//! A collection of series keyed by [`SeriesKey`].
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use crate::admission::AdmissionControl;
+use crate::encoder::DataPoint;
+use crate::series::{AppendError, SeriesConfig, SeriesKey, SeriesQuery, TimeSeries};
+use crate::store::BlockStore;
+
+/// Outcome of [`SeriesMap::flush_all`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlushReport<K> {
+    /// Series whose blocks were all sealed and persisted.
+    pub flushed: Vec<K>,
+    /// Series that still hold unpersisted data, either because the deadline
+    /// passed before they were reached or because their open block could not
+    /// be sealed.
+    pub unfinished: Vec<K>,
+    /// Number of blocks handed to the store.
+    pub blocks_written: usize,
+}
+
+/// Many independent series, each created on first append with a shared
+/// [`SeriesConfig`].
+pub struct SeriesMap<K: SeriesKey> {
+    config: SeriesConfig,
+    series: HashMap<K, TimeSeries>,
+    admission: Option<Box<dyn AdmissionControl<K> + Send>>,
+}
+
+impl<K: SeriesKey> SeriesMap<K> {
+    /// Creates an empty map whose series use `config`.
+    pub fn new(config: SeriesConfig) -> Self {
+        Self {
+            config,
+            series: HashMap::new(),
+            admission: None,
+        }
+    }
+
+    /// Appends a data point to the series identified by `key`, creating the
+    /// series if needed. Fails with [`AppendError::Throttled`] if the
+    /// admission control hook refuses the point.
+    pub fn append(&mut self, key: K, dp: DataPoint) -> Result<(), AppendError> {
+        if let Some(admission) = &mut self.admission {
+            admission
+                .admit(&key, Instant::now())
+                .map_err(AppendError::Throttled)?;
+        }
+        self.get_or_insert(key).append(dp)
+    }
+
+    /// Like [`append`](SeriesMap::append), but clones `key` only if the
+    /// series has to be created.
+    pub(crate) fn append_ref(&mut self, key: &K, dp: DataPoint) -> Result<(), AppendError> {
+        if let Some(admission) = &mut self.admission {
+            admission
+                .admit(key, Instant::now())
+                .map_err(AppendError::Throttled)?;
+        }
+        match self.series.get_mut(key) {
+            Some(series) => series.append(dp),
+            None => self.get_or_insert(key.clone()).append(dp),
+        }
+    }
+
+    /// Installs a hook that every [`append`](SeriesMap::append) must pass,
+    /// e.g. a [`RateLimiter`](crate::RateLimiter), replacing any previous
+    /// one. Series accessed directly, such as through
+    /// [`get_or_insert`](SeriesMap::get_or_insert), bypass it.
+    pub fn set_admission(&mut self, admission: impl AdmissionControl<K> + Send + 'static) {
+        self.admission = Some(Box::new(admission));
+    }
+
+    /// Removes the admission control hook, returning it.
+    pub fn take_admission(&mut self) -> Option<Box<dyn AdmissionControl<K> + Send>> {
+        self.admission.take()
+    }
+
+    /// Returns the series for `key`, creating an empty one if needed.
+    pub fn get_or_insert(&mut self, key: K) -> &mut TimeSeries {
+        self.series
+            .entry(key)
+            .or_insert_with(|| TimeSeries::new(self.config.clone()))
+    }
+
+    /// Returns the series for `key`.
+    pub fn get(&self, key: &K) -> Option<&TimeSeries> {
+        self.series.get(key)
+    }
+
+    /// Returns an iterator over the points of series `key` with timestamps
+    /// in `start..=end`, as [`TimeSeries::query`]. Yields nothing if the
+    /// series does not exist.
+    pub fn query(&self, key: &K, start: u64, end: u64) -> SeriesQuery<'_> {
+        match self.series.get(key) {
+            Some(series) => series.query(start, end),
+            None => SeriesQuery::empty(),
+        }
+    }
+
+    /// Returns the series for `key` mutably, e.g. to seal it.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut TimeSeries> {
+        self.series.get_mut(key)
+    }
+
+    /// Inserts a series under `key`, returning the series it replaces.
+    pub fn insert(&mut self, key: K, series: TimeSeries) -> Option<TimeSeries> {
+        self.series.insert(key, series)
+    }
+
+    /// Removes and returns the series for `key`.
+    pub fn remove(&mut self, key: &K) -> Option<TimeSeries> {
+        self.series.remove(key)
+    }
+
+    /// Removes and yields every series, leaving the map empty.
+    pub fn drain(&mut self) -> impl Iterator<Item = (K, TimeSeries)> + '_ {
+        self.series.drain()
+    }
+
+    /// Returns the configuration new series are created with.
+    pub fn config(&self) -> &SeriesConfig {
+        &self.config
+    }
+
+    /// Iterates over all series in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &TimeSeries)> {
+        self.series.iter()
+    }
+
+    /// Iterates over all keys in arbitrary order.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.series.keys()
+    }
+
+    /// Returns the number of series.
+    pub fn len(&self) -> usize {
+        self.series.len()
+    }
+
+    /// Returns `true` if the map holds no series.
+    pub fn is_empty(&self) -> bool {
+        self.series.is_empty()
+    }
+
+    /// Finishes every open block and moves all sealed blocks into `store`,
+    /// for a clean shutdown.
+    ///
+    /// Series are processed one at a time until `deadline`; series not
+    /// reached in time keep their data in memory and are listed in
+    /// [`FlushReport::unfinished`], as are series whose open block could not
+    /// be sealed. The store is synced once at the end, and blocks are
+    /// removed from memory only after the sync succeeded. A store error
+    /// aborts the flush, leaving every series in memory.
+    pub fn flush_all<S: BlockStore + ?Sized>(
+        &mut self,
+        deadline: Instant,
+        store: &mut S,
+    ) -> Result<FlushReport<K>, S::Error> {
+        let mut report = FlushReport {
+            flushed: Vec::new(),
+            unfinished: Vec::new(),
+            blocks_written: 0,
+        };
+        // Blocks handed to the store per flushed series, in `report.flushed`.
+        let mut written = Vec::new();
+        for (key, series) in &mut self.series {
+            if Instant::now() >= deadline || series.seal().is_err() {
+                report.unfinished.push(key.clone());
+                continue;
+            }
+            let key_bytes = key.to_key_bytes();
+            for (i, block) in series.blocks().iter().enumerate() {
+                let (start, end) = series.block_time_range(i).unwrap();
+                store.put(&key_bytes, start, end, block)?;
+            }
+            report.blocks_written += series.blocks().len();
+            written.push(series.blocks().len());
+            report.flushed.push(key.clone());
+        }
+        store.sync()?;
+        for (key, &blocks) in report.flushed.iter().zip(&written) {
+            let series = self
+                .series
+                .get_mut(key)
+                .expect("flushed series are in the map");
+            for _ in 0..blocks {
+                series.remove_block(0);
+            }
+        }
+        Ok(report)
+    }
+}
+
+impl<K: SeriesKey + std::fmt::Debug> std::fmt::Debug for SeriesMap<K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SeriesMap")
+            .field("config", &self.config)
+            .field("series", &self.series)
+            .field("admission", &self.admission.is_some())
+            .finish()
+    }
+}
+
+impl<K: SeriesKey> Default for SeriesMap<K> {
+    fn default() -> Self {
+        Self::new(SeriesConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admission::Rejection;
+    use crate::encoder::{CompressedBlock, EncoderConfig};
+    use crate::segment::{Segment, SegmentWriter};
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct MemoryStore {
+        blocks: Vec<(Vec<u8>, u64, u64, CompressedBlock)>,
+        synced: bool,
+        fail: bool,
+        fail_sync: bool,
+    }
+
+    impl BlockStore for MemoryStore {
+        type Error = &'static str;
+
+        fn put(
+            &mut self,
+            key: &[u8],
+            start: u64,
+            end: u64,
+            block: &CompressedBlock,
+        ) -> Result<(), Self::Error> {
+            if self.fail {
+                return Err("store unavailable");
+            }
+            self.blocks.push((key.to_vec(), start, end, block.clone()));
+            Ok(())
+        }
+
+        fn sync(&mut self) -> Result<(), Self::Error> {
+            if self.fail_sync {
+                return Err("sync failed");
+            }
+            self.synced = true;
+            Ok(())
+        }
+    }
+
+    fn filled_map() -> SeriesMap<String> {
+        let mut map = SeriesMap::default();
+        for i in 0..10 {
+            map.append("cpu".into(), DataPoint::new(1000 + i * 10, i as f64))
+                .unwrap();
+            map.append("mem".into(), DataPoint::new(1000 + i * 10, 0.5))
+                .unwrap();
+        }
+        map
+    }
+
+    #[test]
+    fn test_append_creates_series() {
+        let map = filled_map();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&"cpu".to_string()).unwrap().len(), 10);
+        assert!(map.get(&"disk".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_query_by_key() {
+        let mut map = filled_map();
+        map.get_mut(&"cpu".to_string()).unwrap().seal().unwrap();
+        map.append("cpu".into(), DataPoint::new(1100, 10.0))
+            .unwrap();
+
+        let values: Vec<_> = map
+            .query(&"cpu".to_string(), 1075, 1100)
+            .map(|dp| dp.unwrap().value)
+            .collect();
+        assert_eq!(values, [8.0, 9.0, 10.0]);
+        assert_eq!(map.query(&"disk".to_string(), 0, u64::MAX).count(), 0);
+    }
+
+    #[test]
+    fn test_admission_rejects_before_storing() {
+        struct OnlyCpu;
+
+        impl AdmissionControl<String> for OnlyCpu {
+            fn admit(&mut self, key: &String, _now: Instant) -> Result<(), Rejection> {
+                if key == "cpu" {
+                    return Ok(());
+                }
+                Err(Rejection::SeriesRate {
+                    retry_after: Duration::from_secs(1),
+                })
+            }
+        }
+
+        let mut map = filled_map();
+        map.set_admission(OnlyCpu);
+        map.append("cpu".into(), DataPoint::new(2000, 1.0)).unwrap();
+        let err = map
+            .append("disk".into(), DataPoint::new(2000, 1.0))
+            .unwrap_err();
+        assert!(matches!(err, AppendError::Throttled(_)));
+        assert!(map.get(&"disk".to_string()).is_none());
+
+        assert!(map.take_admission().is_some());
+        map.append("disk".into(), DataPoint::new(2000, 1.0)).unwrap();
+    }
+
+    #[test]
+    fn test_flush_all_persists_open_and_sealed_blocks() {
+        let mut map = filled_map();
+        map.get_mut(&"cpu".to_string()).unwrap().seal().unwrap();
+        map.append("cpu".into(), DataPoint::new(2000, 1.0)).unwrap();
+
+        let mut store = MemoryStore::default();
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let mut report = map.flush_all(deadline, &mut store).unwrap();
+        report.flushed.sort();
+        assert_eq!(report.flushed, ["cpu", "mem"]);
+        assert!(report.unfinished.is_empty());
+        assert_eq!(report.blocks_written, 3);
+        assert!(store.synced);
+
+        let cpu: Vec<_> = store.blocks.iter().filter(|b| b.0 == b"cpu").collect();
+        assert_eq!((cpu[0].1, cpu[0].2), (1000, 1090));
+        assert_eq!((cpu[1].1, cpu[1].2), (2000, 2000));
+        assert!(map.iter().all(|(_, s)| s.is_empty()));
+    }
+
+    #[test]
+    fn test_flush_all_reports_series_past_deadline() {
+        let mut map = filled_map();
+        let mut store = MemoryStore::default();
+        let report = map.flush_all(Instant::now(), &mut store).unwrap();
+        assert!(report.flushed.is_empty());
+        assert_eq!(report.unfinished.len(), 2);
+        assert!(store.blocks.is_empty());
+        assert_eq!(map.get(&"cpu".to_string()).unwrap().len(), 10);
+    }
+
+    #[test]
+    fn test_flush_all_reports_series_that_cannot_seal() {
+        let mut map = SeriesMap::new(SeriesConfig {
+            encoder: EncoderConfig {
+                max_bytes: Some(16),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        map.append("full".to_string(), DataPoint::new(0, 1.0))
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let report = map
+            .flush_all(deadline, &mut MemoryStore::default())
+            .unwrap();
+        assert_eq!(report.unfinished, ["full"]);
+    }
+
+    #[test]
+    fn test_flush_all_keeps_data_on_store_error() {
+        let mut map = filled_map();
+        let mut store = MemoryStore {
+            fail: true,
+            ..Default::default()
+        };
+        let deadline = Instant::now() + Duration::from_secs(60);
+        assert_eq!(
+            map.flush_all(deadline, &mut store),
+            Err("store unavailable")
+        );
+        assert!(map.iter().all(|(_, s)| s.len() == 10));
+
+        // Series already put are kept too until the store is synced.
+        let mut store = MemoryStore {
+            fail_sync: true,
+            ..Default::default()
+        };
+        assert_eq!(map.flush_all(deadline, &mut store), Err("sync failed"));
+        assert_eq!(store.blocks.len(), 2);
+        assert!(map.iter().all(|(_, s)| s.len() == 10));
+    }
+
+    #[test]
+    fn test_flush_all_writes_a_segment() {
+        let dir = crate::segment::tests::temp_dir("flush-all");
+        let path = dir.join("shutdown.seg");
+        let mut map = filled_map();
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let mut writer = SegmentWriter::new(&path);
+        let report = map.flush_all(deadline, &mut writer).unwrap();
+        assert_eq!(report.blocks_written, 2);
+        drop(writer);
+
+        let segment = Segment::open(&path).unwrap();
+        let mut keys: Vec<_> = segment.entries().iter().map(|e| e.key.clone()).collect();
+        keys.sort();
+        assert_eq!(keys, [b"cpu".to_vec(), b"mem".to_vec()]);
+        let block = segment.read_block(&segment.entries()[0]).unwrap();
+        assert_eq!(block.count(), 10);
+        assert!(map.iter().all(|(_, s)| s.is_empty()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,238 @@
+//! K-way merge of several sorted point streams into one.
+//!
+//! Multiple agents scraping the same target produce one series per agent
+//! whose timestamps differ by a few milliseconds of clock skew. Merged
+//! naively, every sample appears once per agent. [`KWayMerge`] groups
+//! points from different sources that lie within a [`SkewTolerance`] of
+//! each other and emits a single [`Winner`] per group.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::decoder::DecodeError;
+use crate::encoder::DataPoint;
+
+/// Which point of a group of near-simultaneous points is emitted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Winner {
+    /// The point of the source listed first.
+    #[default]
+    Priority,
+    /// The point with the smallest timestamp.
+    Earliest,
+    /// The point with the largest timestamp.
+    Latest,
+}
+
+/// How far apart points from different sources may be and still count as
+/// the same instant.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SkewTolerance {
+    /// Maximum distance from the earliest point of a group. Zero only merges
+    /// exact duplicates.
+    pub window: u64,
+    /// Point emitted for each group.
+    pub winner: Winner,
+}
+
+/// Iterator merging sorted sources into one non-decreasing stream.
+///
+/// A group starts at the earliest pending point and takes in points of
+/// other sources up to `window` later, at most one per source: a second
+/// point of a source already in the group starts the next group. The
+/// window should therefore be smaller than the sampling interval of the
+/// sources. Errors from a source are yielded as they are read and end the
+/// merge. The merged stream is complete as far as
+/// [`merged_watermark`](crate::merged_watermark) of the sources'
+/// watermarks.
+///
+/// # Example
+/// ```
+/// use gorilla::{DataPoint, KWayMerge, SkewTolerance, Winner};
+///
+/// let a = [DataPoint::new(1000, 1.0), DataPoint::new(2000, 2.0)];
+/// let b = [DataPoint::new(1003, 1.5), DataPoint::new(2001, 2.5)];
+/// let tolerance = SkewTolerance { window: 10, winner: Winner::Priority };
+/// let merged: Vec<_> = KWayMerge::new([a, b].map(|s| s.into_iter().map(Ok)), tolerance)
+///     .collect::<Result<_, _>>()
+///     .unwrap();
+/// assert_eq!(merged, a);
+/// ```
+#[derive(Debug)]
+pub struct KWayMerge<I> {
+    sources: Vec<I>,
+    /// Next point of every source that has one.
+    heads: Vec<Option<DataPoint>>,
+    /// Timestamp and source index of every head, earliest first.
+    heap: BinaryHeap<Reverse<(u64, usize)>>,
+    tolerance: SkewTolerance,
+    /// Sources in the group being built, reused across groups.
+    group: Vec<usize>,
+    error: Option<DecodeError>,
+    done: bool,
+}
+
+impl<I> KWayMerge<I>
+where
+    I: Iterator<Item = Result<DataPoint, DecodeError>>,
+{
+    /// Creates a merge of `sources`, each sorted by timestamp. Earlier
+    /// sources take priority under [`Winner::Priority`].
+    pub fn new(sources: impl IntoIterator<Item = I>, tolerance: SkewTolerance) -> Self {
+        let sources: Vec<I> = sources.into_iter().collect();
+        let mut merge = Self {
+            heads: vec![None; sources.len()],
+            heap: BinaryHeap::with_capacity(sources.len()),
+            sources,
+            tolerance,
+            group: Vec::new(),
+            error: None,
+            done: false,
+        };
+        for source in 0..merge.sources.len() {
+            merge.advance(source);
+        }
+        merge
+    }
+
+    /// Returns the tolerance groups are formed with.
+    pub fn tolerance(&self) -> SkewTolerance {
+        self.tolerance
+    }
+
+    /// Reads the next point of `source` into its head.
+    fn advance(&mut self, source: usize) {
+        if self.error.is_some() {
+            return;
+        }
+        match self.sources[source].next() {
+            Some(Ok(dp)) => {
+                self.heads[source] = Some(dp);
+                self.heap.push(Reverse((dp.timestamp, source)));
+            }
+            Some(Err(err)) => self.error = Some(err),
+            None => {}
+        }
+    }
+
+    /// Removes and returns the head of `source`, reading its next point.
+    fn take(&mut self, source: usize) -> DataPoint {
+        let dp = self.heads[source].take().expect("heap entries have a head");
+        self.advance(source);
+        dp
+    }
+}
+
+impl<I> Iterator for KWayMerge<I>
+where
+    I: Iterator<Item = Result<DataPoint, DecodeError>>,
+{
+    type Item = Result<DataPoint, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if let Some(err) = self.error.take() {
+            self.done = true;
+            return Some(Err(err));
+        }
+        let Some(Reverse((_, first))) = self.heap.pop() else {
+            self.done = true;
+            return None;
+        };
+        let mut winner = (self.take(first), first);
+        let limit = winner.0.timestamp.saturating_add(self.tolerance.window);
+        self.group.clear();
+        self.group.push(first);
+        while let Some(&Reverse((timestamp, source))) = self.heap.peek() {
+            if timestamp > limit || self.group.contains(&source) {
+                break;
+            }
+            self.heap.pop();
+            let dp = self.take(source);
+            self.group.push(source);
+            let wins = match self.tolerance.winner {
+                Winner::Priority => source < winner.1,
+                Winner::Earliest => false,
+                Winner::Latest => dp.timestamp > winner.0.timestamp,
+            };
+            if wins {
+                winner = (dp, source);
+            }
+        }
+        Some(Ok(winner.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(points: &[(u64, f64)]) -> impl Iterator<Item = Result<DataPoint, DecodeError>> {
+        points
+            .iter()
+            .map(|&(t, v)| Ok(DataPoint::new(t, v)))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn merged(sources: &[&[(u64, f64)]], window: u64, winner: Winner) -> Vec<(u64, f64)> {
+        KWayMerge::new(
+            sources.iter().map(|points| source(points)),
+            SkewTolerance { window, winner },
+        )
+        .map(|dp| dp.map(|dp| (dp.timestamp, dp.value)))
+        .collect::<Result<_, _>>()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_zero_window_merges_exact_duplicates_only() {
+        let a: &[_] = &[(10, 1.0), (20, 2.0), (30, 3.0)];
+        let b: &[_] = &[(15, 9.0), (20, 8.0), (31, 7.0)];
+        assert_eq!(
+            merged(&[a, b], 0, Winner::Priority),
+            [(10, 1.0), (15, 9.0), (20, 2.0), (30, 3.0), (31, 7.0)]
+        );
+        assert_eq!(
+            merged(&[b, a], 0, Winner::Priority),
+            [(10, 1.0), (15, 9.0), (20, 8.0), (30, 3.0), (31, 7.0)]
+        );
+    }
+
+    #[test]
+    fn test_skewed_sources_collapse_by_winner() {
+        let a: &[_] = &[(1000, 1.0), (2004, 2.0), (3000, 3.0)];
+        let b: &[_] = &[(1003, 1.5), (2000, 2.5), (3050, 3.5)];
+        let c: &[_] = &[(1001, 1.2)];
+        assert_eq!(
+            merged(&[a, b, c], 10, Winner::Priority),
+            [(1000, 1.0), (2004, 2.0), (3000, 3.0), (3050, 3.5)]
+        );
+        assert_eq!(
+            merged(&[a, b, c], 10, Winner::Earliest),
+            [(1000, 1.0), (2000, 2.5), (3000, 3.0), (3050, 3.5)]
+        );
+        assert_eq!(
+            merged(&[a, b, c], 10, Winner::Latest),
+            [(1003, 1.5), (2004, 2.0), (3000, 3.0), (3050, 3.5)]
+        );
+        // A source never contributes two points to one group.
+        let dense: &[_] = &[(0, 0.0), (5, 1.0), (10, 2.0)];
+        assert_eq!(merged(&[dense], 10, Winner::Latest), dense);
+    }
+
+    #[test]
+    fn test_source_error_ends_merge() {
+        let failing = vec![
+            Ok(DataPoint::new(1, 1.0)),
+            Err(DecodeError::UnexpectedEnd),
+            Ok(DataPoint::new(3, 3.0)),
+        ];
+        let mut merge = KWayMerge::new([failing.into_iter()], SkewTolerance::default());
+        assert_eq!(merge.next(), Some(Ok(DataPoint::new(1, 1.0))));
+        assert_eq!(merge.next(), Some(Err(DecodeError::UnexpectedEnd)));
+        assert_eq!(merge.next(), None);
+    }
+}
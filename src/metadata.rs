@@ -0,0 +1,193 @@
+//! Small key/value metadata carried by blocks, e.g. for data lineage.
+
+use std::collections::BTreeMap;
+
+use crate::decoder::DecodeError;
+
+/// Largest serialized size of a [`BlockMetadata`], in bytes.
+pub const MAX_METADATA_LEN: usize = 1024;
+
+/// Error returned when an insert would grow a [`BlockMetadata`] past
+/// [`MAX_METADATA_LEN`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetadataTooLarge {
+    /// Serialized size the insert would have produced.
+    pub len: usize,
+}
+
+impl std::fmt::Display for MetadataTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "block metadata of {} bytes exceeds the limit of {MAX_METADATA_LEN} bytes",
+            self.len
+        )
+    }
+}
+
+impl std::error::Error for MetadataTooLarge {}
+
+/// Provenance attached to a block, such as its source, ingest host or
+/// pipeline version. Stored after the block's payload by
+/// [`CompressedBlock::to_bytes`](crate::CompressedBlock::to_bytes) and
+/// limited to [`MAX_METADATA_LEN`] serialized bytes.
+///
+/// # Example
+/// ```
+/// use gorilla::{BlockMetadata, CompressedBlock, DataPoint, Encoder, EncoderConfig};
+///
+/// let mut metadata = BlockMetadata::new();
+/// metadata.insert("source", "sensor-7").unwrap();
+/// metadata.insert("pipeline", "v2.3.1").unwrap();
+///
+/// let mut encoder = Encoder::with_config(EncoderConfig { metadata, ..Default::default() });
+/// encoder.encode(DataPoint::new(1609459200, 21.5)).unwrap();
+/// encoder.finish().unwrap();
+///
+/// let block = CompressedBlock::from_bytes(&encoder.into_compressed().to_bytes()).unwrap();
+/// assert_eq!(block.metadata.get("source"), Some("sensor-7"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockMetadata {
+    entries: BTreeMap<String, String>,
+}
+
+impl BlockMetadata {
+    /// Creates empty metadata.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets `key` to `value`, returning the previous value. Fails, leaving
+    /// the metadata unchanged, if the result would exceed
+    /// [`MAX_METADATA_LEN`].
+    pub fn insert(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<Option<String>, MetadataTooLarge> {
+        let (key, value) = (key.into(), value.into());
+        let replaced = self.entries.get(&key).map_or(0, |v| entry_len(&key, v));
+        let len = self.encoded_len() - replaced + entry_len(&key, &value);
+        if len > MAX_METADATA_LEN {
+            return Err(MetadataTooLarge { len });
+        }
+        Ok(self.entries.insert(key, value))
+    }
+
+    /// Returns the value of `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(String::as_str)
+    }
+
+    /// Removes `key`, returning its value.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.entries.remove(key)
+    }
+
+    /// Returns the entries in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the serialized size in bytes.
+    pub fn encoded_len(&self) -> usize {
+        2 + self
+            .entries
+            .iter()
+            .map(|(k, v)| entry_len(k, v))
+            .sum::<usize>()
+    }
+
+    /// Serializes the entries: a 2-byte LE entry count, then per entry a
+    /// 2-byte LE key length, the UTF-8 key, a 2-byte LE value length and
+    /// the UTF-8 value.
+    pub(crate) fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.entries.len() as u16).to_le_bytes());
+        for (key, value) in &self.entries {
+            for s in [key, value] {
+                out.extend_from_slice(&(s.len() as u16).to_le_bytes());
+                out.extend_from_slice(s.as_bytes());
+            }
+        }
+    }
+
+    /// Parses metadata from the front of `bytes`, returning it with the
+    /// number of bytes consumed.
+    pub(crate) fn read_from(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let mut pos = 0;
+        let mut take = |n: usize| -> Result<&[u8], DecodeError> {
+            let slice = bytes.get(pos..pos + n).ok_or(DecodeError::UnexpectedEnd)?;
+            pos += n;
+            Ok(slice)
+        };
+        let count = u16::from_le_bytes(take(2)?.try_into().unwrap());
+        let mut metadata = BlockMetadata::new();
+        for _ in 0..count {
+            let mut string = || -> Result<String, DecodeError> {
+                let len = u16::from_le_bytes(take(2)?.try_into().unwrap());
+                std::str::from_utf8(take(len as usize)?)
+                    .map(str::to_string)
+                    .map_err(|_| DecodeError::MalformedHeader("metadata is not UTF-8"))
+            };
+            let (key, value) = (string()?, string()?);
+            metadata
+                .insert(key, value)
+                .map_err(|_| DecodeError::MalformedHeader("metadata exceeds size limit"))?;
+        }
+        Ok((metadata, pos))
+    }
+}
+
+fn entry_len(key: &str, value: &str) -> usize {
+    4 + key.len() + value.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_enforces_limit() {
+        let mut metadata = BlockMetadata::new();
+        metadata.insert("host", "a").unwrap();
+        let big = "x".repeat(MAX_METADATA_LEN);
+        assert_eq!(
+            metadata.insert("note", big.as_str()),
+            Err(MetadataTooLarge {
+                len: 2 + (4 + 4 + 1) + (4 + 4 + MAX_METADATA_LEN)
+            })
+        );
+        assert_eq!(metadata.len(), 1);
+        // Replacing a value only counts the new one.
+        let fits = "y".repeat(MAX_METADATA_LEN - 2 - 4 - 4);
+        assert_eq!(metadata.insert("host", fits.as_str()), Ok(Some("a".into())));
+        assert_eq!(metadata.encoded_len(), MAX_METADATA_LEN);
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let mut metadata = BlockMetadata::new();
+        metadata.insert("source", "sensor-7").unwrap();
+        metadata.insert("host", "ingest-02").unwrap();
+        let mut bytes = Vec::new();
+        metadata.write_to(&mut bytes);
+        assert_eq!(bytes.len(), metadata.encoded_len());
+        let (parsed, used) = BlockMetadata::read_from(&bytes).unwrap();
+        assert_eq!((parsed, used), (metadata, bytes.len()));
+        assert_eq!(
+            BlockMetadata::read_from(&bytes[..bytes.len() - 1]).unwrap_err(),
+            DecodeError::UnexpectedEnd
+        );
+    }
+}
@@ -0,0 +1,662 @@
+//! Cold storage of blocks in Parquet files.
+//!
+//! A file written by [`ParquetWriter`] holds one row per block in a single
+//! row group, with the columns
+//!
+//! | column  | physical type | contents                              |
+//! |---------|---------------|---------------------------------------|
+//! | `key`   | `BYTE_ARRAY`  | encoded key of the block's series     |
+//! | `start` | `INT64` (`UINT_64`) | smallest timestamp in the block       |
+//! | `end`   | `INT64` (`UINT_64`) | largest timestamp in the block        |
+//! | `block` | `BYTE_ARRAY`  | [`CompressedBlock::to_bytes`] output  |
+//!
+//! All columns are required, PLAIN-encoded and uncompressed, so any Parquet
+//! reader can load the index columns and hand the `block` column back to
+//! this crate. The format is produced and parsed here directly, without a
+//! Parquet library; [`read`] accepts files of this shape from any writer
+//! but rejects compression, dictionary pages and nullable columns.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::encoder::CompressedBlock;
+
+const MAGIC: &[u8; 4] = b"PAR1";
+const TYPE_INT64: i64 = 2;
+const TYPE_BYTE_ARRAY: i64 = 6;
+const CONVERTED_UINT_64: i64 = 12;
+const REQUIRED: i64 = 0;
+const ENCODING_PLAIN: i64 = 0;
+const ENCODING_RLE: i64 = 3;
+const CODEC_UNCOMPRESSED: i64 = 0;
+const PAGE_DATA: i64 = 0;
+
+/// Columns in file order, with their physical types.
+const COLUMNS: [(&str, i64); 4] = [
+    ("key", TYPE_BYTE_ARRAY),
+    ("start", TYPE_INT64),
+    ("end", TYPE_INT64),
+    ("block", TYPE_BYTE_ARRAY),
+];
+
+/// One block read back from a Parquet file.
+#[derive(Debug, Clone)]
+pub struct ParquetRecord {
+    /// Encoded key of the series the block belongs to.
+    pub key: Vec<u8>,
+    /// Smallest timestamp in the block.
+    pub start: u64,
+    /// Largest timestamp in the block.
+    pub end: u64,
+    /// The block.
+    pub block: CompressedBlock,
+}
+
+/// Builds a Parquet file with one row per block. Each column is kept in
+/// memory as a single PLAIN-encoded data page; [`finish`](ParquetWriter::finish)
+/// lays the pages out as one row group, appends the Thrift footer and
+/// renames the synced file into place.
+///
+/// # Example
+/// ```
+/// use gorilla::parquet::{self, ParquetWriter};
+/// use gorilla::{DataPoint, Encoder};
+///
+/// let mut encoder = Encoder::new();
+/// encoder.encode(DataPoint::new(1609459200, 1.5)).unwrap();
+/// encoder.finish().unwrap();
+/// let block = encoder.into_compressed();
+///
+/// let path = std::env::temp_dir().join(format!("gorilla-doc-{}.parquet", std::process::id()));
+/// let mut writer = ParquetWriter::new(&path);
+/// writer.add(b"cpu", 1609459200, 1609459200, &block);
+/// writer.finish().unwrap();
+///
+/// let records = parquet::read(&path).unwrap();
+/// assert_eq!(records[0].key, b"cpu");
+/// assert_eq!(records[0].block.bytes(), block.bytes());
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ParquetWriter {
+    path: PathBuf,
+    keys: Vec<u8>,
+    starts: Vec<u8>,
+    ends: Vec<u8>,
+    blocks: Vec<u8>,
+    rows: usize,
+}
+
+impl ParquetWriter {
+    /// Starts a new file that will be written to `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            keys: Vec::new(),
+            starts: Vec::new(),
+            ends: Vec::new(),
+            blocks: Vec::new(),
+            rows: 0,
+        }
+    }
+
+    /// Adds a block belonging to the series with encoded key `key`, covering
+    /// timestamps `start..=end`.
+    pub fn add(&mut self, key: &[u8], start: u64, end: u64, block: &CompressedBlock) {
+        put_bytes(&mut self.keys, key);
+        self.starts.extend_from_slice(&start.to_le_bytes());
+        self.ends.extend_from_slice(&end.to_le_bytes());
+        put_bytes(&mut self.blocks, &block.to_bytes());
+        self.rows += 1;
+    }
+
+    /// Writes the file to disk, syncing it before it becomes visible under
+    /// its final name. Fails if a column exceeds Parquet's 2 GiB page limit.
+    pub fn finish(self) -> io::Result<()> {
+        let rows = self.rows as i64;
+        let mut out = MAGIC.to_vec();
+        let mut chunks = Vec::new();
+        let pages = [&self.keys, &self.starts, &self.ends, &self.blocks];
+        for ((name, ty), page) in COLUMNS.into_iter().zip(pages) {
+            let size = i32::try_from(page.len()).map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, "parquet page exceeds 2 GiB")
+            })?;
+            let offset = out.len() as i64;
+            let header = Thrift::Struct(vec![
+                (1, Thrift::I32(PAGE_DATA)),
+                (2, Thrift::I32(size.into())),
+                (3, Thrift::I32(size.into())),
+                (
+                    5,
+                    Thrift::Struct(vec![
+                        (1, Thrift::I32(rows)),
+                        (2, Thrift::I32(ENCODING_PLAIN)),
+                        (3, Thrift::I32(ENCODING_RLE)),
+                        (4, Thrift::I32(ENCODING_RLE)),
+                    ]),
+                ),
+            ]);
+            header.write(&mut out);
+            out.extend_from_slice(page);
+            let len = out.len() as i64 - offset;
+            chunks.push(Thrift::Struct(vec![
+                (2, Thrift::I64(offset)),
+                (
+                    3,
+                    Thrift::Struct(vec![
+                        (1, Thrift::I32(ty)),
+                        (2, Thrift::List(vec![Thrift::I32(ENCODING_PLAIN)])),
+                        (3, Thrift::List(vec![Thrift::Binary(name.into())])),
+                        (4, Thrift::I32(CODEC_UNCOMPRESSED)),
+                        (5, Thrift::I64(rows)),
+                        (6, Thrift::I64(len)),
+                        (7, Thrift::I64(len)),
+                        (9, Thrift::I64(offset)),
+                    ]),
+                ),
+            ]));
+        }
+
+        let mut schema = vec![Thrift::Struct(vec![
+            (4, Thrift::Binary(b"schema".to_vec())),
+            (5, Thrift::I32(COLUMNS.len() as i64)),
+        ])];
+        for (name, ty) in COLUMNS {
+            let mut element = vec![
+                (1, Thrift::I32(ty)),
+                (3, Thrift::I32(REQUIRED)),
+                (4, Thrift::Binary(name.into())),
+            ];
+            if ty == TYPE_INT64 {
+                element.push((6, Thrift::I32(CONVERTED_UINT_64)));
+            }
+            schema.push(Thrift::Struct(element));
+        }
+        let data_len = out.len() as i64 - MAGIC.len() as i64;
+        let footer = Thrift::Struct(vec![
+            (1, Thrift::I32(1)),
+            (2, Thrift::List(schema)),
+            (3, Thrift::I64(rows)),
+            (
+                4,
+                Thrift::List(vec![Thrift::Struct(vec![
+                    (1, Thrift::List(chunks)),
+                    (2, Thrift::I64(data_len)),
+                    (3, Thrift::I64(rows)),
+                ])]),
+            ),
+            (
+                6,
+                Thrift::Binary(concat!("gorilla version ", env!("CARGO_PKG_VERSION")).into()),
+            ),
+        ]);
+        let footer_start = out.len();
+        footer.write(&mut out);
+        let footer_len = (out.len() - footer_start) as u32;
+        out.extend_from_slice(&footer_len.to_le_bytes());
+        out.extend_from_slice(MAGIC);
+
+        let tmp = self.path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&out)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+/// Reads every block from a Parquet file with the columns described in the
+/// [module documentation](self), in file order.
+pub fn read(path: impl AsRef<Path>) -> io::Result<Vec<ParquetRecord>> {
+    parse(&fs::read(path)?)
+}
+
+/// Parses a Parquet file image.
+fn parse(data: &[u8]) -> io::Result<Vec<ParquetRecord>> {
+    let len = data.len();
+    if len < 12 || &data[..4] != MAGIC || &data[len - 4..] != MAGIC {
+        return Err(invalid("not a parquet file"));
+    }
+    let footer_len = u32::from_le_bytes(data[len - 8..len - 4].try_into().unwrap()) as usize;
+    let footer_start = (len - 8)
+        .checked_sub(footer_len)
+        .filter(|&start| start >= MAGIC.len())
+        .ok_or_else(|| invalid("truncated parquet footer"))?;
+    let meta = Reader::new(&data[footer_start..len - 8]).parse_struct(0)?;
+
+    let schema = meta
+        .field(2)
+        .and_then(Thrift::as_list)
+        .ok_or_else(missing)?;
+    for (name, ty) in COLUMNS {
+        let element = schema
+            .iter()
+            .skip(1)
+            .find(|e| e.field(4).and_then(Thrift::as_bytes) == Some(name.as_bytes()))
+            .ok_or_else(|| invalid(format!("parquet file has no `{name}` column")))?;
+        if element.field(1).and_then(Thrift::as_i64) != Some(ty) {
+            return Err(invalid(format!(
+                "parquet column `{name}` has the wrong type"
+            )));
+        }
+        if element.field(3).and_then(Thrift::as_i64) != Some(REQUIRED) {
+            return Err(invalid(format!("parquet column `{name}` is not required")));
+        }
+    }
+
+    let mut columns: [Vec<&[u8]>; 4] = Default::default();
+    let row_groups = meta
+        .field(4)
+        .and_then(Thrift::as_list)
+        .ok_or_else(missing)?;
+    for group in row_groups {
+        let chunks = group
+            .field(1)
+            .and_then(Thrift::as_list)
+            .ok_or_else(missing)?;
+        for chunk in chunks {
+            let meta = chunk
+                .field(3)
+                .ok_or_else(|| invalid("external parquet column chunks are unsupported"))?;
+            let path = meta
+                .field(3)
+                .and_then(Thrift::as_list)
+                .ok_or_else(missing)?;
+            let Some(column) = COLUMNS.iter().position(|&(name, _)| {
+                path.len() == 1 && path[0].as_bytes() == Some(name.as_bytes())
+            }) else {
+                continue;
+            };
+            if meta.field(4).and_then(Thrift::as_i64) != Some(CODEC_UNCOMPRESSED) {
+                return Err(invalid("compressed parquet columns are unsupported"));
+            }
+            if meta.field(11).is_some() {
+                return Err(invalid("parquet dictionary pages are unsupported"));
+            }
+            let num_values = meta.field(5).and_then(Thrift::as_i64).ok_or_else(missing)?;
+            let offset = meta.field(9).and_then(Thrift::as_i64).ok_or_else(missing)?;
+            let mut pos = usize::try_from(offset).map_err(|_| missing())?;
+            let mut remaining = num_values;
+            while remaining > 0 {
+                let mut reader = Reader::new(data.get(pos..).ok_or_else(truncated)?);
+                let header = reader.parse_struct(0)?;
+                pos += reader.pos;
+                if header.field(1).and_then(Thrift::as_i64) != Some(PAGE_DATA) {
+                    return Err(invalid("only parquet v1 data pages are supported"));
+                }
+                let size = header
+                    .field(3)
+                    .and_then(Thrift::as_i64)
+                    .ok_or_else(missing)?;
+                let page = header.field(5).ok_or_else(missing)?;
+                if page.field(2).and_then(Thrift::as_i64) != Some(ENCODING_PLAIN) {
+                    return Err(invalid("only PLAIN-encoded parquet pages are supported"));
+                }
+                let count = page.field(1).and_then(Thrift::as_i64).ok_or_else(missing)?;
+                let end = usize::try_from(size)
+                    .ok()
+                    .and_then(|size| pos.checked_add(size))
+                    .ok_or_else(missing)?;
+                let body = data.get(pos..end).ok_or_else(truncated)?;
+                plain_values(COLUMNS[column].1, count, body, &mut columns[column])?;
+                pos = end;
+                remaining -= count.max(1);
+            }
+        }
+    }
+
+    let [keys, starts, ends, blocks] = columns;
+    if [starts.len(), ends.len(), blocks.len()] != [keys.len(); 3] {
+        return Err(invalid("parquet columns have different lengths"));
+    }
+    keys.into_iter()
+        .zip(starts)
+        .zip(ends)
+        .zip(blocks)
+        .map(|(((key, start), end), block)| {
+            Ok(ParquetRecord {
+                key: key.to_vec(),
+                start: u64::from_le_bytes(start.try_into().unwrap()),
+                end: u64::from_le_bytes(end.try_into().unwrap()),
+                block: CompressedBlock::from_bytes(block)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            })
+        })
+        .collect()
+}
+
+/// Appends a PLAIN-encoded `BYTE_ARRAY` value.
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+/// Splits a PLAIN-encoded page of `count` values of type `ty` into the
+/// values' bytes.
+fn plain_values<'a>(
+    ty: i64,
+    count: i64,
+    mut body: &'a [u8],
+    out: &mut Vec<&'a [u8]>,
+) -> io::Result<()> {
+    for _ in 0..count {
+        let len = if ty == TYPE_INT64 {
+            8
+        } else {
+            let prefix = body.get(..4).ok_or_else(truncated)?;
+            body = &body[4..];
+            u32::from_le_bytes(prefix.try_into().unwrap()) as usize
+        };
+        out.push(body.get(..len).ok_or_else(truncated)?);
+        body = &body[len..];
+    }
+    Ok(())
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn truncated() -> io::Error {
+    invalid("truncated parquet file")
+}
+
+fn missing() -> io::Error {
+    invalid("malformed parquet metadata")
+}
+
+/// A value in Thrift's compact protocol, as used by Parquet's metadata.
+#[derive(Debug, Clone, PartialEq)]
+enum Thrift {
+    Bool(bool),
+    Byte(i64),
+    I16(i64),
+    I32(i64),
+    I64(i64),
+    Double(u64),
+    Binary(Vec<u8>),
+    List(Vec<Thrift>),
+    Struct(Vec<(i16, Thrift)>),
+}
+
+/// Nesting limit for parsed metadata.
+const MAX_DEPTH: usize = 32;
+
+impl Thrift {
+    fn type_id(&self) -> u8 {
+        match self {
+            Thrift::Bool(true) => 1,
+            Thrift::Bool(false) => 2,
+            Thrift::Byte(_) => 3,
+            Thrift::I16(_) => 4,
+            Thrift::I32(_) => 5,
+            Thrift::I64(_) => 6,
+            Thrift::Double(_) => 7,
+            Thrift::Binary(_) => 8,
+            Thrift::List(_) => 9,
+            Thrift::Struct(_) => 12,
+        }
+    }
+
+    fn field(&self, id: i16) -> Option<&Thrift> {
+        match self {
+            Thrift::Struct(fields) => fields.iter().find(|(f, _)| *f == id).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Thrift::Byte(v) | Thrift::I16(v) | Thrift::I32(v) | Thrift::I64(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Thrift::Binary(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    fn as_list(&self) -> Option<&[Thrift]> {
+        match self {
+            Thrift::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Serializes the value; booleans inside structs are written by the
+    /// field header instead.
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Thrift::Bool(b) => out.push(if *b { 1 } else { 2 }),
+            Thrift::Byte(v) => out.push(*v as u8),
+            Thrift::I16(v) | Thrift::I32(v) | Thrift::I64(v) => write_varint(out, zigzag(*v)),
+            Thrift::Double(bits) => out.extend_from_slice(&bits.to_le_bytes()),
+            Thrift::Binary(bytes) => {
+                write_varint(out, bytes.len() as u64);
+                out.extend_from_slice(bytes);
+            }
+            Thrift::List(items) => {
+                let ty = items.first().map_or(12, |item| match item {
+                    Thrift::Bool(_) => 1,
+                    item => item.type_id(),
+                });
+                if items.len() < 15 {
+                    out.push((items.len() as u8) << 4 | ty);
+                } else {
+                    out.push(0xf0 | ty);
+                    write_varint(out, items.len() as u64);
+                }
+                for item in items {
+                    item.write(out);
+                }
+            }
+            Thrift::Struct(fields) => {
+                let mut last = 0;
+                for (id, value) in fields {
+                    let delta = id - last;
+                    if (1..=15).contains(&delta) {
+                        out.push((delta as u8) << 4 | value.type_id());
+                    } else {
+                        out.push(value.type_id());
+                        write_varint(out, zigzag((*id).into()));
+                    }
+                    if !matches!(value, Thrift::Bool(_)) {
+                        value.write(out);
+                    }
+                    last = *id;
+                }
+                out.push(0);
+            }
+        }
+    }
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn unzigzag(v: u64) -> i64 {
+    (v >> 1) as i64 ^ -((v & 1) as i64)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+/// Parser for compact-protocol values.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        let byte = *self.data.get(self.pos).ok_or_else(truncated)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        let end = self.pos.checked_add(n).ok_or_else(truncated)?;
+        let slice = self.data.get(self.pos..end).ok_or_else(truncated)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn varint(&mut self) -> io::Result<u64> {
+        let mut v = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            v |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+        Err(missing())
+    }
+
+    fn parse_struct(&mut self, depth: usize) -> io::Result<Thrift> {
+        if depth > MAX_DEPTH {
+            return Err(missing());
+        }
+        let mut fields = Vec::new();
+        let mut last: i16 = 0;
+        loop {
+            let header = self.byte()?;
+            if header == 0 {
+                return Ok(Thrift::Struct(fields));
+            }
+            let ty = header & 0x0f;
+            let id = match header >> 4 {
+                0 => i16::try_from(unzigzag(self.varint()?)).map_err(|_| missing())?,
+                delta => last.checked_add(delta.into()).ok_or_else(missing)?,
+            };
+            let value = match ty {
+                1 => Thrift::Bool(true),
+                2 => Thrift::Bool(false),
+                ty => self.parse_value(ty, depth)?,
+            };
+            fields.push((id, value));
+            last = id;
+        }
+    }
+
+    fn parse_value(&mut self, ty: u8, depth: usize) -> io::Result<Thrift> {
+        Ok(match ty {
+            1 | 2 => Thrift::Bool(self.byte()? == 1),
+            3 => Thrift::Byte(self.byte()? as i8 as i64),
+            4 => Thrift::I16(unzigzag(self.varint()?)),
+            5 => Thrift::I32(unzigzag(self.varint()?)),
+            6 => Thrift::I64(unzigzag(self.varint()?)),
+            7 => Thrift::Double(u64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            8 => {
+                let len = usize::try_from(self.varint()?).map_err(|_| truncated())?;
+                Thrift::Binary(self.take(len)?.to_vec())
+            }
+            9 | 10 => {
+                let header = self.byte()?;
+                let mut len = u64::from(header >> 4);
+                if len == 15 {
+                    len = self.varint()?;
+                }
+                // Every element takes at least one byte.
+                if len > (self.data.len() - self.pos) as u64 {
+                    return Err(truncated());
+                }
+                let mut items = Vec::with_capacity(len as usize);
+                for _ in 0..len {
+                    items.push(self.parse_value(header & 0x0f, depth + 1)?);
+                }
+                Thrift::List(items)
+            }
+            12 => self.parse_struct(depth + 1)?,
+            _ => return Err(invalid("unsupported thrift type in parquet metadata")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+    use crate::segment::tests::{regular_block, temp_dir};
+
+    #[test]
+    fn test_roundtrip() {
+        let dir = temp_dir("parquet_roundtrip");
+        let path = dir.join("blocks.parquet");
+        let blocks = [
+            regular_block(0, 20),
+            regular_block(1200, 500),
+            regular_block(0, 1),
+        ];
+        let mut writer = ParquetWriter::new(&path);
+        writer.add(b"cpu", 0, 1140, &blocks[0]);
+        writer.add(b"cpu", 1200, 31140, &blocks[1]);
+        writer.add(b"mem", 0, 0, &blocks[2]);
+        writer.finish().unwrap();
+
+        let records = read(&path).unwrap();
+        assert_eq!(records.len(), 3);
+        for (record, block) in records.iter().zip(&blocks) {
+            assert_eq!(
+                Decoder::decode(&record.block).unwrap(),
+                Decoder::decode(block).unwrap()
+            );
+        }
+        assert_eq!(records[1].key, b"cpu");
+        assert_eq!((records[1].start, records[1].end), (1200, 31140));
+        assert_eq!(records[2].key, b"mem");
+
+        ParquetWriter::new(&path).finish().unwrap();
+        assert!(read(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_thrift_roundtrip() {
+        let value = Thrift::Struct(vec![
+            (1, Thrift::I32(-7)),
+            (2, Thrift::Bool(true)),
+            (3, Thrift::List((0..20).map(Thrift::I64).collect())),
+            (40, Thrift::Binary(b"far field".to_vec())),
+            (41, Thrift::Struct(vec![(1, Thrift::Bool(false))])),
+            (
+                42,
+                Thrift::List(vec![Thrift::Bool(true), Thrift::Bool(false)]),
+            ),
+        ]);
+        let mut bytes = Vec::new();
+        value.write(&mut bytes);
+        let mut reader = Reader::new(&bytes);
+        assert_eq!(reader.parse_struct(0).unwrap(), value);
+        assert_eq!(reader.pos, bytes.len());
+        assert!(Reader::new(&bytes[..bytes.len() - 1])
+            .parse_struct(0)
+            .is_err());
+    }
+
+    #[test]
+    fn test_rejects_damaged_files() {
+        assert!(parse(b"not parquet at all").is_err());
+        let dir = temp_dir("parquet_damaged");
+        let path = dir.join("blocks.parquet");
+        let mut writer = ParquetWriter::new(&path);
+        writer.add(b"cpu", 0, 1140, &regular_block(0, 20));
+        writer.finish().unwrap();
+        let mut data = fs::read(&path).unwrap();
+        let len = data.len();
+        data[len - 8..len - 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(parse(&data).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,294 @@
+use crate::decoder::{DecodeError, Decoder, Sample};
+use crate::encoder::CompressedBlock;
+
+/// Most boundaries [`evaluate_step`] evaluates in one call.
+pub const MAX_STEPS: u64 = 1 << 20;
+
+/// Error returned by [`evaluate_step`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryError {
+    /// One of the blocks could not be decoded.
+    Decode(DecodeError),
+    /// The grid from `start` to `end` has more than [`MAX_STEPS`]
+    /// boundaries; a larger `step` or a shorter range is needed.
+    TooManySteps {
+        /// The maximum number of boundaries.
+        limit: u64,
+    },
+}
+
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryError::Decode(err) => write!(f, "{err}"),
+            QueryError::TooManySteps { limit } => {
+                write!(f, "query grid exceeds the limit of {limit} steps")
+            }
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl From<DecodeError> for QueryError {
+    fn from(err: DecodeError) -> Self {
+        QueryError::Decode(err)
+    }
+}
+
+/// Aggregation function applied to the points that fall into a window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggFn {
+    /// Value of the earliest point in the window.
+    First,
+    /// Value of the latest point in the window.
+    Last,
+    /// Smallest value in the window.
+    Min,
+    /// Largest value in the window.
+    Max,
+    /// Sum of all values in the window.
+    Sum,
+    /// Arithmetic mean of all values in the window.
+    Mean,
+    /// Number of points in the window.
+    Count,
+}
+
+/// Running state for a single window, fed one value at a time.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Accumulator {
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+    first: f64,
+    last: f64,
+}
+
+impl Accumulator {
+    pub(crate) fn new() -> Self {
+        Self {
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            first: 0.0,
+            last: 0.0,
+        }
+    }
+
+    pub(crate) fn push(&mut self, value: f64) {
+        if self.count == 0 {
+            self.first = value;
+        }
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.last = value;
+    }
+
+    /// Returns the aggregate over everything pushed so far, or `None` if the
+    /// window is empty.
+    pub(crate) fn result(&self, agg: AggFn) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(match agg {
+            AggFn::First => self.first,
+            AggFn::Last => self.last,
+            AggFn::Min => self.min,
+            AggFn::Max => self.max,
+            AggFn::Sum => self.sum,
+            AggFn::Mean => self.sum / self.count as f64,
+            AggFn::Count => self.count as f64,
+        })
+    }
+}
+
+/// Evaluates `agg` on a regular grid of step boundaries, PromQL range-vector
+/// style.
+///
+/// `blocks` must be ordered by time and are treated as one continuous series.
+/// For every boundary `t` in `start, start + step, ..., <= end` the aggregate
+/// is computed over the points with timestamps in `(t - step, t]`; missing
+/// samples are skipped. Boundaries whose window contains no points yield
+/// `None`.
+///
+/// The blocks are decoded lazily in a single pass; decoding stops as soon as
+/// a point beyond `end` is seen, and blocks whose [`BlockStats`] show they
+/// end before the first window are skipped without decoding.
+///
+/// [`BlockStats`]: crate::encoder::BlockStats
+///
+/// Returns [`QueryError::TooManySteps`] if the grid has more than
+/// [`MAX_STEPS`] boundaries, and [`QueryError::Decode`] if a block fails to
+/// decode.
+///
+/// # Panics
+/// Panics if `step` is zero.
+///
+/// # Example
+/// ```
+/// use gorilla::{evaluate_step, AggFn, DataPoint, Encoder};
+///
+/// let mut encoder = Encoder::new();
+/// for i in 0..6 {
+///     encoder.encode(DataPoint::new(i * 10, i as f64)).unwrap();
+/// }
+/// encoder.finish().unwrap();
+/// let blocks = vec![encoder.into_compressed()];
+///
+/// let grid = evaluate_step(&blocks, 20, 50, 20, AggFn::Last).unwrap();
+/// assert_eq!(grid, vec![(20, Some(2.0)), (40, Some(4.0))]);
+/// ```
+pub fn evaluate_step(
+    blocks: &[CompressedBlock],
+    start: u64,
+    end: u64,
+    step: u64,
+    agg: AggFn,
+) -> Result<Vec<(u64, Option<f64>)>, QueryError> {
+    assert!(step > 0, "step must be non-zero");
+    let mut out = Vec::new();
+    if start > end {
+        return Ok(out);
+    }
+    let steps = ((end - start) / step)
+        .checked_add(1)
+        .filter(|&n| n <= MAX_STEPS);
+    let Some(steps) = steps else {
+        return Err(QueryError::TooManySteps { limit: MAX_STEPS });
+    };
+
+    let mut boundary = start;
+    let mut acc = Accumulator::new();
+
+    'blocks: for block in blocks {
+        if let Some(stats) = block.stats {
+            if start >= step && stats.end_timestamp <= start - step {
+                continue;
+            }
+        }
+        for result in Decoder::samples(block) {
+            let Sample::Value(dp) = result? else {
+                continue;
+            };
+            if start >= step && dp.timestamp <= start - step {
+                continue;
+            }
+            while dp.timestamp > boundary {
+                out.push((boundary, acc.result(agg)));
+                acc = Accumulator::new();
+                match boundary.checked_add(step) {
+                    Some(next) if (out.len() as u64) < steps => boundary = next,
+                    _ => break 'blocks,
+                }
+            }
+            acc.push(dp.value);
+        }
+    }
+
+    while (out.len() as u64) < steps {
+        out.push((boundary, acc.result(agg)));
+        acc = Accumulator::new();
+        boundary = boundary.saturating_add(step);
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::{DataPoint, Encoder};
+    use crate::segment::tests::block;
+
+    #[test]
+    fn test_step_across_chained_blocks() {
+        let blocks = vec![
+            block(&[(10, 1.0), (20, 2.0), (30, 3.0)]),
+            block(&[(40, 4.0), (50, 5.0), (60, 6.0)]),
+        ];
+        let grid = evaluate_step(&blocks, 20, 60, 20, AggFn::Sum).unwrap();
+        assert_eq!(
+            grid,
+            vec![(20, Some(3.0)), (40, Some(7.0)), (60, Some(11.0))]
+        );
+    }
+
+    #[test]
+    fn test_step_empty_windows_are_none() {
+        let blocks = vec![block(&[(10, 1.0), (100, 2.0)])];
+        let grid = evaluate_step(&blocks, 10, 100, 30, AggFn::Last).unwrap();
+        assert_eq!(
+            grid,
+            vec![(10, Some(1.0)), (40, None), (70, None), (100, Some(2.0))]
+        );
+    }
+
+    #[test]
+    fn test_step_aggregate_functions() {
+        let blocks = vec![block(&[(1, 4.0), (2, -1.0), (3, 6.0), (4, 3.0)])];
+        let eval = |agg| evaluate_step(&blocks, 4, 4, 4, agg).unwrap()[0].1;
+        assert_eq!(eval(AggFn::First), Some(4.0));
+        assert_eq!(eval(AggFn::Last), Some(3.0));
+        assert_eq!(eval(AggFn::Min), Some(-1.0));
+        assert_eq!(eval(AggFn::Max), Some(6.0));
+        assert_eq!(eval(AggFn::Sum), Some(12.0));
+        assert_eq!(eval(AggFn::Mean), Some(3.0));
+        assert_eq!(eval(AggFn::Count), Some(4.0));
+    }
+
+    #[test]
+    fn test_step_ignores_points_outside_range() {
+        let blocks = vec![block(&[(0, 100.0), (15, 1.0), (20, 2.0), (90, 100.0)])];
+        let grid = evaluate_step(&blocks, 20, 40, 10, AggFn::Count).unwrap();
+        assert_eq!(grid, vec![(20, Some(2.0)), (30, None), (40, None)]);
+    }
+
+    #[test]
+    fn test_step_start_after_end() {
+        let blocks = vec![block(&[(10, 1.0)])];
+        assert!(evaluate_step(&blocks, 50, 40, 10, AggFn::Last)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_step_skips_blocks_before_range_by_stats() {
+        let mut old = block(&[(10, 1.0), (20, 2.0)]);
+        old.bytes.iter_mut().for_each(|b| *b = 0xFF);
+        let blocks = vec![old, block(&[(100, 5.0), (110, 6.0)])];
+        let grid = evaluate_step(&blocks, 110, 110, 20, AggFn::Sum).unwrap();
+        assert_eq!(grid, vec![(110, Some(11.0))]);
+    }
+
+    #[test]
+    fn test_step_skips_gaps() {
+        let mut enc = Encoder::new();
+        enc.encode(DataPoint::new(10, 1.0)).unwrap();
+        enc.encode_gap(15).unwrap();
+        enc.encode(DataPoint::new(20, 3.0)).unwrap();
+        enc.encode_gap(30).unwrap();
+        enc.finish().unwrap();
+        let blocks = vec![enc.into_compressed()];
+        let grid = evaluate_step(&blocks, 20, 40, 20, AggFn::Mean).unwrap();
+        assert_eq!(grid, vec![(20, Some(2.0)), (40, None)]);
+    }
+
+    #[test]
+    fn test_step_grid_is_bounded() {
+        let blocks = vec![block(&[(u64::MAX - 15, 1.0), (u64::MAX, 2.0)])];
+        assert_eq!(
+            evaluate_step(&blocks, 0, u64::MAX, 1, AggFn::Sum),
+            Err(QueryError::TooManySteps { limit: MAX_STEPS })
+        );
+        let grid = evaluate_step(&blocks, u64::MAX - 10, u64::MAX, 10, AggFn::Sum).unwrap();
+        assert_eq!(
+            grid,
+            vec![(u64::MAX - 10, Some(1.0)), (u64::MAX, Some(2.0))]
+        );
+    }
+}
@@ -0,0 +1,410 @@
+//! Timestamps of fixed-rate sampling stored as an implicit index.
+//!
+//! At high sampling rates with fine timestamp resolution (e.g. 1 kHz in
+//! nanoseconds), a few nanoseconds of jitter push every delta-of-delta into
+//! the 12-bit bucket. A [`RegularBlock`] instead stores only the first
+//! timestamp and the sampling period: point `i` is expected at
+//! `start + i * period`, and points that deviate are listed as exceptions
+//! with their offset from the expected time. An offset of at least half a
+//! period (a dropped sample or a phase shift) re-anchors the grid at that
+//! point, so a gap costs one exception rather than one per later point.
+//!
+//! Values use the same XOR scheme as [`Encoder`], without timestamps or an
+//! end-of-stream marker.
+//!
+//! [`Encoder`]: crate::encoder::Encoder
+
+use crate::bitbuffer::{BitBuffer, BitReader};
+use crate::decoder::{DecodeError, Decoder};
+use crate::encoder::{write_xor, DataPoint, EncodeError};
+
+/// Version byte of the [`RegularBlock::to_bytes`] layout.
+const REGULAR_FORMAT_VERSION: u8 = 1;
+
+/// A finished fixed-rate block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegularBlock {
+    /// Timestamp of the first point.
+    pub start: u64,
+    /// Expected distance between consecutive timestamps.
+    pub period: u64,
+    /// Number of points in the block.
+    pub count: u64,
+    /// Points off the expected grid, as point index and offset from the
+    /// expected timestamp, in index order.
+    pub exceptions: Vec<(u64, i64)>,
+    /// XOR-compressed values.
+    pub bytes: Vec<u8>,
+    /// Number of valid bits in `bytes`.
+    pub total_bits: usize,
+}
+
+impl RegularBlock {
+    /// Serializes the block into a self-contained byte representation:
+    ///
+    /// | field           | size          |
+    /// |-----------------|---------------|
+    /// | version         | 1 byte        |
+    /// | start           | 8 bytes (LE)  |
+    /// | period          | 8 bytes (LE)  |
+    /// | count           | 8 bytes (LE)  |
+    /// | total bits      | 8 bytes (LE)  |
+    /// | exception count | LEB128 varint |
+    /// | exceptions      | LEB128 index delta and zigzag offset each |
+    /// | stream bytes    | `ceil(total_bits / 8)` |
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(34 + self.exceptions.len() * 4 + self.bytes.len());
+        out.push(REGULAR_FORMAT_VERSION);
+        out.extend_from_slice(&self.start.to_le_bytes());
+        out.extend_from_slice(&self.period.to_le_bytes());
+        out.extend_from_slice(&self.count.to_le_bytes());
+        out.extend_from_slice(&(self.total_bits as u64).to_le_bytes());
+        write_varint(&mut out, self.exceptions.len() as u64);
+        let mut prev = 0;
+        for &(index, offset) in &self.exceptions {
+            write_varint(&mut out, index.wrapping_sub(prev));
+            write_varint(&mut out, zigzag(offset));
+            prev = index;
+        }
+        out.extend_from_slice(&self.bytes);
+        out
+    }
+
+    /// Parses a block produced by [`to_bytes`](RegularBlock::to_bytes).
+    /// `bytes` must contain exactly one serialized block.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut pos = 0;
+        let mut take = |n: usize| -> Result<&[u8], DecodeError> {
+            let slice = bytes.get(pos..pos + n).ok_or(DecodeError::UnexpectedEnd)?;
+            pos += n;
+            Ok(slice)
+        };
+        let version = take(1)?[0];
+        if version != REGULAR_FORMAT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let start = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let period = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let count = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let total_bits = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let total_bits = usize::try_from(total_bits)
+            .map_err(|_| DecodeError::MalformedHeader("total bits out of range"))?;
+        let mut read_varint = || -> Result<u64, DecodeError> {
+            let mut n = 0u64;
+            let mut shift = 0;
+            loop {
+                let byte = take(1)?[0];
+                if shift > 63 {
+                    return Err(DecodeError::MalformedHeader("varint out of range"));
+                }
+                n |= ((byte & 0x7F) as u64) << shift;
+                shift += 7;
+                if byte & 0x80 == 0 {
+                    return Ok(n);
+                }
+            }
+        };
+        let exception_count = read_varint()?;
+        if exception_count > count {
+            return Err(DecodeError::MalformedHeader("more exceptions than points"));
+        }
+        let mut exceptions = Vec::with_capacity(exception_count as usize);
+        let mut index = 0u64;
+        for _ in 0..exception_count {
+            index = index
+                .checked_add(read_varint()?)
+                .ok_or(DecodeError::MalformedHeader("exception index out of range"))?;
+            exceptions.push((index, unzigzag(read_varint()?)));
+        }
+        let stream = take(total_bits.div_ceil(8))?.to_vec();
+        if pos != bytes.len() {
+            return Err(DecodeError::MalformedHeader("trailing bytes after block"));
+        }
+        Ok(RegularBlock {
+            start,
+            period,
+            count,
+            exceptions,
+            bytes: stream,
+            total_bits,
+        })
+    }
+}
+
+/// Compresses fixed-rate samples into a [`RegularBlock`].
+///
+/// # Example
+/// ```
+/// use gorilla::{DataPoint, RegularDecoder, RegularEncoder};
+///
+/// // 1 kHz in nanoseconds, with one late sample.
+/// let mut encoder = RegularEncoder::new(1_000_000);
+/// for i in 0..1000u64 {
+///     let jitter = if i == 500 { 40 } else { 0 };
+///     encoder.encode(DataPoint::new(i * 1_000_000 + jitter, 0.5)).unwrap();
+/// }
+/// let block = encoder.finish();
+/// assert_eq!(block.exceptions, [(500, 40)]);
+/// assert_eq!(RegularDecoder::decode(&block).unwrap()[500].timestamp, 500_000_040);
+/// ```
+#[derive(Debug)]
+pub struct RegularEncoder {
+    buf: BitBuffer,
+    period: u64,
+    start: u64,
+    count: u64,
+    exceptions: Vec<(u64, i64)>,
+    /// Timestamp and index the grid is anchored at.
+    anchor: (u64, u64),
+    prev_timestamp: u64,
+    prev_value_bits: u64,
+    window: (u8, u8),
+}
+
+impl RegularEncoder {
+    /// Creates an empty encoder for samples `period` timestamp units apart.
+    ///
+    /// # Panics
+    /// Panics if `period` is zero.
+    pub fn new(period: u64) -> Self {
+        assert!(period > 0, "sampling period must be non-zero");
+        Self {
+            buf: BitBuffer::new(),
+            period,
+            start: 0,
+            count: 0,
+            exceptions: Vec::new(),
+            anchor: (0, 0),
+            prev_timestamp: 0,
+            prev_value_bits: 0,
+            // No window yet, matching the decoder's initial state.
+            window: (64, 64),
+        }
+    }
+
+    /// Returns the number of points encoded so far.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the number of points off the expected grid so far.
+    pub fn exception_count(&self) -> usize {
+        self.exceptions.len()
+    }
+
+    /// Appends a point. Timestamps must be strictly increasing.
+    ///
+    /// Returns `Err(EncodeError::TimestampOutOfRange)` without encoding the
+    /// point if it is more than `i64::MAX` units after its expected time.
+    pub fn encode(&mut self, dp: DataPoint) -> Result<(), EncodeError> {
+        let bits = dp.value.to_bits();
+        if self.count == 0 {
+            self.buf.write_bits(bits, 64)?;
+            self.start = dp.timestamp;
+            self.anchor = (dp.timestamp, 0);
+        } else {
+            if dp.timestamp <= self.prev_timestamp {
+                return Err(if dp.timestamp == self.prev_timestamp {
+                    EncodeError::DuplicateTimestamp {
+                        timestamp: dp.timestamp,
+                    }
+                } else {
+                    EncodeError::OutOfOrder {
+                        previous: self.prev_timestamp,
+                        timestamp: dp.timestamp,
+                    }
+                });
+            }
+            let expected = expected(self.anchor, self.count, self.period);
+            let offset = i64::try_from(dp.timestamp as i128 - expected).map_err(|_| {
+                EncodeError::TimestampOutOfRange {
+                    previous: self.prev_timestamp,
+                    timestamp: dp.timestamp,
+                }
+            })?;
+            let xor = bits ^ self.prev_value_bits;
+            write_xor(
+                &mut self.buf,
+                xor,
+                xor.leading_zeros() as u8,
+                xor.trailing_zeros() as u8,
+                &mut self.window,
+            )?;
+            if offset != 0 {
+                self.exceptions.push((self.count, offset));
+                if reanchors(offset, self.period) {
+                    self.anchor = (dp.timestamp, self.count);
+                }
+            }
+        }
+        self.prev_timestamp = dp.timestamp;
+        self.prev_value_bits = bits;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Returns the finished block.
+    pub fn finish(self) -> RegularBlock {
+        let total_bits = self.buf.len_bits();
+        RegularBlock {
+            start: self.start,
+            period: self.period,
+            count: self.count,
+            exceptions: self.exceptions,
+            bytes: self.buf.into_bytes(),
+            total_bits,
+        }
+    }
+}
+
+/// Returns the expected timestamp of point `index` on the grid anchored at
+/// `anchor`.
+fn expected((timestamp, index): (u64, u64), at: u64, period: u64) -> i128 {
+    timestamp as i128 + (at - index) as i128 * period as i128
+}
+
+/// Returns `true` if an exception with `offset` moves the grid's anchor.
+fn reanchors(offset: i64, period: u64) -> bool {
+    offset.unsigned_abs().saturating_mul(2) >= period
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    (n >> 1) as i64 ^ -((n & 1) as i64)
+}
+
+/// Decompresses a [`RegularBlock`].
+pub struct RegularDecoder;
+
+impl RegularDecoder {
+    /// Decodes all points of `block`.
+    pub fn decode(block: &RegularBlock) -> Result<Vec<DataPoint>, DecodeError> {
+        if block.period == 0 {
+            return Err(DecodeError::MalformedHeader("zero sampling period"));
+        }
+        let mut reader = BitReader::from_raw(&block.bytes, block.total_bits);
+        let mut points = Vec::with_capacity(block.count.min(1 << 20) as usize);
+        let mut exceptions = block.exceptions.iter().peekable();
+        let mut anchor = (block.start, 0);
+        let mut value_bits = 0;
+        let mut window = (64, 64);
+        for index in 0..block.count {
+            if index == 0 {
+                value_bits = reader.read_bits(64).ok_or(DecodeError::UnexpectedEnd)?;
+                points.push(DataPoint::new(block.start, f64::from_bits(value_bits)));
+                continue;
+            }
+            (value_bits, window.0, window.1) =
+                Decoder::decode_value(&mut reader, value_bits, window.0, window.1)?;
+            let mut timestamp = expected(anchor, index, block.period);
+            if let Some(&(_, offset)) = exceptions.next_if(|&&(i, _)| i == index) {
+                timestamp += offset as i128;
+                if reanchors(offset, block.period) {
+                    anchor = (
+                        u64::try_from(timestamp).map_err(|_| DecodeError::TimestampOverflow)?,
+                        index,
+                    );
+                }
+            }
+            let timestamp = u64::try_from(timestamp).map_err(|_| DecodeError::TimestampOverflow)?;
+            points.push(DataPoint::new(timestamp, f64::from_bits(value_bits)));
+        }
+        if exceptions.next().is_some() {
+            return Err(DecodeError::MalformedHeader("exceptions out of order"));
+        }
+        Ok(points)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::Encoder;
+
+    #[test]
+    fn test_roundtrip_with_jitter_and_gaps() {
+        let period = 1_000_000;
+        let mut input = Vec::new();
+        let mut t = 1_609_459_200_000_000_000u64;
+        for i in 0..10_000u64 {
+            let jitter = if i % 97 == 13 { 250 } else { 0 };
+            if i == 4000 {
+                // Three dropped samples.
+                t += 3 * period;
+            }
+            input.push(DataPoint::new(t + jitter, 0.5));
+            t += period;
+        }
+        let mut encoder = RegularEncoder::new(period);
+        let mut baseline = Encoder::new();
+        for &dp in &input {
+            encoder.encode(dp).unwrap();
+            baseline.encode(dp).unwrap();
+        }
+        baseline.finish().unwrap();
+        let block = encoder.finish();
+        assert_eq!(block.exceptions.len(), 104);
+        assert!(block.exceptions.contains(&(4000, 3 * period as i64)));
+        assert_eq!(RegularDecoder::decode(&block).unwrap(), input);
+        let bytes = block.to_bytes();
+        assert!(bytes.len() * 3 < baseline.into_compressed().to_bytes().len() * 2);
+        assert_eq!(RegularBlock::from_bytes(&bytes).unwrap(), block);
+    }
+
+    #[test]
+    fn test_rejects_unordered_timestamps() {
+        let mut encoder = RegularEncoder::new(10);
+        encoder.encode(DataPoint::new(100, 1.0)).unwrap();
+        assert_eq!(
+            encoder.encode(DataPoint::new(100, 1.0)),
+            Err(EncodeError::DuplicateTimestamp { timestamp: 100 })
+        );
+        assert!(matches!(
+            encoder.encode(DataPoint::new(90, 1.0)),
+            Err(EncodeError::OutOfOrder { .. })
+        ));
+        encoder.encode(DataPoint::new(104, 2.0)).unwrap();
+        let mut block = encoder.finish();
+        assert_eq!(block.exceptions, [(1, -6)]);
+        assert_eq!(
+            RegularDecoder::decode(&block).unwrap(),
+            [DataPoint::new(100, 1.0), DataPoint::new(104, 2.0)]
+        );
+        let bytes = block.to_bytes();
+        assert_eq!(
+            RegularBlock::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        block.exceptions.push((0, 1));
+        assert!(RegularDecoder::decode(&block).is_err());
+    }
+
+    #[test]
+    fn test_rejects_timestamp_too_far_from_grid() {
+        let mut encoder = RegularEncoder::new(1000);
+        encoder.encode(DataPoint::new(0, 1.0)).unwrap();
+        assert_eq!(
+            encoder.encode(DataPoint::new(u64::MAX, 2.0)),
+            Err(EncodeError::TimestampOutOfRange {
+                previous: 0,
+                timestamp: u64::MAX,
+            })
+        );
+        encoder.encode(DataPoint::new(1000, 3.0)).unwrap();
+        assert_eq!(
+            RegularDecoder::decode(&encoder.finish()).unwrap(),
+            [DataPoint::new(0, 1.0), DataPoint::new(1000, 3.0)]
+        );
+    }
+}
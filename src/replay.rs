@@ -0,0 +1,156 @@
+//! Replay of stored blocks in simulated real time.
+//!
+//! [`simulate`] decodes blocks and hands their points to a sink with the
+//! original inter-arrival gaps scaled down by a speedup factor, so that
+//! downstream consumers can be load-tested with real historical data.
+//! Delays are scheduled against the replay's start rather than slept one by
+//! one, so time spent in the sink does not accumulate as drift.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::decoder::{DecodeError, Decoder};
+use crate::encoder::{CompressedBlock, DataPoint};
+
+/// Pacing of a replay.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayConfig {
+    /// How many times faster than recorded the points are delivered.
+    /// `f64::INFINITY` delivers them without delay.
+    pub speedup: f64,
+    /// Wall-clock length of one timestamp unit at a speedup of 1.
+    pub unit: Duration,
+    /// Longest recorded gap replayed as is; longer gaps (e.g. outages) are
+    /// shortened to this before scaling (`None` = no cap).
+    pub max_gap: Option<Duration>,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            speedup: 1.0,
+            unit: Duration::from_secs(1),
+            max_gap: None,
+        }
+    }
+}
+
+/// Replays `blocks` in order at `speedup` times real time, with timestamps
+/// in seconds, and returns the number of points delivered.
+///
+/// # Panics
+/// Panics if `speedup` is not positive.
+///
+/// # Example
+/// ```
+/// use gorilla::{replay, DataPoint, Encoder};
+///
+/// let mut encoder = Encoder::new();
+/// for t in 0..3 {
+///     encoder.encode(DataPoint::new(1609459200 + t, t as f64)).unwrap();
+/// }
+/// encoder.finish().unwrap();
+/// let block = encoder.into_compressed();
+///
+/// // Two seconds of data in about 20 ms.
+/// let mut seen = Vec::new();
+/// let count = replay::simulate([&block], 100.0, |dp| seen.push(dp.value)).unwrap();
+/// assert_eq!((count, seen), (3, vec![0.0, 1.0, 2.0]));
+/// ```
+pub fn simulate<'a>(
+    blocks: impl IntoIterator<Item = &'a CompressedBlock>,
+    speedup: f64,
+    sink: impl FnMut(DataPoint),
+) -> Result<u64, DecodeError> {
+    simulate_with(
+        blocks,
+        ReplayConfig {
+            speedup,
+            ..Default::default()
+        },
+        sink,
+    )
+}
+
+/// Like [`simulate`], with explicit pacing. Timestamps that go backwards,
+/// e.g. across overlapping blocks, are delivered without delay.
+///
+/// # Panics
+/// Panics if `config.speedup` is not positive.
+pub fn simulate_with<'a>(
+    blocks: impl IntoIterator<Item = &'a CompressedBlock>,
+    config: ReplayConfig,
+    mut sink: impl FnMut(DataPoint),
+) -> Result<u64, DecodeError> {
+    assert!(config.speedup > 0.0, "replay speedup must be positive");
+    let start = Instant::now();
+    // Scaled time since the first point at which the next point is due.
+    let mut due = Duration::ZERO;
+    let mut previous = None;
+    let mut count = 0;
+    for block in blocks {
+        for result in Decoder::iter(block) {
+            let dp = result?;
+            if let Some(previous) = previous {
+                let units =
+                    u32::try_from(dp.timestamp.saturating_sub(previous)).unwrap_or(u32::MAX);
+                let mut gap = config.unit.saturating_mul(units);
+                if let Some(max_gap) = config.max_gap {
+                    gap = gap.min(max_gap);
+                }
+                let scaled = Duration::try_from_secs_f64(gap.as_secs_f64() / config.speedup);
+                due = due.saturating_add(scaled.unwrap_or(Duration::MAX));
+                let wait = start.checked_add(due).map_or(Duration::MAX, |at| {
+                    at.saturating_duration_since(Instant::now())
+                });
+                if !wait.is_zero() {
+                    thread::sleep(wait);
+                }
+            }
+            previous = Some(dp.timestamp);
+            sink(dp);
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment::tests::block;
+
+    #[test]
+    fn test_gaps_are_scaled_and_capped() {
+        // Milliseconds, with a one-hour outage between the blocks.
+        let blocks = [
+            block(&[(0, 0.0), (20, 1.0), (40, 2.0)]),
+            block(&[(3_600_040, 3.0), (3_600_060, 4.0)]),
+        ];
+        let config = ReplayConfig {
+            speedup: 2.0,
+            unit: Duration::from_millis(1),
+            max_gap: Some(Duration::from_millis(40)),
+        };
+        let start = Instant::now();
+        let mut arrivals = Vec::new();
+        let count = simulate_with(&blocks, config, |dp| {
+            arrivals.push((dp.timestamp, start.elapsed()));
+        })
+        .unwrap();
+        assert_eq!(count, 5);
+        // 10 + 10 + 20 + 10 ms after the first point.
+        let elapsed = arrivals[4].1 - arrivals[0].1;
+        assert!(elapsed >= Duration::from_millis(50), "{elapsed:?}");
+        assert!(elapsed < Duration::from_secs(1), "{elapsed:?}");
+        assert!(arrivals[2].1 - arrivals[0].1 >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_infinite_speedup_does_not_wait() {
+        let blocks = [block(&[(0, 0.0), (1_000_000, 1.0), (2_000_000, 2.0)])];
+        let start = Instant::now();
+        assert_eq!(simulate(&blocks, f64::INFINITY, |_| {}).unwrap(), 3);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,285 @@
+//! Batched columnar scans of segment files for query engines.
+//!
+//! [`SegmentScan`] decodes a segment block by block and copies the points
+//! into caller-owned column buffers of up to [`VECTOR_SIZE`] rows, matching
+//! the vector-at-a-time model of engines such as DuckDB: each call to
+//! [`fill`](SegmentScan::fill) produces one `DataChunk` worth of `key`,
+//! `ts` and `value` columns. With the `ffi` feature the same scan is
+//! exposed through a C ABI for use from a DuckDB extension.
+
+use std::io;
+use std::path::PathBuf;
+
+use crate::decoder::Decoder;
+use crate::encoder::DataPoint;
+use crate::segment::Segment;
+
+/// Default number of rows per batch, DuckDB's `STANDARD_VECTOR_SIZE`.
+pub const VECTOR_SIZE: usize = 2048;
+
+/// A scan over the points of a segment within a time range.
+#[derive(Debug)]
+pub struct SegmentScan {
+    segment: Segment,
+    start: u64,
+    end: u64,
+    /// Index of the next entry to decode.
+    next_entry: usize,
+    /// Entry the buffered points belong to.
+    current_entry: u32,
+    points: Vec<DataPoint>,
+    pos: usize,
+}
+
+impl SegmentScan {
+    /// Opens the segment at `path` for a scan of points with timestamps in
+    /// `start..=end`.
+    pub fn open(path: impl Into<PathBuf>, start: u64, end: u64) -> io::Result<Self> {
+        Ok(Self::new(Segment::open(path)?, start, end))
+    }
+
+    /// Starts a scan of points with timestamps in `start..=end`.
+    pub fn new(segment: Segment, start: u64, end: u64) -> Self {
+        Self {
+            segment,
+            start,
+            end,
+            next_entry: 0,
+            current_entry: 0,
+            points: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Returns the scanned segment.
+    pub fn segment(&self) -> &Segment {
+        &self.segment
+    }
+
+    /// Returns the encoded series key for a value of the `keys` column.
+    pub fn key(&self, index: u32) -> Option<&[u8]> {
+        self.segment
+            .entries()
+            .get(index as usize)
+            .map(|entry| entry.key.as_slice())
+    }
+
+    /// Fills the column buffers with the next batch of points and returns
+    /// the number of rows written, which is 0 once the scan is exhausted.
+    ///
+    /// `keys[i]` is the index of the segment entry the row came from; see
+    /// [`key`](SegmentScan::key). At most the length of the shortest buffer
+    /// is written. Timestamps are converted to `i64`, saturating at
+    /// `i64::MAX`, as SQL engines have no unsigned 64-bit timestamp type.
+    pub fn fill(
+        &mut self,
+        keys: &mut [u32],
+        timestamps: &mut [i64],
+        values: &mut [f64],
+    ) -> io::Result<usize> {
+        let capacity = keys.len().min(timestamps.len()).min(values.len());
+        let mut rows = 0;
+        while rows < capacity {
+            if self.pos == self.points.len() && !self.load_next_block()? {
+                break;
+            }
+            while rows < capacity && self.pos < self.points.len() {
+                let dp = self.points[self.pos];
+                self.pos += 1;
+                if dp.timestamp < self.start || dp.timestamp > self.end {
+                    continue;
+                }
+                keys[rows] = self.current_entry;
+                timestamps[rows] = i64::try_from(dp.timestamp).unwrap_or(i64::MAX);
+                values[rows] = dp.value;
+                rows += 1;
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Decodes the next entry overlapping the range into the point buffer.
+    /// Returns `false` when no entries are left.
+    fn load_next_block(&mut self) -> io::Result<bool> {
+        let entries = self.segment.entries();
+        while let Some(entry) = entries.get(self.next_entry) {
+            let index = self.next_entry;
+            self.next_entry += 1;
+            if entry.end < self.start || entry.start > self.end {
+                continue;
+            }
+            let block = self.segment.read_block(entry)?;
+            self.points = Decoder::decode(&block)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            self.pos = 0;
+            self.current_entry = index as u32;
+            return Ok(true);
+        }
+        self.points.clear();
+        self.pos = 0;
+        Ok(false)
+    }
+}
+
+/// C ABI over [`SegmentScan`] for DuckDB extensions and other native hosts.
+///
+/// A table function's bind step calls `gorilla_scan_open`, each invocation
+/// of its scan callback calls `gorilla_scan_fill` with the `DataChunk`'s
+/// vector data pointers, and the destructor calls `gorilla_scan_close`.
+#[cfg(feature = "ffi")]
+pub mod ffi {
+    use std::ffi::{c_char, CStr};
+
+    use super::SegmentScan;
+
+    /// Opens a scan over the segment at the NUL-terminated UTF-8 `path` for
+    /// timestamps in `start..=end`. Returns null on error.
+    ///
+    /// # Safety
+    /// `path` must point to a valid NUL-terminated string.
+    #[no_mangle]
+    pub unsafe extern "C" fn gorilla_scan_open(
+        path: *const c_char,
+        start: u64,
+        end: u64,
+    ) -> *mut SegmentScan {
+        if path.is_null() {
+            return std::ptr::null_mut();
+        }
+        let Ok(path) = CStr::from_ptr(path).to_str() else {
+            return std::ptr::null_mut();
+        };
+        match SegmentScan::open(path, start, end) {
+            Ok(scan) => Box::into_raw(Box::new(scan)),
+            Err(_) => std::ptr::null_mut(),
+        }
+    }
+
+    /// Writes up to `capacity` rows into the three column buffers and
+    /// returns the number written, 0 at the end of the scan and -1 on error.
+    ///
+    /// # Safety
+    /// `scan` must come from `gorilla_scan_open` and not be closed; each
+    /// buffer must be valid for writes of `capacity` elements.
+    #[no_mangle]
+    pub unsafe extern "C" fn gorilla_scan_fill(
+        scan: *mut SegmentScan,
+        keys: *mut u32,
+        timestamps: *mut i64,
+        values: *mut f64,
+        capacity: usize,
+    ) -> i64 {
+        let Some(scan) = scan.as_mut() else {
+            return -1;
+        };
+        if keys.is_null() || timestamps.is_null() || values.is_null() {
+            return -1;
+        }
+        let keys = std::slice::from_raw_parts_mut(keys, capacity);
+        let timestamps = std::slice::from_raw_parts_mut(timestamps, capacity);
+        let values = std::slice::from_raw_parts_mut(values, capacity);
+        match scan.fill(keys, timestamps, values) {
+            Ok(rows) => rows as i64,
+            Err(_) => -1,
+        }
+    }
+
+    /// Returns the encoded key for a value of the `keys` column and stores
+    /// its length in `len`, or returns null if `index` is out of range. The
+    /// bytes stay valid until the scan is closed.
+    ///
+    /// # Safety
+    /// `scan` must come from `gorilla_scan_open` and not be closed; `len`
+    /// must be valid for a write.
+    #[no_mangle]
+    pub unsafe extern "C" fn gorilla_scan_key(
+        scan: *const SegmentScan,
+        index: u32,
+        len: *mut usize,
+    ) -> *const u8 {
+        match scan.as_ref().and_then(|scan| scan.key(index)) {
+            Some(key) => {
+                *len = key.len();
+                key.as_ptr()
+            }
+            None => std::ptr::null(),
+        }
+    }
+
+    /// Closes a scan. Passing null is a no-op.
+    ///
+    /// # Safety
+    /// `scan` must come from `gorilla_scan_open` and not already be closed.
+    #[no_mangle]
+    pub unsafe extern "C" fn gorilla_scan_close(scan: *mut SegmentScan) {
+        if !scan.is_null() {
+            drop(Box::from_raw(scan));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::Encoder;
+    use crate::segment::tests::temp_dir;
+    use crate::segment::SegmentWriter;
+
+    fn write_segment(name: &str) -> (PathBuf, PathBuf) {
+        let dir = temp_dir(name);
+        let path = dir.join("scan.seg");
+        let mut writer = SegmentWriter::new(&path);
+        for (key, base) in [(b"cpu", 0u64), (b"mem", 1000)] {
+            let mut enc = Encoder::new();
+            for i in 0..5000 {
+                enc.encode(DataPoint::new(base + i, i as f64)).unwrap();
+            }
+            enc.finish().unwrap();
+            writer.add(key, base, base + 4999, &enc.into_compressed());
+        }
+        writer.finish().unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn test_fill_batches_across_blocks() {
+        let (dir, path) = write_segment("scan-batches");
+        let mut scan = SegmentScan::open(&path, 0, u64::MAX).unwrap();
+        let (mut k, mut t, mut v) = (
+            vec![0; VECTOR_SIZE],
+            vec![0; VECTOR_SIZE],
+            vec![0.0; VECTOR_SIZE],
+        );
+        let mut batches = Vec::new();
+        loop {
+            let rows = scan.fill(&mut k, &mut t, &mut v).unwrap();
+            if rows == 0 {
+                break;
+            }
+            batches.push(rows);
+            if t[rows - 1] == 1000 + 4999 {
+                assert_eq!(scan.key(k[rows - 1]), Some(&b"mem"[..]));
+            }
+        }
+        assert_eq!(batches.iter().sum::<usize>(), 10_000);
+        assert!(batches[..batches.len() - 1]
+            .iter()
+            .all(|&rows| rows == VECTOR_SIZE));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_fill_respects_range() {
+        let (dir, path) = write_segment("scan-range");
+        let mut scan = SegmentScan::open(&path, 4990, 5002).unwrap();
+        let (mut k, mut t, mut v) = ([0; 64], [0; 64], [0.0; 64]);
+        let rows = scan.fill(&mut k, &mut t, &mut v).unwrap();
+        assert_eq!(rows, 10 + 13);
+        assert_eq!(&t[..3], &[4990, 4991, 4992]);
+        assert_eq!(scan.key(k[0]), Some(&b"cpu"[..]));
+        assert_eq!(scan.key(k[rows - 1]), Some(&b"mem"[..]));
+        assert_eq!(v[rows - 1], 4002.0);
+        assert_eq!(scan.fill(&mut k, &mut t, &mut v).unwrap(), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,205 @@
+//! Versioned field descriptors for rows of several values per timestamp.
+//!
+//! A [`Schema`] names the value columns written alongside one timestamp
+//! stream and is stored with every block generation. When fields are added
+//! or removed between generations, a reader resolves a [`Projection`] from
+//! the block's schema to the schema it expects: columns are matched by
+//! name, columns the block lacks are filled with the reader field's
+//! default, and columns the reader no longer knows are dropped.
+//!
+//! The crate has no multi-field block format yet; this module provides the
+//! descriptor and the decode-time mapping such a format is built on.
+
+use crate::decoder::DecodeError;
+
+/// One value column of a [`Schema`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDef {
+    /// Column name, unique within a schema.
+    pub name: String,
+    /// Value reported for rows from blocks written before the field existed.
+    pub default: f64,
+}
+
+impl FieldDef {
+    /// Creates a field.
+    pub fn new(name: impl Into<String>, default: f64) -> Self {
+        Self {
+            name: name.into(),
+            default,
+        }
+    }
+}
+
+/// An ordered list of value columns, tagged with a version number.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schema {
+    /// Generation of the schema; increases whenever fields change.
+    pub version: u32,
+    /// Columns in storage order.
+    pub fields: Vec<FieldDef>,
+}
+
+impl Schema {
+    /// Creates a schema.
+    pub fn new(version: u32, fields: Vec<FieldDef>) -> Self {
+        Self { version, fields }
+    }
+
+    /// Returns the position of the field called `name`.
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.fields.iter().position(|f| f.name == name)
+    }
+
+    /// Returns the mapping from rows written with `written` to rows of this
+    /// schema.
+    pub fn projection_from(&self, written: &Schema) -> Projection {
+        Projection {
+            columns: self
+                .fields
+                .iter()
+                .map(|field| match written.index_of(&field.name) {
+                    Some(index) => Column::Stored(index),
+                    None => Column::Default(field.default),
+                })
+                .collect(),
+        }
+    }
+
+    /// Serializes the schema:
+    ///
+    /// | field       | size |
+    /// |-------------|------|
+    /// | version     | 4 bytes (LE) |
+    /// | field count | 2 bytes (LE) |
+    /// | per field   | name length (2 bytes LE), UTF-8 name, default (8 bytes LE bits) |
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&self.version.to_le_bytes());
+        out.extend_from_slice(&(self.fields.len() as u16).to_le_bytes());
+        for field in &self.fields {
+            out.extend_from_slice(&(field.name.len() as u16).to_le_bytes());
+            out.extend_from_slice(field.name.as_bytes());
+            out.extend_from_slice(&field.default.to_bits().to_le_bytes());
+        }
+        out
+    }
+
+    /// Parses a schema from the front of `bytes`, returning it with the
+    /// number of bytes consumed.
+    pub fn read_from(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let mut pos = 0;
+        let mut take = |n: usize| -> Result<&[u8], DecodeError> {
+            let slice = bytes.get(pos..pos + n).ok_or(DecodeError::UnexpectedEnd)?;
+            pos += n;
+            Ok(slice)
+        };
+        let version = u32::from_le_bytes(take(4)?.try_into().unwrap());
+        let count = u16::from_le_bytes(take(2)?.try_into().unwrap());
+        let mut fields = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let len = u16::from_le_bytes(take(2)?.try_into().unwrap());
+            let name = std::str::from_utf8(take(len as usize)?)
+                .map_err(|_| DecodeError::MalformedHeader("field name is not UTF-8"))?
+                .to_string();
+            let default = f64::from_bits(u64::from_le_bytes(take(8)?.try_into().unwrap()));
+            fields.push(FieldDef { name, default });
+        }
+        Ok((Schema { version, fields }, pos))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Column {
+    /// Take the value at this index of the stored row.
+    Stored(usize),
+    /// The block predates the field: use its default.
+    Default(f64),
+}
+
+/// Maps rows stored under one schema onto another, see
+/// [`Schema::projection_from`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Projection {
+    columns: Vec<Column>,
+}
+
+impl Projection {
+    /// Returns `true` if rows pass through unchanged.
+    pub fn is_identity(&self) -> bool {
+        self.columns
+            .iter()
+            .enumerate()
+            .all(|(i, column)| *column == Column::Stored(i))
+    }
+
+    /// Maps one stored row to the reader's schema, writing into `out`.
+    ///
+    /// # Panics
+    /// Panics if `row` is shorter than the schema it was written with.
+    pub fn apply_into(&self, row: &[f64], out: &mut Vec<f64>) {
+        out.clear();
+        out.extend(self.columns.iter().map(|column| match *column {
+            Column::Stored(index) => row[index],
+            Column::Default(value) => value,
+        }));
+    }
+
+    /// Maps one stored row to the reader's schema.
+    ///
+    /// # Example
+    /// ```
+    /// use gorilla::{FieldDef, Schema};
+    ///
+    /// let v1 = Schema::new(1, vec![FieldDef::new("user", 0.0), FieldDef::new("iowait", 0.0)]);
+    /// let v2 = Schema::new(2, vec![FieldDef::new("user", 0.0), FieldDef::new("steal", f64::NAN)]);
+    ///
+    /// let row = v2.projection_from(&v1).apply(&[12.5, 3.0]);
+    /// assert_eq!(row[0], 12.5);
+    /// assert!(row[1].is_nan());
+    /// ```
+    pub fn apply(&self, row: &[f64]) -> Vec<f64> {
+        let mut out = Vec::with_capacity(self.columns.len());
+        self.apply_into(row, &mut out);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(version: u32, fields: &[(&str, f64)]) -> Schema {
+        Schema::new(
+            version,
+            fields.iter().map(|&(n, d)| FieldDef::new(n, d)).collect(),
+        )
+    }
+
+    #[test]
+    fn test_projection_adds_defaults_and_drops_removed_fields() {
+        let old = schema(1, &[("a", 0.0), ("b", 0.0), ("c", 0.0)]);
+        let new = schema(2, &[("c", 0.0), ("d", -1.0), ("a", 0.0)]);
+        let projection = new.projection_from(&old);
+        assert!(!projection.is_identity());
+        assert_eq!(projection.apply(&[1.0, 2.0, 3.0]), vec![3.0, -1.0, 1.0]);
+        assert!(new.projection_from(&new).is_identity());
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let s = schema(7, &[("rx_bytes", 0.0), ("tx_bytes", f64::NAN)]);
+        let mut bytes = s.to_bytes();
+        let used = bytes.len();
+        bytes.push(0xAB);
+        let (parsed, consumed) = Schema::read_from(&bytes).unwrap();
+        assert_eq!(consumed, used);
+        assert_eq!(parsed.version, 7);
+        assert_eq!(parsed.fields[0], s.fields[0]);
+        assert!(parsed.fields[1].default.is_nan());
+        assert_eq!(
+            Schema::read_from(&bytes[..used - 1]).unwrap_err(),
+            DecodeError::UnexpectedEnd
+        );
+    }
+}
@@ -0,0 +1,461 @@
+//! Immutable on-disk segment files holding sealed blocks of many series.
+//!
+//! A segment is written once by [`SegmentWriter`] and never modified in
+//! place. Its layout is a small header followed by one record per block:
+//!
+//! | field       | size                        |
+//! |-------------|-----------------------------|
+//! | magic       | 4 bytes (`GSEG`)            |
+//! | version     | 1 byte                      |
+//! | *records*   |                             |
+//! | key length  | 4 bytes (LE)                |
+//! | key         | key length bytes            |
+//! | start       | 8 bytes (LE)                |
+//! | end         | 8 bytes (LE)                |
+//! | kind        | 1 byte: 0 = block, 1 = reference |
+//! | *block*     |                             |
+//! | block length| 4 bytes (LE)                |
+//! | block       | [`CompressedBlock::to_bytes`] output |
+//! | *reference* |                             |
+//! | canonical   | 4 bytes (LE): index of an earlier block record |
+//! | hash        | 8 bytes (LE): [`hash64`] of the canonical block |
+//!
+//! A block whose serialized bytes equal those of a block already in the
+//! segment, as with mirrored series, is written as a reference to it.
+//! References are resolved when the segment is opened, so
+//! [`Segment::read_block`] returns the canonical block for them. Version 1
+//! segments, which have no kind byte, are still read.
+//!
+//! With the `mmap` feature, opened segments are memory-mapped and blocks are
+//! read straight out of the mapping; otherwise each block is read from the
+//! file on demand.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::checksum::hash64;
+use crate::encoder::CompressedBlock;
+
+const MAGIC: &[u8; 4] = b"GSEG";
+const VERSION: u8 = 2;
+const HEADER_LEN: usize = 5;
+/// Record kind: the serialized block follows.
+const KIND_BLOCK: u8 = 0;
+/// Record kind: a reference to an earlier block record follows.
+const KIND_REFERENCE: u8 = 1;
+
+/// Location and metadata of one block inside a [`Segment`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentEntry {
+    /// Encoded key of the series the block belongs to.
+    pub key: Vec<u8>,
+    /// Smallest timestamp in the block.
+    pub start: u64,
+    /// Largest timestamp in the block.
+    pub end: u64,
+    /// Byte offset of the serialized block within the segment file.
+    offset: usize,
+    /// Length of the serialized block in bytes.
+    len: usize,
+    /// Index of the entry whose block this one references, if any.
+    canonical: Option<usize>,
+}
+
+impl SegmentEntry {
+    /// Returns the index of the entry holding this entry's block if it was
+    /// stored as a reference to it.
+    pub fn canonical(&self) -> Option<usize> {
+        self.canonical
+    }
+}
+
+/// Builds a segment file. Records are buffered in memory and written to a
+/// temporary file that is atomically renamed into place by
+/// [`finish`](SegmentWriter::finish).
+#[derive(Debug)]
+pub struct SegmentWriter {
+    path: PathBuf,
+    buf: Vec<u8>,
+    records: u32,
+    references: usize,
+    /// Block records by content hash, as record index, offset and length.
+    blocks: HashMap<u64, Vec<(u32, usize, usize)>>,
+}
+
+impl SegmentWriter {
+    /// Starts a new segment that will be written to `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let mut buf = Vec::with_capacity(4096);
+        buf.extend_from_slice(MAGIC);
+        buf.push(VERSION);
+        Self {
+            path: path.into(),
+            buf,
+            records: 0,
+            references: 0,
+            blocks: HashMap::new(),
+        }
+    }
+
+    /// Adds a block belonging to the series with encoded key `key`, covering
+    /// timestamps `start..=end`. A block identical to one added before is
+    /// stored as a reference to it.
+    pub fn add(&mut self, key: &[u8], start: u64, end: u64, block: &CompressedBlock) {
+        let block = block.to_bytes();
+        let hash = hash64(&block);
+        self.buf
+            .extend_from_slice(&(key.len() as u32).to_le_bytes());
+        self.buf.extend_from_slice(key);
+        self.buf.extend_from_slice(&start.to_le_bytes());
+        self.buf.extend_from_slice(&end.to_le_bytes());
+        self.records += 1;
+        let same = self.blocks.get(&hash).and_then(|candidates| {
+            candidates
+                .iter()
+                .find(|&&(_, offset, len)| self.buf[offset..offset + len] == block[..])
+        });
+        if let Some(&(canonical, _, _)) = same {
+            self.buf.push(KIND_REFERENCE);
+            self.buf.extend_from_slice(&canonical.to_le_bytes());
+            self.buf.extend_from_slice(&hash.to_le_bytes());
+            self.references += 1;
+            return;
+        }
+        self.buf.push(KIND_BLOCK);
+        self.buf
+            .extend_from_slice(&(block.len() as u32).to_le_bytes());
+        self.blocks
+            .entry(hash)
+            .or_default()
+            .push((self.records - 1, self.buf.len(), block.len()));
+        self.buf.extend_from_slice(&block);
+    }
+
+    /// Returns how many added blocks were stored as references to an
+    /// identical earlier block.
+    pub fn references(&self) -> usize {
+        self.references
+    }
+
+    /// Writes the segment to disk, syncing it before it becomes visible
+    /// under its final name.
+    pub fn finish(self) -> io::Result<()> {
+        self.write()
+    }
+
+    /// Writes the blocks added so far as the segment, replacing any version
+    /// written before, and syncs it before it becomes visible under its
+    /// final name.
+    pub(crate) fn write(&self) -> io::Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&self.buf)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+#[cfg(feature = "mmap")]
+type SegmentData = memmap2::Mmap;
+
+#[cfg(not(feature = "mmap"))]
+type SegmentData = ();
+
+/// A read-only segment file opened for queries.
+#[derive(Debug)]
+pub struct Segment {
+    path: PathBuf,
+    #[cfg_attr(not(feature = "mmap"), allow(dead_code))]
+    data: SegmentData,
+    entries: Vec<SegmentEntry>,
+}
+
+impl Segment {
+    /// Opens a segment file and indexes its records.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        #[cfg(feature = "mmap")]
+        let (data, entries) = {
+            let file = File::open(&path)?;
+            // SAFETY: segment files are written to a temporary name and
+            // renamed into place, and are never modified afterwards.
+            let data = unsafe { memmap2::Mmap::map(&file)? };
+            let entries = index(&data)?;
+            (data, entries)
+        };
+        #[cfg(not(feature = "mmap"))]
+        let (data, entries) = ((), index(&fs::read(&path)?)?);
+        Ok(Self {
+            path,
+            data,
+            entries,
+        })
+    }
+
+    /// Returns the path of the segment file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the index of all blocks in the segment, in file order.
+    pub fn entries(&self) -> &[SegmentEntry] {
+        &self.entries
+    }
+
+    /// Reads and parses the block described by `entry`.
+    pub fn read_block(&self, entry: &SegmentEntry) -> io::Result<CompressedBlock> {
+        #[cfg(feature = "mmap")]
+        let block = CompressedBlock::from_bytes(&self.data[entry.offset..entry.offset + entry.len]);
+        #[cfg(not(feature = "mmap"))]
+        let block = {
+            use std::io::{Read, Seek, SeekFrom};
+            let mut file = File::open(&self.path)?;
+            file.seek(SeekFrom::Start(entry.offset as u64))?;
+            let mut bytes = vec![0; entry.len];
+            file.read_exact(&mut bytes)?;
+            CompressedBlock::from_bytes(&bytes)
+        };
+        block.map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+/// Walks the records of a segment image and builds its index.
+fn index(data: &[u8]) -> io::Result<Vec<SegmentEntry>> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    if data.len() < HEADER_LEN || &data[..4] != MAGIC {
+        return Err(invalid("not a segment file"));
+    }
+    let version = data[4];
+    if version != 1 && version != VERSION {
+        return Err(invalid("unsupported segment version"));
+    }
+
+    let mut entries = Vec::new();
+    let mut pos = HEADER_LEN;
+    let truncated = || invalid("truncated segment record");
+    while pos < data.len() {
+        let mut take = |n: usize| -> io::Result<&[u8]> {
+            let slice = data.get(pos..pos + n).ok_or_else(truncated)?;
+            pos += n;
+            Ok(slice)
+        };
+        let key_len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+        let key = take(key_len)?.to_vec();
+        let start = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let end = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let kind = if version == 1 {
+            KIND_BLOCK
+        } else {
+            take(1)?[0]
+        };
+        match kind {
+            KIND_BLOCK => {
+                let len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+                take(len)?;
+                entries.push(SegmentEntry {
+                    key,
+                    start,
+                    end,
+                    offset: pos - len,
+                    len,
+                    canonical: None,
+                });
+            }
+            KIND_REFERENCE => {
+                let canonical = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+                let hash = u64::from_le_bytes(take(8)?.try_into().unwrap());
+                let target = entries
+                    .get(canonical)
+                    .filter(|e| e.canonical.is_none())
+                    .ok_or_else(|| invalid("dangling block reference"))?;
+                let (offset, len) = (target.offset, target.len);
+                if hash64(&data[offset..offset + len]) != hash {
+                    return Err(invalid("block reference hash mismatch"));
+                }
+                entries.push(SegmentEntry {
+                    key,
+                    start,
+                    end,
+                    offset,
+                    len,
+                    canonical: Some(canonical),
+                });
+            }
+            _ => return Err(invalid("unknown segment record kind")),
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+    use crate::encoder::{DataPoint, Encoder, EncoderConfig};
+
+    /// Creates a fresh, empty directory under the system temp dir.
+    pub(crate) fn temp_dir(name: &str) -> PathBuf {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let dir =
+            std::env::temp_dir().join(format!("gorilla-{name}-{}-{nanos}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Encodes `points` with `config` into a finished block.
+    pub(crate) fn block_with(
+        config: EncoderConfig,
+        points: impl IntoIterator<Item = DataPoint>,
+    ) -> CompressedBlock {
+        let mut encoder = Encoder::with_config(config);
+        for dp in points {
+            encoder.encode(dp).unwrap();
+        }
+        encoder.finish_into().unwrap()
+    }
+
+    /// Encodes `(timestamp, value)` pairs with the default configuration.
+    pub(crate) fn block(points: &[(u64, f64)]) -> CompressedBlock {
+        block_with(
+            EncoderConfig::default(),
+            points.iter().map(|&(t, v)| DataPoint::new(t, v)),
+        )
+    }
+
+    /// Encodes `n` points one minute apart from `start`, with values
+    /// counting up from zero.
+    pub(crate) fn regular_block(start: u64, n: u64) -> CompressedBlock {
+        block_with(
+            EncoderConfig::default(),
+            (0..n).map(|i| DataPoint::new(start + i * 60, i as f64)),
+        )
+    }
+
+    #[test]
+    fn test_segment_roundtrip() {
+        let dir = temp_dir("segment-roundtrip");
+        let path = dir.join("a.seg");
+        let a = regular_block(1000, 5);
+        let b = regular_block(5000, 3);
+        let mut writer = SegmentWriter::new(&path);
+        writer.add(b"cpu", 1000, 1240, &a);
+        writer.add(b"mem", 5000, 5120, &b);
+        writer.finish().unwrap();
+
+        let segment = Segment::open(&path).unwrap();
+        let entries = segment.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].key, b"cpu");
+        assert_eq!((entries[1].start, entries[1].end), (5000, 5120));
+        let read = segment.read_block(&entries[1]).unwrap();
+        assert_eq!(read.bytes, b.bytes);
+        assert_eq!(read.count, 3);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_segment_roundtrips_blocks_with_an_epoch() {
+        let dir = temp_dir("segment-epoch");
+        let path = dir.join("a.seg");
+        let config = EncoderConfig {
+            epoch: Some(3_600),
+            ..Default::default()
+        };
+        let block = block_with(
+            config,
+            (0..10).map(|i| DataPoint::new(3_700 + i * 60, i as f64)),
+        );
+        let mut writer = SegmentWriter::new(&path);
+        writer.add(b"cpu", 3_700, 4_240, &block);
+        writer.finish().unwrap();
+
+        let segment = Segment::open(&path).unwrap();
+        let read = segment.read_block(&segment.entries()[0]).unwrap();
+        assert_eq!(read.epoch, Some(3_600));
+        assert_eq!(
+            Decoder::decode(&read).unwrap(),
+            Decoder::decode(&block).unwrap()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_segment_stores_repeated_blocks_as_references() {
+        let dir = temp_dir("segment-references");
+        let path = dir.join("a.seg");
+        let a = regular_block(1000, 200);
+        let mut writer = SegmentWriter::new(&path);
+        writer.add(b"cpu", 1000, 12940, &a);
+        writer.add(b"cpu.mirror", 1000, 12940, &a);
+        writer.add(b"mem", 1000, 1240, &regular_block(1000, 5));
+        writer.add(b"cpu.backup", 1000, 12940, &a);
+        assert_eq!(writer.references(), 2);
+        writer.finish().unwrap();
+
+        let segment = Segment::open(&path).unwrap();
+        let entries = segment.entries();
+        let canonical: Vec<_> = entries.iter().map(SegmentEntry::canonical).collect();
+        assert_eq!(canonical, [None, Some(0), None, Some(0)]);
+        assert_eq!(entries[3].key, b"cpu.backup");
+        for entry in [&entries[1], &entries[3]] {
+            assert_eq!(segment.read_block(entry).unwrap().to_bytes(), a.to_bytes());
+        }
+        let len = fs::metadata(&path).unwrap().len() as usize;
+        assert!(len < 2 * a.to_bytes().len());
+
+        // A damaged canonical block no longer matches its references' hash.
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[HEADER_LEN + 4 + 3 + 16 + 1 + 4 + 30] ^= 0xFF;
+        fs::write(&path, bytes).unwrap();
+        let err = Segment::open(&path).unwrap_err();
+        assert_eq!(err.to_string(), "block reference hash mismatch");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_segment_reads_version_1() {
+        let dir = temp_dir("segment-v1");
+        let path = dir.join("old.seg");
+        let b = regular_block(5000, 3);
+        let bytes = b.to_bytes();
+        let mut image = b"GSEG\x01".to_vec();
+        image.extend_from_slice(&3u32.to_le_bytes());
+        image.extend_from_slice(b"mem");
+        image.extend_from_slice(&5000u64.to_le_bytes());
+        image.extend_from_slice(&5120u64.to_le_bytes());
+        image.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        image.extend_from_slice(&bytes);
+        fs::write(&path, image).unwrap();
+
+        let segment = Segment::open(&path).unwrap();
+        assert_eq!(segment.entries().len(), 1);
+        assert_eq!(
+            segment.read_block(&segment.entries()[0]).unwrap().bytes,
+            b.bytes
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_segment_rejects_garbage() {
+        let dir = temp_dir("segment-garbage");
+        let path = dir.join("bad.seg");
+        fs::write(&path, b"not a segment").unwrap();
+        let err = Segment::open(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut writer = SegmentWriter::new(&path);
+        writer.add(b"cpu", 0, 0, &regular_block(0, 1));
+        writer.finish().unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        bytes.truncate(bytes.len() - 3);
+        fs::write(&path, bytes).unwrap();
+        assert!(Segment::open(&path).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
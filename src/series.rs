@@ -0,0 +1,1214 @@
+use std::collections::VecDeque;
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::admission::Rejection;
+use crate::bitbuffer::BufferFull;
+use crate::chunked::{ChunkConfig, ChunkedEncoder};
+use crate::compaction::{BlockInfo, CompactionPolicy};
+use crate::decoder::{DecodeError, Decoder};
+use crate::encoder::{
+    merged_watermark, CompressedBlock, DataPoint, EncodeError, Encoder, EncoderConfig,
+};
+
+/// Error returned by [`TimeSeries::append`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum AppendError {
+    /// The underlying encoder rejected the point.
+    Encode(EncodeError),
+    /// The point repeats the timestamp of the last appended point with a
+    /// different value. Only reported when [`SeriesConfig::idempotent`] is set.
+    Conflict {
+        /// The point already stored for this timestamp.
+        existing: DataPoint,
+        /// The rejected incoming point.
+        incoming: DataPoint,
+    },
+    /// The map's [`AdmissionControl`](crate::AdmissionControl) hook refused
+    /// the point.
+    Throttled(Rejection),
+}
+
+impl std::fmt::Display for AppendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppendError::Encode(err) => write!(f, "{err}"),
+            AppendError::Conflict { existing, incoming } => write!(
+                f,
+                "conflicting value at timestamp {}: stored {}, got {}",
+                existing.timestamp, existing.value, incoming.value
+            ),
+            AppendError::Throttled(rejection) => write!(f, "{rejection}"),
+        }
+    }
+}
+
+impl std::error::Error for AppendError {}
+
+impl From<EncodeError> for AppendError {
+    fn from(err: EncodeError) -> Self {
+        AppendError::Encode(err)
+    }
+}
+
+/// Error returned by [`TimeSeries::replace_range`] and
+/// [`TimeSeries::compact`]. The series is left unchanged.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplaceError {
+    /// A replacement point lies outside the range being replaced.
+    OutOfRange { timestamp: u64 },
+    /// Replacement points are not in strictly increasing timestamp order.
+    Unordered { previous: u64, timestamp: u64 },
+    /// A block covering the range could not be decoded.
+    Decode(DecodeError),
+    /// The replacement blocks could not be encoded.
+    Encode(EncodeError),
+}
+
+impl std::fmt::Display for ReplaceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplaceError::OutOfRange { timestamp } => {
+                write!(f, "replacement point at {timestamp} is outside the range")
+            }
+            ReplaceError::Unordered {
+                previous,
+                timestamp,
+            } => write!(
+                f,
+                "replacement points out of order: {timestamp} follows {previous}"
+            ),
+            ReplaceError::Decode(err) => write!(f, "{err}"),
+            ReplaceError::Encode(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for ReplaceError {}
+
+impl From<DecodeError> for ReplaceError {
+    fn from(err: DecodeError) -> Self {
+        ReplaceError::Decode(err)
+    }
+}
+
+impl From<EncodeError> for ReplaceError {
+    fn from(err: EncodeError) -> Self {
+        ReplaceError::Encode(err)
+    }
+}
+
+impl From<BufferFull> for ReplaceError {
+    fn from(err: BufferFull) -> Self {
+        ReplaceError::Encode(err.into())
+    }
+}
+
+/// A key identifying a series in a multi-series store, with a stable byte
+/// encoding used by on-disk formats.
+pub trait SeriesKey: Clone + Eq + std::hash::Hash {
+    /// Encodes the key as bytes.
+    fn to_key_bytes(&self) -> Vec<u8>;
+    /// Decodes a key produced by [`to_key_bytes`](SeriesKey::to_key_bytes).
+    fn from_key_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+impl SeriesKey for String {
+    fn to_key_bytes(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn from_key_bytes(bytes: &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+impl SeriesKey for u64 {
+    fn to_key_bytes(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+
+    fn from_key_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(u64::from_be_bytes(bytes.try_into().ok()?))
+    }
+}
+
+/// Configuration for a [`TimeSeries`].
+#[derive(Debug, Clone, Default)]
+pub struct SeriesConfig {
+    /// Configuration used for every block's encoder.
+    pub encoder: EncoderConfig,
+    /// Treat appends as idempotent: a point identical to a recently appended
+    /// point (same timestamp, bit-identical value) is skipped, and a point
+    /// with the same timestamp but a different value is rejected with
+    /// [`AppendError::Conflict`].
+    pub idempotent: bool,
+    /// Number of most recent points remembered for idempotent appends, so
+    /// that a whole retried batch is absorbed rather than just its last
+    /// point. Values below 1 are treated as 1.
+    pub dedup_window: usize,
+}
+
+/// Access statistics for one sealed block, as returned by
+/// [`TimeSeries::block_usage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BlockUsage {
+    /// Number of tracked reads of the block.
+    pub reads: u64,
+    /// Logical time of the most recent read (`None` if never read). Ticks
+    /// come from a per-series clock that advances on every tracked read, so
+    /// a larger value means a more recent access.
+    pub last_access: Option<u64>,
+}
+
+/// Lock-free counters behind [`BlockUsage`], updated through `&self`.
+#[derive(Debug, Default)]
+struct UsageCounters {
+    reads: AtomicU64,
+    /// Tick of the last read; 0 means never read.
+    last_access: AtomicU64,
+}
+
+impl UsageCounters {
+    fn snapshot(&self) -> BlockUsage {
+        let last_access = self.last_access.load(Ordering::Relaxed);
+        BlockUsage {
+            reads: self.reads.load(Ordering::Relaxed),
+            last_access: (last_access != 0).then_some(last_access),
+        }
+    }
+}
+
+/// A single time series stored as a chain of sealed blocks plus one open
+/// encoder that receives new points.
+///
+/// # Example
+/// ```
+/// use gorilla::{AppendError, DataPoint, SeriesConfig, TimeSeries};
+///
+/// let mut series = TimeSeries::new(SeriesConfig {
+///     idempotent: true,
+///     ..Default::default()
+/// });
+/// series.append(DataPoint::new(1609459200, 1.0)).unwrap();
+/// // A retried write of the same point is absorbed.
+/// series.append(DataPoint::new(1609459200, 1.0)).unwrap();
+/// assert_eq!(series.len(), 1);
+/// // A different value for the same timestamp is a conflict.
+/// let err = series.append(DataPoint::new(1609459200, 2.0)).unwrap_err();
+/// assert!(matches!(err, AppendError::Conflict { .. }));
+/// ```
+#[derive(Debug)]
+pub struct TimeSeries {
+    config: SeriesConfig,
+    blocks: Vec<CompressedBlock>,
+    open: Encoder,
+    /// Most recently stored points, newest last, carried across `seal()`.
+    recent: VecDeque<DataPoint>,
+    /// Smallest and largest timestamp of each sealed block.
+    ranges: Vec<(u64, u64)>,
+    /// Read statistics, one entry per sealed block.
+    usage: Vec<UsageCounters>,
+    /// Logical clock for `BlockUsage::last_access`.
+    clock: AtomicU64,
+    /// Incremented whenever existing sealed data is rewritten.
+    generation: u64,
+    /// Completeness watermark stamped onto blocks as they are sealed.
+    complete_until: Option<u64>,
+}
+
+impl TimeSeries {
+    /// Creates an empty series.
+    pub fn new(config: SeriesConfig) -> Self {
+        Self {
+            open: Encoder::with_config(config.encoder.clone()),
+            config,
+            blocks: Vec::new(),
+            recent: VecDeque::new(),
+            ranges: Vec::new(),
+            usage: Vec::new(),
+            clock: AtomicU64::new(0),
+            generation: 0,
+            complete_until: None,
+        }
+    }
+
+    /// Appends a data point to the open block.
+    pub fn append(&mut self, dp: DataPoint) -> Result<(), AppendError> {
+        if self.config.idempotent {
+            let seen = self
+                .recent
+                .iter()
+                .rev()
+                .find(|p| p.timestamp == dp.timestamp);
+            if let Some(&existing) = seen {
+                if existing.value.to_bits() == dp.value.to_bits() {
+                    return Ok(());
+                }
+                return Err(AppendError::Conflict {
+                    existing,
+                    incoming: dp,
+                });
+            }
+        }
+
+        self.open.encode(dp)?;
+        if let Some(last) = self.open.last_point() {
+            self.remember(last);
+        }
+        Ok(())
+    }
+
+    /// Records `last` as the newest stored point. A point with the same
+    /// timestamp as the current newest one replaces it (the encoder dropped
+    /// or overwrote the incoming point).
+    fn remember(&mut self, last: DataPoint) {
+        if let Some(newest) = self.recent.back_mut() {
+            if newest.timestamp == last.timestamp {
+                *newest = last;
+                return;
+            }
+        }
+        self.recent.push_back(last);
+        while self.recent.len() > self.config.dedup_window.max(1) {
+            self.recent.pop_front();
+        }
+    }
+
+    /// Finishes the open block and moves it to the sealed list. Does nothing
+    /// if the open block is empty.
+    pub fn seal(&mut self) -> Result<(), BufferFull> {
+        let Some((first, last)) = self.open.time_range() else {
+            return Ok(());
+        };
+        self.open.finish()?;
+        let open = std::mem::replace(
+            &mut self.open,
+            Encoder::with_config(self.config.encoder.clone()),
+        );
+        let mut block = open.into_compressed();
+        block.complete_until = self.complete_until;
+        self.blocks.push(block);
+        self.ranges.push((first, last));
+        self.usage.push(UsageCounters::default());
+        Ok(())
+    }
+
+    /// Adds an already sealed block covering `start..=end` after the existing
+    /// sealed blocks, e.g. when restoring a series from disk. The series'
+    /// watermark advances to the block's if that is later.
+    pub fn push_block(&mut self, block: CompressedBlock, start: u64, end: u64) {
+        if let Some(complete_until) = block.complete_until {
+            self.set_complete_until(complete_until);
+        }
+        self.blocks.push(block);
+        self.ranges.push((start, end));
+        self.usage.push(UsageCounters::default());
+    }
+
+    /// Returns the sealed blocks, oldest first.
+    ///
+    /// Access through this slice is not tracked; use [`read_block`] for
+    /// reads that should count towards [`block_usage`].
+    ///
+    /// [`read_block`]: TimeSeries::read_block
+    /// [`block_usage`]: TimeSeries::block_usage
+    pub fn blocks(&self) -> &[CompressedBlock] {
+        &self.blocks
+    }
+
+    /// Returns the smallest and largest timestamp of the sealed block at
+    /// `index`.
+    pub fn block_time_range(&self, index: usize) -> Option<(u64, u64)> {
+        self.ranges.get(index).copied()
+    }
+
+    /// Returns the sealed block at `index` and records the access in its
+    /// usage statistics.
+    pub fn read_block(&self, index: usize) -> Option<&CompressedBlock> {
+        let block = self.blocks.get(index)?;
+        let tick = self.clock.fetch_add(1, Ordering::Relaxed) + 1;
+        let usage = &self.usage[index];
+        usage.reads.fetch_add(1, Ordering::Relaxed);
+        usage.last_access.fetch_max(tick, Ordering::Relaxed);
+        Some(block)
+    }
+
+    /// Returns the access statistics of every sealed block, oldest first.
+    pub fn block_usage(&self) -> Vec<BlockUsage> {
+        self.usage.iter().map(UsageCounters::snapshot).collect()
+    }
+
+    /// Returns the index of the least recently read sealed block. Blocks
+    /// that were never read come first, oldest block winning ties.
+    pub fn least_recently_used(&self) -> Option<usize> {
+        let usage = self.block_usage();
+        (0..usage.len()).min_by_key(|&i| usage[i].last_access)
+    }
+
+    /// Returns the index of the least frequently read sealed block, breaking
+    /// ties by recency and then by age.
+    pub fn least_frequently_used(&self) -> Option<usize> {
+        let usage = self.block_usage();
+        (0..usage.len()).min_by_key(|&i| (usage[i].reads, usage[i].last_access))
+    }
+
+    /// Removes and returns the sealed block at `index` along with its usage
+    /// statistics, e.g. to evict it to a colder tier.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of bounds.
+    pub fn remove_block(&mut self, index: usize) -> (CompressedBlock, BlockUsage) {
+        let usage = self.usage.remove(index).snapshot();
+        self.ranges.remove(index);
+        (self.blocks.remove(index), usage)
+    }
+
+    /// Replaces every stored point with a timestamp in `range` by
+    /// `new_points`, e.g. to apply a correction or restatement.
+    ///
+    /// The sealed blocks overlapping `range` are decoded, merged with
+    /// `new_points` and re-encoded into fresh blocks, which are swapped in
+    /// only once all of them were built: on error the series is unchanged.
+    /// If the range reaches the open block, it is sealed first. The
+    /// replacement blocks roll over at the encoder's byte limit, which is
+    /// treated as a target rather than a hard cap. A successful replacement
+    /// bumps [`generation`](TimeSeries::generation).
+    ///
+    /// `new_points` must lie within `range` in strictly increasing
+    /// timestamp order.
+    ///
+    /// ```
+    /// use gorilla::{DataPoint, Decoder, TimeSeries};
+    ///
+    /// let mut series = TimeSeries::default();
+    /// for t in 0..6 {
+    ///     series.append(DataPoint::new(t * 10, 1.0)).unwrap();
+    /// }
+    /// series.seal().unwrap();
+    /// series
+    ///     .replace_range(20..=30, &[DataPoint::new(25, 9.0)])
+    ///     .unwrap();
+    ///
+    /// let points = Decoder::decode(&series.blocks()[0]).unwrap();
+    /// let timestamps: Vec<_> = points.iter().map(|dp| dp.timestamp).collect();
+    /// assert_eq!(timestamps, [0, 10, 25, 40, 50]);
+    /// assert_eq!(series.generation(), 1);
+    /// ```
+    pub fn replace_range(
+        &mut self,
+        range: impl RangeBounds<u64>,
+        new_points: &[DataPoint],
+    ) -> Result<(), ReplaceError> {
+        let mut previous = None;
+        for dp in new_points {
+            if !range.contains(&dp.timestamp) {
+                return Err(ReplaceError::OutOfRange {
+                    timestamp: dp.timestamp,
+                });
+            }
+            if let Some(previous) = previous.filter(|&p| p >= dp.timestamp) {
+                return Err(ReplaceError::Unordered {
+                    previous,
+                    timestamp: dp.timestamp,
+                });
+            }
+            previous = Some(dp.timestamp);
+        }
+
+        let reaches_open = self
+            .open
+            .time_range()
+            .is_some_and(|(first, last)| overlaps(&range, first, last))
+            || new_points.last().is_some_and(|dp| {
+                self.open
+                    .time_range()
+                    .is_some_and(|(first, _)| dp.timestamp >= first)
+            });
+        // Sealing is only visible once the replacement succeeds.
+        let mut open = None;
+        if reaches_open {
+            let mut encoder = self.open.clone();
+            encoder.finish()?;
+            let (first, last) = encoder.time_range().unwrap();
+            open = Some((encoder.into_compressed(), first, last));
+        }
+
+        let ranges: Vec<_> = self
+            .ranges
+            .iter()
+            .copied()
+            .chain(open.as_ref().map(|&(_, first, last)| (first, last)))
+            .collect();
+        let start = ranges.partition_point(|&(_, end)| !reaches(&range, end));
+        let end = start
+            + ranges[start..]
+                .iter()
+                .take_while(|&&(first, last)| overlaps(&range, first, last))
+                .count();
+
+        let mut merged = Vec::new();
+        let mut replaced = new_points.iter().copied().peekable();
+        for index in start..end {
+            let block = match self.blocks.get(index) {
+                Some(block) => block,
+                None => &open.as_ref().unwrap().0,
+            };
+            for dp in Decoder::decode(block)? {
+                if range.contains(&dp.timestamp) {
+                    continue;
+                }
+                while let Some(new) = replaced.next_if(|new| new.timestamp < dp.timestamp) {
+                    merged.push(new);
+                }
+                merged.push(dp);
+            }
+        }
+        merged.extend(replaced);
+
+        let mut encoder_config = self.config.encoder.clone();
+        let max_bytes = encoder_config.max_bytes.take();
+        let mut built = Vec::new();
+        let mut chunked = ChunkedEncoder::new(
+            ChunkConfig {
+                encoder: encoder_config,
+                max_bytes,
+                ..Default::default()
+            },
+            |block| built.push(block),
+        );
+        for &dp in &merged {
+            chunked.encode(dp)?;
+        }
+        chunked.finish()?;
+        let complete_until = if start == end {
+            self.complete_until
+        } else {
+            merged_watermark((start..end).map(|index| match self.blocks.get(index) {
+                Some(block) => block.complete_until,
+                None => self.complete_until,
+            }))
+        };
+        for block in &mut built {
+            block.complete_until = complete_until;
+        }
+
+        if open.is_some() {
+            self.open = Encoder::with_config(self.config.encoder.clone());
+        }
+        let removed = end.min(self.blocks.len());
+        let mut offset = 0;
+        let new_ranges: Vec<_> = built
+            .iter()
+            .map(|block| {
+                let points = &merged[offset..offset + block.count as usize];
+                offset += points.len();
+                (points[0].timestamp, points[points.len() - 1].timestamp)
+            })
+            .collect();
+        self.blocks.splice(start..removed, built);
+        self.ranges.splice(start..removed, new_ranges.iter().copied());
+        self.usage.splice(
+            start..removed,
+            new_ranges.iter().map(|_| UsageCounters::default()),
+        );
+        if end == ranges.len() && self.open.count() == 0 && !merged.is_empty() {
+            // The rewritten blocks hold the newest points.
+            let window = self.config.dedup_window.max(1);
+            self.recent = merged[merged.len().saturating_sub(window)..]
+                .iter()
+                .copied()
+                .collect();
+        } else {
+            self.recent.retain(|dp| !range.contains(&dp.timestamp));
+        }
+        self.generation += 1;
+        Ok(())
+    }
+
+    /// Rewrites the sealed blocks chosen by `policy` and returns the number
+    /// of runs rewritten.
+    ///
+    /// Each run of adjacent blocks returned by
+    /// [`CompactionPolicy::select`] is decoded and re-encoded with the
+    /// policy's encoder configuration, rolling over at its target size. The
+    /// new blocks inherit the run's combined read statistics and its
+    /// earliest completeness watermark. All runs are
+    /// built before any is swapped in, so on error the series is unchanged.
+    /// A compaction that rewrites anything bumps
+    /// [`generation`](TimeSeries::generation). The open block is never
+    /// touched.
+    ///
+    /// # Panics
+    /// Panics if the policy returns runs that are out of bounds, unordered
+    /// or overlapping.
+    pub fn compact(&mut self, policy: &dyn CompactionPolicy) -> Result<usize, ReplaceError> {
+        let infos: Vec<_> = self
+            .blocks
+            .iter()
+            .zip(&self.ranges)
+            .map(|(block, &(start, end))| BlockInfo::new(block, start, end))
+            .collect();
+        let runs = policy.select(&infos);
+        assert!(
+            runs.iter().all(|run| run.start < run.end && run.end <= infos.len())
+                && runs.windows(2).all(|w| w[0].end <= w[1].start),
+            "compaction runs must be ascending, non-empty and in bounds"
+        );
+
+        let mut encoder = policy.encoder_config(&self.config.encoder);
+        let max_bytes = encoder.max_bytes.take().or(policy.target_bytes());
+        let mut rewritten = Vec::with_capacity(runs.len());
+        for run in &runs {
+            let mut built = Vec::new();
+            let mut chunked = ChunkedEncoder::new(
+                ChunkConfig {
+                    encoder: encoder.clone(),
+                    max_bytes,
+                    ..Default::default()
+                },
+                |block| built.push(block),
+            );
+            let mut bounds = Vec::new();
+            for block in &self.blocks[run.clone()] {
+                for result in Decoder::iter(block) {
+                    let dp = result?;
+                    chunked.encode(dp)?;
+                    bounds.push(dp.timestamp);
+                }
+            }
+            chunked.finish()?;
+            let complete_until =
+                merged_watermark(self.blocks[run.clone()].iter().map(|b| b.complete_until));
+            for block in &mut built {
+                block.complete_until = complete_until;
+            }
+            let mut offset = 0;
+            let ranges: Vec<_> = built
+                .iter()
+                .map(|block| {
+                    let first = bounds[offset];
+                    offset += block.count as usize;
+                    (first, bounds[offset - 1])
+                })
+                .collect();
+            rewritten.push((built, ranges));
+        }
+
+        for (run, (built, ranges)) in runs.iter().zip(rewritten).rev() {
+            let (reads, last_access) = self.usage[run.clone()]
+                .iter()
+                .map(UsageCounters::snapshot)
+                .fold((0, 0), |(reads, last), usage| {
+                    (reads + usage.reads, last.max(usage.last_access.unwrap_or(0)))
+                });
+            let usage = ranges.iter().map(|_| UsageCounters {
+                reads: AtomicU64::new(reads),
+                last_access: AtomicU64::new(last_access),
+            });
+            self.usage.splice(run.clone(), usage);
+            self.blocks.splice(run.clone(), built);
+            self.ranges.splice(run.clone(), ranges);
+        }
+        if !runs.is_empty() {
+            self.generation += 1;
+        }
+        Ok(runs.len())
+    }
+
+    /// Declares that every point with a timestamp up to and including
+    /// `timestamp` has arrived. Blocks sealed from now on carry the
+    /// watermark in [`CompressedBlock::complete_until`], so readers can tell
+    /// a gap that is final from data that is still in flight. The watermark
+    /// never moves backwards: an earlier `timestamp` is ignored.
+    ///
+    /// ```
+    /// use gorilla::{DataPoint, TimeSeries};
+    ///
+    /// let mut series = TimeSeries::default();
+    /// series.append(DataPoint::new(60, 1.0)).unwrap();
+    /// series.set_complete_until(120);
+    /// series.set_complete_until(90);
+    /// series.seal().unwrap();
+    /// assert_eq!(series.complete_until(), Some(120));
+    /// assert_eq!(series.blocks()[0].complete_until, Some(120));
+    /// ```
+    pub fn set_complete_until(&mut self, timestamp: u64) {
+        self.complete_until = Some(self.complete_until.map_or(timestamp, |w| w.max(timestamp)));
+    }
+
+    /// Returns the completeness watermark, or `None` if none was set.
+    pub fn complete_until(&self) -> Option<u64> {
+        self.complete_until
+    }
+
+    /// Returns the number of times sealed data was rewritten by
+    /// [`replace_range`](TimeSeries::replace_range) or
+    /// [`compact`](TimeSeries::compact). Readers that cache block indices or
+    /// decoded points compare generations to detect that their view is
+    /// stale.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the encoder of the open block.
+    pub fn open_encoder(&self) -> &Encoder {
+        &self.open
+    }
+
+    /// Returns the most recently stored point.
+    pub fn last_point(&self) -> Option<DataPoint> {
+        self.recent.back().copied()
+    }
+
+    /// Returns the total number of points in sealed and open blocks.
+    pub fn len(&self) -> u64 {
+        self.blocks.iter().map(|b| b.count).sum::<u64>() + self.open.count()
+    }
+
+    /// Returns `true` if the series holds no points.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an iterator over the points with timestamps in `start..=end`,
+    /// from the sealed blocks, oldest first, and then the open block.
+    ///
+    /// Blocks whose time range lies outside the query are skipped without
+    /// being decoded. Sealed blocks that are read count towards
+    /// [`block_usage`](TimeSeries::block_usage).
+    ///
+    /// ```
+    /// use gorilla::{DataPoint, TimeSeries};
+    ///
+    /// let mut series = TimeSeries::default();
+    /// for t in 0..10 {
+    ///     series.append(DataPoint::new(t * 60, t as f64)).unwrap();
+    ///     if t == 4 {
+    ///         series.seal().unwrap();
+    ///     }
+    /// }
+    /// let points: Vec<_> = series.query(180, 360).collect::<Result<_, _>>().unwrap();
+    /// let timestamps: Vec<_> = points.iter().map(|dp| dp.timestamp).collect();
+    /// assert_eq!(timestamps, [180, 240, 300, 360]);
+    /// ```
+    pub fn query(&self, start: u64, end: u64) -> SeriesQuery<'_> {
+        let range = start..=end;
+        let mut parts: Vec<_> = (0..self.blocks.len())
+            .filter(|&i| {
+                let (first, last) = self.ranges[i];
+                overlaps(&range, first, last)
+            })
+            .map(|i| Part::Sealed(self, i))
+            .collect();
+        if let Some((first, last)) = self.open.time_range() {
+            if overlaps(&range, first, last) {
+                parts.push(Part::Open(self));
+            }
+        }
+        SeriesQuery {
+            parts: parts.into_iter(),
+            current: Vec::new().into_iter(),
+            start,
+            end,
+            failed: false,
+        }
+    }
+}
+
+/// Where the next batch of points for a [`SeriesQuery`] comes from.
+enum Part<'a> {
+    Sealed(&'a TimeSeries, usize),
+    Open(&'a TimeSeries),
+}
+
+impl Part<'_> {
+    fn load(&self) -> Result<Vec<DataPoint>, DecodeError> {
+        match *self {
+            Part::Sealed(series, i) => Decoder::decode(series.read_block(i).unwrap()),
+            Part::Open(series) => Decoder::decode(&series.open.to_compressed()),
+        }
+    }
+}
+
+/// Iterator over the points of one series in a time range, returned by
+/// [`TimeSeries::query`] and [`SeriesMap::query`](crate::SeriesMap::query).
+///
+/// Blocks are decoded one at a time as the iterator advances. Iteration
+/// stops after the first error.
+pub struct SeriesQuery<'a> {
+    parts: std::vec::IntoIter<Part<'a>>,
+    current: std::vec::IntoIter<DataPoint>,
+    start: u64,
+    end: u64,
+    failed: bool,
+}
+
+impl SeriesQuery<'_> {
+    /// Returns an iterator that yields nothing, e.g. for an unknown series.
+    pub(crate) fn empty() -> Self {
+        Self {
+            parts: Vec::new().into_iter(),
+            current: Vec::new().into_iter(),
+            start: 0,
+            end: 0,
+            failed: false,
+        }
+    }
+}
+
+impl Iterator for SeriesQuery<'_> {
+    type Item = Result<DataPoint, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        loop {
+            if let Some(dp) = self.current.next() {
+                if (self.start..=self.end).contains(&dp.timestamp) {
+                    return Some(Ok(dp));
+                }
+                continue;
+            }
+            match self.parts.next()?.load() {
+                Ok(points) => self.current = points.into_iter(),
+                Err(err) => {
+                    self.failed = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+/// Returns `true` if `first..=last` overlaps `range`.
+fn overlaps(range: &impl RangeBounds<u64>, first: u64, last: u64) -> bool {
+    reaches(range, last) && !past(range, first)
+}
+
+/// Returns `true` if `ts` is at or past the start of `range`.
+fn reaches(range: &impl RangeBounds<u64>, ts: u64) -> bool {
+    match range.start_bound() {
+        Bound::Included(&s) => ts >= s,
+        Bound::Excluded(&s) => ts > s,
+        Bound::Unbounded => true,
+    }
+}
+
+/// Returns `true` if `ts` is past the end of `range`.
+fn past(range: &impl RangeBounds<u64>, ts: u64) -> bool {
+    match range.end_bound() {
+        Bound::Included(&e) => ts > e,
+        Bound::Excluded(&e) => ts >= e,
+        Bound::Unbounded => false,
+    }
+}
+
+impl Default for TimeSeries {
+    fn default() -> Self {
+        Self::new(SeriesConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+
+    fn idempotent() -> TimeSeries {
+        TimeSeries::new(SeriesConfig {
+            idempotent: true,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_idempotent_skips_exact_repeat() {
+        let mut series = idempotent();
+        series.append(DataPoint::new(100, 1.0)).unwrap();
+        series.append(DataPoint::new(160, 2.0)).unwrap();
+        series.append(DataPoint::new(160, 2.0)).unwrap();
+        assert_eq!(series.len(), 2);
+    }
+
+    #[test]
+    fn test_idempotent_conflict() {
+        let mut series = idempotent();
+        series.append(DataPoint::new(100, 1.0)).unwrap();
+        let err = series.append(DataPoint::new(100, 1.5)).unwrap_err();
+        assert_eq!(
+            err,
+            AppendError::Conflict {
+                existing: DataPoint::new(100, 1.0),
+                incoming: DataPoint::new(100, 1.5),
+            }
+        );
+        assert_eq!(series.len(), 1);
+    }
+
+    #[test]
+    fn test_idempotent_across_seal() {
+        let mut series = idempotent();
+        series.append(DataPoint::new(100, 1.0)).unwrap();
+        series.seal().unwrap();
+        series.append(DataPoint::new(100, 1.0)).unwrap();
+        assert!(series.append(DataPoint::new(100, 9.0)).is_err());
+        assert_eq!(series.len(), 1);
+        assert_eq!(series.open_encoder().count(), 0);
+    }
+
+    #[test]
+    fn test_dedup_window_absorbs_retried_batch() {
+        let mut series = TimeSeries::new(SeriesConfig {
+            idempotent: true,
+            dedup_window: 8,
+            ..Default::default()
+        });
+        let batch: Vec<DataPoint> = (0..5).map(|i| DataPoint::new(i * 10, i as f64)).collect();
+        for dp in &batch {
+            series.append(*dp).unwrap();
+        }
+        series.seal().unwrap();
+        for dp in &batch {
+            series.append(*dp).unwrap();
+        }
+        assert_eq!(series.len(), 5);
+        assert!(matches!(
+            series.append(DataPoint::new(20, 7.0)),
+            Err(AppendError::Conflict { .. })
+        ));
+    }
+
+    #[test]
+    fn test_dedup_window_forgets_old_points() {
+        let mut series = TimeSeries::new(SeriesConfig {
+            idempotent: true,
+            dedup_window: 2,
+            ..Default::default()
+        });
+        for i in 0..4 {
+            series.append(DataPoint::new(i * 10, i as f64)).unwrap();
+        }
+        // Timestamp 10 fell out of the window and reaches the encoder, which
+        // accepts it as an out-of-order point under the default policy.
+        series.append(DataPoint::new(10, 1.0)).unwrap();
+        assert_eq!(series.len(), 5);
+    }
+
+    #[test]
+    fn test_non_idempotent_passes_duplicates_through() {
+        let mut series = TimeSeries::default();
+        series.append(DataPoint::new(100, 1.0)).unwrap();
+        series.append(DataPoint::new(100, 1.0)).unwrap();
+        series.append(DataPoint::new(100, 2.0)).unwrap();
+        assert_eq!(series.len(), 3);
+    }
+
+    fn sealed_blocks(n: u64) -> TimeSeries {
+        let mut series = TimeSeries::default();
+        for i in 0..n {
+            series.append(DataPoint::new(i * 60, i as f64)).unwrap();
+            series.seal().unwrap();
+        }
+        series
+    }
+
+    #[test]
+    fn test_block_usage_tracks_reads() {
+        let series = sealed_blocks(3);
+        assert_eq!(series.block_usage(), vec![BlockUsage::default(); 3]);
+
+        series.read_block(1).unwrap();
+        series.read_block(2).unwrap();
+        series.read_block(1).unwrap();
+        assert!(series.read_block(3).is_none());
+
+        let usage = series.block_usage();
+        assert_eq!(usage[0], BlockUsage::default());
+        assert_eq!(
+            usage[1],
+            BlockUsage {
+                reads: 2,
+                last_access: Some(3)
+            }
+        );
+        assert_eq!(
+            usage[2],
+            BlockUsage {
+                reads: 1,
+                last_access: Some(2)
+            }
+        );
+    }
+
+    #[test]
+    fn test_eviction_candidates() {
+        let mut series = sealed_blocks(3);
+        assert_eq!(series.least_recently_used(), Some(0));
+        for index in [0, 1, 1, 2, 0] {
+            series.read_block(index).unwrap();
+        }
+        assert_eq!(series.least_recently_used(), Some(1));
+        assert_eq!(series.least_frequently_used(), Some(2));
+
+        let (block, usage) = series.remove_block(0);
+        assert_eq!(block.count, 1);
+        assert_eq!(usage.reads, 2);
+        assert_eq!(series.blocks().len(), 2);
+        assert_eq!(series.block_usage().len(), 2);
+        assert!(TimeSeries::default().least_recently_used().is_none());
+    }
+
+    #[test]
+    fn test_series_key_bytes_roundtrip() {
+        let key = String::from("cpu.user{host=a}");
+        assert_eq!(String::from_key_bytes(&key.to_key_bytes()), Some(key));
+        assert_eq!(u64::from_key_bytes(&42u64.to_key_bytes()), Some(42));
+        assert_eq!(u64::from_key_bytes(&[1, 2, 3]), None);
+    }
+
+    #[test]
+    fn test_query_prunes_blocks_outside_range() {
+        let mut series = TimeSeries::default();
+        for t in 0..30 {
+            series.append(DataPoint::new(t * 10, t as f64)).unwrap();
+            if t % 10 == 9 {
+                series.seal().unwrap();
+            }
+        }
+        series.append(DataPoint::new(300, 30.0)).unwrap();
+
+        let query = |start, end| -> Vec<u64> {
+            series
+                .query(start, end)
+                .map(|dp| dp.unwrap().timestamp)
+                .collect()
+        };
+        assert_eq!(query(85, 120), [90, 100, 110, 120]);
+        assert_eq!(query(290, u64::MAX), [290, 300]);
+        assert_eq!(query(0, u64::MAX).len(), 31);
+        assert!(query(301, 400).is_empty());
+
+        // Only the overlapping sealed blocks were read, once per query.
+        let reads: Vec<_> = series.block_usage().iter().map(|u| u.reads).collect();
+        assert_eq!(reads, [2, 2, 2]);
+    }
+
+    #[test]
+    fn test_query_finds_out_of_order_points() {
+        let mut series = TimeSeries::default();
+        for t in [100, 200, 50] {
+            series.append(DataPoint::new(t, 1.0)).unwrap();
+        }
+        series.seal().unwrap();
+        for t in [300, 40] {
+            series.append(DataPoint::new(t, 2.0)).unwrap();
+        }
+
+        assert_eq!(series.block_time_range(0), Some((50, 200)));
+        let timestamps: Vec<_> = series
+            .query(0, 60)
+            .map(|dp| dp.unwrap().timestamp)
+            .collect();
+        assert_eq!(timestamps, [50, 40]);
+    }
+
+    #[test]
+    fn test_query_stops_after_decode_error() {
+        let mut series = TimeSeries::default();
+        let mut corrupt = sealed_blocks(1).blocks()[0].clone();
+        corrupt.checksum = Some(0);
+        series.push_block(corrupt, 0, 0);
+        series.append(DataPoint::new(60, 1.0)).unwrap();
+
+        let mut query = series.query(0, 60);
+        assert!(query.next().unwrap().is_err());
+        assert!(query.next().is_none());
+    }
+
+    fn timestamps(series: &TimeSeries) -> Vec<u64> {
+        let mut out = Vec::new();
+        for (i, block) in series.blocks().iter().enumerate() {
+            let points = Decoder::decode(block).unwrap();
+            assert_eq!(
+                series.block_time_range(i),
+                Some((points[0].timestamp, points[points.len() - 1].timestamp))
+            );
+            out.extend(points.iter().map(|dp| dp.timestamp));
+        }
+        out
+    }
+
+    #[test]
+    fn test_replace_range_spans_blocks_and_open_block() {
+        let mut series = TimeSeries::default();
+        for t in 0..12u64 {
+            series.append(DataPoint::new(t * 10, t as f64)).unwrap();
+            if t % 4 == 3 {
+                series.seal().unwrap();
+            }
+        }
+        series.append(DataPoint::new(120, 12.0)).unwrap();
+        let before = series.blocks()[2].clone();
+
+        let fix = [DataPoint::new(35, -1.0), DataPoint::new(55, -2.0)];
+        series.replace_range(30..60, &fix).unwrap();
+        assert_eq!(series.blocks()[1].bytes, before.bytes);
+        assert_eq!(
+            timestamps(&series),
+            [0, 10, 20, 35, 55, 60, 70, 80, 90, 100, 110]
+        );
+        assert_eq!(series.generation(), 1);
+
+        series
+            .replace_range(100.., &[DataPoint::new(130, 13.0)])
+            .unwrap();
+        assert_eq!(series.open_encoder().count(), 0);
+        assert_eq!(
+            timestamps(&series),
+            [0, 10, 20, 35, 55, 60, 70, 80, 90, 130]
+        );
+        assert_eq!(series.last_point(), Some(DataPoint::new(130, 13.0)));
+        assert_eq!(series.blocks().len(), series.block_usage().len());
+    }
+
+    #[test]
+    fn test_replace_range_rejects_bad_points_without_changes() {
+        let mut series = TimeSeries::default();
+        for t in 0..4u64 {
+            series.append(DataPoint::new(t * 10, 1.0)).unwrap();
+        }
+        series.seal().unwrap();
+        series.append(DataPoint::new(40, 1.0)).unwrap();
+
+        let err = series
+            .replace_range(10..=20, &[DataPoint::new(25, 0.0)])
+            .unwrap_err();
+        assert_eq!(err, ReplaceError::OutOfRange { timestamp: 25 });
+        let unordered = [DataPoint::new(40, 0.0), DataPoint::new(35, 0.0)];
+        assert!(matches!(
+            series.replace_range(30..=40, &unordered),
+            Err(ReplaceError::Unordered { .. })
+        ));
+        assert_eq!(series.generation(), 0);
+        assert_eq!(series.open_encoder().count(), 1);
+        assert_eq!(timestamps(&series), [0, 10, 20, 30]);
+
+        series.replace_range(5..=15, &[]).unwrap();
+        assert_eq!(timestamps(&series), [0, 20, 30]);
+    }
+
+    #[test]
+    fn test_compact_merges_runs_and_usage() {
+        use crate::compaction::TimeWindowed;
+
+        let mut series = TimeSeries::default();
+        for t in 0..12u64 {
+            series.append(DataPoint::new(t * 30, t as f64)).unwrap();
+            if t % 2 == 1 {
+                series.seal().unwrap();
+            }
+        }
+        series.append(DataPoint::new(360, 12.0)).unwrap();
+        series.read_block(1);
+        series.read_block(2);
+        series.read_block(2);
+        let before = timestamps(&series);
+
+        let policy = TimeWindowed { window: 120 };
+        assert_eq!(series.compact(&policy).unwrap(), 3);
+        assert_eq!(series.blocks().len(), 3);
+        assert_eq!(timestamps(&series), before);
+        assert_eq!(series.block_time_range(1), Some((120, 210)));
+        assert_eq!(series.block_usage()[0].reads, 1);
+        assert_eq!(series.block_usage()[1].reads, 2);
+        assert_eq!(series.open_encoder().count(), 1);
+        assert_eq!(series.generation(), 1);
+
+        assert_eq!(series.compact(&policy).unwrap(), 0);
+        assert_eq!(series.generation(), 1);
+    }
+
+    #[test]
+    fn test_watermark_propagates_through_rewrites() {
+        use crate::compaction::TimeWindowed;
+
+        let mut series = TimeSeries::default();
+        for t in 0..4u64 {
+            series.append(DataPoint::new(t * 30, 1.0)).unwrap();
+            series.set_complete_until(t * 30 + 10);
+            series.seal().unwrap();
+        }
+        let watermarks = |series: &TimeSeries| -> Vec<_> {
+            series.blocks().iter().map(|b| b.complete_until).collect()
+        };
+        assert_eq!(
+            watermarks(&series),
+            [Some(10), Some(40), Some(70), Some(100)]
+        );
+
+        series
+            .replace_range(60..=90, &[DataPoint::new(75, 2.0)])
+            .unwrap();
+        assert_eq!(watermarks(&series), [Some(10), Some(40), Some(70)]);
+        series.compact(&TimeWindowed { window: 1000 }).unwrap();
+        assert_eq!(watermarks(&series), [Some(10)]);
+        assert_eq!(series.complete_until(), Some(100));
+    }
+
+    #[test]
+    fn test_compact_upgrades_codec() {
+        struct AddChecksums;
+
+        impl CompactionPolicy for AddChecksums {
+            fn select(&self, blocks: &[BlockInfo]) -> Vec<std::ops::Range<usize>> {
+                (0..blocks.len())
+                    .filter(|&i| !blocks[i].checksum)
+                    .map(|i| i..i + 1)
+                    .collect()
+            }
+
+            fn encoder_config(&self, current: &EncoderConfig) -> EncoderConfig {
+                EncoderConfig {
+                    checksum: true,
+                    ..current.clone()
+                }
+            }
+        }
+
+        let mut series = TimeSeries::default();
+        for t in 0..6u64 {
+            series.append(DataPoint::new(t, 1.0)).unwrap();
+            if t % 3 == 2 {
+                series.seal().unwrap();
+            }
+        }
+        assert_eq!(series.compact(&AddChecksums).unwrap(), 2);
+        assert!(series.blocks().iter().all(|b| b.checksum.is_some()));
+        assert_eq!(timestamps(&series), [0, 1, 2, 3, 4, 5]);
+        assert_eq!(series.compact(&AddChecksums).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_seal_produces_decodable_blocks() {
+        let mut series = TimeSeries::default();
+        for i in 0..10 {
+            series.append(DataPoint::new(i * 60, i as f64)).unwrap();
+            if i % 4 == 3 {
+                series.seal().unwrap();
+            }
+        }
+        series.seal().unwrap();
+        series.seal().unwrap();
+        assert_eq!(series.blocks().len(), 3);
+        assert_eq!(series.block_time_range(1), Some((240, 420)));
+        let points: Vec<DataPoint> = series
+            .blocks()
+            .iter()
+            .flat_map(|b| Decoder::decode(b).unwrap())
+            .collect();
+        assert_eq!(points.len(), 10);
+        assert_eq!(points[9], DataPoint::new(540, 9.0));
+    }
+}
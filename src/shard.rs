@@ -0,0 +1,491 @@
+//! Assignment of series to shards for clustered deployments.
+//!
+//! [`Sharding`] maps series keys to shard ids with consistent hashing, so
+//! growing a cluster from `n` to `n + 1` shards moves only about `1/(n + 1)`
+//! of the series. [`ShardedMap`] keeps one [`SeriesMap`] per shard, and
+//! [`export`] / [`import`] move a shard between nodes as a segment file.
+//! [`stream_out`] / [`stream_in`] move it over a socket instead, including
+//! the open blocks, so it can keep accepting points on its new node.
+
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+use crate::checksum::{crc32c, hash64};
+use crate::decoder::Decoder;
+use crate::encoder::{CompressedBlock, DataPoint};
+use crate::map::SeriesMap;
+use crate::segment::{Segment, SegmentWriter};
+use crate::series::{AppendError, SeriesConfig, SeriesKey};
+
+/// Points placed on the hash ring per shard.
+const VIRTUAL_NODES: u32 = 64;
+
+/// Consistent-hash assignment of series keys to shard ids `0..shards`.
+///
+/// The hash is computed from [`SeriesKey::to_key_bytes`] with a fixed
+/// function, so every node running this crate agrees on the assignment.
+#[derive(Debug, Clone)]
+pub struct Sharding {
+    shards: u32,
+    /// `(ring position, shard id)`, sorted by position.
+    ring: Vec<(u64, u32)>,
+}
+
+impl Sharding {
+    /// Creates an assignment over `shards` shards.
+    ///
+    /// # Panics
+    /// Panics if `shards` is 0.
+    pub fn new(shards: u32) -> Self {
+        assert!(shards > 0, "sharding needs at least one shard");
+        let mut ring: Vec<_> = (0..shards)
+            .flat_map(|shard| {
+                (0..VIRTUAL_NODES).map(move |vnode| {
+                    let mut seed = [0u8; 8];
+                    seed[..4].copy_from_slice(&shard.to_le_bytes());
+                    seed[4..].copy_from_slice(&vnode.to_le_bytes());
+                    (hash64(&seed), shard)
+                })
+            })
+            .collect();
+        ring.sort_unstable();
+        Self { shards, ring }
+    }
+
+    /// Returns the number of shards.
+    pub fn shards(&self) -> u32 {
+        self.shards
+    }
+
+    /// Returns the shard id that owns `key`.
+    pub fn shard_for<K: SeriesKey>(&self, key: &K) -> u32 {
+        self.shard_for_bytes(&key.to_key_bytes())
+    }
+
+    /// Returns the shard id that owns the series with encoded key `key`.
+    pub fn shard_for_bytes(&self, key: &[u8]) -> u32 {
+        let h = hash64(key);
+        let i = self.ring.partition_point(|&(pos, _)| pos < h);
+        self.ring[i % self.ring.len()].1
+    }
+
+    /// Splits `map` into one map per shard, indexed by shard id.
+    pub fn partition<K: SeriesKey>(&self, mut map: SeriesMap<K>) -> Vec<SeriesMap<K>> {
+        let mut parts: Vec<_> = (0..self.shards)
+            .map(|_| SeriesMap::new(map.config().clone()))
+            .collect();
+        for (key, series) in map.drain() {
+            let shard = self.shard_for(&key) as usize;
+            parts[shard].insert(key, series);
+        }
+        parts
+    }
+}
+
+/// One [`SeriesMap`] per shard, with appends routed by a [`Sharding`].
+#[derive(Debug)]
+pub struct ShardedMap<K: SeriesKey> {
+    sharding: Sharding,
+    shards: Vec<SeriesMap<K>>,
+}
+
+impl<K: SeriesKey> ShardedMap<K> {
+    /// Creates empty shards whose series use `config`.
+    pub fn new(sharding: Sharding, config: SeriesConfig) -> Self {
+        let shards = (0..sharding.shards())
+            .map(|_| SeriesMap::new(config.clone()))
+            .collect();
+        Self { sharding, shards }
+    }
+
+    /// Appends a data point to the series for `key` in the shard owning it.
+    pub fn append(&mut self, key: K, dp: DataPoint) -> Result<(), AppendError> {
+        let shard = self.sharding.shard_for(&key) as usize;
+        self.shards[shard].append(key, dp)
+    }
+
+    /// Returns the assignment used for routing.
+    pub fn sharding(&self) -> &Sharding {
+        &self.sharding
+    }
+
+    /// Returns the map of shard `id`.
+    ///
+    /// # Panics
+    /// Panics if `id` is not a valid shard id.
+    pub fn shard(&self, id: u32) -> &SeriesMap<K> {
+        &self.shards[id as usize]
+    }
+
+    /// Replaces the contents of shard `id` with `map`, e.g. after importing
+    /// it from another node, and returns the previous contents.
+    ///
+    /// # Panics
+    /// Panics if `id` is not a valid shard id.
+    pub fn replace_shard(&mut self, id: u32, map: SeriesMap<K>) -> SeriesMap<K> {
+        std::mem::replace(&mut self.shards[id as usize], map)
+    }
+
+    /// Removes shard `id`'s series, e.g. once it has been exported to the
+    /// node taking it over, leaving the shard empty.
+    ///
+    /// # Panics
+    /// Panics if `id` is not a valid shard id.
+    pub fn take_shard(&mut self, id: u32) -> SeriesMap<K> {
+        let config = self.shards[id as usize].config().clone();
+        self.replace_shard(id, SeriesMap::new(config))
+    }
+}
+
+/// Writes every block of `map` to a segment file at `path`, returning the
+/// number of blocks written. Open blocks are written as finished copies and
+/// stay open in `map`.
+pub fn export<K: SeriesKey>(map: &SeriesMap<K>, path: impl Into<PathBuf>) -> io::Result<usize> {
+    let mut writer = SegmentWriter::new(path);
+    let mut blocks = 0;
+    for (key, series) in map.iter() {
+        let key_bytes = key.to_key_bytes();
+        for (i, block) in series.blocks().iter().enumerate() {
+            let (start, end) = series.block_time_range(i).unwrap();
+            writer.add(&key_bytes, start, end, block);
+            blocks += 1;
+        }
+        let open = series.open_encoder();
+        if let Some((start, end)) = open.time_range() {
+            writer.add(&key_bytes, start, end, &open.to_compressed());
+            blocks += 1;
+        }
+    }
+    writer.finish()?;
+    Ok(blocks)
+}
+
+/// Reads a shard written by [`export`] into a new map whose series use
+/// `config`. Every imported block is sealed; new points start a fresh open
+/// block.
+pub fn import<K: SeriesKey>(
+    path: impl Into<PathBuf>,
+    config: SeriesConfig,
+) -> io::Result<SeriesMap<K>> {
+    let segment = Segment::open(path)?;
+    let mut map = SeriesMap::new(config);
+    for entry in segment.entries() {
+        let key = K::from_key_bytes(&entry.key).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "invalid series key in shard")
+        })?;
+        let block = segment.read_block(entry)?;
+        map.get_or_insert(key)
+            .push_block(block, entry.start, entry.end);
+    }
+    Ok(map)
+}
+
+const STREAM_MAGIC: &[u8; 4] = b"GSHS";
+const STREAM_VERSION: u8 = 1;
+
+const FRAME_SEALED: u8 = 1;
+const FRAME_OPEN: u8 = 2;
+const FRAME_END: u8 = 3;
+
+/// Writes a whole shard to `writer` as a sequence of checksummed frames:
+/// every sealed block, then each series' open block, then an end frame
+/// carrying the number of block frames. Frames are written as they are
+/// produced, so a socket can be used directly. Returns the number of block
+/// frames written.
+///
+/// Each frame is `[kind u8][payload length u32][CRC32C of payload u32]`
+/// followed by the payload; block payloads are `[key length u32][key]
+/// [start u64][end u64]` and the block's [`CompressedBlock::to_bytes`] form.
+pub fn stream_out<K: SeriesKey, W: Write>(shard: &SeriesMap<K>, mut writer: W) -> io::Result<u64> {
+    writer.write_all(STREAM_MAGIC)?;
+    writer.write_all(&[STREAM_VERSION])?;
+    let mut frames = 0u64;
+    for (key, series) in shard.iter() {
+        let key_bytes = key.to_key_bytes();
+        for (i, block) in series.blocks().iter().enumerate() {
+            let (start, end) = series.block_time_range(i).unwrap();
+            let payload = block_payload(&key_bytes, start, end, block);
+            write_frame(&mut writer, FRAME_SEALED, &payload)?;
+            frames += 1;
+        }
+        let open = series.open_encoder();
+        if let Some((start, end)) = open.time_range() {
+            let payload = block_payload(&key_bytes, start, end, &open.to_compressed());
+            write_frame(&mut writer, FRAME_OPEN, &payload)?;
+            frames += 1;
+        }
+    }
+    write_frame(&mut writer, FRAME_END, &frames.to_le_bytes())?;
+    writer.flush()?;
+    Ok(frames)
+}
+
+/// Reads a shard written by [`stream_out`] into a new map whose series use
+/// `config`. Sealed blocks are restored as sealed blocks; the points of each
+/// open block are re-encoded into the series' open encoder, which
+/// reproduces its state exactly when `config` matches the sender's.
+///
+/// A checksum mismatch, a stream that ends before its end frame, or a frame
+/// count that disagrees with the end frame is reported as
+/// [`io::ErrorKind::InvalidData`] or [`io::ErrorKind::UnexpectedEof`].
+pub fn stream_in<K: SeriesKey, R: Read>(
+    mut reader: R,
+    config: SeriesConfig,
+) -> io::Result<SeriesMap<K>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let mut header = [0u8; 5];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != STREAM_MAGIC {
+        return Err(invalid("not a shard stream".into()));
+    }
+    if header[4] != STREAM_VERSION {
+        return Err(invalid(format!(
+            "unsupported shard stream version {}",
+            header[4]
+        )));
+    }
+
+    let mut map = SeriesMap::new(config);
+    let mut frames = 0u64;
+    loop {
+        let mut head = [0u8; 9];
+        reader.read_exact(&mut head)?;
+        let kind = head[0];
+        let len = u32::from_le_bytes(head[1..5].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(head[5..9].try_into().unwrap());
+        let mut payload = vec![0; len];
+        reader.read_exact(&mut payload)?;
+        if crc32c(&payload) != crc {
+            return Err(invalid(format!("checksum mismatch in frame {frames}")));
+        }
+
+        match kind {
+            FRAME_END => {
+                let expected = payload
+                    .try_into()
+                    .map(u64::from_le_bytes)
+                    .map_err(|_| invalid("malformed end frame".into()))?;
+                if expected != frames {
+                    return Err(invalid(format!(
+                        "stream carried {frames} block frames, end frame says {expected}"
+                    )));
+                }
+                return Ok(map);
+            }
+            FRAME_SEALED | FRAME_OPEN => {
+                let (key, start, end, block) = parse_block_payload(&payload)
+                    .ok_or_else(|| invalid(format!("malformed frame {frames}")))?;
+                let key = K::from_key_bytes(key)
+                    .ok_or_else(|| invalid("invalid series key in shard stream".into()))?;
+                let series = map.get_or_insert(key);
+                if kind == FRAME_SEALED {
+                    series.push_block(block, start, end);
+                } else {
+                    let points = Decoder::decode(&block).map_err(|err| invalid(err.to_string()))?;
+                    for dp in points {
+                        series.append(dp).map_err(|err| invalid(err.to_string()))?;
+                    }
+                }
+                frames += 1;
+            }
+            other => return Err(invalid(format!("unknown frame kind {other}"))),
+        }
+    }
+}
+
+fn block_payload(key: &[u8], start: u64, end: u64, block: &CompressedBlock) -> Vec<u8> {
+    let block = block.to_bytes();
+    let mut payload = Vec::with_capacity(4 + key.len() + 16 + block.len());
+    payload.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    payload.extend_from_slice(key);
+    payload.extend_from_slice(&start.to_le_bytes());
+    payload.extend_from_slice(&end.to_le_bytes());
+    payload.extend_from_slice(&block);
+    payload
+}
+
+fn parse_block_payload(payload: &[u8]) -> Option<(&[u8], u64, u64, CompressedBlock)> {
+    let key_len = u32::from_le_bytes(payload.get(..4)?.try_into().ok()?) as usize;
+    let key = payload.get(4..4 + key_len)?;
+    let rest = payload.get(4 + key_len..)?;
+    let start = u64::from_le_bytes(rest.get(..8)?.try_into().ok()?);
+    let end = u64::from_le_bytes(rest.get(8..16)?.try_into().ok()?);
+    let block = CompressedBlock::from_bytes(&rest[16..]).ok()?;
+    Some((key, start, end, block))
+}
+
+fn write_frame<W: Write>(writer: &mut W, kind: u8, payload: &[u8]) -> io::Result<()> {
+    writer.write_all(&[kind])?;
+    writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+    writer.write_all(&crc32c(payload).to_le_bytes())?;
+    writer.write_all(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment::tests::temp_dir;
+
+    #[test]
+    fn test_assignment_is_stable_and_in_range() {
+        let a = Sharding::new(8);
+        let b = Sharding::new(8);
+        for i in 0..1000u64 {
+            let shard = a.shard_for(&i);
+            assert!(shard < 8);
+            assert_eq!(shard, b.shard_for(&i));
+        }
+        assert_eq!(
+            a.shard_for(&"cpu.total".to_string()),
+            a.shard_for_bytes(b"cpu.total")
+        );
+        // Nodes on different releases must agree, so the hash is pinned.
+        assert_eq!(hash64(b"cpu.total"), 0xACFF_1E48_34FB_588E);
+    }
+
+    #[test]
+    fn test_keys_spread_across_shards() {
+        let sharding = Sharding::new(4);
+        let mut counts = [0; 4];
+        for i in 0..10_000u64 {
+            counts[sharding.shard_for(&i) as usize] += 1;
+        }
+        assert!(counts.iter().all(|&c| c > 1500), "{counts:?}");
+    }
+
+    #[test]
+    fn test_growing_moves_few_keys_and_only_to_new_shard() {
+        let before = Sharding::new(4);
+        let after = Sharding::new(5);
+        let mut moved = 0;
+        for i in 0..10_000u64 {
+            let (old, new) = (before.shard_for(&i), after.shard_for(&i));
+            if old != new {
+                assert_eq!(new, 4);
+                moved += 1;
+            }
+        }
+        assert!(moved < 3500, "moved {moved} of 10000 keys");
+    }
+
+    #[test]
+    fn test_partition_and_sharded_map_agree() {
+        let sharding = Sharding::new(3);
+        let mut map = SeriesMap::default();
+        let mut sharded = ShardedMap::new(sharding.clone(), SeriesConfig::default());
+        for i in 0..30u64 {
+            map.append(i, DataPoint::new(100, i as f64)).unwrap();
+            sharded.append(i, DataPoint::new(100, i as f64)).unwrap();
+        }
+        let parts = sharding.partition(map);
+        for (id, part) in parts.iter().enumerate() {
+            let mut keys: Vec<_> = part.keys().copied().collect();
+            let mut expected: Vec<_> = sharded.shard(id as u32).keys().copied().collect();
+            keys.sort();
+            expected.sort();
+            assert_eq!(keys, expected);
+        }
+        assert_eq!(parts.iter().map(SeriesMap::len).sum::<usize>(), 30);
+    }
+
+    #[test]
+    fn test_export_import_moves_shard() {
+        let dir = temp_dir("shard-export");
+        let path = dir.join("shard-1.seg");
+        let mut sharded = ShardedMap::new(Sharding::new(2), SeriesConfig::default());
+        for i in 0..20u64 {
+            for t in 0..5 {
+                sharded.append(i, DataPoint::new(t * 60, t as f64)).unwrap();
+            }
+        }
+        let shard = sharded.take_shard(1);
+        assert!(sharded.shard(1).is_empty());
+        assert_eq!(export(&shard, &path).unwrap(), shard.len());
+
+        let imported: SeriesMap<u64> = import(&path, SeriesConfig::default()).unwrap();
+        assert_eq!(imported.len(), shard.len());
+        for (key, series) in imported.iter() {
+            assert_eq!(series.blocks().len(), 1);
+            assert_eq!(series.block_time_range(0), Some((0, 240)));
+            assert_eq!(series.len(), shard.get(key).unwrap().len());
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn streamed_shard() -> SeriesMap<String> {
+        let mut map = SeriesMap::default();
+        for s in 0..4 {
+            for t in 0..10 {
+                map.append(format!("s{s}"), DataPoint::new(t * 60, (s * t) as f64))
+                    .unwrap();
+            }
+            map.get_mut(&format!("s{s}")).unwrap().seal().unwrap();
+            for t in 10..13 {
+                map.append(format!("s{s}"), DataPoint::new(t * 60, t as f64))
+                    .unwrap();
+            }
+        }
+        map
+    }
+
+    #[test]
+    fn test_stream_roundtrip_keeps_open_blocks_open() {
+        let shard = streamed_shard();
+        let mut wire = Vec::new();
+        assert_eq!(stream_out(&shard, &mut wire).unwrap(), 8);
+
+        let mut received: SeriesMap<String> =
+            stream_in(wire.as_slice(), SeriesConfig::default()).unwrap();
+        assert_eq!(received.len(), 4);
+        for (key, series) in shard.iter() {
+            let got = received.get(key).unwrap();
+            assert_eq!(got.blocks().len(), 1);
+            assert_eq!(got.blocks()[0].bytes, series.blocks()[0].bytes);
+            assert_eq!(
+                got.open_encoder().buffer().as_bytes(),
+                series.open_encoder().buffer().as_bytes()
+            );
+        }
+        // The receiving node keeps appending to the transferred open block.
+        received
+            .append("s1".into(), DataPoint::new(13 * 60, 0.0))
+            .unwrap();
+        assert_eq!(
+            received
+                .get(&"s1".to_string())
+                .unwrap()
+                .open_encoder()
+                .count(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_stream_detects_corruption_and_truncation() {
+        let mut wire = Vec::new();
+        stream_out(&streamed_shard(), &mut wire).unwrap();
+
+        let truncated = &wire[..wire.len() - 4];
+        let err = stream_in::<String, _>(truncated, SeriesConfig::default()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut corrupt = wire.clone();
+        corrupt[40] ^= 0x01;
+        let err = stream_in::<String, _>(corrupt.as_slice(), SeriesConfig::default()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_stream_over_socket() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let sender = std::thread::spawn(move || {
+            let socket = std::net::TcpStream::connect(addr).unwrap();
+            stream_out(&streamed_shard(), io::BufWriter::new(socket)).unwrap()
+        });
+        let (socket, _) = listener.accept().unwrap();
+        let received: SeriesMap<String> = stream_in(socket, SeriesConfig::default()).unwrap();
+        assert_eq!(sender.join().unwrap(), 8);
+        assert_eq!(received.get(&"s3".to_string()).unwrap().len(), 13);
+    }
+}
@@ -0,0 +1,274 @@
+//! Series with signed `i64` timestamps, such as pre-1970 historical data or
+//! relative and monotonic clocks.
+//!
+//! Timestamps are stored in an ordinary [`CompressedBlock`] with their sign
+//! bit flipped, see [`to_stored`]. That mapping keeps their order and the
+//! distances between them, so the block compresses exactly as well as the
+//! same series shifted to unsigned timestamps, and ordering policies, block
+//! statistics and seeking work unchanged. The block has to be read back
+//! through [`SignedDecoder`]; decoded as a plain block, its timestamps come
+//! out offset by `2^63`.
+
+use crate::bitbuffer::BufferFull;
+use crate::decoder::{DecodeError, Decoder, DecoderIter};
+use crate::encoder::{CompressedBlock, DataPoint, EncodeError, Encoder, EncoderConfig};
+
+/// A single data point with a signed timestamp.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DataPoint64 {
+    /// Timestamp, e.g. in seconds relative to the Unix epoch.
+    pub timestamp: i64,
+    /// The value.
+    pub value: f64,
+}
+
+impl DataPoint64 {
+    /// Creates a new data point.
+    pub fn new(timestamp: i64, value: f64) -> Self {
+        Self { timestamp, value }
+    }
+}
+
+impl From<DataPoint64> for DataPoint {
+    /// Returns the point as stored, with its timestamp mapped by
+    /// [`to_stored`].
+    fn from(dp: DataPoint64) -> Self {
+        DataPoint::new(to_stored(dp.timestamp), dp.value)
+    }
+}
+
+impl From<DataPoint> for DataPoint64 {
+    /// Returns a stored point with its timestamp mapped back by
+    /// [`from_stored`].
+    fn from(dp: DataPoint) -> Self {
+        DataPoint64::new(from_stored(dp.timestamp), dp.value)
+    }
+}
+
+/// Maps a signed timestamp to the unsigned one it is stored as, keeping
+/// the order: `i64::MIN` becomes 0 and 0 becomes `2^63`.
+pub fn to_stored(timestamp: i64) -> u64 {
+    (timestamp as u64) ^ (1 << 63)
+}
+
+/// Inverse of [`to_stored`].
+pub fn from_stored(timestamp: u64) -> i64 {
+    (timestamp ^ (1 << 63)) as i64
+}
+
+/// A compressed block of points with signed timestamps.
+#[derive(Debug, Clone)]
+pub struct SignedBlock {
+    block: CompressedBlock,
+}
+
+impl SignedBlock {
+    /// Wraps a block written by a [`SignedEncoder`], e.g. after reading it
+    /// back with [`CompressedBlock::from_bytes`].
+    pub fn from_block(block: CompressedBlock) -> Self {
+        Self { block }
+    }
+
+    /// Returns the underlying block, whose timestamps are in stored form.
+    pub fn block(&self) -> &CompressedBlock {
+        &self.block
+    }
+
+    /// Consumes the wrapper and returns the underlying block.
+    pub fn into_block(self) -> CompressedBlock {
+        self.block
+    }
+
+    /// Returns the number of points.
+    pub fn count(&self) -> u64 {
+        self.block.count()
+    }
+
+    /// Returns the smallest timestamp in the block, if recorded.
+    pub fn start_timestamp(&self) -> Option<i64> {
+        self.block.start_timestamp().map(from_stored)
+    }
+
+    /// Returns the largest timestamp in the block, if recorded.
+    pub fn end_timestamp(&self) -> Option<i64> {
+        self.block.end_timestamp().map(from_stored)
+    }
+}
+
+/// Compressor for points with signed timestamps, on top of [`Encoder`].
+///
+/// Timestamps in [`EncodeError::OutOfOrder`] and
+/// [`EncodeError::DuplicateTimestamp`] are in stored form; map them back
+/// with [`from_stored`].
+#[derive(Debug, Clone, Default)]
+pub struct SignedEncoder {
+    inner: Encoder,
+}
+
+impl SignedEncoder {
+    /// Creates an encoder with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an encoder with the given configuration. The interval of
+    /// any [`Priors`](crate::Priors) applies as is.
+    pub fn with_config(config: EncoderConfig) -> Self {
+        Self {
+            inner: Encoder::with_config(config),
+        }
+    }
+
+    /// Encodes a data point, as [`Encoder::encode`].
+    pub fn encode(&mut self, dp: DataPoint64) -> Result<(), EncodeError> {
+        self.inner.encode(dp.into())
+    }
+
+    /// Returns the number of data points encoded so far.
+    pub fn count(&self) -> u64 {
+        self.inner.count()
+    }
+
+    /// Returns the most recently encoded data point, if any.
+    pub fn last_point(&self) -> Option<DataPoint64> {
+        self.inner.last_point().map(DataPoint64::from)
+    }
+
+    /// Finishes the stream, as [`Encoder::finish`].
+    pub fn finish(&mut self) -> Result<(), BufferFull> {
+        self.inner.finish()
+    }
+
+    /// Returns the compressed block.
+    pub fn into_block(self) -> SignedBlock {
+        SignedBlock::from_block(self.inner.into_compressed())
+    }
+}
+
+/// Decompressor for [`SignedBlock`]s.
+pub struct SignedDecoder;
+
+impl SignedDecoder {
+    /// Decodes all points, as [`Decoder::decode`].
+    ///
+    /// ```
+    /// use gorilla::{DataPoint64, SignedDecoder, SignedEncoder};
+    ///
+    /// let mut encoder = SignedEncoder::new();
+    /// for t in [-86_400, -60, 0, 60] {
+    ///     encoder.encode(DataPoint64::new(t, 1.0)).unwrap();
+    /// }
+    /// encoder.finish().unwrap();
+    /// let block = encoder.into_block();
+    ///
+    /// assert_eq!(block.start_timestamp(), Some(-86_400));
+    /// let points = SignedDecoder::decode(&block).unwrap();
+    /// assert_eq!(points[1], DataPoint64::new(-60, 1.0));
+    /// ```
+    pub fn decode(block: &SignedBlock) -> Result<Vec<DataPoint64>, DecodeError> {
+        let points = Decoder::decode(&block.block)?;
+        Ok(points.into_iter().map(DataPoint64::from).collect())
+    }
+
+    /// Returns an iterator that lazily decodes the points of `block`.
+    pub fn iter(block: &SignedBlock) -> SignedIter<'_> {
+        SignedIter {
+            inner: Decoder::iter(&block.block),
+        }
+    }
+
+    /// Returns an iterator over the points of `block` with timestamps at or
+    /// after `timestamp`, as [`Decoder::iter_from`].
+    pub fn iter_from(block: &SignedBlock, timestamp: i64) -> SignedIter<'_> {
+        SignedIter {
+            inner: Decoder::iter_from(&block.block, to_stored(timestamp)),
+        }
+    }
+}
+
+/// Iterator returned by [`SignedDecoder::iter`].
+pub struct SignedIter<'a> {
+    inner: DecoderIter<'a>,
+}
+
+impl Iterator for SignedIter<'_> {
+    type Item = Result<DataPoint64, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.inner.next()?.map(DataPoint64::from))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::OutOfOrderPolicy;
+
+    #[test]
+    fn test_signed_timestamps_roundtrip() {
+        let points: Vec<_> = (-500..500i64)
+            .map(|i| DataPoint64::new(i * 60 - 7, (i % 13) as f64))
+            .chain([
+                DataPoint64::new(i64::MAX - 1, 1.0),
+                DataPoint64::new(i64::MAX, 2.0),
+            ])
+            .collect();
+        let mut encoder = SignedEncoder::new();
+        for &dp in &points {
+            encoder.encode(dp).unwrap();
+        }
+        assert_eq!(encoder.last_point(), points.last().copied());
+        encoder.finish().unwrap();
+        let block = encoder.into_block();
+        assert_eq!(SignedDecoder::decode(&block).unwrap(), points);
+        assert_eq!(block.start_timestamp(), Some(-30_007));
+        assert_eq!(block.end_timestamp(), Some(i64::MAX));
+
+        let from: Vec<_> = SignedDecoder::iter_from(&block, -7)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(from, points[500..]);
+
+        // Same size as the series shifted to unsigned timestamps.
+        let mut shifted = Encoder::new();
+        for dp in &points[..1000] {
+            let timestamp = (dp.timestamp + 40_000) as u64;
+            shifted.encode(DataPoint::new(timestamp, dp.value)).unwrap();
+        }
+        shifted.finish().unwrap();
+        let mut head = SignedEncoder::new();
+        for &dp in &points[..1000] {
+            head.encode(dp).unwrap();
+        }
+        head.finish().unwrap();
+        let head = head.into_block();
+        assert_eq!(
+            head.block().total_bits(),
+            shifted.into_compressed().total_bits()
+        );
+    }
+
+    #[test]
+    fn test_signed_order_spans_zero() {
+        let mut encoder = SignedEncoder::with_config(EncoderConfig {
+            on_out_of_order: OutOfOrderPolicy::Reject,
+            ..Default::default()
+        });
+        encoder.encode(DataPoint64::new(-10, 1.0)).unwrap();
+        encoder.encode(DataPoint64::new(5, 1.0)).unwrap();
+        let err = encoder.encode(DataPoint64::new(-3, 1.0)).unwrap_err();
+        assert_eq!(
+            err,
+            EncodeError::OutOfOrder {
+                previous: to_stored(5),
+                timestamp: to_stored(-3),
+            }
+        );
+        assert_eq!(from_stored(to_stored(i64::MIN)), i64::MIN);
+        assert!(to_stored(-1) < to_stored(0));
+    }
+}
@@ -0,0 +1,616 @@
+//! Snapshots of an open encoder, so that a partially filled block survives a
+//! restart without being lost or re-encoded.
+//!
+//! [`Encoder::snapshot`] captures everything about an encoder except the
+//! bits written so far, which stay in its buffer. Persist both, e.g. the
+//! state's [`to_bytes`](EncoderState::to_bytes) next to the buffer's bytes,
+//! and hand them back to [`Encoder::restore`]. The restored encoder
+//! continues the stream exactly as the original would have, bit for bit.
+//!
+//! ```
+//! use gorilla::bitbuffer::BitBuffer;
+//! use gorilla::{DataPoint, Encoder, EncoderConfig, EncoderState};
+//!
+//! let mut encoder = Encoder::new();
+//! for t in 0..100 {
+//!     encoder.encode(DataPoint::new(t * 60, 21.5)).unwrap();
+//! }
+//! // Checkpoint: both parts would be written to disk.
+//! let state = encoder.snapshot().to_bytes();
+//! let bits = encoder.buffer().as_bytes().to_vec();
+//! let len_bits = encoder.buffer().len_bits();
+//! drop(encoder);
+//!
+//! // After the restart.
+//! let state = EncoderState::from_bytes(&state, EncoderConfig::default()).unwrap();
+//! let mut encoder = Encoder::restore(state, BitBuffer::from_raw(bits, len_bits)).unwrap();
+//! encoder.encode(DataPoint::new(6000, 22.0)).unwrap();
+//! assert_eq!(encoder.count(), 101);
+//! ```
+//!
+//! [`Encoder::snapshot`]: crate::Encoder::snapshot
+//! [`Encoder::restore`]: crate::Encoder::restore
+
+use crate::codec::{DeltaState, TimestampScheme, ValueScheme, Values};
+use crate::decoder::DecodeError;
+use crate::encoder::{BlockStats, Checkpoint, DataPoint, EncoderConfig, Priors, Rollback};
+use crate::lossy::QuantizeSpec;
+
+/// Version byte written by [`EncoderState::to_bytes`].
+pub const STATE_FORMAT_VERSION: u8 = 2;
+
+const FLAG_FINISHED: u8 = 1;
+const FLAG_STATS: u8 = 2;
+const FLAG_CHECKSUM: u8 = 4;
+const FLAG_PRIORS: u8 = 8;
+const FLAG_ROLLBACK: u8 = 16;
+const FLAG_EPOCH: u8 = 32;
+const FLAG_HELD: u8 = 64;
+const KNOWN_FLAGS: u8 = FLAG_FINISHED
+    | FLAG_STATS
+    | FLAG_CHECKSUM
+    | FLAG_PRIORS
+    | FLAG_ROLLBACK
+    | FLAG_EPOCH
+    | FLAG_HELD;
+
+/// Error returned by [`Encoder::restore`](crate::Encoder::restore).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestoreError {
+    /// The buffer holds a different number of bits than the encoder had
+    /// written when the snapshot was taken.
+    LengthMismatch {
+        /// Bits recorded in the snapshot.
+        expected: usize,
+        /// Bits in the supplied buffer.
+        actual: usize,
+    },
+}
+
+impl std::fmt::Display for RestoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RestoreError::LengthMismatch { expected, actual } => write!(
+                f,
+                "snapshot was taken at {expected} bits but the buffer holds {actual}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RestoreError {}
+
+/// State of an [`Encoder`](crate::Encoder) apart from its buffer, returned
+/// by [`Encoder::snapshot`](crate::Encoder::snapshot).
+#[derive(Debug, Clone)]
+pub struct EncoderState {
+    pub(crate) config: EncoderConfig,
+    pub(crate) len_bits: usize,
+    pub(crate) count: u64,
+    pub(crate) first_timestamp: u64,
+    pub(crate) stats: Option<BlockStats>,
+    pub(crate) prev_timestamp: u64,
+    pub(crate) deltas: DeltaState,
+    pub(crate) prev_value_bits: u64,
+    pub(crate) values: Values,
+    pub(crate) finished: bool,
+    pub(crate) rollback: Option<Rollback>,
+    pub(crate) checksum: Option<u32>,
+    pub(crate) index: Vec<Checkpoint>,
+    pub(crate) gaps: Vec<u64>,
+    pub(crate) held: Option<DataPoint>,
+}
+
+impl EncoderState {
+    /// Returns the number of bits the encoder had written, which the buffer
+    /// passed to [`Encoder::restore`](crate::Encoder::restore) must hold.
+    pub fn len_bits(&self) -> usize {
+        self.len_bits
+    }
+
+    /// Returns the number of points encoded before the snapshot.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Serializes the state. The configuration is not stored, apart from
+    /// the stream's [`Priors`], codecs, [`epoch`](EncoderConfig::epoch),
+    /// [`quantizer`](EncoderConfig::quantizer) and
+    /// [`count_terminated`](EncoderConfig::count_terminated); everything else comes from the
+    /// config passed to [`from_bytes`](EncoderState::from_bytes).
+    ///
+    /// | field          | encoding |
+    /// |----------------|----------|
+    /// | version        | 1 byte ([`STATE_FORMAT_VERSION`]) |
+    /// | flags          | 1 byte: finished, statistics, checksum, priors, rollback, epoch, held point |
+    /// | codecs         | 1 byte, as in [`CompressedBlock::to_bytes`](crate::CompressedBlock::to_bytes), including the quantizer bit |
+    /// | position       | 5 × 8 bytes (LE): bits written, point count, first and previous timestamp, previous value bits |
+    /// | codec state    | timestamp codec, then value codec |
+    /// | statistics     | 5 × 8 bytes (LE), only if flagged, as in [`CompressedBlock::to_bytes`](crate::CompressedBlock::to_bytes) |
+    /// | checksum       | 4 bytes (LE), only if flagged |
+    /// | priors         | 2 × 8 bytes (LE), only if flagged |
+    /// | epoch          | 8 bytes (LE), only if flagged |
+    /// | quantizer      | 1 + 8 bytes, only if the codecs byte flags it: kind and error bound (LE), as in [`CompressedBlock::to_bytes`](crate::CompressedBlock::to_bytes) |
+    /// | held point     | 2 × 8 bytes (LE), only if flagged: timestamp and value bits of the point dropped by the [`dead_band`](EncoderConfig::dead_band) that `finish` stores |
+    /// | index          | checkpoint count (4 bytes LE), then the checkpoints as in a block |
+    /// | gaps           | gap count (4 bytes LE), then 8 bytes (LE) each |
+    /// | rollback       | only if flagged: the state before the most recent point, kept under [`DuplicatePolicy::KeepLast`](crate::DuplicatePolicy::KeepLast) |
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(128);
+        let mut flags = 0;
+        if self.finished {
+            flags |= FLAG_FINISHED;
+        }
+        if self.stats.is_some() {
+            flags |= FLAG_STATS;
+        }
+        if self.checksum.is_some() {
+            flags |= FLAG_CHECKSUM;
+        }
+        if self.config.priors.is_some() {
+            flags |= FLAG_PRIORS;
+        }
+        if self.rollback.is_some() {
+            flags |= FLAG_ROLLBACK;
+        }
+        if self.config.epoch.is_some() {
+            flags |= FLAG_EPOCH;
+        }
+        if self.held.is_some() {
+            flags |= FLAG_HELD;
+        }
+        out.push(STATE_FORMAT_VERSION);
+        out.push(flags);
+        out.push(
+            (self.config.count_terminated as u8) << 7
+                | self.config.timestamp_codec.id() << 4
+                | (self.config.quantizer.is_some() as u8) << 2
+                | self.config.value_codec.id(),
+        );
+        for word in [
+            self.len_bits as u64,
+            self.count,
+            self.first_timestamp,
+            self.prev_timestamp,
+            self.prev_value_bits,
+        ] {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        self.deltas.write_state(&mut out);
+        self.values.write_state(&mut out);
+        if let Some(stats) = self.stats {
+            write_stats(&mut out, stats);
+        }
+        if let Some(checksum) = self.checksum {
+            out.extend_from_slice(&checksum.to_le_bytes());
+        }
+        if let Some(priors) = self.config.priors {
+            out.extend_from_slice(&priors.interval.to_le_bytes());
+            out.extend_from_slice(&priors.value.to_bits().to_le_bytes());
+        }
+        if let Some(epoch) = self.config.epoch {
+            out.extend_from_slice(&epoch.to_le_bytes());
+        }
+        if let Some(quantizer) = self.config.quantizer {
+            out.push(quantizer.id());
+            out.extend_from_slice(&quantizer.param().to_bits().to_le_bytes());
+        }
+        if let Some(held) = self.held {
+            out.extend_from_slice(&held.timestamp.to_le_bytes());
+            out.extend_from_slice(&held.value.to_bits().to_le_bytes());
+        }
+        out.extend_from_slice(&(self.index.len() as u32).to_le_bytes());
+        for cp in &self.index {
+            for word in [
+                cp.point_index,
+                cp.bit_offset,
+                cp.timestamp,
+                cp.delta as u64,
+                cp.value_bits,
+            ] {
+                out.extend_from_slice(&word.to_le_bytes());
+            }
+            out.push(cp.leading_zeros);
+            out.push(cp.trailing_zeros);
+        }
+        out.extend_from_slice(&(self.gaps.len() as u32).to_le_bytes());
+        for gap in &self.gaps {
+            out.extend_from_slice(&gap.to_le_bytes());
+        }
+        if let Some(rollback) = &self.rollback {
+            rollback.write_state(&mut out);
+        }
+        out
+    }
+
+    /// Parses a state produced by [`to_bytes`](EncoderState::to_bytes).
+    /// `config` applies to the restored encoder, except that the stored
+    /// [`Priors`], codecs, [`epoch`](EncoderConfig::epoch),
+    /// [`quantizer`](EncoderConfig::quantizer) and
+    /// [`count_terminated`](EncoderConfig::count_terminated) replace those of
+    /// `config`, as in [`Encoder::resume`](crate::Encoder::resume).
+    pub fn from_bytes(bytes: &[u8], config: EncoderConfig) -> Result<Self, DecodeError> {
+        let mut reader = StateReader::new(bytes);
+        let version = reader.u8()?;
+        if version != STATE_FORMAT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let flags = reader.u8()?;
+        if flags & !KNOWN_FLAGS != 0 {
+            return Err(DecodeError::MalformedHeader("unknown state flags"));
+        }
+        let codecs = reader.u8()?;
+        let timestamp_codec = TimestampScheme::from_id(codecs >> 4 & 0x07)
+            .ok_or(DecodeError::MalformedHeader("unknown timestamp codec"))?;
+        let value_codec = ValueScheme::from_id(codecs & 0x03)
+            .ok_or(DecodeError::MalformedHeader("unknown value codec"))?;
+        let count_terminated = codecs & 0x80 != 0;
+        let len_bits = usize::try_from(reader.u64()?)
+            .map_err(|_| DecodeError::MalformedHeader("total bits out of range"))?;
+        let count = reader.u64()?;
+        let first_timestamp = reader.u64()?;
+        let prev_timestamp = reader.u64()?;
+        let prev_value_bits = reader.u64()?;
+        let deltas = DeltaState::read_state(&mut reader, len_bits)?;
+        let values = Values::read_state(&mut reader, value_codec)?;
+        let stats = if flags & FLAG_STATS != 0 {
+            Some(read_stats(&mut reader)?)
+        } else {
+            None
+        };
+        let checksum = if flags & FLAG_CHECKSUM != 0 {
+            Some(reader.u32()?)
+        } else {
+            None
+        };
+        let priors = if flags & FLAG_PRIORS != 0 {
+            Some(Priors {
+                interval: reader.u64()?,
+                value: f64::from_bits(reader.u64()?),
+            })
+        } else {
+            None
+        };
+        let epoch = if flags & FLAG_EPOCH != 0 {
+            Some(reader.u64()?)
+        } else {
+            None
+        };
+        let quantizer = if codecs & 0x04 != 0 {
+            let id = reader.u8()?;
+            let param = f64::from_bits(reader.u64()?);
+            Some(
+                QuantizeSpec::from_parts(id, param)
+                    .ok_or(DecodeError::MalformedHeader("unknown quantizer"))?,
+            )
+        } else {
+            None
+        };
+        let held = if flags & FLAG_HELD != 0 {
+            let timestamp = reader.u64()?;
+            Some(DataPoint::new(timestamp, f64::from_bits(reader.u64()?)))
+        } else {
+            None
+        };
+        let mut index = Vec::new();
+        for _ in 0..reader.u32()? {
+            let cp = Checkpoint {
+                point_index: reader.u64()?,
+                bit_offset: reader.u64()?,
+                timestamp: reader.u64()?,
+                delta: reader.u64()? as i64,
+                value_bits: reader.u64()?,
+                leading_zeros: reader.u8()?,
+                trailing_zeros: reader.u8()?,
+            };
+            if cp.point_index >= count || cp.bit_offset > len_bits as u64 {
+                return Err(DecodeError::MalformedHeader("checkpoint beyond stream"));
+            }
+            index.push(cp);
+        }
+        let mut gaps: Vec<u64> = Vec::new();
+        for _ in 0..reader.u32()? {
+            let gap = reader.u64()?;
+            if gap >= count || gaps.last().is_some_and(|&last| gap <= last) {
+                return Err(DecodeError::MalformedHeader("gaps out of order"));
+            }
+            gaps.push(gap);
+        }
+        let rollback = if flags & FLAG_ROLLBACK != 0 {
+            let rollback = Rollback::read_state(&mut reader, value_codec, len_bits)?;
+            if rollback.count >= count
+                || rollback.index_len > index.len()
+                || rollback.gaps_len > gaps.len()
+            {
+                return Err(DecodeError::MalformedHeader("rollback beyond stream"));
+            }
+            Some(rollback)
+        } else {
+            None
+        };
+        if !reader.is_empty() {
+            return Err(DecodeError::MalformedHeader("trailing bytes after state"));
+        }
+        Ok(EncoderState {
+            config: EncoderConfig {
+                priors,
+                timestamp_codec,
+                value_codec,
+                count_terminated,
+                epoch,
+                quantizer,
+                ..config
+            },
+            len_bits,
+            count,
+            first_timestamp,
+            stats,
+            prev_timestamp,
+            deltas,
+            prev_value_bits,
+            values,
+            finished: flags & FLAG_FINISHED != 0,
+            rollback,
+            checksum,
+            index,
+            gaps,
+            held,
+        })
+    }
+}
+
+pub(crate) fn write_stats(out: &mut Vec<u8>, stats: BlockStats) {
+    for word in [
+        stats.start_timestamp,
+        stats.end_timestamp,
+        stats.min_value.to_bits(),
+        stats.max_value.to_bits(),
+        stats.sum.to_bits(),
+    ] {
+        out.extend_from_slice(&word.to_le_bytes());
+    }
+}
+
+pub(crate) fn read_stats(reader: &mut StateReader<'_>) -> Result<BlockStats, DecodeError> {
+    Ok(BlockStats {
+        start_timestamp: reader.u64()?,
+        end_timestamp: reader.u64()?,
+        min_value: f64::from_bits(reader.u64()?),
+        max_value: f64::from_bits(reader.u64()?),
+        sum: f64::from_bits(reader.u64()?),
+    })
+}
+
+/// Little-endian reader over a serialized [`EncoderState`].
+pub(crate) struct StateReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let slice = self
+            .bytes
+            .get(self.pos..self.pos + N)
+            .ok_or(DecodeError::UnexpectedEnd)?;
+        self.pos += N;
+        Ok(slice.try_into().unwrap())
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, DecodeError> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, DecodeError> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos == self.bytes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitbuffer::BitBuffer;
+    use crate::encoder::{DataPoint, DeadBand, DuplicatePolicy, Encoder};
+
+    fn point(i: u64) -> DataPoint {
+        DataPoint::new(1_000 + i * 60, ((i * 7) % 11) as f64 * 0.25)
+    }
+
+    /// Encodes `points`, taking a snapshot through bytes after `split` of
+    /// them, and returns the serialized block.
+    fn encode_with_restart(config: &EncoderConfig, points: &[DataPoint], split: usize) -> Vec<u8> {
+        let mut encoder = Encoder::with_config(config.clone());
+        for (i, &dp) in points[..split].iter().enumerate() {
+            if i % 9 == 4 {
+                encoder.encode_gap(dp.timestamp).unwrap();
+            } else {
+                encoder.encode(dp).unwrap();
+            }
+        }
+        let state = encoder.snapshot().to_bytes();
+        let buf = encoder.into_buffer();
+        let (bytes, len_bits) = (buf.as_bytes().to_vec(), buf.len_bits());
+
+        let state = EncoderState::from_bytes(&state, config.clone()).unwrap();
+        assert_eq!(state.count(), split as u64);
+        let mut encoder = Encoder::restore(state, BitBuffer::from_raw(bytes, len_bits)).unwrap();
+        for (i, &dp) in points.iter().enumerate().skip(split) {
+            if i % 9 == 4 {
+                encoder.encode_gap(dp.timestamp).unwrap();
+            } else {
+                encoder.encode(dp).unwrap();
+            }
+        }
+        encoder.finish().unwrap();
+        encoder.into_compressed().to_bytes()
+    }
+
+    #[test]
+    fn test_restored_encoder_continues_stream() {
+        let points: Vec<_> = (0..300).map(point).collect();
+        let configs = [
+            EncoderConfig {
+                checksum: true,
+                checkpoint_interval: Some(32),
+                priors: Some(Priors {
+                    interval: 60,
+                    value: 0.5,
+                }),
+                ..Default::default()
+            },
+            EncoderConfig {
+                value_codec: ValueScheme::Chimp,
+                ..Default::default()
+            },
+            EncoderConfig {
+                timestamp_codec: TimestampScheme::RunLength,
+                ..Default::default()
+            },
+            EncoderConfig {
+                timestamp_codec: TimestampScheme::Delta,
+                value_codec: ValueScheme::Raw,
+                count_terminated: true,
+                ..Default::default()
+            },
+            EncoderConfig {
+                epoch: Some(0),
+                ..Default::default()
+            },
+        ];
+        for config in &configs {
+            let expected = encode_with_restart(config, &points, points.len());
+            // Splits before the first point, inside the first 128 values and
+            // in the middle of a run.
+            for split in [0, 1, 2, 100, 250] {
+                assert_eq!(
+                    encode_with_restart(config, &points, split),
+                    expected,
+                    "{config:?} split at {split}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_restored_encoder_replaces_last_point() {
+        for value_codec in [ValueScheme::Gorilla, ValueScheme::Chimp] {
+            let config = EncoderConfig {
+                on_duplicate: DuplicatePolicy::KeepLast,
+                value_codec,
+                ..Default::default()
+            };
+            let mut original = Encoder::with_config(config.clone());
+            for i in 0..200 {
+                original.encode(point(i)).unwrap();
+            }
+            let state = EncoderState::from_bytes(&original.snapshot().to_bytes(), config).unwrap();
+            let mut restored = Encoder::restore(state, original.buffer().clone()).unwrap();
+
+            for encoder in [&mut original, &mut restored] {
+                encoder
+                    .encode(DataPoint::new(point(199).timestamp, 99.0))
+                    .unwrap();
+                encoder.encode(point(200)).unwrap();
+                encoder.finish().unwrap();
+            }
+            assert_eq!(
+                restored.into_compressed().to_bytes(),
+                original.into_compressed().to_bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn test_restored_encoder_keeps_dead_band_point() {
+        let config = EncoderConfig {
+            dead_band: Some(DeadBand {
+                threshold: 2.0,
+                max_interval: 300,
+            }),
+            ..Default::default()
+        };
+        let mut original = Encoder::with_config(config.clone());
+        for i in 0..3 {
+            original.encode(point(i)).unwrap();
+        }
+        // The later points are in the dead band; `finish` stores the last.
+        assert_eq!(original.count(), 1);
+        let state = EncoderState::from_bytes(&original.snapshot().to_bytes(), config).unwrap();
+        let mut restored = Encoder::restore(state, original.buffer().clone()).unwrap();
+        for encoder in [&mut original, &mut restored] {
+            encoder.finish().unwrap();
+            assert_eq!(encoder.count(), 2);
+        }
+        assert_eq!(
+            restored.into_compressed().to_bytes(),
+            original.into_compressed().to_bytes()
+        );
+    }
+
+    #[test]
+    fn test_restored_encoder_keeps_quantizer() {
+        let mut original = Encoder::with_quantizer(QuantizeSpec::AbsError(0.25));
+        for i in 0..100 {
+            original.encode(point(i)).unwrap();
+        }
+        // The quantizer comes from the state, not the config.
+        let state = original.snapshot().to_bytes();
+        let state = EncoderState::from_bytes(&state, EncoderConfig::default()).unwrap();
+        let mut restored = Encoder::restore(state, original.buffer().clone()).unwrap();
+        for encoder in [&mut original, &mut restored] {
+            for i in 100..200 {
+                encoder.encode(point(i)).unwrap();
+            }
+            encoder.finish().unwrap();
+        }
+        let block = restored.into_compressed();
+        assert_eq!(block.quantizer, Some(QuantizeSpec::AbsError(0.25)));
+        assert_eq!(block.to_bytes(), original.into_compressed().to_bytes());
+    }
+
+    #[test]
+    fn test_restore_rejects_mismatched_input() {
+        let mut encoder = Encoder::new();
+        encoder.encode(point(0)).unwrap();
+        encoder.encode(point(1)).unwrap();
+        let state = encoder.snapshot();
+        let err = Encoder::restore(state.clone(), BitBuffer::new()).unwrap_err();
+        assert_eq!(
+            err,
+            RestoreError::LengthMismatch {
+                expected: state.len_bits(),
+                actual: 0,
+            }
+        );
+
+        let bytes = state.to_bytes();
+        let parse = |bytes: &[u8]| EncoderState::from_bytes(bytes, EncoderConfig::default());
+        assert!(parse(&bytes).is_ok());
+        assert_eq!(
+            parse(&bytes[..bytes.len() - 1]).unwrap_err(),
+            DecodeError::UnexpectedEnd
+        );
+        let mut future = bytes.clone();
+        future[0] = STATE_FORMAT_VERSION + 1;
+        assert_eq!(
+            parse(&future).unwrap_err(),
+            DecodeError::UnsupportedVersion(STATE_FORMAT_VERSION + 1)
+        );
+    }
+}
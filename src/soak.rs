@@ -0,0 +1,377 @@
+//! Long-running soak harness for a [`TimeSeries`].
+//!
+//! [`run`] repeats encode → seal → rotate → compact → decode cycles until a
+//! wall-clock budget is spent, checking after every cycle that point counts
+//! add up, generations never move backwards, every retained point decodes
+//! back to what was appended and the stored bytes per point stay flat once
+//! retention kicks in. CI nightly jobs run it with the defaults; users point
+//! it at their own [`SeriesConfig`] and [`CompactionPolicy`] to validate an
+//! integration before it meets production traffic.
+
+use std::time::{Duration, Instant};
+
+use crate::bitbuffer::BufferFull;
+use crate::compaction::CompactionPolicy;
+use crate::decoder::{DecodeError, Decoder};
+use crate::encoder::DataPoint;
+use crate::series::{AppendError, ReplaceError, SeriesConfig, TimeSeries};
+
+/// Configuration for [`run`].
+#[derive(Debug, Clone)]
+pub struct SoakConfig {
+    /// Wall-clock budget. The cycle in flight when it runs out completes,
+    /// so at least one cycle always runs.
+    pub duration: Duration,
+    /// Stops after this many cycles even if time is left.
+    pub max_cycles: Option<u64>,
+    /// Configuration of the series under test.
+    pub series: SeriesConfig,
+    /// Points appended before each seal.
+    pub points_per_block: u64,
+    /// Oldest sealed blocks are dropped while the series would still hold at
+    /// least this many points without them.
+    pub retained_points: u64,
+    /// Timestamp of the first point.
+    pub start: u64,
+    /// Distance between consecutive timestamps.
+    pub interval: u64,
+    /// Value of the point with the given index.
+    pub values: fn(u64) -> f64,
+    /// Factor by which stored bytes per point may exceed the level measured
+    /// when retention first dropped a block.
+    pub growth_tolerance: f64,
+}
+
+impl Default for SoakConfig {
+    fn default() -> Self {
+        Self {
+            duration: Duration::from_secs(60),
+            max_cycles: None,
+            series: SeriesConfig::default(),
+            points_per_block: 1_000,
+            retained_points: 100_000,
+            start: 1_609_459_200,
+            interval: 60,
+            values: sensor,
+            growth_tolerance: 1.25,
+        }
+    }
+}
+
+/// Default value generator: a slow sine with low-order noise.
+fn sensor(index: u64) -> f64 {
+    20.0 + (index as f64 * 0.01).sin() * 5.0 + (index * 7919 % 100) as f64 * 0.01
+}
+
+/// Summary of a completed [`run`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SoakReport {
+    /// Cycles completed.
+    pub cycles: u64,
+    /// Points appended.
+    pub points_appended: u64,
+    /// Points dropped with rotated-out blocks.
+    pub points_dropped: u64,
+    /// Block runs rewritten by compaction.
+    pub compactions: u64,
+    /// Largest number of stored bytes seen after a cycle.
+    pub peak_bytes: usize,
+    /// Stored bytes per point when retention first dropped a block, or 0 if
+    /// it never did.
+    pub baseline_bytes_per_point: f64,
+    /// Wall-clock time spent.
+    pub elapsed: Duration,
+}
+
+/// Error returned by [`run`]: either an operation on the series failed or an
+/// invariant was violated. Invariant variants carry the 1-based cycle in
+/// which the violation was detected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SoakError {
+    /// Appending a point failed.
+    Append(AppendError),
+    /// Sealing the open block failed.
+    Seal(BufferFull),
+    /// Compacting the sealed blocks failed.
+    Compact(ReplaceError),
+    /// A sealed block failed to decode.
+    Decode(DecodeError),
+    /// The series holds a different number of points than were appended
+    /// and not dropped.
+    CountMismatch {
+        cycle: u64,
+        expected: u64,
+        actual: u64,
+    },
+    /// The series' generation decreased, or stayed put across a compaction
+    /// that rewrote blocks.
+    Generation { cycle: u64, before: u64, after: u64 },
+    /// A decoded point differs from the appended one.
+    PointMismatch {
+        cycle: u64,
+        expected: DataPoint,
+        actual: DataPoint,
+    },
+    /// Stored bytes per point grew beyond the tolerated factor of the
+    /// baseline.
+    MemoryGrowth {
+        cycle: u64,
+        baseline: f64,
+        current: f64,
+    },
+}
+
+impl std::fmt::Display for SoakError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SoakError::Append(err) => write!(f, "append failed: {err}"),
+            SoakError::Seal(err) => write!(f, "seal failed: {err}"),
+            SoakError::Compact(err) => write!(f, "compaction failed: {err}"),
+            SoakError::Decode(err) => write!(f, "decode failed: {err}"),
+            SoakError::CountMismatch {
+                cycle,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "cycle {cycle}: series holds {actual} points, expected {expected}"
+            ),
+            SoakError::Generation {
+                cycle,
+                before,
+                after,
+            } => write!(f, "cycle {cycle}: generation went from {before} to {after}"),
+            SoakError::PointMismatch {
+                cycle,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "cycle {cycle}: decoded ({}, {}), expected ({}, {})",
+                actual.timestamp, actual.value, expected.timestamp, expected.value
+            ),
+            SoakError::MemoryGrowth {
+                cycle,
+                baseline,
+                current,
+            } => write!(
+                f,
+                "cycle {cycle}: {current:.3} bytes per point, baseline {baseline:.3}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SoakError {}
+
+impl From<AppendError> for SoakError {
+    fn from(err: AppendError) -> Self {
+        SoakError::Append(err)
+    }
+}
+
+impl From<BufferFull> for SoakError {
+    fn from(err: BufferFull) -> Self {
+        SoakError::Seal(err)
+    }
+}
+
+impl From<ReplaceError> for SoakError {
+    fn from(err: ReplaceError) -> Self {
+        SoakError::Compact(err)
+    }
+}
+
+impl From<DecodeError> for SoakError {
+    fn from(err: DecodeError) -> Self {
+        SoakError::Decode(err)
+    }
+}
+
+/// Returns the bytes held by `series`: the compressed streams of its sealed
+/// blocks plus the open block's buffer.
+pub fn stored_bytes(series: &TimeSeries) -> usize {
+    let sealed: usize = series.blocks().iter().map(|b| b.bytes().len()).sum();
+    sealed + series.open_encoder().len_bytes()
+}
+
+/// Runs soak cycles against a fresh series until the configured duration or
+/// cycle count is reached, stopping at the first failure.
+///
+/// ```
+/// use std::time::Duration;
+/// use gorilla::soak::{self, SoakConfig};
+/// use gorilla::SizeTiered;
+///
+/// let report = soak::run(
+///     &SoakConfig {
+///         duration: Duration::from_secs(5),
+///         max_cycles: Some(20),
+///         points_per_block: 100,
+///         retained_points: 500,
+///         ..Default::default()
+///     },
+///     &SizeTiered::default(),
+/// )
+/// .unwrap();
+/// assert_eq!(report.points_appended, 2_000);
+/// ```
+pub fn run(config: &SoakConfig, policy: &dyn CompactionPolicy) -> Result<SoakReport, SoakError> {
+    let started = Instant::now();
+    let mut series = TimeSeries::new(config.series.clone());
+    let precision = config.series.encoder.precision;
+    let quantizer = config.series.encoder.quantizer;
+    let expected_point = |index: u64| {
+        let value = (config.values)(index);
+        let value = quantizer.map_or(value, |q| q.apply(value));
+        DataPoint::new(
+            config.start + index * config.interval,
+            precision.map_or(value, |p| p.apply(value)),
+        )
+    };
+    let mut report = SoakReport::default();
+
+    loop {
+        let cycle = report.cycles + 1;
+        for _ in 0..config.points_per_block {
+            let dp = DataPoint::new(
+                config.start + report.points_appended * config.interval,
+                (config.values)(report.points_appended),
+            );
+            series.append(dp)?;
+            report.points_appended += 1;
+        }
+        series.seal()?;
+
+        while series.blocks().len() > 1
+            && series.len() - series.blocks()[0].count() >= config.retained_points
+        {
+            let (block, _) = series.remove_block(0);
+            report.points_dropped += block.count();
+        }
+
+        let before = series.generation();
+        let compacted = series.compact(policy)? as u64;
+        report.compactions += compacted;
+        let after = series.generation();
+        if after < before || (compacted > 0 && after == before) {
+            return Err(SoakError::Generation {
+                cycle,
+                before,
+                after,
+            });
+        }
+
+        let expected = report.points_appended - report.points_dropped;
+        if series.len() != expected {
+            return Err(SoakError::CountMismatch {
+                cycle,
+                expected,
+                actual: series.len(),
+            });
+        }
+        let mut index = report.points_dropped;
+        for block in series.blocks() {
+            for result in Decoder::iter(block) {
+                let actual = result?;
+                let expected = expected_point(index);
+                if actual.timestamp != expected.timestamp
+                    || actual.value.to_bits() != expected.value.to_bits()
+                {
+                    return Err(SoakError::PointMismatch {
+                        cycle,
+                        expected,
+                        actual,
+                    });
+                }
+                index += 1;
+            }
+        }
+
+        let bytes = stored_bytes(&series);
+        report.peak_bytes = report.peak_bytes.max(bytes);
+        if report.points_dropped > 0 {
+            let current = bytes as f64 / series.len().max(1) as f64;
+            if report.baseline_bytes_per_point == 0.0 {
+                report.baseline_bytes_per_point = current;
+            } else if current > report.baseline_bytes_per_point * config.growth_tolerance {
+                return Err(SoakError::MemoryGrowth {
+                    cycle,
+                    baseline: report.baseline_bytes_per_point,
+                    current,
+                });
+            }
+        }
+
+        report.cycles = cycle;
+        if started.elapsed() >= config.duration || config.max_cycles == Some(cycle) {
+            break;
+        }
+    }
+    report.elapsed = started.elapsed();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::ValueScheme;
+    use crate::compaction::{SizeTiered, TimeWindowed};
+    use crate::encoder::EncoderConfig;
+    use crate::lossy::Precision;
+
+    fn short(series: SeriesConfig) -> SoakConfig {
+        SoakConfig {
+            duration: Duration::from_secs(60),
+            max_cycles: Some(40),
+            series,
+            points_per_block: 50,
+            retained_points: 400,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_soak_holds_invariants() {
+        let configs = [
+            EncoderConfig::default(),
+            EncoderConfig {
+                value_codec: ValueScheme::Chimp,
+                ..Default::default()
+            },
+            EncoderConfig {
+                precision: Some(Precision::SignificantDigits(3)),
+                ..Default::default()
+            },
+        ];
+        for encoder in configs {
+            let config = short(SeriesConfig {
+                encoder,
+                ..Default::default()
+            });
+            let tiered = SizeTiered {
+                target_bytes: 1024,
+                ..Default::default()
+            };
+            let report = run(&config, &tiered).unwrap();
+            assert_eq!(report.cycles, 40);
+            assert_eq!(report.points_appended, 2_000);
+            assert!(report.points_dropped >= 1_000);
+            assert!(report.compactions > 0);
+            assert!(report.baseline_bytes_per_point > 0.0);
+
+            let windowed = TimeWindowed { window: 6_000 };
+            run(&config, &windowed).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_soak_reports_memory_growth() {
+        let config = SoakConfig {
+            values: |i| if i < 600 { 1.0 } else { sensor(i) },
+            ..short(SeriesConfig::default())
+        };
+        let err = run(&config, &SizeTiered::default()).unwrap_err();
+        assert!(matches!(err, SoakError::MemoryGrowth { .. }), "{err}");
+    }
+}
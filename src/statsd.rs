@@ -0,0 +1,359 @@
+//! StatsD ingestion: datagram parsing and per-interval pre-aggregation.
+//!
+//! A [`StatsdAggregator`] accepts StatsD datagrams (newline-separated
+//! `name:value|type[|@rate][|#tags]` lines) from a UDP socket or any other
+//! transport, folds them into per-metric state, and on each
+//! [`flush`](StatsdAggregator::flush) appends one point per derived series
+//! to a [`SeriesMap`]:
+//!
+//! | type            | series appended |
+//! |-----------------|-----------------|
+//! | `c` counter     | `name`: sum of increments, scaled by `1 / rate` |
+//! | `g` gauge       | `name`: last value; `+n`/`-n` adjust it |
+//! | `ms`, `h`, `d`  | `name.count`, `name.sum`, `name.min`, `name.max`, `name.mean` and `name.pNN` per configured percentile |
+//! | `s` set         | `name`: number of distinct members |
+//!
+//! DogStatsD tags are folded into the series name Graphite-style, sorted:
+//! `req:1|c|#route:/a,env:prod` is stored as `req;env=prod;route=/a`.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::net::UdpSocket;
+
+use crate::encoder::DataPoint;
+use crate::map::SeriesMap;
+use crate::series::AppendError;
+
+/// Error describing why a StatsD line was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatsdError {
+    /// The line has no `:` separating name and value, or the name is empty.
+    MissingName,
+    /// The line has no `|type` section.
+    MissingType,
+    /// The value is not a number.
+    InvalidValue(String),
+    /// The metric type is not one of `c`, `g`, `ms`, `h`, `d` or `s`.
+    UnknownType(String),
+    /// The sample rate is not a number in `(0, 1]`.
+    InvalidSampleRate(String),
+}
+
+impl fmt::Display for StatsdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatsdError::MissingName => write!(f, "missing metric name"),
+            StatsdError::MissingType => write!(f, "missing metric type"),
+            StatsdError::InvalidValue(v) => write!(f, "invalid metric value {v:?}"),
+            StatsdError::UnknownType(t) => write!(f, "unknown metric type {t:?}"),
+            StatsdError::InvalidSampleRate(r) => write!(f, "invalid sample rate {r:?}"),
+        }
+    }
+}
+
+impl std::error::Error for StatsdError {}
+
+/// Kind of a StatsD metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+    /// Timers (`ms`), histograms (`h`) and distributions (`d`).
+    Timer,
+    Set,
+}
+
+/// One parsed StatsD line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metric {
+    /// Series name, including any folded tags.
+    pub name: String,
+    pub kind: MetricKind,
+    /// Raw value text; set members need not be numeric.
+    pub value: String,
+    /// Sample rate from `|@rate`, 1.0 if absent.
+    pub sample_rate: f64,
+}
+
+impl Metric {
+    /// Parses one line of a datagram.
+    pub fn parse(line: &str) -> Result<Self, StatsdError> {
+        let (name, rest) = line.split_once(':').ok_or(StatsdError::MissingName)?;
+        if name.is_empty() {
+            return Err(StatsdError::MissingName);
+        }
+        let mut sections = rest.split('|');
+        let value = sections.next().unwrap_or_default();
+        let kind = match sections.next().ok_or(StatsdError::MissingType)? {
+            "c" => MetricKind::Counter,
+            "g" => MetricKind::Gauge,
+            "ms" | "h" | "d" => MetricKind::Timer,
+            "s" => MetricKind::Set,
+            other => return Err(StatsdError::UnknownType(other.to_string())),
+        };
+        if kind != MetricKind::Set && value.parse::<f64>().is_err() {
+            return Err(StatsdError::InvalidValue(value.to_string()));
+        }
+        let mut sample_rate = 1.0;
+        let mut tags = Vec::new();
+        for section in sections {
+            if let Some(rate) = section.strip_prefix('@') {
+                sample_rate = rate
+                    .parse()
+                    .ok()
+                    .filter(|r: &f64| *r > 0.0 && *r <= 1.0)
+                    .ok_or_else(|| StatsdError::InvalidSampleRate(rate.to_string()))?;
+            } else if let Some(list) = section.strip_prefix('#') {
+                tags.extend(
+                    list.split(',')
+                        .filter(|t| !t.is_empty())
+                        .map(|t| t.replacen(':', "=", 1)),
+                );
+            }
+        }
+        tags.sort_unstable();
+        let mut name = name.to_string();
+        for tag in tags {
+            name.push(';');
+            name.push_str(&tag);
+        }
+        Ok(Metric {
+            name,
+            kind,
+            value: value.to_string(),
+            sample_rate,
+        })
+    }
+}
+
+/// Configuration for a [`StatsdAggregator`].
+#[derive(Debug, Clone)]
+pub struct StatsdConfig {
+    /// Percentiles reported for timers, each as a `name.pNN` series.
+    pub percentiles: Vec<f64>,
+    /// Keep reporting a gauge's last value on flushes with no new samples,
+    /// as StatsD does by default.
+    pub keep_gauges: bool,
+}
+
+impl Default for StatsdConfig {
+    fn default() -> Self {
+        Self {
+            percentiles: vec![90.0],
+            keep_gauges: true,
+        }
+    }
+}
+
+/// Pre-aggregates StatsD metrics between flushes.
+#[derive(Debug, Default)]
+pub struct StatsdAggregator {
+    config: StatsdConfig,
+    counters: HashMap<String, f64>,
+    /// Last value of every gauge, and whether it was updated since the
+    /// previous flush.
+    gauges: HashMap<String, (f64, bool)>,
+    timers: HashMap<String, Vec<f64>>,
+    sets: HashMap<String, HashSet<String>>,
+    rejected: u64,
+    buf: Vec<u8>,
+}
+
+impl StatsdAggregator {
+    /// Creates an empty aggregator.
+    pub fn new(config: StatsdConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// Adds one parsed metric.
+    pub fn add(&mut self, metric: Metric) {
+        let value = metric.value.parse::<f64>().unwrap_or(0.0);
+        match metric.kind {
+            MetricKind::Counter => {
+                *self.counters.entry(metric.name).or_default() += value / metric.sample_rate;
+            }
+            MetricKind::Gauge => {
+                let relative = metric.value.starts_with(['+', '-']);
+                let gauge = self.gauges.entry(metric.name).or_insert((0.0, false));
+                gauge.0 = if relative { gauge.0 + value } else { value };
+                gauge.1 = true;
+            }
+            MetricKind::Timer => self.timers.entry(metric.name).or_default().push(value),
+            MetricKind::Set => {
+                self.sets
+                    .entry(metric.name)
+                    .or_default()
+                    .insert(metric.value);
+            }
+        }
+    }
+
+    /// Parses a datagram and adds its metrics, returning how many lines were
+    /// accepted. Malformed lines are skipped and counted in
+    /// [`rejected`](StatsdAggregator::rejected).
+    pub fn ingest(&mut self, datagram: &[u8]) -> usize {
+        let text = String::from_utf8_lossy(datagram);
+        let mut accepted = 0;
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            match Metric::parse(line) {
+                Ok(metric) => {
+                    self.add(metric);
+                    accepted += 1;
+                }
+                Err(_) => self.rejected += 1,
+            }
+        }
+        accepted
+    }
+
+    /// Receives one datagram from `socket` and ingests it, returning the
+    /// number of lines accepted. Blocks according to the socket's settings.
+    pub fn receive(&mut self, socket: &UdpSocket) -> io::Result<usize> {
+        self.buf.resize(65_536, 0);
+        let (len, _) = socket.recv_from(&mut self.buf)?;
+        let datagram = std::mem::take(&mut self.buf);
+        let accepted = self.ingest(&datagram[..len]);
+        self.buf = datagram;
+        Ok(accepted)
+    }
+
+    /// Returns the number of malformed lines skipped so far.
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    /// Appends the aggregates of the interval ending at `timestamp` to `map`
+    /// and starts a new interval. Returns the number of points appended.
+    ///
+    /// Every series is attempted even if some appends fail; the first error
+    /// is returned after the interval has been reset.
+    pub fn flush(
+        &mut self,
+        timestamp: u64,
+        map: &mut SeriesMap<String>,
+    ) -> Result<usize, AppendError> {
+        let mut points = Vec::new();
+        for (name, value) in self.counters.drain() {
+            points.push((name, value));
+        }
+        for (name, (value, updated)) in &mut self.gauges {
+            if *updated || self.config.keep_gauges {
+                points.push((name.clone(), *value));
+            }
+            *updated = false;
+        }
+        if !self.config.keep_gauges {
+            self.gauges.clear();
+        }
+        for (name, members) in self.sets.drain() {
+            points.push((name, members.len() as f64));
+        }
+        for (name, mut samples) in self.timers.drain() {
+            samples.sort_unstable_by(f64::total_cmp);
+            let count = samples.len() as f64;
+            let sum: f64 = samples.iter().sum();
+            points.push((format!("{name}.count"), count));
+            points.push((format!("{name}.sum"), sum));
+            points.push((format!("{name}.min"), samples[0]));
+            points.push((format!("{name}.max"), samples[samples.len() - 1]));
+            points.push((format!("{name}.mean"), sum / count));
+            for &p in &self.config.percentiles {
+                // Nearest-rank percentile.
+                let rank = ((p / 100.0 * count).ceil() as usize).clamp(1, samples.len());
+                points.push((format!("{name}.p{p}"), samples[rank - 1]));
+            }
+        }
+
+        let mut appended = 0;
+        let mut first_err = None;
+        for (name, value) in points {
+            match map.append(name, DataPoint::new(timestamp, value)) {
+                Ok(()) => appended += 1,
+                Err(err) => {
+                    first_err.get_or_insert(err);
+                }
+            }
+        }
+        match first_err {
+            Some(err) => Err(err),
+            None => Ok(appended),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::series::SeriesConfig;
+
+    fn last(map: &SeriesMap<String>, name: &str) -> f64 {
+        map.get(&name.to_string())
+            .unwrap()
+            .last_point()
+            .unwrap()
+            .value
+    }
+
+    #[test]
+    fn test_parse_lines() {
+        let m = Metric::parse("api.req:3|c|@0.5|#route:/a,env:prod").unwrap();
+        assert_eq!(m.name, "api.req;env=prod;route=/a");
+        assert_eq!((m.kind, m.sample_rate), (MetricKind::Counter, 0.5));
+        assert_eq!(Metric::parse("u:alice|s").unwrap().value, "alice");
+        assert_eq!(Metric::parse("x|c"), Err(StatsdError::MissingName));
+        assert_eq!(Metric::parse("x:1"), Err(StatsdError::MissingType));
+        assert_eq!(
+            Metric::parse("x:abc|ms"),
+            Err(StatsdError::InvalidValue("abc".into()))
+        );
+        assert_eq!(
+            Metric::parse("x:1|q"),
+            Err(StatsdError::UnknownType("q".into()))
+        );
+        assert_eq!(
+            Metric::parse("x:1|c|@0"),
+            Err(StatsdError::InvalidSampleRate("0".into()))
+        );
+    }
+
+    #[test]
+    fn test_flush_aggregates_interval() {
+        let mut agg = StatsdAggregator::new(StatsdConfig::default());
+        let datagram = b"hits:1|c\nhits:2|c|@0.5\ntemp:20|g\ntemp:+1.5|g\n\
+            lat:30|ms\nlat:10|ms\nlat:20|ms\nusers:a|s\nusers:b|s\nusers:a|s\nbogus\n";
+        assert_eq!(agg.ingest(datagram), 10);
+        assert_eq!(agg.rejected(), 1);
+
+        let mut map = SeriesMap::new(SeriesConfig::default());
+        assert_eq!(agg.flush(60, &mut map).unwrap(), 3 + 6);
+        assert_eq!(last(&map, "hits"), 5.0);
+        assert_eq!(last(&map, "temp"), 21.5);
+        assert_eq!(last(&map, "users"), 2.0);
+        assert_eq!(last(&map, "lat.mean"), 20.0);
+        assert_eq!(last(&map, "lat.min"), 10.0);
+        assert_eq!(last(&map, "lat.p90"), 30.0);
+
+        // Only the gauge carries over into an empty interval.
+        assert_eq!(agg.flush(120, &mut map).unwrap(), 1);
+        assert_eq!(map.get(&"temp".to_string()).unwrap().len(), 2);
+        assert_eq!(map.get(&"hits".to_string()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_receive_over_udp() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender
+            .send_to(b"jobs:4|c\njobs:x|c", socket.local_addr().unwrap())
+            .unwrap();
+        let mut agg = StatsdAggregator::default();
+        assert_eq!(agg.receive(&socket).unwrap(), 1);
+        let mut map = SeriesMap::new(SeriesConfig::default());
+        agg.flush(10, &mut map).unwrap();
+        assert_eq!(last(&map, "jobs"), 4.0);
+    }
+}
@@ -0,0 +1,88 @@
+//! Persistence interface for sealed blocks, and [`TimeSeriesMap`], the
+//! in-memory store those blocks come from.
+
+use std::io;
+
+use crate::encoder::CompressedBlock;
+use crate::map::SeriesMap;
+use crate::segment::SegmentWriter;
+
+/// An in-memory store of series, each with an open encoder and sealed
+/// blocks, that serves range queries through [`SeriesMap::query`].
+///
+/// ```
+/// use gorilla::store::TimeSeriesMap;
+/// use gorilla::{DataPoint, SeriesConfig};
+///
+/// let mut map = TimeSeriesMap::<u64>::new(SeriesConfig::default());
+/// map.append(7, DataPoint::new(1000, 1.5)).unwrap();
+/// map.append(7, DataPoint::new(1060, 2.5)).unwrap();
+/// let points: Vec<_> = map.query(&7, 1000, 1030).collect::<Result<_, _>>().unwrap();
+/// assert_eq!(points, [DataPoint::new(1000, 1.5)]);
+/// ```
+pub type TimeSeriesMap<K> = SeriesMap<K>;
+
+/// A destination for sealed blocks, keyed by the encoded series key and the
+/// block's time range.
+///
+/// Implemented by [`SegmentWriter`]; services can implement it for their own
+/// storage backends.
+pub trait BlockStore {
+    /// Error returned when a block cannot be stored.
+    type Error;
+
+    /// Stores `block`, which belongs to the series with encoded key `key` and
+    /// covers timestamps `start..=end`.
+    fn put(
+        &mut self,
+        key: &[u8],
+        start: u64,
+        end: u64,
+        block: &CompressedBlock,
+    ) -> Result<(), Self::Error>;
+
+    /// Makes all stored blocks durable. The default does nothing.
+    fn sync(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl BlockStore for SegmentWriter {
+    type Error = io::Error;
+
+    fn put(
+        &mut self,
+        key: &[u8],
+        start: u64,
+        end: u64,
+        block: &CompressedBlock,
+    ) -> Result<(), io::Error> {
+        self.add(key, start, end, block);
+        Ok(())
+    }
+
+    /// Writes the segment with every block put so far, as
+    /// [`finish`](SegmentWriter::finish) would, so that it is on disk even
+    /// if the writer is then dropped.
+    fn sync(&mut self) -> Result<(), io::Error> {
+        self.write()
+    }
+}
+
+impl<S: BlockStore + ?Sized> BlockStore for &mut S {
+    type Error = S::Error;
+
+    fn put(
+        &mut self,
+        key: &[u8],
+        start: u64,
+        end: u64,
+        block: &CompressedBlock,
+    ) -> Result<(), S::Error> {
+        (**self).put(key, start, end, block)
+    }
+
+    fn sync(&mut self) -> Result<(), S::Error> {
+        (**self).sync()
+    }
+}
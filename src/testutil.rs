@@ -0,0 +1,356 @@
+//! Generators of adversarial point sequences and a round-trip assertion,
+//! for property tests of code that embeds the codec (feature `test-util`).
+//!
+//! The generators are deterministic in a seed and take no randomness of
+//! their own, so they plug into any property testing framework: draw a
+//! seed and a length, generate, check. Each [`Shape`] aims at the edges of
+//! one part of the format; [`adversarial`] splices several of them.
+//!
+//! ```
+//! use gorilla::testutil::{adversarial, assert_roundtrip, generate, Shape};
+//!
+//! for seed in 0..50 {
+//!     assert_roundtrip(&adversarial(seed, 300));
+//! }
+//! assert_eq!(generate(Shape::Jitter, 7, 100), generate(Shape::Jitter, 7, 100));
+//! ```
+
+use crate::decoder::Decoder;
+use crate::encoder::{CompressedBlock, DataPoint, Encoder, EncoderConfig};
+
+/// A family of point sequences aimed at one part of the format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shape {
+    /// Fixed interval and slowly drifting values: the common case.
+    Regular,
+    /// Intervals jittered by a few units, values at one decimal.
+    Jitter,
+    /// Delta-of-deltas on both sides of every bucket boundary and XORs
+    /// with the extremes of leading, trailing and meaningful bits.
+    BucketEdges,
+    /// Timestamps that go backwards as often as forwards.
+    Backwards,
+    /// Runs of repeated timestamps and repeated values.
+    Duplicates,
+    /// NaNs with payloads, infinities, signed zeros, subnormals and the
+    /// extremes of `f64`.
+    SpecialValues,
+    /// Random value bits at random intervals, which do not compress.
+    BitNoise,
+    /// Regular runs separated by gaps of up to 2^40 units.
+    LargeGaps,
+}
+
+impl Shape {
+    /// Every shape, e.g. to cover all of them in a test.
+    pub const ALL: [Shape; 8] = [
+        Shape::Regular,
+        Shape::Jitter,
+        Shape::BucketEdges,
+        Shape::Backwards,
+        Shape::Duplicates,
+        Shape::SpecialValues,
+        Shape::BitNoise,
+        Shape::LargeGaps,
+    ];
+}
+
+/// Delta-of-deltas at and around the boundaries of the timestamp buckets.
+const DOD_EDGES: [i64; 16] = [
+    0,
+    1,
+    -1,
+    63,
+    -64,
+    64,
+    -65,
+    255,
+    -256,
+    256,
+    -257,
+    2047,
+    -2048,
+    2048,
+    -2049,
+    1 << 40,
+];
+
+/// Value bits whose XOR with the previous value has extreme leading,
+/// trailing or meaningful bit counts.
+const XOR_EDGES: [u64; 6] = [
+    1,
+    1 << 63,
+    u64::MAX,
+    0x8000_0000_0000_0001,
+    0xFFFF_FFFF,
+    0x7FF0,
+];
+
+/// Values the IEEE 754 encoding treats specially.
+const SPECIAL_VALUES: [u64; 14] = [
+    0x7FF8_0000_0000_0000, // quiet NaN
+    0xFFF8_0000_0000_0000, // negative quiet NaN
+    0x7FF0_0000_0000_0001, // signalling NaN
+    0x7FFF_FFFF_FFFF_FFFF, // NaN with every payload bit set
+    0x7FF0_0000_0000_0000, // infinity
+    0xFFF0_0000_0000_0000, // negative infinity
+    0x0000_0000_0000_0000, // zero
+    0x8000_0000_0000_0000, // negative zero
+    0x0000_0000_0000_0001, // smallest subnormal
+    0x000F_FFFF_FFFF_FFFF, // largest subnormal
+    0x0010_0000_0000_0000, // smallest normal
+    0x7FEF_FFFF_FFFF_FFFF, // largest finite
+    0xFFEF_FFFF_FFFF_FFFF, // most negative finite
+    0x3CB0_0000_0000_0000, // machine epsilon
+];
+
+/// Generates `len` points of `shape`, the same for the same seed.
+/// Timestamps turn back rather than wrap around zero, so the encoder
+/// accepts every point.
+pub fn generate(shape: Shape, seed: u64, len: usize) -> Vec<DataPoint> {
+    let mut rng = Rng::new(seed);
+    let mut timestamp = 1_600_000_000 + rng.below(1_000_000);
+    let interval = 1 + rng.below(3_600);
+    let mut delta = interval as i64;
+    let mut value = rng.next() as f64 / u64::MAX as f64 * 100.0;
+    let mut points = Vec::with_capacity(len);
+    for i in 0..len as u64 {
+        let bits = match shape {
+            Shape::Regular => {
+                value += (rng.below(11) as f64 - 5.0) * 0.01;
+                value.to_bits()
+            }
+            Shape::Jitter => {
+                delta = interval as i64 + rng.below(11) as i64 - 5;
+                value = (rng.below(1_000) as f64 * 0.1 * 10.0).round() / 10.0;
+                value.to_bits()
+            }
+            Shape::BucketEdges => {
+                let dod = DOD_EDGES[rng.below(DOD_EDGES.len() as u64) as usize];
+                // Keep the delta within a few buckets of zero either way.
+                delta = if delta.unsigned_abs() > 1 << 41 {
+                    0
+                } else {
+                    delta + if rng.below(2) == 0 { dod } else { -dod }
+                };
+                let xor = XOR_EDGES[rng.below(XOR_EDGES.len() as u64) as usize];
+                value = f64::from_bits(value.to_bits() ^ xor);
+                value.to_bits()
+            }
+            Shape::Backwards => {
+                delta = rng.below(2 * interval + 1) as i64 - interval as i64;
+                (rng.below(100) as f64).to_bits()
+            }
+            Shape::Duplicates => {
+                if rng.below(4) == 0 {
+                    delta = rng.below(2) as i64 * interval as i64;
+                    value = rng.below(3) as f64;
+                }
+                value.to_bits()
+            }
+            Shape::SpecialValues => match rng.below(4) {
+                0 => rng.next(),
+                _ => SPECIAL_VALUES[rng.below(SPECIAL_VALUES.len() as u64) as usize],
+            },
+            Shape::BitNoise => {
+                delta = rng.below(1 << 32) as i64;
+                rng.next()
+            }
+            Shape::LargeGaps => {
+                delta = match rng.below(20) {
+                    0 => 1 << (20 + rng.below(21)),
+                    _ => interval as i64,
+                };
+                value += 0.5;
+                value.to_bits()
+            }
+        };
+        if i > 0 {
+            if timestamp.checked_add_signed(delta).is_none() {
+                delta = -delta;
+            }
+            timestamp = timestamp.wrapping_add_signed(delta);
+        }
+        points.push(DataPoint::new(timestamp, f64::from_bits(bits)));
+    }
+    points
+}
+
+/// Generates `len` points spliced together from runs of every [`Shape`],
+/// in an order and with run lengths chosen by the seed. The timestamps of
+/// each run continue from the previous one.
+pub fn adversarial(seed: u64, len: usize) -> Vec<DataPoint> {
+    let mut rng = Rng::new(seed);
+    let mut points: Vec<DataPoint> = Vec::with_capacity(len);
+    while points.len() < len {
+        let shape = Shape::ALL[rng.below(Shape::ALL.len() as u64) as usize];
+        let run = (1 + rng.below(64) as usize).min(len - points.len());
+        let offset = points
+            .last()
+            .map_or(0, |dp| dp.timestamp.wrapping_sub(1_600_000_000));
+        points.extend(
+            generate(shape, rng.next(), run)
+                .into_iter()
+                .map(|dp| DataPoint::new(dp.timestamp.wrapping_add(offset), dp.value)),
+        );
+    }
+    points
+}
+
+/// Asserts that `points` survive a round trip through an encoder with the
+/// default configuration, see [`assert_roundtrip_with`].
+#[track_caller]
+pub fn assert_roundtrip(points: &[DataPoint]) {
+    assert_roundtrip_with(points, &EncoderConfig::default());
+}
+
+/// Asserts that `points`, encoded with `config`, decode back bit for bit,
+/// NaN payloads included, through [`Decoder::decode`], [`Decoder::iter`]
+/// and the serialized form of [`CompressedBlock::to_bytes`].
+///
+/// `config` must keep every point as it is: ordering and duplicate
+/// policies that drop or reject points, the dead band, lossy precision and
+/// quantizers fail the assertion. Its byte limit is ignored. An empty
+/// sequence only checks that the block holds no points, as an empty block
+/// does not decode.
+///
+/// # Panics
+///
+/// Panics, naming the first point that differs, if the round trip changes
+/// the points.
+#[track_caller]
+pub fn assert_roundtrip_with(points: &[DataPoint], config: &EncoderConfig) {
+    let mut encoder = Encoder::with_config(EncoderConfig {
+        max_bytes: None,
+        ..config.clone()
+    });
+    for (i, &dp) in points.iter().enumerate() {
+        if let Err(err) = encoder.encode(dp) {
+            panic!("point {i} ({dp:?}) was rejected: {err}");
+        }
+    }
+    let block = encoder
+        .finish_into()
+        .expect("an unlimited buffer always fits the end-of-stream marker");
+    assert_eq!(block.count(), points.len() as u64, "block count");
+    if points.is_empty() {
+        return;
+    }
+
+    let decoded = Decoder::decode(&block).unwrap_or_else(|err| panic!("decode failed: {err}"));
+    assert_same("decode", points, &decoded);
+    let iterated: Vec<_> = Decoder::iter(&block)
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|err| panic!("iteration failed: {err}"));
+    assert_same("iter", points, &iterated);
+    let parsed = CompressedBlock::from_bytes(&block.to_bytes())
+        .unwrap_or_else(|err| panic!("serialized block failed to parse: {err}"));
+    let reparsed = Decoder::decode(&parsed).unwrap_or_else(|err| panic!("decode failed: {err}"));
+    assert_same("serialized decode", points, &reparsed);
+}
+
+/// Panics with the first point of `actual` that differs from `expected` in
+/// its timestamp or value bits.
+#[track_caller]
+fn assert_same(what: &str, expected: &[DataPoint], actual: &[DataPoint]) {
+    let same = |a: &DataPoint, b: &DataPoint| {
+        a.timestamp == b.timestamp && a.value.to_bits() == b.value.to_bits()
+    };
+    if let Some(i) =
+        (0..expected.len().min(actual.len())).find(|&i| !same(&expected[i], &actual[i]))
+    {
+        panic!(
+            "{what} changed point {i}: encoded {:?}, got {:?}",
+            expected[i], actual[i]
+        );
+    }
+    assert_eq!(
+        actual.len(),
+        expected.len(),
+        "{what} returned a different number of points"
+    );
+}
+
+/// xorshift64* generator: small, fast and good enough to spread test
+/// inputs, and stable across releases so seeds stay reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // SplitMix64 of the seed, which is never zero for xorshift.
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Self((z ^ (z >> 31)) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Returns a number below `n`, which must be non-zero.
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{TimestampScheme, ValueScheme};
+
+    #[test]
+    fn test_every_shape_round_trips_under_every_codec() {
+        let codecs = [
+            (TimestampScheme::DeltaOfDelta, ValueScheme::Gorilla),
+            (TimestampScheme::Delta, ValueScheme::Chimp),
+            (TimestampScheme::RunLength, ValueScheme::Raw),
+            (TimestampScheme::Raw, ValueScheme::Gorilla),
+        ];
+        for (timestamp_codec, value_codec) in codecs {
+            let config = EncoderConfig {
+                timestamp_codec,
+                value_codec,
+                checksum: true,
+                checkpoint_interval: Some(16),
+                ..Default::default()
+            };
+            for seed in 0..20 {
+                for shape in Shape::ALL {
+                    assert_roundtrip_with(&generate(shape, seed, 257), &config);
+                }
+                assert_roundtrip_with(&adversarial(seed, 1_000), &config);
+            }
+        }
+        assert_roundtrip(&[]);
+    }
+
+    #[test]
+    fn test_bucket_edges_hit_every_boundary() {
+        let points = generate(Shape::BucketEdges, 3, 5_000);
+        let dods: Vec<i64> = points
+            .windows(3)
+            .map(|w| {
+                let delta =
+                    |a: &DataPoint, b: &DataPoint| b.timestamp.wrapping_sub(a.timestamp) as i64;
+                delta(&w[1], &w[2]) - delta(&w[0], &w[1])
+            })
+            .collect();
+        for edge in [
+            63, 64, -64, -65, 255, 256, -256, -257, 2047, 2048, -2048, -2049,
+        ] {
+            assert!(dods.contains(&edge), "no delta-of-delta of {edge}");
+        }
+        let bits = |points: Vec<DataPoint>| -> Vec<(u64, u64)> {
+            points
+                .iter()
+                .map(|dp| (dp.timestamp, dp.value.to_bits()))
+                .collect()
+        };
+        assert_eq!(bits(adversarial(9, 500)), bits(adversarial(9, 500)));
+        assert_eq!(adversarial(9, 500).len(), 500);
+    }
+}
@@ -0,0 +1,425 @@
+//! Two-tier store: recent blocks in memory, older blocks spilled to disk
+//! segments.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::decoder::{DecodeError, Decoder};
+use crate::encoder::{CompressedBlock, DataPoint, EncodeError};
+use crate::segment::{Segment, SegmentEntry, SegmentWriter};
+use crate::series::{AppendError, SeriesConfig, SeriesKey, TimeSeries};
+
+/// Error returned by [`TieredStore`] operations.
+#[derive(Debug)]
+pub enum TieredError {
+    /// Reading or writing a segment file failed.
+    Io(io::Error),
+    /// A point could not be appended to its series.
+    Append(AppendError),
+    /// A block read back for a query could not be decoded.
+    Decode(DecodeError),
+}
+
+impl std::fmt::Display for TieredError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TieredError::Io(err) => write!(f, "segment I/O error: {err}"),
+            TieredError::Append(err) => write!(f, "{err}"),
+            TieredError::Decode(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for TieredError {}
+
+impl From<io::Error> for TieredError {
+    fn from(err: io::Error) -> Self {
+        TieredError::Io(err)
+    }
+}
+
+impl From<AppendError> for TieredError {
+    fn from(err: AppendError) -> Self {
+        TieredError::Append(err)
+    }
+}
+
+impl From<DecodeError> for TieredError {
+    fn from(err: DecodeError) -> Self {
+        TieredError::Decode(err)
+    }
+}
+
+/// Configuration for a [`TieredStore`].
+#[derive(Debug, Clone)]
+pub struct TieredConfig {
+    /// Configuration of every in-memory series.
+    pub series: SeriesConfig,
+    /// Time span covered by one block, in timestamp units. The open block of
+    /// a series is sealed when a point arrives at or past
+    /// `first_timestamp + block_duration`.
+    pub block_duration: u64,
+    /// How far behind `now` a sealed block must end before
+    /// [`TieredStore::spill`] moves it to disk.
+    pub ram_retention: u64,
+}
+
+impl Default for TieredConfig {
+    fn default() -> Self {
+        Self {
+            series: SeriesConfig::default(),
+            block_duration: 2 * 3600,
+            ram_retention: 26 * 3600,
+        }
+    }
+}
+
+/// Location of a spilled block: index into the store's segment list and
+/// index into that segment's entries.
+#[derive(Debug, Clone, Copy)]
+struct ColdBlock {
+    segment: usize,
+    entry: usize,
+}
+
+/// A multi-series store that keeps recent blocks in memory and spills older
+/// sealed blocks to immutable segment files in a directory.
+///
+/// Queries see both tiers through a single iterator, oldest data first.
+#[derive(Debug)]
+pub struct TieredStore<K: SeriesKey> {
+    dir: PathBuf,
+    config: TieredConfig,
+    series: HashMap<K, TimeSeries>,
+    segments: Vec<Segment>,
+    cold: HashMap<K, Vec<ColdBlock>>,
+    next_segment: u64,
+}
+
+impl<K: SeriesKey> TieredStore<K> {
+    /// Opens a store in `dir`, creating the directory if needed and indexing
+    /// any segments left by a previous run.
+    pub fn open(dir: impl AsRef<Path>, config: TieredConfig) -> Result<Self, TieredError> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+
+        let mut seqs = Vec::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let name = entry?.file_name();
+            let seq = name
+                .to_str()
+                .and_then(|n| n.strip_prefix("segment-"))
+                .and_then(|n| n.strip_suffix(".seg"))
+                .and_then(|n| n.parse::<u64>().ok());
+            seqs.extend(seq);
+        }
+        seqs.sort_unstable();
+
+        let mut store = Self {
+            dir,
+            config,
+            series: HashMap::new(),
+            segments: Vec::new(),
+            cold: HashMap::new(),
+            next_segment: seqs.last().map_or(0, |s| s + 1),
+        };
+        for seq in seqs {
+            let segment = Segment::open(store.segment_path(seq))?;
+            store.add_segment(segment)?;
+        }
+        Ok(store)
+    }
+
+    /// Appends a data point to the series identified by `key`, sealing its
+    /// open block first if the point falls past the block's time span.
+    pub fn append(&mut self, key: K, dp: DataPoint) -> Result<(), TieredError> {
+        let block_duration = self.config.block_duration;
+        let series = self
+            .series
+            .entry(key)
+            .or_insert_with(|| TimeSeries::new(self.config.series.clone()));
+        if let Some(first) = series.open_encoder().first_timestamp() {
+            if dp.timestamp >= first.saturating_add(block_duration) {
+                series
+                    .seal()
+                    .map_err(|err| AppendError::from(EncodeError::from(err)))?;
+            }
+        }
+        series.append(dp)?;
+        Ok(())
+    }
+
+    /// Moves every sealed in-memory block that ends before
+    /// `now - ram_retention` into a new segment file. Returns the number of
+    /// blocks spilled.
+    ///
+    /// Blocks stay in memory until the segment has been written and synced,
+    /// so a failed spill loses no data.
+    pub fn spill(&mut self, now: u64) -> Result<usize, TieredError> {
+        let cutoff = now.saturating_sub(self.config.ram_retention);
+        let mut victims: Vec<(K, usize)> = Vec::new();
+        for (key, series) in &self.series {
+            let expired = (0..series.blocks().len())
+                .take_while(|&i| {
+                    series
+                        .block_time_range(i)
+                        .is_some_and(|(_, end)| end < cutoff)
+                })
+                .count();
+            if expired > 0 {
+                victims.push((key.clone(), expired));
+            }
+        }
+        if victims.is_empty() {
+            return Ok(0);
+        }
+
+        let seq = self.next_segment;
+        let path = self.segment_path(seq);
+        let mut writer = SegmentWriter::new(&path);
+        for (key, expired) in &victims {
+            let series = &self.series[key];
+            let key_bytes = key.to_key_bytes();
+            for (i, block) in series.blocks()[..*expired].iter().enumerate() {
+                let (start, end) = series.block_time_range(i).unwrap();
+                writer.add(&key_bytes, start, end, block);
+            }
+        }
+        writer.finish()?;
+        self.next_segment += 1;
+        self.add_segment(Segment::open(path)?)?;
+
+        let mut spilled = 0;
+        for (key, expired) in victims {
+            let series = self.series.get_mut(&key).unwrap();
+            for _ in 0..expired {
+                series.remove_block(0);
+            }
+            spilled += expired;
+        }
+        Ok(spilled)
+    }
+
+    /// Returns an iterator over the points of series `key` with timestamps
+    /// in `start..=end`, drawn from disk segments, sealed in-memory blocks
+    /// and the open block, in that order.
+    pub fn query(&self, key: &K, start: u64, end: u64) -> TieredQuery<'_> {
+        let overlaps = |s: u64, e: u64| s <= end && e >= start;
+        let mut sources = Vec::new();
+        for cold in self.cold.get(key).into_iter().flatten() {
+            let segment = &self.segments[cold.segment];
+            let entry = &segment.entries()[cold.entry];
+            if overlaps(entry.start, entry.end) {
+                sources.push(Source::Cold(segment, entry));
+            }
+        }
+        if let Some(series) = self.series.get(key) {
+            for i in 0..series.blocks().len() {
+                let (s, e) = series.block_time_range(i).unwrap();
+                if overlaps(s, e) {
+                    sources.push(Source::Hot(series, i));
+                }
+            }
+            let open = series.open_encoder();
+            if let Some((s, e)) = open.time_range() {
+                if overlaps(s, e) {
+                    sources.push(Source::Open(series));
+                }
+            }
+        }
+        TieredQuery {
+            sources: sources.into_iter(),
+            current: Vec::new().into_iter(),
+            start,
+            end,
+            failed: false,
+        }
+    }
+
+    /// Returns the in-memory series for `key`, if any points were appended
+    /// to it since the store was opened.
+    pub fn series(&self, key: &K) -> Option<&TimeSeries> {
+        self.series.get(key)
+    }
+
+    /// Returns the opened segments, oldest first.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Returns the directory holding the segment files.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn segment_path(&self, seq: u64) -> PathBuf {
+        self.dir.join(format!("segment-{seq:08}.seg"))
+    }
+
+    /// Registers an opened segment and indexes its blocks by series key.
+    fn add_segment(&mut self, segment: Segment) -> Result<(), TieredError> {
+        let index = self.segments.len();
+        for (i, entry) in segment.entries().iter().enumerate() {
+            let key = K::from_key_bytes(&entry.key).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "invalid series key in segment")
+            })?;
+            self.cold.entry(key).or_default().push(ColdBlock {
+                segment: index,
+                entry: i,
+            });
+        }
+        self.segments.push(segment);
+        Ok(())
+    }
+}
+
+/// Where the next batch of points for a [`TieredQuery`] comes from.
+enum Source<'a> {
+    Cold(&'a Segment, &'a SegmentEntry),
+    Hot(&'a TimeSeries, usize),
+    Open(&'a TimeSeries),
+}
+
+impl Source<'_> {
+    fn load(&self) -> Result<Vec<DataPoint>, TieredError> {
+        let block: CompressedBlock = match *self {
+            Source::Cold(segment, entry) => segment.read_block(entry)?,
+            Source::Hot(series, i) => series.read_block(i).unwrap().clone(),
+            Source::Open(series) => series.open_encoder().to_compressed(),
+        };
+        Ok(Decoder::decode(&block)?)
+    }
+}
+
+/// Iterator over the points of one series across both tiers, returned by
+/// [`TieredStore::query`].
+///
+/// Blocks are decoded one at a time as the iterator advances. Iteration
+/// stops after the first error.
+pub struct TieredQuery<'a> {
+    sources: std::vec::IntoIter<Source<'a>>,
+    current: std::vec::IntoIter<DataPoint>,
+    start: u64,
+    end: u64,
+    failed: bool,
+}
+
+impl Iterator for TieredQuery<'_> {
+    type Item = Result<DataPoint, TieredError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        loop {
+            if let Some(dp) = self.current.next() {
+                if (self.start..=self.end).contains(&dp.timestamp) {
+                    return Some(Ok(dp));
+                }
+                continue;
+            }
+            match self.sources.next()?.load() {
+                Ok(points) => self.current = points.into_iter(),
+                Err(err) => {
+                    self.failed = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment::tests::temp_dir;
+
+    const HOUR: u64 = 3600;
+
+    fn config() -> TieredConfig {
+        TieredConfig {
+            block_duration: HOUR,
+            ram_retention: 2 * HOUR,
+            ..Default::default()
+        }
+    }
+
+    /// Appends one point per minute for `hours` hours to `key`.
+    fn fill(store: &mut TieredStore<String>, key: &str, hours: u64) {
+        for i in 0..hours * 60 {
+            store
+                .append(key.to_string(), DataPoint::new(i * 60, i as f64))
+                .unwrap();
+        }
+    }
+
+    fn collect(store: &TieredStore<String>, key: &str, start: u64, end: u64) -> Vec<DataPoint> {
+        store
+            .query(&key.to_string(), start, end)
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_append_seals_by_duration() {
+        let dir = temp_dir("tiered-seal");
+        let mut store = TieredStore::open(&dir, config()).unwrap();
+        fill(&mut store, "cpu", 3);
+        let series = store.series(&"cpu".to_string()).unwrap();
+        assert_eq!(series.blocks().len(), 2);
+        assert_eq!(series.block_time_range(1), Some((HOUR, 2 * HOUR - 60)));
+        assert_eq!(series.open_encoder().count(), 60);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_spill_moves_old_blocks_to_disk() {
+        let dir = temp_dir("tiered-spill");
+        let mut store = TieredStore::open(&dir, config()).unwrap();
+        fill(&mut store, "cpu", 5);
+        fill(&mut store, "mem", 5);
+
+        // Blocks ending before 5h - 2h = 3h are spilled: hours 0, 1 and 2.
+        assert_eq!(store.spill(5 * HOUR).unwrap(), 6);
+        assert_eq!(store.segments().len(), 1);
+        assert_eq!(store.series(&"cpu".to_string()).unwrap().blocks().len(), 1);
+        assert_eq!(store.spill(5 * HOUR).unwrap(), 0);
+
+        let all = collect(&store, "cpu", 0, u64::MAX);
+        assert_eq!(all.len(), 300);
+        assert!(all.windows(2).all(|w| w[0].timestamp < w[1].timestamp));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_query_spans_tiers_and_filters_range() {
+        let dir = temp_dir("tiered-query");
+        let mut store = TieredStore::open(&dir, config()).unwrap();
+        fill(&mut store, "cpu", 5);
+        store.spill(5 * HOUR).unwrap();
+
+        let points = collect(&store, "cpu", 2 * HOUR + 1800, 4 * HOUR + 600);
+        assert_eq!(points.first().unwrap().timestamp, 2 * HOUR + 1800);
+        assert_eq!(points.last().unwrap().timestamp, 4 * HOUR + 600);
+        assert_eq!(points.len(), 2 * 60 - 30 + 10 + 1);
+        assert!(collect(&store, "disk", 0, u64::MAX).is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_reopen_indexes_existing_segments() {
+        let dir = temp_dir("tiered-reopen");
+        let mut store = TieredStore::open(&dir, config()).unwrap();
+        fill(&mut store, "cpu", 4);
+        store.spill(4 * HOUR).unwrap();
+        drop(store);
+
+        let store = TieredStore::<String>::open(&dir, config()).unwrap();
+        assert_eq!(store.segments().len(), 1);
+        let points = collect(&store, "cpu", 0, u64::MAX);
+        assert_eq!(points.len(), 120);
+        assert_eq!(points[0], DataPoint::new(0, 0.0));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,227 @@
+//! Re-compression of a block under a different configuration, e.g. to move
+//! cold blocks to a denser codec or coarser timestamps.
+
+use crate::decoder::{DecodeError, Decoder, Sample};
+use crate::encoder::{CompressedBlock, EncodeError, Encoder, EncoderConfig};
+
+/// Error returned by [`transcode`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranscodeError {
+    /// The source block could not be decoded.
+    Decode(DecodeError),
+    /// A point was rejected by the new configuration, or the new block
+    /// exceeds its byte limit.
+    Encode(EncodeError),
+}
+
+impl std::fmt::Display for TranscodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TranscodeError::Decode(err) => write!(f, "{err}"),
+            TranscodeError::Encode(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for TranscodeError {}
+
+impl From<DecodeError> for TranscodeError {
+    fn from(err: DecodeError) -> Self {
+        TranscodeError::Decode(err)
+    }
+}
+
+impl From<EncodeError> for TranscodeError {
+    fn from(err: EncodeError) -> Self {
+        TranscodeError::Encode(err)
+    }
+}
+
+/// Configuration for [`transcode`].
+#[derive(Debug, Clone)]
+pub struct TranscodeConfig {
+    /// Configuration of the new block: codecs, byte limit, checksum, index,
+    /// priors, precision and policies. Its metadata is not used, as the
+    /// block keeps its own.
+    pub encoder: EncoderConfig,
+    /// Every timestamp is divided by this, rounding down, e.g. 1000 to turn
+    /// milliseconds into seconds. Points that end up on the same timestamp
+    /// are handled by [`EncoderConfig::on_duplicate`]. Zero is taken as
+    /// one.
+    pub timestamp_divisor: u64,
+}
+
+impl Default for TranscodeConfig {
+    fn default() -> Self {
+        Self {
+            encoder: EncoderConfig::default(),
+            timestamp_divisor: 1,
+        }
+    }
+}
+
+impl From<EncoderConfig> for TranscodeConfig {
+    fn from(encoder: EncoderConfig) -> Self {
+        Self {
+            encoder,
+            ..Self::default()
+        }
+    }
+}
+
+/// Re-encodes `block` under `config` in a single pass, decoding each point
+/// straight into the new encoder without collecting the points in between.
+///
+/// Missing samples stay missing, and the block's metadata and completeness
+/// watermark carry over, the watermark divided like the timestamps. The
+/// source is checked as [`Decoder::decode_samples`] does.
+///
+/// # Example
+/// ```
+/// use gorilla::{
+///     transcode, DataPoint, Decoder, DuplicatePolicy, Encoder, EncoderConfig, TranscodeConfig,
+///     ValueScheme,
+/// };
+///
+/// let mut encoder = Encoder::new();
+/// for i in 0..100 {
+///     encoder.encode(DataPoint::new(1609459200_000 + i * 500, i as f64)).unwrap();
+/// }
+/// encoder.finish().unwrap();
+/// let millis = encoder.into_compressed();
+///
+/// let chimp = transcode(&millis, &EncoderConfig {
+///     value_codec: ValueScheme::Chimp,
+///     ..Default::default()
+/// }.into()).unwrap();
+/// assert_eq!(Decoder::decode(&chimp).unwrap(), Decoder::decode(&millis).unwrap());
+///
+/// let seconds = transcode(&millis, &TranscodeConfig {
+///     encoder: EncoderConfig {
+///         on_duplicate: DuplicatePolicy::KeepLast,
+///         ..Default::default()
+///     },
+///     timestamp_divisor: 1000,
+/// }).unwrap();
+/// assert_eq!(seconds.count(), 50);
+/// assert_eq!(Decoder::last(&seconds).unwrap(), Some(DataPoint::new(1609459249, 99.0)));
+/// ```
+pub fn transcode<B: AsRef<[u8]>>(
+    block: &CompressedBlock<B>,
+    config: &TranscodeConfig,
+) -> Result<CompressedBlock, TranscodeError> {
+    let divisor = config.timestamp_divisor.max(1);
+    let mut encoder = Encoder::with_config(EncoderConfig {
+        metadata: block.metadata.clone(),
+        ..config.encoder.clone()
+    });
+    let mut decoded = 0;
+    for sample in Decoder::samples(block) {
+        match sample? {
+            Sample::Value(mut dp) => {
+                dp.timestamp /= divisor;
+                encoder.encode(dp)?;
+            }
+            Sample::Missing(timestamp) => encoder.encode_gap(timestamp / divisor)?,
+        }
+        decoded += 1;
+    }
+    if decoded != block.count() {
+        return Err(DecodeError::CountMismatch {
+            expected: block.count(),
+            actual: decoded,
+        }
+        .into());
+    }
+    let mut transcoded = encoder.finish_into().map_err(EncodeError::from)?;
+    transcoded.complete_until = block.complete_until.map(|t| t / divisor);
+    Ok(transcoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{TimestampScheme, ValueScheme};
+    use crate::encoder::{DataPoint, DuplicatePolicy};
+    use crate::metadata::BlockMetadata;
+
+    fn source() -> CompressedBlock {
+        let mut metadata = BlockMetadata::default();
+        metadata.insert("host", "a").unwrap();
+        let mut encoder = Encoder::with_config(EncoderConfig {
+            checksum: true,
+            metadata,
+            ..Default::default()
+        });
+        for i in 0..500u64 {
+            if i % 50 == 7 {
+                encoder.encode_gap(i * 250).unwrap();
+            } else {
+                encoder
+                    .encode(DataPoint::new(i * 250, (i % 13) as f64 * 0.25))
+                    .unwrap();
+            }
+        }
+        encoder.finish().unwrap();
+        let mut block = encoder.into_compressed();
+        block.complete_until = Some(125_999);
+        block
+    }
+
+    #[test]
+    fn test_codec_change_keeps_samples_and_metadata() {
+        let block = source();
+        let config = TranscodeConfig::from(EncoderConfig {
+            timestamp_codec: TimestampScheme::RunLength,
+            value_codec: ValueScheme::Chimp,
+            checkpoint_interval: Some(64),
+            ..Default::default()
+        });
+        let transcoded = transcode(&block, &config).unwrap();
+        assert_eq!(transcoded.value_codec, ValueScheme::Chimp);
+        assert_eq!(
+            Decoder::decode_samples(&transcoded).unwrap(),
+            Decoder::decode_samples(&block).unwrap()
+        );
+        assert_eq!(transcoded.metadata.get("host"), Some("a"));
+        assert_eq!(transcoded.complete_until, Some(125_999));
+    }
+
+    #[test]
+    fn test_coarser_timestamps_collapse_duplicates() {
+        let block = source();
+        let config = TranscodeConfig {
+            encoder: EncoderConfig {
+                on_duplicate: DuplicatePolicy::KeepLast,
+                ..Default::default()
+            },
+            timestamp_divisor: 1000,
+        };
+        let transcoded = transcode(&block, &config).unwrap();
+        let samples = Decoder::decode_samples(&transcoded).unwrap();
+        assert_eq!(samples.len(), 125);
+        assert!(samples
+            .windows(2)
+            .all(|w| w[0].timestamp() + 1 == w[1].timestamp()));
+        assert_eq!(samples[1], Sample::Missing(1));
+        assert_eq!(transcoded.complete_until, Some(125));
+    }
+
+    #[test]
+    fn test_byte_limit_and_corruption_are_reported() {
+        let mut block = source();
+        let config = TranscodeConfig::from(EncoderConfig {
+            max_bytes: Some(64),
+            ..Default::default()
+        });
+        assert_eq!(
+            transcode(&block, &config).unwrap_err(),
+            TranscodeError::Encode(EncodeError::BufferFull)
+        );
+        block.checksum = block.checksum.map(|c| !c);
+        assert!(matches!(
+            transcode(&block, &TranscodeConfig::default()),
+            Err(TranscodeError::Decode(DecodeError::ChecksumMismatch { .. }))
+        ));
+    }
+}
@@ -0,0 +1,393 @@
+//! Type-tagged blocks for stores holding series of different value types.
+//!
+//! A serialized [`TypedBlock`] starts with a one-byte [`ValueType`] tag
+//! followed by the block in its type's own format:
+//!
+//! | type      | payload |
+//! |-----------|---------|
+//! | `F64`     | [`CompressedBlock::to_bytes`] |
+//! | `Bool`    | [`BoolBlock::to_bytes`] |
+//! | `Decimal` | scale (1 byte), count (8 bytes LE), total bits (8 bytes LE), stream bytes |
+//! | `F16`, `Bf16` | count (8 bytes LE), total bits (8 bytes LE), stream bytes |
+//!
+//! Half-precision blocks can only be built and decoded with the `half`
+//! feature, but their tags are always recognised.
+
+use crate::boolean::BoolBlock;
+use crate::decimal::{DecimalBlock, DecimalDecoder, DecimalPoint};
+use crate::decoder::{DecodeError, Decoder};
+use crate::encoder::{CompressedBlock, DataPoint};
+#[cfg(feature = "half")]
+use crate::half_float::{HalfBlock, HalfDecoder, HalfPoint};
+#[cfg(feature = "half")]
+use half::{bf16, f16};
+
+/// The value type stored in a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValueType {
+    /// 64-bit floats, Gorilla XOR compressed.
+    F64,
+    /// Fixed-scale decimals.
+    Decimal,
+    /// Booleans, run-length encoded.
+    Bool,
+    /// IEEE half-precision floats.
+    F16,
+    /// bfloat16 floats.
+    Bf16,
+}
+
+impl ValueType {
+    /// Returns the tag byte written in front of serialized blocks.
+    pub fn tag(self) -> u8 {
+        match self {
+            ValueType::F64 => 1,
+            ValueType::Decimal => 2,
+            ValueType::Bool => 3,
+            ValueType::F16 => 4,
+            ValueType::Bf16 => 5,
+        }
+    }
+
+    /// Returns the type for a tag byte, if known.
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        Some(match tag {
+            1 => ValueType::F64,
+            2 => ValueType::Decimal,
+            3 => ValueType::Bool,
+            4 => ValueType::F16,
+            5 => ValueType::Bf16,
+            _ => return None,
+        })
+    }
+}
+
+impl std::fmt::Display for ValueType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ValueType::F64 => "f64",
+            ValueType::Decimal => "decimal",
+            ValueType::Bool => "bool",
+            ValueType::F16 => "f16",
+            ValueType::Bf16 => "bf16",
+        })
+    }
+}
+
+/// A block of any supported value type.
+#[derive(Debug, Clone)]
+pub enum TypedBlock {
+    /// A float block.
+    F64(CompressedBlock),
+    /// A decimal block.
+    Decimal(DecimalBlock),
+    /// A boolean block.
+    Bool(BoolBlock),
+    /// A half-precision block.
+    #[cfg(feature = "half")]
+    F16(HalfBlock<f16>),
+    /// A bfloat16 block.
+    #[cfg(feature = "half")]
+    Bf16(HalfBlock<bf16>),
+}
+
+/// The points of a decoded [`TypedBlock`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodedSeries {
+    /// Points of a float block.
+    F64(Vec<DataPoint>),
+    /// Points of a decimal block.
+    Decimal(Vec<DecimalPoint>),
+    /// `(timestamp, value)` pairs of a boolean block.
+    Bool(Vec<(u64, bool)>),
+    /// Points of a half-precision block.
+    #[cfg(feature = "half")]
+    F16(Vec<HalfPoint<f16>>),
+    /// Points of a bfloat16 block.
+    #[cfg(feature = "half")]
+    Bf16(Vec<HalfPoint<bf16>>),
+}
+
+impl DecodedSeries {
+    /// Returns the value type of the points.
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            DecodedSeries::F64(_) => ValueType::F64,
+            DecodedSeries::Decimal(_) => ValueType::Decimal,
+            DecodedSeries::Bool(_) => ValueType::Bool,
+            #[cfg(feature = "half")]
+            DecodedSeries::F16(_) => ValueType::F16,
+            #[cfg(feature = "half")]
+            DecodedSeries::Bf16(_) => ValueType::Bf16,
+        }
+    }
+}
+
+impl TypedBlock {
+    /// Returns the value type stored in the block.
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            TypedBlock::F64(_) => ValueType::F64,
+            TypedBlock::Decimal(_) => ValueType::Decimal,
+            TypedBlock::Bool(_) => ValueType::Bool,
+            #[cfg(feature = "half")]
+            TypedBlock::F16(_) => ValueType::F16,
+            #[cfg(feature = "half")]
+            TypedBlock::Bf16(_) => ValueType::Bf16,
+        }
+    }
+
+    /// Decodes the block into the matching [`DecodedSeries`] variant.
+    pub fn decode(&self) -> Result<DecodedSeries, DecodeError> {
+        Ok(match self {
+            TypedBlock::F64(block) => DecodedSeries::F64(Decoder::decode(block)?),
+            TypedBlock::Decimal(block) => DecodedSeries::Decimal(DecimalDecoder::decode(block)?),
+            TypedBlock::Bool(block) => DecodedSeries::Bool(block.iter().collect::<Result<_, _>>()?),
+            #[cfg(feature = "half")]
+            TypedBlock::F16(block) => DecodedSeries::F16(HalfDecoder::decode(block)?),
+            #[cfg(feature = "half")]
+            TypedBlock::Bf16(block) => DecodedSeries::Bf16(HalfDecoder::decode(block)?),
+        })
+    }
+
+    /// Decodes a float block, or fails with [`DecodeError::TypeMismatch`].
+    pub fn decode_f64(&self) -> Result<Vec<DataPoint>, DecodeError> {
+        match self {
+            TypedBlock::F64(block) => Decoder::decode(block),
+            other => Err(other.mismatch(ValueType::F64)),
+        }
+    }
+
+    /// Decodes a decimal block, or fails with [`DecodeError::TypeMismatch`].
+    pub fn decode_decimal(&self) -> Result<Vec<DecimalPoint>, DecodeError> {
+        match self {
+            TypedBlock::Decimal(block) => DecimalDecoder::decode(block),
+            other => Err(other.mismatch(ValueType::Decimal)),
+        }
+    }
+
+    /// Decodes a boolean block, or fails with [`DecodeError::TypeMismatch`].
+    pub fn decode_bool(&self) -> Result<Vec<(u64, bool)>, DecodeError> {
+        match self {
+            TypedBlock::Bool(block) => block.iter().collect(),
+            other => Err(other.mismatch(ValueType::Bool)),
+        }
+    }
+
+    /// Decodes a half-precision block, or fails with
+    /// [`DecodeError::TypeMismatch`].
+    #[cfg(feature = "half")]
+    pub fn decode_f16(&self) -> Result<Vec<HalfPoint<f16>>, DecodeError> {
+        match self {
+            TypedBlock::F16(block) => HalfDecoder::decode(block),
+            other => Err(other.mismatch(ValueType::F16)),
+        }
+    }
+
+    /// Decodes a bfloat16 block, or fails with [`DecodeError::TypeMismatch`].
+    #[cfg(feature = "half")]
+    pub fn decode_bf16(&self) -> Result<Vec<HalfPoint<bf16>>, DecodeError> {
+        match self {
+            TypedBlock::Bf16(block) => HalfDecoder::decode(block),
+            other => Err(other.mismatch(ValueType::Bf16)),
+        }
+    }
+
+    fn mismatch(&self, expected: ValueType) -> DecodeError {
+        DecodeError::TypeMismatch {
+            expected,
+            actual: self.value_type(),
+        }
+    }
+
+    /// Serializes the block with its type tag.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![self.value_type().tag()];
+        match self {
+            TypedBlock::F64(block) => out.extend_from_slice(&block.to_bytes()),
+            TypedBlock::Bool(block) => out.extend_from_slice(&block.to_bytes()),
+            TypedBlock::Decimal(block) => {
+                out.push(block.scale);
+                write_stream(&mut out, block.count, block.total_bits, &block.bytes);
+            }
+            #[cfg(feature = "half")]
+            TypedBlock::F16(block) => {
+                write_stream(&mut out, block.count, block.total_bits, &block.bytes)
+            }
+            #[cfg(feature = "half")]
+            TypedBlock::Bf16(block) => {
+                write_stream(&mut out, block.count, block.total_bits, &block.bytes)
+            }
+        }
+        out
+    }
+
+    /// Parses a block produced by [`to_bytes`](TypedBlock::to_bytes).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let (&tag, payload) = bytes.split_first().ok_or(DecodeError::UnexpectedEnd)?;
+        let value_type = ValueType::from_tag(tag)
+            .ok_or(DecodeError::MalformedHeader("unknown value type tag"))?;
+        Ok(match value_type {
+            ValueType::F64 => TypedBlock::F64(CompressedBlock::from_bytes(payload)?),
+            ValueType::Bool => TypedBlock::Bool(BoolBlock::from_bytes(payload)?),
+            ValueType::Decimal => {
+                let (&scale, payload) = payload.split_first().ok_or(DecodeError::UnexpectedEnd)?;
+                let (count, total_bits, bytes) = read_stream(payload)?;
+                TypedBlock::Decimal(DecimalBlock {
+                    bytes,
+                    total_bits,
+                    count,
+                    scale,
+                })
+            }
+            #[cfg(feature = "half")]
+            ValueType::F16 => {
+                let (count, total_bits, bytes) = read_stream(payload)?;
+                TypedBlock::F16(HalfBlock::from_parts(bytes, total_bits, count))
+            }
+            #[cfg(feature = "half")]
+            ValueType::Bf16 => {
+                let (count, total_bits, bytes) = read_stream(payload)?;
+                TypedBlock::Bf16(HalfBlock::from_parts(bytes, total_bits, count))
+            }
+            #[cfg(not(feature = "half"))]
+            ValueType::F16 | ValueType::Bf16 => {
+                return Err(DecodeError::MalformedHeader(
+                    "half-precision blocks need the `half` feature",
+                ))
+            }
+        })
+    }
+}
+
+impl From<CompressedBlock> for TypedBlock {
+    fn from(block: CompressedBlock) -> Self {
+        TypedBlock::F64(block)
+    }
+}
+
+impl From<DecimalBlock> for TypedBlock {
+    fn from(block: DecimalBlock) -> Self {
+        TypedBlock::Decimal(block)
+    }
+}
+
+impl From<BoolBlock> for TypedBlock {
+    fn from(block: BoolBlock) -> Self {
+        TypedBlock::Bool(block)
+    }
+}
+
+#[cfg(feature = "half")]
+impl From<HalfBlock<f16>> for TypedBlock {
+    fn from(block: HalfBlock<f16>) -> Self {
+        TypedBlock::F16(block)
+    }
+}
+
+#[cfg(feature = "half")]
+impl From<HalfBlock<bf16>> for TypedBlock {
+    fn from(block: HalfBlock<bf16>) -> Self {
+        TypedBlock::Bf16(block)
+    }
+}
+
+fn write_stream(out: &mut Vec<u8>, count: u64, total_bits: usize, bytes: &[u8]) {
+    out.extend_from_slice(&count.to_le_bytes());
+    out.extend_from_slice(&(total_bits as u64).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn read_stream(payload: &[u8]) -> Result<(u64, usize, Vec<u8>), DecodeError> {
+    let header = payload.get(..16).ok_or(DecodeError::UnexpectedEnd)?;
+    let count = u64::from_le_bytes(header[..8].try_into().unwrap());
+    let total_bits = u64::from_le_bytes(header[8..].try_into().unwrap());
+    let total_bits = usize::try_from(total_bits)
+        .map_err(|_| DecodeError::MalformedHeader("total bits out of range"))?;
+    let stream = &payload[16..];
+    if stream.len() < total_bits.div_ceil(8) {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    if stream.len() > total_bits.div_ceil(8) {
+        return Err(DecodeError::MalformedHeader("trailing bytes after block"));
+    }
+    Ok((count, total_bits, stream.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boolean::BoolEncoder;
+    use crate::decimal::{Decimal, DecimalEncoder};
+    use crate::encoder::Encoder;
+
+    fn blocks() -> Vec<TypedBlock> {
+        let mut f = Encoder::new();
+        f.encode(DataPoint::new(10, 1.5)).unwrap();
+        f.finish().unwrap();
+        let mut d = DecimalEncoder::new(2);
+        d.encode(DecimalPoint::new(10, Decimal::new(150, 2)))
+            .unwrap();
+        let mut b = BoolEncoder::new();
+        b.encode(10, true).unwrap();
+        vec![
+            f.into_compressed().into(),
+            d.finish().unwrap().into(),
+            b.finish().unwrap().into(),
+        ]
+    }
+
+    #[test]
+    fn test_bytes_roundtrip_keeps_type() {
+        for block in blocks() {
+            let parsed = TypedBlock::from_bytes(&block.to_bytes()).unwrap();
+            assert_eq!(parsed.value_type(), block.value_type());
+            assert_eq!(parsed.decode().unwrap(), block.decode().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_typed_decode_rejects_other_types() {
+        let blocks = blocks();
+        assert_eq!(blocks[0].decode_f64().unwrap()[0].value, 1.5);
+        assert_eq!(
+            blocks[0].decode_decimal().unwrap_err(),
+            DecodeError::TypeMismatch {
+                expected: ValueType::Decimal,
+                actual: ValueType::F64,
+            }
+        );
+        assert_eq!(
+            blocks[2].decode_f64().unwrap_err(),
+            DecodeError::TypeMismatch {
+                expected: ValueType::F64,
+                actual: ValueType::Bool,
+            }
+        );
+        assert_eq!(blocks[2].decode_bool().unwrap(), vec![(10, true)]);
+    }
+
+    #[test]
+    fn test_unknown_tag_is_rejected() {
+        let mut bytes = blocks()[0].to_bytes();
+        bytes[0] = 0xEE;
+        assert!(matches!(
+            TypedBlock::from_bytes(&bytes),
+            Err(DecodeError::MalformedHeader(_))
+        ));
+    }
+
+    #[cfg(feature = "half")]
+    #[test]
+    fn test_half_blocks_roundtrip() {
+        use crate::half_float::HalfEncoder;
+
+        let mut enc = HalfEncoder::new();
+        enc.encode(HalfPoint::new(5, bf16::from_f32(0.25))).unwrap();
+        let block: TypedBlock = enc.finish().unwrap().into();
+        let parsed = TypedBlock::from_bytes(&block.to_bytes()).unwrap();
+        assert_eq!(parsed.value_type(), ValueType::Bf16);
+        assert_eq!(parsed.decode_bf16().unwrap()[0].value, bf16::from_f32(0.25));
+        assert!(parsed.decode_f16().is_err());
+    }
+}
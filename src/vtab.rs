@@ -0,0 +1,304 @@
+//! Cursor over a block shaped after SQLite's virtual-table interface.
+//!
+//! A virtual table declared with [`SCHEMA`] maps each point of a block to
+//! one row whose rowid is the point's index. The module's `xBestIndex`
+//! feeds usable constraints to [`Filter::constrain`], and a [`BlockCursor`]
+//! implements `xFilter`, `xNext`, `xEof`, `xRowid` and `xColumn`. Timestamp
+//! lower bounds start decoding at the block's nearest checkpoint, and a
+//! rowid lookup decodes only the points up to that row. Upper bounds do not
+//! end the scan early, as a block's points may be out of order. The crate does not
+//! link SQLite itself; a binding such as `rusqlite`'s `vtab` module wraps
+//! these calls.
+
+use crate::decoder::{DecodeError, Decoder, DecoderIter};
+use crate::encoder::{CompressedBlock, DataPoint};
+
+/// Declaration to pass to `sqlite3_declare_vtab`.
+pub const SCHEMA: &str = "CREATE TABLE x(ts INTEGER, value REAL)";
+
+/// Column number of `ts` in [`SCHEMA`].
+pub const COLUMN_TS: i32 = 0;
+/// Column number of `value` in [`SCHEMA`].
+pub const COLUMN_VALUE: i32 = 1;
+/// Column number SQLite uses for the rowid in constraints.
+pub const COLUMN_ROWID: i32 = -1;
+
+/// Comparison operator of a constraint, as in `SQLITE_INDEX_CONSTRAINT_*`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+/// A value of a result column.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SqlValue {
+    Integer(i64),
+    Real(f64),
+}
+
+/// Row restrictions pushed down from a query's `WHERE` clause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Filter {
+    /// Smallest timestamp to return.
+    pub min_ts: u64,
+    /// Largest timestamp to return.
+    pub max_ts: u64,
+    /// Only return the row with this rowid.
+    pub rowid: Option<u64>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self {
+            min_ts: 0,
+            max_ts: u64::MAX,
+            rowid: None,
+        }
+    }
+}
+
+impl Filter {
+    /// Narrows the filter by `column op value` and returns `true` if the
+    /// constraint is fully enforced, so SQLite may omit re-checking it.
+    /// Constraints on `value`, and rowid comparisons other than equality,
+    /// are left to SQLite.
+    pub fn constrain(&mut self, column: i32, op: Op, value: i64) -> bool {
+        match column {
+            COLUMN_TS => {
+                let value = i128::from(value);
+                let (min, max) = match op {
+                    Op::Eq => (value, value),
+                    Op::Gt => (value + 1, i128::MAX),
+                    Op::Ge => (value, i128::MAX),
+                    Op::Lt => (i128::MIN, value - 1),
+                    Op::Le => (i128::MIN, value),
+                };
+                self.restrict(min, max);
+                true
+            }
+            COLUMN_ROWID if op == Op::Eq => {
+                match u64::try_from(value) {
+                    Ok(rowid) if self.rowid.is_none_or(|r| r == rowid) => self.rowid = Some(rowid),
+                    _ => self.restrict(1, 0),
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if no row can match.
+    pub fn is_empty(&self) -> bool {
+        self.min_ts > self.max_ts
+    }
+
+    fn restrict(&mut self, min: i128, max: i128) {
+        let clamp = |v: i128| v.clamp(0, u64::MAX as i128) as u64;
+        if min > max || max < 0 || min > u64::MAX as i128 {
+            self.min_ts = u64::MAX;
+            self.max_ts = 0;
+            return;
+        }
+        self.min_ts = self.min_ts.max(clamp(min));
+        self.max_ts = self.max_ts.min(clamp(max));
+    }
+}
+
+/// A cursor over the rows of one block.
+pub struct BlockCursor<'a> {
+    block: &'a CompressedBlock,
+    iter: Option<DecoderIter<'a>>,
+    filter: Filter,
+    current: Option<DataPoint>,
+    rowid: u64,
+    /// Rowid of the next point `iter` yields.
+    next_rowid: u64,
+}
+
+impl<'a> BlockCursor<'a> {
+    /// Opens a cursor over `block`; call [`filter`](BlockCursor::filter)
+    /// before reading rows.
+    pub fn new(block: &'a CompressedBlock) -> Self {
+        Self {
+            block,
+            iter: None,
+            filter: Filter::default(),
+            current: None,
+            rowid: 0,
+            next_rowid: 0,
+        }
+    }
+
+    /// Starts a scan of the rows matching `filter` and positions the cursor
+    /// on the first one (`xFilter`).
+    pub fn filter(&mut self, filter: Filter) -> Result<(), DecodeError> {
+        self.filter = filter;
+        self.iter = None;
+        self.current = None;
+        if filter.is_empty() {
+            return Ok(());
+        }
+        if let Some(rowid) = filter.rowid {
+            let Ok(n) = usize::try_from(rowid) else {
+                return Ok(());
+            };
+            self.rowid = rowid;
+            self.current = Decoder::nth(self.block, n)?
+                .filter(|dp| (filter.min_ts..=filter.max_ts).contains(&dp.timestamp));
+            return Ok(());
+        }
+        let (first, iter) = Decoder::seek(self.block, filter.min_ts);
+        self.iter = Some(iter);
+        self.next_rowid = first;
+        self.advance()
+    }
+
+    /// Advances to the next matching row (`xNext`). Points may be out of
+    /// order, so the scan runs to the end of the block rather than stopping
+    /// at the first point past the upper bound.
+    pub fn advance(&mut self) -> Result<(), DecodeError> {
+        self.current = None;
+        let Some(iter) = self.iter.as_mut() else {
+            return Ok(());
+        };
+        for result in iter {
+            let dp = result?;
+            self.rowid = self.next_rowid;
+            self.next_rowid += 1;
+            if (self.filter.min_ts..=self.filter.max_ts).contains(&dp.timestamp) {
+                self.current = Some(dp);
+                return Ok(());
+            }
+        }
+        self.iter = None;
+        Ok(())
+    }
+
+    /// Returns `true` once the scan is exhausted (`xEof`).
+    pub fn eof(&self) -> bool {
+        self.current.is_none()
+    }
+
+    /// Returns the rowid of the current row (`xRowid`).
+    pub fn rowid(&self) -> i64 {
+        self.rowid as i64
+    }
+
+    /// Returns the current point, or `None` at the end of the scan.
+    pub fn point(&self) -> Option<DataPoint> {
+        self.current
+    }
+
+    /// Returns column `column` of the current row (`xColumn`). Timestamps
+    /// saturate at `i64::MAX`.
+    pub fn column(&self, column: i32) -> Option<SqlValue> {
+        let dp = self.current?;
+        match column {
+            COLUMN_TS => Some(SqlValue::Integer(
+                i64::try_from(dp.timestamp).unwrap_or(i64::MAX),
+            )),
+            COLUMN_VALUE => Some(SqlValue::Real(dp.value)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::EncoderConfig;
+    use crate::segment::tests::block_with;
+
+    fn block() -> CompressedBlock {
+        let config = EncoderConfig {
+            checkpoint_interval: Some(8),
+            ..Default::default()
+        };
+        block_with(
+            config,
+            (0..100).map(|i| DataPoint::new(1000 + i * 10, i as f64)),
+        )
+    }
+
+    fn rows(cursor: &mut BlockCursor<'_>, filter: Filter) -> Vec<(i64, Option<SqlValue>)> {
+        cursor.filter(filter).unwrap();
+        let mut out = Vec::new();
+        while !cursor.eof() {
+            out.push((cursor.rowid(), cursor.column(COLUMN_VALUE)));
+            cursor.advance().unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn test_timestamp_range_scan() {
+        let block = block();
+        let mut cursor = BlockCursor::new(&block);
+        let mut filter = Filter::default();
+        assert!(filter.constrain(COLUMN_TS, Op::Gt, 1500));
+        assert!(filter.constrain(COLUMN_TS, Op::Le, 1540));
+        assert!(!filter.constrain(COLUMN_VALUE, Op::Eq, 3));
+        let found = rows(&mut cursor, filter);
+        let expected: Vec<_> = (51..=54)
+            .map(|i| (i, Some(SqlValue::Real(i as f64))))
+            .collect();
+        assert_eq!(found, expected);
+        assert_eq!(rows(&mut cursor, Filter::default()).len(), 100);
+
+        let mut empty = Filter::default();
+        empty.constrain(COLUMN_TS, Op::Lt, 0);
+        assert!(empty.is_empty());
+        assert!(rows(&mut cursor, empty).is_empty());
+    }
+
+    #[test]
+    fn test_rowid_lookup() {
+        let block = block();
+        let mut cursor = BlockCursor::new(&block);
+        let mut filter = Filter::default();
+        assert!(filter.constrain(COLUMN_ROWID, Op::Eq, 42));
+        cursor.filter(filter).unwrap();
+        assert_eq!(cursor.column(COLUMN_TS), Some(SqlValue::Integer(1420)));
+        cursor.advance().unwrap();
+        assert!(cursor.eof());
+
+        filter.constrain(COLUMN_TS, Op::Ge, 2000);
+        assert!(rows(&mut cursor, filter).is_empty());
+        let mut missing = Filter::default();
+        missing.constrain(COLUMN_ROWID, Op::Eq, 100);
+        assert!(rows(&mut cursor, missing).is_empty());
+        assert!(!Filter::default().constrain(COLUMN_ROWID, Op::Gt, 5));
+    }
+
+    #[test]
+    fn test_out_of_order_block_scan() {
+        let config = EncoderConfig {
+            checkpoint_interval: Some(2),
+            ..Default::default()
+        };
+        let block = block_with(
+            config,
+            [100, 300, 200, 400, 50].map(|t| DataPoint::new(t, t as f64)),
+        );
+        let mut cursor = BlockCursor::new(&block);
+        let mut filter = Filter::default();
+        assert!(filter.constrain(COLUMN_TS, Op::Le, 250));
+        let found: Vec<_> = rows(&mut cursor, filter)
+            .into_iter()
+            .map(|(rowid, _)| rowid)
+            .collect();
+        assert_eq!(found, [0, 2, 4]);
+
+        let mut filter = Filter::default();
+        assert!(filter.constrain(COLUMN_TS, Op::Ge, 250));
+        let found: Vec<_> = rows(&mut cursor, filter)
+            .into_iter()
+            .map(|(rowid, _)| rowid)
+            .collect();
+        assert_eq!(found, [1, 3]);
+    }
+}
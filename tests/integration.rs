@@ -1,4 +1,4 @@
-use gorilla::{BufferFull, DataPoint, Decoder, Encoder};
+use gorilla::{DataPoint, Decoder, EncodeError, Encoder, EncoderConfig, OutOfOrderPolicy};
 
 /// Round-trip: encode then decode, verify exact equality.
 fn roundtrip(input: &[DataPoint]) -> Vec<DataPoint> {
@@ -24,7 +24,7 @@ fn test_empty_stream() {
 
 #[test]
 fn test_single_point_roundtrip() {
-    let input = vec![DataPoint::new(1609459200, 3.14159)];
+    let input = vec![DataPoint::new(1609459200, std::f64::consts::PI)];
     assert_eq!(roundtrip(&input), input);
 }
 
@@ -150,7 +150,7 @@ fn test_compression_ratio_identical_values() {
     let block = enc.into_compressed();
 
     let uncompressed_bytes = input.len() * 16;
-    let compressed_bytes = block.bytes.len();
+    let compressed_bytes = block.bytes().len();
     let ratio = uncompressed_bytes as f64 / compressed_bytes as f64;
 
     // Identical values + constant interval: ~2 bits per point → very high ratio.
@@ -178,7 +178,7 @@ fn test_compression_ratio_varying_values() {
     let block = enc.into_compressed();
 
     let uncompressed_bytes = input.len() * 16;
-    let compressed_bytes = block.bytes.len();
+    let compressed_bytes = block.bytes().len();
     let ratio = uncompressed_bytes as f64 / compressed_bytes as f64;
 
     // Varying float values still compress decently (XOR shares leading zeros).
@@ -211,6 +211,33 @@ fn test_iterator_matches_decode() {
     assert_eq!(decoded, input);
 }
 
+#[test]
+fn test_negative_delta_roundtrip() {
+    // The default policy keeps backwards timestamps as negative deltas.
+    let input = vec![
+        DataPoint::new(1000, 1.0),
+        DataPoint::new(1060, 2.0),
+        DataPoint::new(1030, 3.0),
+        DataPoint::new(1090, 4.0),
+    ];
+    assert_eq!(roundtrip(&input), input);
+}
+
+#[test]
+fn test_out_of_order_drop_keeps_monotonic_stream() {
+    let mut enc = Encoder::with_config(EncoderConfig {
+        on_out_of_order: OutOfOrderPolicy::Drop,
+        ..Default::default()
+    });
+    for t in [1000, 1060, 1030, 1120, 1090, 1180] {
+        enc.encode(DataPoint::new(t, t as f64)).unwrap();
+    }
+    enc.finish().unwrap();
+    let output = Decoder::decode(&enc.into_compressed()).unwrap();
+    let timestamps: Vec<u64> = output.iter().map(|dp| dp.timestamp).collect();
+    assert_eq!(timestamps, vec![1000, 1060, 1120, 1180]);
+}
+
 #[test]
 fn test_large_timestamp_gaps() {
     let input = vec![
@@ -323,15 +350,14 @@ fn test_limit_partial_data_still_decodable() {
     let block = enc.into_compressed();
     let output = Decoder::decode(&block).unwrap();
     assert_eq!(output, input);
-    assert!(block.bytes.len() <= limit);
+    assert!(block.bytes().len() <= limit);
 }
 
 #[test]
 fn test_limit_error_is_buffer_full() {
     let mut enc = Encoder::with_limit(1);
     let err = enc.encode(DataPoint::new(100, 1.0)).unwrap_err();
-    // Verify it's the expected BufferFull type.
-    let _: BufferFull = err;
+    assert_eq!(err, EncodeError::BufferFull);
 }
 
 #[test]
@@ -426,7 +452,7 @@ fn test_limit_roundtrip_multiple_points() {
     let block = enc.into_compressed();
     let output = Decoder::decode(&block).unwrap();
     assert_eq!(output, points);
-    assert!(block.bytes.len() <= limit);
+    assert!(block.bytes().len() <= limit);
 }
 
 #[test]
@@ -445,9 +471,9 @@ fn test_limit_compressed_size_respects_limit() {
         // Don't call finish() — just check the raw buffer size.
         let block = enc.into_compressed();
         assert!(
-            block.bytes.len() <= limit,
+            block.bytes().len() <= limit,
             "limit={limit}, actual size={}",
-            block.bytes.len()
+            block.bytes().len()
         );
     }
 }